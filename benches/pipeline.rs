@@ -0,0 +1,140 @@
+//! Benchmarks of the capture and render paths
+//!
+//! Run with `cargo bench-pipeline`, or `cargo bench-pipeline -- <filter>` for
+//! one group. Capture backends are compared on synthetic screens, plus the
+//! system backend when the machine has a screen, so changes like a new
+//! capture backend can be compared against the existing ones.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use egui::{Pos2, Vec2};
+use image::{DynamicImage, RgbaImage};
+use lightweight_screenshot_app::capture::mock::{MockBackend, MockScreen};
+use lightweight_screenshot_app::decode::{self, DecodeLimits};
+use lightweight_screenshot_app::export;
+use lightweight_screenshot_app::hdr::HdrOptions;
+use lightweight_screenshot_app::image_store::EncodedImage;
+use lightweight_screenshot_app::memory::SpilledImage;
+use lightweight_screenshot_app::render;
+use lightweight_screenshot_app::{AnnotationItem, CaptureService, ImageFormat};
+
+/// Image sizes benchmarked, from a laptop screen to a 4K monitor
+const RESOLUTIONS: [(u32, u32); 3] = [(1280, 720), (1920, 1080), (3840, 2160)];
+
+/// Annotation counts flattened
+const ANNOTATION_COUNTS: [usize; 4] = [0, 10, 100, 1000];
+
+fn label((width, height): (u32, u32)) -> String {
+    format!("{}x{}", width, height)
+}
+
+fn pixels((width, height): (u32, u32)) -> u64 {
+    width as u64 * height as u64
+}
+
+/// A gradient screenshot, which compresses like real screen content more than a flat color
+fn test_image((width, height): (u32, u32)) -> DynamicImage {
+    DynamicImage::ImageRgba8(MockScreen::new(width, height).pattern.render(width, height))
+}
+
+fn capture_backends(c: &mut Criterion) {
+    let mut group = c.benchmark_group("capture");
+    group.sample_size(20);
+    for size in RESOLUTIONS {
+        group.throughput(Throughput::Elements(pixels(size)));
+
+        let sdr = CaptureService::with_backend(MockBackend::new(vec![MockScreen::new(size.0, size.1)])).unwrap();
+        group.bench_with_input(BenchmarkId::new("mock", label(size)), &sdr, |b, service| {
+            b.iter(|| service.capture_screen_by_index(0).unwrap())
+        });
+
+        // HDR screens take the full-precision path with tone mapping
+        let screen = MockScreen {
+            hdr_gain: Some(4.0),
+            ..MockScreen::new(size.0, size.1)
+        };
+        let mut hdr = CaptureService::with_backend(MockBackend::new(vec![screen])).unwrap();
+        hdr.set_hdr_options(HdrOptions::default());
+        group.bench_with_input(BenchmarkId::new("mock-hdr", label(size)), &hdr, |b, service| {
+            b.iter(|| service.capture_screen_by_index(0).unwrap())
+        });
+    }
+
+    // The system backend captures at the size of the real screen
+    match CaptureService::new() {
+        Ok(service) if !service.get_screens().is_empty() => {
+            group.bench_function("system/primary", |b| b.iter(|| service.capture_primary_screen().unwrap()));
+        }
+        _ => eprintln!("No screen found, skipping the system capture backend"),
+    }
+    group.finish();
+}
+
+fn decode_paths(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode");
+    group.sample_size(20);
+    let directory = SpilledImage::default_directory();
+    for size in RESOLUTIONS {
+        group.throughput(Throughput::Elements(pixels(size)));
+        let image = test_image(size);
+
+        // Compressed tabs are kept as PNG
+        let png = EncodedImage::encode(&image).unwrap();
+        group.bench_with_input(BenchmarkId::new("png", label(size)), &png, |b, png| {
+            b.iter(|| png.decode().unwrap())
+        });
+
+        // Opened and pasted files go through the untrusted decoder
+        let bytes = png.data().to_vec();
+        group.bench_with_input(BenchmarkId::new("png-untrusted", label(size)), &bytes, |b, bytes| {
+            b.iter(|| decode::decode_untrusted(bytes.clone(), DecodeLimits::default()).unwrap())
+        });
+
+        // Images over the memory budget are read back from raw pixels
+        let spilled = SpilledImage::spill(&image, &directory).unwrap();
+        group.bench_with_input(BenchmarkId::new("raw-mmap", label(size)), &spilled, |b, spilled| {
+            b.iter(|| spilled.load().unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn flatten_annotations(c: &mut Criterion) {
+    let mut group = c.benchmark_group("flatten");
+    group.sample_size(20);
+    let size = (1920, 1080);
+    let image = test_image(size);
+    for count in ANNOTATION_COUNTS {
+        let annotations: Vec<AnnotationItem> = (0..count)
+            .map(|i| {
+                let position = Pos2::new((i * 37 % size.0 as usize) as f32, (i * 23 % size.1 as usize) as f32);
+                if i % 4 == 3 {
+                    AnnotationItem::new_text(position, format!("Note {}", i))
+                } else {
+                    AnnotationItem::new_rectangle(position, Vec2::new(120.0, 80.0))
+                }
+            })
+            .collect();
+        group.throughput(Throughput::Elements(count as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(count), &annotations, |b, annotations| {
+            b.iter(|| render::flatten(&image, annotations))
+        });
+    }
+    group.finish();
+}
+
+fn export_encoders(c: &mut Criterion) {
+    let mut group = c.benchmark_group("encode");
+    group.sample_size(10);
+    for size in RESOLUTIONS {
+        group.throughput(Throughput::Elements(pixels(size)));
+        let image: RgbaImage = test_image(size).into_rgba8();
+        for format in [ImageFormat::Png, ImageFormat::Jpg, ImageFormat::Bmp] {
+            let id = BenchmarkId::new(format!("{:?}", format).to_lowercase(), label(size));
+            group.bench_with_input(id, &image, |b, image| b.iter(|| export::encode_image(image, &format).unwrap()));
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, capture_backends, decode_paths, flatten_annotations, export_encoders);
+criterion_main!(benches);
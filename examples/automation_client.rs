@@ -0,0 +1,58 @@
+//! 自動操作APIのクライアント例
+//!
+//! 起動中のアプリに自動操作リクエストを送り、結果を表示します。
+//! プロトコルの詳細は docs/api/automation.md を参照してください。
+//!
+//! 実行方法:
+//! cargo run --example automation_client -- capture [screen|window]
+//! cargo run --example automation_client -- capture --window-title <部分一致> [--process <実行ファイル名>]
+//! cargo run --example automation_client -- capture --process <実行ファイル名>
+//! cargo run --example automation_client -- last-capture
+//! cargo run --example automation_client -- open <path>
+//! cargo run --example automation_client -- export <path> [options-json]
+
+use lightweight_screenshot_app::automation::{self, AutomationRequest, CaptureTarget};
+use lightweight_screenshot_app::window_list::WindowSelector;
+use std::path::PathBuf;
+
+const USAGE: &str = "使い方: automation_client <capture [screen|window] | capture [--window-title <title>] [--process <name>] | last-capture | open <path> | export <path> [options-json]>";
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    let request = match args.as_slice() {
+        ["capture"] | ["capture", "screen"] => AutomationRequest::capture(CaptureTarget::Screen),
+        ["capture", "window"] => AutomationRequest::capture(CaptureTarget::Window),
+        ["capture", "--window-title", title] => AutomationRequest::capture_window(WindowSelector {
+            title: Some(title.to_string()),
+            process: None,
+        }),
+        ["capture", "--process", process] => AutomationRequest::capture_window(WindowSelector {
+            title: None,
+            process: Some(process.to_string()),
+        }),
+        ["capture", "--window-title", title, "--process", process]
+        | ["capture", "--process", process, "--window-title", title] => AutomationRequest::capture_window(WindowSelector {
+            title: Some(title.to_string()),
+            process: Some(process.to_string()),
+        }),
+        ["last-capture"] => AutomationRequest::LastCapture,
+        ["open", path] => AutomationRequest::Open { path: PathBuf::from(path) },
+        ["export", path] => AutomationRequest::Export { path: PathBuf::from(path), options: None },
+        ["export", path, options] => AutomationRequest::Export {
+            path: PathBuf::from(path),
+            // 設定ファイルの "export" と同じ形式。省略した項目は既定値になります
+            options: Some(serde_json::from_str(options)?),
+        },
+        _ => {
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    // 起動中のアプリが書き出した接続情報を使って送信します
+    let result = automation::send_request(&automation::default_endpoint_path(), request)?;
+    println!("{}", serde_json::to_string_pretty(&result)?);
+    Ok(())
+}
@@ -0,0 +1,25 @@
+//! Fuzz the hardened loader used for opened, dropped, and pasted images
+//!
+//! Run with `cargo +nightly fuzz run decode` from the repository root.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use lightweight_screenshot_app::decode::{self, DecodeLimits};
+use std::time::Duration;
+
+/// Limits small enough to keep each run fast, so the fuzzer explores more inputs
+const LIMITS: DecodeLimits = DecodeLimits {
+    max_input_bytes: 1024 * 1024,
+    max_width: 4096,
+    max_height: 4096,
+    max_alloc: 64 * 1024 * 1024,
+    timeout: Duration::from_secs(5),
+};
+
+fuzz_target!(|data: &[u8]| {
+    // Any input may be rejected, but only with an error, and a decoded image must fit the limits
+    if let Ok(image) = decode::decode_untrusted(data.to_vec(), LIMITS) {
+        assert!(image.width() <= LIMITS.max_width && image.height() <= LIMITS.max_height);
+    }
+});
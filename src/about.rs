@@ -0,0 +1,105 @@
+//! Version, build, and licence information
+//!
+//! Everything shown in the About dialog is compiled in: the package version,
+//! the build time and git commit recorded by the build script, and the
+//! licences of the bundled third-party crates.
+
+use crate::datetime::DateTime;
+use crate::settings::config_dir;
+use crate::types::{AppError, AppResult};
+use std::path::{Path, PathBuf};
+
+/// Application version from Cargo.toml
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Short git commit hash the application was built from, or "unknown"
+pub const GIT_HASH: &str = env!("GIT_HASH");
+
+/// Build time in seconds since the Unix epoch
+const BUILD_TIMESTAMP: &str = env!("BUILD_TIMESTAMP");
+
+/// Log file name in the log directory
+const LOG_FILE_NAME: &str = "lightweight-screenshot-app.log";
+
+/// A third-party crate bundled into the executable
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BundledLicense {
+    pub name: &'static str,
+    /// SPDX licence expression
+    pub license: &'static str,
+    pub homepage: &'static str,
+}
+
+/// Licences of the crates bundled into the executable
+pub const BUNDLED_LICENSES: &[BundledLicense] = &[
+    BundledLicense { name: "egui / eframe", license: "MIT OR Apache-2.0", homepage: "https://github.com/emilk/egui" },
+    BundledLicense { name: "image", license: "MIT OR Apache-2.0", homepage: "https://github.com/image-rs/image" },
+    BundledLicense { name: "screenshots", license: "Apache-2.0", homepage: "https://github.com/nashaofu/screenshots-rs" },
+    BundledLicense { name: "thiserror", license: "MIT OR Apache-2.0", homepage: "https://github.com/dtolnay/thiserror" },
+    BundledLicense { name: "tokio", license: "MIT", homepage: "https://github.com/tokio-rs/tokio" },
+    BundledLicense { name: "crossbeam-channel", license: "MIT OR Apache-2.0", homepage: "https://github.com/crossbeam-rs/crossbeam" },
+    BundledLicense { name: "uuid", license: "Apache-2.0 OR MIT", homepage: "https://github.com/uuid-rs/uuid" },
+    BundledLicense { name: "serde / serde_json", license: "MIT OR Apache-2.0", homepage: "https://github.com/serde-rs/serde" },
+    BundledLicense { name: "roxmltree", license: "MIT OR Apache-2.0", homepage: "https://github.com/RazrFalcon/roxmltree" },
+    BundledLicense { name: "log / env_logger", license: "MIT OR Apache-2.0", homepage: "https://github.com/rust-lang/log" },
+    BundledLicense { name: "winapi", license: "MIT OR Apache-2.0", homepage: "https://github.com/retep998/winapi-rs" },
+];
+
+/// Get the time the application was built, in UTC
+pub fn build_time() -> DateTime {
+    DateTime::from_unix_seconds(BUILD_TIMESTAMP.parse().unwrap_or_default())
+}
+
+/// Get the build date as YYYY-MM-DD
+pub fn build_date() -> String {
+    let time = build_time();
+    format!("{:04}-{:02}-{:02}", time.year, time.month, time.day)
+}
+
+/// Get a one-line version description, e.g. "0.1.0 (abc1234, built 2024-05-01)"
+pub fn version_line() -> String {
+    format!("{} ({}, built {})", VERSION, GIT_HASH, build_date())
+}
+
+/// Get the directory the log file is written to
+pub fn log_directory() -> PathBuf {
+    config_dir().join("logs")
+}
+
+/// Get the log file of the current session
+pub fn log_file_path() -> PathBuf {
+    log_directory().join(LOG_FILE_NAME)
+}
+
+/// Open a folder in the platform file manager
+pub fn open_folder(path: &Path) -> AppResult<()> {
+    std::fs::create_dir_all(path)?;
+    let program = if cfg!(windows) {
+        "explorer"
+    } else if cfg!(target_os = "macos") {
+        "open"
+    } else {
+        "xdg-open"
+    };
+    std::process::Command::new(program)
+        .arg(path)
+        .spawn()
+        .map(|_| ())
+        .map_err(AppError::FileAccess)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_info() {
+        assert_eq!(VERSION, env!("CARGO_PKG_VERSION"));
+        assert!(!GIT_HASH.is_empty());
+        assert!(build_time().year >= 2024);
+        assert_eq!(build_date().len(), 10);
+        assert!(version_line().starts_with(VERSION));
+        assert!(log_file_path().starts_with(config_dir()));
+        assert!(BUNDLED_LICENSES.iter().all(|crate_license| !crate_license.license.is_empty()));
+    }
+}
@@ -0,0 +1,134 @@
+//! Accessibility helpers
+//!
+//! egui reports widgets to screen readers through AccessKit, naming them by
+//! their text. Widgets without text, such as color swatches and the canvas,
+//! are named here. The menu bar is reached from the keyboard with Alt plus
+//! the first letter of a menu, or F10 for the first menu, and a high-contrast
+//! theme is offered for low-vision users.
+
+use egui::{Color32, Context, Key, Modifiers, Response, Rounding, Stroke, Visuals, WidgetInfo, WidgetType};
+
+/// Menus of the menu bar with their Alt+letter mnemonics, in order
+pub const MENU_MNEMONICS: [(&str, Key); 5] = [
+    ("File", Key::F),
+    ("Edit", Key::E),
+    ("Image", Key::I),
+    ("Settings", Key::S),
+    ("Help", Key::H),
+];
+
+/// Give a widget without text a name for screen readers
+pub fn label(response: Response, kind: WidgetType, name: &str) -> Response {
+    response.widget_info(|| WidgetInfo::labeled(kind, name));
+    response
+}
+
+/// Add a color swatch button named for screen readers
+pub fn color_button(ui: &mut egui::Ui, color: &mut Color32, name: &str) -> Response {
+    label(ui.color_edit_button_srgba(color), WidgetType::ColorButton, name)
+}
+
+/// Get the menu whose mnemonic was pressed this frame, consuming the key
+///
+/// F10 selects the first menu, as in native Windows menu bars.
+pub fn pressed_menu(ctx: &Context) -> Option<&'static str> {
+    ctx.input_mut(|input| {
+        if input.consume_key(Modifiers::NONE, Key::F10) {
+            return Some(MENU_MNEMONICS[0].0);
+        }
+        MENU_MNEMONICS
+            .iter()
+            .find(|(_, key)| input.consume_key(Modifiers::ALT, *key))
+            .map(|(menu, _)| *menu)
+    })
+}
+
+/// Theme with black backgrounds, white text, and yellow focus and selection
+pub fn high_contrast_visuals() -> Visuals {
+    let mut visuals = Visuals::dark();
+    visuals.override_text_color = Some(Color32::WHITE);
+    visuals.hyperlink_color = Color32::from_rgb(0x66, 0xCC, 0xFF);
+    visuals.panel_fill = Color32::BLACK;
+    visuals.window_fill = Color32::BLACK;
+    visuals.extreme_bg_color = Color32::BLACK;
+    visuals.faint_bg_color = Color32::from_gray(24);
+    visuals.window_stroke = Stroke::new(2.0, Color32::WHITE);
+    visuals.selection.bg_fill = Color32::from_rgb(0xFF, 0xD7, 0x00);
+    visuals.selection.stroke = Stroke::new(2.0, Color32::BLACK);
+
+    let widgets = &mut visuals.widgets;
+    for state in [
+        &mut widgets.noninteractive,
+        &mut widgets.inactive,
+        &mut widgets.hovered,
+        &mut widgets.active,
+        &mut widgets.open,
+    ] {
+        state.bg_fill = Color32::BLACK;
+        state.weak_bg_fill = Color32::BLACK;
+        state.fg_stroke = Stroke::new(1.5, Color32::WHITE);
+        state.bg_stroke = Stroke::new(1.0, Color32::WHITE);
+        state.rounding = Rounding::same(2.0);
+    }
+    // Hovered and pressed widgets get a thick yellow outline instead of a lighter fill
+    widgets.noninteractive.bg_stroke = Stroke::new(1.0, Color32::from_gray(160));
+    widgets.hovered.bg_stroke = Stroke::new(2.0, Color32::from_rgb(0xFF, 0xD7, 0x00));
+    widgets.active.bg_stroke = Stroke::new(3.0, Color32::from_rgb(0xFF, 0xD7, 0x00));
+    widgets.open.bg_stroke = Stroke::new(2.0, Color32::from_rgb(0xFF, 0xD7, 0x00));
+    visuals
+}
+
+/// WCAG contrast ratio between two opaque colors, from 1 to 21
+pub fn contrast_ratio(a: Color32, b: Color32) -> f32 {
+    let luminance = |color: Color32| {
+        let linear = egui::Rgba::from(color);
+        0.2126 * linear.r() + 0.7152 * linear.g() + 0.0722 * linear.b()
+    };
+    let (lighter, darker) = {
+        let (a, b) = (luminance(a), luminance(b));
+        (a.max(b), a.min(b))
+    };
+    (lighter + 0.05) / (darker + 0.05)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_high_contrast_meets_wcag_aaa() {
+        let visuals = high_contrast_visuals();
+        let text = visuals.override_text_color.unwrap();
+        for background in [visuals.panel_fill, visuals.window_fill, visuals.extreme_bg_color, visuals.faint_bg_color] {
+            assert!(contrast_ratio(text, background) >= 7.0);
+        }
+        // Selected text is drawn over the selection fill
+        assert!(contrast_ratio(visuals.selection.stroke.color, visuals.selection.bg_fill) >= 7.0);
+        assert!(contrast_ratio(visuals.hyperlink_color, visuals.panel_fill) >= 7.0);
+
+        // The default theme doesn't
+        let dark = Visuals::dark();
+        assert!(contrast_ratio(dark.widgets.noninteractive.fg_stroke.color, dark.panel_fill) < 7.0);
+        assert!((contrast_ratio(Color32::WHITE, Color32::BLACK) - 21.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_menu_mnemonics() {
+        let ctx = Context::default();
+        let press = |key, modifiers| egui::RawInput {
+            events: vec![egui::Event::Key { key, pressed: true, repeat: false, modifiers }],
+            modifiers,
+            ..Default::default()
+        };
+        let mut pressed = Vec::new();
+        for input in [
+            press(Key::E, Modifiers::ALT),
+            press(Key::F10, Modifiers::NONE),
+            press(Key::E, Modifiers::NONE),
+            press(Key::X, Modifiers::ALT),
+        ] {
+            let _ = ctx.run(input, |ctx| pressed.push(pressed_menu(ctx)));
+        }
+        assert_eq!(pressed, vec![Some("Edit"), Some("File"), None, None]);
+    }
+}
@@ -0,0 +1,402 @@
+//! Image adjustments
+//!
+//! Adjustments are kept as a stack of steps (brightness, contrast,
+//! saturation, grayscale, invert) applied in order to the source image, which
+//! itself is never changed. The stack is saved with the project, so it stays
+//! editable, and is part of the undo history like the annotations.
+//!
+//! Applying a stack runs on a worker thread. While an adjustment slider is
+//! dragged, a debounced preview is rendered from a low-resolution proxy; the
+//! full-resolution image is worked out once the user releases the slider.
+
+use crate::types::{AppError, AppResult};
+use crossbeam_channel::Receiver;
+use image::DynamicImage;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// Longest side of the low-resolution proxy used for live previews
+pub const PROXY_MAX_SIDE: u32 = 1024;
+
+/// Delay after the last slider change before the preview is regenerated
+pub const PREVIEW_DEBOUNCE: Duration = Duration::from_millis(80);
+
+/// Longest time a change waits for the preview while the slider keeps moving
+pub const PREVIEW_MAX_WAIT: Duration = Duration::from_millis(200);
+
+/// Rec. 709 weights of the red, green, and blue channels in the luminance of a pixel
+const LUMA_WEIGHTS: [f32; 3] = [0.2126, 0.7152, 0.0722];
+
+/// One step of the adjustment stack
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Adjustment {
+    /// Brightness offset in the range -100..=100
+    Brightness(f32),
+    /// Contrast change in percent in the range -100..=100
+    Contrast(f32),
+    /// Saturation change in percent in the range -100..=100, where -100 leaves only gray
+    Saturation(f32),
+    /// Remove all color
+    Grayscale,
+    /// Invert the colors, keeping transparency
+    Invert,
+}
+
+impl Adjustment {
+    /// Get a new step of every kind, in menu order
+    pub fn all() -> [Adjustment; 5] {
+        [
+            Adjustment::Brightness(0.0),
+            Adjustment::Contrast(0.0),
+            Adjustment::Saturation(0.0),
+            Adjustment::Grayscale,
+            Adjustment::Invert,
+        ]
+    }
+
+    /// Get the amount of the step in the range -100..=100, `None` for steps without one
+    pub fn amount_mut(&mut self) -> Option<&mut f32> {
+        match self {
+            Adjustment::Brightness(amount) | Adjustment::Contrast(amount) | Adjustment::Saturation(amount) => {
+                Some(amount)
+            }
+            Adjustment::Grayscale | Adjustment::Invert => None,
+        }
+    }
+
+    /// Check if the step leaves the image unchanged
+    pub fn is_identity(&self) -> bool {
+        match self {
+            Adjustment::Brightness(amount) | Adjustment::Contrast(amount) | Adjustment::Saturation(amount) => {
+                *amount == 0.0
+            }
+            Adjustment::Grayscale | Adjustment::Invert => false,
+        }
+    }
+
+    fn apply(&self, mut image: DynamicImage) -> DynamicImage {
+        match *self {
+            _ if self.is_identity() => image,
+            Adjustment::Brightness(amount) => image.brighten((amount * 2.55).round() as i32),
+            Adjustment::Contrast(amount) => image.adjust_contrast(amount),
+            Adjustment::Saturation(amount) => saturate(image, 1.0 + amount / 100.0),
+            Adjustment::Grayscale => saturate(image, 0.0),
+            Adjustment::Invert => {
+                image.invert();
+                image
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Adjustment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Adjustment::Brightness(_) => write!(f, "Brightness"),
+            Adjustment::Contrast(_) => write!(f, "Contrast"),
+            Adjustment::Saturation(_) => write!(f, "Saturation"),
+            Adjustment::Grayscale => write!(f, "Grayscale"),
+            Adjustment::Invert => write!(f, "Invert"),
+        }
+    }
+}
+
+/// Scale the color of every pixel away from its luminance by `factor`; 0 makes it gray
+fn saturate(image: DynamicImage, factor: f32) -> DynamicImage {
+    let mut rgba = image.into_rgba8();
+    for pixel in rgba.pixels_mut() {
+        let [r, g, b, _] = pixel.0.map(f32::from);
+        let luma = r * LUMA_WEIGHTS[0] + g * LUMA_WEIGHTS[1] + b * LUMA_WEIGHTS[2];
+        for channel in &mut pixel.0[..3] {
+            *channel = (luma + (f32::from(*channel) - luma) * factor).round().clamp(0.0, 255.0) as u8;
+        }
+    }
+    DynamicImage::ImageRgba8(rgba)
+}
+
+/// Stack of adjustments applied to the source image, in order
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Adjustments {
+    pub steps: Vec<Adjustment>,
+}
+
+impl Adjustments {
+    /// Create a stack of steps
+    pub fn new(steps: Vec<Adjustment>) -> Self {
+        Self { steps }
+    }
+
+    /// Check if these adjustments leave the image unchanged
+    pub fn is_identity(&self) -> bool {
+        self.steps.iter().all(Adjustment::is_identity)
+    }
+
+    /// Apply the adjustments to an image, returning a new image
+    pub fn apply(&self, image: &DynamicImage) -> DynamicImage {
+        self.steps.iter().fold(image.clone(), |image, step| step.apply(image))
+    }
+}
+
+/// Adjustments being applied on a worker thread
+#[derive(Debug)]
+pub struct AdjustmentJob {
+    /// Adjustments of the image the job produces
+    pub adjustments: Adjustments,
+    receiver: Receiver<DynamicImage>,
+}
+
+impl AdjustmentJob {
+    /// Start applying `adjustments` to `image`
+    pub fn spawn(image: DynamicImage, adjustments: Adjustments) -> Self {
+        let (sender, receiver) = crossbeam_channel::bounded(1);
+        let job_adjustments = adjustments.clone();
+        let spawned = std::thread::Builder::new()
+            .name("adjustments".to_string())
+            .spawn(move || {
+                let _ = sender.send(job_adjustments.apply(&image));
+            });
+        if let Err(e) = spawned {
+            log::warn!("Failed to start the adjustment worker: {}", e);
+        }
+        Self { adjustments, receiver }
+    }
+
+    /// Get the result if the job finished, `None` while it is still running
+    pub fn try_finish(&self) -> Option<AppResult<DynamicImage>> {
+        match self.receiver.try_recv() {
+            Ok(image) => Some(Ok(image)),
+            Err(crossbeam_channel::TryRecvError::Empty) => None,
+            Err(crossbeam_channel::TryRecvError::Disconnected) => Some(Err(interrupted())),
+        }
+    }
+
+    /// Wait for the job to finish
+    pub fn wait(self) -> AppResult<DynamicImage> {
+        self.receiver.recv().map_err(|_| interrupted())
+    }
+}
+
+fn interrupted() -> AppError {
+    AppError::ImageProcessing("画像の調整が中断されました".to_string())
+}
+
+/// Create a low-resolution proxy of an image for fast previews
+pub fn create_proxy(image: &DynamicImage, max_side: u32) -> DynamicImage {
+    if image.width() <= max_side && image.height() <= max_side {
+        image.clone()
+    } else {
+        image.thumbnail(max_side, max_side)
+    }
+}
+
+/// Debounced preview state for interactive adjustments
+#[derive(Debug, Default)]
+pub struct AdjustmentPreview {
+    /// Low-resolution copy of the source image, created on first use
+    proxy: Option<DynamicImage>,
+    /// Time of the first adjustment change not yet reflected in the preview
+    pending_since: Option<Instant>,
+    /// Time of the latest adjustment change
+    last_change: Option<Instant>,
+    /// Preview being rendered on the worker thread
+    job: Option<AdjustmentJob>,
+}
+
+impl AdjustmentPreview {
+    /// Create an empty preview state
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record that the adjustments changed at `now`
+    pub fn mark_changed(&mut self, now: Instant) {
+        self.pending_since.get_or_insert(now);
+        self.last_change = Some(now);
+    }
+
+    /// Check if a preview refresh is due at `now`
+    ///
+    /// A refresh is due once the changes pause for [`PREVIEW_DEBOUNCE`], or
+    /// after [`PREVIEW_MAX_WAIT`] so the preview keeps up with a slider that
+    /// never stops moving.
+    pub fn should_refresh(&self, now: Instant) -> bool {
+        match (self.pending_since, self.last_change) {
+            (Some(since), Some(last)) => {
+                now.duration_since(last) >= PREVIEW_DEBOUNCE || now.duration_since(since) >= PREVIEW_MAX_WAIT
+            }
+            _ => false,
+        }
+    }
+
+    /// Check if there is a change waiting for the debounce delay or a preview being rendered
+    pub fn is_pending(&self) -> bool {
+        self.pending_since.is_some() || self.job.is_some()
+    }
+
+    /// Start rendering the preview from the proxy and clear the pending state
+    ///
+    /// A preview still being rendered is dropped in favor of the new one.
+    pub fn start(&mut self, source: &DynamicImage, adjustments: &Adjustments) {
+        self.pending_since = None;
+        self.last_change = None;
+        let proxy = self
+            .proxy
+            .get_or_insert_with(|| create_proxy(source, PROXY_MAX_SIDE));
+        self.job = Some(AdjustmentJob::spawn(proxy.clone(), adjustments.clone()));
+    }
+
+    /// Take the rendered preview once it is ready
+    pub fn poll(&mut self) -> Option<DynamicImage> {
+        let result = self.job.as_ref()?.try_finish()?;
+        self.job = None;
+        result.map_err(|e| log::warn!("Adjustment preview failed: {}", e)).ok()
+    }
+
+    /// Drop pending and running previews once the full-resolution result was started
+    pub fn finish(&mut self) {
+        self.pending_since = None;
+        self.last_change = None;
+        self.job = None;
+    }
+
+    /// Drop the proxy (call when the source image changes)
+    pub fn reset(&mut self) {
+        self.proxy = None;
+        self.finish();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    fn gray_image(width: u32, height: u32, value: u8) -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_pixel(width, height, Rgb([value, value, value])))
+    }
+
+    #[test]
+    fn test_default_adjustments_are_identity() {
+        let adjustments = Adjustments::default();
+        assert!(adjustments.is_identity());
+        assert!(Adjustments::new(vec![Adjustment::Brightness(0.0), Adjustment::Saturation(0.0)]).is_identity());
+        assert!(!Adjustments::new(vec![Adjustment::Invert]).is_identity());
+
+        let image = gray_image(4, 4, 100);
+        assert_eq!(adjustments.apply(&image).to_rgb8(), image.to_rgb8());
+    }
+
+    #[test]
+    fn test_brightness_adjustment() {
+        let image = gray_image(2, 2, 100);
+        let brighter = Adjustments::new(vec![Adjustment::Brightness(20.0)]).apply(&image);
+        let darker = Adjustments::new(vec![Adjustment::Brightness(-20.0)]).apply(&image);
+
+        assert!(brighter.to_rgb8().get_pixel(0, 0).0[0] > 100);
+        assert!(darker.to_rgb8().get_pixel(0, 0).0[0] < 100);
+    }
+
+    #[test]
+    fn test_contrast_adjustment() {
+        let image = gray_image(2, 2, 200);
+        let result = Adjustments::new(vec![Adjustment::Contrast(50.0)]).apply(&image);
+        assert!(result.to_rgb8().get_pixel(0, 0).0[0] > 200);
+    }
+
+    #[test]
+    fn test_color_adjustments_apply_in_order() {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(1, 1, Rgb([200, 100, 50])));
+        let pixel = |steps: Vec<Adjustment>| Adjustments::new(steps).apply(&image).to_rgba8().get_pixel(0, 0).0;
+
+        let [r, g, b, a] = pixel(vec![Adjustment::Grayscale]);
+        assert!(r == g && g == b && a == 255);
+        assert_eq!(pixel(vec![Adjustment::Saturation(-100.0)]), pixel(vec![Adjustment::Grayscale]));
+        let [r, _, b, _] = pixel(vec![Adjustment::Saturation(50.0)]);
+        assert!(r > 200 && b < 50);
+        assert_eq!(pixel(vec![Adjustment::Invert]), [55, 155, 205, 255]);
+
+        // Inverting after brightening is not the same as brightening after inverting
+        let brighten = Adjustment::Brightness(10.0);
+        assert_ne!(pixel(vec![brighten, Adjustment::Invert]), pixel(vec![Adjustment::Invert, brighten]));
+    }
+
+    #[test]
+    fn test_adjustments_round_trip_as_json() {
+        let adjustments = Adjustments::new(vec![Adjustment::Contrast(-30.0), Adjustment::Invert]);
+        let json = serde_json::to_string(&adjustments).unwrap();
+        assert_eq!(json, r#"[{"Contrast":-30.0},"Invert"]"#);
+        assert_eq!(serde_json::from_str::<Adjustments>(&json).unwrap(), adjustments);
+    }
+
+    #[test]
+    fn test_job_applies_on_worker_thread() {
+        let adjustments = Adjustments::new(vec![Adjustment::Invert]);
+        let job = AdjustmentJob::spawn(gray_image(3, 2, 100), adjustments.clone());
+        assert_eq!(job.adjustments, adjustments);
+        let result = job.wait().unwrap();
+        assert_eq!(result.to_rgb8().get_pixel(2, 1).0, [155, 155, 155]);
+    }
+
+    #[test]
+    fn test_create_proxy_limits_size() {
+        let large = gray_image(3000, 1500, 0);
+        let proxy = create_proxy(&large, 1000);
+        assert_eq!(proxy.width(), 1000);
+        assert_eq!(proxy.height(), 500);
+
+        let small = gray_image(100, 50, 0);
+        let proxy = create_proxy(&small, 1000);
+        assert_eq!((proxy.width(), proxy.height()), (100, 50));
+    }
+
+    #[test]
+    fn test_preview_debounce() {
+        let mut preview = AdjustmentPreview::new();
+        let start = Instant::now();
+        assert!(!preview.should_refresh(start));
+
+        preview.mark_changed(start);
+        assert!(preview.is_pending());
+        assert!(!preview.should_refresh(start));
+        assert!(preview.should_refresh(start + PREVIEW_DEBOUNCE));
+    }
+
+    #[test]
+    fn test_preview_refreshes_during_continuous_drag() {
+        let mut preview = AdjustmentPreview::new();
+        let start = Instant::now();
+        let step = PREVIEW_DEBOUNCE / 2;
+
+        // Changes arriving faster than the debounce delay still refresh after the maximum wait
+        let mut now = start;
+        while now.duration_since(start) < PREVIEW_MAX_WAIT {
+            preview.mark_changed(now);
+            assert!(!preview.should_refresh(now));
+            now += step;
+        }
+        preview.mark_changed(now);
+        assert!(preview.should_refresh(now));
+
+        // The wait starts over once the refresh started
+        preview.start(&gray_image(8, 8, 0), &Adjustments::default());
+        preview.mark_changed(now + step);
+        assert!(!preview.should_refresh(now + step * 2));
+    }
+
+    #[test]
+    fn test_preview_render_uses_proxy() {
+        let mut preview = AdjustmentPreview::new();
+        let source = gray_image(2048, 1024, 100);
+        preview.mark_changed(Instant::now());
+
+        preview.start(&source, &Adjustments::new(vec![Adjustment::Brightness(10.0)]));
+        assert!(!preview.should_refresh(Instant::now() + PREVIEW_DEBOUNCE));
+        let rendered = preview.job.take().unwrap().wait().unwrap();
+        assert_eq!(rendered.width(), PROXY_MAX_SIDE);
+        assert!(!preview.is_pending());
+
+        preview.reset();
+        assert!(preview.proxy.is_none());
+    }
+}
@@ -0,0 +1,216 @@
+//! Alignment guides
+//!
+//! While an annotation is moved or drawn, its edges and center snap to the
+//! edges and centers of other annotations and to the image center when they
+//! are within a tolerance. The alignments that hold after snapping are
+//! returned as guide lines for the editor to draw. All coordinates are in
+//! image pixels.
+
+use egui::{Pos2, Rect, Vec2};
+
+/// Positions closer than this count as aligned when collecting guides
+const ALIGNED_EPSILON: f32 = 0.01;
+
+/// Orientation of a guide line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GuideAxis {
+    /// A vertical line at an x coordinate
+    Vertical,
+    /// A horizontal line at a y coordinate
+    Horizontal,
+}
+
+/// A guide line showing an alignment
+///
+/// `position` is the x coordinate of a vertical guide (or y of a horizontal
+/// one), and `start..end` is the range it spans along the other axis.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Guide {
+    pub axis: GuideAxis,
+    pub position: f32,
+    pub start: f32,
+    pub end: f32,
+}
+
+/// Snapping correction and the guides to show for it
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Alignment {
+    /// Offset to add to the moved rectangle or point
+    pub offset: Vec2,
+    pub guides: Vec<Guide>,
+}
+
+/// A line that can be aligned to, spanning `start..end` along the other axis
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct TargetLine {
+    position: f32,
+    start: f32,
+    end: f32,
+}
+
+/// Edges and centers that moved annotations align to
+#[derive(Debug, Clone, Default)]
+pub struct AlignTargets {
+    vertical: Vec<TargetLine>,
+    horizontal: Vec<TargetLine>,
+}
+
+impl AlignTargets {
+    /// Create an empty set of targets
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the edges and center of a rectangle (e.g. another annotation)
+    pub fn add_rect(&mut self, rect: Rect) {
+        for x in [rect.min.x, rect.center().x, rect.max.x] {
+            self.vertical.push(TargetLine { position: x, start: rect.min.y, end: rect.max.y });
+        }
+        for y in [rect.min.y, rect.center().y, rect.max.y] {
+            self.horizontal.push(TargetLine { position: y, start: rect.min.x, end: rect.max.x });
+        }
+    }
+
+    /// Add only the center lines of a rectangle (e.g. the image)
+    pub fn add_center(&mut self, rect: Rect) {
+        let center = rect.center();
+        self.vertical.push(TargetLine { position: center.x, start: rect.min.y, end: rect.max.y });
+        self.horizontal.push(TargetLine { position: center.y, start: rect.min.x, end: rect.max.x });
+    }
+
+    /// Check whether there is nothing to align to
+    pub fn is_empty(&self) -> bool {
+        self.vertical.is_empty() && self.horizontal.is_empty()
+    }
+
+    /// Snap a rectangle's edges or center to the closest targets within `tolerance` on each axis
+    pub fn align_rect(&self, rect: Rect, tolerance: f32) -> Alignment {
+        let dx = snap_offset(&self.vertical, &[rect.min.x, rect.center().x, rect.max.x], tolerance);
+        let dy = snap_offset(&self.horizontal, &[rect.min.y, rect.center().y, rect.max.y], tolerance);
+        let offset = Vec2::new(dx.unwrap_or(0.0), dy.unwrap_or(0.0));
+        let snapped = rect.translate(offset);
+
+        let mut guides = Vec::new();
+        if dx.is_some() {
+            let candidates = [snapped.min.x, snapped.center().x, snapped.max.x];
+            let span = (snapped.min.y, snapped.max.y);
+            collect_guides(&mut guides, GuideAxis::Vertical, &self.vertical, &candidates, span);
+        }
+        if dy.is_some() {
+            let candidates = [snapped.min.y, snapped.center().y, snapped.max.y];
+            let span = (snapped.min.x, snapped.max.x);
+            collect_guides(&mut guides, GuideAxis::Horizontal, &self.horizontal, &candidates, span);
+        }
+        Alignment { offset, guides }
+    }
+
+    /// Snap a point (e.g. the dragged corner of a new rectangle) to the closest targets
+    pub fn align_point(&self, point: Pos2, tolerance: f32) -> Alignment {
+        self.align_rect(Rect::from_min_max(point, point), tolerance)
+    }
+}
+
+/// Offset moving the candidate closest to a target onto it, if within `tolerance`
+fn snap_offset(targets: &[TargetLine], candidates: &[f32], tolerance: f32) -> Option<f32> {
+    candidates
+        .iter()
+        .flat_map(|&candidate| targets.iter().map(move |target| target.position - candidate))
+        .filter(|offset| offset.abs() <= tolerance)
+        .min_by(|a, b| a.abs().total_cmp(&b.abs()))
+}
+
+/// Add a guide for every target a candidate lies on, spanning both the target and the moved range
+fn collect_guides(
+    guides: &mut Vec<Guide>,
+    axis: GuideAxis,
+    targets: &[TargetLine],
+    candidates: &[f32],
+    span: (f32, f32),
+) {
+    for target in targets {
+        if !candidates.iter().any(|candidate| (target.position - candidate).abs() <= ALIGNED_EPSILON) {
+            continue;
+        }
+        let start = target.start.min(span.0);
+        let end = target.end.max(span.1);
+        match guides
+            .iter_mut()
+            .find(|guide| guide.axis == axis && (guide.position - target.position).abs() <= ALIGNED_EPSILON)
+        {
+            Some(guide) => {
+                guide.start = guide.start.min(start);
+                guide.end = guide.end.max(end);
+            }
+            None => guides.push(Guide { axis, position: target.position, start, end }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: f32, y: f32, width: f32, height: f32) -> Rect {
+        Rect::from_min_size(Pos2::new(x, y), Vec2::new(width, height))
+    }
+
+    #[test]
+    fn test_edges_snap_within_tolerance() {
+        let mut targets = AlignTargets::new();
+        targets.add_rect(rect(100.0, 100.0, 50.0, 20.0));
+
+        // Left edge 3px right of the target's left edge, far below it
+        let alignment = targets.align_rect(rect(103.0, 300.0, 30.0, 30.0), 5.0);
+        assert_eq!(alignment.offset, Vec2::new(-3.0, 0.0));
+        assert_eq!(
+            alignment.guides,
+            vec![Guide { axis: GuideAxis::Vertical, position: 100.0, start: 100.0, end: 330.0 }]
+        );
+
+        // Outside the tolerance nothing moves
+        let alignment = targets.align_rect(rect(160.0, 300.0, 30.0, 30.0), 5.0);
+        assert_eq!(alignment, Alignment::default());
+    }
+
+    #[test]
+    fn test_closest_candidate_wins() {
+        let mut targets = AlignTargets::new();
+        targets.add_rect(rect(0.0, 0.0, 100.0, 100.0));
+
+        // Right edge at 98 is 2px from 100, left edge at 48 is 2px from center 50; top at 1 is 1px from 0
+        let alignment = targets.align_rect(rect(48.0, 1.0, 50.0, 10.0), 4.0);
+        assert_eq!(alignment.offset, Vec2::new(2.0, -1.0));
+        // Left edge on the center and right edge on the right edge, plus the top edge
+        let positions: Vec<(GuideAxis, f32)> =
+            alignment.guides.iter().map(|guide| (guide.axis, guide.position)).collect();
+        assert_eq!(
+            positions,
+            vec![(GuideAxis::Vertical, 50.0), (GuideAxis::Vertical, 100.0), (GuideAxis::Horizontal, 0.0)]
+        );
+    }
+
+    #[test]
+    fn test_center_to_image_center() {
+        let mut targets = AlignTargets::new();
+        targets.add_center(rect(0.0, 0.0, 800.0, 600.0));
+
+        let alignment = targets.align_rect(rect(352.0, 10.0, 100.0, 40.0), 5.0);
+        assert_eq!(alignment.offset, Vec2::new(-2.0, 0.0));
+        assert_eq!(alignment.guides[0].position, 400.0);
+        assert_eq!((alignment.guides[0].start, alignment.guides[0].end), (0.0, 600.0));
+
+        // Image edges are not targets
+        assert_eq!(targets.align_rect(rect(2.0, 2.0, 10.0, 10.0), 5.0).offset, Vec2::ZERO);
+    }
+
+    #[test]
+    fn test_align_point() {
+        let mut targets = AlignTargets::new();
+        assert!(targets.is_empty());
+        targets.add_rect(rect(20.0, 20.0, 10.0, 10.0));
+
+        let alignment = targets.align_point(Pos2::new(31.5, 80.0), 2.0);
+        assert_eq!(alignment.offset, Vec2::new(-1.5, 0.0));
+        assert_eq!(alignment.guides.len(), 1);
+    }
+}
@@ -0,0 +1,249 @@
+//! Animated walkthrough export
+//!
+//! Annotations can be tagged with a step number. An animated export shows
+//! the screenshot with its untagged annotations first, then adds the
+//! annotations of each step in turn, one frame per step, encoded as an
+//! animated GIF or APNG. A single screenshot becomes a walkthrough that can
+//! be dropped into chat or documentation.
+
+use crate::render;
+use crate::types::{AnnotationItem, AppError, AppResult};
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, DynamicImage, Frame, RgbaImage};
+use serde::{Deserialize, Serialize};
+
+/// Highest step number an annotation can be tagged with
+pub const MAX_STEP: u32 = 99;
+
+/// Color quantization speed of GIF frames, from 1 (best) to 30 (fastest)
+const GIF_QUANTIZATION_SPEED: i32 = 10;
+
+/// Animated image formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum AnimationFormat {
+    /// 256 colors per frame, supported everywhere
+    #[default]
+    Gif,
+    /// Full color animated PNG
+    Apng,
+}
+
+impl AnimationFormat {
+    /// Get all animation formats
+    pub fn all() -> [AnimationFormat; 2] {
+        [AnimationFormat::Gif, AnimationFormat::Apng]
+    }
+
+    /// Get the file extension for this format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            AnimationFormat::Gif => "gif",
+            AnimationFormat::Apng => "png",
+        }
+    }
+}
+
+impl std::fmt::Display for AnimationFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AnimationFormat::Gif => write!(f, "GIF"),
+            AnimationFormat::Apng => write!(f, "APNG"),
+        }
+    }
+}
+
+/// Animated export settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnimationOptions {
+    pub format: AnimationFormat,
+    /// How long each step is shown, in milliseconds
+    pub step_delay_ms: u32,
+    /// How long the last step is shown before the animation ends or repeats
+    pub last_delay_ms: u32,
+    /// Whether the animation repeats instead of playing once
+    pub loop_forever: bool,
+}
+
+impl Default for AnimationOptions {
+    fn default() -> Self {
+        Self {
+            format: AnimationFormat::default(),
+            step_delay_ms: 1500,
+            last_delay_ms: 3000,
+            loop_forever: true,
+        }
+    }
+}
+
+impl AnimationOptions {
+    /// Range of frame delays, in milliseconds
+    pub const DELAY_RANGE: std::ops::RangeInclusive<u32> = 100..=10_000;
+
+    /// Validate animation options
+    pub fn validate(&self) -> AppResult<()> {
+        if !Self::DELAY_RANGE.contains(&self.step_delay_ms) || !Self::DELAY_RANGE.contains(&self.last_delay_ms) {
+            return Err(AppError::Settings(format!(
+                "アニメーションの表示時間は{}〜{}ミリ秒で指定してください",
+                Self::DELAY_RANGE.start(),
+                Self::DELAY_RANGE.end()
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Get the step numbers used by annotations, in order
+pub fn steps(annotations: &[AnnotationItem]) -> Vec<u32> {
+    let mut steps: Vec<u32> = annotations.iter().filter_map(|annotation| annotation.step).collect();
+    steps.sort_unstable();
+    steps.dedup();
+    steps
+}
+
+/// Render the frames of a walkthrough
+///
+/// The first frame has only the untagged annotations, and each following
+/// frame adds the annotations of the next step. Annotations keep their
+/// stacking order in every frame.
+pub fn step_frames(image: &DynamicImage, annotations: &[AnnotationItem]) -> Vec<RgbaImage> {
+    let shown_until = |last: Option<u32>| -> Vec<AnnotationItem> {
+        annotations
+            .iter()
+            // `None` orders before any step, so untagged annotations are always shown
+            .filter(|annotation| annotation.step <= last)
+            .cloned()
+            .collect()
+    };
+    std::iter::once(None)
+        .chain(steps(annotations).into_iter().map(Some))
+        .map(|last| render::flatten(image, &shown_until(last)))
+        .collect()
+}
+
+/// Encode frames of the same size as an animation
+pub fn encode(frames: &[RgbaImage], options: &AnimationOptions) -> AppResult<Vec<u8>> {
+    if frames.is_empty() {
+        return Err(AppError::ImageProcessing("アニメーションのフレームがありません".to_string()));
+    }
+    let delays: Vec<u32> = (0..frames.len())
+        .map(|index| if index + 1 == frames.len() { options.last_delay_ms } else { options.step_delay_ms })
+        .collect();
+    match options.format {
+        AnimationFormat::Gif => encode_gif(frames, &delays, options.loop_forever)
+            .map_err(|e| AppError::ImageProcessing(format!("GIFのエンコードに失敗しました: {}", e))),
+        AnimationFormat::Apng => encode_apng(frames, &delays, options.loop_forever)
+            .map_err(|e| AppError::ImageProcessing(format!("APNGのエンコードに失敗しました: {}", e))),
+    }
+}
+
+fn encode_gif(frames: &[RgbaImage], delays: &[u32], loop_forever: bool) -> image::ImageResult<Vec<u8>> {
+    let mut data = Vec::new();
+    {
+        let mut encoder = GifEncoder::new_with_speed(&mut data, GIF_QUANTIZATION_SPEED);
+        encoder.set_repeat(if loop_forever { Repeat::Infinite } else { Repeat::Finite(0) })?;
+        encoder.encode_frames(
+            frames
+                .iter()
+                .zip(delays)
+                .map(|(frame, &delay)| Frame::from_parts(frame.clone(), 0, 0, Delay::from_numer_denom_ms(delay, 1))),
+        )?;
+    }
+    Ok(data)
+}
+
+fn encode_apng(frames: &[RgbaImage], delays: &[u32], loop_forever: bool) -> Result<Vec<u8>, png::EncodingError> {
+    let (width, height) = frames[0].dimensions();
+    let mut data = Vec::new();
+    let mut encoder = png::Encoder::new(&mut data, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    // Zero plays means forever
+    encoder.set_animated(frames.len() as u32, if loop_forever { 0 } else { 1 })?;
+    let mut writer = encoder.write_header()?;
+    for (frame, &delay) in frames.iter().zip(delays) {
+        writer.set_frame_delay(delay as u16, 1000)?;
+        writer.write_image_data(frame.as_raw())?;
+    }
+    writer.finish()?;
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use egui::{Pos2, Vec2};
+    use image::AnimationDecoder;
+
+    fn stepped(x: f32, step: Option<u32>) -> AnnotationItem {
+        AnnotationItem {
+            step,
+            ..AnnotationItem::new_rectangle(Pos2::new(x, 2.0), Vec2::new(6.0, 6.0))
+        }
+    }
+
+    #[test]
+    fn test_step_frames() {
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(40, 10, image::Rgba([255, 255, 255, 255])));
+        let annotations = [stepped(2.0, Some(3)), stepped(12.0, None), stepped(22.0, Some(1)), stepped(32.0, Some(3))];
+        assert_eq!(steps(&annotations), vec![1, 3]);
+
+        // Untagged first, then step 1, then step 3 (both of its annotations)
+        let frames = step_frames(&image, &annotations);
+        let drawn = |frame: &RgbaImage| -> Vec<bool> {
+            [2, 12, 22, 32].iter().map(|&x| frame.get_pixel(x, 2).0 != [255, 255, 255, 255]).collect()
+        };
+        let shown: Vec<Vec<bool>> = frames.iter().map(drawn).collect();
+        assert_eq!(
+            shown,
+            vec![
+                vec![false, true, false, false],
+                vec![false, true, true, false],
+                vec![true, true, true, true],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_encode_gif_and_apng() {
+        let frames: Vec<RgbaImage> = [0u8, 128, 255]
+            .iter()
+            .map(|&level| RgbaImage::from_pixel(8, 4, image::Rgba([level, level, level, 255])))
+            .collect();
+        let options = AnimationOptions { step_delay_ms: 500, last_delay_ms: 2000, ..AnimationOptions::default() };
+
+        let gif = encode(&frames, &options).unwrap();
+        let decoded = image::codecs::gif::GifDecoder::new(std::io::Cursor::new(gif))
+            .unwrap()
+            .into_frames()
+            .collect_frames()
+            .unwrap();
+        let delays: Vec<(u32, u32)> = decoded.iter().map(|frame| frame.delay().numer_denom_ms()).collect();
+        assert_eq!(delays, vec![(500, 1), (500, 1), (2000, 1)]);
+        assert_eq!(decoded[1].buffer().get_pixel(0, 0).0, [128, 128, 128, 255]);
+
+        let apng = encode(&frames, &AnimationOptions { format: AnimationFormat::Apng, loop_forever: false, ..options })
+            .unwrap();
+        let mut reader = png::Decoder::new(std::io::Cursor::new(apng)).read_info().unwrap();
+        let control = reader.info().animation_control().unwrap();
+        assert_eq!((control.num_frames, control.num_plays), (3, 1));
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let mut delays = Vec::new();
+        for _ in 0..3 {
+            reader.next_frame(&mut buffer).unwrap();
+            let frame = reader.info().frame_control().unwrap();
+            delays.push((frame.delay_num, frame.delay_den));
+        }
+        assert_eq!(delays, vec![(500, 1000), (500, 1000), (2000, 1000)]);
+        assert_eq!(&buffer[..4], &[255, 255, 255, 255]);
+
+        assert!(encode(&[], &options).is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(AnimationOptions::default().validate().is_ok());
+        assert!(AnimationOptions { step_delay_ms: 50, ..AnimationOptions::default() }.validate().is_err());
+        assert!(AnimationOptions { last_delay_ms: 20_000, ..AnimationOptions::default() }.validate().is_err());
+    }
+}
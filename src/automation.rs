@@ -0,0 +1,385 @@
+//! Automation API for external tools
+//!
+//! External tools command the running editor with JSON messages: start a
+//! capture, ask where the last capture was saved, open a file, or export the
+//! current image with custom options. The protocol is documented in
+//! `docs/api/automation.md`, and `examples/automation_client.rs` is a
+//! minimal client.
+//!
+//! Like the control interface, the server listens on a loopback port that is
+//! written to the per-user config directory together with a random token,
+//! so the same protocol works on every platform. Each connection carries one
+//! request line and gets one response line back. Requests are handed to the
+//! editor over the event bus and answered from the UI thread.
+
+use crate::control::Endpoint;
+use crate::events::{AppEvent, EventSender};
+use crate::export::ExportOptions;
+use crate::files::write_atomic;
+use crate::settings::config_dir;
+use crate::types::{AppError, AppResult};
+use crate::window_list::WindowSelector;
+use crossbeam_channel::Sender;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// File in the config directory describing the running instance's automation endpoint
+const ENDPOINT_FILE_NAME: &str = "automation.json";
+
+/// How long a connection may take to send its request line
+const READ_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long a request may wait for the editor, long enough for a capture to finish
+pub const REPLY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Get the default automation endpoint file path
+pub fn default_endpoint_path() -> PathBuf {
+    config_dir().join(ENDPOINT_FILE_NAME)
+}
+
+/// What a capture request captures
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CaptureTarget {
+    /// The screen, as File > New Screenshot would
+    #[default]
+    Screen,
+    /// Only the focused window
+    Window,
+}
+
+/// A command from an external tool
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum AutomationRequest {
+    /// Capture and open the result in the editor, answered once it is loaded
+    ///
+    /// With `window_title` or `process`, the one window matching them is
+    /// captured instead of `target`.
+    Capture {
+        #[serde(default)]
+        target: CaptureTarget,
+        /// Part of the title of the window to capture
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        window_title: Option<String>,
+        /// Executable name of the process of the window to capture
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        process: Option<String>,
+    },
+    /// Get the file the most recent capture was saved to
+    LastCapture,
+    /// Open an image or project file in the editor
+    Open { path: PathBuf },
+    /// Export the current image, using the saved export options unless given
+    Export {
+        path: PathBuf,
+        #[serde(default)]
+        options: Option<Box<ExportOptions>>,
+    },
+}
+
+impl AutomationRequest {
+    /// Create a capture request for a target
+    pub fn capture(target: CaptureTarget) -> Self {
+        AutomationRequest::Capture {
+            target,
+            window_title: None,
+            process: None,
+        }
+    }
+
+    /// Create a capture request for the one window matching `selector`
+    pub fn capture_window(selector: WindowSelector) -> Self {
+        AutomationRequest::Capture {
+            target: CaptureTarget::Window,
+            window_title: selector.title,
+            process: selector.process,
+        }
+    }
+}
+
+/// Result of a request: a JSON result object, or why it failed
+pub type AutomationResponse = Result<Value, AutomationError>;
+
+/// Why a request failed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AutomationError {
+    /// Stable identifier of the kind of error, such as `screen_not_found`
+    pub code: String,
+    /// Localized description for people
+    pub message: String,
+}
+
+impl AutomationError {
+    /// Create an error with a code and message
+    pub fn new(code: &str, message: impl Into<String>) -> Self {
+        Self {
+            code: code.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+impl From<&AppError> for AutomationError {
+    fn from(error: &AppError) -> Self {
+        Self::new(error.code(), error.to_string())
+    }
+}
+
+/// A request waiting for the editor to answer it
+#[derive(Debug, Clone)]
+pub struct AutomationCall {
+    pub request: AutomationRequest,
+    reply: Sender<AutomationResponse>,
+}
+
+impl AutomationCall {
+    /// Create a call answered over `reply`
+    pub fn new(request: AutomationRequest, reply: Sender<AutomationResponse>) -> Self {
+        Self { request, reply }
+    }
+
+    /// Answer the request
+    pub fn respond(self, response: AutomationResponse) {
+        // The client is gone if it timed out waiting
+        let _ = self.reply.send(response);
+    }
+}
+
+// Calls compare by request; where the answer goes doesn't affect what is asked
+impl PartialEq for AutomationCall {
+    fn eq(&self, other: &Self) -> bool {
+        self.request == other.request
+    }
+}
+
+/// Request line as sent over the connection
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct RequestMessage {
+    token: String,
+    #[serde(flatten)]
+    request: AutomationRequest,
+}
+
+/// Response line as sent over the connection
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct ResponseMessage {
+    ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    /// Identifier of the kind of error, alongside `error`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    code: Option<String>,
+}
+
+impl From<AutomationResponse> for ResponseMessage {
+    fn from(response: AutomationResponse) -> Self {
+        match response {
+            Ok(result) => Self { ok: true, result: Some(result), error: None, code: None },
+            Err(error) => Self { ok: false, result: None, error: Some(error.message), code: Some(error.code) },
+        }
+    }
+}
+
+/// Handle one request line, returning the response
+fn handle_request(line: &str, token: &str, events: &EventSender) -> AutomationResponse {
+    let message: RequestMessage =
+        serde_json::from_str(line).map_err(|e| AutomationError::new("invalid_request", format!("invalid request: {}", e)))?;
+    if message.token != token {
+        return Err(AutomationError::new("invalid_token", "invalid token"));
+    }
+    log::info!("Automation request {:?}", message.request);
+
+    let (reply, receiver) = crossbeam_channel::bounded(1);
+    if !events.send(AppEvent::Automation(AutomationCall::new(message.request, reply))) {
+        return Err(AutomationError::new("shutting_down", "the editor is shutting down"));
+    }
+    receiver
+        .recv_timeout(REPLY_TIMEOUT)
+        .unwrap_or_else(|_| Err(AutomationError::new("timeout", "timed out waiting for the editor")))
+}
+
+/// Listener for automation requests from other processes
+#[derive(Debug)]
+pub struct AutomationServer {
+    endpoint_path: PathBuf,
+    token: String,
+}
+
+impl AutomationServer {
+    /// Start listening on a loopback port and publish it in the endpoint file
+    ///
+    /// Requests are handled one at a time. The listener thread runs until
+    /// the process exits.
+    pub fn start(events: EventSender, endpoint_path: &Path) -> AppResult<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+        let endpoint = Endpoint {
+            port: listener.local_addr()?.port(),
+            token: uuid::Uuid::new_v4().simple().to_string(),
+        };
+        let json = serde_json::to_string(&endpoint)
+            .map_err(|e| AppError::Automation(format!("接続情報のシリアライズに失敗しました: {}", e)))?;
+        if let Some(parent) = endpoint_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        write_atomic(endpoint_path, json.as_bytes())?;
+
+        let token = endpoint.token.clone();
+        std::thread::Builder::new()
+            .name("automation-server".to_string())
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    let result = stream.and_then(|stream| {
+                        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+                        let mut line = String::new();
+                        BufReader::new(&stream).read_line(&mut line)?;
+                        let response = ResponseMessage::from(handle_request(&line, &token, &events));
+                        let json = serde_json::to_string(&response).map_err(std::io::Error::other)?;
+                        (&stream).write_all(format!("{}\n", json).as_bytes())
+                    });
+                    if let Err(e) = result {
+                        log::warn!("Automation connection failed: {}", e);
+                    }
+                }
+            })?;
+
+        log::info!("Listening for automation requests on port {}", endpoint.port);
+        Ok(Self {
+            endpoint_path: endpoint_path.to_path_buf(),
+            token: endpoint.token,
+        })
+    }
+}
+
+impl Drop for AutomationServer {
+    fn drop(&mut self) {
+        // Leave the file alone if another instance has taken over the endpoint
+        let ours = std::fs::read_to_string(&self.endpoint_path)
+            .ok()
+            .and_then(|json| serde_json::from_str::<Endpoint>(&json).ok())
+            .is_some_and(|endpoint| endpoint.token == self.token);
+        if ours {
+            let _ = std::fs::remove_file(&self.endpoint_path);
+        }
+    }
+}
+
+/// Send a request to the running instance, returning its result object
+pub fn send_request(endpoint_path: &Path, request: AutomationRequest) -> AppResult<Value> {
+    let json = std::fs::read_to_string(endpoint_path)
+        .map_err(|_| AppError::Automation("起動中のアプリが見つかりません".to_string()))?;
+    let endpoint: Endpoint = serde_json::from_str(&json)
+        .map_err(|e| AppError::Automation(format!("接続情報の解析に失敗しました: {}", e)))?;
+
+    let address = SocketAddr::from((Ipv4Addr::LOCALHOST, endpoint.port));
+    let stream = TcpStream::connect_timeout(&address, READ_TIMEOUT)
+        .map_err(|_| AppError::Automation("起動中のアプリに接続できません".to_string()))?;
+    // Leave the server time to time out on its own first
+    stream.set_read_timeout(Some(REPLY_TIMEOUT + READ_TIMEOUT))?;
+    let message = RequestMessage { token: endpoint.token, request };
+    let line = serde_json::to_string(&message)
+        .map_err(|e| AppError::Automation(format!("リクエストのシリアライズに失敗しました: {}", e)))?;
+    (&stream).write_all(format!("{}\n", line).as_bytes())?;
+
+    let mut response = String::new();
+    BufReader::new(&stream).read_line(&mut response)?;
+    let response: ResponseMessage = serde_json::from_str(&response)
+        .map_err(|e| AppError::Automation(format!("応答の解析に失敗しました: {}", e)))?;
+    match response {
+        ResponseMessage { ok: true, result, .. } => Ok(result.unwrap_or(Value::Null)),
+        ResponseMessage { error, .. } => Err(AppError::Automation(error.unwrap_or_default())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::EventBus;
+    use serde_json::json;
+
+    #[test]
+    fn test_request_format() {
+        let message: RequestMessage =
+            serde_json::from_str(r#"{"token":"t","command":"capture","target":"window"}"#).unwrap();
+        assert_eq!(message.request, AutomationRequest::capture(CaptureTarget::Window));
+
+        let message: RequestMessage = serde_json::from_str(r#"{"token":"t","command":"capture"}"#).unwrap();
+        assert_eq!(message.request, AutomationRequest::capture(CaptureTarget::Screen));
+
+        let message: RequestMessage =
+            serde_json::from_str(r#"{"token":"t","command":"capture","target":"window","process":"notepad.exe"}"#).unwrap();
+        let selector = WindowSelector { title: None, process: Some("notepad.exe".to_string()) };
+        assert_eq!(message.request, AutomationRequest::capture_window(selector));
+
+        let message: RequestMessage =
+            serde_json::from_str(r#"{"token":"t","command":"export","path":"out.png","options":{"resize":{"Percentage":50.0}}}"#)
+                .unwrap();
+        let AutomationRequest::Export { path, options: Some(options) } = message.request else {
+            panic!("expected an export request with options");
+        };
+        assert_eq!(path, PathBuf::from("out.png"));
+        assert_eq!(options.resize, crate::export::ResizeMode::Percentage(50.0));
+
+        assert!(serde_json::from_str::<RequestMessage>(r#"{"token":"t","command":"shutdown"}"#).is_err());
+        assert_eq!(
+            serde_json::to_string(&ResponseMessage::from(Err(AutomationError::new("automation", "nope")))).unwrap(),
+            r#"{"ok":false,"error":"nope","code":"automation"}"#
+        );
+        let error = AutomationError::from(&AppError::ScreenNotFound { index: 3 });
+        assert_eq!(error, AutomationError::new("screen_not_found", "画面 3 が見つかりません"));
+    }
+
+    #[test]
+    fn test_requests_over_loopback() {
+        let dir = std::env::temp_dir().join(format!("automation_test_{}", uuid::Uuid::new_v4()));
+        let endpoint_path = dir.join(ENDPOINT_FILE_NAME);
+        let bus = EventBus::new();
+        let server = AutomationServer::start(bus.sender(), &endpoint_path).unwrap();
+
+        // Stand in for the editor's UI loop
+        let editor = std::thread::spawn(move || {
+            let started = std::time::Instant::now();
+            while started.elapsed() < Duration::from_secs(10) {
+                for event in bus.drain() {
+                    let AppEvent::Automation(call) = event else {
+                        continue;
+                    };
+                    let response = match &call.request {
+                        AutomationRequest::LastCapture => Ok(json!({ "path": "shot.png" })),
+                        _ => Err(AutomationError::new("unsupported", "not supported")),
+                    };
+                    let done = matches!(call.request, AutomationRequest::Open { .. });
+                    call.respond(response);
+                    if done {
+                        return;
+                    }
+                }
+                std::thread::sleep(Duration::from_millis(1));
+            }
+        });
+
+        assert_eq!(
+            send_request(&endpoint_path, AutomationRequest::LastCapture).unwrap(),
+            json!({ "path": "shot.png" })
+        );
+        let error = send_request(&endpoint_path, AutomationRequest::Open { path: PathBuf::from("a.png") });
+        assert!(matches!(error, Err(AppError::Automation(message)) if message == "not supported"));
+        editor.join().unwrap();
+
+        assert_eq!(
+            handle_request(r#"{"token":"wrong","command":"last_capture"}"#, &server.token, &EventBus::new().sender()),
+            Err(AutomationError::new("invalid_token", "invalid token"))
+        );
+
+        drop(server);
+        assert!(!endpoint_path.exists());
+        assert!(send_request(&endpoint_path, AutomationRequest::LastCapture).is_err());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
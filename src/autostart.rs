@@ -0,0 +1,147 @@
+//! Launch at login
+//!
+//! On Windows the app is registered under the current user's `Run` registry
+//! key, started with [`BACKGROUND_FLAG`] so only the hotkey listener and the
+//! control and automation servers run until a capture opens the editor.
+//! Other platforms don't support launching at login.
+
+use crate::types::{AppError, AppResult};
+use std::path::Path;
+
+/// Command line flag starting the app without showing the editor window
+pub const BACKGROUND_FLAG: &str = "--background";
+
+/// Registry key listing the programs started at login
+#[cfg_attr(not(windows), allow(dead_code))]
+const RUN_KEY: &str = r"Software\Microsoft\Windows\CurrentVersion\Run";
+
+/// Name of the app's value under the `Run` key
+#[cfg_attr(not(windows), allow(dead_code))]
+const RUN_VALUE_NAME: &str = "LightweightScreenshotApp";
+
+/// Get the command line registered to run at login
+pub fn launch_command(executable: &Path) -> String {
+    format!("\"{}\" {}", executable.display(), BACKGROUND_FLAG)
+}
+
+/// Register or unregister the running executable to start at login
+///
+/// Registering again after the executable moved updates the registered path.
+pub fn set_launch_at_login(enabled: bool) -> AppResult<()> {
+    if enabled {
+        let executable = std::env::current_exe()?;
+        let command = launch_command(&executable);
+        if registered_command().as_deref() != Some(command.as_str()) {
+            write_run_value(Some(&command))?;
+            log::info!("Registered to launch at login: {}", command);
+        }
+    } else if registered_command().is_some() {
+        write_run_value(None)?;
+        log::info!("Unregistered launch at login");
+    }
+    Ok(())
+}
+
+/// Get the command line registered to run at login, if any
+#[cfg(windows)]
+pub fn registered_command() -> Option<String> {
+    use crate::files::to_wide;
+    use winapi::shared::minwindef::DWORD;
+    use winapi::um::winreg::{RegGetValueW, HKEY_CURRENT_USER, RRF_RT_REG_SZ};
+
+    let key = to_wide(RUN_KEY);
+    let name = to_wide(RUN_VALUE_NAME);
+    let mut buffer = vec![0u16; 32_768];
+    let mut size = (buffer.len() * 2) as DWORD;
+    // SAFETY: the strings are null-terminated and `size` is the buffer size in bytes
+    let status = unsafe {
+        RegGetValueW(
+            HKEY_CURRENT_USER,
+            key.as_ptr(),
+            name.as_ptr(),
+            RRF_RT_REG_SZ,
+            std::ptr::null_mut(),
+            buffer.as_mut_ptr().cast(),
+            &mut size,
+        )
+    };
+    if status != 0 {
+        return None;
+    }
+    let length = buffer.iter().position(|&c| c == 0).unwrap_or(buffer.len());
+    Some(String::from_utf16_lossy(&buffer[..length]))
+}
+
+/// Get the command line registered to run at login, if any
+///
+/// Launching at login is not supported on this platform, so this always
+/// returns `None`.
+#[cfg(not(windows))]
+pub fn registered_command() -> Option<String> {
+    None
+}
+
+/// Set or delete the app's value under the `Run` key
+#[cfg(windows)]
+fn write_run_value(command: Option<&str>) -> AppResult<()> {
+    use crate::files::to_wide;
+    use winapi::um::winnt::REG_SZ;
+    use winapi::um::winreg::{RegDeleteKeyValueW, RegSetKeyValueW, HKEY_CURRENT_USER};
+
+    let key = to_wide(RUN_KEY);
+    let name = to_wide(RUN_VALUE_NAME);
+    let status = match command {
+        Some(command) => {
+            let data = to_wide(command);
+            // SAFETY: the strings are null-terminated and the data size includes the terminator
+            unsafe {
+                RegSetKeyValueW(
+                    HKEY_CURRENT_USER,
+                    key.as_ptr(),
+                    name.as_ptr(),
+                    REG_SZ,
+                    data.as_ptr().cast(),
+                    (data.len() * 2) as u32,
+                )
+            }
+        }
+        // SAFETY: the strings are null-terminated
+        None => unsafe { RegDeleteKeyValueW(HKEY_CURRENT_USER, key.as_ptr(), name.as_ptr()) },
+    };
+    if status != 0 {
+        return Err(AppError::Settings(format!(
+            "ログイン時の起動を登録できませんでした (エラー {})",
+            status
+        )));
+    }
+    Ok(())
+}
+
+/// Set or delete the app's value under the `Run` key
+#[cfg(not(windows))]
+fn write_run_value(_command: Option<&str>) -> AppResult<()> {
+    Err(AppError::Settings("この環境ではログイン時の起動に対応していません".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_launch_command() {
+        let executable = Path::new(r"C:\Program Files\Screenshot App\app.exe");
+        assert_eq!(
+            launch_command(executable),
+            r#""C:\Program Files\Screenshot App\app.exe" --background"#
+        );
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn test_unsupported_platform() {
+        assert!(registered_command().is_none());
+        // Nothing is registered, so disabling has nothing to do
+        assert!(set_launch_at_login(false).is_ok());
+        assert!(matches!(set_launch_at_login(true), Err(AppError::Settings(_))));
+    }
+}
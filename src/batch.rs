@@ -0,0 +1,316 @@
+//! Batch processing of image folders
+//!
+//! `batch --input DIR --output DIR --preset NAME` applies a batch preset to
+//! every image in a folder: the annotations of a template project are drawn
+//! on, the preset's export options (resize, watermark, banner, decoration)
+//! are applied, and the result is written in the preset's format to the
+//! output folder. Images go through the same render and export pipeline as
+//! editor exports. An image that fails is reported and skipped; the others
+//! are still processed.
+
+use crate::decode::{self, DecodeLimits};
+use crate::export::{self, ExportOptions};
+use crate::files::write_atomic;
+use crate::project::ProjectFile;
+use crate::render;
+use crate::types::{AnnotationItem, AppError, AppResult, ImageFormat};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Extensions of the files processed in the input folder
+const INPUT_EXTENSIONS: [&str; 6] = ["png", "jpg", "jpeg", "bmp", "gif", "webp"];
+
+/// A named set of batch processing steps
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BatchPreset {
+    pub name: String,
+    /// Project file whose annotations are drawn on every image, if any
+    pub template: Option<PathBuf>,
+    /// Format of the written files
+    pub format: ImageFormat,
+    /// Resize, watermark, banner, and decoration applied to every image
+    pub export: ExportOptions,
+}
+
+impl Default for BatchPreset {
+    fn default() -> Self {
+        Self {
+            name: "New Batch Preset".to_string(),
+            template: None,
+            format: ImageFormat::Png,
+            export: ExportOptions::default(),
+        }
+    }
+}
+
+impl BatchPreset {
+    /// Validate the preset on its own
+    pub fn validate(&self) -> AppResult<()> {
+        if self.name.trim().is_empty() {
+            return Err(AppError::Settings("一括処理プリセットの名前が空です".to_string()));
+        }
+        self.export.validate()
+    }
+
+    /// Load the annotations of the template project, if the preset has one
+    pub fn template_annotations(&self) -> AppResult<Vec<AnnotationItem>> {
+        match &self.template {
+            Some(path) => Ok(ProjectFile::load_from(path)
+                .map_err(|e| e.context(format!("テンプレート {} を読み込めません", path.display())))?
+                .annotations),
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+/// Validate a list of batch presets: each on its own, with unique names
+pub fn validate_batch_presets(presets: &[BatchPreset]) -> AppResult<()> {
+    let mut names = HashSet::new();
+    for preset in presets {
+        preset.validate()?;
+        if !names.insert(preset.name.trim()) {
+            return Err(AppError::Settings(format!("一括処理プリセット「{}」が重複しています", preset.name)));
+        }
+    }
+    Ok(())
+}
+
+/// Find a batch preset by name, ignoring surrounding whitespace
+pub fn find_preset<'a>(presets: &'a [BatchPreset], name: &str) -> AppResult<&'a BatchPreset> {
+    presets.iter().find(|preset| preset.name.trim() == name.trim()).ok_or_else(|| {
+        let names: Vec<&str> = presets.iter().map(|preset| preset.name.as_str()).collect();
+        AppError::Batch(format!(
+            "一括処理プリセット「{}」が見つかりません (登録済み: {})",
+            name,
+            if names.is_empty() { "なし".to_string() } else { names.join(", ") }
+        ))
+    })
+}
+
+/// Arguments of the `batch` command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchArgs {
+    pub input: PathBuf,
+    pub output: PathBuf,
+    pub preset: String,
+}
+
+impl BatchArgs {
+    /// Parse command arguments, e.g. `["--input", "shots", "--output", "out", "--preset", "Docs"]`
+    pub fn parse<S: AsRef<str>>(args: &[S]) -> AppResult<Self> {
+        let usage = || AppError::Batch("使い方: batch --input <フォルダ> --output <フォルダ> --preset <名前>".to_string());
+        let (mut input, mut output, mut preset) = (None, None, None);
+        let mut args = args.iter().map(AsRef::as_ref);
+        while let Some(arg) = args.next() {
+            let value = args.next().ok_or_else(usage)?;
+            match arg {
+                "--input" => input = Some(PathBuf::from(value)),
+                "--output" => output = Some(PathBuf::from(value)),
+                "--preset" => preset = Some(value.to_string()),
+                _ => return Err(usage()),
+            }
+        }
+        match (input, output, preset) {
+            (Some(input), Some(output), Some(preset)) => Ok(Self { input, output, preset }),
+            _ => Err(usage()),
+        }
+    }
+}
+
+/// Outcome of a batch run
+#[derive(Debug, Default)]
+pub struct BatchReport {
+    /// Files written, in input order
+    pub written: Vec<PathBuf>,
+    /// Input files that couldn't be processed, with the reason
+    pub failed: Vec<(PathBuf, AppError)>,
+}
+
+impl BatchReport {
+    /// Describe the outcome in one line, e.g. "12 images processed, 1 failed"
+    pub fn summary(&self) -> String {
+        let processed = format!(
+            "{} image{} processed",
+            self.written.len(),
+            if self.written.len() == 1 { "" } else { "s" }
+        );
+        if self.failed.is_empty() {
+            processed
+        } else {
+            format!("{}, {} failed", processed, self.failed.len())
+        }
+    }
+}
+
+/// List the images in a folder, sorted by name
+///
+/// Subfolders and files with other extensions are skipped.
+pub fn input_images(directory: &Path) -> AppResult<Vec<PathBuf>> {
+    let entries = std::fs::read_dir(directory)
+        .map_err(|e| AppError::from(e).context(format!("入力フォルダ {} を読み込めません", directory.display())))?;
+    let mut images = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        let is_image = path.extension().and_then(|ext| ext.to_str()).is_some_and(|ext| {
+            INPUT_EXTENSIONS.iter().any(|allowed| ext.eq_ignore_ascii_case(allowed))
+        });
+        if is_image && path.is_file() {
+            images.push(path);
+        }
+    }
+    images.sort();
+    Ok(images)
+}
+
+/// Process every image in `input`, writing the results to `output`
+///
+/// Outputs are named after their input with the preset's extension, and
+/// replace files of the same name, so a run can be repeated. Images whose
+/// names would collide get a counter. Errors that stop the whole run,
+/// such as a missing template, are returned; errors of single images are
+/// collected in the report.
+pub fn run(input: &Path, output: &Path, preset: &BatchPreset) -> AppResult<BatchReport> {
+    preset.validate()?;
+    if same_directory(input, output) {
+        return Err(AppError::Batch("出力フォルダは入力フォルダと別のフォルダを指定してください".to_string()));
+    }
+    let annotations = preset.template_annotations()?;
+    let images = input_images(input)?;
+    std::fs::create_dir_all(output)
+        .map_err(|e| AppError::from(e).context(format!("出力フォルダ {} を作成できません", output.display())))?;
+
+    let mut report = BatchReport::default();
+    let mut used_names = HashSet::new();
+    for path in images {
+        let stem = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+        let mut name = stem.clone();
+        let mut counter = 2;
+        while !used_names.insert(name.to_lowercase()) {
+            name = format!("{}_{}", stem, counter);
+            counter += 1;
+        }
+        let target = output.join(format!("{}.{}", name, preset.format.extension()));
+        match process_image(&path, &target, &annotations, preset) {
+            Ok(()) => {
+                log::info!("Batch processed {} to {}", path.display(), target.display());
+                report.written.push(target);
+            }
+            Err(e) => {
+                log::warn!("Batch processing of {} failed: {}", path.display(), e);
+                report.failed.push((path, e));
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Annotate, transform, and write one image
+fn process_image(path: &Path, target: &Path, annotations: &[AnnotationItem], preset: &BatchPreset) -> AppResult<()> {
+    let image = decode::decode_untrusted_file(path, DecodeLimits::default())?;
+    // The banner shows when the screenshot was taken, which the file's age approximates
+    let captured_at = std::fs::metadata(path).and_then(|metadata| metadata.modified()).unwrap_or_else(|_| SystemTime::now());
+    let composite = render::flatten(&image, annotations);
+    let output = export::prepare_export(&composite, &preset.export, captured_at);
+    write_atomic(target, &export::encode_image(&output, &preset.format)?)
+}
+
+/// Check whether two paths name the same existing folder
+fn same_directory(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::{ResizeFilter, ResizeMode};
+    use egui::{Pos2, Vec2};
+    use image::{Rgba, RgbaImage};
+    use uuid::Uuid;
+
+    #[test]
+    fn test_parse_args() {
+        let args = BatchArgs::parse(&["--preset", "Docs", "--input", "shots", "--output", "out"]).unwrap();
+        assert_eq!(
+            args,
+            BatchArgs { input: PathBuf::from("shots"), output: PathBuf::from("out"), preset: "Docs".to_string() }
+        );
+        assert!(BatchArgs::parse(&["--input", "shots", "--output", "out"]).is_err());
+        assert!(BatchArgs::parse(&["--input", "shots", "--output", "out", "--preset"]).is_err());
+        assert!(BatchArgs::parse(&["--input", "a", "--output", "b", "--preset", "c", "--force", "yes"]).is_err());
+    }
+
+    #[test]
+    fn test_presets() {
+        let docs = BatchPreset { name: "Docs".to_string(), ..BatchPreset::default() };
+        let presets = vec![docs.clone(), BatchPreset { name: "Blog".to_string(), ..BatchPreset::default() }];
+        assert!(validate_batch_presets(&presets).is_ok());
+        assert!(validate_batch_presets(&[docs.clone(), BatchPreset { name: " Docs ".to_string(), ..docs.clone() }]).is_err());
+        assert!(BatchPreset { name: " ".to_string(), ..BatchPreset::default() }.validate().is_err());
+
+        assert_eq!(find_preset(&presets, " Docs").unwrap().name, "Docs");
+        let missing = find_preset(&presets, "Wiki").unwrap_err().to_string();
+        assert!(missing.contains("Docs, Blog"));
+    }
+
+    #[test]
+    fn test_run_batch() {
+        let root = std::env::temp_dir().join(format!("lsa-batch-{}", Uuid::new_v4()));
+        let input = root.join("input");
+        let output = root.join("output");
+        std::fs::create_dir_all(input.join("nested")).unwrap();
+        let white = RgbaImage::from_pixel(40, 20, Rgba([255, 255, 255, 255]));
+        export::save_image(&white, &input.join("b.png"), &ImageFormat::Png).unwrap();
+        export::save_image(&white, &input.join("a.bmp"), &ImageFormat::Bmp).unwrap();
+        export::save_image(&white, &input.join("a.png"), &ImageFormat::Png).unwrap();
+        std::fs::write(input.join("broken.png"), b"not a png").unwrap();
+        std::fs::write(input.join("notes.txt"), b"skipped").unwrap();
+
+        // A red box in the top-left corner of every image
+        let mut rectangle = AnnotationItem::new_rectangle(Pos2::new(0.0, 0.0), Vec2::new(10.0, 10.0));
+        if let crate::types::AnnotationType::Rectangle { fill_color, .. } = &mut rectangle.annotation_type {
+            *fill_color = Some(egui::Color32::RED);
+        }
+        let template = root.join("template.lsaproj");
+        ProjectFile::new(PathBuf::from("template.png"), [40, 20], vec![rectangle]).save_to(&template).unwrap();
+        let preset = BatchPreset {
+            name: "Docs".to_string(),
+            template: Some(template),
+            format: ImageFormat::Png,
+            export: ExportOptions {
+                resize: ResizeMode::Percentage(50.0),
+                filter: ResizeFilter::Nearest,
+                ..ExportOptions::default()
+            },
+        };
+
+        assert!(run(&input, &input, &preset).is_err());
+        let report = run(&input, &output, &preset).unwrap();
+        let names: Vec<String> =
+            report.written.iter().map(|path| path.file_name().unwrap().to_string_lossy().into_owned()).collect();
+        assert_eq!(names, vec!["a.png", "a_2.png", "b.png"]);
+        assert_eq!(report.failed.len(), 1);
+        assert!(report.failed[0].0.ends_with("broken.png"));
+        assert_eq!(report.summary(), "3 images processed, 1 failed");
+
+        let written = image::open(&report.written[2]).unwrap().to_rgba8();
+        assert_eq!(written.dimensions(), (20, 10));
+        assert_eq!(written.get_pixel(2, 2).0, [255, 0, 0, 255]);
+        assert_eq!(written.get_pixel(15, 8).0, [255, 255, 255, 255]);
+
+        // Running again replaces the outputs instead of adding numbered copies
+        assert_eq!(run(&input, &output, &preset).unwrap().written, report.written);
+        assert_eq!(input_images(&output).unwrap().len(), 3);
+
+        // Without the template the whole run stops
+        let missing = BatchPreset { template: Some(root.join("missing.lsaproj")), ..preset };
+        assert!(run(&input, &output, &missing).is_err());
+
+        std::fs::remove_dir_all(&root).ok();
+    }
+}
@@ -2,38 +2,632 @@
 //! 
 //! This module provides screen capture services including full screen capture,
 //! area-specific capture, and multi-monitor support using the screenshots crate.
-
+//! Captures can also run on a worker thread so the editor stays responsive
+//! while large multi-monitor frames are grabbed, decoded, and converted.
+//!
+//! Screens come from a [`CaptureBackend`]: the system backend uses the
+//! screenshots crate, and [`mock`] serves synthetic screens for tests.
+//!
+//! Window captures take the visible frame of the window, leaving out the
+//! invisible resize borders Windows 10 and 11 put around it. The rounded
+//! corners of Windows 11 windows are made transparent, and a drop shadow
+//! can be added on a transparent background, so the capture pastes cleanly
+//! onto any background.
+
+#[cfg(windows)]
+mod dxgi;
+pub mod mock;
+
+use crate::color::{ColorProfile, ColorTransform};
+use crate::foreground::{self, ForegroundWindow};
+use crate::hdr::{self, HdrImage, HdrOptions};
+use crate::image_ops::{self, DropShadow};
+use crate::window_list::{self, WindowInfo, WindowSelector};
+use crate::profiling::{self, Stage};
 use crate::types::{AppError, AppResult, CaptureArea, ScreenInfo};
+use crossbeam_channel::{Receiver, TryRecvError};
 use egui::{Pos2, Rect, Vec2};
-use image::DynamicImage;
+use image::{DynamicImage, RgbaImage};
 use screenshots::Screen;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// A captured frame converted to RGBA, with the screen it came from
+#[derive(Debug, Clone)]
+pub struct CapturedFrame {
+    pub image: RgbaImage,
+    pub screen: ScreenInfo,
+    /// Title of the captured window, for window captures
+    pub window_title: Option<String>,
+    /// Executable name of the captured window's application, for window and region captures
+    pub application: Option<String>,
+    /// ICC profile of the pixels, when they are left in a non-sRGB color space
+    pub icc_profile: Option<Arc<Vec<u8>>>,
+}
+
+/// Outline of a top-level window, in desktop coordinates like screen bounds
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct WindowFrame {
+    /// Bounds of the visible frame, without invisible resize borders
+    pub frame: Rect,
+    /// Radius of the rounded corners at 100% scaling, 0 for square corners
+    pub corner_radius: f32,
+}
+
+/// How captures of single windows are cleaned up
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WindowCaptureOptions {
+    /// Make the parts outside rounded window corners transparent
+    pub round_corners: bool,
+    /// Frame the window in a drop shadow like the system's, on a transparent background
+    pub include_shadow: bool,
+}
+
+impl Default for WindowCaptureOptions {
+    fn default() -> Self {
+        Self {
+            round_corners: true,
+            include_shadow: false,
+        }
+    }
+}
+
+/// Clean up a captured window with corners of `corner_radius` physical pixels
+///
+/// `scale` is the display scaling of the window's screen, which the drop
+/// shadow is sized for.
+pub fn clean_window_image(mut image: RgbaImage, corner_radius: f32, scale: f32, options: WindowCaptureOptions) -> RgbaImage {
+    let radius = if options.round_corners { corner_radius } else { 0.0 };
+    image_ops::round_corners(&mut image, radius);
+    if options.include_shadow {
+        image = image_ops::add_drop_shadow(&image, radius, DropShadow::WINDOW.scaled(scale));
+    }
+    image
+}
+
+/// A capture running on a worker thread
+#[derive(Debug)]
+pub struct PendingCapture {
+    receiver: Receiver<AppResult<CapturedFrame>>,
+    started: Instant,
+}
+
+impl PendingCapture {
+    /// Run `capture` on a worker thread
+    pub fn spawn(capture: impl FnOnce() -> AppResult<CapturedFrame> + Send + 'static) -> AppResult<Self> {
+        let (sender, receiver) = crossbeam_channel::bounded(1);
+        std::thread::Builder::new()
+            .name("capture".to_string())
+            .spawn(move || {
+                let timer = profiling::scope(Stage::Capture);
+                let result = capture();
+                drop(timer);
+                // The receiver is gone if the capture was abandoned
+                let _ = sender.send(result);
+            })
+            .map_err(|e| AppError::ScreenCapture(format!("Failed to start capture thread: {}", e)))?;
+
+        Ok(Self {
+            receiver,
+            started: Instant::now(),
+        })
+    }
+
+    /// Get the result if the capture has finished
+    pub fn poll(&self) -> Option<AppResult<CapturedFrame>> {
+        match self.receiver.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => {
+                Some(Err(AppError::ScreenCapture("Capture thread stopped unexpectedly".to_string())))
+            }
+        }
+    }
+
+    /// Time since the capture started
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+}
+
+/// A capture prepared on the calling thread that can run on any thread
+pub type CaptureJob = Box<dyn FnOnce() -> AppResult<DynamicImage> + Send>;
+
+/// An HDR capture prepared on the calling thread that can run on any thread
+pub type HdrCaptureJob = Box<dyn FnOnce() -> AppResult<HdrImage> + Send>;
+
+/// Source of screens and their pixels
+pub trait CaptureBackend: Send {
+    /// Enumerate the connected screens
+    fn enumerate(&mut self) -> Vec<ScreenInfo>;
+
+    /// Prepare a capture of a screen from the last enumeration
+    fn capture_job(&self, screen_index: usize) -> AppResult<CaptureJob>;
+
+    /// Get the bounds of the monitor showing the focused window, if the platform can tell
+    fn active_monitor_bounds(&self) -> Option<Rect> {
+        None
+    }
+
+    /// Get the desktop bounds of the focused window, if the platform can tell
+    fn active_window_bounds(&self) -> Option<Rect> {
+        None
+    }
+
+    /// Get the visible frame and corner radius of the focused window, if the platform can tell
+    ///
+    /// Without it, window captures use [`Self::active_window_bounds`] as they are.
+    fn active_window_frame(&self) -> Option<WindowFrame> {
+        None
+    }
+
+    /// Get the title of the focused window, if the platform can tell
+    fn active_window_title(&self) -> Option<String> {
+        None
+    }
+
+    /// List the visible top-level windows, front to back
+    fn windows(&self) -> Vec<WindowInfo> {
+        Vec::new()
+    }
+
+    /// Get the executable name of the focused window's application, if the platform can tell
+    fn active_application(&self) -> Option<String> {
+        None
+    }
+
+    /// Get the desktop position of the mouse cursor, if the platform can tell
+    fn cursor_position(&self) -> Option<Pos2> {
+        None
+    }
+
+    /// Get the ICC color profile of a screen from the last enumeration, if it has one
+    fn color_profile(&self, _screen_index: usize) -> Option<Vec<u8>> {
+        None
+    }
+
+    /// Check whether a screen from the last enumeration is in HDR mode
+    fn is_hdr(&self, _screen_index: usize) -> bool {
+        false
+    }
+
+    /// Prepare a capture of an HDR screen in linear scRGB
+    fn capture_hdr_job(&self, screen_index: usize) -> AppResult<HdrCaptureJob> {
+        Err(AppError::CaptureFailed {
+            screen: screen_index,
+            source: "this backend can't capture in HDR".into(),
+        })
+    }
+}
+
+/// Backend capturing the real screens with the screenshots crate
+#[derive(Debug, Default)]
+pub struct SystemBackend {
+    screens: Vec<Screen>,
+}
+
+impl CaptureBackend for SystemBackend {
+    fn enumerate(&mut self) -> Vec<ScreenInfo> {
+        self.screens = Screen::all();
+        self.screens
+            .iter()
+            .enumerate()
+            .map(|(index, screen)| ScreenInfo {
+                index,
+                // Convert screen coordinates to egui Rect
+                bounds: Rect::from_min_size(
+                    Pos2::new(screen.x as f32, screen.y as f32),
+                    Vec2::new(screen.width as f32, screen.height as f32),
+                ),
+                // For now, assume 1.0 DPI scaling - this can be enhanced later with proper DPI detection
+                dpi_scale_x: 1.0,
+                dpi_scale_y: 1.0,
+                // Assume the first screen is primary - this can be enhanced later
+                is_primary: index == 0,
+            })
+            .collect()
+    }
+
+    fn capture_job(&self, screen_index: usize) -> AppResult<CaptureJob> {
+        let screen = *self
+            .screens
+            .get(screen_index)
+            .ok_or(AppError::ScreenNotFound { index: screen_index })?;
+        Ok(Box::new(move || capture_system_screen(&screen, screen_index)))
+    }
+
+    fn active_monitor_bounds(&self) -> Option<Rect> {
+        foreground_monitor_bounds()
+    }
+
+    fn active_window_bounds(&self) -> Option<Rect> {
+        foreground_window_bounds()
+    }
+
+    fn active_window_frame(&self) -> Option<WindowFrame> {
+        foreground_window_frame()
+    }
+
+    fn active_window_title(&self) -> Option<String> {
+        foreground::window_title()
+    }
+
+    fn windows(&self) -> Vec<WindowInfo> {
+        window_list::enumerate()
+    }
+
+    fn active_application(&self) -> Option<String> {
+        foreground::application()
+    }
+
+    fn cursor_position(&self) -> Option<Pos2> {
+        system_cursor_position()
+    }
+
+    fn color_profile(&self, screen_index: usize) -> Option<Vec<u8>> {
+        monitor_color_profile(screen_center(self.screens.get(screen_index)?))
+    }
+
+    fn is_hdr(&self, screen_index: usize) -> bool {
+        self.screens.get(screen_index).is_some_and(|screen| monitor_is_hdr(screen_center(screen)))
+    }
+
+    fn capture_hdr_job(&self, screen_index: usize) -> AppResult<HdrCaptureJob> {
+        let screen = self
+            .screens
+            .get(screen_index)
+            .ok_or(AppError::ScreenNotFound { index: screen_index })?;
+        let center = screen_center(screen);
+        Ok(Box::new(move || capture_hdr_monitor(center, screen_index)))
+    }
+}
+
+/// Get the desktop point at the middle of a screen
+fn screen_center(screen: &Screen) -> Pos2 {
+    Pos2::new(
+        screen.x as f32 + screen.width as f32 / 2.0,
+        screen.y as f32 + screen.height as f32 / 2.0,
+    )
+}
+
+/// Get the bounds of the monitor showing the foreground window
+#[cfg(windows)]
+fn foreground_monitor_bounds() -> Option<Rect> {
+    use winapi::um::winuser::{
+        GetForegroundWindow, GetMonitorInfoW, MonitorFromWindow, MONITORINFO, MONITOR_DEFAULTTONEAREST,
+    };
+
+    // SAFETY: GetForegroundWindow has no preconditions and may return null
+    let window = unsafe { GetForegroundWindow() };
+    if window.is_null() {
+        return None;
+    }
+    // SAFETY: `window` is a window handle returned by the system; a stale handle yields null
+    let monitor = unsafe { MonitorFromWindow(window, MONITOR_DEFAULTTONEAREST) };
+    if monitor.is_null() {
+        return None;
+    }
+    let mut info: MONITORINFO = unsafe { std::mem::zeroed() };
+    info.cbSize = std::mem::size_of::<MONITORINFO>() as u32;
+    // SAFETY: `info` is a writable MONITORINFO with its size field set
+    if unsafe { GetMonitorInfoW(monitor, &mut info) } == 0 {
+        return None;
+    }
+    let rect = info.rcMonitor;
+    Some(Rect::from_min_max(
+        Pos2::new(rect.left as f32, rect.top as f32),
+        Pos2::new(rect.right as f32, rect.bottom as f32),
+    ))
+}
+
+/// Get the bounds of the monitor showing the foreground window
+#[cfg(not(windows))]
+fn foreground_monitor_bounds() -> Option<Rect> {
+    None
+}
+
+/// Get the desktop position of the mouse cursor
+#[cfg(windows)]
+fn system_cursor_position() -> Option<Pos2> {
+    use winapi::shared::windef::POINT;
+    use winapi::um::winuser::GetCursorPos;
+
+    let mut point = POINT { x: 0, y: 0 };
+    // SAFETY: `point` is a writable POINT; this fails on secure desktops such as the lock screen
+    if unsafe { GetCursorPos(&mut point) } == 0 {
+        return None;
+    }
+    Some(Pos2::new(point.x as f32, point.y as f32))
+}
+
+/// Get the desktop position of the mouse cursor
+#[cfg(not(windows))]
+fn system_cursor_position() -> Option<Pos2> {
+    None
+}
+
+/// Get the desktop bounds of the foreground window
+#[cfg(windows)]
+fn foreground_window_bounds() -> Option<Rect> {
+    use winapi::shared::windef::RECT;
+    use winapi::um::winuser::{GetForegroundWindow, GetWindowRect, IsIconic};
+
+    // SAFETY: GetForegroundWindow has no preconditions and may return null
+    let window = unsafe { GetForegroundWindow() };
+    // SAFETY: `window` is a window handle returned by the system; a stale handle yields false
+    if window.is_null() || unsafe { IsIconic(window) } != 0 {
+        return None;
+    }
+    let mut rect: RECT = unsafe { std::mem::zeroed() };
+    // SAFETY: `rect` is a writable RECT
+    if unsafe { GetWindowRect(window, &mut rect) } == 0 {
+        return None;
+    }
+    Some(Rect::from_min_max(
+        Pos2::new(rect.left as f32, rect.top as f32),
+        Pos2::new(rect.right as f32, rect.bottom as f32),
+    ))
+}
+
+/// Get the desktop bounds of the foreground window
+#[cfg(not(windows))]
+fn foreground_window_bounds() -> Option<Rect> {
+    None
+}
+
+/// Arrange screen images by their desktop bounds on one image at `scale`
+fn compose_desktop(desktop: Rect, scale: f32, screens: &[(Rect, RgbaImage)]) -> RgbaImage {
+    let size = (desktop.size() * scale).round();
+    let mut canvas = RgbaImage::new(size.x as u32, size.y as u32);
+    for (bounds, image) in screens {
+        let position = ((bounds.min - desktop.min) * scale).round();
+        let target = (bounds.size() * scale).round();
+        let (width, height) = (target.x as u32, target.y as u32);
+        if image.dimensions() == (width, height) {
+            image::imageops::replace(&mut canvas, image, position.x as i64, position.y as i64);
+        } else {
+            let resized = image::imageops::resize(image, width, height, image::imageops::FilterType::Triangle);
+            image::imageops::replace(&mut canvas, &resized, position.x as i64, position.y as i64);
+        }
+    }
+    canvas
+}
+
+/// Get the visible frame and corner radius of the foreground window from the window manager
+///
+/// Windows 11 rounds the corners of windows that aren't maximized, unless
+/// the application asks for square or small corners. Windows 10 doesn't
+/// know the corner preference, so its windows get square corners.
+#[cfg(windows)]
+fn foreground_window_frame() -> Option<WindowFrame> {
+    use winapi::um::winuser::GetForegroundWindow;
+
+    // SAFETY: GetForegroundWindow has no preconditions and may return null
+    let window = unsafe { GetForegroundWindow() };
+    if window.is_null() {
+        return None;
+    }
+    window_frame(window)
+}
+
+/// Get the visible frame and corner radius of a window, `None` if it is minimized
+#[cfg(windows)]
+pub(crate) fn window_frame(window: winapi::shared::windef::HWND) -> Option<WindowFrame> {
+    use winapi::shared::windef::RECT;
+    use winapi::shared::winerror::S_OK;
+    use winapi::um::dwmapi::{DwmGetWindowAttribute, DWMWA_EXTENDED_FRAME_BOUNDS};
+    use winapi::um::winuser::{IsIconic, IsZoomed};
+
+    /// `DWMWA_WINDOW_CORNER_PREFERENCE`, added in Windows 11
+    const DWMWA_WINDOW_CORNER_PREFERENCE: u32 = 33;
+    /// `DWMWCP_DONOTROUND`
+    const DWMWCP_DONOTROUND: u32 = 1;
+    /// `DWMWCP_ROUNDSMALL`
+    const DWMWCP_ROUNDSMALL: u32 = 3;
+
+    // SAFETY: `window` is a window handle returned by the system; a stale handle yields false
+    if unsafe { IsIconic(window) } != 0 {
+        return None;
+    }
+    let mut rect: RECT = unsafe { std::mem::zeroed() };
+    // SAFETY: `rect` is a writable RECT of the size passed
+    let result = unsafe {
+        DwmGetWindowAttribute(
+            window,
+            DWMWA_EXTENDED_FRAME_BOUNDS,
+            &mut rect as *mut RECT as *mut _,
+            std::mem::size_of::<RECT>() as u32,
+        )
+    };
+    if result != S_OK {
+        return None;
+    }
+
+    let mut preference: u32 = 0;
+    // SAFETY: `preference` is a writable u32 of the size passed
+    let result = unsafe {
+        DwmGetWindowAttribute(
+            window,
+            DWMWA_WINDOW_CORNER_PREFERENCE,
+            &mut preference as *mut u32 as *mut _,
+            std::mem::size_of::<u32>() as u32,
+        )
+    };
+    // SAFETY: `window` is a window handle returned by the system
+    let maximized = unsafe { IsZoomed(window) } != 0;
+    let corner_radius = match preference {
+        _ if result != S_OK || maximized => 0.0,
+        DWMWCP_DONOTROUND => 0.0,
+        DWMWCP_ROUNDSMALL => 4.0,
+        _ => 8.0,
+    };
+    Some(WindowFrame {
+        frame: Rect::from_min_max(
+            Pos2::new(rect.left as f32, rect.top as f32),
+            Pos2::new(rect.right as f32, rect.bottom as f32),
+        ),
+        corner_radius,
+    })
+}
+
+/// Get the visible frame and corner radius of the foreground window
+#[cfg(not(windows))]
+fn foreground_window_frame() -> Option<WindowFrame> {
+    None
+}
+
+/// Read the ICC profile assigned to the monitor containing a desktop point
+#[cfg(windows)]
+fn monitor_color_profile(point: Pos2) -> Option<Vec<u8>> {
+    use std::os::windows::ffi::OsStringExt;
+    use winapi::shared::windef::POINT;
+    use winapi::um::wingdi::{CreateDCW, DeleteDC, GetICMProfileW};
+    use winapi::um::winuser::{GetMonitorInfoW, MonitorFromPoint, MONITORINFOEXW, MONITOR_DEFAULTTONULL};
+
+    let point = POINT { x: point.x as i32, y: point.y as i32 };
+    // SAFETY: MonitorFromPoint has no preconditions and may return null
+    let monitor = unsafe { MonitorFromPoint(point, MONITOR_DEFAULTTONULL) };
+    if monitor.is_null() {
+        return None;
+    }
+    let mut info: MONITORINFOEXW = unsafe { std::mem::zeroed() };
+    info.cbSize = std::mem::size_of::<MONITORINFOEXW>() as u32;
+    // SAFETY: `info` is a writable MONITORINFOEXW with its size field set
+    if unsafe { GetMonitorInfoW(monitor, &mut info as *mut MONITORINFOEXW as *mut _) } == 0 {
+        return None;
+    }
+    let driver = crate::files::to_wide("DISPLAY");
+    // SAFETY: both names are null-terminated; the device name comes from the system
+    let dc = unsafe { CreateDCW(driver.as_ptr(), info.szDevice.as_ptr(), std::ptr::null(), std::ptr::null()) };
+    if dc.is_null() {
+        return None;
+    }
+    let mut path = [0u16; 260];
+    let mut length = path.len() as u32;
+    // SAFETY: the buffer length is passed in characters; `dc` is released right after
+    let found = unsafe { GetICMProfileW(dc, &mut length, path.as_mut_ptr()) };
+    // SAFETY: `dc` was created above and is not used afterwards
+    unsafe { DeleteDC(dc) };
+    if found == 0 {
+        return None;
+    }
+    let end = path.iter().position(|&unit| unit == 0).unwrap_or(path.len());
+    let path = std::path::PathBuf::from(std::ffi::OsString::from_wide(&path[..end]));
+    match std::fs::read(&path) {
+        Ok(data) => Some(data),
+        Err(e) => {
+            log::warn!("Failed to read color profile {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// Read the ICC profile assigned to the monitor containing a desktop point
+#[cfg(not(windows))]
+fn monitor_color_profile(_point: Pos2) -> Option<Vec<u8>> {
+    None
+}
+
+/// Check whether the monitor containing a desktop point is in HDR mode
+#[cfg(windows)]
+fn monitor_is_hdr(point: Pos2) -> bool {
+    dxgi::is_hdr_output(point)
+}
+
+/// Check whether the monitor containing a desktop point is in HDR mode
+#[cfg(not(windows))]
+fn monitor_is_hdr(_point: Pos2) -> bool {
+    false
+}
+
+/// Capture the monitor containing a desktop point in linear scRGB
+#[cfg(windows)]
+fn capture_hdr_monitor(point: Pos2, screen: usize) -> AppResult<HdrImage> {
+    dxgi::capture_output(point).map_err(|source| AppError::CaptureFailed { screen, source })
+}
+
+/// Capture the monitor containing a desktop point in linear scRGB
+#[cfg(not(windows))]
+fn capture_hdr_monitor(_point: Pos2, screen: usize) -> AppResult<HdrImage> {
+    Err(AppError::CaptureFailed { screen, source: "HDR capture is not supported on this platform".into() })
+}
+
+/// Grab and decode a screen
+fn capture_system_screen(screen: &Screen, screen_index: usize) -> AppResult<DynamicImage> {
+    let image = screen.capture().ok_or_else(|| AppError::CaptureFailed {
+        screen: screen_index,
+        source: "the system returned no image".into(),
+    })?;
+
+    // Convert screenshots::Image to image::DynamicImage
+    // The screenshots crate returns PNG-encoded data, so we need to decode it
+    let buffer = image.buffer();
+
+    // Decode the PNG data using the image crate
+    let dynamic_image = image::load_from_memory(buffer).map_err(|e| AppError::CaptureFailed {
+        screen: screen_index,
+        source: Box::new(e),
+    })?;
+
+    Ok(dynamic_image)
+}
+
+/// Color handling of a screen whose profile isn't sRGB
+#[derive(Debug, Clone)]
+struct ScreenColor {
+    icc_profile: Arc<Vec<u8>>,
+    to_srgb: Arc<ColorTransform>,
+}
 
 /// Service for capturing screenshots
 pub struct CaptureService {
-    screens: Vec<Screen>,
+    backend: Box<dyn CaptureBackend>,
     screen_cache: HashMap<usize, ScreenInfo>,
+    /// Screens with a non-sRGB color profile
+    screen_colors: HashMap<usize, ScreenColor>,
+    /// Convert captures of those screens to sRGB
+    convert_to_srgb: bool,
+    /// Capture and tone mapping of screens in HDR mode
+    hdr: HdrOptions,
+    /// Cleanup of window captures
+    window_options: WindowCaptureOptions,
+}
+
+impl std::fmt::Debug for CaptureService {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CaptureService").field("screens", &self.screen_cache.len()).finish()
+    }
 }
 
 impl CaptureService {
     /// Create a new capture service instance
     pub fn new() -> AppResult<Self> {
-        let screens = Screen::all();
-
-        if screens.is_empty() {
-            return Err(AppError::ScreenCapture(
-                "No screens found on the system".to_string(),
-            ));
-        }
+        Self::with_backend(SystemBackend::default())
+    }
 
+    /// Create a capture service using a specific backend
+    pub fn with_backend(backend: impl CaptureBackend + 'static) -> AppResult<Self> {
         let mut service = Self {
-            screens,
+            backend: Box::new(backend),
             screen_cache: HashMap::new(),
+            screen_colors: HashMap::new(),
+            convert_to_srgb: true,
+            hdr: HdrOptions::default(),
+            window_options: WindowCaptureOptions::default(),
         };
 
         // Initialize screen cache
         service.refresh_screen_info()?;
-        
+
+        if service.screen_cache.is_empty() {
+            return Err(AppError::ScreenCapture(
+                "No screens found on the system".to_string(),
+            ));
+        }
+
         Ok(service)
     }
 
@@ -43,27 +637,323 @@ impl CaptureService {
         self.capture_screen_by_index(primary_screen.index)
     }
 
+    /// Set whether captures of screens with a non-sRGB profile are converted to sRGB
+    pub fn set_convert_to_srgb(&mut self, convert: bool) {
+        self.convert_to_srgb = convert;
+    }
+
+    /// Set how screens in HDR mode are captured and tone mapped
+    pub fn set_hdr_options(&mut self, options: HdrOptions) {
+        self.hdr = options;
+    }
+
+    /// Set how captures of single windows are cleaned up
+    pub fn set_window_capture_options(&mut self, options: WindowCaptureOptions) {
+        self.window_options = options;
+    }
+
+    /// Get the ICC profile of a screen, if it has one that isn't sRGB
+    pub fn screen_icc_profile(&self, screen_index: usize) -> Option<&[u8]> {
+        self.screen_colors.get(&screen_index).map(|color| color.icc_profile.as_slice())
+    }
+
     /// Capture a specific screen by index
     pub fn capture_screen_by_index(&self, screen_index: usize) -> AppResult<DynamicImage> {
-        let screen = self.screens.get(screen_index).ok_or_else(|| {
-            AppError::ScreenCapture(format!("Screen index {} not found", screen_index))
-        })?;
+        self.screen_job(screen_index)?.0()
+    }
+
+    /// Prepare a capture of a screen, converted to sRGB if enabled
+    ///
+    /// Screens in HDR mode are captured in full precision and tone mapped,
+    /// falling back to the 8-bit image if that fails. Also returns the ICC
+    /// profile of the captured pixels when they are left in the screen's
+    /// non-sRGB color space.
+    fn screen_job(&self, screen_index: usize) -> AppResult<(CaptureJob, Option<Arc<Vec<u8>>>)> {
+        let job = self.backend.capture_job(screen_index)?;
+        if self.hdr.capture_hdr && self.backend.is_hdr(screen_index) {
+            let hdr_job = self.backend.capture_hdr_job(screen_index)?;
+            let options = self.hdr.clone();
+            let tone_mapped: CaptureJob = Box::new(move || match hdr_job() {
+                Ok(image) => Ok(DynamicImage::ImageRgba8(hdr::tone_map(&image, &options))),
+                Err(e) => {
+                    log::warn!("Falling back to the 8-bit desktop image: {}", e);
+                    job()
+                }
+            });
+            // Tone mapping outputs sRGB
+            return Ok((tone_mapped, None));
+        }
+        match self.screen_colors.get(&screen_index) {
+            None => Ok((job, None)),
+            Some(color) if !self.convert_to_srgb => Ok((job, Some(color.icc_profile.clone()))),
+            Some(color) => {
+                let to_srgb = color.to_srgb.clone();
+                let converted: CaptureJob = Box::new(move || {
+                    let mut image = job()?.into_rgba8();
+                    to_srgb.apply(&mut image);
+                    Ok(DynamicImage::ImageRgba8(image))
+                });
+                Ok((converted, None))
+            }
+        }
+    }
+
+    /// Capture a screen by index on a worker thread
+    ///
+    /// Grabbing, PNG decoding, and RGBA conversion all happen off the UI thread.
+    pub fn capture_screen_async(&self, screen_index: usize) -> AppResult<PendingCapture> {
+        let (job, icc_profile) = self.screen_job(screen_index)?;
+        let screen_info = self.get_screen_info(screen_index)?.clone();
+
+        PendingCapture::spawn(move || {
+            let image = job()?.into_rgba8();
+            Ok(CapturedFrame { image, screen: screen_info, window_title: None, application: None, icc_profile })
+        })
+    }
+
+    /// Capture every screen, arranged as on the desktop, on a worker thread
+    ///
+    /// The screens are drawn at the highest display scaling among them so
+    /// none loses detail; screens with lower scaling are enlarged. Gaps
+    /// between screens of different sizes stay transparent. The frame's
+    /// screen spans the whole desktop.
+    pub fn capture_all_screens_async(&self) -> AppResult<PendingCapture> {
+        let mut screens = self.get_screens();
+        screens.sort_by_key(|screen| screen.index);
+        let primary = self.get_primary_screen()?.index;
+        let jobs = screens
+            .iter()
+            .map(|screen| self.screen_job(screen.index))
+            .collect::<AppResult<Vec<_>>>()?;
+        // Screens left in different color spaces can't share one profile
+        let first_profile = jobs.first().and_then(|(_, profile)| profile.clone());
+        let icc_profile = jobs
+            .iter()
+            .all(|(_, profile)| *profile == first_profile)
+            .then_some(first_profile)
+            .flatten();
+        let desktop = self.get_desktop_bounds();
+        let scale = screens.iter().map(|screen| screen.dpi_scale_x).fold(1.0, f32::max);
+        let desktop_info = ScreenInfo {
+            index: primary,
+            bounds: desktop,
+            dpi_scale_x: scale,
+            dpi_scale_y: scale,
+            is_primary: false,
+        };
+
+        PendingCapture::spawn(move || {
+            let mut images = Vec::with_capacity(jobs.len());
+            for ((job, _), screen) in jobs.into_iter().zip(&screens) {
+                images.push((screen.bounds, job()?.into_rgba8()));
+            }
+            Ok(CapturedFrame {
+                image: compose_desktop(desktop, scale, &images),
+                screen: desktop_info,
+                window_title: None,
+                application: None,
+                icc_profile,
+            })
+        })
+    }
+
+    /// Capture the primary screen on a worker thread
+    pub fn capture_primary_screen_async(&self) -> AppResult<PendingCapture> {
+        let primary_screen = self.get_primary_screen()?;
+        self.capture_screen_async(primary_screen.index)
+    }
+
+    /// Capture the monitor showing the focused window, or the primary screen if unknown
+    pub fn capture_active_monitor(&self) -> AppResult<DynamicImage> {
+        let active_screen = self.get_active_screen()?;
+        self.capture_screen_by_index(active_screen.index)
+    }
+
+    /// Capture the monitor showing the focused window on a worker thread
+    pub fn capture_active_monitor_async(&self) -> AppResult<PendingCapture> {
+        let active_screen = self.get_active_screen()?;
+        self.capture_screen_async(active_screen.index)
+    }
+
+    /// Capture the monitor containing the mouse cursor on a worker thread
+    pub fn capture_cursor_monitor_async(&self) -> AppResult<PendingCapture> {
+        let cursor_screen = self.get_cursor_screen()?;
+        self.capture_screen_async(cursor_screen.index)
+    }
+
+    /// Capture the focused window
+    ///
+    /// Only the part of the window on the screen it mostly covers is
+    /// captured; parts on other monitors or off the desktop are cut off.
+    /// Windows that are fully on their screen are cleaned up per the
+    /// [`WindowCaptureOptions`].
+    pub fn capture_active_window(&self) -> AppResult<DynamicImage> {
+        let (area, corner_radius) = self.active_window_target()?;
+        let image = self.capture_area(&area)?;
+        Ok(match corner_radius {
+            Some(radius) => DynamicImage::ImageRgba8(clean_window_image(
+                image.into_rgba8(),
+                radius,
+                area.dpi_scale_x,
+                self.window_options,
+            )),
+            None => image,
+        })
+    }
+
+    /// Capture the focused window on a worker thread
+    pub fn capture_active_window_async(&self) -> AppResult<PendingCapture> {
+        let (area, corner_radius) = self.active_window_target()?;
+        // Read while the window still has focus
+        let window = self.foreground_window();
+        self.capture_area_async(area, window, corner_radius)
+    }
+
+    /// Capture the one window matching `selector`, whether or not it is focused
+    ///
+    /// The window is captured from the screen like the focused window, so
+    /// windows in front of it show in the capture.
+    pub fn capture_window(&self, selector: &WindowSelector) -> AppResult<DynamicImage> {
+        let window = self.find_window(selector)?;
+        let (area, corner_radius) = self.window_target(window.frame.frame, window.frame.corner_radius)?;
+        let image = self.capture_area(&area)?;
+        Ok(match corner_radius {
+            Some(radius) => DynamicImage::ImageRgba8(clean_window_image(
+                image.into_rgba8(),
+                radius,
+                area.dpi_scale_x,
+                self.window_options,
+            )),
+            None => image,
+        })
+    }
+
+    /// Capture the one window matching `selector` on a worker thread
+    pub fn capture_window_async(&self, selector: &WindowSelector) -> AppResult<PendingCapture> {
+        let window = self.find_window(selector)?;
+        let (area, corner_radius) = self.window_target(window.frame.frame, window.frame.corner_radius)?;
+        let foreground = ForegroundWindow {
+            title: Some(window.title),
+            application: window.application,
+        };
+        self.capture_area_async(area, foreground, corner_radius)
+    }
+
+    /// Find the one window matching `selector`
+    pub fn find_window(&self, selector: &WindowSelector) -> AppResult<WindowInfo> {
+        selector.find(&self.backend.windows()).cloned()
+    }
+
+    /// Get the executable name of the focused window's application
+    pub fn active_application(&self) -> Option<String> {
+        self.backend.active_application()
+    }
+
+    /// Get the title and application of the focused window
+    pub fn foreground_window(&self) -> ForegroundWindow {
+        ForegroundWindow {
+            title: self.backend.active_window_title(),
+            application: self.backend.active_application(),
+        }
+    }
 
-        let image = screen.capture().ok_or_else(|| {
-            AppError::ScreenCapture(format!("Failed to capture screen {}", screen_index))
+    /// Capture a rectangle of the desktop on a worker thread
+    ///
+    /// Like the focused window, only the part on the screen the rectangle
+    /// mostly covers is captured. The focused window is recorded as the
+    /// window the region shows.
+    pub fn capture_region_async(&self, bounds: Rect) -> AppResult<PendingCapture> {
+        let screen_info = self.find_screen_overlapping(bounds).ok_or_else(|| AppError::RegionOutOfBounds {
+            requested: bounds,
+            available: self.get_desktop_bounds(),
         })?;
+        let area = Self::clipped_area(bounds, screen_info);
+        self.capture_area_async(area, self.foreground_window(), None)
+    }
 
-        // Convert screenshots::Image to image::DynamicImage
-        // The screenshots crate returns PNG-encoded data, so we need to decode it
-        let buffer = image.buffer();
-        
-        // Decode the PNG data using the image crate
-        let dynamic_image = image::load_from_memory(buffer)
-            .map_err(|e| {
-                AppError::ScreenCapture(format!("Failed to decode PNG data: {}", e))
-            })?;
+    /// Capture an area of one screen on a worker thread
+    ///
+    /// With a `corner_radius`, the area is a whole window and is cleaned up
+    /// like in [`Self::capture_active_window`].
+    fn capture_area_async(
+        &self,
+        area: CaptureArea,
+        window: ForegroundWindow,
+        corner_radius: Option<f32>,
+    ) -> AppResult<PendingCapture> {
+        let (job, icc_profile) = self.screen_job(area.screen_index)?;
+        let screen_info = self.get_screen_info(area.screen_index)?.clone();
+        let window_options = self.window_options;
+
+        PendingCapture::spawn(move || {
+            let bounds = area.physical_bounds();
+            let image = job()?
+                .crop_imm(
+                    bounds.min.x as u32,
+                    bounds.min.y as u32,
+                    bounds.width() as u32,
+                    bounds.height() as u32,
+                )
+                .into_rgba8();
+            let image = match corner_radius {
+                Some(radius) => clean_window_image(image, radius, area.dpi_scale_x, window_options),
+                None => image,
+            };
+            Ok(CapturedFrame {
+                image,
+                screen: screen_info,
+                window_title: window.title,
+                application: window.application,
+                icc_profile,
+            })
+        })
+    }
 
-        Ok(dynamic_image)
+    /// Get the capture area of the focused window, clipped to the screen it mostly covers
+    pub fn active_window_area(&self) -> AppResult<CaptureArea> {
+        Ok(self.active_window_target()?.0)
+    }
+
+    /// Get the capture area of the focused window and its corner radius in physical pixels
+    ///
+    /// The area is the visible frame when the platform reports it.
+    fn active_window_target(&self) -> AppResult<(CaptureArea, Option<f32>)> {
+        let (window, corner_radius) = match self.backend.active_window_frame() {
+            Some(frame) => (frame.frame, frame.corner_radius),
+            None => (
+                self.backend
+                    .active_window_bounds()
+                    .ok_or_else(|| AppError::WindowNotFound("前面のウィンドウ".to_string()))?,
+                0.0,
+            ),
+        };
+        self.window_target(window, corner_radius)
+    }
+
+    /// Get the capture area of a window frame and its corner radius in physical pixels
+    ///
+    /// The radius is `None` when the window is cut off by its screen, since
+    /// the captured corners then aren't the window's.
+    fn window_target(&self, window: Rect, corner_radius: f32) -> AppResult<(CaptureArea, Option<f32>)> {
+        let screen_info = self
+            .find_screen_overlapping(window)
+            .ok_or_else(|| AppError::NoScreen("ウィンドウと重なる画面".to_string()))?;
+        let corner_radius = screen_info
+            .bounds
+            .contains_rect(window)
+            .then_some(corner_radius * screen_info.dpi_scale_x);
+        Ok((Self::clipped_area(window, screen_info), corner_radius))
+    }
+
+    /// Get the capture area of a desktop rectangle clipped to a screen
+    fn clipped_area(bounds: Rect, screen_info: &ScreenInfo) -> CaptureArea {
+        let visible = bounds.intersect(screen_info.bounds);
+        CaptureArea::with_dpi_scaling(
+            visible.translate(-screen_info.bounds.min.to_vec2()),
+            screen_info.index,
+            screen_info.dpi_scale_x,
+            screen_info.dpi_scale_y,
+        )
     }
 
     /// Capture a specific area of the screen
@@ -74,15 +964,16 @@ impl CaptureService {
         // Get physical bounds accounting for DPI scaling
         let physical_bounds = area.physical_bounds();
         
-        // Validate bounds
-        let screen_info = self.get_screen_info(area.screen_index)?;
-        if physical_bounds.min.x < 0.0 
-            || physical_bounds.min.y < 0.0 
-            || physical_bounds.max.x > screen_info.bounds.max.x * screen_info.dpi_scale_x
-            || physical_bounds.max.y > screen_info.bounds.max.y * screen_info.dpi_scale_y {
-            return Err(AppError::ScreenCapture(
-                "Capture area extends beyond screen boundaries".to_string(),
-            ));
+        // Validate bounds against the captured pixels
+        let available = Rect::from_min_size(
+            Pos2::ZERO,
+            Vec2::new(full_image.width() as f32, full_image.height() as f32),
+        );
+        if !available.contains_rect(physical_bounds) {
+            return Err(AppError::RegionOutOfBounds {
+                requested: physical_bounds,
+                available,
+            });
         }
 
         // Crop the image to the specified area
@@ -103,9 +994,9 @@ impl CaptureService {
 
     /// Get information about a specific screen
     pub fn get_screen_info(&self, screen_index: usize) -> AppResult<&ScreenInfo> {
-        self.screen_cache.get(&screen_index).ok_or_else(|| {
-            AppError::ScreenCapture(format!("Screen info for index {} not found", screen_index))
-        })
+        self.screen_cache
+            .get(&screen_index)
+            .ok_or(AppError::ScreenNotFound { index: screen_index })
     }
 
     /// Get the primary screen information
@@ -113,42 +1004,76 @@ impl CaptureService {
         self.screen_cache
             .values()
             .find(|screen| screen.is_primary)
-            .ok_or_else(|| {
-                AppError::ScreenCapture("No primary screen found".to_string())
+            .ok_or_else(|| AppError::NoScreen("プライマリ画面".to_string()))
+    }
+
+    /// Get the screen showing the focused window, falling back to the primary screen
+    pub fn get_active_screen(&self) -> AppResult<&ScreenInfo> {
+        match self.backend.active_monitor_bounds().and_then(|bounds| self.find_screen_overlapping(bounds)) {
+            Some(screen) => Ok(screen),
+            None => {
+                log::debug!("Active monitor unknown, using the primary screen");
+                self.get_primary_screen()
+            }
+        }
+    }
+
+    /// Get the screen containing the mouse cursor, falling back to the primary screen
+    pub fn get_cursor_screen(&self) -> AppResult<&ScreenInfo> {
+        match self.backend.cursor_position().and_then(|point| self.find_screen_at_point(point)) {
+            Some(screen) => Ok(screen),
+            None => {
+                log::debug!("Cursor position unknown, using the primary screen");
+                self.get_primary_screen()
+            }
+        }
+    }
+
+    /// Find the screen sharing the largest area with a rectangle
+    ///
+    /// Monitor rectangles reported by the OS can differ slightly from the
+    /// enumerated screen bounds (e.g. under DPI scaling), so the closest
+    /// match by overlap is used rather than an exact comparison.
+    pub fn find_screen_overlapping(&self, bounds: Rect) -> Option<&ScreenInfo> {
+        self.screen_cache
+            .values()
+            .map(|screen| (screen, screen.bounds.intersect(bounds)))
+            .filter(|(_, overlap)| overlap.is_positive())
+            .max_by(|(a, overlap_a), (b, overlap_b)| {
+                overlap_a.area().total_cmp(&overlap_b.area()).then(b.index.cmp(&a.index))
             })
+            .map(|(screen, _)| screen)
     }
 
     /// Refresh screen information (useful when display configuration changes)
     pub fn refresh_screen_info(&mut self) -> AppResult<()> {
         self.screen_cache.clear();
-        
-        // Refresh the screens list
-        self.screens = Screen::all();
-
-        // Rebuild screen cache
-        for (index, screen) in self.screens.iter().enumerate() {
-            // Convert screen coordinates to egui Rect
-            let bounds = Rect::from_min_size(
-                Pos2::new(screen.x as f32, screen.y as f32),
-                Vec2::new(screen.width as f32, screen.height as f32),
-            );
 
-            // For now, assume 1.0 DPI scaling - this can be enhanced later with proper DPI detection
-            let dpi_scale_x = 1.0;
-            let dpi_scale_y = 1.0;
-
-            // Assume the first screen is primary - this can be enhanced later
-            let is_primary = index == 0;
+        // Refresh the screens list and rebuild the screen cache
+        for screen_info in self.backend.enumerate() {
+            self.screen_cache.insert(screen_info.index, screen_info);
+        }
 
-            let screen_info = ScreenInfo {
-                index,
-                bounds,
-                dpi_scale_x,
-                dpi_scale_y,
-                is_primary,
+        // Monitor profiles can change along with the display configuration
+        self.screen_colors.clear();
+        for &index in self.screen_cache.keys() {
+            let Some(data) = self.backend.color_profile(index) else {
+                continue;
             };
-
-            self.screen_cache.insert(index, screen_info);
+            match ColorProfile::parse(&data) {
+                Ok(profile) if profile.is_srgb() => {}
+                Ok(profile) => {
+                    log::info!("Screen {} uses color profile \"{}\"", index, profile.description);
+                    self.screen_colors.insert(
+                        index,
+                        ScreenColor {
+                            icc_profile: Arc::new(data),
+                            to_srgb: Arc::new(ColorTransform::to_srgb(&profile)),
+                        },
+                    );
+                }
+                Err(e) => log::warn!("Ignoring color profile of screen {}: {}", index, e),
+            }
         }
 
         Ok(())
@@ -202,8 +1127,9 @@ impl CaptureService {
         // Find which screen contains the center of the selection
         let center = bounds.center();
         let screen_info = self.find_screen_at_point(center)
-            .ok_or_else(|| {
-                AppError::ScreenCapture("Selection area is not within any screen".to_string())
+            .ok_or_else(|| AppError::RegionOutOfBounds {
+                requested: bounds,
+                available: self.get_desktop_bounds(),
             })?;
 
         // Convert to screen-relative coordinates
@@ -232,8 +1158,12 @@ impl Default for CaptureService {
         Self::new().unwrap_or_else(|_| {
             // Fallback for when screen enumeration fails
             Self {
-                screens: Vec::new(),
+                backend: Box::new(SystemBackend::default()),
                 screen_cache: HashMap::new(),
+                screen_colors: HashMap::new(),
+                convert_to_srgb: true,
+                hdr: HdrOptions::default(),
+                window_options: WindowCaptureOptions::default(),
             }
         })
     }
@@ -248,7 +1178,7 @@ mod tests {
         // This test might fail in headless environments, so we handle that gracefully
         match CaptureService::new() {
             Ok(service) => {
-                assert!(!service.screens.is_empty());
+                assert!(!service.get_screens().is_empty());
                 assert!(!service.screen_cache.is_empty());
             }
             Err(AppError::ScreenCapture(_)) => {
@@ -264,14 +1194,18 @@ mod tests {
         let service = CaptureService::default();
         // Should not panic even if screen enumeration fails
         // This test ensures the default constructor doesn't panic
-        let _screen_count = service.screens.len();
+        let _screen_count = service.get_screens().len();
     }
 
     #[test]
     fn test_desktop_bounds_empty_screens() {
         let service = CaptureService {
-            screens: Vec::new(),
+            backend: Box::new(SystemBackend::default()),
             screen_cache: HashMap::new(),
+            screen_colors: HashMap::new(),
+            convert_to_srgb: true,
+            hdr: HdrOptions::default(),
+            window_options: WindowCaptureOptions::default(),
         };
         
         let bounds = service.get_desktop_bounds();
@@ -282,8 +1216,12 @@ mod tests {
     #[test]
     fn test_desktop_bounds_single_screen() {
         let mut service = CaptureService {
-            screens: Vec::new(),
+            backend: Box::new(SystemBackend::default()),
             screen_cache: HashMap::new(),
+            screen_colors: HashMap::new(),
+            convert_to_srgb: true,
+            hdr: HdrOptions::default(),
+            window_options: WindowCaptureOptions::default(),
         };
 
         // Add a mock screen
@@ -304,8 +1242,12 @@ mod tests {
     #[test]
     fn test_desktop_bounds_multiple_screens() {
         let mut service = CaptureService {
-            screens: Vec::new(),
+            backend: Box::new(SystemBackend::default()),
             screen_cache: HashMap::new(),
+            screen_colors: HashMap::new(),
+            convert_to_srgb: true,
+            hdr: HdrOptions::default(),
+            window_options: WindowCaptureOptions::default(),
         };
 
         // Add mock screens
@@ -335,8 +1277,12 @@ mod tests {
     #[test]
     fn test_find_screen_at_point() {
         let mut service = CaptureService {
-            screens: Vec::new(),
+            backend: Box::new(SystemBackend::default()),
             screen_cache: HashMap::new(),
+            screen_colors: HashMap::new(),
+            convert_to_srgb: true,
+            hdr: HdrOptions::default(),
+            window_options: WindowCaptureOptions::default(),
         };
 
         let screen_info = ScreenInfo {
@@ -361,8 +1307,12 @@ mod tests {
     #[test]
     fn test_create_capture_area() {
         let mut service = CaptureService {
-            screens: Vec::new(),
+            backend: Box::new(SystemBackend::default()),
             screen_cache: HashMap::new(),
+            screen_colors: HashMap::new(),
+            convert_to_srgb: true,
+            hdr: HdrOptions::default(),
+            window_options: WindowCaptureOptions::default(),
         };
 
         let screen_info = ScreenInfo {
@@ -390,8 +1340,12 @@ mod tests {
     #[test]
     fn test_create_capture_area_normalized_coordinates() {
         let mut service = CaptureService {
-            screens: Vec::new(),
+            backend: Box::new(SystemBackend::default()),
             screen_cache: HashMap::new(),
+            screen_colors: HashMap::new(),
+            convert_to_srgb: true,
+            hdr: HdrOptions::default(),
+            window_options: WindowCaptureOptions::default(),
         };
 
         let screen_info = ScreenInfo {
@@ -418,8 +1372,12 @@ mod tests {
     #[test]
     fn test_create_capture_area_outside_screen() {
         let mut service = CaptureService {
-            screens: Vec::new(),
+            backend: Box::new(SystemBackend::default()),
             screen_cache: HashMap::new(),
+            screen_colors: HashMap::new(),
+            convert_to_srgb: true,
+            hdr: HdrOptions::default(),
+            window_options: WindowCaptureOptions::default(),
         };
 
         let screen_info = ScreenInfo {
@@ -439,47 +1397,45 @@ mod tests {
         assert!(result.is_err());
         
         match result.unwrap_err() {
-            AppError::ScreenCapture(msg) => {
-                assert!(msg.contains("not within any screen"));
+            AppError::RegionOutOfBounds { requested, available } => {
+                assert_eq!(requested, Rect::from_min_max(start, end));
+                assert_eq!(available, Rect::from_min_size(Pos2::ZERO, Vec2::new(1920.0, 1080.0)));
             }
-            _ => panic!("Expected ScreenCapture error"),
+            _ => panic!("Expected RegionOutOfBounds error"),
         }
     }
 
     #[test]
     fn test_get_primary_screen_not_found() {
         let service = CaptureService {
-            screens: Vec::new(),
+            backend: Box::new(SystemBackend::default()),
             screen_cache: HashMap::new(),
+            screen_colors: HashMap::new(),
+            convert_to_srgb: true,
+            hdr: HdrOptions::default(),
+            window_options: WindowCaptureOptions::default(),
         };
 
-        let result = service.get_primary_screen();
-        assert!(result.is_err());
-        
-        match result.unwrap_err() {
-            AppError::ScreenCapture(msg) => {
-                assert!(msg.contains("No primary screen found"));
-            }
-            _ => panic!("Expected ScreenCapture error"),
-        }
+        let error = service.get_primary_screen().unwrap_err();
+        assert!(matches!(error, AppError::NoScreen(_)));
+        assert_eq!((error.code(), error.exit_code()), ("screen_not_found", 3));
     }
 
     #[test]
     fn test_get_screen_info_not_found() {
         let service = CaptureService {
-            screens: Vec::new(),
+            backend: Box::new(SystemBackend::default()),
             screen_cache: HashMap::new(),
+            screen_colors: HashMap::new(),
+            convert_to_srgb: true,
+            hdr: HdrOptions::default(),
+            window_options: WindowCaptureOptions::default(),
         };
 
         let result = service.get_screen_info(0);
         assert!(result.is_err());
         
-        match result.unwrap_err() {
-            AppError::ScreenCapture(msg) => {
-                assert!(msg.contains("Screen info for index 0 not found"));
-            }
-            _ => panic!("Expected ScreenCapture error"),
-        }
+        assert!(matches!(result.unwrap_err(), AppError::ScreenNotFound { index: 0 }));
     }
 
     #[test]
@@ -494,4 +1450,43 @@ mod tests {
         assert_eq!(physical.width(), 200.0); // 100.0 * 2.0
         assert_eq!(physical.height(), 75.0); // 50.0 * 1.5
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_pending_capture_delivers_result() {
+        let screen = ScreenInfo {
+            index: 0,
+            bounds: Rect::from_min_size(Pos2::ZERO, Vec2::new(4.0, 2.0)),
+            dpi_scale_x: 1.0,
+            dpi_scale_y: 1.0,
+            is_primary: true,
+        };
+        let pending = PendingCapture::spawn(move || {
+            Ok(CapturedFrame { image: RgbaImage::new(4, 2), screen, window_title: None, application: None, icc_profile: None })
+        })
+        .unwrap();
+
+        let started = Instant::now();
+        let result = loop {
+            if let Some(result) = pending.poll() {
+                break result;
+            }
+            assert!(started.elapsed() < Duration::from_secs(10), "capture did not finish");
+            std::thread::sleep(Duration::from_millis(1));
+        };
+        assert_eq!(result.unwrap().image.dimensions(), (4, 2));
+    }
+
+    #[test]
+    fn test_pending_capture_reports_panics() {
+        let pending = PendingCapture::spawn(|| panic!("capture backend crashed")).unwrap();
+        let started = Instant::now();
+        let result = loop {
+            if let Some(result) = pending.poll() {
+                break result;
+            }
+            assert!(started.elapsed() < Duration::from_secs(10), "capture did not finish");
+            std::thread::sleep(Duration::from_millis(1));
+        };
+        assert!(matches!(result, Err(AppError::ScreenCapture(_))));
+    }
+}
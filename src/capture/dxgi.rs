@@ -0,0 +1,268 @@
+//! HDR screen capture with DXGI desktop duplication
+//!
+//! The screenshots crate grabs the 8-bit desktop image, which clips
+//! everything brighter than SDR white on a screen in HDR mode. Those screens
+//! are duplicated as `R16G16B16A16_FLOAT` instead, which keeps the full
+//! scRGB range for tone mapping. Outputs are captured in their unrotated
+//! orientation.
+
+use crate::hdr::HdrImage;
+use egui::Pos2;
+use std::ops::Deref;
+use std::ptr;
+use winapi::shared::dxgi::{
+    CreateDXGIFactory1, IDXGIAdapter, IDXGIAdapter1, IDXGIFactory1, IDXGIOutput, IDXGIResource,
+    DXGI_OUTPUT_DESC,
+};
+use winapi::shared::dxgi1_2::{IDXGIOutputDuplication, DXGI_OUTDUPL_FRAME_INFO};
+use winapi::shared::dxgi1_5::IDXGIOutput5;
+use winapi::shared::dxgi1_6::{IDXGIOutput6, DXGI_OUTPUT_DESC1};
+use winapi::shared::dxgiformat::DXGI_FORMAT_R16G16B16A16_FLOAT;
+use winapi::shared::winerror::{DXGI_ERROR_WAIT_TIMEOUT, FAILED, SUCCEEDED};
+use winapi::um::d3d11::{
+    D3D11CreateDevice, ID3D11Device, ID3D11DeviceContext, ID3D11Resource, ID3D11Texture2D, D3D11_CPU_ACCESS_READ,
+    D3D11_MAPPED_SUBRESOURCE, D3D11_MAP_READ, D3D11_SDK_VERSION, D3D11_TEXTURE2D_DESC, D3D11_USAGE_STAGING,
+};
+use winapi::um::d3dcommon::D3D_DRIVER_TYPE_UNKNOWN;
+use winapi::um::unknwnbase::IUnknown;
+use winapi::Interface;
+
+/// `DXGI_COLOR_SPACE_RGB_FULL_G2084_NONE_P2020`, reported by outputs in HDR mode
+const COLOR_SPACE_HDR10: u32 = 12;
+
+/// How long to wait for each duplicated frame, in milliseconds
+const FRAME_TIMEOUT_MS: u32 = 200;
+
+/// Frames to try before giving up on getting one with desktop content
+const FRAME_ATTEMPTS: usize = 5;
+
+/// An owned COM interface pointer, released on drop
+struct Com<T: Interface>(*mut T);
+
+impl<T: Interface> Com<T> {
+    /// Take ownership of a pointer returned by a creating call
+    fn new(pointer: *mut T) -> Option<Self> {
+        (!pointer.is_null()).then_some(Self(pointer))
+    }
+
+    /// Query another interface of the same object
+    fn cast<U: Interface>(&self) -> Option<Com<U>> {
+        let mut pointer = ptr::null_mut();
+        // SAFETY: `self.0` is a live interface pointer, and every interface derives from IUnknown
+        let result = unsafe { (*(self.0 as *mut IUnknown)).QueryInterface(&U::uuidof(), &mut pointer) };
+        if SUCCEEDED(result) {
+            Com::new(pointer as *mut U)
+        } else {
+            None
+        }
+    }
+}
+
+impl<T: Interface> Deref for Com<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: the pointer is non-null and kept alive by the reference this guard owns
+        unsafe { &*self.0 }
+    }
+}
+
+impl<T: Interface> Drop for Com<T> {
+    fn drop(&mut self) {
+        // SAFETY: the guard owns one reference, given up exactly once here
+        unsafe { (*(self.0 as *mut IUnknown)).Release() };
+    }
+}
+
+/// Why a duplication failed, wrapped by the caller with the screen it was for
+pub type CaptureError = Box<dyn std::error::Error + Send + Sync>;
+
+fn capture_error(message: &str) -> CaptureError {
+    format!("HDR capture failed: {}", message).into()
+}
+
+/// Find the adapter and output showing a desktop point
+fn find_output(point: Pos2) -> Option<(Com<IDXGIAdapter1>, Com<IDXGIOutput>)> {
+    let mut factory = ptr::null_mut();
+    // SAFETY: the out pointer receives an IDXGIFactory1, as requested by the interface id
+    if FAILED(unsafe { CreateDXGIFactory1(&IDXGIFactory1::uuidof(), &mut factory) }) {
+        return None;
+    }
+    let factory = Com::new(factory as *mut IDXGIFactory1)?;
+
+    let (x, y) = (point.x as i32, point.y as i32);
+    for adapter_index in 0.. {
+        let mut adapter = ptr::null_mut();
+        // SAFETY: enumeration past the last adapter fails without writing the out pointer
+        if FAILED(unsafe { factory.EnumAdapters1(adapter_index, &mut adapter) }) {
+            break;
+        }
+        let Some(adapter) = Com::new(adapter) else {
+            break;
+        };
+        for output_index in 0.. {
+            let mut output = ptr::null_mut();
+            // SAFETY: as above, for the adapter's outputs
+            if FAILED(unsafe { adapter.EnumOutputs(output_index, &mut output) }) {
+                break;
+            }
+            let Some(output) = Com::new(output) else {
+                break;
+            };
+            let mut desc: DXGI_OUTPUT_DESC = unsafe { std::mem::zeroed() };
+            // SAFETY: `desc` is a writable DXGI_OUTPUT_DESC
+            if FAILED(unsafe { output.GetDesc(&mut desc) }) {
+                continue;
+            }
+            let rect = desc.DesktopCoordinates;
+            if (rect.left..rect.right).contains(&x) && (rect.top..rect.bottom).contains(&y) {
+                return Some((adapter, output));
+            }
+        }
+    }
+    None
+}
+
+/// Check whether the output showing a desktop point is in HDR mode
+pub fn is_hdr_output(point: Pos2) -> bool {
+    let Some(output) = find_output(point).and_then(|(_, output)| output.cast::<IDXGIOutput6>()) else {
+        return false;
+    };
+    let mut desc: DXGI_OUTPUT_DESC1 = unsafe { std::mem::zeroed() };
+    // SAFETY: `desc` is a writable DXGI_OUTPUT_DESC1
+    SUCCEEDED(unsafe { output.GetDesc1(&mut desc) }) && desc.ColorSpace as u32 == COLOR_SPACE_HDR10
+}
+
+/// Capture the output showing a desktop point as linear scRGB
+pub fn capture_output(point: Pos2) -> Result<HdrImage, CaptureError> {
+    let (adapter, output) = find_output(point).ok_or_else(|| capture_error("no display output at the screen"))?;
+    let output = output
+        .cast::<IDXGIOutput5>()
+        .ok_or_else(|| capture_error("HDR duplication requires Windows 10 1703 or later"))?;
+
+    let mut device = ptr::null_mut();
+    let mut context = ptr::null_mut();
+    // SAFETY: the adapter is live; the out pointers receive the device and its immediate context
+    let result = unsafe {
+        D3D11CreateDevice(
+            adapter.0 as *mut IDXGIAdapter,
+            D3D_DRIVER_TYPE_UNKNOWN,
+            ptr::null_mut(),
+            0,
+            ptr::null(),
+            0,
+            D3D11_SDK_VERSION,
+            &mut device,
+            ptr::null_mut(),
+            &mut context,
+        )
+    };
+    if FAILED(result) {
+        return Err(capture_error(&format!("D3D11CreateDevice returned {:#010x}", result)));
+    }
+    let device: Com<ID3D11Device> = Com::new(device).ok_or_else(|| capture_error("no Direct3D device"))?;
+    let context: Com<ID3D11DeviceContext> = Com::new(context).ok_or_else(|| capture_error("no Direct3D context"))?;
+
+    let mut duplication = ptr::null_mut();
+    let format = DXGI_FORMAT_R16G16B16A16_FLOAT;
+    // SAFETY: the device is live and the format list holds one entry
+    let result = unsafe { output.DuplicateOutput1(device.0 as *mut IUnknown, 0, 1, &format, &mut duplication) };
+    if FAILED(result) {
+        return Err(capture_error(&format!("DuplicateOutput1 returned {:#010x}", result)));
+    }
+    let duplication: Com<IDXGIOutputDuplication> =
+        Com::new(duplication).ok_or_else(|| capture_error("no output duplication"))?;
+
+    let texture = acquire_frame(&duplication)?;
+    let copied = copy_to_staging(&device, &context, &texture);
+    drop(texture);
+    // SAFETY: a frame was acquired above; the copy no longer needs it
+    unsafe { duplication.ReleaseFrame() };
+    read_staging(&context, &copied?)
+}
+
+/// Wait for a duplicated frame with desktop content, falling back to the last one
+fn acquire_frame(duplication: &Com<IDXGIOutputDuplication>) -> Result<Com<ID3D11Texture2D>, CaptureError> {
+    for attempt in 1..=FRAME_ATTEMPTS {
+        let mut info: DXGI_OUTDUPL_FRAME_INFO = unsafe { std::mem::zeroed() };
+        let mut resource = ptr::null_mut();
+        // SAFETY: `info` and `resource` are writable; a successful call must be paired with ReleaseFrame
+        let result = unsafe { duplication.AcquireNextFrame(FRAME_TIMEOUT_MS, &mut info, &mut resource) };
+        if result == DXGI_ERROR_WAIT_TIMEOUT {
+            continue;
+        }
+        if FAILED(result) {
+            return Err(capture_error(&format!("AcquireNextFrame returned {:#010x}", result)));
+        }
+        let resource: Option<Com<IDXGIResource>> = Com::new(resource);
+        // SAFETY: LastPresentTime is a plain 64-bit integer
+        let presented = unsafe { *info.LastPresentTime.QuadPart() } != 0;
+        if presented || attempt == FRAME_ATTEMPTS {
+            return resource
+                .and_then(|resource| resource.cast::<ID3D11Texture2D>())
+                .ok_or_else(|| capture_error("the frame is not a texture"));
+        }
+        drop(resource);
+        // SAFETY: the frame was acquired above and its resource is already dropped
+        unsafe { duplication.ReleaseFrame() };
+    }
+    Err(capture_error("timed out waiting for a frame"))
+}
+
+/// Copy a frame into a texture the CPU can read
+fn copy_to_staging(
+    device: &Com<ID3D11Device>,
+    context: &Com<ID3D11DeviceContext>,
+    texture: &Com<ID3D11Texture2D>,
+) -> Result<Com<ID3D11Texture2D>, CaptureError> {
+    let mut desc: D3D11_TEXTURE2D_DESC = unsafe { std::mem::zeroed() };
+    // SAFETY: `desc` is a writable D3D11_TEXTURE2D_DESC
+    unsafe { texture.GetDesc(&mut desc) };
+    if desc.Format != DXGI_FORMAT_R16G16B16A16_FLOAT {
+        return Err(capture_error(&format!("unexpected frame format {}", desc.Format)));
+    }
+    desc.Usage = D3D11_USAGE_STAGING;
+    desc.BindFlags = 0;
+    desc.CPUAccessFlags = D3D11_CPU_ACCESS_READ;
+    desc.MiscFlags = 0;
+    desc.MipLevels = 1;
+    desc.ArraySize = 1;
+
+    let mut staging = ptr::null_mut();
+    // SAFETY: `desc` describes a staging texture without initial data
+    let result = unsafe { device.CreateTexture2D(&desc, ptr::null(), &mut staging) };
+    if FAILED(result) {
+        return Err(capture_error(&format!("CreateTexture2D returned {:#010x}", result)));
+    }
+    let staging: Com<ID3D11Texture2D> = Com::new(staging).ok_or_else(|| capture_error("no staging texture"))?;
+    // SAFETY: both textures are live and have the same size and format
+    unsafe { context.CopyResource(staging.0 as *mut ID3D11Resource, texture.0 as *mut ID3D11Resource) };
+    Ok(staging)
+}
+
+/// Read the pixels of a staging texture
+fn read_staging(context: &Com<ID3D11DeviceContext>, staging: &Com<ID3D11Texture2D>) -> Result<HdrImage, CaptureError> {
+    let mut desc: D3D11_TEXTURE2D_DESC = unsafe { std::mem::zeroed() };
+    // SAFETY: `desc` is a writable D3D11_TEXTURE2D_DESC
+    unsafe { staging.GetDesc(&mut desc) };
+    let resource = staging.0 as *mut ID3D11Resource;
+    let mut mapped: D3D11_MAPPED_SUBRESOURCE = unsafe { std::mem::zeroed() };
+    // SAFETY: the staging texture was created with CPU read access
+    let result = unsafe { context.Map(resource, 0, D3D11_MAP_READ, 0, &mut mapped) };
+    if FAILED(result) {
+        return Err(capture_error(&format!("Map returned {:#010x}", result)));
+    }
+
+    let (width, height) = (desc.Width as usize, desc.Height as usize);
+    let mut values = Vec::with_capacity(width * height * 4);
+    for row in 0..height {
+        // SAFETY: the mapping holds `height` rows of `RowPitch` bytes, each with `width` 8-byte pixels
+        let bytes = unsafe {
+            std::slice::from_raw_parts((mapped.pData as *const u8).add(row * mapped.RowPitch as usize), width * 8)
+        };
+        values.extend(bytes.chunks_exact(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]])));
+    }
+    // SAFETY: the subresource was mapped above and the slices into it are gone
+    unsafe { context.Unmap(resource, 0) };
+    Ok(HdrImage::from_f16(desc.Width, desc.Height, &values)?)
+}
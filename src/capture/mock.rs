@@ -0,0 +1,656 @@
+//! Mock capture backend
+//!
+//! Serves synthetic screens so tests can run the capture, edit, and export
+//! pipeline deterministically without a display. Screens are described in
+//! code or loaded from JSON fixtures:
+//!
+//! ```json
+//! { "screens": [
+//!     { "width": 1920, "height": 1080, "primary": true },
+//!     { "x": 1920, "width": 1280, "height": 720, "scale": 1.5,
+//!       "pattern": { "type": "checkerboard", "size": 16 } }
+//! ] }
+//! ```
+
+use super::{CaptureBackend, CaptureJob, HdrCaptureJob, WindowFrame};
+use crate::window_list::WindowInfo;
+use crate::color::srgb_to_linear;
+use crate::hdr::HdrImage;
+use crate::types::{AppError, AppResult, ScreenInfo};
+use egui::{Pos2, Rect, Vec2};
+use image::{DynamicImage, Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Pixels served for a mock screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PixelPattern {
+    /// A single color
+    Solid { color: [u8; 4] },
+    /// Red increasing left to right and green top to bottom
+    #[default]
+    Gradient,
+    /// Alternating black and white squares of `size` pixels
+    Checkerboard { size: u32 },
+}
+
+impl PixelPattern {
+    /// Get the color of a pixel in an image of the given size
+    pub fn pixel(&self, x: u32, y: u32, width: u32, height: u32) -> Rgba<u8> {
+        match *self {
+            PixelPattern::Solid { color } => Rgba(color),
+            PixelPattern::Gradient => {
+                let r = (x * 255 / width.saturating_sub(1).max(1)) as u8;
+                let g = (y * 255 / height.saturating_sub(1).max(1)) as u8;
+                Rgba([r, g, 128, 255])
+            }
+            PixelPattern::Checkerboard { size } => {
+                let size = size.max(1);
+                let value = if (x / size + y / size).is_multiple_of(2) { 0 } else { 255 };
+                Rgba([value, value, value, 255])
+            }
+        }
+    }
+
+    /// Render the pattern at a size
+    pub fn render(&self, width: u32, height: u32) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| self.pixel(x, y, width, height))
+    }
+}
+
+/// A synthetic screen in virtual desktop coordinates
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MockScreen {
+    pub x: i32,
+    pub y: i32,
+    /// Logical size; captures are `scale` times larger
+    pub width: u32,
+    pub height: u32,
+    /// DPI scale factor
+    pub scale: f32,
+    pub primary: bool,
+    pub pattern: PixelPattern,
+    /// ICC color profile of the screen, if any
+    pub icc_profile: Option<Vec<u8>>,
+    /// Brightness of the pattern relative to SDR white on a screen in HDR mode;
+    /// `None` for SDR screens
+    pub hdr_gain: Option<f32>,
+}
+
+impl Default for MockScreen {
+    fn default() -> Self {
+        Self {
+            x: 0,
+            y: 0,
+            width: 1920,
+            height: 1080,
+            scale: 1.0,
+            primary: false,
+            pattern: PixelPattern::default(),
+            icc_profile: None,
+            hdr_gain: None,
+        }
+    }
+}
+
+impl MockScreen {
+    /// Create a screen at the origin with a gradient
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            ..Self::default()
+        }
+    }
+
+    /// Size of captures of this screen in physical pixels
+    pub fn physical_size(&self) -> (u32, u32) {
+        (
+            (self.width as f32 * self.scale).round() as u32,
+            (self.height as f32 * self.scale).round() as u32,
+        )
+    }
+}
+
+/// Backend serving synthetic screens
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MockBackend {
+    pub screens: Vec<MockScreen>,
+    /// Make every capture fail, to exercise error handling
+    pub fail_captures: bool,
+    /// Screen showing the focused window, if any
+    pub active_screen: Option<usize>,
+    /// Desktop bounds of the focused window, if any
+    pub active_window: Option<Rect>,
+    /// Visible frame and corner radius of the focused window, if reported
+    pub active_window_frame: Option<WindowFrame>,
+    /// Title of the focused window, if any
+    pub active_window_title: Option<String>,
+    /// Executable name of the focused window's application, if any
+    pub active_application: Option<String>,
+    /// Top-level windows, front to back
+    pub windows: Vec<WindowInfo>,
+    /// Desktop position of the mouse cursor, if known
+    pub cursor: Option<Pos2>,
+}
+
+impl MockBackend {
+    /// Create a backend with the given screens
+    pub fn new(screens: Vec<MockScreen>) -> Self {
+        Self {
+            screens,
+            fail_captures: false,
+            active_screen: None,
+            active_window: None,
+            active_window_frame: None,
+            active_window_title: None,
+            active_application: None,
+            windows: Vec::new(),
+            cursor: None,
+        }
+    }
+
+    /// Load a backend from a JSON fixture
+    pub fn from_json(json: &str) -> AppResult<Self> {
+        let backend: MockBackend = serde_json::from_str(json)
+            .map_err(|e| AppError::ScreenCapture(format!("Invalid mock screen fixture: {}", e)))?;
+        if let Some(screen) = backend.screens.iter().find(|screen| screen.scale <= 0.0) {
+            return Err(AppError::ScreenCapture(format!("Invalid mock screen scale {}", screen.scale)));
+        }
+        Ok(backend)
+    }
+
+    /// Load a backend from a JSON fixture file
+    pub fn from_fixture_file(path: &Path) -> AppResult<Self> {
+        Self::from_json(&std::fs::read_to_string(path)?)
+    }
+}
+
+impl CaptureBackend for MockBackend {
+    fn enumerate(&mut self) -> Vec<ScreenInfo> {
+        // Like the system backend, the first screen is primary unless one is marked
+        let primary = self.screens.iter().position(|screen| screen.primary).unwrap_or(0);
+        self.screens
+            .iter()
+            .enumerate()
+            .map(|(index, screen)| ScreenInfo {
+                index,
+                bounds: Rect::from_min_size(
+                    Pos2::new(screen.x as f32, screen.y as f32),
+                    Vec2::new(screen.width as f32, screen.height as f32),
+                ),
+                dpi_scale_x: screen.scale,
+                dpi_scale_y: screen.scale,
+                is_primary: index == primary,
+            })
+            .collect()
+    }
+
+    fn capture_job(&self, screen_index: usize) -> AppResult<CaptureJob> {
+        let screen = self
+            .screens
+            .get(screen_index)
+            .cloned()
+            .ok_or(AppError::ScreenNotFound { index: screen_index })?;
+        if self.fail_captures {
+            return Ok(Box::new(move || {
+                Err(AppError::CaptureFailed {
+                    screen: screen_index,
+                    source: "mock capture failure".into(),
+                })
+            }));
+        }
+        Ok(Box::new(move || {
+            let (width, height) = screen.physical_size();
+            Ok(DynamicImage::ImageRgba8(screen.pattern.render(width, height)))
+        }))
+    }
+
+    fn active_monitor_bounds(&self) -> Option<Rect> {
+        let screen = self.screens.get(self.active_screen?)?;
+        Some(Rect::from_min_size(
+            Pos2::new(screen.x as f32, screen.y as f32),
+            Vec2::new(screen.width as f32, screen.height as f32),
+        ))
+    }
+
+    fn active_window_bounds(&self) -> Option<Rect> {
+        self.active_window
+    }
+
+    fn active_window_frame(&self) -> Option<WindowFrame> {
+        self.active_window_frame
+    }
+
+    fn active_window_title(&self) -> Option<String> {
+        self.active_window_title.clone()
+    }
+
+    fn active_application(&self) -> Option<String> {
+        self.active_application.clone()
+    }
+
+    fn windows(&self) -> Vec<WindowInfo> {
+        self.windows.clone()
+    }
+
+    fn cursor_position(&self) -> Option<Pos2> {
+        self.cursor
+    }
+
+    fn color_profile(&self, screen_index: usize) -> Option<Vec<u8>> {
+        self.screens.get(screen_index)?.icc_profile.clone()
+    }
+
+    fn is_hdr(&self, screen_index: usize) -> bool {
+        self.screens.get(screen_index).is_some_and(|screen| screen.hdr_gain.is_some())
+    }
+
+    fn capture_hdr_job(&self, screen_index: usize) -> AppResult<HdrCaptureJob> {
+        let screen = self
+            .screens
+            .get(screen_index)
+            .cloned()
+            .ok_or(AppError::ScreenNotFound { index: screen_index })?;
+        let Some(gain) = screen.hdr_gain else {
+            return Err(AppError::CaptureFailed {
+                screen: screen_index,
+                source: "the screen is not in HDR mode".into(),
+            });
+        };
+        if self.fail_captures {
+            return Ok(Box::new(move || {
+                Err(AppError::CaptureFailed {
+                    screen: screen_index,
+                    source: "mock HDR capture failure".into(),
+                })
+            }));
+        }
+        Ok(Box::new(move || {
+            let (width, height) = screen.physical_size();
+            let pixels = screen
+                .pattern
+                .render(width, height)
+                .pixels()
+                .map(|pixel| {
+                    let channel = |value: u8| srgb_to_linear(f32::from(value) / 255.0) * gain;
+                    [channel(pixel[0]), channel(pixel[1]), channel(pixel[2]), 1.0]
+                })
+                .collect();
+            Ok(HdrImage { width, height, pixels })
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::{CaptureService, WindowCaptureOptions};
+    use crate::window_list::WindowSelector;
+    use crate::hdr::{HdrOptions, ToneMapping};
+    use crate::types::CaptureArea;
+
+    #[test]
+    fn test_fixture_screens() {
+        let backend = MockBackend::from_json(
+            r#"{ "screens": [
+                { "width": 800, "height": 600 },
+                { "x": 800, "width": 640, "height": 480, "scale": 1.5, "primary": true,
+                  "pattern": { "type": "solid", "color": [255, 0, 0, 255] } }
+            ] }"#,
+        )
+        .unwrap();
+        let service = CaptureService::with_backend(backend).unwrap();
+
+        let primary = service.get_primary_screen().unwrap();
+        assert_eq!(primary.index, 1);
+        assert_eq!(primary.dpi_scale_x, 1.5);
+        assert_eq!(service.get_desktop_bounds().size(), Vec2::new(1440.0, 600.0));
+
+        let image = service.capture_primary_screen().unwrap().into_rgba8();
+        assert_eq!(image.dimensions(), (960, 720));
+        assert_eq!(*image.get_pixel(10, 10), Rgba([255, 0, 0, 255]));
+
+        assert!(MockBackend::from_json(r#"{ "screens": [{ "scale": 0 }] }"#).is_err());
+        assert!(CaptureService::with_backend(MockBackend::default()).is_err());
+    }
+
+    #[test]
+    fn test_capture_area_of_pattern() {
+        let screen = MockScreen {
+            pattern: PixelPattern::Checkerboard { size: 10 },
+            ..MockScreen::new(100, 100)
+        };
+        let service = CaptureService::with_backend(MockBackend::new(vec![screen])).unwrap();
+        let area = CaptureArea::new(Rect::from_min_size(Pos2::new(10.0, 0.0), Vec2::new(20.0, 10.0)), 0);
+
+        let image = service.capture_area(&area).unwrap().into_rgba8();
+        assert_eq!(image.dimensions(), (20, 10));
+        assert_eq!(image.get_pixel(0, 0).0[0], 255);
+        assert_eq!(image.get_pixel(10, 0).0[0], 0);
+    }
+
+    #[test]
+    fn test_failing_captures() {
+        let backend = MockBackend {
+            fail_captures: true,
+            ..MockBackend::new(vec![MockScreen::new(10, 10)])
+        };
+        let service = CaptureService::with_backend(backend).unwrap();
+        assert!(matches!(service.capture_primary_screen(), Err(AppError::CaptureFailed { screen: 0, .. })));
+        assert!(matches!(service.capture_screen_by_index(5), Err(AppError::ScreenNotFound { index: 5 })));
+    }
+
+    #[test]
+    fn test_capture_all_screens() {
+        let second = MockScreen {
+            x: 800,
+            scale: 2.0,
+            pattern: PixelPattern::Solid { color: [0, 0, 255, 255] },
+            ..MockScreen::new(400, 300)
+        };
+        let first = MockScreen {
+            pattern: PixelPattern::Solid { color: [255, 0, 0, 255] },
+            ..MockScreen::new(800, 600)
+        };
+        let service = CaptureService::with_backend(MockBackend::new(vec![first, second])).unwrap();
+
+        let pending = service.capture_all_screens_async().unwrap();
+        let frame = loop {
+            if let Some(result) = pending.poll() {
+                break result.unwrap();
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        };
+        // Drawn at the 2x scaling of the second screen, which enlarges the first
+        assert_eq!(frame.image.dimensions(), (2400, 1200));
+        assert_eq!(frame.image.get_pixel(100, 100).0, [255, 0, 0, 255]);
+        assert_eq!(frame.image.get_pixel(1700, 100).0, [0, 0, 255, 255]);
+        // Below the shorter second screen is outside the desktop
+        assert_eq!(frame.image.get_pixel(1700, 1000).0[3], 0);
+        assert_eq!(frame.screen.bounds, Rect::from_min_max(Pos2::ZERO, Pos2::new(1200.0, 600.0)));
+    }
+
+    #[test]
+    fn test_capture_active_monitor() {
+        let second = MockScreen {
+            x: 800,
+            pattern: PixelPattern::Solid { color: [0, 0, 255, 255] },
+            ..MockScreen::new(640, 480)
+        };
+        let mut backend = MockBackend::new(vec![MockScreen::new(800, 600), second]);
+
+        // Without a focused window the primary screen is captured
+        let service = CaptureService::with_backend(backend.clone()).unwrap();
+        assert_eq!(service.capture_active_monitor().unwrap().width(), 800);
+
+        backend.active_screen = Some(1);
+        let service = CaptureService::with_backend(backend).unwrap();
+        assert_eq!(service.get_active_screen().unwrap().index, 1);
+        let image = service.capture_active_monitor().unwrap().into_rgba8();
+        assert_eq!(image.dimensions(), (640, 480));
+        assert_eq!(image.get_pixel(0, 0).0, [0, 0, 255, 255]);
+
+        // A window straddling both monitors belongs to the one it mostly covers
+        let straddling = Rect::from_min_max(Pos2::new(700.0, 0.0), Pos2::new(1000.0, 100.0));
+        assert_eq!(service.find_screen_overlapping(straddling).unwrap().index, 1);
+        assert!(service.find_screen_overlapping(Rect::from_min_size(Pos2::new(-50.0, 0.0), Vec2::splat(10.0))).is_none());
+    }
+
+    #[test]
+    fn test_cursor_screen() {
+        let second = MockScreen { x: 800, ..MockScreen::new(640, 480) };
+        let mut backend = MockBackend::new(vec![MockScreen::new(800, 600), second]);
+
+        // Unknown or off-screen cursors fall back to the primary screen
+        let service = CaptureService::with_backend(backend.clone()).unwrap();
+        assert_eq!(service.get_cursor_screen().unwrap().index, 0);
+        backend.cursor = Some(Pos2::new(-100.0, 50.0));
+        let service = CaptureService::with_backend(backend.clone()).unwrap();
+        assert_eq!(service.get_cursor_screen().unwrap().index, 0);
+
+        // Not the monitor of the focused window
+        backend.cursor = Some(Pos2::new(900.0, 50.0));
+        backend.active_screen = Some(0);
+        let service = CaptureService::with_backend(backend).unwrap();
+        assert_eq!(service.get_cursor_screen().unwrap().index, 1);
+        assert_eq!(service.get_active_screen().unwrap().index, 0);
+    }
+
+    #[test]
+    fn test_capture_active_window() {
+        let second = MockScreen {
+            x: 800,
+            scale: 2.0,
+            pattern: PixelPattern::Solid { color: [0, 0, 255, 255] },
+            ..MockScreen::new(640, 480)
+        };
+        let mut backend = MockBackend::new(vec![MockScreen::new(800, 600), second]);
+
+        let service = CaptureService::with_backend(backend.clone()).unwrap();
+        assert!(service.capture_active_window().is_err());
+
+        // Mostly on the second screen: the part on the first is cut off
+        backend.active_window = Some(Rect::from_min_max(Pos2::new(750.0, 10.0), Pos2::new(1000.0, 110.0)));
+        backend.active_window_title = Some("Untitled - Notepad".to_string());
+        backend.active_application = Some("notepad.exe".to_string());
+        let service = CaptureService::with_backend(backend).unwrap();
+        let area = service.active_window_area().unwrap();
+        assert_eq!(area.screen_index, 1);
+        assert_eq!(area.bounds, Rect::from_min_max(Pos2::new(0.0, 10.0), Pos2::new(200.0, 110.0)));
+
+        let image = service.capture_active_window().unwrap().into_rgba8();
+        assert_eq!(image.dimensions(), (400, 200));
+        assert_eq!(image.get_pixel(0, 0).0, [0, 0, 255, 255]);
+
+        let pending = service.capture_active_window_async().unwrap();
+        let frame = loop {
+            if let Some(result) = pending.poll() {
+                break result.unwrap();
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        };
+        assert_eq!(frame.image.dimensions(), (400, 200));
+        assert_eq!(frame.screen.index, 1);
+        assert_eq!(frame.window_title.as_deref(), Some("Untitled - Notepad"));
+        assert_eq!(frame.application.as_deref(), Some("notepad.exe"));
+        assert_eq!(service.active_application().as_deref(), Some("notepad.exe"));
+    }
+
+    #[test]
+    fn test_capture_rounded_window() {
+        let screen = MockScreen {
+            scale: 2.0,
+            pattern: PixelPattern::Solid { color: [0, 0, 255, 255] },
+            ..MockScreen::new(800, 600)
+        };
+        let mut backend = MockBackend::new(vec![screen]);
+        // The frame leaves out the invisible resize borders around the bounds
+        backend.active_window = Some(Rect::from_min_max(Pos2::new(92.0, 92.0), Pos2::new(308.0, 208.0)));
+        backend.active_window_frame = Some(WindowFrame {
+            frame: Rect::from_min_max(Pos2::new(100.0, 100.0), Pos2::new(300.0, 200.0)),
+            corner_radius: 8.0,
+        });
+        let mut service = CaptureService::with_backend(backend.clone()).unwrap();
+
+        let image = service.capture_active_window().unwrap().into_rgba8();
+        assert_eq!(image.dimensions(), (400, 200));
+        assert_eq!(image.get_pixel(0, 0).0[3], 0);
+        assert_eq!(image.get_pixel(200, 0).0, [0, 0, 255, 255]);
+
+        service.set_window_capture_options(WindowCaptureOptions {
+            round_corners: false,
+            include_shadow: true,
+        });
+        let pending = service.capture_active_window_async().unwrap();
+        let frame = loop {
+            if let Some(result) = pending.poll() {
+                break result.unwrap();
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        };
+        // The shadow margin surrounds the square-cornered window
+        assert!(frame.image.width() > 400 && frame.image.height() > 200);
+        assert_eq!(frame.image.get_pixel(0, 0).0[3], 0);
+        let margin = (frame.image.width() - 400) / 2;
+        assert_eq!(frame.image.get_pixel(margin, margin).0, [0, 0, 255, 255]);
+        let shadow = frame.image.get_pixel(margin + 200, margin + 202).0[3];
+        assert!(shadow > 0 && shadow < 255);
+
+        // A window cut off by the screen edge keeps its captured corners
+        backend.active_window_frame = Some(WindowFrame {
+            frame: Rect::from_min_max(Pos2::new(700.0, 100.0), Pos2::new(900.0, 200.0)),
+            corner_radius: 8.0,
+        });
+        let service = CaptureService::with_backend(backend).unwrap();
+        let image = service.capture_active_window().unwrap().into_rgba8();
+        assert_eq!(image.dimensions(), (200, 200));
+        assert_eq!(image.get_pixel(0, 0).0, [0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn test_capture_window_by_selector() {
+        let mut backend = MockBackend::new(vec![MockScreen {
+            pattern: PixelPattern::Solid { color: [0, 0, 255, 255] },
+            ..MockScreen::new(800, 600)
+        }]);
+        let window = |title: &str, min: Pos2| WindowInfo {
+            title: title.to_string(),
+            application: Some("notepad.exe".to_string()),
+            frame: WindowFrame {
+                frame: Rect::from_min_size(min, Vec2::new(200.0, 100.0)),
+                corner_radius: 0.0,
+            },
+        };
+        backend.windows = vec![
+            window("Untitled - Notepad", Pos2::new(10.0, 10.0)),
+            window("notes.txt - Notepad", Pos2::new(300.0, 200.0)),
+        ];
+        let service = CaptureService::with_backend(backend).unwrap();
+
+        let selector = WindowSelector {
+            title: Some("notes.txt".to_string()),
+            process: None,
+        };
+        let image = service.capture_window(&selector).unwrap();
+        assert_eq!((image.width(), image.height()), (200, 100));
+
+        let pending = service.capture_window_async(&selector).unwrap();
+        let frame = loop {
+            if let Some(result) = pending.poll() {
+                break result.unwrap();
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        };
+        assert_eq!(frame.window_title.as_deref(), Some("notes.txt - Notepad"));
+        assert_eq!(frame.application.as_deref(), Some("notepad.exe"));
+
+        let by_process = WindowSelector {
+            title: None,
+            process: Some("notepad".to_string()),
+        };
+        let error = service.capture_window(&by_process).unwrap_err();
+        assert!(matches!(error, AppError::AmbiguousWindow { ref titles, .. } if titles.len() == 2));
+    }
+
+    #[test]
+    fn test_capture_region() {
+        let second = MockScreen {
+            x: 800,
+            pattern: PixelPattern::Solid { color: [0, 255, 0, 255] },
+            ..MockScreen::new(640, 480)
+        };
+        let mut backend = MockBackend::new(vec![MockScreen::new(800, 600), second]);
+        backend.active_window_title = Some("Inbox - Outlook".to_string());
+        backend.active_application = Some("OUTLOOK.EXE".to_string());
+        let service = CaptureService::with_backend(backend).unwrap();
+
+        let pending = service
+            .capture_region_async(Rect::from_min_size(Pos2::new(780.0, 20.0), Vec2::new(100.0, 50.0)))
+            .unwrap();
+        let frame = loop {
+            if let Some(result) = pending.poll() {
+                break result.unwrap();
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        };
+        // Clipped to the second screen, which the region mostly covers
+        assert_eq!(frame.screen.index, 1);
+        assert_eq!(frame.image.dimensions(), (80, 50));
+        assert_eq!(frame.image.get_pixel(0, 0).0, [0, 255, 0, 255]);
+        // The region is recorded as showing the focused window
+        assert_eq!(frame.window_title.as_deref(), Some("Inbox - Outlook"));
+        assert_eq!(frame.application.as_deref(), Some("OUTLOOK.EXE"));
+
+        let off_screen = Rect::from_min_size(Pos2::new(-500.0, 0.0), Vec2::splat(100.0));
+        assert!(service.capture_region_async(off_screen).is_err());
+    }
+
+    #[test]
+    fn test_wide_gamut_screen() {
+        let display_p3 = [[0.5151, 0.2920, 0.1571], [0.2412, 0.6922, 0.0666], [-0.0011, 0.0419, 0.7841]];
+        let wide = MockScreen {
+            x: 800,
+            pattern: PixelPattern::Solid { color: [200, 100, 100, 255] },
+            icc_profile: Some(crate::color::build_matrix_profile("Wide", &display_p3, &[])),
+            ..MockScreen::new(100, 100)
+        };
+        let srgb = MockScreen {
+            pattern: PixelPattern::Solid { color: [200, 100, 100, 255] },
+            icc_profile: Some(crate::color::srgb_icc_profile()),
+            ..MockScreen::new(100, 100)
+        };
+        let mut service = CaptureService::with_backend(MockBackend::new(vec![srgb, wide])).unwrap();
+        assert!(service.screen_icc_profile(0).is_none());
+        assert!(service.screen_icc_profile(1).is_some());
+
+        let capture = |service: &CaptureService, index: usize| {
+            let pending = service.capture_screen_async(index).unwrap();
+            loop {
+                if let Some(result) = pending.poll() {
+                    break result.unwrap();
+                }
+                std::thread::sleep(std::time::Duration::from_millis(1));
+            }
+        };
+        // sRGB screens are left alone
+        let frame = capture(&service, 0);
+        assert_eq!(frame.image.get_pixel(0, 0).0, [200, 100, 100, 255]);
+        assert!(frame.icc_profile.is_none());
+
+        let frame = capture(&service, 1);
+        assert_ne!(frame.image.get_pixel(0, 0).0, [200, 100, 100, 255]);
+        assert!(frame.icc_profile.is_none());
+        assert_eq!(service.capture_screen_by_index(1).unwrap().into_rgba8(), frame.image);
+
+        // Without conversion the pixels keep the screen's profile
+        service.set_convert_to_srgb(false);
+        let frame = capture(&service, 1);
+        assert_eq!(frame.image.get_pixel(0, 0).0, [200, 100, 100, 255]);
+        assert_eq!(frame.icc_profile.as_deref().map(Vec::as_slice), service.screen_icc_profile(1));
+    }
+
+    #[test]
+    fn test_hdr_screen() {
+        let hdr = MockScreen {
+            pattern: PixelPattern::Solid { color: [255, 200, 0, 255] },
+            hdr_gain: Some(4.0),
+            ..MockScreen::new(10, 10)
+        };
+        let mut service = CaptureService::with_backend(MockBackend::new(vec![hdr])).unwrap();
+
+        // Clipping loses the highlights the tone curves keep
+        service.set_hdr_options(HdrOptions { tone_mapping: ToneMapping::Clip, ..HdrOptions::default() });
+        assert_eq!(service.capture_primary_screen().unwrap().into_rgba8().get_pixel(0, 0).0, [255, 255, 0, 255]);
+        service.set_hdr_options(HdrOptions::default());
+        let [r, g, b, _] = service.capture_primary_screen().unwrap().into_rgba8().get_pixel(0, 0).0;
+        assert!(r > g && g > 200 && b == 0, "got {} {} {}", r, g, b);
+
+        // Without HDR capture the 8-bit image is used as is
+        service.set_hdr_options(HdrOptions { capture_hdr: false, ..HdrOptions::default() });
+        assert_eq!(service.capture_primary_screen().unwrap().into_rgba8().get_pixel(0, 0).0, [255, 200, 0, 255]);
+    }
+}
@@ -0,0 +1,248 @@
+//! System clipboard image access
+//!
+//! Images on the Windows clipboard are read as a device-independent bitmap
+//! (`CF_DIB`) and converted to an in-memory BMP file, which is then decoded
+//! like any other untrusted image. Images are written to the clipboard as a
+//! 32-bit `CF_DIB` as well, together with a PNG in the registered "PNG"
+//! format, which applications that honor transparency paste instead of the
+//! bitmap.
+
+use crate::types::{AppError, AppResult};
+use image::RgbaImage;
+
+/// Size of a `BITMAPFILEHEADER`
+const BMP_FILE_HEADER_SIZE: usize = 14;
+
+/// Size of a `BITMAPINFOHEADER`, the smallest header with compression fields
+const BITMAP_INFO_HEADER_SIZE: usize = 40;
+
+/// `BI_BITFIELDS` compression, which stores three color masks after a `BITMAPINFOHEADER`
+const BI_BITFIELDS: u32 = 3;
+
+/// Read an image from the clipboard as BMP file bytes
+///
+/// Returns `Ok(None)` when the clipboard holds no image.
+#[cfg(windows)]
+pub fn read_image() -> AppResult<Option<Vec<u8>>> {
+    use winapi::um::winbase::{GlobalLock, GlobalSize, GlobalUnlock};
+    use winapi::um::winuser::{CloseClipboard, GetClipboardData, IsClipboardFormatAvailable, OpenClipboard, CF_DIB};
+
+    // SAFETY: plain Win32 calls; the clipboard is closed on every path below
+    unsafe {
+        if IsClipboardFormatAvailable(CF_DIB) == 0 {
+            return Ok(None);
+        }
+        if OpenClipboard(std::ptr::null_mut()) == 0 {
+            return Err(AppError::Clipboard("クリップボードを開けませんでした".to_string()));
+        }
+
+        let handle = GetClipboardData(CF_DIB);
+        let dib = if handle.is_null() {
+            None
+        } else {
+            let data = GlobalLock(handle) as *const u8;
+            if data.is_null() {
+                None
+            } else {
+                let dib = std::slice::from_raw_parts(data, GlobalSize(handle)).to_vec();
+                GlobalUnlock(handle);
+                Some(dib)
+            }
+        };
+        CloseClipboard();
+
+        match dib {
+            Some(dib) => dib_to_bmp(&dib).map(Some),
+            None => Err(AppError::Clipboard("クリップボードの画像を取得できませんでした".to_string())),
+        }
+    }
+}
+
+/// Read an image from the clipboard as BMP file bytes
+///
+/// Clipboard images are only supported on Windows.
+#[cfg(not(windows))]
+pub fn read_image() -> AppResult<Option<Vec<u8>>> {
+    Err(AppError::Clipboard("この環境ではクリップボードの画像を読み込めません".to_string()))
+}
+
+/// Put an image on the clipboard, replacing its contents
+#[cfg(windows)]
+pub fn write_image(image: &RgbaImage) -> AppResult<()> {
+    use winapi::um::winbase::{GlobalAlloc, GlobalFree, GlobalLock, GlobalUnlock, GMEM_MOVEABLE};
+    use winapi::um::winuser::{
+        CloseClipboard, EmptyClipboard, OpenClipboard, RegisterClipboardFormatW, SetClipboardData, CF_DIB,
+    };
+
+    /// Copy bytes into movable global memory for the clipboard
+    unsafe fn global_copy(bytes: &[u8]) -> AppResult<winapi::shared::minwindef::HGLOBAL> {
+        let handle = GlobalAlloc(GMEM_MOVEABLE, bytes.len());
+        if handle.is_null() {
+            return Err(AppError::Clipboard("クリップボード用のメモリを確保できませんでした".to_string()));
+        }
+        let data = GlobalLock(handle) as *mut u8;
+        if data.is_null() {
+            GlobalFree(handle);
+            return Err(AppError::Clipboard("クリップボード用のメモリを確保できませんでした".to_string()));
+        }
+        std::ptr::copy_nonoverlapping(bytes.as_ptr(), data, bytes.len());
+        GlobalUnlock(handle);
+        Ok(handle)
+    }
+
+    let dib = image_to_dib(image);
+    let png = crate::export::encode_image(image, &crate::types::ImageFormat::Png)?;
+    let png_format_name = crate::files::to_wide("PNG");
+    // SAFETY: plain Win32 calls; the memory is freed unless the clipboard took ownership of it
+    unsafe {
+        let dib_handle = global_copy(&dib)?;
+        let png_handle = match global_copy(&png) {
+            Ok(handle) => handle,
+            Err(e) => {
+                GlobalFree(dib_handle);
+                return Err(e);
+            }
+        };
+
+        if OpenClipboard(std::ptr::null_mut()) == 0 {
+            GlobalFree(dib_handle);
+            GlobalFree(png_handle);
+            return Err(AppError::Clipboard("クリップボードを開けませんでした".to_string()));
+        }
+        EmptyClipboard();
+        let stored = !SetClipboardData(CF_DIB, dib_handle).is_null();
+        // The PNG is optional: applications without PNG support paste the bitmap
+        let png_format = RegisterClipboardFormatW(png_format_name.as_ptr());
+        let png_stored = stored && png_format != 0 && !SetClipboardData(png_format, png_handle).is_null();
+        CloseClipboard();
+        if !png_stored {
+            GlobalFree(png_handle);
+        }
+        if !stored {
+            GlobalFree(dib_handle);
+            return Err(AppError::Clipboard("クリップボードに画像をコピーできませんでした".to_string()));
+        }
+    }
+    Ok(())
+}
+
+/// Put an image on the clipboard, replacing its contents
+///
+/// Clipboard images are only supported on Windows.
+#[cfg(not(windows))]
+pub fn write_image(_image: &RgbaImage) -> AppResult<()> {
+    Err(AppError::Clipboard("この環境ではクリップボードに画像をコピーできません".to_string()))
+}
+
+/// Convert an image to a packed 32-bit bottom-up DIB
+pub fn image_to_dib(image: &RgbaImage) -> Vec<u8> {
+    let (width, height) = image.dimensions();
+    let pixel_size = width as usize * height as usize * 4;
+    let mut dib = Vec::with_capacity(BITMAP_INFO_HEADER_SIZE + pixel_size);
+    dib.extend_from_slice(&(BITMAP_INFO_HEADER_SIZE as u32).to_le_bytes());
+    dib.extend_from_slice(&(width as i32).to_le_bytes());
+    // A positive height stores the bottom row first
+    dib.extend_from_slice(&(height as i32).to_le_bytes());
+    dib.extend_from_slice(&1u16.to_le_bytes());
+    dib.extend_from_slice(&32u16.to_le_bytes());
+    // BI_RGB compression
+    dib.extend_from_slice(&0u32.to_le_bytes());
+    dib.extend_from_slice(&(pixel_size as u32).to_le_bytes());
+    // Resolution and color table counts
+    dib.extend_from_slice(&[0; 16]);
+    for row in image.rows().rev() {
+        for pixel in row {
+            let [r, g, b, a] = pixel.0;
+            dib.extend_from_slice(&[b, g, r, a]);
+        }
+    }
+    dib
+}
+
+/// Convert a packed DIB (info header, color table, pixels) to a BMP file
+pub fn dib_to_bmp(dib: &[u8]) -> AppResult<Vec<u8>> {
+    let invalid = || AppError::Clipboard("クリップボードの画像データが不正です".to_string());
+    let read_u16 = |offset: usize| dib.get(offset..offset + 2).map(|b| u16::from_le_bytes([b[0], b[1]]));
+    let read_u32 =
+        |offset: usize| dib.get(offset..offset + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+
+    let header_size = read_u32(0).ok_or_else(invalid)? as usize;
+    if header_size < BITMAP_INFO_HEADER_SIZE || header_size > dib.len() {
+        return Err(invalid());
+    }
+    let bit_count = read_u16(14).ok_or_else(invalid)?;
+    let compression = read_u32(16).ok_or_else(invalid)?;
+    let colors_used = read_u32(32).ok_or_else(invalid)? as usize;
+
+    let mut palette_size = match (colors_used, bit_count) {
+        (0, 1..=8) => (1usize << bit_count) * 4,
+        (used, _) => used.checked_mul(4).ok_or_else(invalid)?,
+    };
+    if compression == BI_BITFIELDS && header_size == BITMAP_INFO_HEADER_SIZE {
+        palette_size += 12;
+    }
+
+    let pixel_offset = BMP_FILE_HEADER_SIZE + header_size + palette_size;
+    let file_size = BMP_FILE_HEADER_SIZE + dib.len();
+    if pixel_offset > file_size {
+        return Err(invalid());
+    }
+    let file_size = u32::try_from(file_size).map_err(|_| invalid())?;
+
+    let mut bmp = Vec::with_capacity(file_size as usize);
+    bmp.extend_from_slice(b"BM");
+    bmp.extend_from_slice(&file_size.to_le_bytes());
+    bmp.extend_from_slice(&[0; 4]);
+    bmp.extend_from_slice(&(pixel_offset as u32).to_le_bytes());
+    bmp.extend_from_slice(dib);
+    Ok(bmp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    /// Strip the file header from an encoded BMP to get a packed DIB
+    fn packed_dib(width: u32, height: u32) -> Vec<u8> {
+        let image = image::DynamicImage::ImageRgba8(RgbaImage::from_pixel(width, height, Rgba([200, 100, 50, 255])));
+        let mut bmp = Vec::new();
+        image.write_to(&mut std::io::Cursor::new(&mut bmp), image::ImageOutputFormat::Bmp).unwrap();
+        bmp[BMP_FILE_HEADER_SIZE..].to_vec()
+    }
+
+    #[test]
+    fn test_dib_round_trip() {
+        let bmp = dib_to_bmp(&packed_dib(5, 3)).unwrap();
+        let image = crate::decode::decode_untrusted(bmp, crate::decode::DecodeLimits::default()).unwrap();
+        assert_eq!((image.width(), image.height()), (5, 3));
+        assert_eq!(image.to_rgba8().get_pixel(2, 1).0, [200, 100, 50, 255]);
+    }
+
+    #[test]
+    fn test_image_to_dib() {
+        let mut image = RgbaImage::from_pixel(3, 2, Rgba([10, 20, 30, 255]));
+        image.put_pixel(0, 0, Rgba([255, 0, 0, 255]));
+        let dib = image_to_dib(&image);
+        assert_eq!(dib.len(), BITMAP_INFO_HEADER_SIZE + 3 * 2 * 4);
+
+        let bmp = dib_to_bmp(&dib).unwrap();
+        let decoded = crate::decode::decode_untrusted(bmp, crate::decode::DecodeLimits::default())
+            .unwrap()
+            .to_rgba8();
+        assert_eq!(decoded.dimensions(), (3, 2));
+        assert_eq!(decoded.get_pixel(0, 0).0, [255, 0, 0, 255]);
+        assert_eq!(decoded.get_pixel(2, 1).0, [10, 20, 30, 255]);
+    }
+
+    #[test]
+    fn test_invalid_dib_is_rejected() {
+        assert!(dib_to_bmp(&[]).is_err());
+        assert!(dib_to_bmp(&[40, 0, 0, 0, 1, 2]).is_err());
+
+        // Palette larger than the data
+        let mut dib = packed_dib(2, 2);
+        dib[32..36].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(dib_to_bmp(&dib).is_err());
+    }
+}
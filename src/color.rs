@@ -0,0 +1,534 @@
+//! Color management
+//!
+//! Wide-gamut and HDR monitors have primaries well outside sRGB, so their
+//! pixels look washed out when shown or saved as if they were sRGB. This
+//! module reads the matrix and tone curve part of a monitor's ICC profile,
+//! converts captured pixels from it to sRGB, and builds an sRGB profile for
+//! embedding in exported files.
+//!
+//! Only matrix/TRC RGB profiles are supported, which covers what Windows
+//! installs for displays; profiles built on lookup tables are rejected.
+
+use crate::types::{AppError, AppResult};
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+
+/// ICC profile header size; the tag table follows it
+const HEADER_SIZE: usize = 128;
+
+/// Entries of the linear-to-sRGB encoding table
+const ENCODE_TABLE_SIZE: usize = 4096;
+
+/// Entries of the tone curve in the generated sRGB profile
+const SRGB_CURVE_POINTS: usize = 1024;
+
+/// Largest difference from sRGB at which a profile still counts as sRGB
+const SRGB_TOLERANCE: f32 = 0.005;
+
+/// D50 white point of the profile connection space
+const D50: [f32; 3] = [0.9642, 1.0, 0.8249];
+
+/// Linear sRGB to XYZ, Bradford-adapted to D50; columns are the sRGB colorants
+const SRGB_TO_XYZ_D50: [[f32; 3]; 3] = [
+    [0.436_074_7, 0.385_064_9, 0.143_080_4],
+    [0.222_504_5, 0.716_878_6, 0.060_616_9],
+    [0.013_932_2, 0.097_104_5, 0.714_173_3],
+];
+
+/// XYZ (D50) to linear sRGB, the inverse of [`SRGB_TO_XYZ_D50`]
+const XYZ_D50_TO_SRGB: [[f32; 3]; 3] = [
+    [3.133_856, -1.616_867, -0.490_614_6],
+    [-0.978_768_4, 1.916_142, 0.033_454],
+    [0.071_945_3, -0.228_991_4, 1.405_243],
+];
+
+/// Color handling of captures and exports
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ColorOptions {
+    /// Convert captures from monitors with a non-sRGB profile to sRGB
+    pub convert_to_srgb: bool,
+    /// Embed the color profile of the pixels in exported PNG and JPEG files
+    pub embed_icc_profile: bool,
+}
+
+impl Default for ColorOptions {
+    fn default() -> Self {
+        Self {
+            convert_to_srgb: true,
+            embed_icc_profile: false,
+        }
+    }
+}
+
+/// Decode an sRGB component in 0..=1 to linear light
+pub fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.040_45 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Encode linear light in 0..=1 as an sRGB component
+pub fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.003_130_8 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// How a profile's channel values map to linear light
+#[derive(Debug, Clone, PartialEq)]
+pub enum ToneCurve {
+    /// A pure power function
+    Gamma(f32),
+    /// Evenly spaced samples in 0..=1, interpolated linearly
+    Table(Vec<f32>),
+    /// ICC parametric curve: `(a*x + b)^g + e` from `d` up, `c*x + f` below
+    Parametric { g: f32, a: f32, b: f32, c: f32, d: f32, e: f32, f: f32 },
+}
+
+impl ToneCurve {
+    /// Map a channel value in 0..=1 to linear light
+    pub fn to_linear(&self, value: f32) -> f32 {
+        let value = value.clamp(0.0, 1.0);
+        let linear = match self {
+            ToneCurve::Gamma(gamma) => value.powf(*gamma),
+            ToneCurve::Table(samples) => match samples.len() {
+                0 => value,
+                1 => samples[0],
+                len => {
+                    let position = value * (len - 1) as f32;
+                    let index = (position as usize).min(len - 2);
+                    let fraction = position - index as f32;
+                    samples[index] + (samples[index + 1] - samples[index]) * fraction
+                }
+            },
+            ToneCurve::Parametric { g, a, b, c, d, e, f } => {
+                if value >= *d {
+                    (a * value + b).max(0.0).powf(*g) + e
+                } else {
+                    c * value + f
+                }
+            }
+        };
+        linear.clamp(0.0, 1.0)
+    }
+}
+
+/// The parts of an RGB ICC profile needed to convert its pixels
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorProfile {
+    /// Profile description, e.g. "Display P3"
+    pub description: String,
+    /// Linear RGB to XYZ (D50); columns are the red, green, and blue colorants
+    pub to_xyz: [[f32; 3]; 3],
+    /// Red, green, and blue tone curves
+    pub curves: [ToneCurve; 3],
+}
+
+impl ColorProfile {
+    /// Read a matrix/TRC RGB profile from ICC data
+    pub fn parse(data: &[u8]) -> AppResult<Self> {
+        if data.len() < HEADER_SIZE + 4 || &data[36..40] != b"acsp" {
+            return Err(invalid_profile("ICCプロファイルの形式が不正です"));
+        }
+        if &data[16..20] != b"RGB " || &data[20..24] != b"XYZ " {
+            return Err(invalid_profile("RGB以外のICCプロファイルには対応していません"));
+        }
+
+        let tag_count = read_u32(data, HEADER_SIZE)? as usize;
+        let mut tags = Vec::with_capacity(tag_count.min(64));
+        for index in 0..tag_count {
+            let entry = HEADER_SIZE + 4 + index * 12;
+            let signature = data
+                .get(entry..entry + 4)
+                .ok_or_else(|| invalid_profile("ICCプロファイルのタグ表が途中で切れています"))?;
+            let offset = read_u32(data, entry + 4)? as usize;
+            let size = read_u32(data, entry + 8)? as usize;
+            let body = offset
+                .checked_add(size)
+                .and_then(|end| data.get(offset..end))
+                .ok_or_else(|| invalid_profile("ICCプロファイルのタグがファイルの範囲外です"))?;
+            tags.push((signature, body));
+        }
+        let tag = |signature: &[u8; 4]| {
+            tags.iter()
+                .find(|(name, _)| *name == signature)
+                .map(|(_, body)| *body)
+                .ok_or_else(|| invalid_profile("行列とトーンカーブを持たないICCプロファイルには対応していません"))
+        };
+
+        let columns = [parse_xyz(tag(b"rXYZ")?)?, parse_xyz(tag(b"gXYZ")?)?, parse_xyz(tag(b"bXYZ")?)?];
+        let mut to_xyz = [[0.0; 3]; 3];
+        for (column, xyz) in columns.iter().enumerate() {
+            for (row, value) in xyz.iter().enumerate() {
+                to_xyz[row][column] = *value;
+            }
+        }
+        let curves = [parse_curve(tag(b"rTRC")?)?, parse_curve(tag(b"gTRC")?)?, parse_curve(tag(b"bTRC")?)?];
+        let description = tag(b"desc").ok().and_then(parse_description).unwrap_or_default();
+
+        Ok(Self { description, to_xyz, curves })
+    }
+
+    /// Check whether the profile describes sRGB, so its pixels need no conversion
+    pub fn is_srgb(&self) -> bool {
+        let matrix_matches = self
+            .to_xyz
+            .iter()
+            .flatten()
+            .zip(SRGB_TO_XYZ_D50.iter().flatten())
+            .all(|(value, srgb)| (value - srgb).abs() <= SRGB_TOLERANCE);
+        let curves_match = self.curves.iter().all(|curve| {
+            (0..=16).map(|step| step as f32 / 16.0).all(|value| {
+                // Compare in encoded terms, where dark tones aren't squashed
+                let linear = curve.to_linear(value);
+                (linear_to_srgb(linear) - value).abs() <= SRGB_TOLERANCE * 2.0
+            })
+        });
+        matrix_matches && curves_match
+    }
+}
+
+/// Converts 8-bit pixels from a profile's color space to sRGB
+#[derive(Debug, Clone)]
+pub struct ColorTransform {
+    /// Linear light of each 8-bit value, per channel
+    linearize: [[f32; 256]; 3],
+    /// Source linear RGB to linear sRGB
+    matrix: [[f32; 3]; 3],
+    /// sRGB encoding of evenly spaced linear values
+    encode: Vec<u8>,
+}
+
+impl ColorTransform {
+    /// Create a conversion from `profile` to sRGB
+    pub fn to_srgb(profile: &ColorProfile) -> Self {
+        let mut linearize = [[0.0; 256]; 3];
+        for (table, curve) in linearize.iter_mut().zip(&profile.curves) {
+            for (value, entry) in table.iter_mut().enumerate() {
+                *entry = curve.to_linear(value as f32 / 255.0);
+            }
+        }
+        let encode = (0..ENCODE_TABLE_SIZE)
+            .map(|index| {
+                let linear = index as f32 / (ENCODE_TABLE_SIZE - 1) as f32;
+                (linear_to_srgb(linear) * 255.0).round() as u8
+            })
+            .collect();
+        Self {
+            linearize,
+            matrix: multiply(&XYZ_D50_TO_SRGB, &profile.to_xyz),
+            encode,
+        }
+    }
+
+    /// Convert one pixel's color channels
+    pub fn convert(&self, rgb: [u8; 3]) -> [u8; 3] {
+        let linear = [
+            self.linearize[0][rgb[0] as usize],
+            self.linearize[1][rgb[1] as usize],
+            self.linearize[2][rgb[2] as usize],
+        ];
+        let mut output = [0; 3];
+        for (channel, row) in output.iter_mut().zip(&self.matrix) {
+            // Colors outside the sRGB gamut are clipped
+            let value = (row[0] * linear[0] + row[1] * linear[1] + row[2] * linear[2]).clamp(0.0, 1.0);
+            *channel = self.encode[(value * (ENCODE_TABLE_SIZE - 1) as f32).round() as usize];
+        }
+        output
+    }
+
+    /// Convert an image in place, leaving alpha alone
+    pub fn apply(&self, image: &mut RgbaImage) {
+        for pixel in image.pixels_mut() {
+            let [r, g, b] = self.convert([pixel[0], pixel[1], pixel[2]]);
+            pixel.0 = [r, g, b, pixel[3]];
+        }
+    }
+}
+
+/// Build an ICC profile describing sRGB, for embedding in exported files
+pub fn srgb_icc_profile() -> Vec<u8> {
+    let curve: Vec<u16> = (0..SRGB_CURVE_POINTS)
+        .map(|index| {
+            let linear = srgb_to_linear(index as f32 / (SRGB_CURVE_POINTS - 1) as f32);
+            (linear * 65535.0).round() as u16
+        })
+        .collect();
+    build_matrix_profile("sRGB IEC61966-2.1", &SRGB_TO_XYZ_D50, &curve)
+}
+
+/// Build a version 2 display profile from colorants and a tone curve shared by all channels
+///
+/// `curve` holds evenly spaced samples of the curve scaled to `u16`; an empty
+/// curve is linear.
+pub fn build_matrix_profile(description: &str, to_xyz: &[[f32; 3]; 3], curve: &[u16]) -> Vec<u8> {
+    let mut desc = b"desc\0\0\0\0".to_vec();
+    desc.extend_from_slice(&(description.len() as u32 + 1).to_be_bytes());
+    desc.extend_from_slice(description.as_bytes());
+    // Terminating null, then empty Unicode and ScriptCode descriptions
+    desc.extend_from_slice(&[0; 1 + 8 + 3 + 67]);
+
+    let xyz = |values: [f32; 3]| {
+        let mut body = b"XYZ \0\0\0\0".to_vec();
+        for value in values {
+            body.extend_from_slice(&((value * 65536.0).round() as i32).to_be_bytes());
+        }
+        body
+    };
+    let column = |index: usize| xyz([to_xyz[0][index], to_xyz[1][index], to_xyz[2][index]]);
+
+    let mut trc = b"curv\0\0\0\0".to_vec();
+    trc.extend_from_slice(&(curve.len() as u32).to_be_bytes());
+    for value in curve {
+        trc.extend_from_slice(&value.to_be_bytes());
+    }
+
+    let mut copyright = b"text\0\0\0\0".to_vec();
+    copyright.extend_from_slice(b"No copyright, use freely\0");
+
+    let tags: [(&[u8; 4], Vec<u8>); 9] = [
+        (b"desc", desc),
+        (b"cprt", copyright),
+        (b"wtpt", xyz(D50)),
+        (b"rXYZ", column(0)),
+        (b"gXYZ", column(1)),
+        (b"bXYZ", column(2)),
+        (b"rTRC", trc.clone()),
+        (b"gTRC", trc.clone()),
+        (b"bTRC", trc),
+    ];
+
+    let mut table = (tags.len() as u32).to_be_bytes().to_vec();
+    let mut bodies = Vec::new();
+    let mut offset = HEADER_SIZE + 4 + tags.len() * 12;
+    for (signature, body) in &tags {
+        table.extend_from_slice(*signature);
+        table.extend_from_slice(&(offset as u32).to_be_bytes());
+        table.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        bodies.extend_from_slice(body);
+        // Tag data starts on four-byte boundaries
+        let padding = (4 - body.len() % 4) % 4;
+        bodies.resize(bodies.len() + padding, 0);
+        offset += body.len() + padding;
+    }
+
+    let mut header = vec![0u8; HEADER_SIZE];
+    header[0..4].copy_from_slice(&(offset as u32).to_be_bytes());
+    header[8..12].copy_from_slice(&[2, 0x10, 0, 0]);
+    header[12..16].copy_from_slice(b"mntr");
+    header[16..20].copy_from_slice(b"RGB ");
+    header[20..24].copy_from_slice(b"XYZ ");
+    header[36..40].copy_from_slice(b"acsp");
+    for (index, value) in D50.iter().enumerate() {
+        let start = 68 + index * 4;
+        header[start..start + 4].copy_from_slice(&((value * 65536.0).round() as i32).to_be_bytes());
+    }
+
+    [header, table, bodies].concat()
+}
+
+fn invalid_profile(message: &str) -> AppError {
+    AppError::ImageProcessing(message.to_string())
+}
+
+fn read_u32(data: &[u8], offset: usize) -> AppResult<u32> {
+    data.get(offset..offset + 4)
+        .map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .ok_or_else(|| invalid_profile("ICCプロファイルが途中で切れています"))
+}
+
+fn read_u16(data: &[u8], offset: usize) -> AppResult<u16> {
+    data.get(offset..offset + 2)
+        .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+        .ok_or_else(|| invalid_profile("ICCプロファイルが途中で切れています"))
+}
+
+/// Read an s15Fixed16Number
+fn read_fixed(data: &[u8], offset: usize) -> AppResult<f32> {
+    Ok(read_u32(data, offset)? as i32 as f32 / 65536.0)
+}
+
+/// Read an XYZType tag
+fn parse_xyz(body: &[u8]) -> AppResult<[f32; 3]> {
+    if !body.starts_with(b"XYZ ") {
+        return Err(invalid_profile("ICCプロファイルの色度タグが不正です"));
+    }
+    Ok([read_fixed(body, 8)?, read_fixed(body, 12)?, read_fixed(body, 16)?])
+}
+
+/// Read a curveType or parametricCurveType tag
+fn parse_curve(body: &[u8]) -> AppResult<ToneCurve> {
+    match body.get(0..4) {
+        Some(b"curv") => {
+            let count = read_u32(body, 8)? as usize;
+            match count {
+                0 => Ok(ToneCurve::Gamma(1.0)),
+                // A single u8Fixed8Number gamma
+                1 => Ok(ToneCurve::Gamma(read_u16(body, 12)? as f32 / 256.0)),
+                _ => (0..count)
+                    .map(|index| Ok(read_u16(body, 12 + index * 2)? as f32 / 65535.0))
+                    .collect::<AppResult<Vec<f32>>>()
+                    .map(ToneCurve::Table),
+            }
+        }
+        Some(b"para") => {
+            let function = read_u16(body, 8)?;
+            let parameter_count = match function {
+                0 => 1,
+                1 => 3,
+                2 => 4,
+                3 => 5,
+                4 => 7,
+                _ => return Err(invalid_profile("ICCプロファイルのトーンカーブの種類に対応していません")),
+            };
+            let mut p = [0.0; 7];
+            for (index, parameter) in p.iter_mut().take(parameter_count).enumerate() {
+                *parameter = read_fixed(body, 12 + index * 4)?;
+            }
+            let [g, a, b, c, d, e, f] = p;
+            // Express every function type in the general form of type 4
+            let start = if a != 0.0 { -b / a } else { 0.0 };
+            Ok(match function {
+                0 => ToneCurve::Gamma(g),
+                1 => ToneCurve::Parametric { g, a, b, c: 0.0, d: start, e: 0.0, f: 0.0 },
+                2 => ToneCurve::Parametric { g, a, b, c: 0.0, d: start, e: c, f: c },
+                3 => ToneCurve::Parametric { g, a, b, c, d, e: 0.0, f: 0.0 },
+                _ => ToneCurve::Parametric { g, a, b, c, d, e, f },
+            })
+        }
+        _ => Err(invalid_profile("ICCプロファイルのトーンカーブが不正です")),
+    }
+}
+
+/// Read a textDescriptionType or multiLocalizedUnicodeType tag
+fn parse_description(body: &[u8]) -> Option<String> {
+    match body.get(0..4)? {
+        b"desc" => {
+            let length = read_u32(body, 8).ok()? as usize;
+            let text = body.get(12..12 + length)?;
+            let end = text.iter().position(|&byte| byte == 0).unwrap_or(text.len());
+            Some(String::from_utf8_lossy(&text[..end]).into_owned())
+        }
+        b"mluc" => {
+            // The first record is as good as any for a label
+            let length = read_u32(body, 20).ok()? as usize;
+            let offset = read_u32(body, 24).ok()? as usize;
+            let text = body.get(offset..offset.checked_add(length)?)?;
+            let units: Vec<u16> = text.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect();
+            Some(String::from_utf16_lossy(&units).trim_end_matches('\0').to_string())
+        }
+        _ => None,
+    }
+}
+
+fn multiply(a: &[[f32; 3]; 3], b: &[[f32; 3]; 3]) -> [[f32; 3]; 3] {
+    let mut product = [[0.0; 3]; 3];
+    for (row, output) in product.iter_mut().enumerate() {
+        for (column, value) in output.iter_mut().enumerate() {
+            *value = (0..3).map(|k| a[row][k] * b[k][column]).sum();
+        }
+    }
+    product
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Display P3 colorants, Bradford-adapted to D50
+    const DISPLAY_P3_TO_XYZ_D50: [[f32; 3]; 3] = [
+        [0.515_1, 0.292_0, 0.157_1],
+        [0.241_2, 0.692_2, 0.066_6],
+        [-0.001_1, 0.041_9, 0.784_1],
+    ];
+
+    fn display_p3_profile() -> Vec<u8> {
+        // Same tone curve as sRGB, so only the primaries differ
+        let srgb = ColorProfile::parse(&srgb_icc_profile()).unwrap();
+        let ToneCurve::Table(samples) = &srgb.curves[0] else {
+            panic!("the sRGB profile should have a sampled curve");
+        };
+        let curve: Vec<u16> = samples.iter().map(|value| (value * 65535.0).round() as u16).collect();
+        build_matrix_profile("Display P3", &DISPLAY_P3_TO_XYZ_D50, &curve)
+    }
+
+    #[test]
+    fn test_srgb_profile_round_trip() {
+        let data = srgb_icc_profile();
+        assert_eq!(u32::from_be_bytes(data[0..4].try_into().unwrap()) as usize, data.len());
+        let profile = ColorProfile::parse(&data).unwrap();
+        assert_eq!(profile.description, "sRGB IEC61966-2.1");
+        assert!(profile.is_srgb());
+
+        // Converting sRGB to itself changes nothing beyond rounding
+        let transform = ColorTransform::to_srgb(&profile);
+        for value in [0u8, 1, 10, 64, 128, 200, 254, 255] {
+            let [r, g, b] = transform.convert([value, value / 2, 255 - value]);
+            assert!(r.abs_diff(value) <= 1 && g.abs_diff(value / 2) <= 1 && b.abs_diff(255 - value) <= 1);
+        }
+    }
+
+    #[test]
+    fn test_wide_gamut_conversion() {
+        let profile = ColorProfile::parse(&display_p3_profile()).unwrap();
+        assert_eq!(profile.description, "Display P3");
+        assert!(!profile.is_srgb());
+
+        let transform = ColorTransform::to_srgb(&profile);
+        // Same white point, so grays stay gray
+        for value in [0u8, 50, 128, 255] {
+            let [r, g, b] = transform.convert([value; 3]);
+            assert!(r.abs_diff(value) <= 1 && g.abs_diff(value) <= 1 && b.abs_diff(value) <= 1);
+        }
+        // Wide-gamut colors come out more saturated
+        let [r, g, _] = transform.convert([200, 100, 100]);
+        assert!(r > 205 && g < 100, "got {} {}", r, g);
+
+        let mut image = RgbaImage::from_pixel(2, 1, image::Rgba([200, 100, 100, 77]));
+        transform.apply(&mut image);
+        assert_eq!(image.get_pixel(1, 0).0, [r, g, transform.convert([200, 100, 100])[2], 77]);
+    }
+
+    #[test]
+    fn test_tone_curves() {
+        assert_eq!(ToneCurve::Gamma(2.0).to_linear(0.5), 0.25);
+        assert_eq!(ToneCurve::Table(vec![0.0, 0.5, 1.0]).to_linear(0.25), 0.25);
+        assert_eq!(ToneCurve::Table(Vec::new()).to_linear(0.3), 0.3);
+
+        // The sRGB curve as ICC parametric type 3
+        let curve = parse_curve(&[
+            b"para\0\0\0\0".as_slice(),
+            &[0, 3, 0, 0],
+            &((2.4 * 65536.0) as i32).to_be_bytes(),
+            &((65536.0 / 1.055) as i32).to_be_bytes(),
+            &((0.055 / 1.055 * 65536.0) as i32).to_be_bytes(),
+            &((65536.0 / 12.92) as i32).to_be_bytes(),
+            &((0.040_45 * 65536.0) as i32).to_be_bytes(),
+        ]
+        .concat())
+        .unwrap();
+        for value in [0.01, 0.2, 0.5, 0.9] {
+            assert!((curve.to_linear(value) - srgb_to_linear(value)).abs() < 0.001);
+        }
+        assert!(parse_curve(b"para\0\0\0\0\0\x09\0\0").is_err());
+    }
+
+    #[test]
+    fn test_invalid_profiles() {
+        assert!(ColorProfile::parse(b"not a profile").is_err());
+
+        // Claims a tag past the end of the data
+        let mut data = srgb_icc_profile();
+        data.truncate(HEADER_SIZE + 4 + 12 * 9 + 8);
+        assert!(ColorProfile::parse(&data).is_err());
+
+        let mut gray = srgb_icc_profile();
+        gray[16..20].copy_from_slice(b"GRAY");
+        assert!(ColorProfile::parse(&gray).is_err());
+    }
+}
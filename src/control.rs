@@ -0,0 +1,397 @@
+//! Control interface for scripts
+//!
+//! Presentation tools and scripts can pause the automatic capture triggers
+//! (hotkeys, clipboard watcher, and interval capture) of a running instance
+//! with `lightweight-screenshot-app control pause [hotkeys|clipboard|interval]`,
+//! and start or stop [interval capture](crate::schedule) with
+//! `lightweight-screenshot-app control start interval`.
+//!
+//! The running instance listens on a loopback port. The port is written to
+//! the per-user config directory together with a random token, which clients
+//! send with every command so other users on the machine can't control it.
+
+use crate::events::{AppEvent, EventSender};
+use crate::files::write_atomic;
+use crate::settings::config_dir;
+use crate::types::{AppError, AppResult};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// File in the config directory describing the running instance's endpoint
+const ENDPOINT_FILE_NAME: &str = "control.json";
+
+/// How long a client connection may take to send or receive a line
+const CONTROL_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Get the default endpoint file path
+pub fn default_endpoint_path() -> PathBuf {
+    config_dir().join(ENDPOINT_FILE_NAME)
+}
+
+/// An automatic capture trigger that can be paused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trigger {
+    Hotkeys,
+    ClipboardWatcher,
+    IntervalCapture,
+}
+
+impl Trigger {
+    /// Get all triggers
+    pub fn all() -> [Trigger; 3] {
+        [Trigger::Hotkeys, Trigger::ClipboardWatcher, Trigger::IntervalCapture]
+    }
+
+    /// Name used on the command line
+    pub fn name(self) -> &'static str {
+        match self {
+            Trigger::Hotkeys => "hotkeys",
+            Trigger::ClipboardWatcher => "clipboard",
+            Trigger::IntervalCapture => "interval",
+        }
+    }
+
+    /// Parse a command line name
+    pub fn from_name(name: &str) -> Option<Trigger> {
+        Trigger::all().into_iter().find(|trigger| trigger.name().eq_ignore_ascii_case(name))
+    }
+}
+
+impl std::fmt::Display for Trigger {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Trigger::Hotkeys => "Hotkeys",
+            Trigger::ClipboardWatcher => "Clipboard Watcher",
+            Trigger::IntervalCapture => "Interval Capture",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Which capture triggers are enabled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TriggerState {
+    pub hotkeys: bool,
+    pub clipboard_watcher: bool,
+    pub interval_capture: bool,
+}
+
+impl Default for TriggerState {
+    fn default() -> Self {
+        Self {
+            hotkeys: true,
+            clipboard_watcher: true,
+            interval_capture: true,
+        }
+    }
+}
+
+impl TriggerState {
+    /// Check whether a trigger is enabled
+    pub fn is_enabled(&self, trigger: Trigger) -> bool {
+        match trigger {
+            Trigger::Hotkeys => self.hotkeys,
+            Trigger::ClipboardWatcher => self.clipboard_watcher,
+            Trigger::IntervalCapture => self.interval_capture,
+        }
+    }
+
+    /// Enable or disable a trigger
+    pub fn set_enabled(&mut self, trigger: Trigger, enabled: bool) {
+        match trigger {
+            Trigger::Hotkeys => self.hotkeys = enabled,
+            Trigger::ClipboardWatcher => self.clipboard_watcher = enabled,
+            Trigger::IntervalCapture => self.interval_capture = enabled,
+        }
+    }
+
+    /// Check whether any trigger is paused
+    pub fn any_paused(&self) -> bool {
+        Trigger::all().into_iter().any(|trigger| !self.is_enabled(trigger))
+    }
+}
+
+impl std::fmt::Display for TriggerState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let states: Vec<String> = Trigger::all()
+            .into_iter()
+            .map(|trigger| format!("{}={}", trigger.name(), if self.is_enabled(trigger) { "on" } else { "off" }))
+            .collect();
+        write!(f, "{}", states.join(" "))
+    }
+}
+
+/// Trigger state shared between the editor and the control server
+pub type SharedTriggers = Arc<Mutex<TriggerState>>;
+
+/// A command sent to the running instance
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlCommand {
+    /// Disable one trigger, or all of them
+    Pause(Option<Trigger>),
+    /// Enable one trigger, or all of them
+    Resume(Option<Trigger>),
+    /// Pause one trigger, or all of them, if enabled and resume otherwise
+    Toggle(Option<Trigger>),
+    /// Report which triggers are enabled
+    Status,
+    /// Start interval capture with the saved settings
+    StartInterval,
+    /// Stop interval capture
+    StopInterval,
+}
+
+impl ControlCommand {
+    /// Parse command arguments, e.g. `["pause", "hotkeys"]`
+    pub fn parse<S: AsRef<str>>(args: &[S]) -> AppResult<Self> {
+        let usage = || {
+            AppError::Control(
+                "使い方: control <pause|resume|toggle|status> [hotkeys|clipboard|interval] または control <start|stop> interval"
+                    .to_string(),
+            )
+        };
+        let (name, target) = match args {
+            [name] => (name.as_ref(), None),
+            [name, target] => {
+                let target = target.as_ref();
+                let trigger = Trigger::from_name(target)
+                    .ok_or_else(|| AppError::Control(format!("不明な対象です: {}", target)))?;
+                (name.as_ref(), Some(trigger))
+            }
+            _ => return Err(usage()),
+        };
+        match (name, target) {
+            ("pause", target) => Ok(ControlCommand::Pause(target)),
+            ("resume", target) => Ok(ControlCommand::Resume(target)),
+            ("toggle", target) => Ok(ControlCommand::Toggle(target)),
+            ("status", None) => Ok(ControlCommand::Status),
+            ("start", Some(Trigger::IntervalCapture)) => Ok(ControlCommand::StartInterval),
+            ("stop", Some(Trigger::IntervalCapture)) => Ok(ControlCommand::StopInterval),
+            _ => Err(usage()),
+        }
+    }
+
+    /// Apply the command to the trigger state
+    ///
+    /// Starting and stopping interval capture leaves the trigger state alone.
+    pub fn apply(self, state: &mut TriggerState) {
+        let (target, enabled) = match self {
+            ControlCommand::Pause(target) => (target, false),
+            ControlCommand::Resume(target) => (target, true),
+            // Toggling everything resumes if anything is paused
+            ControlCommand::Toggle(None) => (None, state.any_paused()),
+            ControlCommand::Toggle(Some(trigger)) => (Some(trigger), !state.is_enabled(trigger)),
+            ControlCommand::Status | ControlCommand::StartInterval | ControlCommand::StopInterval => return,
+        };
+        match target {
+            Some(trigger) => state.set_enabled(trigger, enabled),
+            None => Trigger::all().into_iter().for_each(|trigger| state.set_enabled(trigger, enabled)),
+        }
+    }
+}
+
+impl std::fmt::Display for ControlCommand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (name, target) = match self {
+            ControlCommand::Pause(target) => ("pause", target),
+            ControlCommand::Resume(target) => ("resume", target),
+            ControlCommand::Toggle(target) => ("toggle", target),
+            ControlCommand::Status => ("status", &None),
+            ControlCommand::StartInterval => ("start", &Some(Trigger::IntervalCapture)),
+            ControlCommand::StopInterval => ("stop", &Some(Trigger::IntervalCapture)),
+        };
+        match target {
+            Some(trigger) => write!(f, "{} {}", name, trigger.name()),
+            None => write!(f, "{}", name),
+        }
+    }
+}
+
+/// Address and token of the running instance
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Endpoint {
+    pub(crate) port: u16,
+    pub(crate) token: String,
+}
+
+/// Handle one request line ("<token> <command> [target]"), returning the response line
+fn handle_request(line: &str, token: &str, triggers: &SharedTriggers, events: &EventSender) -> String {
+    let mut words = line.split_whitespace();
+    if words.next() != Some(token) {
+        return "error invalid token".to_string();
+    }
+    let args: Vec<&str> = words.collect();
+    match ControlCommand::parse(&args) {
+        Ok(command) => {
+            let mut state = triggers.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            command.apply(&mut state);
+            log::info!("Control command \"{}\": {}", command, state);
+            match command {
+                ControlCommand::Status => {}
+                ControlCommand::StartInterval => {
+                    events.send(AppEvent::IntervalCapture(true));
+                }
+                ControlCommand::StopInterval => {
+                    events.send(AppEvent::IntervalCapture(false));
+                }
+                _ => {
+                    events.send(AppEvent::TriggersChanged(*state));
+                }
+            }
+            format!("ok {}", state)
+        }
+        Err(e) => format!("error {}", e),
+    }
+}
+
+/// Listener for control commands from other processes
+#[derive(Debug)]
+pub struct ControlServer {
+    endpoint_path: PathBuf,
+    token: String,
+}
+
+impl ControlServer {
+    /// Start listening on a loopback port and publish it in the endpoint file
+    ///
+    /// Trigger changes are posted to `events` so the editor can show them.
+    /// The listener thread runs until the process exits.
+    pub fn start(triggers: SharedTriggers, events: EventSender, endpoint_path: &Path) -> AppResult<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+        let endpoint = Endpoint {
+            port: listener.local_addr()?.port(),
+            token: uuid::Uuid::new_v4().simple().to_string(),
+        };
+        let json = serde_json::to_string(&endpoint)
+            .map_err(|e| AppError::Control(format!("接続情報のシリアライズに失敗しました: {}", e)))?;
+        if let Some(parent) = endpoint_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        write_atomic(endpoint_path, json.as_bytes())?;
+
+        let token = endpoint.token.clone();
+        std::thread::Builder::new()
+            .name("control-server".to_string())
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    let result = stream.and_then(|stream| {
+                        stream.set_read_timeout(Some(CONTROL_TIMEOUT))?;
+                        let mut line = String::new();
+                        BufReader::new(&stream).read_line(&mut line)?;
+                        let response = handle_request(&line, &token, &triggers, &events);
+                        (&stream).write_all(format!("{}\n", response).as_bytes())
+                    });
+                    if let Err(e) = result {
+                        log::warn!("Control connection failed: {}", e);
+                    }
+                }
+            })?;
+
+        log::info!("Listening for control commands on port {}", endpoint.port);
+        Ok(Self {
+            endpoint_path: endpoint_path.to_path_buf(),
+            token: endpoint.token,
+        })
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        // Leave the file alone if another instance has taken over the endpoint
+        let ours = std::fs::read_to_string(&self.endpoint_path)
+            .ok()
+            .and_then(|json| serde_json::from_str::<Endpoint>(&json).ok())
+            .is_some_and(|endpoint| endpoint.token == self.token);
+        if ours {
+            let _ = std::fs::remove_file(&self.endpoint_path);
+        }
+    }
+}
+
+/// Send a command to the running instance, returning its trigger state
+pub fn send_command(endpoint_path: &Path, command: ControlCommand) -> AppResult<String> {
+    let json = std::fs::read_to_string(endpoint_path)
+        .map_err(|_| AppError::Control("起動中のアプリが見つかりません".to_string()))?;
+    let endpoint: Endpoint = serde_json::from_str(&json)
+        .map_err(|e| AppError::Control(format!("接続情報の解析に失敗しました: {}", e)))?;
+
+    let address = SocketAddr::from((Ipv4Addr::LOCALHOST, endpoint.port));
+    let stream = TcpStream::connect_timeout(&address, CONTROL_TIMEOUT)
+        .map_err(|_| AppError::Control("起動中のアプリに接続できません".to_string()))?;
+    stream.set_read_timeout(Some(CONTROL_TIMEOUT))?;
+    (&stream).write_all(format!("{} {}\n", endpoint.token, command).as_bytes())?;
+
+    let mut response = String::new();
+    BufReader::new(&stream).read_line(&mut response)?;
+    let response = response.trim();
+    match response.strip_prefix("ok ") {
+        Some(state) => Ok(state.to_string()),
+        None => Err(AppError::Control(response.strip_prefix("error ").unwrap_or(response).to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::EventBus;
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!(ControlCommand::parse(&["pause"]).unwrap(), ControlCommand::Pause(None));
+        assert_eq!(
+            ControlCommand::parse(&["resume", "clipboard"]).unwrap(),
+            ControlCommand::Resume(Some(Trigger::ClipboardWatcher))
+        );
+        assert_eq!(ControlCommand::parse(&["status"]).unwrap(), ControlCommand::Status);
+        assert!(ControlCommand::parse(&["pause", "mouse"]).is_err());
+        assert!(ControlCommand::parse(&["status", "hotkeys"]).is_err());
+        assert!(ControlCommand::parse(&["stop"]).is_err());
+        assert!(ControlCommand::parse(&["start", "hotkeys"]).is_err());
+        assert_eq!(ControlCommand::parse(&["start", "interval"]).unwrap(), ControlCommand::StartInterval);
+        assert_eq!(ControlCommand::StopInterval.to_string(), "stop interval");
+        assert!(ControlCommand::parse::<&str>(&[]).is_err());
+        assert_eq!(ControlCommand::Toggle(Some(Trigger::IntervalCapture)).to_string(), "toggle interval");
+    }
+
+    #[test]
+    fn test_apply_commands() {
+        let mut state = TriggerState::default();
+        ControlCommand::Pause(Some(Trigger::Hotkeys)).apply(&mut state);
+        assert!(!state.hotkeys && state.clipboard_watcher);
+        assert_eq!(state.to_string(), "hotkeys=off clipboard=on interval=on");
+
+        // Toggling all resumes everything while anything is paused
+        ControlCommand::Toggle(None).apply(&mut state);
+        assert_eq!(state, TriggerState::default());
+        ControlCommand::Toggle(None).apply(&mut state);
+        assert!(!state.hotkeys && !state.clipboard_watcher && !state.interval_capture);
+    }
+
+    #[test]
+    fn test_commands_over_loopback() {
+        let dir = std::env::temp_dir().join(format!("control_test_{}", uuid::Uuid::new_v4()));
+        let endpoint_path = dir.join(ENDPOINT_FILE_NAME);
+        let triggers = SharedTriggers::default();
+        let bus = EventBus::new();
+        let server = ControlServer::start(triggers.clone(), bus.sender(), &endpoint_path).unwrap();
+
+        assert_eq!(
+            send_command(&endpoint_path, ControlCommand::Pause(None)).unwrap(),
+            "hotkeys=off clipboard=off interval=off"
+        );
+        assert!(triggers.lock().unwrap().any_paused());
+        assert_eq!(bus.drain(), vec![AppEvent::TriggersChanged(*triggers.lock().unwrap())]);
+        assert_eq!(handle_request("wrong pause", &server.token, &triggers, &bus.sender()), "error invalid token");
+        send_command(&endpoint_path, ControlCommand::StartInterval).unwrap();
+        assert_eq!(bus.drain(), vec![AppEvent::IntervalCapture(true)]);
+
+        drop(server);
+        assert!(!endpoint_path.exists());
+        assert!(send_command(&endpoint_path, ControlCommand::Status).is_err());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,147 @@
+//! Secret storage in the Windows Credential Manager
+//!
+//! Tokens and webhook URLs are not written to the settings file. They are
+//! stored as generic credentials of the current user, under
+//! `lightweight-screenshot-app/<key>`, and can be inspected or removed in
+//! the Credential Manager control panel. Other platforms have no credential
+//! store.
+
+use crate::types::{AppError, AppResult};
+
+/// Prefix of the credential names, so the app's credentials are grouped together
+const TARGET_PREFIX: &str = "lightweight-screenshot-app/";
+
+/// Get the name of the credential holding the secret stored under `key`
+pub fn target_name(key: &str) -> String {
+    format!("{}{}", TARGET_PREFIX, key)
+}
+
+/// Store a secret under `key`, replacing any previous one
+#[cfg(windows)]
+pub fn store(key: &str, secret: &str) -> AppResult<()> {
+    use crate::files::to_wide;
+    use winapi::um::wincred::{CredWriteW, CREDENTIALW, CRED_PERSIST_LOCAL_MACHINE, CRED_TYPE_GENERIC};
+
+    let mut target = to_wide(&target_name(key));
+    let mut user = to_wide(crate::editor_app::APP_TITLE);
+    let mut blob = secret.as_bytes().to_vec();
+    // SAFETY: all fields are plain data or pointers, for which zero is a valid "unset" value
+    let mut credential: CREDENTIALW = unsafe { std::mem::zeroed() };
+    credential.Type = CRED_TYPE_GENERIC;
+    credential.TargetName = target.as_mut_ptr();
+    credential.UserName = user.as_mut_ptr();
+    credential.CredentialBlobSize = blob.len() as u32;
+    credential.CredentialBlob = blob.as_mut_ptr();
+    credential.Persist = CRED_PERSIST_LOCAL_MACHINE;
+    // SAFETY: the strings are null-terminated and outlive the call, and the blob size is its length
+    if unsafe { CredWriteW(&mut credential, 0) } == 0 {
+        return Err(AppError::Settings(format!(
+            "資格情報 {} を保存できませんでした (エラー {})",
+            target_name(key),
+            last_error()
+        )));
+    }
+    Ok(())
+}
+
+/// Store a secret under `key`, replacing any previous one
+#[cfg(not(windows))]
+pub fn store(_key: &str, _secret: &str) -> AppResult<()> {
+    Err(unsupported())
+}
+
+/// Get the secret stored under `key`, if any
+#[cfg(windows)]
+pub fn load(key: &str) -> AppResult<Option<String>> {
+    use crate::files::to_wide;
+    use winapi::shared::winerror::ERROR_NOT_FOUND;
+    use winapi::um::wincred::{CredFree, CredReadW, CRED_TYPE_GENERIC, PCREDENTIALW};
+
+    let target = to_wide(&target_name(key));
+    let mut credential: PCREDENTIALW = std::ptr::null_mut();
+    // SAFETY: the name is null-terminated and `credential` receives a buffer freed below
+    if unsafe { CredReadW(target.as_ptr(), CRED_TYPE_GENERIC, 0, &mut credential) } == 0 {
+        let error = last_error();
+        if error == ERROR_NOT_FOUND {
+            return Ok(None);
+        }
+        return Err(AppError::Settings(format!(
+            "資格情報 {} を読み込めませんでした (エラー {})",
+            target_name(key),
+            error
+        )));
+    }
+    // SAFETY: a successful read returns a valid credential whose blob has the given size
+    let secret = unsafe {
+        let blob = std::slice::from_raw_parts((*credential).CredentialBlob, (*credential).CredentialBlobSize as usize);
+        let secret = String::from_utf8_lossy(blob).into_owned();
+        CredFree(credential.cast());
+        secret
+    };
+    Ok(Some(secret))
+}
+
+/// Get the secret stored under `key`, if any
+#[cfg(not(windows))]
+pub fn load(_key: &str) -> AppResult<Option<String>> {
+    Err(unsupported())
+}
+
+/// Delete the secret stored under `key`, if any
+#[cfg(windows)]
+pub fn delete(key: &str) -> AppResult<()> {
+    use crate::files::to_wide;
+    use winapi::shared::winerror::ERROR_NOT_FOUND;
+    use winapi::um::wincred::{CredDeleteW, CRED_TYPE_GENERIC};
+
+    let target = to_wide(&target_name(key));
+    // SAFETY: the name is null-terminated
+    if unsafe { CredDeleteW(target.as_ptr(), CRED_TYPE_GENERIC, 0) } == 0 {
+        let error = last_error();
+        if error != ERROR_NOT_FOUND {
+            return Err(AppError::Settings(format!(
+                "資格情報 {} を削除できませんでした (エラー {})",
+                target_name(key),
+                error
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Delete the secret stored under `key`, if any
+///
+/// Nothing can be stored on this platform, so there is nothing to delete.
+#[cfg(not(windows))]
+pub fn delete(_key: &str) -> AppResult<()> {
+    Ok(())
+}
+
+#[cfg(not(windows))]
+fn unsupported() -> AppError {
+    AppError::Settings("この環境では資格情報マネージャーを利用できません".to_string())
+}
+
+#[cfg(windows)]
+fn last_error() -> u32 {
+    // SAFETY: reads the calling thread's last error code
+    unsafe { winapi::um::errhandlingapi::GetLastError() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_target_name() {
+        assert_eq!(target_name("share/1234"), "lightweight-screenshot-app/share/1234");
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn test_unsupported_platform() {
+        assert!(matches!(store("share/test", "secret"), Err(AppError::Settings(_))));
+        assert!(matches!(load("share/test"), Err(AppError::Settings(_))));
+        assert!(delete("share/test").is_ok());
+    }
+}
@@ -0,0 +1,158 @@
+//! Canvas cursors
+//!
+//! The pointer over the canvas shows what pressing or dragging would do:
+//! a crosshair for drawing tools, an I-beam for text, a hand while
+//! panning, move and resize arrows over annotations and their handles, and
+//! an eyedropper while picking a color. egui has no eyedropper cursor, so
+//! that one is drawn over the canvas in place of the system cursor.
+
+use crate::types::Tool;
+use egui::{Color32, CursorIcon, Painter, Pos2, Stroke, Vec2};
+
+/// Length of the drawn eyedropper from its tip
+const EYEDROPPER_LENGTH: f32 = 18.0;
+
+/// What the pointer is over on the canvas, as far as the cursor depends on it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CanvasTarget {
+    /// The image or the empty canvas
+    Canvas,
+    /// An annotation the Select tool can move
+    Annotation,
+    /// The rotate handle of a selected annotation
+    RotateHandle,
+    /// A resize handle, with the direction from the annotation's center to it
+    ResizeHandle(Vec2),
+    /// A corner handle of the Perspective tool
+    PerspectiveHandle,
+    /// Inside the capture region being selected
+    RegionSelection,
+}
+
+/// State of the canvas deciding its cursor
+#[derive(Debug, Clone, PartialEq)]
+pub struct CursorState {
+    pub tool: Tool,
+    pub target: CanvasTarget,
+    /// Whether the primary or middle button is dragging
+    pub dragging: bool,
+    /// Whether the drag pans the view, or a pan would start (Shift held)
+    pub panning: bool,
+    /// Whether a color is being picked from a frozen frame
+    pub picking_color: bool,
+    /// Whether a capture region is being selected
+    pub selecting_region: bool,
+}
+
+/// Cursor shown over the canvas
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CanvasCursor {
+    /// A system cursor
+    Icon(CursorIcon),
+    /// The eyedropper, drawn over the canvas
+    Eyedropper,
+}
+
+/// Get the cursor for the canvas in `state`
+pub fn canvas_cursor(state: &CursorState) -> CanvasCursor {
+    let grab = if state.dragging { CursorIcon::Grabbing } else { CursorIcon::Grab };
+    if state.picking_color {
+        return CanvasCursor::Eyedropper;
+    }
+    if state.panning {
+        return CanvasCursor::Icon(grab);
+    }
+    let icon = if state.selecting_region {
+        match state.target {
+            CanvasTarget::RegionSelection => CursorIcon::Move,
+            _ => CursorIcon::Crosshair,
+        }
+    } else {
+        match (&state.tool, state.target) {
+            (Tool::Select, CanvasTarget::ResizeHandle(direction)) => resize_icon(direction),
+            (Tool::Select, CanvasTarget::RotateHandle) => grab,
+            (Tool::Select, CanvasTarget::Annotation) => CursorIcon::Move,
+            (Tool::Select, _) => CursorIcon::Default,
+            (Tool::Text, _) => CursorIcon::Text,
+            (Tool::Rectangle | Tool::Sticker | Tool::Eraser | Tool::Measure, _) => CursorIcon::Crosshair,
+            (Tool::Perspective, CanvasTarget::PerspectiveHandle) => grab,
+            (Tool::Perspective, _) => CursorIcon::Default,
+        }
+    };
+    CanvasCursor::Icon(icon)
+}
+
+/// Get the diagonal resize arrows closest to a handle's direction from the center
+fn resize_icon(direction: Vec2) -> CursorIcon {
+    // Screen y points down, so the top-left and bottom-right directions have the same sign
+    if direction.x * direction.y >= 0.0 {
+        CursorIcon::ResizeNwSe
+    } else {
+        CursorIcon::ResizeNeSw
+    }
+}
+
+/// Draw the eyedropper cursor with its tip at `tip`
+pub fn paint_eyedropper(painter: &Painter, tip: Pos2) {
+    let direction = Vec2::new(1.0, -1.0).normalized();
+    let end = tip + direction * EYEDROPPER_LENGTH;
+    let bulb = tip + direction * (EYEDROPPER_LENGTH - 3.0);
+    let outline = Stroke::new(5.0, Color32::BLACK);
+    painter.line_segment([tip, end], outline);
+    painter.line_segment([tip, end], Stroke::new(3.0, Color32::WHITE));
+    painter.circle(bulb, 4.5, Color32::WHITE, Stroke::new(1.0, Color32::BLACK));
+    painter.circle_filled(tip, 1.0, Color32::BLACK);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(tool: Tool, target: CanvasTarget) -> CursorState {
+        CursorState {
+            tool,
+            target,
+            dragging: false,
+            panning: false,
+            picking_color: false,
+            selecting_region: false,
+        }
+    }
+
+    #[test]
+    fn test_cursor_per_tool() {
+        let icon = |tool, target| canvas_cursor(&state(tool, target));
+        assert_eq!(icon(Tool::Rectangle, CanvasTarget::Annotation), CanvasCursor::Icon(CursorIcon::Crosshair));
+        assert_eq!(icon(Tool::Text, CanvasTarget::Canvas), CanvasCursor::Icon(CursorIcon::Text));
+        assert_eq!(icon(Tool::Select, CanvasTarget::Canvas), CanvasCursor::Icon(CursorIcon::Default));
+        assert_eq!(icon(Tool::Select, CanvasTarget::Annotation), CanvasCursor::Icon(CursorIcon::Move));
+        assert_eq!(
+            icon(Tool::Select, CanvasTarget::ResizeHandle(Vec2::new(-1.0, -1.0))),
+            CanvasCursor::Icon(CursorIcon::ResizeNwSe)
+        );
+        assert_eq!(
+            icon(Tool::Select, CanvasTarget::ResizeHandle(Vec2::new(1.0, -1.0))),
+            CanvasCursor::Icon(CursorIcon::ResizeNeSw)
+        );
+        assert_eq!(icon(Tool::Perspective, CanvasTarget::PerspectiveHandle), CanvasCursor::Icon(CursorIcon::Grab));
+    }
+
+    #[test]
+    fn test_panning_and_picking_override_the_tool() {
+        let mut panning = state(Tool::Text, CanvasTarget::Canvas);
+        panning.panning = true;
+        assert_eq!(canvas_cursor(&panning), CanvasCursor::Icon(CursorIcon::Grab));
+        panning.dragging = true;
+        assert_eq!(canvas_cursor(&panning), CanvasCursor::Icon(CursorIcon::Grabbing));
+
+        let mut picking = panning;
+        picking.picking_color = true;
+        assert_eq!(canvas_cursor(&picking), CanvasCursor::Eyedropper);
+
+        let mut selecting = state(Tool::Select, CanvasTarget::RegionSelection);
+        selecting.selecting_region = true;
+        assert_eq!(canvas_cursor(&selecting), CanvasCursor::Icon(CursorIcon::Move));
+        selecting.target = CanvasTarget::Canvas;
+        assert_eq!(canvas_cursor(&selecting), CanvasCursor::Icon(CursorIcon::Crosshair));
+    }
+}
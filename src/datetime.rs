@@ -0,0 +1,116 @@
+//! Calendar dates and times
+//!
+//! Minimal conversion of `SystemTime` to a calendar date and time of day for
+//! export banners and generated file names, without a date library.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A calendar date and time of day in UTC
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DateTime {
+    pub year: i32,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+}
+
+impl DateTime {
+    /// Convert a system time to UTC (times before 1970 are clamped to the epoch)
+    pub fn from_system_time(time: SystemTime) -> Self {
+        let seconds = time.duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_secs()).unwrap_or_default();
+        Self::from_unix_seconds(seconds)
+    }
+
+    /// Get the current time in UTC
+    pub fn now() -> Self {
+        Self::from_system_time(SystemTime::now())
+    }
+
+    /// Get the current local time
+    #[cfg(windows)]
+    pub fn now_local() -> Self {
+        use winapi::um::minwinbase::SYSTEMTIME;
+        use winapi::um::sysinfoapi::GetLocalTime;
+
+        // SAFETY: GetLocalTime only writes to the provided struct
+        let mut time: SYSTEMTIME = unsafe { std::mem::zeroed() };
+        unsafe { GetLocalTime(&mut time) };
+        Self {
+            year: i32::from(time.wYear),
+            month: u32::from(time.wMonth),
+            day: u32::from(time.wDay),
+            hour: u32::from(time.wHour),
+            minute: u32::from(time.wMinute),
+            second: u32::from(time.wSecond),
+        }
+    }
+
+    /// Get the current local time
+    ///
+    /// Time zones are only resolved on Windows, so this is UTC elsewhere.
+    #[cfg(not(windows))]
+    pub fn now_local() -> Self {
+        Self::now()
+    }
+
+    /// Convert seconds since the Unix epoch to UTC
+    pub fn from_unix_seconds(seconds: u64) -> Self {
+        let days = (seconds / 86_400) as i64;
+        let time_of_day = (seconds % 86_400) as u32;
+
+        // Civil-from-days conversion (Howard Hinnant's algorithm)
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let day_of_era = z.rem_euclid(146_097);
+        let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month_index = (5 * day_of_year + 2) / 153;
+        let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+        let month = if month_index < 10 { month_index + 3 } else { month_index - 9 } as u32;
+        let year = (year_of_era + era * 400) as i32 + i32::from(month <= 2);
+
+        Self {
+            year,
+            month,
+            day,
+            hour: time_of_day / 3600,
+            minute: time_of_day / 60 % 60,
+            second: time_of_day % 60,
+        }
+    }
+}
+
+impl std::fmt::Display for DateTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_known_dates() {
+        assert_eq!(DateTime::from_unix_seconds(0).to_string(), "1970-01-01 00:00:00 UTC");
+        assert_eq!(DateTime::from_unix_seconds(951_782_400).to_string(), "2000-02-29 00:00:00 UTC");
+        assert_eq!(DateTime::from_unix_seconds(1_709_210_096).to_string(), "2024-02-29 12:34:56 UTC");
+        assert_eq!(DateTime::from_unix_seconds(1_735_689_599).to_string(), "2024-12-31 23:59:59 UTC");
+    }
+
+    #[test]
+    fn test_from_system_time() {
+        let time = UNIX_EPOCH + Duration::from_secs(86_400 + 61);
+        assert_eq!(
+            DateTime::from_system_time(time),
+            DateTime { year: 1970, month: 1, day: 2, hour: 0, minute: 1, second: 1 }
+        );
+    }
+}
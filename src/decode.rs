@@ -0,0 +1,273 @@
+//! Hardened decoding of untrusted images
+//!
+//! Images from outside the application (dropped files, clipboard, URLs) may
+//! be malformed or hostile. They are decoded with strict size and memory
+//! limits, restricted to known formats, and on a worker thread guarded by
+//! `catch_unwind` so a decoder panic is reported as an error instead of
+//! taking down the editor session. A decode that runs past its timeout is
+//! abandoned, so a pathological file can't hang the caller. The loader is
+//! fuzzed by the `decode` target in `fuzz/`.
+
+use crate::profiling::{self, Stage};
+use crate::types::{AppError, AppResult};
+use crossbeam_channel::RecvTimeoutError;
+use image::error::ImageError;
+use image::io::{Limits, Reader};
+use image::{DynamicImage, ImageFormat};
+use std::io::{Cursor, ErrorKind, Read};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::time::Duration;
+
+/// Formats accepted from untrusted sources
+const ALLOWED_FORMATS: [ImageFormat; 5] = [
+    ImageFormat::Png,
+    ImageFormat::Jpeg,
+    ImageFormat::Bmp,
+    ImageFormat::Gif,
+    ImageFormat::WebP,
+];
+
+/// Limits applied when decoding untrusted images
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeLimits {
+    /// Maximum encoded input size in bytes
+    pub max_input_bytes: u64,
+    pub max_width: u32,
+    pub max_height: u32,
+    /// Maximum memory the decoder may allocate in bytes
+    pub max_alloc: u64,
+    /// Longest time to wait for the decoder before giving up
+    pub timeout: Duration,
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        Self {
+            max_input_bytes: 256 * 1024 * 1024,
+            max_width: 32_768,
+            max_height: 32_768,
+            max_alloc: 1024 * 1024 * 1024,
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+impl DecodeLimits {
+    /// Convert to `image` crate decoder limits
+    fn to_image_limits(self) -> Limits {
+        let mut limits = Limits::default();
+        limits.max_image_width = Some(self.max_width);
+        limits.max_image_height = Some(self.max_height);
+        limits.max_alloc = Some(self.max_alloc);
+        limits
+    }
+}
+
+/// Decode untrusted image bytes on an isolated worker thread
+///
+/// A worker that times out can't be stopped; it keeps running detached
+/// until the decoder returns, and its result is dropped.
+pub fn decode_untrusted(bytes: Vec<u8>, limits: DecodeLimits) -> AppResult<DynamicImage> {
+    if bytes.is_empty() {
+        return Err(AppError::ImageProcessing("画像ファイルが空です".to_string()));
+    }
+    if bytes.len() as u64 > limits.max_input_bytes {
+        return Err(AppError::ImageProcessing(format!(
+            "画像ファイルが大きすぎます ({} バイト、上限 {} バイト)",
+            bytes.len(),
+            limits.max_input_bytes
+        )));
+    }
+
+    let (sender, receiver) = crossbeam_channel::bounded(1);
+    std::thread::Builder::new()
+        .name("untrusted-decode".to_string())
+        .spawn(move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| decode_with_limits(&bytes, limits)));
+            // The receiver is gone if the decode timed out
+            let _ = sender.send(result);
+        })
+        .map_err(AppError::FileAccess)?;
+
+    match receiver.recv_timeout(limits.timeout) {
+        Ok(Ok(result)) => result,
+        Ok(Err(_)) | Err(RecvTimeoutError::Disconnected) => {
+            log::error!("Image decoder panicked on untrusted input");
+            Err(AppError::ImageProcessing("画像のデコード中に異常が発生しました".to_string()))
+        }
+        Err(RecvTimeoutError::Timeout) => {
+            log::error!("Image decoder timed out after {:?} on untrusted input", limits.timeout);
+            Err(AppError::ImageProcessing(format!(
+                "画像のデコードが{}秒以内に終わらなかったため中止しました",
+                limits.timeout.as_secs_f32()
+            )))
+        }
+    }
+}
+
+/// Read and decode an untrusted image file
+///
+/// Decode errors name the file, since several may be opened at once.
+pub fn decode_untrusted_file(path: &Path, limits: DecodeLimits) -> AppResult<DynamicImage> {
+    let file = std::fs::File::open(crate::files::extended_length_path(path))?;
+    // Read one byte past the limit to detect oversized files without trusting metadata
+    let mut bytes = Vec::new();
+    file.take(limits.max_input_bytes + 1).read_to_end(&mut bytes)?;
+    decode_untrusted(bytes, limits).map_err(|e| match e {
+        AppError::ImageProcessing(message) => {
+            let name = path.file_name().map_or_else(|| path.display().to_string(), |name| name.to_string_lossy().into_owned());
+            AppError::ImageProcessing(format!("{}: {}", name, message))
+        }
+        other => other,
+    })
+}
+
+/// Decode bytes with format restrictions and decoder limits
+fn decode_with_limits(bytes: &[u8], limits: DecodeLimits) -> AppResult<DynamicImage> {
+    let _timer = profiling::scope(Stage::Decode);
+    let mut reader = Reader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .map_err(AppError::FileAccess)?;
+
+    match reader.format() {
+        Some(format) if ALLOWED_FORMATS.contains(&format) => {}
+        Some(format) => {
+            return Err(AppError::ImageProcessing(format!("対応していない画像形式です: {:?}", format)));
+        }
+        None => return Err(AppError::ImageProcessing("画像形式を判別できません".to_string())),
+    }
+
+    reader.limits(limits.to_image_limits());
+    reader.decode().map_err(|e| decode_error(e, limits))
+}
+
+/// Describe why an image could not be decoded
+fn decode_error(error: ImageError, limits: DecodeLimits) -> AppError {
+    let message = match &error {
+        ImageError::Limits(_) => format!(
+            "画像が大きすぎます（{}×{}ピクセルまで）: {}",
+            limits.max_width, limits.max_height, error
+        ),
+        ImageError::Unsupported(_) => format!("この画像の形式または機能には対応していません: {}", error),
+        ImageError::IoError(e) if e.kind() == ErrorKind::UnexpectedEof => {
+            "画像ファイルが途中で途切れています".to_string()
+        }
+        ImageError::Decoding(_) | ImageError::Parameter(_) => format!("画像ファイルが破損しています: {}", error),
+        _ => format!("画像のデコードに失敗しました: {}", error),
+    };
+    AppError::ImageProcessing(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    fn png_bytes(width: u32, height: u32) -> Vec<u8> {
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(width, height, Rgba([1, 2, 3, 255])));
+        let mut bytes = Vec::new();
+        image.write_to(&mut Cursor::new(&mut bytes), image::ImageOutputFormat::Png).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_decode_valid_png() {
+        let image = decode_untrusted(png_bytes(16, 8), DecodeLimits::default()).unwrap();
+        assert_eq!((image.width(), image.height()), (16, 8));
+    }
+
+    #[test]
+    fn test_rejects_oversized_input() {
+        let limits = DecodeLimits {
+            max_input_bytes: 10,
+            ..DecodeLimits::default()
+        };
+        assert!(matches!(decode_untrusted(png_bytes(4, 4), limits), Err(AppError::ImageProcessing(_))));
+    }
+
+    #[test]
+    fn test_rejects_oversized_dimensions() {
+        let limits = DecodeLimits {
+            max_width: 8,
+            ..DecodeLimits::default()
+        };
+        assert!(decode_untrusted(png_bytes(16, 4), limits).is_err());
+    }
+
+    #[test]
+    fn test_rejects_garbage_and_truncated_data() {
+        assert!(decode_untrusted(b"definitely not an image".to_vec(), DecodeLimits::default()).is_err());
+
+        let mut truncated = png_bytes(32, 32);
+        truncated.truncate(truncated.len() / 2);
+        assert!(decode_untrusted(truncated, DecodeLimits::default()).is_err());
+    }
+
+    #[test]
+    fn test_errors_describe_the_problem() {
+        let message = |bytes: Vec<u8>| match decode_untrusted(bytes, DecodeLimits::default()) {
+            Err(AppError::ImageProcessing(message)) => message,
+            other => panic!("Expected ImageProcessing error, got {:?}", other.map(|_| ())),
+        };
+        assert!(message(Vec::new()).contains("空"));
+
+        let mut truncated = png_bytes(32, 32);
+        truncated.truncate(truncated.len() / 2);
+        assert!(message(truncated).contains("途切れ"));
+
+        let mut corrupt = png_bytes(32, 32);
+        let last = corrupt.len() - 20;
+        corrupt[40..last].fill(0xAB);
+        assert!(message(corrupt).contains("破損"));
+    }
+
+    #[test]
+    fn test_gives_up_after_timeout() {
+        // The worker can't finish before a zero timeout expires
+        let limits = DecodeLimits {
+            timeout: Duration::ZERO,
+            ..DecodeLimits::default()
+        };
+        match decode_untrusted(png_bytes(512, 512), limits) {
+            Err(AppError::ImageProcessing(message)) => assert!(message.contains("中止")),
+            other => panic!("Expected a timeout, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_rejects_disallowed_format() {
+        // A minimal TIFF header is recognized but not accepted from untrusted sources
+        let tiff = b"II*\0\x08\0\0\0\0\0".to_vec();
+        match decode_untrusted(tiff, DecodeLimits::default()) {
+            Err(AppError::ImageProcessing(message)) => assert!(message.contains("Tiff")),
+            other => panic!("Expected ImageProcessing error, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_decode_file() {
+        let path = std::env::temp_dir().join(format!("lsa-decode-test-{}.png", uuid::Uuid::new_v4()));
+        std::fs::write(&path, png_bytes(3, 5)).unwrap();
+        let image = decode_untrusted_file(&path, DecodeLimits::default()).unwrap();
+        assert_eq!((image.width(), image.height()), (3, 5));
+
+        // Errors name the file
+        std::fs::write(&path, b"not an image").unwrap();
+        match decode_untrusted_file(&path, DecodeLimits::default()) {
+            Err(AppError::ImageProcessing(message)) => assert!(message.starts_with("lsa-decode-test-")),
+            other => panic!("Expected ImageProcessing error, got {:?}", other.map(|_| ())),
+        }
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_decode_file_with_japanese_name() {
+        let dir = std::env::temp_dir().join(format!("lsa-decode-test-{}", uuid::Uuid::new_v4())).join("画像");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("スクリーンショット.png");
+        std::fs::write(&path, png_bytes(2, 2)).unwrap();
+        assert!(decode_untrusted_file(&path, DecodeLimits::default()).is_ok());
+        std::fs::remove_dir_all(dir.parent().unwrap()).ok();
+    }
+}
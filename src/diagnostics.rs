@@ -0,0 +1,196 @@
+//! Performance diagnostics
+//!
+//! Collects recent frame times, texture upload times, and document memory
+//! use so they can be shown in the debug overlay when investigating reports
+//! of sluggishness on specific hardware.
+
+use crate::profiling::{self, Stage};
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// Number of samples kept per series
+pub const DIAGNOSTICS_HISTORY: usize = 240;
+
+/// Frame budget for 60 FPS, drawn as a reference line in the overlay
+pub const FRAME_BUDGET: Duration = Duration::from_micros(16_667);
+
+/// Fixed-size history of timing samples in milliseconds
+#[derive(Debug, Clone)]
+pub struct SampleHistory {
+    samples: VecDeque<f32>,
+    capacity: usize,
+}
+
+impl SampleHistory {
+    /// Create an empty history holding at most `capacity` samples
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            samples: VecDeque::with_capacity(capacity),
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// Record a duration, dropping the oldest sample when full
+    pub fn push(&mut self, duration: Duration) {
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(duration.as_secs_f32() * 1000.0);
+    }
+
+    /// Get the samples in milliseconds, oldest first
+    pub fn samples(&self) -> impl Iterator<Item = f32> + '_ {
+        self.samples.iter().copied()
+    }
+
+    /// Number of recorded samples
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// Check whether no samples were recorded
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Most recent sample in milliseconds
+    pub fn last(&self) -> Option<f32> {
+        self.samples.back().copied()
+    }
+
+    /// Mean of the recorded samples in milliseconds
+    pub fn average(&self) -> Option<f32> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        Some(self.samples.iter().sum::<f32>() / self.samples.len() as f32)
+    }
+
+    /// Largest recorded sample in milliseconds
+    pub fn max(&self) -> Option<f32> {
+        self.samples.iter().copied().reduce(f32::max)
+    }
+
+    /// Sample at the given percentile (0.0-1.0) in milliseconds
+    pub fn percentile(&self, percentile: f32) -> Option<f32> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        let mut sorted: Vec<f32> = self.samples.iter().copied().collect();
+        sorted.sort_by(f32::total_cmp);
+        let index = ((sorted.len() - 1) as f32 * percentile.clamp(0.0, 1.0)).round() as usize;
+        Some(sorted[index])
+    }
+}
+
+/// Diagnostics collected by the editor
+#[derive(Debug, Clone)]
+pub struct Diagnostics {
+    pub frame_times: SampleHistory,
+    pub texture_uploads: SampleHistory,
+    /// Approximate bytes held by loaded documents (images, caches, textures)
+    pub document_memory: usize,
+}
+
+impl Default for Diagnostics {
+    fn default() -> Self {
+        Self {
+            frame_times: SampleHistory::new(DIAGNOSTICS_HISTORY),
+            texture_uploads: SampleHistory::new(DIAGNOSTICS_HISTORY),
+            document_memory: 0,
+        }
+    }
+}
+
+impl Diagnostics {
+    /// Create empty diagnostics
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the CPU time spent building a frame
+    pub fn record_frame(&mut self, duration: Duration) {
+        self.frame_times.push(duration);
+    }
+
+    /// Record the time spent converting and uploading a texture
+    pub fn record_texture_upload(&mut self, duration: Duration) {
+        self.texture_uploads.push(duration);
+        profiling::record(Stage::TextureUpload, duration);
+    }
+
+    /// Fraction of recorded frames that exceeded the 60 FPS budget
+    pub fn over_budget_ratio(&self) -> f32 {
+        if self.frame_times.is_empty() {
+            return 0.0;
+        }
+        let budget = FRAME_BUDGET.as_secs_f32() * 1000.0;
+        let over = self.frame_times.samples().filter(|&ms| ms > budget).count();
+        over as f32 / self.frame_times.len() as f32
+    }
+}
+
+/// Format a byte count for display (e.g. "12.3 MB")
+pub fn format_bytes(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history_drops_oldest() {
+        let mut history = SampleHistory::new(3);
+        for ms in 1..=5 {
+            history.push(Duration::from_millis(ms));
+        }
+        assert_eq!(history.len(), 3);
+        let samples: Vec<f32> = history.samples().collect();
+        assert_eq!(samples, vec![3.0, 4.0, 5.0]);
+        assert_eq!(history.last(), Some(5.0));
+    }
+
+    #[test]
+    fn test_history_statistics() {
+        let mut history = SampleHistory::new(10);
+        assert!(history.average().is_none());
+        assert!(history.percentile(0.5).is_none());
+
+        for ms in [10, 20, 30, 40] {
+            history.push(Duration::from_millis(ms));
+        }
+        assert_eq!(history.average(), Some(25.0));
+        assert_eq!(history.max(), Some(40.0));
+        assert_eq!(history.percentile(0.0), Some(10.0));
+        assert_eq!(history.percentile(1.0), Some(40.0));
+    }
+
+    #[test]
+    fn test_over_budget_ratio() {
+        let mut diagnostics = Diagnostics::new();
+        assert_eq!(diagnostics.over_budget_ratio(), 0.0);
+
+        diagnostics.record_frame(Duration::from_millis(5));
+        diagnostics.record_frame(Duration::from_millis(40));
+        assert_eq!(diagnostics.over_budget_ratio(), 0.5);
+    }
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(2048), "2.0 KB");
+        assert_eq!(format_bytes(5 * 1024 * 1024), "5.0 MB");
+    }
+}
@@ -0,0 +1,81 @@
+//! Native file dialogs
+//!
+//! Uses the Win32 common dialogs on Windows. Other platforms have no native
+//! dialog here, and the editor falls back to typing a path instead.
+
+use std::path::{Path, PathBuf};
+
+/// Image types offered by the open dialog
+pub const OPEN_IMAGE_EXTENSIONS: [&str; 6] = ["png", "jpg", "jpeg", "bmp", "webp", "gif"];
+
+/// Check whether a native file dialog is available on this platform
+pub fn has_native_dialog() -> bool {
+    cfg!(windows)
+}
+
+/// Build a Win32 dialog filter string ("Name\0*.a;*.b\0...\0\0")
+#[cfg_attr(not(windows), allow(dead_code))]
+fn dialog_filter(name: &str, extensions: &[&str]) -> String {
+    let patterns: Vec<String> = extensions.iter().map(|ext| format!("*.{}", ext)).collect();
+    let patterns = patterns.join(";");
+    format!("{} ({})\0{}\0All Files (*.*)\0*.*\0\0", name, patterns, patterns)
+}
+
+/// Ask the user for an image file to open
+///
+/// Returns `None` if the dialog was cancelled or is unavailable.
+pub fn pick_image_file(initial_dir: Option<&Path>) -> Option<PathBuf> {
+    pick_file("Images", &OPEN_IMAGE_EXTENSIONS, initial_dir)
+}
+
+/// Ask the user for a file with one of the given extensions
+///
+/// Returns `None` if the dialog was cancelled or is unavailable.
+#[cfg(windows)]
+pub fn pick_file(name: &str, extensions: &[&str], initial_dir: Option<&Path>) -> Option<PathBuf> {
+    use std::ffi::OsString;
+    use std::os::windows::ffi::{OsStrExt, OsStringExt};
+    use winapi::um::commdlg::{GetOpenFileNameW, OFN_EXPLORER, OFN_FILEMUSTEXIST, OFN_PATHMUSTEXIST, OPENFILENAMEW};
+
+    let filter: Vec<u16> = dialog_filter(name, extensions).encode_utf16().collect();
+    let initial_dir: Option<Vec<u16>> =
+        initial_dir.map(|dir| dir.as_os_str().encode_wide().chain(std::iter::once(0)).collect());
+    // Large enough for extended-length paths
+    let mut buffer = vec![0u16; 32_768];
+
+    // SAFETY: all pointers stay valid for the duration of the call
+    let mut dialog: OPENFILENAMEW = unsafe { std::mem::zeroed() };
+    dialog.lStructSize = std::mem::size_of::<OPENFILENAMEW>() as u32;
+    dialog.lpstrFilter = filter.as_ptr();
+    dialog.lpstrFile = buffer.as_mut_ptr();
+    dialog.nMaxFile = buffer.len() as u32;
+    dialog.lpstrInitialDir = initial_dir.as_ref().map_or(std::ptr::null(), |dir| dir.as_ptr());
+    dialog.Flags = OFN_EXPLORER | OFN_FILEMUSTEXIST | OFN_PATHMUSTEXIST;
+    if unsafe { GetOpenFileNameW(&mut dialog) } == 0 {
+        return None;
+    }
+
+    let length = buffer.iter().position(|&c| c == 0).unwrap_or(buffer.len());
+    Some(PathBuf::from(OsString::from_wide(&buffer[..length])))
+}
+
+/// Ask the user for a file with one of the given extensions
+///
+/// No native dialog exists on this platform, so this always returns `None`.
+#[cfg(not(windows))]
+pub fn pick_file(_name: &str, _extensions: &[&str], _initial_dir: Option<&Path>) -> Option<PathBuf> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dialog_filter() {
+        assert_eq!(
+            dialog_filter("Images", &["png", "gif"]),
+            "Images (*.png;*.gif)\0*.png;*.gif\0All Files (*.*)\0*.*\0\0"
+        );
+    }
+}
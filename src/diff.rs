@@ -0,0 +1,260 @@
+//! Screenshot comparison
+//!
+//! Two images are compared pixel by pixel. Pixels whose largest channel
+//! difference exceeds a threshold count as changed; they are painted into a
+//! heatmap overlay and grouped into bounding boxes of changed regions. The
+//! editor's Compare view and the `diff` command line mode both use
+//! [`compare`]. Change detection while capturing repeatedly uses the cheaper
+//! [`changed_beyond`], which only answers whether enough pixels changed.
+
+use egui::{Pos2, Rect, Vec2};
+use image::{Rgba, RgbaImage};
+
+/// Default per-channel difference treated as unchanged, absorbing compression noise
+pub const DEFAULT_THRESHOLD: u8 = 16;
+
+/// Side of the square cells changed pixels are grouped by when forming regions
+///
+/// Changes in touching cells merge into one region, so nearby edits within
+/// about a cell of each other are reported together.
+const REGION_CELL_SIZE: u32 = 8;
+
+/// Result of comparing two images
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiffResult {
+    /// Compared width: the larger of the two image widths
+    pub width: u32,
+    /// Compared height: the larger of the two image heights
+    pub height: u32,
+    /// Number of pixels whose difference exceeds the threshold
+    pub changed_pixels: u64,
+    /// Bounding boxes of changed regions in image pixels, top to bottom
+    pub regions: Vec<Rect>,
+    /// Transparent where unchanged, yellow to red as the difference grows
+    pub heatmap: RgbaImage,
+}
+
+impl DiffResult {
+    /// Check whether no pixel changed
+    pub fn is_identical(&self) -> bool {
+        self.changed_pixels == 0
+    }
+
+    /// Fraction of compared pixels that changed (0.0-1.0)
+    pub fn changed_ratio(&self) -> f64 {
+        let total = self.width as u64 * self.height as u64;
+        if total == 0 {
+            0.0
+        } else {
+            self.changed_pixels as f64 / total as f64
+        }
+    }
+
+    /// One-line summary, e.g. "1234 pixels changed (0.52%) in 3 regions"
+    pub fn summary(&self) -> String {
+        if self.is_identical() {
+            return "No differences".to_string();
+        }
+        format!(
+            "{} pixels changed ({:.2}%) in {} region{}",
+            self.changed_pixels,
+            self.changed_ratio() * 100.0,
+            self.regions.len(),
+            if self.regions.len() == 1 { "" } else { "s" }
+        )
+    }
+}
+
+/// Compare two images, treating channel differences up to `threshold` as unchanged
+///
+/// Images of different sizes are compared over the larger size on each
+/// axis, with pixels covered by only one image counting as fully changed.
+pub fn compare(a: &RgbaImage, b: &RgbaImage, threshold: u8) -> DiffResult {
+    let width = a.width().max(b.width());
+    let height = a.height().max(b.height());
+    let cells_x = width.div_ceil(REGION_CELL_SIZE);
+    let cells_y = height.div_ceil(REGION_CELL_SIZE);
+
+    let mut heatmap = RgbaImage::new(width, height);
+    // Bounds of the changed pixels within each cell
+    let mut cells: Vec<Option<Rect>> = vec![None; cells_x as usize * cells_y as usize];
+    let mut changed_pixels = 0;
+    for y in 0..height {
+        for x in 0..width {
+            let difference = pixel_difference(a.get_pixel_checked(x, y), b.get_pixel_checked(x, y));
+            if difference <= threshold {
+                continue;
+            }
+            changed_pixels += 1;
+            heatmap.put_pixel(x, y, heat_color(difference));
+
+            let pixel = Rect::from_min_size(Pos2::new(x as f32, y as f32), Vec2::splat(1.0));
+            let cell = &mut cells[((y / REGION_CELL_SIZE) * cells_x + x / REGION_CELL_SIZE) as usize];
+            *cell = Some(cell.map_or(pixel, |bounds| bounds.union(pixel)));
+        }
+    }
+
+    DiffResult {
+        width,
+        height,
+        changed_pixels,
+        regions: merge_cells(&cells, cells_x, cells_y),
+        heatmap,
+    }
+}
+
+/// Check whether more than `min_ratio` (0.0-1.0) of the pixels of two images changed
+///
+/// Made for polling the screen: identical rows are skipped with a plain
+/// byte comparison and counting stops as soon as the ratio is exceeded, so
+/// an unchanged frame costs little more than a memory compare. Images of
+/// different sizes always count as changed.
+pub fn changed_beyond(a: &RgbaImage, b: &RgbaImage, threshold: u8, min_ratio: f64) -> bool {
+    if a.dimensions() != b.dimensions() {
+        return true;
+    }
+    let row_bytes = a.width() as usize * 4;
+    if row_bytes == 0 {
+        return false;
+    }
+    let limit = (a.width() as f64 * a.height() as f64 * min_ratio.clamp(0.0, 1.0)) as u64;
+    let mut changed = 0;
+    for (row_a, row_b) in a.as_raw().chunks_exact(row_bytes).zip(b.as_raw().chunks_exact(row_bytes)) {
+        if row_a == row_b {
+            continue;
+        }
+        for (pixel_a, pixel_b) in row_a.chunks_exact(4).zip(row_b.chunks_exact(4)) {
+            let difference = pixel_a.iter().zip(pixel_b).map(|(&a, &b)| a.abs_diff(b)).max().unwrap_or(0);
+            if difference > threshold {
+                changed += 1;
+                if changed > limit {
+                    return true;
+                }
+            }
+        }
+    }
+    false
+}
+
+/// Largest channel difference of two pixels, 255 where only one image has the pixel
+fn pixel_difference(a: Option<&Rgba<u8>>, b: Option<&Rgba<u8>>) -> u8 {
+    match (a, b) {
+        (Some(a), Some(b)) => a.0.iter().zip(b.0).map(|(&a, b)| a.abs_diff(b)).max().unwrap_or(0),
+        (None, None) => 0,
+        _ => u8::MAX,
+    }
+}
+
+/// Heatmap color for a difference, from translucent yellow to opaque red
+fn heat_color(difference: u8) -> Rgba<u8> {
+    let t = difference as f32 / 255.0;
+    Rgba([255, (255.0 * (1.0 - t)).round() as u8, 0, (128.0 + 127.0 * t).round() as u8])
+}
+
+/// Join touching changed cells (including diagonally) into region bounding boxes
+fn merge_cells(cells: &[Option<Rect>], cells_x: u32, cells_y: u32) -> Vec<Rect> {
+    let mut visited = vec![false; cells.len()];
+    let mut regions = Vec::new();
+    for start in 0..cells.len() {
+        if visited[start] || cells[start].is_none() {
+            continue;
+        }
+        visited[start] = true;
+        let mut bounds = Rect::NOTHING;
+        let mut stack = vec![start];
+        while let Some(index) = stack.pop() {
+            bounds = bounds.union(cells[index].unwrap_or(Rect::NOTHING));
+            let (cx, cy) = ((index as u32 % cells_x) as i64, (index as u32 / cells_x) as i64);
+            for (nx, ny) in (-1..=1).flat_map(|dy| (-1..=1).map(move |dx| (cx + dx, cy + dy))) {
+                if nx < 0 || ny < 0 || nx >= cells_x as i64 || ny >= cells_y as i64 {
+                    continue;
+                }
+                let neighbor = (ny * cells_x as i64 + nx) as usize;
+                if !visited[neighbor] && cells[neighbor].is_some() {
+                    visited[neighbor] = true;
+                    stack.push(neighbor);
+                }
+            }
+        }
+        regions.push(bounds);
+    }
+    regions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn white(width: u32, height: u32) -> RgbaImage {
+        RgbaImage::from_pixel(width, height, Rgba([255, 255, 255, 255]))
+    }
+
+    fn paint(image: &mut RgbaImage, rect: (u32, u32, u32, u32), color: [u8; 4]) {
+        let (x0, y0, width, height) = rect;
+        for y in y0..y0 + height {
+            for x in x0..x0 + width {
+                image.put_pixel(x, y, Rgba(color));
+            }
+        }
+    }
+
+    #[test]
+    fn test_identical_images() {
+        let result = compare(&white(20, 10), &white(20, 10), DEFAULT_THRESHOLD);
+        assert!(result.is_identical());
+        assert!(result.regions.is_empty());
+        assert_eq!(result.summary(), "No differences");
+        assert!(result.heatmap.pixels().all(|pixel| pixel.0[3] == 0));
+    }
+
+    #[test]
+    fn test_changed_regions() {
+        let mut changed = white(100, 100);
+        paint(&mut changed, (10, 12, 5, 4), [0, 0, 0, 255]);
+        paint(&mut changed, (16, 16, 2, 2), [0, 0, 0, 255]);
+        paint(&mut changed, (70, 80, 10, 10), [255, 0, 0, 255]);
+        // Below the threshold
+        paint(&mut changed, (50, 5, 5, 5), [250, 250, 250, 255]);
+
+        let result = compare(&white(100, 100), &changed, DEFAULT_THRESHOLD);
+        assert_eq!(result.changed_pixels, 20 + 4 + 100);
+        assert_eq!(
+            result.regions,
+            vec![
+                Rect::from_min_max(Pos2::new(10.0, 12.0), Pos2::new(18.0, 18.0)),
+                Rect::from_min_max(Pos2::new(70.0, 80.0), Pos2::new(80.0, 90.0)),
+            ]
+        );
+        assert_eq!(result.heatmap.get_pixel(10, 12).0, [255, 0, 0, 255]);
+        assert_eq!(result.heatmap.get_pixel(50, 5).0[3], 0);
+        assert!(result.summary().ends_with("in 2 regions"));
+
+        // A zero threshold catches the faint change too
+        assert_eq!(compare(&white(100, 100), &changed, 0).regions.len(), 3);
+    }
+
+    #[test]
+    fn test_different_sizes() {
+        let result = compare(&white(10, 10), &white(12, 10), DEFAULT_THRESHOLD);
+        assert_eq!((result.width, result.height), (12, 10));
+        assert_eq!(result.changed_pixels, 20);
+        assert_eq!(result.regions, vec![Rect::from_min_max(Pos2::new(10.0, 0.0), Pos2::new(12.0, 10.0))]);
+        assert!((result.changed_ratio() - 20.0 / 120.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_changed_beyond() {
+        let mut changed = white(10, 10);
+        paint(&mut changed, (0, 0, 2, 2), [0, 0, 0, 255]);
+        // Below the threshold
+        paint(&mut changed, (5, 5, 5, 5), [250, 250, 250, 255]);
+
+        assert!(!changed_beyond(&white(10, 10), &white(10, 10), DEFAULT_THRESHOLD, 0.0));
+        // 4 of 100 pixels changed
+        assert!(changed_beyond(&white(10, 10), &changed, DEFAULT_THRESHOLD, 0.0));
+        assert!(changed_beyond(&white(10, 10), &changed, DEFAULT_THRESHOLD, 0.03));
+        assert!(!changed_beyond(&white(10, 10), &changed, DEFAULT_THRESHOLD, 0.04));
+        assert!(changed_beyond(&white(10, 10), &changed, 0, 0.04));
+        assert!(changed_beyond(&white(10, 10), &white(10, 11), DEFAULT_THRESHOLD, 0.5));
+    }
+}
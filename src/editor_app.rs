@@ -5,623 +5,12769 @@
 
 use eframe::egui;
 use egui::{Context, TextureHandle, Vec2, Pos2, Rect, Response, Sense};
-use image::DynamicImage;
-use crate::{AnnotationItem, Tool, AppResult};
+use image::{DynamicImage, GenericImageView, RgbaImage};
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use uuid::Uuid;
+use crate::{rotate_about, AnnotationItem, AnnotationType, BlendMode, Tool, AppError, AppResult, DoubleClickAction, Hotkey, HotkeyEvent, ImageFormat, PanScheme, ResultExt, ScreenInfo, ScrollAction};
+use crate::about;
+use crate::accessibility;
+use crate::adjustments::{Adjustment, AdjustmentJob, AdjustmentPreview, Adjustments};
+use crate::align::{AlignTargets, Guide, GuideAxis};
+use crate::animation::{self, AnimationFormat, AnimationOptions};
+use crate::automation::{self, AutomationCall, AutomationError, AutomationRequest, AutomationResponse, AutomationServer, CaptureTarget};
+use crate::autostart;
+use crate::capture::{CaptureService, CapturedFrame, PendingCapture};
+use crate::clipboard;
+use crate::color;
+use crate::control::{self, ControlServer, SharedTriggers, Trigger, TriggerState};
+use crate::credentials;
+use crate::cursors::{self, CanvasCursor, CanvasTarget, CursorState};
+use crate::decode::{self, DecodeLimits};
+use crate::dialogs;
+use crate::datetime::DateTime;
+use crate::diagnostics::{self, Diagnostics, FRAME_BUDGET};
+use crate::diff::{self, DiffResult};
+use crate::emoji;
+use crate::events::{AppController, AppEvent, EventSender};
+use crate::export::{self, ExportOptions, ResizeFilter, ResizeMode};
+use crate::export::secure::{self, SecureFormat};
+use crate::files::RetryPolicy;
+use crate::foreground::ForegroundWindow;
+use crate::grid::{self, GridOptions, MIN_GRID_LINE_SPACING, RULER_SIZE};
+use crate::hdr::{HdrOptions, ToneMapping};
+use crate::history::{self, CaptureHistory, HistoryEntry};
+use crate::hotkeys::{self, HotkeyAction, HotkeyListener};
+use crate::image_ops;
+use crate::image_store::ImageStore;
+use crate::import::{self, ImportResult};
+use crate::measure;
+use crate::memory::{self, MemoryOptions, SpilledImage};
+use crate::metadata::{self, ImageMetadata};
+use crate::monitor_cycle::{CycleTarget, MonitorCycle};
+use crate::naming::{self, CounterReset, CounterStore, FileNameTemplate};
+use crate::notifications::{self, CaptureAction, CaptureToast, Notifications, Severity, SystemToast};
+use crate::palette::{self, PaletteFormat};
+use crate::ocr;
+use crate::plugins::{self, ActionRegistry, ActionResult, PostCaptureAction};
+use crate::render::{
+    self, BannerPosition, DecorationBackground, DecorationSettings, DropShadow, MetadataBanner, Watermark,
+    WatermarkAnchor, WatermarkContent,
+};
+use crate::power::{self, LowResourceMode, PowerSource, ResourceProfile};
+use crate::presets::{self, AreaPlacement, CapturePreset, MonitorAnchor, PresetOutput, PresetTarget};
+use crate::privacy::{self, Finding};
+use crate::profiling;
+use crate::properties::{self, SelectionKind};
+use crate::project::{self, AnnotationDiff, ProjectFile, PROJECT_EXTENSION};
+use crate::regions::{self, Edge, OverlaySettings, RegionMemory, SelectionConstraint};
+use crate::remote::{self, RemoteLocation, SaveDestination, TransferEvent};
+use crate::render::FlattenCache;
+use crate::routing::{MonitorIdentity, MonitorRoute, PostSaveCommand};
+use crate::schedule::{self, IntervalRun, IntervalSettings, StopReason};
+use crate::services::LazyService;
+use crate::session::{self, RecoveredSession, SessionStore, SessionTab, TabSnapshot};
+use crate::settings::SettingsManager;
+use crate::share::{self, ShareService, ShareTarget};
+use crate::shortcuts::{KeyChord, ShortcutAction};
+use crate::style::AnnotationStyle;
+use crate::svg::{self, SvgBackground, SvgOptions};
+use crate::templates::{self, AnnotationTemplate, TemplateLibrary};
+use crate::text;
+use crate::tiles::{TextureFilter, TileRect, TiledTexture, TILE_SIZE, TILE_UPLOADS_PER_FRAME};
+use crate::toolbar::{self, ToolbarLayout};
+use crate::undo::UndoHistory;
+use crate::watch::{self, RegionWatch, WatchSettings};
+use crate::window_list::WindowSelector;
+use crate::writer::BackgroundWriter;
+use crossbeam_channel::Receiver;
+
+/// Minimum size (in image pixels) for a dragged rectangle to become an annotation
+const MIN_RECTANGLE_SIZE: f32 = 2.0;
+
+/// How often the power source is re-checked while low-resource mode is `Auto`
+const POWER_POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// egui's default animation time, restored when leaving low-resource mode
+const DEFAULT_ANIMATION_TIME: f32 = 1.0 / 12.0;
+
+/// How often finished background saves are collected while any are running
+const BACKGROUND_SAVE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Screen distance within which moved and drawn annotations snap into alignment
+const ALIGN_TOLERANCE: f32 = 6.0;
+
+/// Offset of each successive paste or duplicate from the copied annotations
+const PASTE_OFFSET: Vec2 = Vec2::new(10.0, 10.0);
+
+/// Distance in image pixels a nudge (an arrow key by default) moves the selected annotations
+const NUDGE_STEP: f32 = 1.0;
+
+/// Distance a far nudge (Shift+arrow by default) moves the selected annotations
+const NUDGE_STEP_LARGE: f32 = 10.0;
+
+/// Size in image pixels of a rectangle placed from the keyboard
+const PLACED_RECTANGLE_SIZE: Vec2 = Vec2::new(160.0, 90.0);
+
+/// Screen distance of the rotate handle above the selection box
+const ROTATE_HANDLE_DISTANCE: f32 = 24.0;
+
+/// Screen radius of the rotate handle
+const ROTATE_HANDLE_RADIUS: f32 = 5.0;
+
+/// Rotation step in degrees while Ctrl is held during a rotate drag
+const ROTATION_SNAP_STEP: f32 = 15.0;
+
+/// Screen pixels left around the image or selection when zooming to fit it
+const ZOOM_FIT_MARGIN: f32 = 20.0;
+
+/// Screen size of the square selection handles
+const SELECTION_HANDLE_SIZE: f32 = 6.0;
+
+/// Largest fraction of the screenshot's width or height an inserted image starts at
+const MAX_INSERTED_IMAGE_FRACTION: f32 = 0.5;
+
+/// Color of the temporary alignment guide lines
+const GUIDE_COLOR: egui::Color32 = egui::Color32::from_rgb(255, 0, 255);
+
+/// Color of the Eraser tool's region and of the annotations it will delete
+const ERASER_COLOR: egui::Color32 = egui::Color32::from_rgb(230, 60, 60);
+
+/// Screen radius of the Perspective tool's corner handles
+const PERSPECTIVE_HANDLE_RADIUS: f32 = 6.0;
+
+/// Screen size of the loupe shown while selecting a capture region
+const LOUPE_SIZE: f32 = 120.0;
+
+/// Screen distance of the loupe from the pointer
+const LOUPE_OFFSET: f32 = 24.0;
+
+/// How much the loupe enlarges the canvas
+const LOUPE_MAGNIFICATION: f32 = 4.0;
+
+/// Highlight colors for annotations added, removed, and changed between project revisions
+const DIFF_ADDED_COLOR: egui::Color32 = egui::Color32::from_rgb(46, 160, 67);
+const DIFF_REMOVED_COLOR: egui::Color32 = egui::Color32::from_rgb(218, 54, 51);
+const DIFF_CHANGED_COLOR: egui::Color32 = egui::Color32::from_rgb(230, 145, 20);
+
+/// Highlight color for sensitive content found before copying or uploading
+const PRIVACY_HIGHLIGHT_COLOR: egui::Color32 = egui::Color32::from_rgb(220, 40, 120);
+
+/// Symbols per row of the emoji picker
+const EMOJI_PICKER_COLUMNS: usize = 8;
+
+/// Id of the selected text editor, for inserting picked emoji at its cursor
+const SELECTED_TEXT_ID: &str = "selected_text";
+
+/// Describe an annotation for the project comparison list
+fn describe_annotation(annotation: &AnnotationItem) -> String {
+    let position = format!("({:.0}, {:.0})", annotation.position.x, annotation.position.y);
+    match &annotation.annotation_type {
+        AnnotationType::Rectangle { size, .. } => format!("Rectangle {:.0}x{:.0} at {}", size.x, size.y, position),
+        AnnotationType::Text { content, .. } => format!("Text \"{}\" at {}", content, position),
+        AnnotationType::Image { size, .. } => format!("Image {:.0}x{:.0} at {}", size.x, size.y, position),
+        AnnotationType::Dimension { .. } => format!("Dimension {} at {}", render::dimension_label(annotation), position),
+    }
+}
+
+/// Describe what a capture shows for the capture history
+fn capture_target(screen: &ScreenInfo, window_title: Option<&str>) -> String {
+    match window_title {
+        Some(title) => format!("Window: {}", title),
+        None => ImageMetadata::describe_monitor(screen),
+    }
+}
+
+/// Convert a desktop rectangle to pixels of a capture of a screen
+fn desktop_to_image(rect: Rect, screen: &ScreenInfo) -> Rect {
+    let scale = Vec2::new(screen.dpi_scale_x, screen.dpi_scale_y);
+    let min = (rect.min - screen.bounds.min) * scale;
+    Rect::from_min_size(min.to_pos2(), rect.size() * scale)
+}
+
+/// Convert pixels of a capture of a screen to a desktop rectangle
+fn image_to_desktop(rect: Rect, screen: &ScreenInfo) -> Rect {
+    let scale = Vec2::new(screen.dpi_scale_x, screen.dpi_scale_y);
+    Rect::from_min_size(screen.bounds.min + rect.min.to_vec2() / scale, rect.size() / scale)
+}
 
 /// Main editor application for screenshot editing
-pub struct EditorApp {
-    /// The source image being edited
-    source_image: Option<DynamicImage>,
-    /// Texture handle for displaying the image in egui
+/// Title of the main window, prefixed by the active image's name
+pub const APP_TITLE: &str = "Lightweight Screenshot App";
+
+/// Answer to a prompt about unsaved changes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SaveChoice {
+    Save,
+    Discard,
+    Cancel,
+}
+
+/// Choices in the Save As window
+struct SaveAsDraft {
+    file_name: String,
+    /// Folder of local saves
+    folder: String,
+    /// Remote destination, `None` for a local folder
+    destination: Option<Uuid>,
+}
+
+/// Captures picked in the Create Collage window
+#[derive(Default)]
+struct CollageSources {
+    /// Session ids of the picked tabs
+    tabs: HashSet<Uuid>,
+    /// Saved captures picked from the capture history
+    captures: HashSet<PathBuf>,
+}
+
+/// An upload to a remote destination in progress
+struct Transfer {
+    /// Name of the destination
+    destination: String,
+    file_name: String,
+    /// Bytes written so far, out of `total`
+    sent: u64,
+    total: u64,
+    /// History entry the remote location is recorded in
+    history_id: Option<Uuid>,
+    receiver: Receiver<TransferEvent>,
+}
+
+/// Output held back until the image is checked for sensitive content
+enum GuardedOutput {
+    CopyImage,
+    Actions(Vec<Arc<dyn PostCaptureAction>>),
+}
+
+impl GuardedOutput {
+    /// Title of the error shown when the output fails
+    fn failure_title(&self) -> &'static str {
+        match self {
+            GuardedOutput::CopyImage => "Copy Failed",
+            GuardedOutput::Actions(_) => "Action Failed",
+        }
+    }
+}
+
+/// Scan of the image for sensitive content before it is copied or uploaded
+struct PrivacyCheck {
+    /// Tab whose image is checked; the output is dropped if another tab is active
+    session_id: Uuid,
+    output: GuardedOutput,
+    /// Receives the findings, until the scan finishes
+    receiver: Option<Receiver<AppResult<Vec<Finding>>>>,
+    /// Sensitive content not yet blurred, highlighted on the canvas
+    findings: Vec<Finding>,
+}
+
+/// Comparison of a tab's image against a reference image, shown as an overlay
+struct ImageComparison {
+    /// Image compared against, kept to recompare when the threshold changes
+    reference: RgbaImage,
+    /// Reference file or tab name
+    reference_name: String,
+    threshold: u8,
+    result: DiffResult,
+    show_heatmap: bool,
+    /// Heatmap texture, uploaded on the next frame
+    heatmap_texture: Option<TextureHandle>,
+}
+
+/// Canvas preview of an annotation with a blend mode egui can't draw
+///
+/// The annotation and those beneath it are flattened onto the image pixels
+/// it covers, and the result is drawn in its place.
+struct BlendPreview {
+    /// Content revision the preview was rendered at
+    revision: u64,
+    /// Image-space area the texture covers
+    region: Rect,
+    texture: TextureHandle,
+}
+
+/// Region being selected on a fresh hotkey capture before it is opened
+struct RegionSelection {
+    /// Executable of the application focused when the hotkey was pressed
+    application: Option<String>,
+    /// Selected rectangle in image pixels
+    rect: Rect,
+    /// Drag in progress on the canvas
+    drag: Option<RegionDrag>,
+    /// Edge adjusted with the arrow keys, or `None` to move the whole selection
+    edge: Option<Edge>,
+}
+
+/// How a drag on the canvas changes the selected region
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RegionDrag {
+    /// Moving the selection, grabbed at this offset from its top-left corner
+    Move { grab: Vec2 },
+    /// Drawing a new selection from a corner, in image pixels, with the
+    /// selection's width to height ratio when the drag started
+    Draw { start: Pos2, aspect: f32 },
+}
+
+/// Toast of a capture a preset copied or saved without opening the editor
+struct SilentCapture {
+    toast: CaptureToast,
+    /// The capture, for opening it in the editor when it wasn't saved
+    frame: CapturedFrame,
+    history_id: Option<Uuid>,
+    /// Texture of the toast's thumbnail, once loaded
     texture: Option<TextureHandle>,
+}
+
+/// Upload command still running for a saved file
+struct PendingUpload {
+    /// File the command reads, which must not be deleted until it finishes
+    path: PathBuf,
+    /// History entry the link is recorded in
+    history_id: Option<Uuid>,
+    receiver: Receiver<Option<String>>,
+}
+
+/// Source image replaced by an undoable edit, shared with the undo history
+///
+/// Versions are compared by identity rather than by pixels, so comparing
+/// snapshots stays cheap.
+#[derive(Debug, Clone)]
+struct ImageVersion(Arc<DynamicImage>);
+
+impl PartialEq for ImageVersion {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// Edited state of a tab as the undo history records it
+#[derive(Debug, Clone, PartialEq, Default)]
+struct EditSnapshot {
+    /// Annotations without selection state
+    annotations: Vec<AnnotationItem>,
+    adjustments: Adjustments,
+    /// Source image, `None` while it's the image the history started with
+    image: Option<ImageVersion>,
+}
+
+/// An image open in an editor tab, with its own annotations, view, and save state
+struct Document {
+    /// The source image being edited, or its downsampled proxy while `full_resolution` is set
+    source_image: Option<DynamicImage>,
+    /// Compressed source image while the tab is not active, in place of `source_image`
+    suspended_image: Option<ImageStore>,
+    /// Full-resolution pixels on disk while the image is over the memory budget
+    full_resolution: Option<SpilledImage>,
+    /// Whether the image stays in memory over the budget: writing it to disk
+    /// failed, or the full resolution was loaded back for editing
+    keep_in_memory: bool,
+    /// Tiled texture for displaying the image in egui, uploaded over several frames
+    texture: Option<TiledTexture>,
     /// List of annotations on the image
     annotations: Vec<AnnotationItem>,
-    /// Currently selected editing tool
-    current_tool: Tool,
     /// Current zoom level for the image
     zoom_level: f64,
     /// Pan offset for the image
     pan_offset: Vec2,
-    /// Whether the application should close
-    should_close: bool,
-    /// Whether we're currently panning
-    is_panning: bool,
-    /// Last mouse position for panning
-    last_mouse_pos: Option<Pos2>,
+    /// Revision counter bumped whenever the image or annotations change
+    content_revision: u64,
+    /// Revision last saved to or opened from `file_path`
+    saved_revision: u64,
+    /// Undo and redo steps of the annotations and adjustments
+    undo: UndoHistory<EditSnapshot>,
+    /// Revision last committed to the undo history
+    undo_revision: u64,
+    /// Cached flattened composite of the image and annotations
+    flatten_cache: FlattenCache,
+    /// Adjustment stack applied to the source image
+    adjustments: Adjustments,
+    /// Full-resolution source image with adjustments applied
+    adjusted_image: Option<DynamicImage>,
+    /// Full-resolution adjustments being applied on the worker thread
+    adjustment_job: Option<AdjustmentJob>,
+    /// Debounced low-resolution preview state while sliders are dragged
+    adjustment_preview: AdjustmentPreview,
+    /// Texture showing the low-resolution adjustment preview
+    preview_texture: Option<TextureHandle>,
+    /// Textures of image annotations by annotation id, `None` if the image failed to decode
+    image_textures: HashMap<Uuid, Option<TextureHandle>>,
+    /// Canvas previews of blended annotations by annotation id
+    blend_previews: HashMap<Uuid, BlendPreview>,
+    /// Image-space start point of a rectangle being drawn
+    drawing_start: Option<Pos2>,
+    /// Image-space start point of a region being dragged out with the Eraser tool
+    erasing_start: Option<Pos2>,
+    /// Image-space start point of a region being dragged out with the Select tool
+    selecting_start: Option<Pos2>,
+    /// Region dragged out with the Select tool, in image pixels, kept until the next click
+    selection_region: Option<Rect>,
+    /// Source image once an undoable edit like cropping replaced it, `None`
+    /// while it's the image the undo history started with
+    image_version: Option<ImageVersion>,
+    /// Image the undo history started with, kept once an undoable edit replaced it
+    history_base: Option<ImageVersion>,
+    /// Start and end of the line being or last measured with the Measure tool
+    measurement: Option<[Pos2; 2]>,
+    /// Corners placed with the Perspective tool, `None` until one is moved from the image corners
+    perspective: Option<[Pos2; 4]>,
+    /// Perspective corner being dragged
+    perspective_handle: Option<usize>,
+    /// Annotation being moved with the Select tool
+    drag_target: Option<Uuid>,
+    /// Position of the moved annotation when the drag started
+    drag_origin: Pos2,
+    /// Pointer movement in image pixels since the drag started, before snapping
+    drag_moved: Vec2,
+    /// Annotation being rotated by its rotate handle
+    rotating: Option<Uuid>,
+    /// Annotation and corner index being resized by a selection handle
+    resizing: Option<(Uuid, usize)>,
+    /// Alignment guides shown while moving or drawing an annotation
+    guides: Vec<Guide>,
+    /// File the image was opened from or last saved to
+    file_path: Option<PathBuf>,
+    /// Background save in flight (path, revision being saved)
+    pending_save: Option<(PathBuf, u64)>,
+    /// Monitor the image was captured from, for save routing
+    source_screen: Option<ScreenInfo>,
+    /// When the image was captured, shown in the export metadata banner
+    captured_at: SystemTime,
+    /// Annotation changes highlighted on the canvas after comparing projects
+    project_diff: Option<AnnotationDiff>,
+    /// Pixel differences from a reference image highlighted on the canvas
+    image_comparison: Option<ImageComparison>,
+    /// Identifies the tab in the recovery session
+    session_id: Uuid,
+    /// Title of the captured window, embedded in exported files
+    window_title: Option<String>,
+    /// Executable name of the captured window's application, embedded in exported files
+    application: Option<String>,
+    /// Comment entered by the user, embedded in exported files
+    comment: String,
+    /// ICC profile of the source pixels, when they were captured in a non-sRGB color space
+    icc_profile: Option<Arc<Vec<u8>>>,
+    /// Capture history entry of the image, updated when it is saved
+    history_id: Option<Uuid>,
+    /// Region of the capture being selected, before the capture is cropped and opened
+    region_selection: Option<RegionSelection>,
+    /// Whether the image is a frozen frame a color is being picked from, closed once one is picked
+    color_picking: bool,
 }
 
-impl Default for EditorApp {
+impl Default for Document {
     fn default() -> Self {
         Self {
             source_image: None,
+            suspended_image: None,
+            full_resolution: None,
+            keep_in_memory: false,
             texture: None,
             annotations: Vec::new(),
-            current_tool: Tool::default(),
             zoom_level: 1.0,
             pan_offset: Vec2::ZERO,
-            should_close: false,
-            is_panning: false,
-            last_mouse_pos: None,
+            content_revision: 0,
+            saved_revision: 0,
+            undo: UndoHistory::default(),
+            undo_revision: 0,
+            flatten_cache: FlattenCache::new(),
+            adjustments: Adjustments::default(),
+            adjusted_image: None,
+            adjustment_job: None,
+            adjustment_preview: AdjustmentPreview::new(),
+            preview_texture: None,
+            image_textures: HashMap::new(),
+            blend_previews: HashMap::new(),
+            drawing_start: None,
+            erasing_start: None,
+            selecting_start: None,
+            selection_region: None,
+            image_version: None,
+            history_base: None,
+            measurement: None,
+            perspective: None,
+            perspective_handle: None,
+            drag_target: None,
+            drag_origin: Pos2::ZERO,
+            drag_moved: Vec2::ZERO,
+            rotating: None,
+            resizing: None,
+            guides: Vec::new(),
+            file_path: None,
+            pending_save: None,
+            source_screen: None,
+            captured_at: SystemTime::now(),
+            project_diff: None,
+            image_comparison: None,
+            session_id: Uuid::new_v4(),
+            window_title: None,
+            application: None,
+            comment: String::new(),
+            icc_profile: None,
+            history_id: None,
+            region_selection: None,
+            color_picking: false,
         }
     }
 }
 
-impl EditorApp {
-    /// Create a new editor application
-    pub fn new() -> Self {
-        Self::default()
+impl Document {
+    /// Check whether the tab has no image
+    fn is_empty(&self) -> bool {
+        self.source_image.is_none() && self.suspended_image.is_none()
     }
 
-    /// Load an image into the editor
-    pub fn load_image(&mut self, image: DynamicImage) -> AppResult<()> {
-        self.source_image = Some(image);
-        // Reset view state when loading new image
-        self.zoom_level = 1.0;
-        self.pan_offset = Vec2::ZERO;
-        self.texture = None; // Force texture recreation
-        Ok(())
+    /// Check whether the image or annotations changed since the last save
+    fn is_dirty(&self) -> bool {
+        !self.is_empty() && self.content_revision != self.saved_revision
     }
 
-    /// Load a test image for demonstration purposes
-    pub fn load_test_image(&mut self) -> AppResult<()> {
-        // Create a test image with a gradient pattern
-        let width = 400;
-        let height = 300;
-        let mut img_buffer = image::ImageBuffer::new(width, height);
-        
-        for (x, y, pixel) in img_buffer.enumerate_pixels_mut() {
-            let r = (x as f32 / width as f32 * 255.0) as u8;
-            let g = (y as f32 / height as f32 * 255.0) as u8;
-            let b = ((x + y) as f32 / (width + height) as f32 * 255.0) as u8;
-            *pixel = image::Rgb([r, g, b]);
-        }
-        
-        let test_image = DynamicImage::ImageRgb8(img_buffer);
-        self.load_image(test_image)
+    /// Record that the current revision is saved
+    fn mark_saved(&mut self) {
+        self.saved_revision = self.content_revision;
     }
 
-    /// Get the current tool
-    pub fn current_tool(&self) -> &Tool {
-        &self.current_tool
+    /// Get the annotations and adjustments as the undo history records them
+    fn undo_snapshot(&self) -> EditSnapshot {
+        EditSnapshot {
+            annotations: self
+                .annotations
+                .iter()
+                .map(|annotation| AnnotationItem { is_selected: false, ..annotation.clone() })
+                .collect(),
+            adjustments: self.adjustments.clone(),
+            image: self.image_version.clone(),
+        }
     }
 
-    /// Set the current tool
-    pub fn set_tool(&mut self, tool: Tool) {
-        self.current_tool = tool;
+    /// Start the undo history over at the current annotations, adjustments, and image
+    fn reset_undo(&mut self) {
+        self.image_version = None;
+        self.history_base = None;
+        self.undo.reset(self.undo_snapshot());
+        self.undo_revision = self.content_revision;
     }
 
-    /// Check if the application should close
-    pub fn should_close(&self) -> bool {
-        self.should_close
+    /// Approximate bytes held by the document (images, caches, textures)
+    fn memory_usage(&self) -> usize {
+        let image_bytes = |image: &Option<DynamicImage>| image.as_ref().map_or(0, |image| image.as_bytes().len());
+        let version_bytes =
+            |version: &Option<ImageVersion>| version.as_ref().map_or(0, |version| version.0.as_bytes().len());
+        let preview_bytes = self
+            .preview_texture
+            .as_ref()
+            .map_or(0, |texture| texture.size()[0] * texture.size()[1] * 4);
+        let tiled_bytes = self.texture.as_ref().map_or(0, TiledTexture::memory_usage);
+        let annotation_texture_bytes: usize = self
+            .image_textures
+            .values()
+            .flatten()
+            .chain(self.blend_previews.values().map(|preview| &preview.texture))
+            .map(|texture| texture.size()[0] * texture.size()[1] * 4)
+            .sum();
+
+        image_bytes(&self.source_image)
+            + self.suspended_image.as_ref().map_or(0, ImageStore::memory_usage)
+            + image_bytes(&self.adjusted_image)
+            + self.flatten_cache.memory_usage()
+            + tiled_bytes
+            + preview_bytes
+            + annotation_texture_bytes
+            + version_bytes(&self.image_version)
+            + version_bytes(&self.history_base)
     }
 
-    /// Request the application to close
-    pub fn request_close(&mut self) {
-        self.should_close = true;
+    /// Show the full-resolution adjustments once the worker thread finished them
+    fn poll_adjustments(&mut self) {
+        if let Some(result) = self.adjustment_job.as_ref().and_then(AdjustmentJob::try_finish) {
+            self.adjustment_job = None;
+            self.finish_adjustments(result);
+        }
     }
 
-    /// Create texture from image if needed
-    fn ensure_texture(&mut self, ctx: &Context) {
-        if self.texture.is_none() && self.source_image.is_some() {
-            if let Some(ref image) = self.source_image {
-                let rgba_image = image.to_rgba8();
-                let size = [rgba_image.width() as usize, rgba_image.height() as usize];
-                let pixels = rgba_image.as_flat_samples();
-                
-                let color_image = egui::ColorImage::from_rgba_unmultiplied(size, pixels.as_slice());
-                self.texture = Some(ctx.load_texture("screenshot", color_image, Default::default()));
-            }
+    /// Wait for the full-resolution adjustments being applied, if any
+    fn wait_for_adjustments(&mut self) {
+        if let Some(job) = self.adjustment_job.take() {
+            self.finish_adjustments(job.wait());
         }
     }
 
-    /// Draw the main menu bar
-    fn draw_menu_bar(&mut self, ctx: &Context) {
-        egui::TopBottomPanel::top("menu_bar").show(ctx, |ui| {
-            egui::menu::bar(ui, |ui| {
-                ui.menu_button("File", |ui| {
-                    if ui.button("New Screenshot").clicked() {
-                        // TODO: Implement new screenshot
-                        ui.close_menu();
-                    }
-                    if ui.button("Open").clicked() {
-                        // TODO: Implement open file
-                        ui.close_menu();
-                    }
-                    ui.separator();
-                    if ui.button("Save").clicked() {
-                        // TODO: Implement save
-                        ui.close_menu();
-                    }
-                    if ui.button("Save As").clicked() {
-                        // TODO: Implement save as
-                        ui.close_menu();
-                    }
-                    ui.separator();
-                    if ui.button("Exit").clicked() {
-                        self.request_close();
-                        ui.close_menu();
-                    }
-                });
+    /// Show changed pixels of the displayed image, uploading only the tiles touching `dirty`
+    ///
+    /// The old pixels stay on screen until the new ones are uploaded. The
+    /// texture is created anew if the image size changed.
+    fn refresh_texture(&mut self, dirty: Option<TileRect>) {
+        let image = self.adjusted_image.as_ref().or(self.source_image.as_ref());
+        let updated = match (self.texture.as_mut(), image) {
+            (Some(texture), Some(image)) => texture.update(image.to_rgba8(), dirty),
+            _ => false,
+        };
+        if !updated {
+            self.texture = None;
+        }
+    }
 
-                ui.menu_button("Edit", |ui| {
-                    if ui.button("Undo").clicked() {
-                        // TODO: Implement undo
-                        ui.close_menu();
-                    }
-                    if ui.button("Redo").clicked() {
-                        // TODO: Implement redo
-                        ui.close_menu();
-                    }
-                    ui.separator();
-                    if ui.button("Copy to Clipboard").clicked() {
-                        // TODO: Implement copy to clipboard
-                        ui.close_menu();
-                    }
-                });
+    /// Replace the adjusted image with the result of an adjustment job
+    ///
+    /// The adjustments were recorded as an edit when the job started, so a
+    /// tab saved in the meantime stays saved.
+    fn finish_adjustments(&mut self, result: AppResult<DynamicImage>) {
+        match result {
+            Ok(image) => self.adjusted_image = Some(image),
+            Err(e) => log::warn!("Failed to apply adjustments: {}", e),
+        }
+        self.adjustment_preview.finish();
+        self.preview_texture = None;
+        self.refresh_texture(None);
+        let saved = self.saved_revision == self.content_revision;
+        let committed = self.undo_revision == self.content_revision;
+        self.content_revision = self.content_revision.wrapping_add(1);
+        if saved {
+            self.saved_revision = self.content_revision;
+        }
+        if committed {
+            self.undo_revision = self.content_revision;
+        }
+    }
 
-                ui.menu_button("Help", |ui| {
-                    if ui.button("About").clicked() {
-                        // TODO: Implement about dialog
-                        ui.close_menu();
-                    }
-                });
-            });
-        });
+    /// Get the image with all annotations applied, cached until the content changes
+    ///
+    /// An image over the memory budget is flattened from its full-resolution
+    /// pixels on disk each time instead, so they are not kept in memory, and
+    /// so is the compressed image of a tab that is not active.
+    fn composite(&mut self) -> Option<Arc<RgbaImage>> {
+        self.wait_for_adjustments();
+        if self.full_resolution.is_some() || self.suspended_image.is_some() {
+            return match self.full_display_image() {
+                Ok(image) => image.map(|image| Arc::new(render::flatten(&image, &self.annotations))),
+                Err(e) => {
+                    log::warn!("Failed to read the full-resolution image: {}", e);
+                    None
+                }
+            };
+        }
+        let image = self.adjusted_image.as_ref().or(self.source_image.as_ref())?;
+        Some(self.flatten_cache.get_or_flatten(self.content_revision, image, &self.annotations))
     }
 
-    /// Draw the tool panel
-    fn draw_tool_panel(&mut self, ctx: &Context) {
-        egui::SidePanel::left("tool_panel").show(ctx, |ui| {
-            ui.heading("Tools");
-            ui.separator();
+    /// Get the size of the image in pixels at full resolution
+    fn image_dimensions(&self) -> Option<(u32, u32)> {
+        match &self.full_resolution {
+            Some(full) => Some(full.dimensions()),
+            None => match &self.suspended_image {
+                Some(suspended) => Some(suspended.dimensions()),
+                None => self.source_image.as_ref().map(|image| (image.width(), image.height())),
+            },
+        }
+    }
 
-            // Tool selection buttons
-            if ui.selectable_label(matches!(self.current_tool, Tool::Select), "Select").clicked() {
-                self.current_tool = Tool::Select;
-            }
-            if ui.selectable_label(matches!(self.current_tool, Tool::Rectangle), "Rectangle").clicked() {
-                self.current_tool = Tool::Rectangle;
-            }
-            if ui.selectable_label(matches!(self.current_tool, Tool::Text), "Text").clicked() {
-                self.current_tool = Tool::Text;
-            }
+    /// Get proxy pixels per image pixel, 1.0 unless a downsampled proxy is shown
+    fn proxy_scale(&self) -> f32 {
+        match (&self.full_resolution, &self.source_image) {
+            (Some(full), Some(proxy)) => proxy.width() as f32 / full.dimensions().0.max(1) as f32,
+            _ => 1.0,
+        }
+    }
 
-            ui.separator();
+    /// Get the source image at full resolution, read from disk while a proxy is shown
+    fn full_source_image(&self) -> AppResult<Option<Cow<'_, DynamicImage>>> {
+        match (&self.full_resolution, &self.suspended_image) {
+            (Some(full), _) => full.load().map(|image| Some(Cow::Owned(image))),
+            (None, Some(suspended)) => suspended.to_image().map(|image| Some(Cow::Owned(image))),
+            (None, None) => Ok(self.source_image.as_ref().map(Cow::Borrowed)),
+        }
+    }
 
-            // Zoom controls
-            ui.heading("View");
-            ui.horizontal(|ui| {
-                if ui.button("Zoom In").clicked() {
-                    self.zoom_level = (self.zoom_level * 1.2).min(10.0);
-                }
-                if ui.button("Zoom Out").clicked() {
-                    self.zoom_level = (self.zoom_level / 1.2).max(0.1);
-                }
-            });
-            
-            // Zoom slider
-            ui.add(egui::Slider::new(&mut self.zoom_level, 0.1..=10.0)
-                .text("Zoom")
-                .suffix("%")
-                .custom_formatter(|n, _| format!("{:.0}", n * 100.0))
-                .custom_parser(|s| s.parse::<f64>().map(|n| n / 100.0).ok()));
-            
-            if ui.button("Actual Size").clicked() {
-                self.zoom_level = 1.0;
-            }
-            if ui.button("Fit to Screen").clicked() {
-                if let Some(ref texture) = self.texture {
-                    // Calculate zoom to fit the image in the available space
-                    let image_size = texture.size_vec2();
-                    let available_size = Vec2::new(800.0, 600.0); // Approximate canvas size
-                    let zoom_x = available_size.x as f64 / image_size.x as f64;
-                    let zoom_y = available_size.y as f64 / image_size.y as f64;
-                    self.zoom_level = zoom_x.min(zoom_y).min(1.0); // Don't zoom in beyond 100%
-                    self.pan_offset = Vec2::ZERO; // Center the image
-                }
-            }
-            if ui.button("Reset View").clicked() {
-                self.zoom_level = 1.0;
-                self.pan_offset = Vec2::ZERO;
-            }
-            
-            ui.separator();
-            
-            // Test image button
-            if ui.button("Load Test Image").clicked() {
-                if let Err(e) = self.load_test_image() {
-                    log::error!("Failed to load test image: {}", e);
+    /// Get the adjusted image at full resolution, read from disk and adjusted while a proxy is shown
+    ///
+    /// Call [`Document::wait_for_adjustments`] first so the adjusted image is current.
+    fn full_display_image(&self) -> AppResult<Option<Cow<'_, DynamicImage>>> {
+        match &self.full_resolution {
+            Some(full) if !self.adjustments.is_identity() => Ok(Some(Cow::Owned(self.adjustments.apply(&full.load()?)))),
+            Some(full) => full.load().map(|image| Some(Cow::Owned(image))),
+            None => match &self.suspended_image {
+                Some(suspended) => {
+                    let image = suspended.to_image()?;
+                    let image = if self.adjustments.is_identity() { image } else { self.adjustments.apply(&image) };
+                    Ok(Some(Cow::Owned(image)))
                 }
-            }
-            
-            ui.separator();
-            ui.label(format!("Zoom: {:.0}%", self.zoom_level * 100.0));
-            if self.pan_offset != Vec2::ZERO {
-                ui.label(format!("Pan: ({:.0}, {:.0})", self.pan_offset.x, self.pan_offset.y));
-            }
-        });
+                None => Ok(self.adjusted_image.as_ref().or(self.source_image.as_ref()).map(Cow::Borrowed)),
+            },
+        }
     }
 
-    /// Draw the main canvas area
-    fn draw_canvas(&mut self, ctx: &Context) {
-        egui::CentralPanel::default().show(ctx, |ui| {
-            // Ensure texture is created
-            self.ensure_texture(ctx);
+    /// Move the full-resolution image to a memory-mapped file and show a downsampled proxy
+    ///
+    /// Returns the bytes freed: none if the image is already a proxy or not
+    /// larger than `max_side`.
+    fn spill_to_disk(&mut self, max_side: u32, directory: &Path) -> AppResult<usize> {
+        if self.full_resolution.is_some() || self.suspended_image.is_some() || self.keep_in_memory {
+            return Ok(0);
+        }
+        let Some(image) = self.source_image.as_ref() else {
+            return Ok(0);
+        };
+        let Some(proxy) = memory::proxy(image, max_side) else {
+            return Ok(0);
+        };
+        let before = self.memory_usage();
+        let full = SpilledImage::spill(image, directory).inspect_err(|_| self.keep_in_memory = true)?;
+        self.full_resolution = Some(full);
+        self.set_source_pixels(proxy);
+        Ok(before.saturating_sub(self.memory_usage()))
+    }
 
-            // Clone the texture handle to avoid borrowing issues
-            if let Some(texture) = self.texture.clone() {
-                self.draw_image_with_controls(ui, &texture);
-            } else {
-                // Show placeholder when no image is loaded
-                ui.centered_and_justified(|ui| {
-                    ui.vertical_centered(|ui| {
-                        ui.label("Take a screenshot or open an image file");
-                        ui.separator();
-                        ui.label("Or click 'Load Test Image' button in the left panel");
-                    });
-                });
-            }
-        });
+    /// Read the full-resolution image back into memory in place of the proxy
+    ///
+    /// Needed before editing the pixels themselves, e.g. cropping or blurring.
+    /// The image then stays in memory, so edits don't write it out again.
+    fn restore_full_resolution(&mut self) -> AppResult<()> {
+        let Some(full) = self.full_resolution.as_ref() else {
+            return Ok(());
+        };
+        let image = full.load()?;
+        self.full_resolution = None;
+        self.keep_in_memory = true;
+        self.set_source_pixels(image);
+        Ok(())
     }
 
-    /// Draw the image with zoom and pan controls
-    fn draw_image_with_controls(&mut self, ui: &mut egui::Ui, texture: &TextureHandle) {
-        let available_rect = ui.available_rect_before_wrap();
-        let response = ui.allocate_rect(available_rect, Sense::click_and_drag());
+    /// Swap the source image for the same image at another resolution, adjusting it again
+    fn set_source_pixels(&mut self, image: DynamicImage) {
+        self.source_image = Some(image);
+        self.adjusted_image = None;
+        self.adjustment_job = None;
+        self.adjustment_preview.reset();
+        self.preview_texture = None;
+        self.texture = None;
+        self.blend_previews.clear();
+        self.flatten_cache = FlattenCache::new();
+        if let Some(image) = self.source_image.as_ref().filter(|_| !self.adjustments.is_identity()) {
+            self.adjustment_job = Some(AdjustmentJob::spawn(image.clone(), self.adjustments.clone()));
+        }
+    }
 
-        // Handle mouse interactions
-        self.handle_mouse_interactions(&response, available_rect);
+    /// Compress the pixels of a tab that is no longer active, dropping its textures and caches
+    ///
+    /// The image stays decoded if it can't be compressed.
+    fn suspend(&mut self) {
+        let Some(image) = self.source_image.take() else {
+            return;
+        };
+        let mut store = ImageStore::new(image);
+        if let Err(e) = store.compress() {
+            log::warn!("Failed to compress the image of {}: {}", self.title(), e);
+        }
+        match store {
+            ImageStore::Decoded(image) => self.source_image = Some(image),
+            encoded => self.suspended_image = Some(encoded),
+        }
+        self.adjusted_image = None;
+        self.adjustment_job = None;
+        self.adjustment_preview.reset();
+        self.preview_texture = None;
+        self.texture = None;
+        self.image_textures.clear();
+        self.blend_previews.clear();
+        self.flatten_cache = FlattenCache::new();
+    }
 
-        // Calculate image display parameters
-        let original_size = texture.size_vec2();
-        let display_size = original_size * self.zoom_level as f32;
-        
-        // Calculate image position with pan offset
-        let center_offset = (available_rect.size() - display_size) * 0.5;
-        let image_pos = available_rect.min + center_offset + self.pan_offset;
-        let image_rect = Rect::from_min_size(image_pos, display_size);
+    /// Decode the pixels of a tab that becomes active again, adjusting them anew
+    fn resume(&mut self) -> AppResult<()> {
+        let Some(suspended) = self.suspended_image.take() else {
+            return Ok(());
+        };
+        self.set_source_pixels(suspended.into_image()?);
+        Ok(())
+    }
 
-        // Clip the drawing to the available area
-        ui.allocate_ui_at_rect(available_rect, |ui| {
-            // Set clipping rectangle to prevent drawing outside the canvas area
-            ui.set_clip_rect(available_rect);
-            
-            // Draw background
-            ui.painter().rect_filled(
-                available_rect,
-                0.0,
-                ui.style().visuals.extreme_bg_color,
-            );
+    /// Tab title: the file name, or "Untitled" for unsaved images
+    fn title(&self) -> String {
+        self.file_path
+            .as_deref()
+            .and_then(Path::file_name)
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "Untitled".to_string())
+    }
+}
 
-            // Calculate the visible portion of the image that intersects with available area
-            let visible_image_rect = image_rect.intersect(available_rect);
-            
-            // Draw the image only if it's visible
-            if visible_image_rect.width() > 0.0 && visible_image_rect.height() > 0.0 {
-                // Calculate UV coordinates for the visible portion
-                let uv_rect = if image_rect.width() > 0.0 && image_rect.height() > 0.0 {
-                    let left = ((visible_image_rect.min.x - image_rect.min.x) / image_rect.width()).max(0.0);
-                    let top = ((visible_image_rect.min.y - image_rect.min.y) / image_rect.height()).max(0.0);
-                    let right = ((visible_image_rect.max.x - image_rect.min.x) / image_rect.width()).min(1.0);
-                    let bottom = ((visible_image_rect.max.y - image_rect.min.y) / image_rect.height()).min(1.0);
-                    
-                    Rect::from_min_max(
-                        Pos2::new(left, top),
-                        Pos2::new(right, bottom)
-                    )
-                } else {
-                    Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0))
+pub struct EditorApp {
+    /// Image in the active tab
+    doc: Document,
+    /// Documents of all tabs; the active tab's slot holds a placeholder while its document is in `doc`
+    tabs: Vec<Document>,
+    /// Index of the active tab
+    active_tab: usize,
+    /// Dirty tab waiting for the user to save or discard it before closing
+    closing_tab: Option<usize>,
+    /// Whether exiting waits for the user to save or discard unsaved changes
+    show_exit_prompt: bool,
+    /// Window title sent to the window on the last frame
+    applied_title: String,
+    /// Currently selected editing tool
+    current_tool: Tool,
+    /// Whether the application should close
+    should_close: bool,
+    /// Whether we're currently panning
+    is_panning: bool,
+    /// Last mouse position for panning
+    last_mouse_pos: Option<Pos2>,
+    /// Application settings (styles, formats, etc.)
+    settings: SettingsManager,
+    /// Whether settings changed and should be persisted once interaction ends
+    settings_dirty: bool,
+    /// Style copied with "Copy Style", ready to paste onto other annotations
+    copied_style: Option<AnnotationStyle>,
+    /// Annotations copied with Copy, serialized as JSON and shared by all tabs
+    annotation_clipboard: Option<String>,
+    /// Pastes since the last copy, so each paste lands further from the original
+    paste_count: u32,
+    /// Name typed into the "Save as Preset" field
+    preset_name_input: String,
+    /// Whether the keyboard shortcut settings window is open
+    show_shortcut_settings: bool,
+    /// Action waiting for a new key chord in the shortcut settings window
+    rebinding_action: Option<ShortcutAction>,
+    /// Last shortcut rebinding error (e.g. a conflict) shown to the user
+    shortcut_error: Option<String>,
+    /// Screen capture service, created on first use or in the background
+    capture: LazyService<AppResult<CaptureService>>,
+    /// Last detected power source
+    power_source: PowerSource,
+    /// When the power source was last checked
+    power_checked_at: Option<Instant>,
+    /// Profile applied to the UI on the last frame, if any
+    applied_profile: Option<ResourceProfile>,
+    /// Whether the high-contrast theme was applied on the last frame, if any theme was
+    applied_high_contrast: Option<bool>,
+    /// Whether the path entry window for opening a file is shown (no native dialog)
+    show_open_dialog: bool,
+    /// Path typed into the open window
+    open_path_input: String,
+    /// Whether the export options window is open
+    show_export_options: bool,
+    /// Name typed into the decoration "Save as Preset" field
+    decoration_name_input: String,
+    /// Frame time, texture upload, and memory statistics
+    diagnostics: Diagnostics,
+    /// Whether the performance diagnostics overlay is shown
+    show_diagnostics: bool,
+    /// Whether the About window is open
+    show_about: bool,
+    /// Whether the monitor routing window is open
+    show_monitor_routing: bool,
+    /// Screen capture running on a worker thread
+    pending_capture: Option<PendingCapture>,
+    /// Preset whose output action handles the pending capture, if it was started by one
+    pending_preset: Option<CapturePreset>,
+    /// Error shown in a dialog until dismissed (title, message)
+    error_dialog: Option<(String, String)>,
+    /// Writer for saves onto network and cloud-synced folders
+    writer: BackgroundWriter,
+    /// Folders synchronized by a cloud client, whose saves also go through the writer
+    cloud_sync_roots: Vec<PathBuf>,
+    /// Whether the project comparison window is open
+    show_compare_projects: bool,
+    /// Project paths typed into the comparison window (old, new)
+    compare_inputs: (String, String),
+    /// Capture triggers enabled from the menu or by control commands
+    triggers: SharedTriggers,
+    /// Event bus from background subsystems, filtered by the enabled triggers
+    controller: AppController,
+    /// Listener for control commands from scripts
+    control_server: Option<ControlServer>,
+    /// Global capture hotkeys, once registered
+    hotkeys: Option<HotkeyListener>,
+    /// Listener for automation requests from external tools
+    automation_server: Option<AutomationServer>,
+    /// Automation capture requests waiting for the pending capture
+    capture_calls: Vec<AutomationCall>,
+    /// File the most recently saved capture was written to
+    last_capture_path: Option<PathBuf>,
+    /// Whether the window is hidden until there is an image to edit
+    hidden_until_capture: bool,
+    /// File name template being edited, while the file naming window is open
+    file_naming_draft: Option<FileNameTemplate>,
+    /// Capture presets being edited and the selected one, while the presets window is open
+    presets_draft: Option<(Vec<CapturePreset>, usize)>,
+    /// Share targets being edited, the selected one, and the secret being typed, while the share targets window is open
+    share_targets_draft: Option<(Vec<ShareTarget>, usize, String)>,
+    /// Save As choices, while the Save As window is open
+    save_as: Option<SaveAsDraft>,
+    /// Remote destinations being edited, the selected one, and the password being typed, while the destinations window is open
+    destinations_draft: Option<(Vec<SaveDestination>, usize, String)>,
+    /// Uploads to remote destinations still running
+    transfers: Vec<Transfer>,
+    /// Password and its confirmation, while the encrypted export window is open
+    encrypted_export: Option<(String, String)>,
+    /// Container path and password, while the window opening an encrypted capture is open
+    encrypted_open: Option<(String, String)>,
+    /// Sensitive-content scan holding back a copy or upload
+    privacy_check: Option<PrivacyCheck>,
+    /// Whether the check is enabled and the terms, one per line, while the privacy settings are edited
+    privacy_draft: Option<(bool, String)>,
+    /// Interval capture in progress
+    interval_run: Option<IntervalRun>,
+    /// Capture of the next interval frame, while it runs
+    interval_capture: Option<PendingCapture>,
+    /// Interval settings and the folder being typed, while the interval capture window is open
+    interval_draft: Option<(IntervalSettings, String)>,
+    /// Region being watched for changes
+    region_watch: Option<RegionWatch>,
+    /// Check of the watched region, while it runs
+    watch_capture: Option<PendingCapture>,
+    /// Watch settings and the folder being typed, while the region watch window is open
+    watch_draft: Option<(WatchSettings, String)>,
+    /// Emoji or symbol the sticker tool stamps
+    sticker_symbol: String,
+    /// Category shown in the emoji picker
+    emoji_category: usize,
+    /// Whether measurements are kept as dimension annotations when the Measure tool is released
+    keep_measurements: bool,
+    /// Installed font faces registered with egui for drawing text annotations, `None` if a face failed to load
+    text_faces: HashMap<String, Option<egui::FontData>>,
+    /// Whether the fonts with the emoji fallback chain were registered with egui
+    text_fonts_registered: bool,
+    /// Canvas area in the last frame, for fitting the image into it
+    canvas_rect: Option<Rect>,
+    /// Toasts on screen and the history shown in the error log
+    notifications: Notifications,
+    /// Whether the error log window is open
+    show_error_log: bool,
+    /// Image comment being edited, while the comment window is open
+    comment_draft: Option<String>,
+    /// Recovery copy of unsaved tabs, once session recovery is enabled
+    session: Option<SessionStore>,
+    /// When unsaved tabs were last checked for the recovery session
+    session_saved_at: Instant,
+    /// Tabs and revisions in the last recovery session written, `None` before the first write
+    session_signature: Option<Vec<(Uuid, u64)>>,
+    /// Session left by a run that didn't exit cleanly, waiting for the user to restore or discard it
+    recovered_session: Option<RecoveredSession>,
+    /// Log of captures, once capture history is enabled
+    history: Option<CaptureHistory>,
+    /// Whether the capture history window is open
+    show_history: bool,
+    /// Search text in the capture history window
+    history_query: String,
+    /// Textures of recent capture thumbnails by history entry, `None` if there is no thumbnail
+    recent_thumbnails: HashMap<Uuid, Option<TextureHandle>>,
+    /// Saved annotation templates, once templates are enabled
+    templates: Option<TemplateLibrary>,
+    /// `{counter}` numbers of file name templates, kept in a file once enabled
+    file_counters: CounterStore,
+    /// Name being entered for a new template, while the Save as Template window is open
+    template_name_draft: Option<String>,
+    /// Captures picked for a collage, while the Create Collage window is open
+    collage_sources: Option<CollageSources>,
+    /// Whether the adjustments window is open
+    show_adjustments: bool,
+    /// Upload commands still running
+    pending_uploads: Vec<PendingUpload>,
+    /// Post-capture actions declared in the plugins file
+    plugins: ActionRegistry,
+    /// Action chains still running, with the history entry their last link is recorded in
+    pending_actions: Vec<(Option<Uuid>, Receiver<Vec<ActionResult>>)>,
+    /// Set while a hotkey capture whose region will be selected is in progress,
+    /// with the window that was focused
+    pending_region_selection: Option<ForegroundWindow>,
+    /// Monitor chosen by pressing the screen hotkey repeatedly, until it is captured
+    monitor_cycle: MonitorCycle,
+    /// Toast of the last capture taken without opening the editor
+    silent_capture: Option<SilentCapture>,
+    /// Set while a capture to pick a color from is in progress
+    pending_color_pick: bool,
+    /// Aspect ratio or size the selected capture region is locked to
+    region_constraint: SelectionConstraint,
+}
+
+impl Default for EditorApp {
+    fn default() -> Self {
+        let triggers = SharedTriggers::default();
+        Self {
+            doc: Document::default(),
+            tabs: vec![Document::default()],
+            active_tab: 0,
+            closing_tab: None,
+            show_exit_prompt: false,
+            applied_title: APP_TITLE.to_string(),
+            current_tool: Tool::default(),
+            should_close: false,
+            is_panning: false,
+            last_mouse_pos: None,
+            settings: SettingsManager::default(),
+            settings_dirty: false,
+            copied_style: None,
+            annotation_clipboard: None,
+            paste_count: 0,
+            preset_name_input: String::new(),
+            show_shortcut_settings: false,
+            rebinding_action: None,
+            shortcut_error: None,
+            capture: LazyService::new("capture service", CaptureService::new),
+            power_source: PowerSource::Unknown,
+            power_checked_at: None,
+            applied_profile: None,
+            applied_high_contrast: None,
+            show_open_dialog: false,
+            open_path_input: String::new(),
+            show_export_options: false,
+            decoration_name_input: String::new(),
+            diagnostics: Diagnostics::new(),
+            show_diagnostics: false,
+            show_about: false,
+            show_monitor_routing: false,
+            pending_capture: None,
+            pending_preset: None,
+            error_dialog: None,
+            writer: BackgroundWriter::new(),
+            cloud_sync_roots: crate::files::cloud_sync_roots(),
+            show_compare_projects: false,
+            compare_inputs: (String::new(), String::new()),
+            controller: AppController::new(triggers.clone()),
+            triggers,
+            control_server: None,
+            hotkeys: None,
+            automation_server: None,
+            capture_calls: Vec::new(),
+            last_capture_path: None,
+            hidden_until_capture: false,
+            file_naming_draft: None,
+            presets_draft: None,
+            share_targets_draft: None,
+            save_as: None,
+            destinations_draft: None,
+            transfers: Vec::new(),
+            encrypted_export: None,
+            encrypted_open: None,
+            privacy_check: None,
+            privacy_draft: None,
+            interval_run: None,
+            interval_capture: None,
+            interval_draft: None,
+            region_watch: None,
+            watch_capture: None,
+            watch_draft: None,
+            sticker_symbol: emoji::DEFAULT_STICKER.to_string(),
+            emoji_category: 0,
+            keep_measurements: false,
+            text_faces: HashMap::new(),
+            text_fonts_registered: false,
+            canvas_rect: None,
+            notifications: Notifications::new(),
+            show_error_log: false,
+            comment_draft: None,
+            session: None,
+            session_saved_at: Instant::now(),
+            session_signature: None,
+            recovered_session: None,
+            history: None,
+            show_history: false,
+            history_query: String::new(),
+            recent_thumbnails: HashMap::new(),
+            templates: None,
+            template_name_draft: None,
+            file_counters: CounterStore::default(),
+            collage_sources: None,
+            show_adjustments: false,
+            pending_uploads: Vec::new(),
+            plugins: ActionRegistry::new(),
+            pending_actions: Vec::new(),
+            pending_region_selection: None,
+            monitor_cycle: MonitorCycle::default(),
+            silent_capture: None,
+            pending_color_pick: false,
+            region_constraint: SelectionConstraint::Free,
+        }
+    }
+}
+
+impl EditorApp {
+    /// Create a new editor application
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a new editor application using persisted settings
+    pub fn with_settings(settings: SettingsManager) -> Self {
+        Self {
+            settings,
+            ..Self::default()
+        }
+    }
+
+    /// Get the settings manager
+    pub fn settings(&self) -> &SettingsManager {
+        &self.settings
+    }
+
+    /// Start initializing expensive services on background threads
+    ///
+    /// Called once the window is up so that screen enumeration and font
+    /// loading do not delay the first frame.
+    pub fn start_background_init(&mut self) {
+        self.capture.start_background();
+        if let Err(e) = std::thread::Builder::new()
+            .name("init-fonts".to_string())
+            .spawn(crate::render::preload_fonts)
+        {
+            log::warn!("Failed to spawn font preload thread: {}", e);
+        }
+
+        // Remove temp files left behind by saves interrupted in a previous session
+        let directories = [self.settings.get_settings().save_directory(), std::env::temp_dir()];
+        if let Err(e) = std::thread::Builder::new()
+            .name("cleanup-temp-files".to_string())
+            .spawn(move || {
+                for directory in &directories {
+                    let removed = crate::files::cleanup_orphaned_temp_files(directory, crate::files::ORPHAN_MIN_AGE);
+                    if removed > 0 {
+                        log::info!("Removed {} orphaned temp files from {}", removed, directory.display());
+                    }
+                }
+            })
+        {
+            log::warn!("Failed to spawn temp file cleanup thread: {}", e);
+        }
+    }
+
+    /// Start listening for control commands from scripts
+    pub fn start_control_server(&mut self) {
+        match ControlServer::start(self.triggers.clone(), self.controller.sender(), &control::default_endpoint_path()) {
+            Ok(server) => self.control_server = Some(server),
+            Err(e) => {
+                self.notifications.push(Severity::Warning, "Control Server Unavailable", e.to_string());
+            }
+        }
+    }
+
+    /// Wake the editor whenever a background subsystem posts an event
+    pub fn set_event_waker(&mut self, ctx: &Context) {
+        let ctx = ctx.clone();
+        self.controller.set_waker(move || ctx.request_repaint());
+    }
+
+    /// Get a handle for posting events to the editor from other threads
+    pub fn event_sender(&self) -> EventSender {
+        self.controller.sender()
+    }
+
+    /// Keep the window hidden until a capture or opened file gives it something to show
+    ///
+    /// The window must be created invisible; it is shown by [`Self::reveal_window`].
+    pub fn start_in_background(&mut self) {
+        self.hidden_until_capture = true;
+    }
+
+    /// Show and focus the window once a background instance has an image
+    fn reveal_window(&mut self, ctx: &Context) {
+        if self.hidden_until_capture && !self.doc.is_empty() {
+            log::info!("Showing the editor window");
+            ctx.send_viewport_cmd(egui::ViewportCommand::Visible(true));
+            ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
+            self.hidden_until_capture = false;
+        }
+    }
+
+    /// Register the global capture hotkeys
+    pub fn start_hotkeys(&mut self) {
+        let hotkeys = hotkeys::configured_hotkeys(self.settings.get_settings());
+        match HotkeyListener::start(hotkeys, self.controller.sender()) {
+            Ok(listener) => self.hotkeys = Some(listener),
+            Err(e) => {
+                self.notifications.push(Severity::Warning, "Hotkeys Unavailable", e.to_string());
+            }
+        }
+    }
+
+    /// Handle the events posted since the last frame
+    fn poll_events(&mut self) {
+        for event in self.controller.poll() {
+            self.handle_event(event);
+        }
+    }
+
+    /// Handle an event from a background subsystem
+    fn handle_event(&mut self, event: AppEvent) {
+        match event {
+            AppEvent::Hotkey(hotkey) => self.handle_hotkey(&hotkey),
+            // The trigger state is shared; the repaint that delivered this shows it
+            AppEvent::TriggersChanged(state) => log::debug!("Triggers changed: {}", state),
+            AppEvent::OpenFile(path) => self.open_and_report(&path),
+            AppEvent::Automation(call) => self.handle_automation(call),
+            AppEvent::IntervalCapture(true) => {
+                if let Err(e) = self.start_interval_capture() {
+                    self.notify_error("Interval Capture Failed", &e);
+                }
+            }
+            AppEvent::IntervalCapture(false) => self.stop_interval_capture(StopReason::Stopped),
+        }
+    }
+
+    /// Start listening for automation requests from external tools
+    pub fn start_automation_server(&mut self) {
+        match AutomationServer::start(self.controller.sender(), &automation::default_endpoint_path()) {
+            Ok(server) => self.automation_server = Some(server),
+            Err(e) => {
+                self.notifications.push(Severity::Warning, "Automation Unavailable", e.to_string());
+            }
+        }
+    }
+
+    /// Carry out a request from an external tool and answer it
+    ///
+    /// Capture requests are answered once the capture has loaded.
+    fn handle_automation(&mut self, call: AutomationCall) {
+        let result = match &call.request {
+            AutomationRequest::Capture { target, window_title, process } => {
+                let selector = WindowSelector {
+                    title: window_title.clone(),
+                    process: process.clone(),
+                };
+                let started = match target {
+                    _ if !selector.is_empty() => self.start_matching_window_capture(&selector),
+                    CaptureTarget::Screen => self.start_capture(),
+                    CaptureTarget::Window => self.start_window_capture(),
                 };
+                match started {
+                    Ok(()) => {
+                        self.capture_calls.push(call);
+                        return;
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+            AutomationRequest::LastCapture => Ok(serde_json::json!({ "path": self.last_capture_path })),
+            AutomationRequest::Open { path } => {
+                let is_project = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case(PROJECT_EXTENSION));
+                let opened = if is_project { self.open_project(path) } else { self.open_file(path) };
+                opened.map(|()| serde_json::json!({}))
+            }
+            AutomationRequest::Export { path, options } => {
+                let options = options.as_deref().cloned().unwrap_or_else(|| self.settings.get_settings().export.clone());
+                self.export_to(path, &options)
+                    .map(|(width, height)| serde_json::json!({ "path": path, "width": width, "height": height }))
+            }
+        };
+        call.respond(result.map_err(|e| AutomationError::from(&e)));
+    }
+
+    /// Start the capture bound to a hotkey
+    fn handle_hotkey(&mut self, event: &HotkeyEvent) {
+        log::info!("Hotkey {} pressed", hotkeys::describe(event.modifiers, event.vk_code));
+        let result = match HotkeyAction::from_id(event.id) {
+            Some(HotkeyAction::CaptureScreen) if self.settings.get_settings().cycle_monitors => {
+                self.press_monitor_cycle(Instant::now())
+            }
+            Some(HotkeyAction::CaptureScreen) => self.start_hotkey_capture(),
+            Some(HotkeyAction::CaptureWindow) => self.start_window_capture(),
+            Some(HotkeyAction::CaptureCursorMonitor) => self.start_cursor_monitor_capture(),
+            Some(HotkeyAction::RunPreset(index)) => self.run_capture_preset(index),
+            Some(HotkeyAction::PickColor) => self.start_color_pick(),
+            None => Ok(()),
+        };
+        if let Err(e) = result {
+            self.show_error("Capture Failed", e.to_string());
+        }
+    }
+
+    /// Get which capture triggers are enabled
+    pub fn trigger_state(&self) -> TriggerState {
+        *self.triggers.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Enable or disable a capture trigger
+    pub fn set_trigger_enabled(&mut self, trigger: Trigger, enabled: bool) {
+        self.triggers
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .set_enabled(trigger, enabled);
+    }
+
+    /// Show an error dialog
+    pub fn show_error(&mut self, title: &str, message: String) {
+        self.notifications.record(Severity::Error, title, message.clone());
+        self.error_dialog = Some((title.to_string(), message));
+    }
+
+    /// Show a non-fatal error as a toast
+    ///
+    /// While the window is hidden, the error is shown as a system
+    /// notification instead so it isn't missed.
+    pub fn notify_error(&mut self, title: &str, error: &AppError) {
+        self.notifications.error(title, error);
+        if self.hidden_until_capture {
+            notifications::show_system_notification(title, &error.to_string());
+        }
+    }
+
+    /// Get the notification toasts and history
+    pub fn notifications(&self) -> &Notifications {
+        &self.notifications
+    }
+
+    /// Get the error currently shown in the dialog, if any
+    pub fn error_dialog(&self) -> Option<&(String, String)> {
+        self.error_dialog.as_ref()
+    }
+
+    /// Draw the error dialog until it is dismissed
+    fn draw_error_dialog(&mut self, ctx: &Context) {
+        let Some((title, message)) = &self.error_dialog else {
+            return;
+        };
+
+        let mut dismissed = false;
+        egui::Window::new(title.as_str())
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, Vec2::ZERO)
+            .show(ctx, |ui| {
+                ui.label(message.as_str());
+                ui.add_space(8.0);
+                if ui.button("OK").clicked() {
+                    dismissed = true;
+                }
+            });
+        if dismissed {
+            self.error_dialog = None;
+        }
+    }
+
+    /// Draw the tab strip with one tab per open image
+    fn draw_tab_bar(&mut self, ctx: &Context) {
+        let mut selected = None;
+        let mut closed = None;
+        egui::TopBottomPanel::top("tab_bar").show(ctx, |ui| {
+            ui.horizontal_wrapped(|ui| {
+                for index in 0..self.tabs.len() {
+                    let document = self.tab(index);
+                    let title = if document.is_dirty() {
+                        format!("{} *", document.title())
+                    } else {
+                        document.title()
+                    };
+                    let mut tab = ui.selectable_label(index == self.active_tab, title);
+                    if let Some(path) = &document.file_path {
+                        tab = tab.on_hover_text(path.display().to_string());
+                    }
+                    if tab.clicked() {
+                        selected = Some(index);
+                    }
+                    if tab.middle_clicked() || accessibility::label(ui.small_button("×"), egui::WidgetType::Button, "Close Tab").on_hover_text("Close Tab").clicked() {
+                        closed = Some(index);
+                    }
+                    ui.separator();
+                }
+            });
+        });
+
+        if let Some(index) = selected {
+            self.select_tab(index);
+        }
+        if let Some(index) = closed {
+            self.close_tab(index);
+        }
+    }
+
+    /// Draw the Save/Discard/Cancel prompt for a dirty tab being closed
+    fn draw_close_tab_prompt(&mut self, ctx: &Context) {
+        let Some(index) = self.closing_tab.filter(|&index| index < self.tabs.len()) else {
+            self.closing_tab = None;
+            return;
+        };
+
+        let message = format!("Save changes to {} before closing?", self.tab(index).title());
+        match Self::draw_save_prompt(ctx, &message) {
+            Some(SaveChoice::Save) => self.save_and_close_tab(index),
+            Some(SaveChoice::Discard) => self.discard_tab(index),
+            Some(SaveChoice::Cancel) => self.closing_tab = None,
+            None => {}
+        }
+    }
+
+    /// Draw the Save/Discard/Cancel prompt shown when exiting with unsaved changes
+    fn draw_exit_prompt(&mut self, ctx: &Context) {
+        if !self.show_exit_prompt {
+            return;
+        }
+
+        let titles: Vec<String> = (0..self.tabs.len())
+            .filter(|&index| self.tab(index).is_dirty())
+            .map(|index| self.tab(index).title())
+            .collect();
+        let message = format!("Save changes before exiting?\n{}", titles.join("\n"));
+        match Self::draw_save_prompt(ctx, &message) {
+            Some(SaveChoice::Save) => {
+                self.show_exit_prompt = false;
+                if self.save_all() {
+                    self.should_close = true;
+                }
+            }
+            Some(SaveChoice::Discard) => {
+                self.show_exit_prompt = false;
+                self.should_close = true;
+            }
+            Some(SaveChoice::Cancel) => self.show_exit_prompt = false,
+            None => {}
+        }
+    }
+
+    /// Draw a modal offering to restore the tabs of a session that didn't exit cleanly
+    fn draw_recovery_prompt(&mut self, ctx: &Context) {
+        let Some(recovered) = &self.recovered_session else {
+            return;
+        };
+
+        let names: Vec<String> = recovered
+            .tabs
+            .iter()
+            .map(|tab| {
+                tab.file_path
+                    .as_deref()
+                    .and_then(Path::file_name)
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_else(|| "Untitled".to_string())
+            })
+            .collect();
+        let mut restore = false;
+        let mut discard = false;
+        egui::Window::new("Restore Unsaved Work")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, Vec2::ZERO)
+            .show(ctx, |ui| {
+                ui.label("The editor didn't close properly last time. Restore these unsaved images?");
+                for name in &names {
+                    ui.label(format!("  {}", name));
+                }
+                ui.add_space(8.0);
+                ui.horizontal(|ui| {
+                    restore = ui.button("Restore").clicked();
+                    discard = ui.button("Discard").clicked();
+                });
+            });
+
+        if restore {
+            self.restore_session();
+        } else if discard {
+            self.discard_recovered_session();
+        }
+    }
+
+    /// Draw a modal asking whether to save unsaved changes
+    fn draw_save_prompt(ctx: &Context, message: &str) -> Option<SaveChoice> {
+        let mut choice = None;
+        egui::Window::new("Unsaved Changes")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, Vec2::ZERO)
+            .show(ctx, |ui| {
+                ui.label(message);
+                ui.add_space(8.0);
+                ui.horizontal(|ui| {
+                    if ui.button("Save").clicked() {
+                        choice = Some(SaveChoice::Save);
+                    }
+                    if ui.button("Discard").clicked() {
+                        choice = Some(SaveChoice::Discard);
+                    }
+                    if ui.button("Cancel").clicked() {
+                        choice = Some(SaveChoice::Cancel);
+                    }
+                });
+            });
+        choice
+    }
+
+    /// Get the optional features enabled under the current low-resource mode
+    pub fn resource_profile(&self) -> ResourceProfile {
+        ResourceProfile::for_mode(self.settings.get_settings().low_resource_mode, self.power_source)
+    }
+
+    /// Change the low-resource mode setting
+    pub fn set_low_resource_mode(&mut self, mode: LowResourceMode) {
+        self.settings.settings_mut().low_resource_mode = mode;
+        self.settings_dirty = true;
+    }
+
+    /// Re-detect the power source when automatic low-resource mode needs it
+    fn poll_power_source(&mut self, now: Instant) {
+        if self.settings.get_settings().low_resource_mode != LowResourceMode::Auto {
+            return;
+        }
+        let due = match self.power_checked_at {
+            Some(checked) => now.duration_since(checked) >= POWER_POLL_INTERVAL,
+            None => true,
+        };
+        if due {
+            self.power_checked_at = Some(now);
+            let source = power::detect_power_source();
+            if source != self.power_source {
+                log::info!("Power source changed to {:?}", source);
+                self.power_source = source;
+            }
+        }
+    }
+
+    /// Apply the resource profile to egui when it changes
+    fn apply_resource_profile(&mut self, ctx: &Context) {
+        let profile = self.resource_profile();
+        if self.applied_profile == Some(profile) {
+            return;
+        }
+        ctx.style_mut(|style| {
+            style.animation_time = if profile.animations { DEFAULT_ANIMATION_TIME } else { 0.0 };
+        });
+        self.applied_profile = Some(profile);
+    }
+
+    /// Switch between the default and high-contrast themes when the setting changes
+    fn apply_theme(&mut self, ctx: &Context) {
+        let high_contrast = self.settings.get_settings().high_contrast;
+        if self.applied_high_contrast == Some(high_contrast) {
+            return;
+        }
+        ctx.set_visuals(if high_contrast { accessibility::high_contrast_visuals() } else { egui::Visuals::dark() });
+        self.applied_high_contrast = Some(high_contrast);
+    }
+
+    /// Register the installed font faces used by text annotations with egui
+    ///
+    /// The emoji fallback chain is registered on the first frame. Faces are
+    /// loaded the first time an annotation uses them; until then the canvas
+    /// draws the text with the built-in font.
+    fn register_text_faces(&mut self, ctx: &Context) {
+        let new_faces: Vec<text::InstalledFont> = self
+            .doc
+            .annotations
+            .iter()
+            .filter_map(|annotation| match &annotation.annotation_type {
+                AnnotationType::Text { format, .. } => text::resolve_face(format, text::installed_fonts()).face,
+                _ => None,
+            })
+            .filter(|font| !self.text_faces.contains_key(&font.name))
+            .collect();
+        if new_faces.is_empty() && self.text_fonts_registered {
+            return;
+        }
+        for font in new_faces {
+            let data = text::load_font(&font)
+                .map_err(|e| log::warn!("Drawing text with the built-in font: {}", e))
+                .ok();
+            self.text_faces.insert(font.name, data);
+        }
+        let faces = self
+            .text_faces
+            .iter()
+            .filter_map(|(name, data)| Some((name.as_str(), data.clone()?)));
+        ctx.set_fonts(text::font_definitions(faces));
+        self.text_fonts_registered = true;
+    }
+
+    /// Get the capture service, initializing it if needed
+    pub fn capture_service(&mut self) -> AppResult<&mut CaptureService> {
+        let convert_to_srgb = self.settings.get_settings().color.convert_to_srgb;
+        let hdr = self.settings.get_settings().hdr.clone();
+        let window_capture = self.settings.get_settings().window_capture;
+        // A failed initialization is retried on the next use
+        let service = self.capture.try_get(CaptureService::new)?;
+        service.set_convert_to_srgb(convert_to_srgb);
+        service.set_hdr_options(hdr);
+        service.set_window_capture_options(window_capture);
+        Ok(service)
+    }
+
+    /// Use a specific capture service instead of detecting the system screens
+    pub fn set_capture_service(&mut self, service: CaptureService) {
+        self.capture = LazyService::ready("capture service", Ok(service));
+    }
+
+    /// Persist settings, reporting failures in a toast
+    fn save_settings(&mut self) {
+        self.settings_dirty = false;
+        if let Err(e) = self.settings.save() {
+            self.notify_error("Settings Not Saved", &e);
+        }
+    }
+
+    /// Load an image into the editor
+    ///
+    /// The image replaces the active tab if it is empty and opens in a new
+    /// tab otherwise.
+    pub fn load_image(&mut self, image: DynamicImage) -> AppResult<()> {
+        let document = Document {
+            source_image: Some(image),
+            ..Document::default()
+        };
+        if self.doc.is_empty() {
+            self.doc = document;
+        } else {
+            self.open_tab(document);
+        }
+        self.mark_content_changed();
+        Ok(())
+    }
+
+    /// Open a document in a new tab after the active one and switch to it
+    fn open_tab(&mut self, document: Document) {
+        self.doc.suspend();
+        self.tabs[self.active_tab] = std::mem::replace(&mut self.doc, document);
+        self.active_tab += 1;
+        self.tabs.insert(self.active_tab, Document::default());
+    }
+
+    /// Get the document of a tab
+    fn tab(&self, index: usize) -> &Document {
+        if index == self.active_tab {
+            &self.doc
+        } else {
+            &self.tabs[index]
+        }
+    }
+
+    /// Get the number of open tabs
+    pub fn tab_count(&self) -> usize {
+        self.tabs.len()
+    }
+
+    /// Get the index of the active tab
+    pub fn active_tab(&self) -> usize {
+        self.active_tab
+    }
+
+    /// Get the title of a tab
+    pub fn tab_title(&self, index: usize) -> String {
+        self.tab(index).title()
+    }
+
+    /// Check whether a tab has unsaved changes
+    pub fn is_tab_dirty(&self, index: usize) -> bool {
+        self.tab(index).is_dirty()
+    }
+
+    /// Switch to a tab
+    ///
+    /// The tab switched away from keeps its image compressed until it is
+    /// selected again.
+    pub fn select_tab(&mut self, index: usize) {
+        if index >= self.tabs.len() || index == self.active_tab {
+            return;
+        }
+        self.doc.suspend();
+        self.tabs[self.active_tab] = std::mem::take(&mut self.doc);
+        self.active_tab = index;
+        self.doc = std::mem::take(&mut self.tabs[index]);
+        self.resume_active_tab();
+    }
+
+    /// Decode the image of the newly active tab
+    fn resume_active_tab(&mut self) {
+        if let Err(e) = self.doc.resume() {
+            self.notify_error("Tab Image Not Restored", &e);
+        }
+    }
+
+    /// Switch to the next tab, wrapping around after the last
+    pub fn next_tab(&mut self) {
+        self.select_tab((self.active_tab + 1) % self.tabs.len());
+    }
+
+    /// Switch to the previous tab, wrapping around before the first
+    pub fn previous_tab(&mut self) {
+        self.select_tab((self.active_tab + self.tabs.len() - 1) % self.tabs.len());
+    }
+
+    /// Close a tab, asking to save it first if it has unsaved changes
+    ///
+    /// Returns whether the tab was closed.
+    pub fn close_tab(&mut self, index: usize) -> bool {
+        if index >= self.tabs.len() {
+            return false;
+        }
+        if self.tab(index).is_dirty() {
+            self.closing_tab = Some(index);
+            return false;
+        }
+        self.discard_tab(index);
+        true
+    }
+
+    /// Close a tab without saving
+    pub fn discard_tab(&mut self, index: usize) {
+        if index >= self.tabs.len() {
+            return;
+        }
+        self.closing_tab = None;
+        self.tabs.remove(index);
+        if index == self.active_tab {
+            if self.tabs.is_empty() {
+                self.tabs.push(Document::default());
+            }
+            self.active_tab = index.min(self.tabs.len() - 1);
+            self.doc = std::mem::take(&mut self.tabs[self.active_tab]);
+            self.resume_active_tab();
+        } else if index < self.active_tab {
+            self.active_tab -= 1;
+        }
+    }
+
+    /// Check whether the active image has unsaved annotations or is an unsaved capture
+    pub fn is_dirty(&self) -> bool {
+        self.doc.is_dirty()
+    }
+
+    /// Check whether any tab has unsaved changes
+    pub fn has_unsaved_changes(&self) -> bool {
+        (0..self.tabs.len()).any(|index| self.tab(index).is_dirty())
+    }
+
+    /// Keep a recovery copy of unsaved tabs in `dir` and look for one left by a crash
+    ///
+    /// A session found there is offered for restoring until the user
+    /// restores or discards it; nothing is written over it until then.
+    pub fn enable_session_recovery(&mut self, dir: PathBuf) {
+        let mut store = SessionStore::new(dir);
+        match store.find_recoverable() {
+            Ok(Some(recovered)) => {
+                log::info!("Found {} unsaved tabs from a previous session", recovered.tabs.len());
+                self.recovered_session = Some(recovered);
+            }
+            Ok(None) => {}
+            Err(e) => {
+                self.notifications.push(Severity::Warning, "Session Not Recovered", e.to_string());
+                store.clear();
+            }
+        }
+        self.session = Some(store);
+    }
+
+    /// Record captures in the history file at `path`
+    pub fn enable_capture_history(&mut self, path: PathBuf) {
+        match CaptureHistory::load(path) {
+            Ok(history) => self.history = Some(history),
+            Err(e) => self.notify_error("Capture History Unavailable", &e),
+        }
+    }
+
+    /// Keep the `{counter}` numbers of file names in the file at `path`, so numbering continues after a restart
+    pub fn enable_file_counters(&mut self, path: PathBuf) {
+        match CounterStore::load(path) {
+            Ok(counters) => self.file_counters = counters,
+            Err(e) => self.notify_error("File Numbering Unavailable", &e),
+        }
+    }
+
+    /// Keep annotation templates in the file at `path`
+    pub fn enable_templates(&mut self, path: PathBuf) {
+        match TemplateLibrary::load(path) {
+            Ok(library) => self.templates = Some(library),
+            Err(e) => self.notify_error("Templates Unavailable", &e),
+        }
+    }
+
+    /// Get the saved annotation templates
+    pub fn templates(&self) -> &[AnnotationTemplate] {
+        self.templates.as_ref().map(TemplateLibrary::templates).unwrap_or_default()
+    }
+
+    /// Save the selected annotations, or all of them if none is selected, as a named template
+    ///
+    /// A template with the same name is replaced.
+    pub fn save_template(&mut self, name: &str) -> AppResult<()> {
+        let library = self
+            .templates
+            .as_mut()
+            .ok_or_else(|| AppError::Settings("テンプレートは使用できません".to_string()))?;
+        let annotations = &self.doc.annotations;
+        let template = if annotations.iter().any(|a| a.is_selected) {
+            let selected: Vec<AnnotationItem> = annotations.iter().filter(|a| a.is_selected).cloned().collect();
+            AnnotationTemplate::new(name, &selected)?
+        } else {
+            AnnotationTemplate::new(name, annotations)?
+        };
+        log::info!("Saved template {:?} with {} annotations", template.name, template.annotations.len());
+        library.save(template)
+    }
+
+    /// Add the annotations of a template to the current image and select them
+    ///
+    /// Returns whether the template was found and there was an image to add it to.
+    pub fn apply_template(&mut self, name: &str) -> bool {
+        let Some(image_size) = self.image_size() else {
+            return false;
+        };
+        let Some(template) = self.templates.as_ref().and_then(|library| library.get(name)) else {
+            return false;
+        };
+        let copies = template.instantiate(image_size);
+        self.select_annotation(None);
+        for mut copy in copies {
+            copy.is_selected = true;
+            self.doc.annotations.push(copy);
+        }
+        self.mark_content_changed();
+        true
+    }
+
+    /// Delete a saved template
+    pub fn delete_template(&mut self, name: &str) -> AppResult<bool> {
+        match self.templates.as_mut() {
+            Some(library) => library.remove(name),
+            None => Ok(false),
+        }
+    }
+
+    /// Load the post-capture actions declared in the plugins file at `path`
+    pub fn enable_plugins(&mut self, path: &Path) {
+        match ActionRegistry::load(path) {
+            Ok(plugins) => {
+                log::info!("Loaded {} post-capture actions", plugins.actions().len());
+                self.plugins = plugins;
+            }
+            Err(e) => self.notify_error("Plugins Unavailable", &e),
+        }
+    }
+
+    /// Get the post-capture actions
+    pub fn plugins_mut(&mut self) -> &mut ActionRegistry {
+        &mut self.plugins
+    }
+
+    /// Run post-capture actions in order on the current image, on a worker thread
+    ///
+    /// The image is the flattened composite with the annotations, without
+    /// export options applied.
+    pub fn run_actions(&mut self, actions: Vec<Arc<dyn PostCaptureAction>>) -> AppResult<()> {
+        if actions.is_empty() {
+            return Ok(());
+        }
+        let image = self
+            .flattened_image()
+            .ok_or_else(|| AppError::ImageProcessing("アクションを実行する画像がありません".to_string()))?;
+        let names: Vec<&str> = actions.iter().map(|action| action.name()).collect();
+        log::info!("Running post-capture actions: {}", names.join(", "));
+        let receiver = plugins::spawn_chain(actions, image, self.image_metadata(), self.doc.file_path.clone());
+        self.pending_actions.push((self.doc.history_id, receiver));
+        Ok(())
+    }
+
+    /// Report the results of action chains that finished since the last frame
+    ///
+    /// The last link of a chain is recorded in the capture history like an upload link.
+    fn poll_actions(&mut self) {
+        let mut finished = Vec::new();
+        self.pending_actions.retain(|(id, receiver)| match receiver.try_recv() {
+            Ok(results) => {
+                finished.push((*id, results));
+                false
+            }
+            Err(crossbeam_channel::TryRecvError::Empty) => true,
+            Err(crossbeam_channel::TryRecvError::Disconnected) => false,
+        });
+        for (id, results) in finished {
+            let mut last_url = None;
+            for ActionResult { action, result } in results {
+                match result {
+                    Ok(output) => {
+                        let message = output.message.or(output.url.clone()).unwrap_or_else(|| "Done".to_string());
+                        self.notifications.push(Severity::Info, &action, message);
+                        last_url = output.url.or(last_url);
+                    }
+                    Err(e) => self.notify_error("Action Failed", &e.context(format!("アクション「{}」", action))),
+                }
+            }
+            if let Some(url) = last_url {
+                self.update_history(id, |entry| entry.upload_url = Some(url));
+            }
+        }
+    }
+
+    /// Check whether post-capture actions are running
+    pub fn has_pending_actions(&self) -> bool {
+        !self.pending_actions.is_empty()
+    }
+
+    /// Post the current image to a share target, on a worker thread
+    ///
+    /// The result is reported like that of a post-capture action. The image
+    /// is checked for sensitive content first.
+    pub fn share(&mut self, target: &ShareTarget) -> AppResult<()> {
+        log::info!("Sharing to {} ({})", target.name, target.service);
+        self.guard_output(GuardedOutput::Actions(vec![Arc::new(target.clone())]))
+    }
+
+    /// Copy the current image with its annotations to the clipboard
+    pub fn copy_image(&mut self) -> AppResult<()> {
+        let image = self
+            .flattened_image()
+            .ok_or_else(|| AppError::Clipboard("コピーする画像がありません".to_string()))?;
+        clipboard::write_image(&image)?;
+        log::info!("Copied {}x{} image to the clipboard", image.width(), image.height());
+        Ok(())
+    }
+
+    /// Copy or upload the current image once it is checked for sensitive content
+    ///
+    /// The image is scanned on a worker thread; if anything is found, the
+    /// output waits until the user blurs it or continues anyway.
+    fn guard_output(&mut self, output: GuardedOutput) -> AppResult<()> {
+        let settings = self.settings.get_settings().privacy.clone();
+        if !settings.scans(self.resource_profile()) || !ocr::is_supported() {
+            return self.release_output(output);
+        }
+        let image = self
+            .flattened_image()
+            .ok_or_else(|| AppError::ImageProcessing("確認する画像がありません".to_string()))?;
+        log::info!("Checking the image for sensitive content");
+        self.privacy_check = Some(PrivacyCheck {
+            session_id: self.doc.session_id,
+            output,
+            receiver: Some(privacy::spawn_scan(image, settings)),
+            findings: Vec::new(),
+        });
+        Ok(())
+    }
+
+    fn release_output(&mut self, output: GuardedOutput) -> AppResult<()> {
+        match output {
+            GuardedOutput::CopyImage => self.copy_image(),
+            GuardedOutput::Actions(actions) => self.run_actions(actions),
+        }
+    }
+
+    /// Release the held-back output if the scan finished without findings
+    fn poll_privacy_check(&mut self) {
+        let Some(receiver) = self.privacy_check.as_ref().and_then(|check| check.receiver.as_ref()) else {
+            return;
+        };
+        let result = match receiver.try_recv() {
+            Ok(result) => result,
+            Err(crossbeam_channel::TryRecvError::Empty) => return,
+            Err(crossbeam_channel::TryRecvError::Disconnected) => {
+                Err(AppError::Ocr("文字認識が中断されました".to_string()))
+            }
+        };
+        let Some(mut check) = self.privacy_check.take() else {
+            return;
+        };
+        if check.session_id != self.doc.session_id {
+            log::info!("Dropped output of a tab that is no longer active");
+            return;
+        }
+        let findings = result.unwrap_or_else(|e| {
+            // The check is a safeguard; failing to recognize text doesn't block the output
+            log::warn!("Sensitive content check failed: {}", e);
+            Vec::new()
+        });
+        if findings.is_empty() {
+            let title = check.output.failure_title();
+            if let Err(e) = self.release_output(check.output) {
+                self.notify_error(title, &e);
+            }
+            return;
+        }
+        log::info!("Found {} possibly sensitive items", findings.len());
+        check.receiver = None;
+        check.findings = findings;
+        self.privacy_check = Some(check);
+    }
+
+    /// Get the sensitive content found in the image being copied or uploaded
+    pub fn privacy_findings(&self) -> &[Finding] {
+        self.privacy_check.as_ref().map_or(&[], |check| &check.findings)
+    }
+
+    /// Blur a finding of the sensitive-content check in the image
+    pub fn blur_finding(&mut self, index: usize) {
+        let Some(check) = self.privacy_check.as_mut() else {
+            return;
+        };
+        if index >= check.findings.len() {
+            return;
+        }
+        let finding = check.findings.remove(index);
+        if let Err(e) = self.doc.restore_full_resolution() {
+            self.notify_error("Blur Failed", &e);
+            return;
+        }
+        if let Some(image) = self.doc.source_image.as_mut() {
+            let blurred = privacy::blur_region(image, finding.bounds);
+            self.reapply_adjustments(blurred.map(TileRect::covering));
+        }
+    }
+
+    /// Finish the sensitive-content check, sending the output or dropping it
+    pub fn resolve_privacy_check(&mut self, proceed: bool) {
+        let Some(check) = self.privacy_check.take() else {
+            return;
+        };
+        if !proceed {
+            log::info!("Output cancelled after the sensitive content check");
+            return;
+        }
+        let title = check.output.failure_title();
+        if let Err(e) = self.release_output(check.output) {
+            self.notify_error(title, &e);
+        }
+    }
+
+    /// Draw the warning about sensitive content found in the image
+    fn draw_privacy_warning(&mut self, ctx: &Context) {
+        let Some(check) = &self.privacy_check else {
+            return;
+        };
+        if check.findings.is_empty() && check.receiver.is_some() {
+            return;
+        }
+
+        let mut open = true;
+        let mut blur = None;
+        let mut blur_all = false;
+        let mut resolution = None;
+        egui::Window::new("Sensitive Content")
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                if check.findings.is_empty() {
+                    ui.label("All findings are blurred.");
+                } else {
+                    ui.label("The image may contain sensitive information, highlighted on the canvas:");
+                    egui::Grid::new("privacy_findings_grid").num_columns(3).striped(true).show(ui, |ui| {
+                        for (index, finding) in check.findings.iter().enumerate() {
+                            ui.label(finding.kind.to_string());
+                            ui.monospace(finding.masked_text());
+                            if ui.button("Blur").clicked() {
+                                blur = Some(index);
+                            }
+                            ui.end_row();
+                        }
+                    });
+                }
+                ui.horizontal(|ui| {
+                    if !check.findings.is_empty() {
+                        blur_all = ui.button("Blur All").clicked();
+                    }
+                    let label = if check.findings.is_empty() { "Continue" } else { "Continue Anyway" };
+                    if ui.button(label).clicked() {
+                        resolution = Some(true);
+                    }
+                    if ui.button("Cancel").clicked() {
+                        resolution = Some(false);
+                    }
+                });
+            });
+
+        if let Some(index) = blur {
+            self.blur_finding(index);
+        }
+        if blur_all {
+            while !self.privacy_findings().is_empty() {
+                self.blur_finding(0);
+            }
+        }
+        if !open {
+            resolution = Some(false);
+        }
+        if let Some(proceed) = resolution {
+            self.resolve_privacy_check(proceed);
+        }
+    }
+
+    /// Outline sensitive content found by the check on the canvas
+    fn draw_privacy_highlights(&self, ui: &mut egui::Ui, image_rect: Rect) {
+        let zoom = self.doc.zoom_level as f32;
+        for finding in self.privacy_findings() {
+            let rect = Rect::from_min_size(image_rect.min + finding.bounds.min.to_vec2() * zoom, finding.bounds.size() * zoom)
+                .expand(3.0);
+            ui.painter().rect_filled(rect, 2.0, PRIVACY_HIGHLIGHT_COLOR.gamma_multiply(0.2));
+            ui.painter().rect_stroke(rect, 2.0, egui::Stroke::new(2.0, PRIVACY_HIGHLIGHT_COLOR));
+        }
+    }
+
+    /// Draw the settings of the sensitive-content check
+    fn draw_privacy_settings(&mut self, ctx: &Context) {
+        let Some((enabled, terms)) = self.privacy_draft.as_mut() else {
+            return;
+        };
+
+        let mut open = true;
+        let mut apply = false;
+        let mut cancel = false;
+        egui::Window::new("Privacy Check")
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.add_enabled_ui(ocr::is_supported(), |ui| {
+                    ui.checkbox(enabled, "Check for sensitive content before copying or uploading");
+                })
+                .response
+                .on_disabled_hover_text("Text recognition is only available on Windows");
+                ui.label("Email addresses, credit card numbers, and API keys are always detected. Also warn about:");
+                ui.add(
+                    egui::TextEdit::multiline(terms)
+                        .hint_text("One term per line, e.g. a project code name")
+                        .desired_rows(5),
+                );
+                ui.horizontal(|ui| {
+                    apply = ui.button("Apply").clicked();
+                    cancel = ui.button("Cancel").clicked();
+                });
+            });
+
+        if apply {
+            let privacy = privacy::PrivacySettings {
+                enabled: *enabled,
+                custom_terms: terms.lines().map(str::trim).filter(|term| !term.is_empty()).map(str::to_string).collect(),
+            };
+            match privacy.validate() {
+                Ok(()) => {
+                    self.settings.settings_mut().privacy = privacy;
+                    self.settings_dirty = true;
+                    self.privacy_draft = None;
+                }
+                Err(e) => self.show_error("Invalid Settings", e.to_string()),
+            }
+        } else if cancel || !open {
+            self.privacy_draft = None;
+        }
+    }
+
+    /// Draw the interval capture settings window
+    fn draw_interval_settings(&mut self, ctx: &Context) {
+        if self.interval_draft.is_none() {
+            return;
+        }
+        let screens = if self.capture.is_ready() {
+            self.capture_service().map(|service| service.get_screens()).unwrap_or_default()
+        } else {
+            Vec::new()
+        };
+        let running = self.is_interval_capture_running();
+        let Some((interval, folder)) = self.interval_draft.as_mut() else {
+            return;
+        };
+
+        let mut open = true;
+        let mut apply = false;
+        let mut cancel = false;
+        egui::Window::new("Interval Capture")
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                egui::Grid::new("interval_capture_grid").num_columns(2).show(ui, |ui| {
+                    Self::draw_target_fields(ui, "interval_capture_target", &mut interval.target, &screens);
+
+                    ui.label("Every:");
+                    ui.add(
+                        egui::DragValue::new(&mut interval.interval_secs)
+                            .clamp_range(schedule::MIN_INTERVAL_SECS..=schedule::MAX_INTERVAL_SECS)
+                            .suffix(" s"),
+                    );
+                    ui.end_row();
+
+                    ui.label("Stop after:");
+                    ui.horizontal(|ui| {
+                        let mut limited = interval.max_frames.is_some();
+                        if ui.checkbox(&mut limited, "").changed() {
+                            interval.max_frames = limited.then_some(100);
+                        }
+                        if let Some(max_frames) = interval.max_frames.as_mut() {
+                            ui.add(egui::DragValue::new(max_frames).clamp_range(1..=u32::MAX).suffix(" frames"));
+                        } else {
+                            ui.weak("No frame limit");
+                        }
+                    });
+                    ui.end_row();
+
+                    ui.label("");
+                    ui.horizontal(|ui| {
+                        let mut limited = interval.max_duration_mins.is_some();
+                        if ui.checkbox(&mut limited, "").changed() {
+                            interval.max_duration_mins = limited.then_some(60);
+                        }
+                        if let Some(max_duration) = interval.max_duration_mins.as_mut() {
+                            ui.add(egui::DragValue::new(max_duration).clamp_range(1..=u64::MAX).suffix(" min"));
+                        } else {
+                            ui.weak("No time limit");
+                        }
+                    });
+                    ui.end_row();
+
+                    ui.label("Format:");
+                    egui::ComboBox::from_id_source("interval_capture_format")
+                        .selected_text(interval.format.to_string())
+                        .show_ui(ui, |ui| {
+                            for format in ImageFormat::all() {
+                                let label = format.to_string();
+                                ui.selectable_value(&mut interval.format, format, label);
+                            }
+                        });
+                    ui.end_row();
+
+                    ui.label("Folder:");
+                    ui.add(egui::TextEdit::singleline(folder).hint_text("Save folder"));
+                    ui.end_row();
+                });
+                ui.checkbox(&mut interval.skip_unchanged, "Skip frames identical to the last saved one");
+                ui.weak("Each run saves numbered frames into a new dated folder.");
+                if running {
+                    ui.weak("Changes apply from the next run.");
+                }
+                ui.horizontal(|ui| {
+                    apply = ui.button("Apply").clicked();
+                    cancel = ui.button("Cancel").clicked();
+                });
+            });
+
+        if apply {
+            let folder = folder.trim();
+            let interval = IntervalSettings {
+                directory: (!folder.is_empty()).then(|| PathBuf::from(folder)),
+                ..interval.clone()
+            };
+            match interval.validate() {
+                Ok(()) => {
+                    self.settings.settings_mut().interval_capture = interval;
+                    self.settings_dirty = true;
+                    self.interval_draft = None;
+                }
+                Err(e) => self.show_error("Invalid Settings", e.to_string()),
+            }
+        } else if cancel || !open {
+            self.interval_draft = None;
+        }
+    }
+
+    /// Draw the region watch settings window
+    fn draw_watch_settings(&mut self, ctx: &Context) {
+        let watching = self.is_watching_region();
+        let Some((watch, folder)) = self.watch_draft.as_mut() else {
+            return;
+        };
+
+        let mut open = true;
+        let mut apply = false;
+        let mut cancel = false;
+        egui::Window::new("Region Watch")
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                egui::Grid::new("region_watch_grid").num_columns(2).show(ui, |ui| {
+                    ui.label("Check every:");
+                    ui.add(
+                        egui::DragValue::new(&mut watch.poll_interval_ms)
+                            .clamp_range(watch::MIN_POLL_INTERVAL_MS..=watch::MAX_POLL_INTERVAL_MS)
+                            .speed(10.0)
+                            .suffix(" ms"),
+                    );
+                    ui.end_row();
+
+                    ui.label("Save when more than:");
+                    ui.add(
+                        egui::DragValue::new(&mut watch.min_changed_percent)
+                            .clamp_range(0.0..=100.0)
+                            .speed(0.1)
+                            .suffix(" % changed"),
+                    );
+                    ui.end_row();
+
+                    ui.label("Format:");
+                    egui::ComboBox::from_id_source("region_watch_format")
+                        .selected_text(watch.format.to_string())
+                        .show_ui(ui, |ui| {
+                            for format in ImageFormat::all() {
+                                let label = format.to_string();
+                                ui.selectable_value(&mut watch.format, format, label);
+                            }
+                        });
+                    ui.end_row();
+
+                    ui.label("Folder:");
+                    ui.add(egui::TextEdit::singleline(folder).hint_text("Save folder"));
+                    ui.end_row();
+                });
+                ui.weak("Start a watch from File > Watch Region for Changes.");
+                if watching {
+                    ui.weak("Changes apply from the next watch.");
+                }
+                ui.horizontal(|ui| {
+                    apply = ui.button("Apply").clicked();
+                    cancel = ui.button("Cancel").clicked();
+                });
+            });
+
+        if apply {
+            let folder = folder.trim();
+            let watch = WatchSettings {
+                directory: (!folder.is_empty()).then(|| PathBuf::from(folder)),
+                ..watch.clone()
+            };
+            match watch.validate() {
+                Ok(()) => {
+                    self.settings.settings_mut().watch = watch;
+                    self.settings_dirty = true;
+                    self.watch_draft = None;
+                }
+                Err(e) => self.show_error("Invalid Settings", e.to_string()),
+            }
+        } else if cancel || !open {
+            self.watch_draft = None;
+        }
+    }
+
+    /// Get the capture history, if enabled
+    pub fn capture_history(&self) -> Option<&CaptureHistory> {
+        self.history.as_ref()
+    }
+
+    /// Make the thumbnail of a capture, unless low-resource mode turned thumbnails off
+    fn capture_thumbnail(&self, image: &impl GenericImageView<Pixel = image::Rgba<u8>>) -> Option<RgbaImage> {
+        self.resource_profile().thumbnails.then(|| history::thumbnail(image))
+    }
+
+    /// Add a capture and its thumbnail, if any, to the history, returning its entry
+    fn record_capture(&mut self, target: String, (width, height): (u32, u32), thumbnail: Option<RgbaImage>, path: Option<&Path>) -> Option<Uuid> {
+        let history = self.history.as_mut()?;
+        let entry = HistoryEntry {
+            path: path.map(Path::to_path_buf),
+            ..HistoryEntry::new(SystemTime::now(), target, width, height)
+        };
+        let id = entry.id;
+        match history.record(entry) {
+            Ok(()) => {
+                // A missing thumbnail only leaves the Recent Captures menu without a picture
+                if let Some(Err(e)) = thumbnail.map(|thumbnail| history.save_thumbnail(id, &thumbnail)) {
+                    log::warn!("Failed to save the thumbnail of capture {}: {}", id, e);
+                }
+                Some(id)
+            }
+            Err(e) => {
+                self.notify_error("Capture Not Recorded", &e);
+                None
+            }
+        }
+    }
+
+    /// Change a capture history entry, reporting failures to write the history
+    fn update_history(&mut self, id: Option<Uuid>, change: impl FnOnce(&mut HistoryEntry)) {
+        let (Some(history), Some(id)) = (self.history.as_mut(), id) else {
+            return;
+        };
+        if let Err(e) = history.update(id, change) {
+            self.notify_error("Capture History Not Updated", &e);
+        }
+    }
+
+    /// Run an upload command and record the link it prints in a history entry
+    fn start_upload(&mut self, command: &PostSaveCommand, path: &Path, history_id: Option<Uuid>) -> AppResult<()> {
+        log::info!("Running upload command {} for {}", command.program, path.display());
+        let receiver = command.spawn_for_url(path)?;
+        self.pending_uploads.push(PendingUpload { path: path.to_path_buf(), history_id, receiver });
+        Ok(())
+    }
+
+    /// Check whether an upload command is still reading a file
+    fn is_uploading(&self, path: &Path) -> bool {
+        self.pending_uploads.iter().any(|upload| upload.path == path)
+    }
+
+    /// Record the links of upload commands that finished since the last frame
+    fn poll_uploads(&mut self) {
+        let mut finished = Vec::new();
+        self.pending_uploads.retain(|upload| match upload.receiver.try_recv() {
+            Ok(url) => {
+                finished.extend(url.map(|url| (upload.history_id, url)));
+                false
+            }
+            Err(crossbeam_channel::TryRecvError::Empty) => true,
+            Err(crossbeam_channel::TryRecvError::Disconnected) => false,
+        });
+        for (id, url) in finished {
+            log::info!("Capture uploaded to {}", url);
+            self.update_history(id, |entry| entry.upload_url = Some(url));
+        }
+    }
+
+    /// Check whether a session from a previous run is waiting to be restored
+    pub fn has_recovered_session(&self) -> bool {
+        self.recovered_session.is_some()
+    }
+
+    /// Open the tabs of the session left by a previous run
+    ///
+    /// Tabs whose image can't be read are skipped and reported. Returns the
+    /// number of tabs restored.
+    pub fn restore_session(&mut self) -> usize {
+        let (Some(recovered), Some(store)) = (self.recovered_session.take(), self.session.as_ref()) else {
+            return 0;
+        };
+        let loaded: Vec<(SessionTab, AppResult<DynamicImage>)> = recovered
+            .tabs
+            .into_iter()
+            .map(|tab| {
+                let image = store.load_image(&tab);
+                (tab, image)
+            })
+            .collect();
+
+        let first_tab = if self.doc.is_empty() { self.active_tab } else { self.active_tab + 1 };
+        let mut failures = Vec::new();
+        let mut restored = 0;
+        for (tab, image) in loaded {
+            match image {
+                Ok(image) => {
+                    self.open_recovered_tab(tab, image);
+                    restored += 1;
+                }
+                Err(e) => failures.push(e.context(tab.file_path.map_or_else(
+                    || "無題の画像を復元できませんでした".to_string(),
+                    |path| format!("{} を復元できませんでした", path.display()),
+                ))),
+            }
+        }
+        for error in failures {
+            self.notify_error("Tab Not Restored", &error);
+        }
+        if restored > 0 {
+            self.select_tab(first_tab + recovered.active_tab.min(restored - 1));
+        }
+        log::info!("Restored {} tabs from the previous session", restored);
+        restored
+    }
+
+    /// Open a recovered tab, keeping it unsaved
+    fn open_recovered_tab(&mut self, tab: SessionTab, image: DynamicImage) {
+        let document = Document {
+            source_image: Some(image),
+            annotations: tab.annotations,
+            adjustments: tab.adjustments,
+            file_path: tab.file_path,
+            session_id: tab.id,
+            ..Document::default()
+        };
+        if self.doc.is_empty() {
+            self.doc = document;
+        } else {
+            self.open_tab(document);
+        }
+        self.apply_adjustments();
+        self.doc.reset_undo();
+    }
+
+    /// Throw away the session left by a previous run
+    pub fn discard_recovered_session(&mut self) {
+        if self.recovered_session.take().is_some() {
+            if let Some(store) = &mut self.session {
+                store.clear();
+            }
+        }
+    }
+
+    /// Write unsaved tabs to the recovery session if they changed since the last write
+    ///
+    /// Checks at most once per [`session::AUTOSAVE_INTERVAL`]. Returns
+    /// whether changes are still waiting to be written.
+    fn autosave_session(&mut self, now: Instant) -> bool {
+        if self.session.is_none() || self.recovered_session.is_some() {
+            return false;
+        }
+        let dirty: Vec<usize> = (0..self.tabs.len()).filter(|&index| self.tab(index).is_dirty()).collect();
+        let signature: Vec<(Uuid, u64)> = dirty
+            .iter()
+            .map(|&index| (self.tab(index).session_id, self.tab(index).content_revision))
+            .collect();
+        if self.session_signature.as_ref() == Some(&signature) {
+            return false;
+        }
+        if now.duration_since(self.session_saved_at) < session::AUTOSAVE_INTERVAL {
+            return true;
+        }
+        self.session_saved_at = now;
+
+        let Some(store) = self.session.as_mut() else {
+            return false;
+        };
+        if signature.is_empty() {
+            store.clear();
+            self.session_signature = Some(signature);
+            return false;
+        }
+        let snapshots: Vec<TabSnapshot> = dirty
+            .iter()
+            .map(|&index| {
+                let document = if index == self.active_tab { &self.doc } else { &self.tabs[index] };
+                TabSnapshot {
+                    tab: SessionTab {
+                        id: document.session_id,
+                        file_path: document.file_path.clone(),
+                        annotations: document.annotations.clone(),
+                        adjustments: document.adjustments.clone(),
+                    },
+                    image: if store.has_image(document.session_id) {
+                        None
+                    } else {
+                        document
+                            .full_source_image()
+                            .unwrap_or_else(|e| {
+                                log::warn!("Failed to read the full-resolution image for the session: {}", e);
+                                None
+                            })
+                            .map(|image| image.to_rgba8())
+                    },
+                }
+            })
+            .collect();
+        let active_tab = dirty.iter().position(|&index| index == self.active_tab).unwrap_or(0);
+        if !store.save(active_tab, snapshots) {
+            // The previous write is still running; try again on the next check
+            return true;
+        }
+        self.session_signature = Some(signature);
+        false
+    }
+
+    /// Get the window title: the active image's name, marked with an asterisk when unsaved
+    pub fn window_title(&self) -> String {
+        if self.doc.is_empty() {
+            return APP_TITLE.to_string();
+        }
+        let marker = if self.doc.is_dirty() { "*" } else { "" };
+        format!("{}{} - {}", self.doc.title(), marker, APP_TITLE)
+    }
+
+    /// Save every tab with unsaved changes
+    ///
+    /// Stops at the first failure, showing it and leaving that tab active.
+    /// Returns whether all tabs were saved.
+    fn save_all(&mut self) -> bool {
+        for index in 0..self.tabs.len() {
+            if !self.tab(index).is_dirty() {
+                continue;
+            }
+            self.select_tab(index);
+            if let Err(e) = self.save() {
+                self.show_error("Save Failed", e.to_string());
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Save a tab and close it once the save is written or handed to the background writer
+    fn save_and_close_tab(&mut self, index: usize) {
+        self.select_tab(index);
+        match self.save() {
+            Ok(()) => self.discard_tab(self.active_tab),
+            Err(e) => {
+                self.closing_tab = None;
+                self.show_error("Save Failed", e.to_string());
+            }
+        }
+    }
+
+    /// Load a capture and remember the monitor it came from
+    pub fn load_capture(&mut self, image: DynamicImage, screen: ScreenInfo) -> AppResult<()> {
+        self.load_image(image)?;
+        self.doc.source_screen = Some(screen);
+        Ok(())
+    }
+
+    /// Get the route configured for the monitor the current image was captured from
+    fn active_route(&self) -> Option<&MonitorRoute> {
+        let screen = self.doc.source_screen.as_ref()?;
+        self.settings.get_settings().monitor_routing.route_for(screen)
+    }
+
+    /// Run the post-save command of the route for the monitor a saved image was captured from
+    ///
+    /// A link printed by the command is recorded in the image's capture history entry.
+    fn run_post_save_command(&mut self, path: &Path, screen: Option<&ScreenInfo>, history_id: Option<Uuid>) {
+        let route = screen.and_then(|screen| self.settings.get_settings().monitor_routing.route_for(screen));
+        let Some(command) = route.and_then(|route| route.command.clone()) else {
+            return;
+        };
+        log::info!("Running post-save command {} for {}", command.program, path.display());
+        if let Err(e) = self.start_upload(&command, path, history_id) {
+            self.show_error("Post-Save Command Failed", format!("{}\n{}", command.program, e));
+        }
+    }
+
+    /// Get the image as displayed, with adjustments applied
+    fn display_image(&self) -> Option<&DynamicImage> {
+        self.doc.adjusted_image.as_ref().or(self.doc.source_image.as_ref())
+    }
+
+    /// Get the size of the loaded image in pixels
+    fn image_size(&self) -> Option<Vec2> {
+        self.doc
+            .image_dimensions()
+            .map(|(width, height)| Vec2::new(width as f32, height as f32))
+    }
+
+    /// Get the current image adjustments
+    pub fn adjustments(&self) -> &Adjustments {
+        &self.doc.adjustments
+    }
+
+    /// Set the image adjustments and apply them at full resolution
+    pub fn set_adjustments(&mut self, adjustments: Adjustments) {
+        self.doc.adjustments = adjustments;
+        self.apply_adjustments();
+    }
+
+    /// Apply the current adjustments to the full-resolution image
+    ///
+    /// The adjustments run on a worker thread; the image shown and exported
+    /// is replaced once they finish. Removing all adjustments takes effect
+    /// at once.
+    fn apply_adjustments(&mut self) {
+        self.reapply_adjustments(None);
+    }
+
+    /// Apply the current adjustments after the source image changed within `dirty`
+    ///
+    /// Without adjustments only the changed tiles are uploaded again; `None`
+    /// means the whole image may have changed.
+    fn reapply_adjustments(&mut self, dirty: Option<TileRect>) {
+        self.doc.adjustment_preview.finish();
+        self.doc.adjustment_job = None;
+        match self.doc.source_image {
+            Some(ref image) if !self.doc.adjustments.is_identity() => {
+                self.doc.adjustment_job = Some(AdjustmentJob::spawn(image.clone(), self.doc.adjustments.clone()));
+            }
+            _ => {
+                // Removing the adjustments changes every pixel
+                let dirty = dirty.filter(|_| self.doc.adjusted_image.is_none());
+                self.doc.adjusted_image = None;
+                self.doc.preview_texture = None;
+                self.doc.refresh_texture(dirty);
+            }
+        }
+        self.mark_content_changed();
+    }
+
+    /// Check whether adjustments are being applied or previewed
+    fn is_adjusting(&self) -> bool {
+        self.doc.adjustment_job.is_some() || self.doc.adjustment_preview.is_pending()
+    }
+
+    /// Track an adjustment slider interaction, previewing while dragging and
+    /// applying at full resolution when released
+    fn handle_adjustment_slider(&mut self, response: &Response) {
+        if response.changed() {
+            if response.dragged() {
+                self.doc.adjustment_preview.mark_changed(Instant::now());
+            } else {
+                // Keyboard or typed edits apply immediately
+                self.apply_adjustments();
+            }
+        }
+        if response.drag_released() {
+            self.apply_adjustments();
+        }
+    }
+
+    /// Start the low-resolution preview once the debounce delay or maximum
+    /// wait elapsed, and show previews and full-resolution results finished
+    /// on the worker thread
+    fn update_adjustment_preview(&mut self, ctx: &Context) {
+        self.doc.poll_adjustments();
+        if self.doc.adjustment_preview.should_refresh(Instant::now()) {
+            if let Some(ref image) = self.doc.source_image {
+                self.doc.adjustment_preview.start(image, &self.doc.adjustments);
+            }
+        }
+        if let Some(preview) = self.doc.adjustment_preview.poll() {
+            let started = Instant::now();
+            // Successive previews of the same size reuse the texture
+            let size = [preview.width() as usize, preview.height() as usize];
+            match self.doc.preview_texture.as_mut().filter(|texture| texture.size() == size) {
+                Some(texture) => texture.set(Self::color_image(&preview), Default::default()),
+                None => self.doc.preview_texture = Some(Self::load_texture(ctx, "adjustment_preview", &preview)),
+            }
+            self.diagnostics.record_texture_upload(started.elapsed());
+        }
+    }
+
+    /// Remove uniform borders around the image, moving the annotations with it
+    ///
+    /// Returns whether anything was trimmed. Like cropping a capture region,
+    /// trimming starts the undo history over.
+    pub fn auto_trim(&mut self) -> AppResult<bool> {
+        self.doc.restore_full_resolution()?;
+        let image = self
+            .doc
+            .source_image
+            .as_ref()
+            .ok_or_else(|| AppError::ImageProcessing("トリミングする画像がありません".to_string()))?;
+        let tolerance = self.settings.get_settings().trim.tolerance;
+        let Some(bounds) = image_ops::trim_bounds(&image.to_rgba8(), tolerance) else {
+            return Ok(false);
+        };
+        let trimmed = image.crop_imm(
+            bounds.min.x as u32,
+            bounds.min.y as u32,
+            bounds.width() as u32,
+            bounds.height() as u32,
+        );
+        log::info!(
+            "Trimmed {}x{} image to {}x{}",
+            image.width(),
+            image.height(),
+            trimmed.width(),
+            trimmed.height()
+        );
+        for annotation in &mut self.doc.annotations {
+            annotation.position -= bounds.min.to_vec2();
+        }
+        self.replace_source_image(trimmed);
+        Ok(true)
+    }
+
+    /// Replace the source image with a resized version, already moving the annotations onto it
+    ///
+    /// Adjustments are applied again, and the undo history starts over, as
+    /// its steps place annotations on the old image.
+    fn replace_source_image(&mut self, image: DynamicImage) {
+        self.doc.source_image = Some(image);
+        self.doc.adjusted_image = None;
+        self.doc.adjustment_preview.reset();
+        self.doc.preview_texture = None;
+        self.doc.texture = None;
+        self.doc.drawing_start = None;
+        self.doc.erasing_start = None;
+        self.doc.selecting_start = None;
+        self.doc.selection_region = None;
+        self.doc.measurement = None;
+        self.doc.drag_target = None;
+        self.doc.rotating = None;
+        self.doc.resizing = None;
+        self.doc.guides.clear();
+        self.doc.perspective = None;
+        self.doc.perspective_handle = None;
+        self.apply_adjustments();
+        self.doc.reset_undo();
+    }
+
+    /// Get the region dragged out with the Select tool, in image pixels
+    pub fn selection_region(&self) -> Option<Rect> {
+        self.doc.selection_region
+    }
+
+    /// Crop the image to the region dragged out with the Select tool, moving the annotations with it
+    ///
+    /// Unlike trimming, the crop is a step in the undo history, so undoing
+    /// it brings back the whole image.
+    pub fn crop_to_selection(&mut self) -> AppResult<()> {
+        let Some(region) = self.doc.selection_region.take() else {
+            return Ok(());
+        };
+        self.doc.restore_full_resolution()?;
+        let image = self
+            .doc
+            .source_image
+            .take()
+            .ok_or_else(|| AppError::ImageProcessing("切り抜く画像がありません".to_string()))?;
+        let bounds = Rect::from_min_size(Pos2::ZERO, Vec2::new(image.width() as f32, image.height() as f32));
+        let rect = Rect::from_min_max(region.min.round(), region.max.round()).intersect(bounds);
+        if rect.width() < 1.0 || rect.height() < 1.0 {
+            self.doc.source_image = Some(image);
+            return Err(AppError::ImageProcessing("選択範囲が画像の外にあります".to_string()));
+        }
+
+        // The state before the crop is a step of its own
+        let before = self.doc.undo_snapshot();
+        self.doc.undo.commit(&before);
+        let cropped = image.crop_imm(rect.min.x as u32, rect.min.y as u32, rect.width() as u32, rect.height() as u32);
+        log::info!("Cropped {}x{} image to {}x{}", image.width(), image.height(), cropped.width(), cropped.height());
+        if self.doc.image_version.is_none() {
+            self.doc.history_base = Some(ImageVersion(Arc::new(image)));
+        }
+        self.doc.image_version = Some(ImageVersion(Arc::new(cropped.clone())));
+        for annotation in &mut self.doc.annotations {
+            annotation.position -= rect.min.to_vec2();
+        }
+        self.doc.set_source_pixels(cropped);
+        self.doc.measurement = None;
+        self.doc.perspective = None;
+        self.doc.perspective_handle = None;
+        self.mark_content_changed();
+
+        let after = self.doc.undo_snapshot();
+        self.doc.undo.commit(&after);
+        self.doc.undo_revision = self.doc.content_revision;
+        Ok(())
+    }
+
+    /// Get the corners of the region the Perspective tool straightens, in image pixels
+    ///
+    /// Corners are in the order top-left, top-right, bottom-right,
+    /// bottom-left, and start at the corners of the image.
+    pub fn perspective_corners(&self) -> Option<[Pos2; 4]> {
+        let size = self.image_size()?;
+        Some(self.doc.perspective.unwrap_or([
+            Pos2::ZERO,
+            Pos2::new(size.x, 0.0),
+            size.to_pos2(),
+            Pos2::new(0.0, size.y),
+        ]))
+    }
+
+    /// Move a corner of the Perspective tool's region, keeping it on the image
+    pub fn set_perspective_corner(&mut self, index: usize, pos: Pos2) {
+        let (Some(mut corners), Some(size)) = (self.perspective_corners(), self.image_size()) else {
+            return;
+        };
+        if let Some(corner) = corners.get_mut(index) {
+            *corner = pos.clamp(Pos2::ZERO, size.to_pos2());
+            self.doc.perspective = Some(corners);
+        }
+    }
+
+    /// Put the Perspective tool's corners back on the corners of the image
+    pub fn reset_perspective(&mut self) {
+        self.doc.perspective = None;
+        self.doc.perspective_handle = None;
+    }
+
+    /// Find the Perspective tool corner whose handle is under an image-space point
+    fn perspective_handle_at(&self, point: Pos2) -> Option<usize> {
+        let reach = (PERSPECTIVE_HANDLE_RADIUS + 3.0) / self.doc.zoom_level as f32;
+        let corners = self.perspective_corners()?;
+        (0..4)
+            .filter(|&index| corners[index].distance(point) <= reach)
+            .min_by(|&a, &b| corners[a].distance(point).total_cmp(&corners[b].distance(point)))
+    }
+
+    /// Warp the region within the Perspective tool's corners into a straight rectangle
+    ///
+    /// Annotations are moved to where their position lands in the
+    /// straightened image. Like cropping a capture region, the correction
+    /// starts the undo history over.
+    pub fn correct_perspective(&mut self) -> AppResult<()> {
+        let corners = self
+            .perspective_corners()
+            .ok_or_else(|| AppError::ImageProcessing("補正する画像がありません".to_string()))?;
+        self.doc.restore_full_resolution()?;
+        let Some(image) = self.doc.source_image.as_ref() else {
+            return Ok(());
+        };
+        let warped = image_ops::warp_perspective(&image.to_rgba8(), corners)?;
+        let (width, height) = (warped.width() as f32, warped.height() as f32);
+        let rectangle = [Pos2::ZERO, Pos2::new(width, 0.0), Pos2::new(width, height), Pos2::new(0.0, height)];
+        if let Some(to_warped) = image_ops::Homography::from_points(corners, rectangle) {
+            for annotation in &mut self.doc.annotations {
+                annotation.position = to_warped.map(annotation.position);
+            }
+        }
+        log::info!("Straightened {}x{} image to {}x{}", image.width(), image.height(), width, height);
+        self.replace_source_image(DynamicImage::ImageRgba8(warped));
+        Ok(())
+    }
+
+    /// Get the annotations on the current image
+    pub fn annotations(&self) -> &[AnnotationItem] {
+        &self.doc.annotations
+    }
+
+    /// Add an annotation to the current image
+    pub fn add_annotation(&mut self, annotation: AnnotationItem) {
+        self.doc.annotations.push(annotation);
+        self.mark_content_changed();
+    }
+
+    /// Stamp an image onto the current image as a selected annotation
+    ///
+    /// The image is centered and scaled down to fit within half of the
+    /// screenshot. Returns the new annotation's id.
+    pub fn insert_image(&mut self, image: &DynamicImage) -> AppResult<Uuid> {
+        let canvas = self
+            .image_size()
+            .ok_or_else(|| AppError::ImageProcessing("画像を挿入する画像がありません".to_string()))?;
+        let data = export::encode_image(&image.to_rgba8(), &ImageFormat::Png)?;
+        let natural = Vec2::new(image.width() as f32, image.height() as f32);
+        let scale = (canvas.x * MAX_INSERTED_IMAGE_FRACTION / natural.x)
+            .min(canvas.y * MAX_INSERTED_IMAGE_FRACTION / natural.y)
+            .min(1.0);
+        let size = natural * scale;
+
+        let annotation = AnnotationItem::new_image(((canvas - size) * 0.5).to_pos2(), data, size);
+        let id = annotation.id;
+        self.add_annotation(annotation);
+        self.select_annotation(Some(id));
+        Ok(id)
+    }
+
+    /// Stamp an image file onto the current image
+    pub fn insert_image_file(&mut self, path: &Path) -> AppResult<Uuid> {
+        let image = decode::decode_untrusted_file(path, DecodeLimits::default())?;
+        self.insert_image(&image)
+    }
+
+    /// Stamp the clipboard image onto the current image
+    pub fn insert_image_from_clipboard(&mut self) -> AppResult<Uuid> {
+        let bytes = clipboard::read_image()?
+            .ok_or_else(|| AppError::Clipboard("クリップボードに画像がありません".to_string()))?;
+        let image = decode::decode_untrusted(bytes, DecodeLimits::default())?;
+        self.insert_image(&image)
+    }
+
+    /// Ask for an image file and stamp it onto the current image
+    fn insert_image_with_dialog(&mut self) {
+        let Some(path) = dialogs::pick_image_file(Some(&self.dialog_directory())) else {
+            return;
+        };
+        if let Err(e) = self.insert_image_file(&path) {
+            self.show_error("Insert Failed", format!("{}\n{}", path.display(), e));
+        }
+    }
+
+    /// Remove an annotation by id, returning it if it existed
+    pub fn remove_annotation(&mut self, id: uuid::Uuid) -> Option<AnnotationItem> {
+        let index = self.doc.annotations.iter().position(|a| a.id == id)?;
+        let removed = self.doc.annotations.remove(index);
+        self.mark_content_changed();
+        Some(removed)
+    }
+
+    /// Create a new annotation for `tool` styled with the active preset or tool default
+    pub fn create_annotation(&self, tool: &Tool, position: Pos2, size: Vec2) -> Option<AnnotationItem> {
+        let mut annotation = match tool {
+            Tool::Rectangle => AnnotationItem::new_rectangle(position, size),
+            Tool::Text => AnnotationItem::new_text(position, "Text".to_string()),
+            Tool::Sticker => AnnotationItem::new_text(position, self.sticker_symbol.clone()),
+            Tool::Measure => {
+                let pixels_per_inch = self.doc.source_screen.as_ref().map(measure::pixels_per_inch);
+                AnnotationItem::new_dimension(position, position + size, pixels_per_inch)
+            }
+            Tool::Select | Tool::Eraser | Tool::Perspective => return None,
+        };
+        self.settings.get_settings().styles.style_for_tool(tool).apply_to(&mut annotation);
+        // Stickers are centered on the click
+        if *tool == Tool::Sticker {
+            annotation.position -= annotation.bounds().size() / 2.0;
+        }
+        Some(annotation)
+    }
+
+    /// Select a single annotation by id, or clear the selection with `None`
+    pub fn select_annotation(&mut self, id: Option<Uuid>) {
+        for annotation in &mut self.doc.annotations {
+            annotation.is_selected = Some(annotation.id) == id;
+        }
+    }
+
+    /// Get the ids of all selected annotations
+    pub fn selected_annotation_ids(&self) -> Vec<Uuid> {
+        self.doc.annotations
+            .iter()
+            .filter(|a| a.is_selected)
+            .map(|a| a.id)
+            .collect()
+    }
+
+    /// Delete all selected annotations
+    pub fn delete_selected(&mut self) {
+        let before = self.doc.annotations.len();
+        self.doc.annotations.retain(|a| !a.is_selected);
+        if self.doc.annotations.len() != before {
+            self.mark_content_changed();
+        }
+    }
+
+    /// Keep the current measurement as a dimension annotation, returning whether one was added
+    ///
+    /// Measurements shorter than a dragged rectangle's minimum size are left as they are.
+    pub fn keep_measurement(&mut self) -> bool {
+        let Some(dimension) = self.measurement_dimension() else {
+            return false;
+        };
+        let Some([start, end]) = dimension.measured_line() else {
+            return false;
+        };
+        if start.distance(end) < MIN_RECTANGLE_SIZE {
+            return false;
+        }
+        self.doc.measurement = None;
+        self.add_annotation(dimension);
+        true
+    }
+
+    /// Delete annotations by id, returning how many were deleted
+    pub fn erase(&mut self, ids: &[Uuid]) -> usize {
+        let before = self.doc.annotations.len();
+        self.doc.annotations.retain(|a| !ids.contains(&a.id));
+        let erased = before - self.doc.annotations.len();
+        if erased > 0 {
+            self.mark_content_changed();
+        }
+        erased
+    }
+
+    /// Delete every annotation touching an image-space rectangle, returning how many were deleted
+    pub fn erase_in(&mut self, rect: Rect) -> usize {
+        let ids: Vec<Uuid> = self.doc.annotations.iter().filter(|a| a.intersects(rect)).map(|a| a.id).collect();
+        self.erase(&ids)
+    }
+
+    /// Copy the selected annotations to the annotation clipboard
+    ///
+    /// Returns whether anything was selected.
+    pub fn copy_annotations(&mut self) -> AppResult<bool> {
+        let selected: Vec<&AnnotationItem> = self.doc.annotations.iter().filter(|a| a.is_selected).collect();
+        if selected.is_empty() {
+            return Ok(false);
+        }
+        let json = serde_json::to_string(&selected)
+            .map_err(|e| AppError::Clipboard(format!("注釈のコピーに失敗しました: {}", e)))?;
+        self.annotation_clipboard = Some(json);
+        self.paste_count = 0;
+        Ok(true)
+    }
+
+    /// Check whether annotations were copied
+    pub fn has_copied_annotations(&self) -> bool {
+        self.annotation_clipboard.is_some()
+    }
+
+    /// Paste copied annotations onto the current image, offset from the originals
+    ///
+    /// Returns whether annotations were pasted.
+    pub fn paste_annotations(&mut self) -> AppResult<bool> {
+        let Some(json) = self.annotation_clipboard.as_deref().filter(|_| !self.doc.is_empty()) else {
+            return Ok(false);
+        };
+        let copied: Vec<AnnotationItem> = serde_json::from_str(json)
+            .map_err(|e| AppError::Clipboard(format!("注釈の貼り付けに失敗しました: {}", e)))?;
+        self.paste_count += 1;
+        self.insert_copies(&copied, PASTE_OFFSET * self.paste_count as f32);
+        Ok(true)
+    }
+
+    /// Duplicate the selected annotations next to the originals
+    pub fn duplicate_selected(&mut self) -> bool {
+        let selected: Vec<AnnotationItem> = self.doc.annotations.iter().filter(|a| a.is_selected).cloned().collect();
+        if selected.is_empty() {
+            return false;
+        }
+        self.insert_copies(&selected, PASTE_OFFSET);
+        true
+    }
+
+    /// Add an annotation with the current tool in the middle of the view and select it
+    ///
+    /// This is the keyboard alternative to drawing. Returns the new
+    /// annotation's id, or `None` with the Select tool or no image.
+    pub fn place_annotation(&mut self) -> Option<Uuid> {
+        let image_size = self.image_size()?;
+        let center = (image_size * 0.5 - self.doc.pan_offset / self.doc.zoom_level as f32)
+            .clamp(Vec2::ZERO, image_size)
+            .to_pos2();
+        let size = PLACED_RECTANGLE_SIZE.min(image_size);
+        let position = match self.current_tool {
+            Tool::Rectangle => (center - size * 0.5).clamp(Pos2::ZERO, (image_size - size).to_pos2()),
+            _ => center,
+        };
+        let annotation = self.create_annotation(&self.current_tool, position, size)?;
+        let id = annotation.id;
+        self.add_annotation(annotation);
+        self.select_annotation(Some(id));
+        Some(id)
+    }
+
+    /// Select the annotation after the selected one, or the first if none is selected
+    ///
+    /// Returns the newly selected annotation's id.
+    pub fn select_next_annotation(&mut self) -> Option<Uuid> {
+        let annotations = &self.doc.annotations;
+        let next = match annotations.iter().rposition(|a| a.is_selected) {
+            Some(index) => annotations.get((index + 1) % annotations.len()),
+            None => annotations.first(),
+        }
+        .map(|a| a.id);
+        self.select_annotation(next);
+        next
+    }
+
+    /// Tag the selected annotations with the step after the highest one used
+    ///
+    /// Returns the step, or `None` if nothing is selected.
+    pub fn assign_next_step(&mut self) -> Option<u32> {
+        if !self.doc.annotations.iter().any(|a| a.is_selected) {
+            return None;
+        }
+        let highest = animation::steps(&self.doc.annotations).last().copied().unwrap_or(0);
+        let step = (highest + 1).min(animation::MAX_STEP);
+        for annotation in self.doc.annotations.iter_mut().filter(|a| a.is_selected) {
+            annotation.step = Some(step);
+        }
+        self.mark_content_changed();
+        Some(step)
+    }
+
+    /// Add copies of annotations with new ids and select only the copies
+    fn insert_copies(&mut self, annotations: &[AnnotationItem], offset: Vec2) {
+        self.select_annotation(None);
+        for annotation in annotations {
+            let mut copy = annotation.duplicate(offset);
+            copy.is_selected = true;
+            self.doc.annotations.push(copy);
+        }
+        self.mark_content_changed();
+    }
+
+    /// Move the selected annotations by `delta` image pixels
+    ///
+    /// Returns whether anything was selected.
+    pub fn nudge_selected(&mut self, delta: Vec2) -> bool {
+        let mut moved = false;
+        for annotation in self.doc.annotations.iter_mut().filter(|a| a.is_selected) {
+            annotation.position += delta;
+            moved = true;
+        }
+        if moved {
+            self.mark_content_changed();
+        }
+        moved
+    }
+
+    /// Find the topmost annotation containing an image-space point
+    fn annotation_at(&self, point: Pos2) -> Option<Uuid> {
+        self.doc.annotations
+            .iter()
+            .rev()
+            .find(|a| a.contains_point(point))
+            .map(|a| a.id)
+    }
+
+    /// Copy the style of the first selected annotation
+    pub fn copy_style(&mut self) -> bool {
+        let base = self.settings.get_settings().styles.style_for_tool(&self.current_tool);
+        match self.doc.annotations.iter().find(|a| a.is_selected) {
+            Some(annotation) => {
+                self.copied_style = Some(AnnotationStyle::from_annotation(annotation, &base));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Paste the copied style onto all selected annotations
+    pub fn paste_style(&mut self) -> bool {
+        let Some(style) = self.copied_style.clone() else {
+            return false;
+        };
+        let mut changed = false;
+        for annotation in self.doc.annotations.iter_mut().filter(|a| a.is_selected) {
+            style.apply_to(annotation);
+            changed = true;
+        }
+        if changed {
+            self.mark_content_changed();
+        }
+        changed
+    }
+
+    /// Record that the image or annotations changed, invalidating cached composites
+    pub fn mark_content_changed(&mut self) {
+        self.doc.content_revision = self.doc.content_revision.wrapping_add(1);
+    }
+
+    /// Record finished annotation edits in the undo history
+    ///
+    /// Edits are committed once no mouse button is held and no text field
+    /// has keyboard focus, so a drag, a slider movement, or typed text
+    /// becomes a single step.
+    fn commit_undo_step(&mut self, ctx: &Context) {
+        if self.doc.undo_revision == self.doc.content_revision
+            || ctx.input(|i| i.pointer.any_down())
+            || ctx.wants_keyboard_input()
+        {
+            return;
+        }
+        let snapshot = self.doc.undo_snapshot();
+        self.doc.undo.commit(&snapshot);
+        self.doc.undo_revision = self.doc.content_revision;
+    }
+
+    /// Undo the last annotation or adjustment edit, returning whether there was one
+    pub fn undo(&mut self) -> bool {
+        let current = self.doc.undo_snapshot();
+        match self.doc.undo.undo(&current) {
+            Some(snapshot) => {
+                self.restore_snapshot(snapshot);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Redo the last undone annotation or adjustment edit, returning whether there was one
+    pub fn redo(&mut self) -> bool {
+        let current = self.doc.undo_snapshot();
+        match self.doc.undo.redo(&current) {
+            Some(snapshot) => {
+                self.restore_snapshot(snapshot);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Replace the annotations, adjustments, and image with a state from the undo history
+    ///
+    /// Annotations that are still there stay selected, and any drag in
+    /// progress is dropped.
+    fn restore_snapshot(&mut self, snapshot: EditSnapshot) {
+        let EditSnapshot { mut annotations, adjustments, image } = snapshot;
+        if image != self.doc.image_version {
+            if let Some(version) = image.as_ref().or(self.doc.history_base.as_ref()) {
+                self.doc.full_resolution = None;
+                self.doc.keep_in_memory = true;
+                self.doc.set_source_pixels(version.0.as_ref().clone());
+            }
+            self.doc.image_version = image;
+            self.doc.selection_region = None;
+            self.doc.measurement = None;
+            self.doc.perspective = None;
+        }
+        if adjustments != self.doc.adjustments {
+            self.set_adjustments(adjustments);
+        }
+        for annotation in &mut annotations {
+            annotation.is_selected = self.doc.annotations.iter().any(|a| a.id == annotation.id && a.is_selected);
+        }
+        self.doc.annotations = annotations;
+        self.doc.drawing_start = None;
+        self.doc.erasing_start = None;
+        self.doc.selecting_start = None;
+        self.doc.drag_target = None;
+        self.doc.rotating = None;
+        self.doc.resizing = None;
+        self.doc.guides.clear();
+        self.mark_content_changed();
+        self.doc.undo_revision = self.doc.content_revision;
+    }
+
+    /// Get the flattened image with all annotations applied
+    ///
+    /// The composite is cached and reused until the image or annotations change,
+    /// so repeated copy/save operations don't redo the rasterization.
+    pub fn flattened_image(&mut self) -> Option<Arc<RgbaImage>> {
+        self.doc.composite()
+    }
+
+    /// Arrange tabs and saved captures in a collage and open it in a new tab
+    ///
+    /// Tabs are included with their annotations, in tab order, followed by
+    /// the saved captures. Each is captioned with its file name.
+    pub fn create_collage(&mut self, tabs: &[usize], captures: &[PathBuf]) -> AppResult<()> {
+        let mut images = Vec::new();
+        for index in 0..self.tabs.len() {
+            if !tabs.contains(&index) {
+                continue;
+            }
+            let document = if index == self.active_tab { &mut self.doc } else { &mut self.tabs[index] };
+            if let Some(image) = document.composite() {
+                images.push((image.as_ref().clone(), document.title()));
+            }
+        }
+        for path in captures {
+            let image = decode::decode_untrusted_file(path, DecodeLimits::default())?;
+            let name = path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| path.display().to_string());
+            images.push((image.to_rgba8(), name));
+        }
+
+        let collage = render::collage(&images, &self.settings.get_settings().export.collage)?;
+        log::info!("Created a collage of {} images", images.len());
+        self.load_image(DynamicImage::ImageRgba8(collage))
+    }
+
+    /// Decode an untrusted image (dropped file, clipboard, etc.) and load it
+    pub fn load_untrusted_bytes(&mut self, bytes: Vec<u8>) -> AppResult<()> {
+        let image = decode::decode_untrusted(bytes, DecodeLimits::default())?;
+        self.load_image(image)
+    }
+
+    /// Open the image on the clipboard
+    pub fn paste_from_clipboard(&mut self) -> AppResult<()> {
+        let bytes = clipboard::read_image()?
+            .ok_or_else(|| AppError::Clipboard("クリップボードに画像がありません".to_string()))?;
+        self.load_untrusted_bytes(bytes)
+    }
+
+    /// Load the first file dropped onto the window
+    fn handle_dropped_files(&mut self, ctx: &Context) {
+        let Some(file) = ctx.input(|i| i.raw.dropped_files.first().cloned()) else {
+            return;
+        };
+
+        let result = match (file.bytes, file.path) {
+            (Some(bytes), _) => self.load_untrusted_bytes(bytes.to_vec()),
+            (None, Some(path)) => self.open_file(&path),
+            (None, None) => return,
+        };
+        if let Err(e) = result {
+            self.notify_error("Open Failed", &e.context(format!("{} を開けませんでした", file.name)));
+        }
+    }
+
+    /// Open an image file and remember it as the save target
+    pub fn open_file(&mut self, path: &Path) -> AppResult<()> {
+        let image = decode::decode_untrusted_file(path, DecodeLimits::default())?;
+        self.load_image(image)?;
+        // The file's modification time is the best record of when it was captured
+        if let Ok(modified) = std::fs::metadata(path).and_then(|metadata| metadata.modified()) {
+            self.doc.captured_at = modified;
+        }
+        log::info!("Opened image {}", path.display());
+        self.doc.file_path = Some(path.to_path_buf());
+        self.doc.mark_saved();
+        self.remember_recent_file(path);
+        Ok(())
+    }
+
+    /// Save the annotations to a project file next to the saved image
+    pub fn save_project(&mut self) -> AppResult<PathBuf> {
+        let image_path = self
+            .doc
+            .file_path
+            .clone()
+            .ok_or_else(|| AppError::Project("プロジェクトを保存する前に画像を保存してください".to_string()))?;
+        let image_size = self
+            .doc
+            .image_dimensions()
+            .map(|(width, height)| [width, height])
+            .unwrap_or_default();
+        let path = project::project_path_for(&image_path);
+        let project = ProjectFile {
+            adjustments: self.doc.adjustments.clone(),
+            ..ProjectFile::new(image_path, image_size, self.doc.annotations.clone())
+        };
+        project.save_to(&path)?;
+        log::info!("Saved project to {}", path.display());
+        Ok(path)
+    }
+
+    /// Open a project file with its image and annotations
+    pub fn open_project(&mut self, path: &Path) -> AppResult<()> {
+        let project = ProjectFile::load_from(path)?;
+        self.open_file(&project.resolve_image_path(path))?;
+        self.doc.annotations = project.annotations;
+        self.set_adjustments(project.adjustments);
+        self.doc.mark_saved();
+        self.doc.reset_undo();
+        log::info!("Opened project {}", path.display());
+        Ok(())
+    }
+
+    /// Open the new revision of a project and highlight its changes from the old revision
+    pub fn compare_projects(&mut self, old_path: &Path, new_path: &Path) -> AppResult<()> {
+        let old = ProjectFile::load_from(old_path)?;
+        self.open_project(new_path)?;
+        if self.doc.image_dimensions().map(|(width, height)| [width, height]) != Some(old.image_size) {
+            log::warn!("Compared projects have different image sizes");
+        }
+        self.doc.project_diff = Some(project::diff_annotations(&old.annotations, &self.doc.annotations));
+        Ok(())
+    }
+
+    /// Get the annotation changes highlighted after comparing projects
+    pub fn project_diff(&self) -> Option<&AnnotationDiff> {
+        self.doc.project_diff.as_ref()
+    }
+
+    /// Compare the current image against a reference image and highlight the differences
+    pub fn compare_with_image(&mut self, reference: RgbaImage, reference_name: String) -> AppResult<&DiffResult> {
+        self.doc.wait_for_adjustments();
+        let image = self
+            .doc
+            .full_display_image()?
+            .ok_or_else(|| AppError::ImageProcessing("比較する画像がありません".to_string()))?
+            .to_rgba8();
+        let result = diff::compare(&image, &reference, diff::DEFAULT_THRESHOLD);
+        log::info!("Compared with {}: {}", reference_name, result.summary());
+        let comparison = self.doc.image_comparison.insert(ImageComparison {
+            reference,
+            reference_name,
+            threshold: diff::DEFAULT_THRESHOLD,
+            result,
+            show_heatmap: true,
+            heatmap_texture: None,
+        });
+        Ok(&comparison.result)
+    }
+
+    /// Compare the current image against an image file
+    pub fn compare_with_file(&mut self, path: &Path) -> AppResult<&DiffResult> {
+        let reference = decode::decode_untrusted_file(path, DecodeLimits::default())?;
+        let name = path.file_name().map_or_else(|| path.display().to_string(), |name| name.to_string_lossy().into_owned());
+        self.compare_with_image(reference.to_rgba8(), name)
+    }
+
+    /// Compare the current image against the image in another tab
+    pub fn compare_with_tab(&mut self, index: usize) -> AppResult<&DiffResult> {
+        if index == self.active_tab || index >= self.tabs.len() {
+            return Err(AppError::ImageProcessing("比較するタブが見つかりません".to_string()));
+        }
+        let tab = &mut self.tabs[index];
+        tab.wait_for_adjustments();
+        let reference = tab
+            .full_display_image()?
+            .ok_or_else(|| AppError::ImageProcessing("比較するタブに画像がありません".to_string()))?
+            .to_rgba8();
+        let name = tab.title();
+        self.compare_with_image(reference, name)
+    }
+
+    /// Get the differences from the compared reference image
+    pub fn image_comparison(&self) -> Option<&DiffResult> {
+        self.doc.image_comparison.as_ref().map(|comparison| &comparison.result)
+    }
+
+    /// Recompare against the reference image with a new difference threshold
+    pub fn set_comparison_threshold(&mut self, threshold: u8) {
+        self.doc.wait_for_adjustments();
+        let image = match self.doc.full_display_image() {
+            Ok(Some(image)) => image.to_rgba8(),
+            Ok(None) => return,
+            Err(e) => {
+                self.notify_error("Comparison Failed", &e);
+                return;
+            }
+        };
+        if let Some(comparison) = self.doc.image_comparison.as_mut().filter(|c| c.threshold != threshold) {
+            comparison.threshold = threshold;
+            comparison.result = diff::compare(&image, &comparison.reference, threshold);
+            comparison.heatmap_texture = None;
+        }
+    }
+
+    /// Stop highlighting differences from the reference image
+    pub fn clear_image_comparison(&mut self) {
+        self.doc.image_comparison = None;
+    }
+
+    /// Ask for a reference image file and compare the current image against it
+    fn compare_with_file_dialog(&mut self) {
+        let Some(path) = dialogs::pick_image_file(Some(&self.dialog_directory())) else {
+            return;
+        };
+        if let Err(e) = self.compare_with_file(&path) {
+            self.show_error("Compare Failed", format!("{}\n{}", path.display(), e));
+        }
+    }
+
+    /// Add annotations converted from another tool's file to the current image
+    pub fn import_annotations(&mut self, path: &Path) -> AppResult<ImportResult> {
+        if self.doc.source_image.is_none() {
+            return Err(AppError::Project("注釈を読み込む画像がありません".to_string()));
+        }
+        let result = import::import_file(path)?;
+        self.doc.annotations.extend(result.annotations.iter().cloned());
+        self.mark_content_changed();
+        log::info!(
+            "Imported {} annotations from {} ({} skipped)",
+            result.annotations.len(),
+            path.display(),
+            result.skipped
+        );
+        Ok(result)
+    }
+
+    /// Import annotations, telling the user about failures and skipped shapes
+    fn import_and_report(&mut self, path: &Path) {
+        match self.import_annotations(path) {
+            Ok(result) if result.skipped > 0 => self.show_error(
+                "Import Incomplete",
+                format!(
+                    "Imported {} annotations. {} shapes have no equivalent annotation type and were skipped.",
+                    result.annotations.len(),
+                    result.skipped
+                ),
+            ),
+            Ok(_) => {}
+            Err(e) => self.show_error("Import Failed", format!("{}\n{}", path.display(), e)),
+        }
+    }
+
+    /// Ask for a ShareX or SVG file and import its annotations
+    fn import_with_dialog(&mut self) {
+        if !dialogs::has_native_dialog() {
+            self.show_open_dialog = true;
+            return;
+        }
+
+        if let Some(path) = dialogs::pick_file("Annotations", &import::IMPORT_EXTENSIONS, Some(&self.dialog_directory())) {
+            self.import_and_report(&path);
+        }
+    }
+
+    /// Open an image or project file, or import annotations, showing an error dialog on failure
+    fn open_and_report(&mut self, path: &Path) {
+        if secure::is_secure_file(path) {
+            // Encrypted captures need their password first
+            self.encrypted_open = Some((path.display().to_string(), String::new()));
+            return;
+        }
+        if import::is_importable(path) {
+            self.import_and_report(path);
+            return;
+        }
+        let is_project = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case(PROJECT_EXTENSION));
+        let result = if is_project { self.open_project(path) } else { self.open_file(path) };
+        if let Err(e) = result {
+            self.show_error("Open Failed", format!("{}\n{}", path.display(), e));
+        }
+    }
+
+    /// Ask for an image file and open it
+    fn open_with_dialog(&mut self) {
+        if !dialogs::has_native_dialog() {
+            self.show_open_dialog = true;
+            return;
+        }
+
+        if let Some(path) = dialogs::pick_image_file(Some(&self.dialog_directory())) {
+            self.open_and_report(&path);
+        }
+    }
+
+    /// Ask for a project file and open it
+    fn open_project_with_dialog(&mut self) {
+        if !dialogs::has_native_dialog() {
+            self.show_open_dialog = true;
+            return;
+        }
+
+        if let Some(path) = dialogs::pick_file("Projects", &[PROJECT_EXTENSION], Some(&self.dialog_directory())) {
+            self.open_and_report(&path);
+        }
+    }
+
+    /// Get the folder file dialogs start in
+    fn dialog_directory(&self) -> PathBuf {
+        self.doc.file_path
+            .as_deref()
+            .and_then(Path::parent)
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| self.settings.get_settings().save_directory())
+    }
+
+    /// Draw the window for comparing two revisions of a project
+    fn draw_compare_projects(&mut self, ctx: &Context) {
+        if !self.show_compare_projects {
+            return;
+        }
+
+        let initial_dir = self.dialog_directory();
+        let mut open = true;
+        let mut compare = false;
+        egui::Window::new("Compare Projects")
+            .open(&mut open)
+            .resizable(true)
+            .show(ctx, |ui| {
+                let (old_input, new_input) = &mut self.compare_inputs;
+                for (label, input) in [("Old:", old_input), ("New:", new_input)] {
+                    ui.horizontal(|ui| {
+                        ui.label(label);
+                        ui.text_edit_singleline(input);
+                        if dialogs::has_native_dialog() && ui.button("Browse...").clicked() {
+                            if let Some(path) = dialogs::pick_file("Projects", &[PROJECT_EXTENSION], Some(&initial_dir)) {
+                                *input = path.display().to_string();
+                            }
+                        }
+                    });
+                }
+                let ready = !self.compare_inputs.0.trim().is_empty() && !self.compare_inputs.1.trim().is_empty();
+                compare = ui.add_enabled(ready, egui::Button::new("Compare")).clicked();
+
+                let Some(diff) = &self.doc.project_diff else {
+                    return;
+                };
+                ui.separator();
+                if diff.is_empty() {
+                    ui.label(format!("No changes ({} annotations)", diff.unchanged));
+                    return;
+                }
+                ui.label(format!(
+                    "{} added, {} removed, {} changed, {} unchanged",
+                    diff.added.len(),
+                    diff.removed.len(),
+                    diff.changed.len(),
+                    diff.unchanged
+                ));
+                egui::ScrollArea::vertical().max_height(240.0).show(ui, |ui| {
+                    for annotation in &diff.added {
+                        ui.colored_label(DIFF_ADDED_COLOR, format!("+ {}", describe_annotation(annotation)));
+                    }
+                    for annotation in &diff.removed {
+                        ui.colored_label(DIFF_REMOVED_COLOR, format!("- {}", describe_annotation(annotation)));
+                    }
+                    for (old, new) in &diff.changed {
+                        ui.colored_label(
+                            DIFF_CHANGED_COLOR,
+                            format!("~ {} -> {}", describe_annotation(old), describe_annotation(new)),
+                        );
+                    }
+                });
+            });
+
+        if compare {
+            let old_path = PathBuf::from(self.compare_inputs.0.trim());
+            let new_path = PathBuf::from(self.compare_inputs.1.trim());
+            if let Err(e) = self.compare_projects(&old_path, &new_path) {
+                self.show_error("Compare Failed", e.to_string());
+            }
+        }
+        if !open {
+            self.doc.project_diff = None;
+        }
+        self.show_compare_projects = open;
+    }
+
+    /// Draw the summary and controls of the image comparison
+    fn draw_image_comparison(&mut self, ctx: &Context) {
+        let Some(comparison) = self.doc.image_comparison.as_mut() else {
+            return;
+        };
+
+        let mut open = true;
+        let mut threshold = comparison.threshold;
+        egui::Window::new("Image Comparison")
+            .open(&mut open)
+            .resizable(true)
+            .show(ctx, |ui| {
+                ui.label(format!("Reference: {}", comparison.reference_name));
+                ui.label(comparison.result.summary());
+                ui.add(egui::Slider::new(&mut threshold, 0..=255).text("Threshold"));
+                ui.checkbox(&mut comparison.show_heatmap, "Show heatmap");
+                if comparison.result.regions.is_empty() {
+                    return;
+                }
+                ui.separator();
+                egui::ScrollArea::vertical().max_height(240.0).show(ui, |ui| {
+                    for region in &comparison.result.regions {
+                        ui.label(format!(
+                            "{:.0}x{:.0} at ({:.0}, {:.0})",
+                            region.width(),
+                            region.height(),
+                            region.min.x,
+                            region.min.y
+                        ));
+                    }
+                });
+            });
+
+        self.set_comparison_threshold(threshold);
+        if !open {
+            self.clear_image_comparison();
+        }
+    }
+
+    /// Draw the path entry window used to open files without a native dialog
+    fn draw_open_dialog(&mut self, ctx: &Context) {
+        if !self.show_open_dialog {
+            return;
+        }
+
+        let mut open = true;
+        let mut submitted = false;
+        egui::Window::new("Open Image")
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "Supported formats: {}, {}",
+                    dialogs::OPEN_IMAGE_EXTENSIONS.join(", "),
+                    PROJECT_EXTENSION
+                ));
+                ui.weak(format!(
+                    "Annotations ({}) are imported onto the current image",
+                    import::IMPORT_EXTENSIONS.join(", ")
+                ));
+                let response = ui.text_edit_singleline(&mut self.open_path_input);
+                submitted = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                if ui.button("Open").clicked() {
+                    submitted = true;
+                }
+            });
+
+        if submitted && !self.open_path_input.trim().is_empty() {
+            let path = PathBuf::from(self.open_path_input.trim());
+            self.open_and_report(&path);
+            open = false;
+        }
+        self.show_open_dialog = open;
+    }
+
+    /// Add a file to the persisted recent files list
+    fn remember_recent_file(&mut self, path: &Path) {
+        let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        self.settings.settings_mut().add_recent_file(&path);
+        self.settings_dirty = true;
+    }
+
+    /// Get the flattened image with export options (e.g. resizing) applied
+    pub fn export_image(&mut self) -> Option<RgbaImage> {
+        let composite = self.flattened_image()?;
+        Some(export::prepare_export(&composite, &self.settings.get_settings().export, self.doc.captured_at))
+    }
+
+    /// Export a copy of the current image with the given options, returning its size
+    ///
+    /// Unlike saving, the document keeps its save target. The format is
+    /// taken from the file extension, falling back to the default format.
+    pub fn export_to(&mut self, path: &Path, options: &ExportOptions) -> AppResult<(u32, u32)> {
+        options.validate()?;
+        let format = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(ImageFormat::from_extension)
+            .unwrap_or_else(|| self.settings.get_settings().default_image_format.clone());
+        let composite = self
+            .flattened_image()
+            .ok_or_else(|| AppError::ImageProcessing("書き出す画像がありません".to_string()))?;
+        let image = export::prepare_export(&composite, options, self.doc.captured_at);
+        let data = export::encode_export(&image, &format, options, &self.image_metadata())?;
+        crate::files::write_atomic(path, &data)?;
+        log::info!("Exported image to {}", path.display());
+        Ok(image.dimensions())
+    }
+
+    /// Export the annotation steps of the current image as an animation, returning the frame count
+    ///
+    /// Each frame gets the same export options as a still export.
+    pub fn export_step_animation(&mut self, path: &Path, options: &ExportOptions) -> AppResult<usize> {
+        options.validate()?;
+        let image = self
+            .doc
+            .full_source_image()?
+            .ok_or_else(|| AppError::ImageProcessing("書き出す画像がありません".to_string()))?;
+        if animation::steps(&self.doc.annotations).is_empty() {
+            return Err(AppError::ImageProcessing("ステップが設定された注釈がありません".to_string()));
+        }
+        let frames: Vec<RgbaImage> = animation::step_frames(&image, &self.doc.annotations)
+            .iter()
+            .map(|frame| export::prepare_export(frame, options, self.doc.captured_at))
+            .collect();
+        let data = animation::encode(&frames, &options.animation)?;
+        crate::files::write_atomic(path, &data)?;
+        log::info!("Exported {} step animation frames to {}", frames.len(), path.display());
+        Ok(frames.len())
+    }
+
+    /// Export the step animation next to the saved image, or to the save directory
+    pub fn export_step_animation_file(&mut self) -> AppResult<PathBuf> {
+        let options = self.settings.get_settings().export.clone();
+        let path = self.export_file_path("steps", options.animation.format.extension())?;
+        self.export_step_animation(&path, &options)?;
+        Ok(path)
+    }
+
+    /// Export the annotations as editable SVG elements over the screenshot
+    ///
+    /// Export options other than `svg` don't apply; the screenshot is
+    /// included at full size, as edited, without annotations. When it isn't
+    /// embedded it is written as `<name>-screenshot.png` next to the SVG.
+    pub fn export_svg(&mut self, path: &Path, options: &SvgOptions) -> AppResult<()> {
+        self.doc.wait_for_adjustments();
+        let image = self
+            .doc
+            .full_display_image()?
+            .ok_or_else(|| AppError::ImageProcessing("書き出す画像がありません".to_string()))?;
+        let png = export::encode_image(&image.to_rgba8(), &ImageFormat::Png)?;
+        let svg = if options.embed_image {
+            svg::to_svg(image.width(), image.height(), &self.doc.annotations, SvgBackground::Embedded(&png))
+        } else {
+            let stem = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+            let file_name = format!("{}-screenshot.png", stem);
+            crate::files::write_atomic(&path.with_file_name(&file_name), &png)?;
+            svg::to_svg(image.width(), image.height(), &self.doc.annotations, SvgBackground::Linked(&file_name))
+        };
+        crate::files::write_atomic(path, svg.as_bytes())?;
+        log::info!("Exported {} annotations as SVG to {}", self.doc.annotations.len(), path.display());
+        Ok(())
+    }
+
+    /// Export the SVG next to the saved image, or to the save directory
+    pub fn export_svg_file(&mut self) -> AppResult<PathBuf> {
+        let options = self.settings.get_settings().export.svg;
+        let path = self.export_file_path("annotations", "svg")?;
+        self.export_svg(&path, &options)?;
+        Ok(path)
+    }
+
+    /// Export the current image into a password-protected container
+    ///
+    /// The image inside is encoded like a save, in the default image format.
+    pub fn export_encrypted(&mut self, path: &Path, password: &str) -> AppResult<()> {
+        let format = self.settings.get_settings().default_image_format.clone();
+        let options = self.settings.get_settings().export.clone();
+        let image = self
+            .export_image()
+            .ok_or_else(|| AppError::ImageProcessing("書き出す画像がありません".to_string()))?;
+        let data = export::encode_export(&image, &format, &options, &self.image_metadata())?;
+        let stem = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+        let container = secure::encrypt(&data, &format!("{}.{}", stem, format.extension()), password, options.encryption)?;
+        crate::files::write_atomic(path, &container)?;
+        log::info!("Exported encrypted capture to {}", path.display());
+        Ok(())
+    }
+
+    /// Export the encrypted container next to the saved image, or to the save directory
+    pub fn export_encrypted_file(&mut self, password: &str) -> AppResult<PathBuf> {
+        let extension = self.settings.get_settings().export.encryption.extension();
+        let path = self.export_file_path("encrypted", extension)?;
+        self.export_encrypted(&path, password)?;
+        Ok(path)
+    }
+
+    /// Open the image in a password-protected container in a new tab
+    ///
+    /// The tab has no file to save to, so saving never writes the image
+    /// unencrypted over the container.
+    pub fn open_encrypted(&mut self, path: &Path, password: &str) -> AppResult<()> {
+        let container = std::fs::read(crate::files::extended_length_path(path))
+            .context(format!("{} を読み込めません", path.display()))?;
+        let file = secure::decrypt(&container, password)?;
+        let image = decode::decode_untrusted(file.data, DecodeLimits::default())?;
+        self.load_image(image)?;
+        if let Ok(modified) = std::fs::metadata(path).and_then(|metadata| metadata.modified()) {
+            self.doc.captured_at = modified;
+        }
+        log::info!("Opened encrypted capture {}", path.display());
+        Ok(())
+    }
+
+    /// Draw the window asking for the password of an encrypted export
+    fn draw_encrypted_export(&mut self, ctx: &Context) {
+        let format = self.settings.get_settings().export.encryption;
+        let Some((password, confirmation)) = self.encrypted_export.as_mut() else {
+            return;
+        };
+
+        let mut open = true;
+        let mut export = false;
+        egui::Window::new("Export Encrypted")
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.label(format!("Container: {}", format)).on_hover_text("Change the container in Settings > Export Options");
+                egui::Grid::new("encrypted_export_grid").num_columns(2).show(ui, |ui| {
+                    ui.label("Password");
+                    ui.add(egui::TextEdit::singleline(password).password(true));
+                    ui.end_row();
+                    ui.label("Confirm");
+                    ui.add(egui::TextEdit::singleline(confirmation).password(true));
+                    ui.end_row();
+                });
+                let problem = match secure::validate_password(password) {
+                    Err(e) => Some(e.to_string()),
+                    Ok(()) if password != confirmation => Some("The passwords don't match".to_string()),
+                    Ok(()) => None,
+                };
+                if let Some(problem) = &problem {
+                    ui.colored_label(ui.visuals().error_fg_color, problem);
+                }
+                ui.weak("The image can't be recovered without the password");
+                export = ui.add_enabled(problem.is_none(), egui::Button::new("Export")).clicked();
+            });
+
+        if export {
+            let password = password.clone();
+            self.encrypted_export = None;
+            match self.export_encrypted_file(&password) {
+                Ok(path) => self.notifications.push(Severity::Info, "Encrypted Export", path.display().to_string()),
+                Err(e) => {
+                    self.notify_error("Export Failed", &e);
+                    0
+                }
+            };
+        } else if !open {
+            self.encrypted_export = None;
+        }
+    }
+
+    /// Draw the window opening an encrypted capture with its password
+    fn draw_encrypted_open(&mut self, ctx: &Context) {
+        let initial_dir = self.dialog_directory();
+        let Some((path, password)) = self.encrypted_open.as_mut() else {
+            return;
+        };
+
+        let mut open = true;
+        let mut submitted = false;
+        egui::Window::new("Open Encrypted Capture")
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                egui::Grid::new("encrypted_open_grid").num_columns(2).show(ui, |ui| {
+                    ui.label("File");
+                    ui.horizontal(|ui| {
+                        ui.text_edit_singleline(path);
+                        if dialogs::has_native_dialog() && ui.button("Browse...").clicked() {
+                            let extensions = SecureFormat::all().map(|format| format.extension());
+                            if let Some(picked) = dialogs::pick_file("Encrypted captures", &extensions, Some(&initial_dir)) {
+                                *path = picked.display().to_string();
+                            }
+                        }
+                    });
+                    ui.end_row();
+                    ui.label("Password");
+                    let response = ui.add(egui::TextEdit::singleline(password).password(true));
+                    submitted = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                    ui.end_row();
+                });
+                let ready = !path.trim().is_empty() && !password.is_empty();
+                submitted |= ui.add_enabled(ready, egui::Button::new("Open")).clicked();
+            });
+
+        if submitted && !path.trim().is_empty() {
+            let path = PathBuf::from(path.trim());
+            let password = std::mem::take(password);
+            match self.open_encrypted(&path, &password) {
+                Ok(()) => self.encrypted_open = None,
+                // Stay open to try another password
+                Err(e) => self.show_error("Open Failed", format!("{}\n{}", path.display(), e)),
+            }
+        } else if !open {
+            self.encrypted_open = None;
+        }
+    }
+
+    /// Pick an unused path for an export in another format
+    ///
+    /// Saved images are exported next to the file, named after it with
+    /// `suffix`; unsaved ones go to the save directory, named by the file
+    /// naming template.
+    fn export_file_path(&mut self, suffix: &str, extension: &str) -> AppResult<PathBuf> {
+        let (directory, name) = match &self.doc.file_path {
+            Some(path) => (
+                path.parent().map(Path::to_path_buf).unwrap_or_default(),
+                path.file_stem().map(|stem| format!("{}-{}", stem.to_string_lossy(), suffix)).unwrap_or_default(),
+            ),
+            None => {
+                let template = self.settings.get_settings().file_naming.clone();
+                let window = self.captured_window();
+                (self.settings.get_settings().save_directory(), self.numbered_file_name(&template, &window)?)
+            }
+        };
+        std::fs::create_dir_all(crate::files::extended_length_path(&directory))
+            .context(format!("保存先フォルダ {} を作成できません", directory.display()))?;
+        Ok(naming::unique_path(&directory, &name, extension))
+    }
+
+    /// Name a new file with a template at the current time, using up the next `{counter}` number
+    ///
+    /// A counter that can't be saved still advances for this session.
+    fn numbered_file_name(&mut self, template: &FileNameTemplate, window: &ForegroundWindow) -> AppResult<String> {
+        let now = DateTime::now_local();
+        let counter = self.file_counters.peek(template, &now);
+        let name = template.render_numbered(&now, &naming::system_locale(), window, counter)?;
+        if let Err(e) = self.file_counters.advance(template, &now) {
+            log::warn!("Failed to save the file name counter: {}", e);
+        }
+        Ok(name)
+    }
+
+    /// Get the window the current image was captured from, for file names
+    fn captured_window(&self) -> ForegroundWindow {
+        ForegroundWindow {
+            title: self.doc.window_title.clone(),
+            application: self.doc.application.clone(),
+        }
+    }
+
+    /// Get the capture metadata embedded in exports of the current image
+    pub fn image_metadata(&self) -> ImageMetadata {
+        ImageMetadata {
+            monitor: self.doc.source_screen.as_ref().map(ImageMetadata::describe_monitor),
+            window_title: self.doc.window_title.clone(),
+            application: self.doc.application.clone(),
+            comment: Some(self.doc.comment.trim().to_string()).filter(|comment| !comment.is_empty()),
+            icc_profile: self.export_icc_profile(self.doc.icc_profile.as_ref()),
+            ..ImageMetadata::new(self.doc.captured_at)
+        }
+    }
+
+    /// Get the color profile to embed in exports of pixels in `source`, if enabled
+    ///
+    /// Pixels without a profile of their own are sRGB.
+    fn export_icc_profile(&self, source: Option<&Arc<Vec<u8>>>) -> Option<Arc<Vec<u8>>> {
+        if !self.settings.get_settings().color.embed_icc_profile {
+            return None;
+        }
+        Some(source.cloned().unwrap_or_else(|| Arc::new(color::srgb_icc_profile())))
+    }
+
+    /// Set the comment embedded in exports of the current image
+    pub fn set_comment(&mut self, comment: &str) {
+        let comment: String = comment.chars().take(metadata::MAX_COMMENT_CHARS).collect();
+        if comment != self.doc.comment {
+            self.doc.comment = comment;
+            self.mark_content_changed();
+        }
+    }
+
+    /// Get the file the current image was last saved to
+    pub fn file_path(&self) -> Option<&Path> {
+        self.doc.file_path.as_deref()
+    }
+
+    /// Export the current image to a file
+    ///
+    /// The format is taken from the file extension, falling back to the
+    /// default image format from settings.
+    pub fn save_to(&mut self, path: &Path) -> AppResult<()> {
+        let format = self.format_for(path);
+        let image = self
+            .export_image()
+            .ok_or_else(|| AppError::ImageProcessing("保存する画像がありません".to_string()))?;
+
+        let data = export::encode_export(&image, &format, &self.settings.get_settings().export, &self.image_metadata())?;
+        let location = crate::files::save_location(path, &self.cloud_sync_roots);
+        if location.is_remote() {
+            // Slow and lock-prone locations are written in the background
+            log::info!("Saving image to {:?} location {} in the background", location, path.display());
+            self.writer.submit(path.to_path_buf(), location, data);
+            self.doc.pending_save = Some((path.to_path_buf(), self.doc.content_revision));
+            return Ok(());
+        }
+
+        crate::files::write_atomic(path, &data)?;
+        log::info!("Saved image to {}", path.display());
+        if self.doc.source_screen.is_some() {
+            self.last_capture_path = Some(path.to_path_buf());
+        }
+        self.doc.file_path = Some(path.to_path_buf());
+        self.doc.mark_saved();
+        self.remember_recent_file(path);
+        let history_id = self.doc.history_id;
+        self.update_history(history_id, |entry| entry.path = Some(path.to_path_buf()));
+        let screen = self.doc.source_screen.clone();
+        self.run_post_save_command(path, screen.as_ref(), history_id);
+        Ok(())
+    }
+
+    /// Get the format to save a file in from its extension, falling back to the default image format
+    fn format_for(&self, path: &Path) -> ImageFormat {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(ImageFormat::from_extension)
+            .unwrap_or_else(|| self.settings.get_settings().default_image_format.clone())
+    }
+
+    /// Upload the current image to a remote destination in the background
+    ///
+    /// The document keeps its local file, if any; the remote location is
+    /// recorded in the capture history like an upload link.
+    pub fn save_to_destination(&mut self, destination: &SaveDestination, file_name: &str) -> AppResult<()> {
+        let format = self.format_for(Path::new(file_name));
+        let image = self
+            .export_image()
+            .ok_or_else(|| AppError::ImageProcessing("保存する画像がありません".to_string()))?;
+        let data = export::encode_export(&image, &format, &self.settings.get_settings().export, &self.image_metadata())?;
+        log::info!("Saving image as {} to {} in the background", file_name, destination.name);
+        let total = data.len() as u64;
+        let receiver = remote::spawn_upload(destination.clone(), file_name.to_string(), data, RetryPolicy::default());
+        self.transfers.push(Transfer {
+            destination: destination.name.clone(),
+            file_name: file_name.to_string(),
+            sent: 0,
+            total,
+            history_id: self.doc.history_id,
+            receiver,
+        });
+        Ok(())
+    }
+
+    /// Update the progress of uploads and report those that finished since the last frame
+    fn poll_transfers(&mut self) {
+        let mut finished = Vec::new();
+        self.transfers.retain_mut(|transfer| loop {
+            match transfer.receiver.try_recv() {
+                Ok(TransferEvent::Progress(sent)) => transfer.sent = sent,
+                Ok(TransferEvent::Finished(result)) => {
+                    finished.push((transfer.history_id, result));
+                    return false;
+                }
+                Err(crossbeam_channel::TryRecvError::Empty) => return true,
+                Err(crossbeam_channel::TryRecvError::Disconnected) => {
+                    finished.push((transfer.history_id, Err(AppError::Transfer("転送が中断されました".to_string()))));
+                    return false;
+                }
+            }
+        });
+        for (history_id, result) in finished {
+            match result {
+                Ok(location) => {
+                    log::info!("Saved image to {}", location);
+                    self.notifications.push(Severity::Info, "Saved", location.clone());
+                    self.update_history(history_id, |entry| entry.upload_url = Some(location));
+                }
+                Err(e) => self.show_error("Save Failed", e.to_string()),
+            }
+        }
+    }
+
+    /// Check whether any background saves or uploads are still running
+    pub fn has_pending_saves(&self) -> bool {
+        self.writer.pending() > 0 || !self.transfers.is_empty()
+    }
+
+    /// Open the Save As window, starting from the current file name and the last destination
+    fn open_save_as(&mut self) {
+        if self.doc.is_empty() {
+            return;
+        }
+        let settings = self.settings.get_settings();
+        let file_name = match self.doc.file_path.as_deref().and_then(Path::file_name) {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => {
+                // The counter is only used up by saving automatically named files
+                let now = DateTime::now_local();
+                let counter = self.file_counters.peek(&settings.file_naming, &now);
+                let name = settings
+                    .file_naming
+                    .render_numbered(&now, &naming::system_locale(), &self.captured_window(), counter)
+                    .unwrap_or_else(|_| "screenshot".to_string());
+                format!("{}.{}", name, settings.default_image_format.extension())
+            }
+        };
+        let destination = settings
+            .last_save_destination
+            .filter(|id| settings.save_destinations.iter().any(|destination| destination.id == *id));
+        self.save_as = Some(SaveAsDraft {
+            file_name,
+            folder: self.dialog_directory().display().to_string(),
+            destination,
+        });
+    }
+
+    /// Save to the choices of the Save As window, remembering the destination for next time
+    fn submit_save_as(&mut self, draft: &SaveAsDraft) -> AppResult<()> {
+        let file_name = draft.file_name.trim();
+        if file_name.is_empty() {
+            return Err(AppError::Settings("ファイル名を入力してください".to_string()));
+        }
+        match draft.destination {
+            Some(id) => {
+                let destination = self
+                    .settings
+                    .get_settings()
+                    .save_destinations
+                    .iter()
+                    .find(|destination| destination.id == id)
+                    .cloned()
+                    .ok_or_else(|| AppError::Settings("保存先が見つかりません".to_string()))?;
+                self.save_to_destination(&destination, file_name)?;
+            }
+            None => self.save_to(&Path::new(draft.folder.trim()).join(file_name))?,
+        }
+        if self.settings.get_settings().last_save_destination != draft.destination {
+            self.settings.settings_mut().last_save_destination = draft.destination;
+            self.settings_dirty = true;
+        }
+        Ok(())
+    }
+
+    /// Handle background saves that finished since the last frame
+    fn poll_background_saves(&mut self, ctx: &Context) {
+        for outcome in self.writer.poll() {
+            match outcome.result {
+                Ok(()) => {
+                    log::info!("Saved image to {}", outcome.path.display());
+                    if self.settings.get_settings().copy_remote_save_location {
+                        let location = outcome.path.display().to_string();
+                        ctx.output_mut(|output| output.copied_text = location);
+                    }
+                    self.remember_recent_file(&outcome.path);
+                    // The tab may have been switched or closed while saving
+                    let document = self.document_saving_to(&outcome.path);
+                    let screen = document.as_ref().and_then(|document| document.source_screen.clone());
+                    let history_id = document.as_ref().and_then(|document| document.history_id);
+                    if let Some(document) = document {
+                        if let Some((_, revision)) = document.pending_save.take() {
+                            document.saved_revision = revision;
+                        }
+                        document.file_path = Some(outcome.path.clone());
+                    }
+                    if screen.is_some() {
+                        self.last_capture_path = Some(outcome.path.clone());
+                    }
+                    self.update_history(history_id, |entry| entry.path = Some(outcome.path.clone()));
+                    self.run_post_save_command(&outcome.path, screen.as_ref(), history_id);
+                }
+                Err(e) => {
+                    if let Some(document) = self.document_saving_to(&outcome.path) {
+                        document.pending_save = None;
+                    }
+                    self.show_error("Save Failed", e.to_string());
+                }
+            }
+        }
+    }
+
+    /// Find the open document with a background save to a path in flight
+    fn document_saving_to(&mut self, path: &Path) -> Option<&mut Document> {
+        std::iter::once(&mut self.doc)
+            .chain(self.tabs.iter_mut())
+            .find(|document| document.pending_save.as_ref().is_some_and(|(pending, _)| pending == path))
+    }
+
+    /// Save to the last used file, or a new file in the default save directory
+    fn save(&mut self) -> AppResult<()> {
+        let path = match self.doc.file_path.clone() {
+            Some(path) => path,
+            None => {
+                let settings = self.settings.get_settings();
+                let directory = self
+                    .active_route()
+                    .and_then(|route| route.directory.clone())
+                    .unwrap_or_else(|| settings.save_directory());
+                std::fs::create_dir_all(crate::files::extended_length_path(&directory))
+                    .context(format!("保存先フォルダ {} を作成できません", directory.display()))?;
+                let (template, extension) = (settings.file_naming.clone(), settings.default_image_format.extension());
+                let window = self.captured_window();
+                let name = self.numbered_file_name(&template, &window)?;
+                naming::unique_path(&directory, &name, extension)
+            }
+        };
+        self.save_to(&path)
+    }
+
+    /// Load a test image for demonstration purposes
+    pub fn load_test_image(&mut self) -> AppResult<()> {
+        // Create a test image with a gradient pattern
+        let width = 400;
+        let height = 300;
+        let mut img_buffer = image::ImageBuffer::new(width, height);
+        
+        for (x, y, pixel) in img_buffer.enumerate_pixels_mut() {
+            let r = (x as f32 / width as f32 * 255.0) as u8;
+            let g = (y as f32 / height as f32 * 255.0) as u8;
+            let b = ((x + y) as f32 / (width + height) as f32 * 255.0) as u8;
+            *pixel = image::Rgb([r, g, b]);
+        }
+        
+        let test_image = DynamicImage::ImageRgb8(img_buffer);
+        self.load_image(test_image)
+    }
+
+    /// Get the current tool
+    pub fn current_tool(&self) -> &Tool {
+        &self.current_tool
+    }
+
+    /// Set the current tool
+    pub fn set_tool(&mut self, tool: Tool) {
+        self.current_tool = tool;
+    }
+
+    /// Zoom in by one step
+    pub fn zoom_in(&mut self) {
+        self.doc.zoom_level = (self.doc.zoom_level * 1.2).min(10.0);
+    }
+
+    /// Zoom out by one step
+    pub fn zoom_out(&mut self) {
+        self.doc.zoom_level = (self.doc.zoom_level / 1.2).max(0.1);
+    }
+
+    /// Get the current zoom level
+    pub fn zoom_level(&self) -> f64 {
+        self.doc.zoom_level
+    }
+
+    /// Zoom to fit the whole image in the canvas, without enlarging it past 100%
+    pub fn zoom_to_fit(&mut self) {
+        if let Some(size) = self.image_size() {
+            self.zoom_to_rect(Rect::from_min_size(Pos2::ZERO, size), 1.0);
+        }
+    }
+
+    /// Zoom to frame the selected annotations
+    ///
+    /// Returns whether anything was selected.
+    pub fn zoom_to_selection(&mut self) -> bool {
+        let corners: Vec<Pos2> = self
+            .doc
+            .annotations
+            .iter()
+            .filter(|a| a.is_selected)
+            .flat_map(AnnotationItem::corners)
+            .collect();
+        if corners.is_empty() {
+            return false;
+        }
+        self.zoom_to_rect(Rect::from_points(&corners), 10.0);
+        true
+    }
+
+    /// Zoom and pan so an image-space rectangle fills the canvas, centered
+    fn zoom_to_rect(&mut self, rect: Rect, max_zoom: f64) {
+        let (Some(canvas), Some(image_size)) = (self.canvas_rect, self.image_size()) else {
+            return;
+        };
+        let available = (canvas.size() - Vec2::splat(2.0 * ZOOM_FIT_MARGIN)).max(Vec2::splat(1.0));
+        let size = rect.size().max(Vec2::splat(1.0));
+        let zoom = (available.x / size.x).min(available.y / size.y) as f64;
+        self.doc.zoom_level = zoom.min(max_zoom).clamp(0.1, 10.0);
+        // Without panning, the image center is at the canvas center
+        self.doc.pan_offset = (image_size * 0.5 - rect.center().to_vec2()) * self.doc.zoom_level as f32;
+    }
+
+    /// Perform an action triggered by a keyboard shortcut or menu item
+    pub fn perform_action(&mut self, action: ShortcutAction) {
+        match action {
+            ShortcutAction::Open => self.open_with_dialog(),
+            ShortcutAction::Save => {
+                if let Err(e) = self.save() {
+                    self.show_error("Save Failed", e.to_string());
+                }
+            }
+            ShortcutAction::SaveAs => self.open_save_as(),
+            ShortcutAction::Copy => match self.copy_annotations() {
+                Ok(true) => {}
+                Ok(false) if !self.doc.is_empty() => {
+                    if let Err(e) = self.guard_output(GuardedOutput::CopyImage) {
+                        self.show_error("Copy Failed", e.to_string());
+                    }
+                }
+                Ok(false) => {}
+                Err(e) => self.show_error("Copy Failed", e.to_string()),
+            },
+            ShortcutAction::Paste => {
+                let result = match self.paste_annotations() {
+                    Ok(true) => Ok(()),
+                    Ok(false) => self.paste_from_clipboard(),
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = result {
+                    self.show_error("Paste Failed", e.to_string());
+                }
+            }
+            ShortcutAction::Undo => {
+                self.undo();
+            }
+            ShortcutAction::Redo => {
+                self.redo();
+            }
+            ShortcutAction::Delete => self.delete_selected(),
+            ShortcutAction::Duplicate => {
+                self.duplicate_selected();
+            }
+            ShortcutAction::ZoomIn => self.zoom_in(),
+            ShortcutAction::ZoomOut => self.zoom_out(),
+            ShortcutAction::ZoomReset => self.doc.zoom_level = 1.0,
+            ShortcutAction::ZoomFit => self.zoom_to_fit(),
+            ShortcutAction::ZoomToSelection => {
+                self.zoom_to_selection();
+            }
+            ShortcutAction::NextTab => self.next_tab(),
+            ShortcutAction::PreviousTab => self.previous_tab(),
+            ShortcutAction::CloseTab => {
+                self.close_tab(self.active_tab);
+            }
+            ShortcutAction::SwitchTool(slot) => {
+                if let Some(tool) = Tool::all().into_iter().nth((slot as usize).wrapping_sub(1)) {
+                    self.current_tool = tool;
+                }
+            }
+            ShortcutAction::PlaceAnnotation => {
+                self.place_annotation();
+            }
+            ShortcutAction::SelectNext => {
+                self.select_next_annotation();
+            }
+            ShortcutAction::Nudge(direction) => {
+                self.nudge_selected(direction.offset() * NUDGE_STEP);
+            }
+            ShortcutAction::NudgeFar(direction) => {
+                self.nudge_selected(direction.offset() * NUDGE_STEP_LARGE);
+            }
+        }
+    }
+
+    /// Bind a key chord to an action in the persisted shortcut map
+    pub fn rebind_shortcut(&mut self, action: ShortcutAction, chord: KeyChord) -> AppResult<()> {
+        self.settings.settings_mut().shortcuts.bind(action, chord)?;
+        self.settings_dirty = true;
+        Ok(())
+    }
+
+    /// Dispatch shortcuts pressed this frame through the shortcut map
+    fn handle_shortcuts(&mut self, ctx: &Context) {
+        // Keys go to focused text fields and the rebinding capture instead
+        if ctx.wants_keyboard_input() || self.rebinding_action.is_some() {
+            return;
+        }
+
+        if self.doc.region_selection.is_some() {
+            self.handle_region_keys(ctx);
+            return;
+        }
+        if self.doc.color_picking {
+            if ctx.input_mut(|i| i.consume_key(egui::Modifiers::NONE, egui::Key::Escape)) {
+                self.cancel_color_pick();
+            }
+            return;
+        }
+        let actions = self.settings.get_settings().shortcuts.triggered(ctx);
+        for action in actions {
+            self.perform_action(action);
+        }
+    }
+
+    /// Menu button showing the action's bound shortcut
+    fn action_button(&self, label: &str, action: ShortcutAction) -> egui::Button<'static> {
+        egui::Button::new(label.to_string())
+            .shortcut_text(self.settings.get_settings().shortcuts.display(action))
+    }
+
+    /// Check if the application should close
+    pub fn should_close(&self) -> bool {
+        self.should_close
+    }
+
+    /// Request the application to close, asking first if there are unsaved changes
+    pub fn request_close(&mut self) {
+        if self.has_unsaved_changes() {
+            self.show_exit_prompt = true;
+        } else {
+            self.should_close = true;
+        }
+    }
+
+    /// Intercept the window's close button while there are unsaved changes or running saves
+    fn handle_close_request(&mut self, ctx: &Context) {
+        if !ctx.input(|i| i.viewport().close_requested()) || self.should_close && !self.has_pending_saves() {
+            return;
+        }
+        ctx.send_viewport_cmd(egui::ViewportCommand::CancelClose);
+        if self.has_pending_saves() {
+            // Close once the background saves are written
+            self.should_close = true;
+        } else {
+            self.request_close();
+        }
+    }
+
+    /// Show the active image's name and unsaved state in the window title
+    fn update_window_title(&mut self, ctx: &Context) {
+        let title = self.window_title();
+        if title != self.applied_title {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Title(title.clone()));
+            self.applied_title = title;
+        }
+    }
+
+    /// Create the tiled texture if needed and upload the next batch of tiles
+    ///
+    /// The texture is rebuilt when the texture filter setting changes.
+    fn ensure_texture(&mut self, ctx: &Context) {
+        let filter = self.settings.get_settings().texture_filter;
+        if self.doc.texture.as_ref().is_some_and(|texture| texture.filter() != filter) {
+            self.doc.texture = None;
+        }
+        if self.doc.texture.is_none() {
+            let tile_size = TILE_SIZE.min(ctx.input(|i| i.max_texture_side) as u32);
+            self.doc.texture = self
+                .display_image()
+                .map(|image| TiledTexture::with_tile_size(image.to_rgba8(), tile_size).with_filter(filter));
+        }
+        if let Some(texture) = self.doc.texture.as_mut().filter(|texture| !texture.is_complete()) {
+            let started = Instant::now();
+            texture.upload_next(ctx, TILE_UPLOADS_PER_FRAME);
+            self.diagnostics.record_texture_upload(started.elapsed());
+        }
+    }
+
+    /// Check whether the image texture is still being uploaded
+    fn is_uploading_texture(&self) -> bool {
+        self.doc.texture.as_ref().is_some_and(|texture| !texture.is_complete())
+    }
+
+    /// Start capturing on a worker thread
+    ///
+    /// Captures the monitor showing the focused window when that setting is
+    /// on, and the primary screen otherwise.
+    pub fn start_capture(&mut self) -> AppResult<()> {
+        if self.pending_capture.is_some() {
+            return Ok(());
+        }
+        let active_monitor = self.settings.get_settings().capture_active_monitor;
+        let service = self.capture_service()?;
+        let pending = if active_monitor {
+            service.capture_active_monitor_async()?
+        } else {
+            service.capture_primary_screen_async()?
+        };
+        self.pending_capture = Some(pending);
+        Ok(())
+    }
+
+    /// Start the capture of the screen capture hotkey
+    ///
+    /// With region selection on, the capture opens with the region last
+    /// selected for the focused application, on the screen showing it.
+    pub fn start_hotkey_capture(&mut self) -> AppResult<()> {
+        if self.pending_capture.is_some() {
+            return Ok(());
+        }
+        if !self.settings.get_settings().select_capture_region {
+            return self.start_capture();
+        }
+        let window = self.capture_service()?.foreground_window();
+        let remembered = window
+            .application
+            .as_deref()
+            .and_then(|application| self.settings.get_settings().region_memory.region_for(application));
+        let service = self.capture_service()?;
+        match remembered.and_then(|region| service.find_screen_overlapping(region)) {
+            Some(screen) => self.pending_capture = Some(service.capture_screen_async(screen.index)?),
+            None => self.start_capture()?,
+        }
+        self.pending_region_selection = Some(window);
+        Ok(())
+    }
+
+    /// Choose the next monitor to capture with the screen hotkey
+    ///
+    /// The choice is captured by [`poll_monitor_cycle`](Self::poll_monitor_cycle)
+    /// once the hotkey hasn't been pressed for a moment. With a single
+    /// monitor there is nothing to choose, so it is captured right away.
+    fn press_monitor_cycle(&mut self, now: Instant) -> AppResult<()> {
+        if self.pending_capture.is_some() {
+            return Ok(());
+        }
+        let screens = self.capture_service()?.get_screens().len();
+        if screens <= 1 {
+            return self.start_hotkey_capture();
+        }
+        let target = self.monitor_cycle.press(screens, now);
+        log::info!("Hotkey capture target: {}", target.label());
+        Ok(())
+    }
+
+    /// Capture the monitor chosen with the screen hotkey once it is due
+    fn poll_monitor_cycle(&mut self, now: Instant) {
+        let Some(target) = self.monitor_cycle.take_due(now) else {
+            return;
+        };
+        if self.pending_capture.is_some() {
+            return;
+        }
+        let result = self.capture_service().and_then(|service| match target {
+            CycleTarget::Monitor(index) => service.capture_screen_async(index),
+            CycleTarget::AllMonitors => service.capture_all_screens_async(),
+        });
+        match result {
+            Ok(pending) => self.pending_capture = Some(pending),
+            Err(e) => self.notify_error("Capture Failed", &e),
+        }
+    }
+
+    /// Show the monitor chosen with the screen hotkey in a label on that monitor
+    ///
+    /// The label is a small window of its own, so it shows even while the
+    /// editor is hidden or on another monitor.
+    fn draw_monitor_cycle_label(&mut self, ctx: &Context) {
+        let Some(target) = self.monitor_cycle.chosen() else {
+            return;
+        };
+        let Ok(service) = self.capture_service() else {
+            return;
+        };
+        let bounds = match target {
+            CycleTarget::Monitor(index) => service.get_screen_info(index).map(|screen| screen.bounds).ok(),
+            CycleTarget::AllMonitors => Some(service.get_desktop_bounds()),
+        };
+        let Some(bounds) = bounds else {
+            return;
+        };
+        let size = Vec2::new(240.0, 64.0);
+        let builder = egui::ViewportBuilder::default()
+            .with_title(target.label())
+            .with_inner_size(size)
+            .with_position(bounds.center() - size / 2.0)
+            .with_decorations(false)
+            .with_resizable(false)
+            .with_transparent(true)
+            .with_always_on_top()
+            .with_mouse_passthrough(true)
+            .with_active(false);
+        ctx.show_viewport_immediate(egui::ViewportId::from_hash_of("monitor-cycle-label"), builder, |ctx, _| {
+            let frame = egui::Frame::none()
+                .fill(egui::Color32::from_black_alpha(200))
+                .rounding(8.0)
+                .inner_margin(egui::Margin::same(12.0));
+            egui::CentralPanel::default().frame(frame).show(ctx, |ui| {
+                ui.centered_and_justified(|ui| {
+                    ui.label(egui::RichText::new(target.label()).size(24.0).strong().color(egui::Color32::WHITE));
+                });
+            });
+        });
+        if let Some(remaining) = self.monitor_cycle.time_until_due(Instant::now()) {
+            ctx.request_repaint_after(remaining);
+        }
+    }
+
+    /// Start capturing the focused window on a worker thread
+    pub fn start_window_capture(&mut self) -> AppResult<()> {
+        if self.pending_capture.is_some() {
+            return Ok(());
+        }
+        let pending = self.capture_service()?.capture_active_window_async()?;
+        self.pending_capture = Some(pending);
+        Ok(())
+    }
+
+    /// Start capturing the one window matching `selector` on a worker thread
+    pub fn start_matching_window_capture(&mut self, selector: &WindowSelector) -> AppResult<()> {
+        if self.pending_capture.is_some() {
+            return Ok(());
+        }
+        let pending = self.capture_service()?.capture_window_async(selector)?;
+        self.pending_capture = Some(pending);
+        Ok(())
+    }
+
+    /// Start capturing the monitor containing the mouse cursor on a worker thread
+    ///
+    /// Unlike [`start_capture`](Self::start_capture), this ignores which
+    /// monitor shows the focused window, so the capture follows the cursor
+    /// on multi-monitor setups.
+    pub fn start_cursor_monitor_capture(&mut self) -> AppResult<()> {
+        if self.pending_capture.is_some() {
+            return Ok(());
+        }
+        let pending = self.capture_service()?.capture_cursor_monitor_async()?;
+        self.pending_capture = Some(pending);
+        Ok(())
+    }
+
+    /// Freeze the monitor containing the mouse cursor to pick a color from
+    ///
+    /// The frozen frame opens in the editor without being recorded or
+    /// saved like a capture, and is closed once a color is picked.
+    pub fn start_color_pick(&mut self) -> AppResult<()> {
+        if self.pending_capture.is_some() {
+            return Ok(());
+        }
+        let pending = self.capture_service()?.capture_cursor_monitor_async()?;
+        self.pending_capture = Some(pending);
+        self.pending_color_pick = true;
+        Ok(())
+    }
+
+    /// Check whether a color is being picked from a frozen frame
+    pub fn is_picking_color(&self) -> bool {
+        self.doc.color_picking
+    }
+
+    /// Get the color of a pixel of the image, ignoring annotations
+    fn pixel_color(&self, position: Pos2) -> Option<egui::Color32> {
+        let image = self.display_image()?;
+        // A downsampled proxy is close enough for picking a color
+        let position = position * self.doc.proxy_scale();
+        let (x, y) = (position.x.floor(), position.y.floor());
+        if x < 0.0 || y < 0.0 || x >= image.width() as f32 || y >= image.height() as f32 {
+            return None;
+        }
+        let [r, g, b, _] = image.get_pixel(x as u32, y as u32).0;
+        Some(egui::Color32::from_rgb(r, g, b))
+    }
+
+    /// Copy the HEX value of a pixel of the frozen frame and add it to the picked colors
+    ///
+    /// The frame is closed afterwards. Returns the picked color, `None` if no
+    /// color is being picked or `position` is off the image.
+    pub fn pick_screen_color(&mut self, ctx: &Context, position: Pos2) -> Option<egui::Color32> {
+        if !self.doc.color_picking {
+            return None;
+        }
+        let color = self.pixel_color(position)?;
+        let hex = palette::hex(color);
+        log::info!("Picked color {}", hex);
+        ctx.output_mut(|output| output.copied_text = hex.clone());
+        self.settings.settings_mut().palette.push(color);
+        self.settings_dirty = true;
+        self.notifications.push(Severity::Info, "Color Copied", hex);
+        self.discard_tab(self.active_tab);
+        Some(color)
+    }
+
+    /// Close the frozen frame without picking a color
+    pub fn cancel_color_pick(&mut self) {
+        if self.doc.color_picking {
+            log::info!("Color picking cancelled");
+            self.discard_tab(self.active_tab);
+        }
+    }
+
+    /// Export the picked colors to a palette file in the save folder
+    pub fn export_palette(&mut self, format: PaletteFormat) -> AppResult<PathBuf> {
+        let directory = self.settings.get_settings().save_directory();
+        std::fs::create_dir_all(crate::files::extended_length_path(&directory))
+            .context(format!("保存先フォルダ {} を作成できません", directory.display()))?;
+        let path = naming::unique_path(&directory, "palette", format.extension());
+        palette::export(&path, format, &self.settings.get_settings().palette.colors)?;
+        log::info!("Exported picked colors to {}", path.display());
+        Ok(path)
+    }
+
+    /// Start the capture of a preset on a worker thread
+    ///
+    /// The finished capture is handled by the preset's output action, which
+    /// may copy or save it without opening it in the editor.
+    pub fn run_capture_preset(&mut self, index: usize) -> AppResult<()> {
+        if self.pending_capture.is_some() {
+            return Ok(());
+        }
+        let preset = self
+            .settings
+            .get_settings()
+            .capture_presets
+            .get(index)
+            .cloned()
+            .ok_or_else(|| AppError::Settings(format!("キャプチャプリセット {} が見つかりません", index + 1)))?;
+        log::info!("Running capture preset {}", preset.name);
+        self.pending_capture = Some(self.capture_target_async(preset.target)?);
+        self.pending_preset = Some(preset);
+        Ok(())
+    }
+
+    /// Start capturing a preset or interval target on a worker thread
+    fn capture_target_async(&mut self, target: PresetTarget) -> AppResult<PendingCapture> {
+        let service = self.capture_service()?;
+        match target {
+            PresetTarget::Monitor(screen_index) => service.capture_screen_async(screen_index),
+            PresetTarget::Region { x, y, width, height } => service.capture_region_async(Rect::from_min_size(
+                Pos2::new(x as f32, y as f32),
+                Vec2::new(width as f32, height as f32),
+            )),
+            PresetTarget::Area { monitor, placement } => {
+                let area = presets::resolve_area(monitor, placement, &service.get_screens())?;
+                service.capture_region_async(area)
+            }
+            PresetTarget::ActiveWindow => service.capture_active_window_async(),
+        }
+    }
+
+    /// Check whether a capture is in progress
+    pub fn is_capturing(&self) -> bool {
+        self.pending_capture.is_some()
+    }
+
+    /// Load the result of a finished background capture
+    fn poll_pending_capture(&mut self) {
+        let Some(result) = self.pending_capture.as_ref().and_then(PendingCapture::poll) else {
+            return;
+        };
+        if let Some(pending) = self.pending_capture.take() {
+            log::info!("Capture finished in {:?}", pending.elapsed());
+        }
+
+        let preset = self.pending_preset.take().filter(|preset| preset.output != PresetOutput::Editor);
+        let response = match preset {
+            Some(preset) => match result.and_then(|frame| self.deliver_preset_capture(&preset, frame)) {
+                Ok(value) => Ok(value),
+                Err(e) => {
+                    self.notify_error("Capture Preset Failed", &e);
+                    Err(AutomationError::from(&e))
+                }
+            },
+            None => self.open_capture(result),
+        };
+        for call in self.capture_calls.drain(..) {
+            call.respond(response.clone());
+        }
+    }
+
+    /// Open a finished capture in the editor, saving it too if auto-save is on
+    ///
+    /// Hotkey captures with region selection on are saved once the region is confirmed.
+    fn open_capture(&mut self, result: AppResult<CapturedFrame>) -> AutomationResponse {
+        let select_region = self.pending_region_selection.take();
+        let pick_color = std::mem::take(&mut self.pending_color_pick);
+        let loaded = result.and_then(|frame| {
+            self.load_capture(DynamicImage::ImageRgba8(frame.image), frame.screen)?;
+            self.doc.window_title = frame.window_title;
+            self.doc.application = frame.application;
+            self.doc.icc_profile = frame.icc_profile;
+            Ok(())
+        });
+        match loaded {
+            Ok(()) => {
+                match select_region {
+                    // A frozen frame to pick a color from is never saved, so it has nothing to lose
+                    _ if pick_color => {
+                        self.doc.color_picking = true;
+                        self.doc.mark_saved();
+                    }
+                    Some(window) => {
+                        // The selected region shows the window focused when the hotkey was pressed
+                        self.doc.window_title = self.doc.window_title.take().or(window.title);
+                        self.doc.application = self.doc.application.take().or(window.application.clone());
+                        self.start_region_selection(window.application)
+                    }
+                    None => self.finish_capture(),
+                }
+                let size = self.image_size().unwrap_or(Vec2::ZERO);
+                Ok(serde_json::json!({
+                    "width": size.x as u32,
+                    "height": size.y as u32,
+                    "path": self.doc.file_path,
+                }))
+            }
+            Err(e) => {
+                self.show_error("Capture Failed", e.to_string());
+                Err(AutomationError::from(&e))
+            }
+        }
+    }
+
+    /// Record a capture opened in the editor in the history, and save it if auto-save is on
+    fn finish_capture(&mut self) {
+        if let Some(screen) = self.doc.source_screen.clone() {
+            let target = capture_target(&screen, self.doc.window_title.as_deref());
+            let size = self.doc.image_dimensions().unwrap_or_default();
+            let thumbnail = self.doc.source_image.as_ref().and_then(|image| self.capture_thumbnail(image));
+            self.doc.history_id = self.record_capture(target, size, thumbnail, None);
+        }
+        if self.settings.get_settings().auto_save_captures {
+            if let Err(e) = self.save() {
+                self.show_error("Save Failed", e.to_string());
+            }
+        }
+        let actions = self.plugins.after_capture();
+        if !actions.is_empty() {
+            if let Err(e) = self.guard_output(GuardedOutput::Actions(actions)) {
+                self.notify_error("Action Failed", &e);
+            }
+        }
+    }
+
+    /// Start selecting a region of the capture just opened
+    ///
+    /// The region last selected for the application is selected if it is on
+    /// the captured screen, and the whole capture otherwise.
+    fn start_region_selection(&mut self, application: Option<String>) {
+        let Some(size) = self.image_size() else {
+            return;
+        };
+        let whole = Rect::from_min_size(Pos2::ZERO, size);
+        let remembered = application
+            .as_deref()
+            .and_then(|application| self.settings.get_settings().region_memory.region_for(application));
+        let rect = match (remembered, &self.doc.source_screen) {
+            (Some(region), Some(screen)) => desktop_to_image(region, screen).intersect(whole),
+            _ => whole,
+        };
+        let rect = if rect.width() >= 1.0 && rect.height() >= 1.0 { rect } else { whole };
+        let rect = regions::constrain(rect, self.region_constraint, whole);
+        self.doc.region_selection = Some(RegionSelection { application, rect, drag: None, edge: None });
+    }
+
+    /// Lock the selected capture region to an aspect ratio or size, reshaping the current selection
+    pub fn set_region_constraint(&mut self, constraint: SelectionConstraint) {
+        self.region_constraint = constraint;
+        let Some(size) = self.image_size() else {
+            return;
+        };
+        if let Some(selection) = &mut self.doc.region_selection {
+            selection.rect = regions::constrain(selection.rect, constraint, Rect::from_min_size(Pos2::ZERO, size));
+        }
+    }
+
+    /// Check whether a region of the capture is being selected
+    pub fn is_selecting_region(&self) -> bool {
+        self.doc.region_selection.is_some()
+    }
+
+    /// Get the region of the capture being selected, in image pixels
+    pub fn selected_region(&self) -> Option<Rect> {
+        self.doc.region_selection.as_ref().map(|selection| selection.rect)
+    }
+
+    /// Change the region of the capture being selected, in image pixels
+    pub fn set_selected_region(&mut self, rect: Rect) {
+        let Some(size) = self.image_size() else {
+            return;
+        };
+        if let Some(selection) = &mut self.doc.region_selection {
+            let rect = rect.intersect(Rect::from_min_size(Pos2::ZERO, size));
+            if rect.width() >= 1.0 && rect.height() >= 1.0 {
+                selection.rect = rect;
+            }
+        }
+    }
+
+    /// Crop the capture to the selected region and remember it for the application
+    pub fn confirm_region_selection(&mut self) -> AppResult<()> {
+        let Some(selection) = self.doc.region_selection.take() else {
+            return Ok(());
+        };
+        self.doc.restore_full_resolution()?;
+        let image = self
+            .doc
+            .source_image
+            .as_ref()
+            .ok_or_else(|| AppError::ImageProcessing("切り抜く画像がありません".to_string()))?;
+        let rect = Rect::from_min_max(selection.rect.min.round(), selection.rect.max.round());
+        let cropped = image.crop_imm(
+            rect.min.x as u32,
+            rect.min.y as u32,
+            (rect.width() as u32).max(1),
+            (rect.height() as u32).max(1),
+        );
+        self.doc = Document {
+            source_image: Some(cropped),
+            source_screen: self.doc.source_screen.take(),
+            captured_at: self.doc.captured_at,
+            session_id: self.doc.session_id,
+            window_title: self.doc.window_title.take(),
+            application: self.doc.application.take(),
+            icc_profile: self.doc.icc_profile.take(),
+            ..Document::default()
+        };
+        self.mark_content_changed();
+
+        if let (Some(application), Some(screen)) = (&selection.application, &self.doc.source_screen) {
+            let region = image_to_desktop(rect, screen);
+            log::info!("Remembering capture region {:?} for {}", region, application);
+            self.settings.settings_mut().region_memory.remember(application, region);
+            self.settings_dirty = true;
+        }
+        self.finish_capture();
+        Ok(())
+    }
+
+    /// Keep the whole capture instead of a selected region
+    pub fn select_whole_screen(&mut self) {
+        if self.doc.region_selection.take().is_some() {
+            self.finish_capture();
+        }
+    }
+
+    /// Throw away the capture whose region is being selected
+    pub fn cancel_region_selection(&mut self) {
+        if self.doc.region_selection.take().is_some() {
+            log::info!("Capture cancelled during region selection");
+            self.discard_tab(self.active_tab);
+        }
+    }
+
+    /// Move and resize the capture region being selected with the keyboard
+    ///
+    /// Arrows move the selection, by 10 pixels with Ctrl held, and Shift+arrows
+    /// resize it. Tab picks an edge for the arrows to move instead. Enter
+    /// confirms the selection and Escape cancels the capture.
+    fn handle_region_keys(&mut self, ctx: &Context) {
+        let (confirm, cancel, tabs, back_tabs) = ctx.input_mut(|i| {
+            (
+                i.consume_key(egui::Modifiers::NONE, egui::Key::Enter),
+                i.consume_key(egui::Modifiers::NONE, egui::Key::Escape),
+                i.count_and_consume_key(egui::Modifiers::NONE, egui::Key::Tab),
+                i.count_and_consume_key(egui::Modifiers::SHIFT, egui::Key::Tab),
+            )
+        });
+        let (offset, resize) = ctx.input(|i| {
+            let step = if i.modifiers.command { NUDGE_STEP_LARGE } else { NUDGE_STEP };
+            let directions = [
+                (egui::Key::ArrowLeft, Vec2::new(-1.0, 0.0)),
+                (egui::Key::ArrowRight, Vec2::new(1.0, 0.0)),
+                (egui::Key::ArrowUp, Vec2::new(0.0, -1.0)),
+                (egui::Key::ArrowDown, Vec2::new(0.0, 1.0)),
+            ];
+            let offset = directions
+                .into_iter()
+                .fold(Vec2::ZERO, |offset, (key, direction)| offset + direction * i.num_presses(key) as f32);
+            (offset * step, i.modifiers.shift)
+        });
+
+        let Some(size) = self.image_size() else {
+            return;
+        };
+        let constraint = self.region_constraint;
+        if let Some(selection) = &mut self.doc.region_selection {
+            for _ in 0..tabs {
+                selection.edge = Edge::cycle(selection.edge, false);
+            }
+            for _ in 0..back_tabs {
+                selection.edge = Edge::cycle(selection.edge, true);
+            }
+            if offset != Vec2::ZERO {
+                let bounds = Rect::from_min_size(Pos2::ZERO, size);
+                selection.rect = regions::nudge(selection.rect, offset, selection.edge, resize, constraint, bounds);
+            }
+        }
+        if confirm {
+            if let Err(e) = self.confirm_region_selection() {
+                self.show_error("Capture Failed", e.to_string());
+            }
+        } else if cancel {
+            self.cancel_region_selection();
+        }
+    }
+
+    /// Copy or save a preset's capture without opening it in the editor
+    fn deliver_preset_capture(&mut self, preset: &CapturePreset, frame: CapturedFrame) -> AppResult<serde_json::Value> {
+        let (width, height) = frame.image.dimensions();
+        let path = if preset.output.saves_file() {
+            Some(self.save_preset_capture(preset, &frame)?)
+        } else {
+            clipboard::write_image(&frame.image)?;
+            None
+        };
+        let target = format!("{}: {}", preset.name, capture_target(&frame.screen, frame.window_title.as_deref()));
+        let thumbnail = self.capture_thumbnail(&frame.image);
+        let history_id = self.record_capture(target, (width, height), thumbnail.clone(), path.as_deref());
+        if let (Some(path), PresetOutput::Upload) = (&path, preset.output) {
+            self.start_upload(&preset.upload_command, path, history_id)
+                .context(format!("アップロードコマンド {} を実行できません", preset.upload_command.program))?;
+        }
+        let message = match &path {
+            Some(path) => format!("Saved {}", path.display()),
+            None => format!("Copied {}x{} capture to the clipboard", width, height),
+        };
+        if self.settings.get_settings().capture_toast {
+            // The capture toast takes the place of the usual toast
+            self.notifications.record(Severity::Info, &preset.name, message.clone());
+            let toast = CaptureToast::new(&preset.name, message, path.clone(), thumbnail);
+            self.show_capture_toast(SilentCapture { toast, frame, history_id, texture: None });
+        } else {
+            self.notifications.push(Severity::Info, &preset.name, message);
+        }
+        Ok(serde_json::json!({
+            "width": width,
+            "height": height,
+            "path": path,
+        }))
+    }
+
+    /// Show the toast of a capture taken without opening the editor
+    ///
+    /// While the editor window is hidden, Windows shows the toast instead.
+    /// It can only offer to open a saved file, in the default image viewer.
+    fn show_capture_toast(&mut self, capture: SilentCapture) {
+        if !self.hidden_until_capture {
+            self.silent_capture = Some(capture);
+            return;
+        }
+        let toast = &capture.toast;
+        let image = capture
+            .history_id
+            .zip(self.history.as_ref())
+            .map(|(id, history)| history.thumbnail_path(id))
+            .filter(|path| path.exists());
+        let links = toast
+            .path
+            .iter()
+            .map(|path| (CaptureAction::Open.label().to_string(), notifications::file_uri(path)))
+            .collect();
+        SystemToast { image, links, ..SystemToast::new(&toast.title, &toast.message) }.show();
+    }
+
+    /// Carry out a button of the silent capture toast, closing it
+    ///
+    /// A save can't be undone while an upload command is still reading the
+    /// file; the toast stays open so it can be undone once the upload ends.
+    pub fn run_capture_action(&mut self, ctx: &Context, action: CaptureAction) -> AppResult<()> {
+        let path = self.silent_capture.as_ref().and_then(|capture| capture.toast.path.as_deref());
+        if let (CaptureAction::UndoSave, Some(path)) = (action, path) {
+            if self.is_uploading(path) {
+                return Err(AppError::Transfer(format!("{} はアップロード中のため削除できません", path.display())));
+            }
+        }
+        let Some(capture) = self.silent_capture.take() else {
+            return Ok(());
+        };
+        match (action, capture.toast.path) {
+            (CaptureAction::Open, Some(path)) => self.open_file(&path)?,
+            (CaptureAction::Open, None) => {
+                self.load_capture(DynamicImage::ImageRgba8(capture.frame.image), capture.frame.screen)?;
+            }
+            (CaptureAction::CopyPath, Some(path)) => {
+                ctx.output_mut(|output| output.copied_text = path.display().to_string());
+            }
+            (CaptureAction::UndoSave, Some(path)) => {
+                std::fs::remove_file(&path)?;
+                self.update_history(capture.history_id, |entry| entry.path = None);
+                if self.last_capture_path.as_deref() == Some(path.as_path()) {
+                    self.last_capture_path = None;
+                }
+                self.notifications.push(Severity::Info, "Save Undone", format!("Deleted {}", path.display()));
+            }
+            (CaptureAction::CopyPath | CaptureAction::UndoSave, None) => {}
+        }
+        if action == CaptureAction::Open {
+            ctx.send_viewport_cmd_to(egui::ViewportId::ROOT, egui::ViewportCommand::Focus);
+        }
+        Ok(())
+    }
+
+    /// Draw the toast of the last silent capture in a small window above other windows
+    ///
+    /// It sits in the bottom-right corner of the primary monitor and closes
+    /// itself after [`notifications::CAPTURE_TOAST_DURATION`].
+    fn draw_capture_toast(&mut self, ctx: &Context) {
+        if self.silent_capture.is_none() {
+            return;
+        }
+        let bounds = self
+            .capture_service()
+            .ok()
+            .and_then(|service| service.get_primary_screen().ok())
+            .map(|screen| screen.bounds);
+        let Some(capture) = &mut self.silent_capture else {
+            return;
+        };
+        let time_left = capture.toast.time_left(Instant::now());
+        let (Some(bounds), false) = (bounds, time_left.is_zero()) else {
+            self.silent_capture = None;
+            return;
+        };
+        if capture.texture.is_none() {
+            capture.texture = capture.toast.thumbnail.as_ref().map(|thumbnail| {
+                Self::load_texture(ctx, "capture_toast_thumbnail", &DynamicImage::ImageRgba8(thumbnail.clone()))
+            });
+        }
+        let texture = capture.texture.clone();
+        let toast = capture.toast.clone();
+        let uploading = toast.path.as_deref().is_some_and(|path| self.is_uploading(path));
+
+        // Above the taskbar, in the bottom-right corner
+        let size = Vec2::new(360.0, history::THUMBNAIL_SIZE as f32 + 24.0);
+        let builder = egui::ViewportBuilder::default()
+            .with_title(&toast.title)
+            .with_inner_size(size)
+            .with_position(bounds.max - size - Vec2::new(16.0, 64.0))
+            .with_decorations(false)
+            .with_resizable(false)
+            .with_always_on_top()
+            .with_active(false);
+        let mut clicked = None;
+        let mut close = false;
+        ctx.show_viewport_immediate(egui::ViewportId::from_hash_of("capture-toast"), builder, |ctx, _| {
+            egui::CentralPanel::default().show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    if let Some(texture) = &texture {
+                        let thumbnail = Vec2::splat(history::THUMBNAIL_SIZE as f32);
+                        ui.add(egui::Image::new(texture).max_size(thumbnail));
+                    }
+                    ui.vertical(|ui| {
+                        ui.horizontal(|ui| {
+                            ui.strong(&toast.title);
+                            ui.with_layout(egui::Layout::right_to_left(egui::Align::Min), |ui| {
+                                close = ui.small_button("x").on_hover_text("Dismiss").clicked();
+                            });
+                        });
+                        ui.label(&toast.message);
+                        ui.horizontal_wrapped(|ui| {
+                            for action in toast.actions() {
+                                let enabled = !(uploading && action == CaptureAction::UndoSave);
+                                if ui
+                                    .add_enabled(enabled, egui::Button::new(action.label()))
+                                    .on_disabled_hover_text("The capture is still being uploaded")
+                                    .clicked()
+                                {
+                                    clicked = Some(action);
+                                }
+                            }
+                        });
+                    });
+                });
+            });
+            close |= ctx.input(|input| input.viewport().close_requested());
+        });
+        if let Some(action) = clicked {
+            if let Err(e) = self.run_capture_action(ctx, action) {
+                self.notify_error(&format!("{} Failed", action.label()), &e);
+            }
+        } else if close {
+            self.silent_capture = None;
+        } else if uploading {
+            // Enable Undo Save as soon as the upload finishes
+            ctx.request_repaint_after(time_left.min(Duration::from_millis(250)));
+        } else {
+            ctx.request_repaint_after(time_left);
+        }
+    }
+
+    /// Save a preset's capture to a new file in the save folder, returning its path
+    fn save_preset_capture(&mut self, preset: &CapturePreset, frame: &CapturedFrame) -> AppResult<PathBuf> {
+        let settings = self.settings.get_settings();
+        let directory = settings
+            .monitor_routing
+            .route_for(&frame.screen)
+            .and_then(|route| route.directory.clone())
+            .unwrap_or_else(|| settings.save_directory());
+        std::fs::create_dir_all(crate::files::extended_length_path(&directory))
+            .context(format!("保存先フォルダ {} を作成できません", directory.display()))?;
+        let window = ForegroundWindow { title: frame.window_title.clone(), application: frame.application.clone() };
+        let name = self.numbered_file_name(&preset.file_naming, &window)?;
+        let path = naming::unique_path(&directory, &name, preset.format.extension());
+
+        let settings = self.settings.get_settings();
+        let captured_at = SystemTime::now();
+        let image = export::prepare_export(&frame.image, &settings.export, captured_at);
+        let metadata = ImageMetadata {
+            monitor: Some(ImageMetadata::describe_monitor(&frame.screen)),
+            window_title: frame.window_title.clone(),
+            application: frame.application.clone(),
+            icc_profile: self.export_icc_profile(frame.icc_profile.as_ref()),
+            ..ImageMetadata::new(captured_at)
+        };
+        let data = export::encode_export(&image, &preset.format, &settings.export, &metadata)?;
+        crate::files::write_atomic(&path, &data)?;
+        log::info!("Saved capture of preset {} to {}", preset.name, path.display());
+        self.last_capture_path = Some(path.clone());
+        self.remember_recent_file(&path);
+        Ok(path)
+    }
+
+    /// Start capturing the interval target on a schedule, with the saved settings
+    pub fn start_interval_capture(&mut self) -> AppResult<()> {
+        if self.interval_run.is_some() {
+            return Ok(());
+        }
+        let settings = self.settings.get_settings();
+        let interval = settings.interval_capture.clone();
+        interval.validate()?;
+        let parent = interval.directory.clone().unwrap_or_else(|| settings.save_directory());
+        let run = IntervalRun::new(interval, &parent, &DateTime::now_local(), Instant::now());
+        log::info!("Started interval capture into {}", run.folder().display());
+        self.notifications.push(
+            Severity::Info,
+            "Interval Capture Started",
+            format!("Capturing {} every {} s into {}", run.settings().target, run.settings().interval_secs, run.folder().display()),
+        );
+        self.interval_run = Some(run);
+        Ok(())
+    }
+
+    /// Stop interval capture, reporting how many frames were saved
+    pub fn stop_interval_capture(&mut self, reason: StopReason) {
+        let Some(run) = self.interval_run.take() else {
+            return;
+        };
+        self.interval_capture = None;
+        log::info!("Interval capture ended ({}): {} frames saved, {} skipped", reason, run.saved(), run.skipped());
+        let mut message = format!("{}. Saved {} frames to {}", reason, run.saved(), run.folder().display());
+        if run.skipped() > 0 {
+            message.push_str(&format!(" ({} unchanged frames skipped)", run.skipped()));
+        }
+        self.notifications.push(Severity::Info, "Interval Capture Stopped", message);
+    }
+
+    /// Check whether interval capture is running
+    pub fn is_interval_capture_running(&self) -> bool {
+        self.interval_run.is_some()
+    }
+
+    /// Save finished interval frames and start the next capture when it is due
+    ///
+    /// Frames that come due while the interval trigger is paused are skipped.
+    fn poll_interval_capture(&mut self, now: Instant) {
+        if let Some(result) = self.interval_capture.as_ref().and_then(PendingCapture::poll) {
+            self.interval_capture = None;
+            if let Err(e) = result.and_then(|frame| self.save_interval_frame(frame)) {
+                self.interval_run = None;
+                self.notify_error("Interval Capture Failed", &e);
+                return;
+            }
+        }
+        let Some(run) = self.interval_run.as_mut() else {
+            return;
+        };
+        if let Some(reason) = run.limit_reached(now) {
+            self.stop_interval_capture(reason);
+            return;
+        }
+        if !run.take_due(now) || self.interval_capture.is_some() {
+            return;
+        }
+        let target = run.settings().target;
+        if !self.trigger_state().interval_capture {
+            log::debug!("Skipped interval frame while paused");
+            return;
+        }
+        match self.capture_target_async(target) {
+            Ok(pending) => self.interval_capture = Some(pending),
+            Err(e) => {
+                self.interval_run = None;
+                self.notify_error("Interval Capture Failed", &e);
+            }
+        }
+    }
+
+    /// Save an interval frame to the run's folder, unless it is unchanged
+    fn save_interval_frame(&mut self, frame: CapturedFrame) -> AppResult<()> {
+        let Some(run) = self.interval_run.as_mut() else {
+            return Ok(());
+        };
+        let Some(path) = run.accept_frame(&frame.image) else {
+            log::debug!("Skipped unchanged interval frame");
+            return Ok(());
+        };
+        let format = run.settings().format.clone();
+        self.write_frame_file(&frame, &path, &format)?;
+        log::debug!("Saved interval frame {}", path.display());
+        self.last_capture_path = Some(path);
+        Ok(())
+    }
+
+    /// Encode a capture with the export options and write it, creating its folder
+    fn write_frame_file(&self, frame: &CapturedFrame, path: &Path, format: &ImageFormat) -> AppResult<()> {
+        if let Some(folder) = path.parent() {
+            std::fs::create_dir_all(crate::files::extended_length_path(folder))
+                .context(format!("保存先フォルダ {} を作成できません", folder.display()))?;
+        }
+        let settings = self.settings.get_settings();
+        let captured_at = SystemTime::now();
+        let image = export::prepare_export(&frame.image, &settings.export, captured_at);
+        let metadata = ImageMetadata {
+            monitor: Some(ImageMetadata::describe_monitor(&frame.screen)),
+            window_title: frame.window_title.clone(),
+            application: frame.application.clone(),
+            icc_profile: self.export_icc_profile(frame.icc_profile.as_ref()),
+            ..ImageMetadata::new(captured_at)
+        };
+        let data = export::encode_export(&image, format, &settings.export, &metadata)?;
+        crate::files::write_atomic(path, &data)
+    }
+
+    /// Capture the screen to select a region to watch for changes on
+    ///
+    /// The Select Region window offers Watch Region for the capture.
+    pub fn start_watch_selection(&mut self) -> AppResult<()> {
+        if self.pending_capture.is_some() {
+            return Ok(());
+        }
+        self.start_capture()?;
+        // The editor is focused, so no application's region is remembered
+        self.pending_region_selection = Some(ForegroundWindow::default());
+        Ok(())
+    }
+
+    /// Watch the region being selected for changes instead of capturing it
+    pub fn watch_selected_region(&mut self) -> AppResult<()> {
+        let (Some(selection), Some(screen)) = (&self.doc.region_selection, &self.doc.source_screen) else {
+            return Ok(());
+        };
+        let rect = Rect::from_min_max(selection.rect.min.round(), selection.rect.max.round());
+        self.start_region_watch(image_to_desktop(rect, screen))?;
+        // The capture was only taken to choose the region
+        self.doc.region_selection = None;
+        self.discard_tab(self.active_tab);
+        Ok(())
+    }
+
+    /// Start polling a desktop rectangle, saving a capture whenever it changes
+    ///
+    /// A region already being watched is replaced.
+    pub fn start_region_watch(&mut self, region: Rect) -> AppResult<()> {
+        if region.width() < 1.0 || region.height() < 1.0 {
+            return Err(AppError::InvalidArgument("監視する範囲が小さすぎます".to_string()));
+        }
+        let settings = self.settings.get_settings();
+        let watch_settings = settings.watch.clone();
+        watch_settings.validate()?;
+        let parent = watch_settings.directory.clone().unwrap_or_else(|| settings.save_directory());
+        self.stop_region_watch();
+        let watch = RegionWatch::new(region, watch_settings, &parent, &DateTime::now_local(), Instant::now());
+        log::info!("Watching region {:?} for changes into {}", region, watch.folder().display());
+        self.notifications.push(
+            Severity::Info,
+            "Watching Region",
+            format!(
+                "Checking {}x{} at ({}, {}) every {} ms; changes are saved to {}",
+                region.width() as i32,
+                region.height() as i32,
+                region.min.x as i32,
+                region.min.y as i32,
+                watch.settings().poll_interval_ms,
+                watch.folder().display()
+            ),
+        );
+        self.region_watch = Some(watch);
+        Ok(())
+    }
+
+    /// Stop watching the region, reporting how many changes were saved
+    pub fn stop_region_watch(&mut self) {
+        let Some(watch) = self.region_watch.take() else {
+            return;
+        };
+        self.watch_capture = None;
+        log::info!("Region watch ended: {} changes saved in {} checks", watch.saved(), watch.checks());
+        self.notifications.push(
+            Severity::Info,
+            "Region Watch Stopped",
+            format!("Saved {} changes in {} checks to {}", watch.saved(), watch.checks(), watch.folder().display()),
+        );
+    }
+
+    /// Check whether a region is being watched for changes
+    pub fn is_watching_region(&self) -> bool {
+        self.region_watch.is_some()
+    }
+
+    /// Compare finished checks of the watched region and start the next one when it is due
+    fn poll_region_watch(&mut self, now: Instant) {
+        if let Some(result) = self.watch_capture.as_ref().and_then(PendingCapture::poll) {
+            self.watch_capture = None;
+            if let Err(e) = result.and_then(|frame| self.save_watch_frame(frame)) {
+                self.region_watch = None;
+                self.notify_error("Region Watch Failed", &e);
+                return;
+            }
+        }
+        let Some(watch) = self.region_watch.as_mut() else {
+            return;
+        };
+        if self.watch_capture.is_some() || !watch.take_due(now) {
+            return;
+        }
+        let region = watch.region();
+        match self.capture_service().and_then(|service| service.capture_region_async(region)) {
+            Ok(pending) => self.watch_capture = Some(pending),
+            Err(e) => {
+                self.region_watch = None;
+                self.notify_error("Region Watch Failed", &e);
+            }
+        }
+    }
+
+    /// Save a check of the watched region if it changed since the previous one
+    fn save_watch_frame(&mut self, frame: CapturedFrame) -> AppResult<()> {
+        let Some(watch) = self.region_watch.as_mut() else {
+            return Ok(());
+        };
+        let Some(path) = watch.check_frame(&frame.image, &DateTime::now_local()) else {
+            return Ok(());
+        };
+        let format = watch.settings().format.clone();
+        self.write_frame_file(&frame, &path, &format)?;
+        log::info!("Saved change of the watched region to {}", path.display());
+        self.notifications.push(Severity::Info, "Change Captured", path.display().to_string());
+        self.last_capture_path = Some(path);
+        Ok(())
+    }
+
+    /// Approximate bytes held by the documents of all tabs (images, caches, textures)
+    pub fn document_memory(&self) -> usize {
+        self.doc.memory_usage() + self.tabs.iter().map(Document::memory_usage).sum::<usize>()
+    }
+
+    /// Show the largest images as downsampled proxies while the tabs use more memory than the budget
+    ///
+    /// The full-resolution images are moved to memory-mapped files. Other
+    /// tabs are spilled before the active one.
+    fn enforce_memory_budget(&mut self) {
+        let options = self.settings.get_settings().memory;
+        let mut usage = self.document_memory();
+        if usage <= options.budget_bytes() {
+            return;
+        }
+        let directory = SpilledImage::default_directory();
+        let mut others: Vec<usize> = (0..self.tabs.len()).filter(|&index| index != self.active_tab).collect();
+        others.sort_by_key(|&index| Reverse(self.tabs[index].memory_usage()));
+        for index in others.into_iter().map(Some).chain([None]) {
+            if usage <= options.budget_bytes() {
+                break;
+            }
+            let document = match index {
+                Some(index) => &mut self.tabs[index],
+                None => &mut self.doc,
+            };
+            match document.spill_to_disk(options.proxy_max_side, &directory) {
+                Ok(0) => {}
+                Ok(freed) => {
+                    usage = usage.saturating_sub(freed);
+                    log::info!("Moved {} to disk to stay within the memory budget", document.title());
+                    if index.is_none() {
+                        self.notifications.push(
+                            Severity::Info,
+                            "Showing Reduced Resolution",
+                            "The image is over the memory budget. Exports still use the full resolution.".to_string(),
+                        );
+                    }
+                }
+                Err(e) => log::warn!("Failed to move {} to disk: {}", document.title(), e),
+            }
+        }
+    }
+
+    /// Upload textures for new image annotations and drop those of removed ones
+    fn ensure_image_textures(&mut self, ctx: &Context) {
+        let doc = &mut self.doc;
+        doc.image_textures.retain(|id, _| doc.annotations.iter().any(|a| a.id == *id));
+        for annotation in &doc.annotations {
+            let AnnotationType::Image { data, .. } = &annotation.annotation_type else {
+                continue;
+            };
+            if doc.image_textures.contains_key(&annotation.id) {
+                continue;
+            }
+            let texture = match decode::decode_untrusted(data.clone(), DecodeLimits::default()) {
+                Ok(image) => Some(Self::load_texture(ctx, &format!("annotation_{}", annotation.id), &image)),
+                Err(e) => {
+                    log::warn!("Failed to decode image annotation {}: {}", annotation.id, e);
+                    None
+                }
+            };
+            doc.image_textures.insert(annotation.id, texture);
+        }
+    }
+
+    /// Render the canvas previews of annotations blended other than normally
+    ///
+    /// A preview is rendered again whenever the image or annotations change.
+    fn ensure_blend_previews(&mut self, ctx: &Context) {
+        let doc = &mut self.doc;
+        doc.blend_previews
+            .retain(|id, _| doc.annotations.iter().any(|a| a.id == *id && a.blend_mode != BlendMode::Normal));
+        // Annotations are placed in full-resolution pixels, so a proxy can't be blended
+        let Some(image) = doc
+            .adjusted_image
+            .as_ref()
+            .or(doc.source_image.as_ref())
+            .filter(|_| doc.full_resolution.is_none())
+        else {
+            return;
+        };
+        for (index, annotation) in doc.annotations.iter().enumerate() {
+            if annotation.blend_mode == BlendMode::Normal
+                || doc.blend_previews.get(&annotation.id).is_some_and(|p| p.revision == doc.content_revision)
+            {
+                continue;
+            }
+            let extent = render::annotation_extent(annotation);
+            let Some((pixels, region)) = render::flatten_region(image, &doc.annotations[..=index], extent) else {
+                doc.blend_previews.remove(&annotation.id);
+                continue;
+            };
+            let size = [pixels.width() as usize, pixels.height() as usize];
+            let color_image = egui::ColorImage::from_rgba_unmultiplied(size, pixels.as_flat_samples().as_slice());
+            match doc.blend_previews.get_mut(&annotation.id) {
+                Some(preview) => {
+                    preview.texture.set(color_image, Default::default());
+                    preview.revision = doc.content_revision;
+                    preview.region = region;
+                }
+                None => {
+                    let texture = ctx.load_texture(format!("blend_{}", annotation.id), color_image, Default::default());
+                    doc.blend_previews.insert(annotation.id, BlendPreview { revision: doc.content_revision, region, texture });
+                }
+            }
+        }
+    }
+
+    /// Upload the heatmap of the image comparison if it changed
+    fn ensure_comparison_texture(&mut self, ctx: &Context) {
+        let Some(comparison) = self.doc.image_comparison.as_mut() else {
+            return;
+        };
+        if comparison.heatmap_texture.is_none() {
+            let heatmap = DynamicImage::ImageRgba8(comparison.result.heatmap.clone());
+            comparison.heatmap_texture = Some(Self::load_texture(ctx, "comparison_heatmap", &heatmap));
+        }
+    }
+
+    /// Upload an image as an egui texture
+    fn load_texture(ctx: &Context, name: &str, image: &DynamicImage) -> TextureHandle {
+        ctx.load_texture(name, Self::color_image(image), Default::default())
+    }
+
+    /// Convert an image to egui's pixel format
+    fn color_image(image: &DynamicImage) -> egui::ColorImage {
+        let rgba_image = image.to_rgba8();
+        let size = [rgba_image.width() as usize, rgba_image.height() as usize];
+        let pixels = rgba_image.as_flat_samples();
+        egui::ColorImage::from_rgba_unmultiplied(size, pixels.as_slice())
+    }
+
+    /// Draw the main menu bar
+    fn draw_menu_bar(&mut self, ctx: &Context) {
+        egui::TopBottomPanel::top("menu_bar").show(ctx, |ui| {
+            egui::menu::bar(ui, |ui| {
+                let file_menu = ui.menu_button("File", |ui| {
+                    if ui.add_enabled(!self.is_capturing(), egui::Button::new("New Screenshot")).clicked() {
+                        if let Err(e) = self.start_capture() {
+                            self.show_error("Capture Failed", e.to_string());
+                        }
+                        ui.close_menu();
+                    }
+                    ui.menu_button("Capture Preset", |ui| {
+                        let presets = self.settings.get_settings().capture_presets.clone();
+                        if presets.is_empty() {
+                            ui.weak("No capture presets");
+                        }
+                        for (index, preset) in presets.iter().enumerate() {
+                            let shortcut = preset
+                                .hotkey
+                                .map(|hotkey| hotkeys::describe(hotkey.modifiers, hotkey.vk_code))
+                                .unwrap_or_default();
+                            let button = egui::Button::new(&preset.name).shortcut_text(shortcut);
+                            if ui
+                                .add_enabled(!self.is_capturing(), button)
+                                .on_hover_text(format!("{}, {}", preset.target, preset.output))
+                                .clicked()
+                            {
+                                if let Err(e) = self.run_capture_preset(index) {
+                                    self.show_error("Capture Failed", e.to_string());
+                                }
+                                ui.close_menu();
+                            }
+                        }
+                        ui.separator();
+                        if ui.button("Manage Presets...").clicked() {
+                            self.presets_draft = Some((presets, 0));
+                            ui.close_menu();
+                        }
+                    });
+                    if self.is_interval_capture_running() {
+                        if ui.button("Stop Interval Capture").clicked() {
+                            self.stop_interval_capture(StopReason::Stopped);
+                            ui.close_menu();
+                        }
+                    } else if ui
+                        .button("Start Interval Capture")
+                        .on_hover_text("Capture the target set in Settings > Interval Capture on a schedule")
+                        .clicked()
+                    {
+                        if let Err(e) = self.start_interval_capture() {
+                            self.show_error("Capture Failed", e.to_string());
+                        }
+                        ui.close_menu();
+                    }
+                    if self.is_watching_region() {
+                        if ui.button("Stop Watching Region").clicked() {
+                            self.stop_region_watch();
+                            ui.close_menu();
+                        }
+                    } else if ui
+                        .add_enabled(!self.is_capturing(), egui::Button::new("Watch Region for Changes..."))
+                        .on_hover_text("Select a region, then save a capture whenever it changes")
+                        .clicked()
+                    {
+                        if let Err(e) = self.start_watch_selection() {
+                            self.show_error("Capture Failed", e.to_string());
+                        }
+                        ui.close_menu();
+                    }
+                    if ui.add(self.action_button("Open", ShortcutAction::Open)).clicked() {
+                        self.perform_action(ShortcutAction::Open);
+                        ui.close_menu();
+                    }
+                    ui.menu_button("Open Recent", |ui| {
+                        let recent_files = self.settings.get_settings().recent_files.clone();
+                        if recent_files.is_empty() {
+                            ui.weak("No recent files");
+                        }
+                        for path in recent_files {
+                            let name = path
+                                .file_name()
+                                .map(|name| name.to_string_lossy().into_owned())
+                                .unwrap_or_else(|| path.display().to_string());
+                            if ui.button(name).on_hover_text(path.display().to_string()).clicked() {
+                                self.open_and_report(&path);
+                                ui.close_menu();
+                            }
+                        }
+                        ui.separator();
+                        if ui.button("Clear Recent Files").clicked() {
+                            self.settings.settings_mut().recent_files.clear();
+                            self.settings_dirty = true;
+                            ui.close_menu();
+                        }
+                    });
+                    ui.add_enabled_ui(self.history.is_some(), |ui| {
+                        ui.menu_button("Recent Captures", |ui| self.draw_recent_captures_menu(ui));
+                    });
+                    if ui.add_enabled(self.history.is_some(), egui::Button::new("Capture History...")).clicked() {
+                        self.show_history = true;
+                        ui.close_menu();
+                    }
+                    if ui.button("Create Collage...").clicked() {
+                        let tabs = (0..self.tab_count())
+                            .filter(|&index| !self.tab(index).is_empty())
+                            .map(|index| self.tab(index).session_id)
+                            .collect();
+                        self.collage_sources = Some(CollageSources { tabs, ..CollageSources::default() });
+                        ui.close_menu();
+                    }
+                    if ui.button("Paste Image").clicked() {
+                        if let Err(e) = self.paste_from_clipboard() {
+                            self.show_error("Paste Failed", e.to_string());
+                        }
+                        ui.close_menu();
+                    }
+                    ui.separator();
+                    if ui.button("Open Encrypted Capture...").clicked() {
+                        self.encrypted_open = Some((String::new(), String::new()));
+                        ui.close_menu();
+                    }
+                    if ui.button("Open Project...").clicked() {
+                        self.open_project_with_dialog();
+                        ui.close_menu();
+                    }
+                    if ui.add_enabled(self.doc.file_path.is_some(), egui::Button::new("Save Project")).clicked() {
+                        match self.save_project() {
+                            Ok(path) => self.remember_recent_file(&path),
+                            Err(e) => self.show_error("Save Project Failed", e.to_string()),
+                        }
+                        ui.close_menu();
+                    }
+                    if ui.add_enabled(self.doc.source_image.is_some(), egui::Button::new("Import Annotations...")).clicked() {
+                        self.import_with_dialog();
+                        ui.close_menu();
+                    }
+                    if ui.button("Compare Projects...").clicked() {
+                        self.show_compare_projects = true;
+                        ui.close_menu();
+                    }
+                    let has_image = !self.doc.is_empty();
+                    if ui.add_enabled(has_image && dialogs::has_native_dialog(), egui::Button::new("Compare With Image...")).clicked() {
+                        self.compare_with_file_dialog();
+                        ui.close_menu();
+                    }
+                    let other_tabs: Vec<usize> = (0..self.tab_count())
+                        .filter(|&index| index != self.active_tab && !self.tab(index).is_empty())
+                        .collect();
+                    ui.add_enabled_ui(has_image && !other_tabs.is_empty(), |ui| {
+                        ui.menu_button("Compare With Tab", |ui| {
+                            for index in other_tabs {
+                                if ui.button(self.tab_title(index)).clicked() {
+                                    if let Err(e) = self.compare_with_tab(index) {
+                                        self.show_error("Compare Failed", e.to_string());
+                                    }
+                                    ui.close_menu();
+                                }
+                            }
+                        });
+                    });
+                    ui.separator();
+                    if ui.add(self.action_button("Save", ShortcutAction::Save)).clicked() {
+                        self.perform_action(ShortcutAction::Save);
+                        ui.close_menu();
+                    }
+                    if ui.add(self.action_button("Save As", ShortcutAction::SaveAs)).clicked() {
+                        self.perform_action(ShortcutAction::SaveAs);
+                        ui.close_menu();
+                    }
+                    if ui.add(self.action_button("Close Tab", ShortcutAction::CloseTab)).clicked() {
+                        self.perform_action(ShortcutAction::CloseTab);
+                        ui.close_menu();
+                    }
+                    if ui
+                        .add_enabled(!self.doc.is_empty(), egui::Button::new("Export SVG"))
+                        .on_hover_text("Export the annotations as editable shapes and text over the screenshot")
+                        .clicked()
+                    {
+                        match self.export_svg_file() {
+                            Ok(path) => {
+                                self.notifications.push(Severity::Info, "SVG Exported", path.display().to_string());
+                            }
+                            Err(e) => self.notify_error("Export Failed", &e),
+                        }
+                        ui.close_menu();
+                    }
+                    if ui
+                        .add_enabled(!self.doc.is_empty(), egui::Button::new("Export Encrypted..."))
+                        .on_hover_text("Export into a password-protected container for sharing sensitive captures")
+                        .clicked()
+                    {
+                        self.encrypted_export = Some((String::new(), String::new()));
+                        ui.close_menu();
+                    }
+                    let actions = self.plugins.actions().to_vec();
+                    ui.add_enabled_ui(!self.doc.is_empty() && !actions.is_empty(), |ui| {
+                        ui.menu_button("Run Action", |ui| {
+                            for action in actions {
+                                if ui.button(action.name()).clicked() {
+                                    if let Err(e) = self.guard_output(GuardedOutput::Actions(vec![action])) {
+                                        self.notify_error("Action Failed", &e);
+                                    }
+                                    ui.close_menu();
+                                }
+                            }
+                        });
+                    })
+                    .response
+                    .on_disabled_hover_text("Declare actions in plugins.json in the settings folder");
+                    let targets = self.settings.get_settings().share_targets.clone();
+                    ui.add_enabled_ui(!self.doc.is_empty() && !targets.is_empty(), |ui| {
+                        ui.menu_button("Share", |ui| {
+                            for target in &targets {
+                                if ui.button(&target.name).on_hover_text(target.service.to_string()).clicked() {
+                                    if let Err(e) = self.share(target) {
+                                        self.notify_error("Share Failed", &e);
+                                    }
+                                    ui.close_menu();
+                                }
+                            }
+                        });
+                    })
+                    .response
+                    .on_disabled_hover_text("Add share targets in Settings > Share Targets");
+                    let has_steps = !animation::steps(&self.doc.annotations).is_empty();
+                    if ui
+                        .add_enabled(has_steps, egui::Button::new("Export Step Animation"))
+                        .on_hover_text("Show the annotations one step at a time in an animated GIF or APNG")
+                        .on_disabled_hover_text("Tag annotations with steps in the Edit menu or their properties first")
+                        .clicked()
+                    {
+                        match self.export_step_animation_file() {
+                            Ok(path) => {
+                                self.notifications.push(Severity::Info, "Animation Exported", path.display().to_string());
+                            }
+                            Err(e) => self.notify_error("Export Failed", &e),
+                        }
+                        ui.close_menu();
+                    }
+                    ui.separator();
+                    if ui.button("Exit").clicked() {
+                        self.request_close();
+                        ui.close_menu();
+                    }
+                }).response;
+
+                let edit_menu = ui.menu_button("Edit", |ui| {
+                    let snapshot = self.doc.undo_snapshot();
+                    let (can_undo, can_redo) = (self.doc.undo.can_undo(&snapshot), self.doc.undo.can_redo(&snapshot));
+                    if ui.add_enabled(can_undo, self.action_button("Undo", ShortcutAction::Undo)).clicked() {
+                        self.perform_action(ShortcutAction::Undo);
+                        ui.close_menu();
+                    }
+                    if ui.add_enabled(can_redo, self.action_button("Redo", ShortcutAction::Redo)).clicked() {
+                        self.perform_action(ShortcutAction::Redo);
+                        ui.close_menu();
+                    }
+                    ui.separator();
+                    let has_selection = self.doc.annotations.iter().any(|a| a.is_selected);
+                    if ui.add(self.action_button("Copy", ShortcutAction::Copy)).clicked() {
+                        self.perform_action(ShortcutAction::Copy);
+                        ui.close_menu();
+                    }
+                    if ui.add(self.action_button("Paste", ShortcutAction::Paste)).clicked() {
+                        self.perform_action(ShortcutAction::Paste);
+                        ui.close_menu();
+                    }
+                    if ui.add_enabled(has_selection, self.action_button("Duplicate", ShortcutAction::Duplicate)).clicked() {
+                        self.perform_action(ShortcutAction::Duplicate);
+                        ui.close_menu();
+                    }
+                    ui.separator();
+                    let has_image = !self.doc.is_empty();
+                    if ui.add_enabled(has_image, egui::Button::new("Image Comment...")).clicked() {
+                        self.comment_draft = Some(self.doc.comment.clone());
+                        ui.close_menu();
+                    }
+                    if ui.add_enabled(has_image && dialogs::has_native_dialog(), egui::Button::new("Insert Image...")).clicked() {
+                        self.insert_image_with_dialog();
+                        ui.close_menu();
+                    }
+                    if ui.add_enabled(has_image, egui::Button::new("Insert Image from Clipboard")).clicked() {
+                        if let Err(e) = self.insert_image_from_clipboard() {
+                            self.show_error("Insert Failed", e.to_string());
+                        }
+                        ui.close_menu();
+                    }
+                    ui.separator();
+                    if ui
+                        .add_enabled(has_selection, egui::Button::new("Assign Next Step"))
+                        .on_hover_text("Show the selected annotations in a new step of animated exports")
+                        .clicked()
+                    {
+                        self.assign_next_step();
+                        ui.close_menu();
+                    }
+                    ui.separator();
+                    if ui.add_enabled(has_selection, egui::Button::new("Copy Style")).clicked() {
+                        self.copy_style();
+                        ui.close_menu();
+                    }
+                    if ui.add_enabled(has_selection && self.copied_style.is_some(), egui::Button::new("Paste Style")).clicked() {
+                        self.paste_style();
+                        ui.close_menu();
+                    }
+                    if ui.add_enabled(has_selection, self.action_button("Delete", ShortcutAction::Delete)).clicked() {
+                        self.perform_action(ShortcutAction::Delete);
+                        ui.close_menu();
+                    }
+                    ui.separator();
+                    ui.add_enabled_ui(self.templates.is_some(), |ui| {
+                        ui.menu_button("Templates", |ui| self.draw_templates_menu(ui));
+                    });
+                }).response;
+
+                let image_menu = ui.menu_button("Image", |ui| {
+                    let has_image = !self.doc.is_empty();
+                    if ui.add_enabled(has_image, egui::Button::new("Adjustments...")).clicked() {
+                        self.show_adjustments = true;
+                        ui.close_menu();
+                    }
+                    ui.separator();
+                    if ui
+                        .add_enabled(has_image, egui::Button::new("Auto Trim"))
+                        .on_hover_text("Remove uniform borders around the image")
+                        .clicked()
+                    {
+                        match self.auto_trim() {
+                            Ok(true) => {}
+                            Ok(false) => {
+                                self.notifications.push(Severity::Info, "Nothing to Trim", "The image has no uniform border".to_string());
+                            }
+                            Err(e) => self.show_error("Trim Failed", e.to_string()),
+                        }
+                        ui.close_menu();
+                    }
+                    let mut trim = self.settings.get_settings().trim;
+                    ui.add(egui::Slider::new(&mut trim.tolerance, 0..=image_ops::TrimOptions::MAX_TOLERANCE).text("Trim tolerance"))
+                        .on_hover_text("How much border pixels may differ from the border color");
+                    if trim != self.settings.get_settings().trim {
+                        self.settings.settings_mut().trim = trim;
+                        self.settings_dirty = true;
+                    }
+                }).response;
+
+                let settings_menu = ui.menu_button("Settings", |ui| {
+                    if ui.button("Keyboard Shortcuts...").clicked() {
+                        self.show_shortcut_settings = true;
+                        ui.close_menu();
+                    }
+                    if ui.button("Export Options...").clicked() {
+                        self.show_export_options = true;
+                        ui.close_menu();
+                    }
+                    if ui.button("File Naming...").clicked() {
+                        self.file_naming_draft = Some(self.settings.get_settings().file_naming.clone());
+                        ui.close_menu();
+                    }
+                    if ui.button("Monitor Routing...").clicked() {
+                        self.show_monitor_routing = true;
+                        ui.close_menu();
+                    }
+                    if ui.button("Capture Presets...").clicked() {
+                        self.presets_draft = Some((self.settings.get_settings().capture_presets.clone(), 0));
+                        ui.close_menu();
+                    }
+                    if ui.button("Interval Capture...").clicked() {
+                        let interval = self.settings.get_settings().interval_capture.clone();
+                        let folder = interval.directory.as_ref().map(|path| path.display().to_string()).unwrap_or_default();
+                        self.interval_draft = Some((interval, folder));
+                        ui.close_menu();
+                    }
+                    if ui.button("Region Watch...").clicked() {
+                        let watch = self.settings.get_settings().watch.clone();
+                        let folder = watch.directory.as_ref().map(|path| path.display().to_string()).unwrap_or_default();
+                        self.watch_draft = Some((watch, folder));
+                        ui.close_menu();
+                    }
+                    if ui.button("Privacy Check...").clicked() {
+                        let privacy = &self.settings.get_settings().privacy;
+                        self.privacy_draft = Some((privacy.enabled, privacy.custom_terms.join("\n")));
+                        ui.close_menu();
+                    }
+                    if ui.button("Save Destinations...").clicked() {
+                        self.destinations_draft =
+                            Some((self.settings.get_settings().save_destinations.clone(), 0, String::new()));
+                        ui.close_menu();
+                    }
+                    if ui.button("Share Targets...").clicked() {
+                        self.share_targets_draft =
+                            Some((self.settings.get_settings().share_targets.clone(), 0, String::new()));
+                        ui.close_menu();
+                    }
+                    let mut active_monitor = self.settings.get_settings().capture_active_monitor;
+                    if ui.checkbox(&mut active_monitor, "Capture Monitor of Active Window").changed() {
+                        self.settings.settings_mut().capture_active_monitor = active_monitor;
+                        self.settings_dirty = true;
+                    }
+                    let mut select_region = self.settings.get_settings().select_capture_region;
+                    if ui
+                        .checkbox(&mut select_region, "Select Region After Hotkey Capture")
+                        .on_hover_text("The region last selected for the focused application is selected first")
+                        .changed()
+                    {
+                        self.settings.settings_mut().select_capture_region = select_region;
+                        self.settings_dirty = true;
+                    }
+                    let mut cycle_monitors = self.settings.get_settings().cycle_monitors;
+                    if ui
+                        .checkbox(&mut cycle_monitors, "Cycle Monitors with Screen Hotkey")
+                        .on_hover_text("Press the screen capture hotkey again within 2 seconds to move on to the next monitor, then all monitors")
+                        .changed()
+                    {
+                        self.settings.settings_mut().cycle_monitors = cycle_monitors;
+                        self.settings_dirty = true;
+                    }
+                    let mut capture_toast = self.settings.get_settings().capture_toast;
+                    if ui
+                        .checkbox(&mut capture_toast, "Show Toast After Silent Captures")
+                        .on_hover_text("Show the capture with Open, Copy Path, and Undo Save buttons when a preset copies or saves it without the editor")
+                        .changed()
+                    {
+                        self.settings.settings_mut().capture_toast = capture_toast;
+                        self.settings_dirty = true;
+                    }
+                    let mut high_contrast = self.settings.get_settings().high_contrast;
+                    if ui.checkbox(&mut high_contrast, "High Contrast Theme").changed() {
+                        self.settings.settings_mut().high_contrast = high_contrast;
+                        self.settings_dirty = true;
+                    }
+                    ui.menu_button("Selection Overlay", |ui| {
+                        let mut overlay = self.settings.get_settings().overlay;
+                        let mut changed = false;
+                        ui.horizontal(|ui| {
+                            ui.label("Dim Outside:");
+                            changed |= ui
+                                .add(
+                                    egui::Slider::new(&mut overlay.dim_opacity, 0.0..=1.0)
+                                        .custom_formatter(|n, _| format!("{:.0}%", n * 100.0))
+                                        .custom_parser(|s| s.trim_end_matches('%').parse::<f64>().map(|n| n / 100.0).ok()),
+                                )
+                                .changed();
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Border:");
+                            changed |= accessibility::color_button(ui, &mut overlay.border_color, "Selection border color").changed();
+                            changed |= ui
+                                .add(
+                                    egui::DragValue::new(&mut overlay.border_width)
+                                        .clamp_range(0.5..=OverlaySettings::MAX_BORDER_WIDTH)
+                                        .speed(0.1)
+                                        .suffix(" px"),
+                                )
+                                .changed();
+                        });
+                        changed |= ui.checkbox(&mut overlay.show_dimensions, "Show Dimensions").changed();
+                        changed |= ui.checkbox(&mut overlay.show_crosshair, "Show Crosshair").changed();
+                        changed |= ui.checkbox(&mut overlay.show_thirds, "Show Rule-of-Thirds Guides").changed();
+                        changed |= ui.checkbox(&mut overlay.show_loupe, "Show Loupe").changed();
+                        if changed {
+                            self.settings.settings_mut().overlay = overlay;
+                            self.settings_dirty = true;
+                        }
+                    });
+                    let has_regions = !self.settings.get_settings().region_memory.regions.is_empty();
+                    if ui.add_enabled(has_regions, egui::Button::new("Forget Remembered Regions")).clicked() {
+                        self.settings.settings_mut().region_memory = RegionMemory::default();
+                        self.settings_dirty = true;
+                        ui.close_menu();
+                    }
+                    let mut launch_at_login = self.settings.get_settings().launch_at_login;
+                    if ui.checkbox(&mut launch_at_login, "Launch at Login").changed() {
+                        match autostart::set_launch_at_login(launch_at_login) {
+                            Ok(()) => {
+                                self.settings.settings_mut().launch_at_login = launch_at_login;
+                                self.settings_dirty = true;
+                            }
+                            Err(e) => self.show_error("Launch at Login Failed", e.to_string()),
+                        }
+                    }
+                    let mut auto_save = self.settings.get_settings().auto_save_captures;
+                    if ui.checkbox(&mut auto_save, "Auto-Save Captures").changed() {
+                        self.settings.settings_mut().auto_save_captures = auto_save;
+                        self.settings_dirty = true;
+                    }
+                    let mut copy_location = self.settings.get_settings().copy_remote_save_location;
+                    if ui
+                        .checkbox(&mut copy_location, "Copy Location After Network/Cloud Save")
+                        .changed()
+                    {
+                        self.settings.settings_mut().copy_remote_save_location = copy_location;
+                        self.settings_dirty = true;
+                    }
+                    ui.menu_button("Color Management", |ui| {
+                        let mut options = self.settings.get_settings().color.clone();
+                        let mut changed = ui
+                            .checkbox(&mut options.convert_to_srgb, "Convert Captures to sRGB")
+                            .on_hover_text("Convert captures of wide-gamut monitors so they don't look washed out")
+                            .changed();
+                        changed |= ui
+                            .checkbox(&mut options.embed_icc_profile, "Embed Color Profile")
+                            .on_hover_text("Embed the ICC profile of the pixels in saved PNG and JPEG files")
+                            .changed();
+                        if changed {
+                            self.settings.settings_mut().color = options;
+                            self.settings_dirty = true;
+                        }
+
+                        ui.separator();
+                        let mut hdr = self.settings.get_settings().hdr.clone();
+                        ui.checkbox(&mut hdr.capture_hdr, "Capture HDR Monitors")
+                            .on_hover_text("Capture monitors in HDR mode at full precision and tone map highlights instead of clipping them");
+                        ui.add_enabled_ui(hdr.capture_hdr, |ui| {
+                            ui.label("Tone Mapping:");
+                            for tone_mapping in ToneMapping::all() {
+                                ui.radio_value(&mut hdr.tone_mapping, tone_mapping, tone_mapping.to_string());
+                            }
+                            ui.add(
+                                egui::Slider::new(&mut hdr.sdr_white_nits, HdrOptions::SDR_WHITE_RANGE)
+                                    .text("SDR white (nits)")
+                                    .step_by(10.0),
+                            )
+                            .on_hover_text("Match the SDR content brightness in the Windows display settings");
+                        });
+                        if hdr != self.settings.get_settings().hdr {
+                            self.settings.settings_mut().hdr = hdr;
+                            self.settings_dirty = true;
+                        }
+                    });
+                    ui.menu_button("Window Capture", |ui| {
+                        let mut options = self.settings.get_settings().window_capture;
+                        let mut changed = ui
+                            .checkbox(&mut options.round_corners, "Round Window Corners")
+                            .on_hover_text("Make the desktop behind rounded window corners transparent")
+                            .changed();
+                        changed |= ui
+                            .checkbox(&mut options.include_shadow, "Include Window Shadow")
+                            .on_hover_text("Frame window captures in a drop shadow on a transparent background")
+                            .changed();
+                        if changed {
+                            self.settings.settings_mut().window_capture = options;
+                            self.settings_dirty = true;
+                        }
+                    });
+                    ui.menu_button("Low-Resource Mode", |ui| {
+                        let current = self.settings.get_settings().low_resource_mode;
+                        for mode in LowResourceMode::all() {
+                            if ui.radio(current == mode, mode.to_string()).clicked() {
+                                self.set_low_resource_mode(mode);
+                                ui.close_menu();
+                            }
+                        }
+                        if current == LowResourceMode::Auto {
+                            ui.separator();
+                            ui.weak(format!("Power source: {:?}", self.power_source));
+                        }
+                    });
+                    ui.menu_button("Panning", |ui| {
+                        let mut pan = self.settings.get_settings().pan;
+                        let mut changed = false;
+                        for scheme in PanScheme::all() {
+                            changed |= ui.radio_value(&mut pan.scheme, scheme, scheme.to_string()).changed();
+                        }
+                        ui.separator();
+                        changed |= ui
+                            .checkbox(&mut pan.free_pan, "Free Pan")
+                            .on_hover_text("Allow moving the image completely off the canvas")
+                            .changed();
+                        ui.add_enabled_ui(!pan.free_pan, |ui| {
+                            ui.horizontal(|ui| {
+                                ui.label("Keep visible:");
+                                changed |= ui
+                                    .add(
+                                        egui::DragValue::new(&mut pan.min_visible_margin)
+                                            .clamp_range(0.0..=crate::PanOptions::MAX_MARGIN)
+                                            .suffix(" px"),
+                                    )
+                                    .changed();
+                            });
+                        });
+                        if changed {
+                            self.settings.settings_mut().pan = pan;
+                            self.settings_dirty = true;
+                        }
+                    });
+                    ui.menu_button("Gestures", |ui| {
+                        let mut gestures = self.settings.get_settings().gestures;
+                        let mut changed = false;
+                        ui.label("Double-click:");
+                        for action in DoubleClickAction::all() {
+                            changed |= ui.radio_value(&mut gestures.double_click, action, action.to_string()).changed();
+                        }
+                        ui.separator();
+                        ui.label("Scroll:");
+                        for action in ScrollAction::all() {
+                            changed |= ui.radio_value(&mut gestures.scroll, action, action.to_string()).changed();
+                        }
+                        ui.separator();
+                        ui.horizontal(|ui| {
+                            ui.label("Zoom sensitivity:");
+                            changed |= ui
+                                .add(
+                                    egui::DragValue::new(&mut gestures.zoom_sensitivity)
+                                        .clamp_range(crate::GestureOptions::ZOOM_SENSITIVITY_RANGE)
+                                        .speed(0.05)
+                                        .suffix("×"),
+                                )
+                                .on_hover_text("How far scrolling and pinching zoom")
+                                .changed();
+                        });
+                        if changed {
+                            self.settings.settings_mut().gestures = gestures;
+                            self.settings_dirty = true;
+                        }
+                    });
+                    ui.menu_button("Memory", |ui| {
+                        let mut memory = self.settings.get_settings().memory;
+                        let mut changed = false;
+                        ui.horizontal(|ui| {
+                            ui.label("Budget:");
+                            changed |= ui
+                                .add(
+                                    egui::DragValue::new(&mut memory.budget_mb)
+                                        .clamp_range(MemoryOptions::MIN_BUDGET_MB..=MemoryOptions::MAX_BUDGET_MB)
+                                        .speed(16.0)
+                                        .suffix(" MB"),
+                                )
+                                .on_hover_text("Larger images are shown at reduced resolution above this")
+                                .changed();
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Reduced size:");
+                            changed |= ui
+                                .add(
+                                    egui::DragValue::new(&mut memory.proxy_max_side)
+                                        .clamp_range(MemoryOptions::MIN_PROXY_SIDE..=MemoryOptions::MAX_PROXY_SIDE)
+                                        .speed(16.0)
+                                        .suffix(" px"),
+                                )
+                                .changed();
+                        });
+                        ui.weak(format!("In use: {}", diagnostics::format_bytes(self.document_memory())));
+                        if changed {
+                            self.settings.settings_mut().memory = memory;
+                            self.settings_dirty = true;
+                        }
+                    });
+                    ui.menu_button("Zoom Quality", |ui| {
+                        let current = self.settings.get_settings().texture_filter;
+                        for filter in TextureFilter::all() {
+                            if ui.radio(current == filter, filter.to_string()).clicked() {
+                                self.settings.settings_mut().texture_filter = filter;
+                                self.settings_dirty = true;
+                                ui.close_menu();
+                            }
+                        }
+                    });
+                    ui.menu_button("Grid & Rulers", |ui| {
+                        let mut grid = self.settings.get_settings().grid;
+                        let mut changed = ui.checkbox(&mut grid.show_rulers, "Show Rulers").changed();
+                        changed |= ui.checkbox(&mut grid.show_grid, "Show Grid").changed();
+                        changed |= ui
+                            .checkbox(&mut grid.snap, "Snap to Grid")
+                            .on_hover_text("Hold Alt to invert while drawing")
+                            .changed();
+                        changed |= ui
+                            .checkbox(&mut grid.pixel_grid, "Pixel Grid")
+                            .on_hover_text("Draw lines between pixels when zoomed in above 800%")
+                            .changed();
+                        ui.horizontal(|ui| {
+                            ui.label("Spacing:");
+                            changed |= ui
+                                .add(
+                                    egui::DragValue::new(&mut grid.spacing)
+                                        .clamp_range(2.0..=GridOptions::MAX_SPACING)
+                                        .suffix(" px"),
+                                )
+                                .changed();
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Subdivisions:");
+                            changed |= ui
+                                .add(egui::DragValue::new(&mut grid.subdivisions).clamp_range(1..=GridOptions::MAX_SUBDIVISIONS))
+                                .changed();
+                        });
+                        if changed {
+                            self.settings.settings_mut().grid = grid;
+                            self.settings_dirty = true;
+                        }
+                    });
+                    ui.menu_button("Capture Triggers", |ui| {
+                        let state = self.trigger_state();
+                        for trigger in Trigger::all() {
+                            let mut enabled = state.is_enabled(trigger);
+                            if ui.checkbox(&mut enabled, trigger.to_string()).changed() {
+                                self.set_trigger_enabled(trigger, enabled);
+                            }
+                        }
+                        ui.separator();
+                        ui.weak("Scripts: lightweight-screenshot-app control pause");
+                    });
+                }).response;
+
+                let triggers = self.trigger_state();
+                if triggers.any_paused() {
+                    ui.weak("Triggers paused").on_hover_text(triggers.to_string());
+                }
+
+                let help_menu = ui.menu_button("Help", |ui| {
+                    if ui.checkbox(&mut self.show_diagnostics, "Performance Overlay").clicked() {
+                        ui.close_menu();
+                    }
+                    if ui.button("Error Log...").clicked() {
+                        self.show_error_log = true;
+                        ui.close_menu();
+                    }
+                    ui.separator();
+                    if ui.button("About").clicked() {
+                        self.show_about = true;
+                        ui.close_menu();
+                    }
+                }).response;
+
+                // Alt+letter or F10 focuses a menu, which Enter or Space then opens
+                let menus = [("File", file_menu), ("Edit", edit_menu), ("Image", image_menu), ("Settings", settings_menu), ("Help", help_menu)];
+                if let Some(pressed) = accessibility::pressed_menu(ctx) {
+                    if let Some((_, menu)) = menus.iter().find(|(name, _)| *name == pressed) {
+                        menu.request_focus();
+                    }
+                }
+            });
+        });
+    }
+
+    /// Draw the keyboard shortcut settings window
+    fn draw_shortcut_settings(&mut self, ctx: &Context) {
+        if !self.show_shortcut_settings {
+            self.rebinding_action = None;
+            return;
+        }
+
+        if let Some(action) = self.rebinding_action {
+            self.capture_rebinding(ctx, action);
+        }
+
+        let mut open = self.show_shortcut_settings;
+        egui::Window::new("Keyboard Shortcuts")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                egui::Grid::new("shortcut_grid").striped(true).show(ui, |ui| {
+                    for action in ShortcutAction::all() {
+                        ui.label(action.label());
+                        if self.rebinding_action == Some(action) {
+                            ui.label("Press a key... (Esc to cancel)");
+                        } else {
+                            ui.monospace(self.settings.get_settings().shortcuts.display(action));
+                        }
+                        if ui.button("Rebind").clicked() {
+                            self.rebinding_action = Some(action);
+                            self.shortcut_error = None;
+                        }
+                        if ui.button("Clear").clicked() {
+                            self.settings.settings_mut().shortcuts.unbind(action);
+                            self.settings_dirty = true;
+                        }
+                        ui.end_row();
+                    }
+                });
+
+                if let Some(error) = &self.shortcut_error {
+                    ui.colored_label(egui::Color32::RED, error);
+                }
+
+                ui.separator();
+                if ui.button("Reset to Defaults").clicked() {
+                    self.settings.settings_mut().shortcuts = Default::default();
+                    self.settings_dirty = true;
+                    self.rebinding_action = None;
+                    self.shortcut_error = None;
+                }
+            });
+        self.show_shortcut_settings = open;
+    }
+
+    /// Draw the export options window (output resizing)
+    fn draw_export_options(&mut self, ctx: &Context) {
+        if !self.show_export_options {
+            return;
+        }
+
+        let image_size = self.image_size();
+        let mut open = self.show_export_options;
+        let mut options = self.settings.get_settings().export.clone();
+        egui::Window::new("Export Options")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                let mode_name = |mode: &ResizeMode| match mode {
+                    ResizeMode::Original => "Original size",
+                    ResizeMode::Percentage(_) => "Percentage",
+                    ResizeMode::MaxDimensions { .. } => "Max width/height",
+                    ResizeMode::Exact { .. } => "Exact size",
+                };
+                let (width, height) = image_size.map_or((1920, 1080), |size| (size.x as u32, size.y as u32));
+                let modes = [
+                    ResizeMode::Original,
+                    ResizeMode::Percentage(50.0),
+                    ResizeMode::MaxDimensions { max_width: 1280, max_height: 1280 },
+                    ResizeMode::Exact { width, height },
+                ];
+
+                egui::ComboBox::from_label("Resize")
+                    .selected_text(mode_name(&options.resize))
+                    .show_ui(ui, |ui| {
+                        for mode in modes {
+                            let selected = std::mem::discriminant(&mode) == std::mem::discriminant(&options.resize);
+                            if ui.selectable_label(selected, mode_name(&mode)).clicked() && !selected {
+                                options.resize = mode;
+                            }
+                        }
+                    });
+
+                match &mut options.resize {
+                    ResizeMode::Original => {}
+                    ResizeMode::Percentage(percent) => {
+                        ui.add(egui::Slider::new(percent, 1.0..=400.0).text("Scale").suffix("%"));
+                    }
+                    ResizeMode::MaxDimensions { max_width, max_height } => {
+                        ui.add(egui::DragValue::new(max_width).prefix("Max width: ").suffix(" px"));
+                        ui.add(egui::DragValue::new(max_height).prefix("Max height: ").suffix(" px"));
+                        ui.weak("0 = no limit");
+                    }
+                    ResizeMode::Exact { width, height } => {
+                        let limits = DecodeLimits::default();
+                        ui.add(egui::DragValue::new(width).clamp_range(1..=limits.max_width).prefix("Width: ").suffix(" px"));
+                        ui.add(egui::DragValue::new(height).clamp_range(1..=limits.max_height).prefix("Height: ").suffix(" px"));
+                    }
+                }
+
+                ui.add_enabled_ui(options.resize != ResizeMode::Original, |ui| {
+                    egui::ComboBox::from_label("Filter")
+                        .selected_text(options.filter.to_string())
+                        .show_ui(ui, |ui| {
+                            for filter in ResizeFilter::all() {
+                                ui.selectable_value(&mut options.filter, filter, filter.to_string());
+                            }
+                        });
+                });
+
+                ui.separator();
+                Self::draw_watermark_editor(ui, &mut options.watermark);
+                ui.separator();
+                Self::draw_banner_editor(ui, &mut options.banner);
+                ui.separator();
+                ui.checkbox(&mut options.strip_metadata, "Strip metadata").on_hover_text(
+                    "Leave out the capture time, monitor, window title, and comment normally embedded in PNG and JPEG files",
+                );
+                ui.separator();
+                Self::draw_animation_editor(ui, &mut options.animation);
+                egui::ComboBox::from_label("Encrypted exports")
+                    .selected_text(options.encryption.to_string())
+                    .show_ui(ui, |ui| {
+                        for format in SecureFormat::all() {
+                            ui.selectable_value(&mut options.encryption, format, format.to_string());
+                        }
+                    });
+                ui.checkbox(&mut options.svg.embed_image, "Embed screenshot in SVG exports").on_hover_text(
+                    "Otherwise the screenshot is written as a separate PNG next to the SVG",
+                );
+                ui.separator();
+                Self::draw_decoration_editor(
+                    ui,
+                    &mut options.decoration,
+                    &mut self.decoration_name_input,
+                    &mut self.notifications,
+                );
+
+                if let Some(size) = image_size {
+                    let (mut out_width, mut out_height) = options.resize.target_size(size.x as u32, size.y as u32);
+                    if let Some(decoration) = options.decoration.active() {
+                        out_width += decoration.padding * 2;
+                        out_height += decoration.padding * 2;
+                    }
+                    ui.separator();
+                    let banner_note = if options.banner.enabled { " plus banner" } else { "" };
+                    ui.label(format!("Output: {} x {} px{}", out_width, out_height, banner_note));
+                }
+            });
+
+        if options != self.settings.get_settings().export && options.validate().is_ok() {
+            self.settings.settings_mut().export = options;
+            self.settings_dirty = true;
+        }
+        self.show_export_options = open;
+    }
+
+    /// Draw the window editing the comment embedded in exports
+    fn draw_comment_editor(&mut self, ctx: &Context) {
+        let Some(draft) = self.comment_draft.as_mut() else {
+            return;
+        };
+
+        let mut open = true;
+        let mut apply = false;
+        let mut cancel = false;
+        egui::Window::new("Image Comment")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label("Saved into exported PNG and JPEG files unless metadata is stripped");
+                ui.add(
+                    egui::TextEdit::multiline(draft)
+                        .char_limit(metadata::MAX_COMMENT_CHARS)
+                        .desired_rows(4)
+                        .hint_text("Comment"),
+                );
+                ui.horizontal(|ui| {
+                    apply = ui.button("OK").clicked();
+                    cancel = ui.button("Cancel").clicked();
+                });
+            });
+
+        if apply {
+            if let Some(comment) = self.comment_draft.take() {
+                self.set_comment(&comment);
+            }
+        } else if cancel || !open {
+            self.comment_draft = None;
+        }
+    }
+
+    /// Draw the templates submenu: saving the annotations as a template, and applying or deleting saved ones
+    fn draw_templates_menu(&mut self, ui: &mut egui::Ui) {
+        let has_annotations = !self.doc.annotations.is_empty();
+        if ui
+            .add_enabled(has_annotations, egui::Button::new("Save as Template..."))
+            .on_hover_text("Save the selected annotations, or all of them, for reuse on other captures")
+            .clicked()
+        {
+            self.template_name_draft = Some(String::new());
+            ui.close_menu();
+        }
+        let names: Vec<String> = self.templates().iter().map(|template| template.name.clone()).collect();
+        if names.is_empty() {
+            return;
+        }
+        ui.separator();
+        let has_image = !self.doc.is_empty();
+        for name in &names {
+            if ui.add_enabled(has_image, egui::Button::new(name)).clicked() {
+                self.apply_template(name);
+                ui.close_menu();
+            }
+        }
+        ui.separator();
+        ui.menu_button("Delete", |ui| {
+            for name in &names {
+                if ui.button(name).clicked() {
+                    if let Err(e) = self.delete_template(name) {
+                        self.notify_error("Template Not Deleted", &e);
+                    }
+                    ui.close_menu();
+                }
+            }
+        });
+    }
+
+    /// Draw the window naming a new template
+    fn draw_template_name_dialog(&mut self, ctx: &Context) {
+        let Some(draft) = self.template_name_draft.as_mut() else {
+            return;
+        };
+
+        let mut open = true;
+        let mut save = false;
+        let mut cancel = false;
+        let selected = self.doc.annotations.iter().filter(|a| a.is_selected).count();
+        egui::Window::new("Save as Template")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label(match selected {
+                    0 => format!("Saves all {} annotations", self.doc.annotations.len()),
+                    count => format!("Saves the {} selected annotations", count),
+                });
+                let response = ui.add(
+                    egui::TextEdit::singleline(draft)
+                        .char_limit(templates::MAX_TEMPLATE_NAME_CHARS)
+                        .hint_text("Template name"),
+                );
+                let entered = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                ui.horizontal(|ui| {
+                    save = ui.button("Save").clicked() || entered;
+                    cancel = ui.button("Cancel").clicked();
+                });
+            });
+
+        if save {
+            let name = self.template_name_draft.take().unwrap_or_default();
+            match self.save_template(&name) {
+                Ok(()) => {
+                    self.notifications.push(Severity::Info, "Template Saved", format!("Saved template {}", name.trim()));
+                }
+                Err(e) => {
+                    // Keep the window open to fix the name
+                    self.template_name_draft = Some(name);
+                    self.notify_error("Template Not Saved", &e);
+                }
+            }
+        } else if cancel || !open {
+            self.template_name_draft = None;
+        }
+    }
+
+    /// Draw the file name template window with a live preview
+    fn draw_file_naming(&mut self, ctx: &Context) {
+        let Some(draft) = self.file_naming_draft.as_mut() else {
+            return;
+        };
+
+        let extension = self.settings.get_settings().default_image_format.extension();
+        let mut open = true;
+        egui::Window::new("File Naming")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                egui::Grid::new("file_naming_grid").num_columns(2).show(ui, |ui| {
+                    ui.label("Template:");
+                    ui.text_edit_singleline(&mut draft.template);
+                    ui.end_row();
+                    ui.label("{date} format:");
+                    ui.add(egui::TextEdit::singleline(&mut draft.date_format).hint_text("System locale"));
+                    ui.end_row();
+                    ui.label("{time} format:");
+                    ui.add(egui::TextEdit::singleline(&mut draft.time_format).hint_text("System locale"));
+                    ui.end_row();
+                    ui.label("{counter} digits:");
+                    ui.add(egui::DragValue::new(&mut draft.counter_width).clamp_range(1..=naming::MAX_COUNTER_WIDTH));
+                    ui.end_row();
+                    ui.label("{counter} restarts:");
+                    ui.horizontal(|ui| {
+                        for reset in CounterReset::all() {
+                            ui.radio_value(&mut draft.counter_reset, reset, reset.to_string());
+                        }
+                    });
+                    ui.end_row();
+                });
+                ui.weak("Tokens: {date} {time} {counter}, and {app} {title} of the captured window");
+                ui.weak("Templates with the same text before {counter} share its numbers");
+                ui.weak("Formats: %Y %y %m %d %H %I %M %S %p, %-d without padding, %EC%Ey for the Japanese era");
+                ui.separator();
+                let now = DateTime::now_local();
+                let counter = self.file_counters.peek(draft, &now);
+                let preview = draft.validate().and_then(|_| {
+                    draft.render_numbered(&now, &naming::system_locale(), &ForegroundWindow::default(), counter)
+                });
+                match preview {
+                    Ok(name) => {
+                        ui.label(format!("Preview: {}.{}", name, extension));
+                    }
+                    Err(e) => {
+                        ui.colored_label(ui.visuals().error_fg_color, e.to_string());
+                    }
+                }
+            });
+
+        // Only valid templates are applied, so the draft can be invalid while typing
+        if *draft != self.settings.get_settings().file_naming && draft.validate().is_ok() {
+            self.settings.settings_mut().file_naming = draft.clone();
+            self.settings_dirty = true;
+        }
+        if !open {
+            self.file_naming_draft = None;
+        }
+    }
+
+    /// Draw the capture presets window
+    ///
+    /// Presets are edited as a draft and replace the saved ones on Apply,
+    /// which also registers their hotkeys again.
+    fn draw_capture_presets(&mut self, ctx: &Context) {
+        if self.presets_draft.is_none() {
+            return;
+        }
+        let screens = if self.capture.is_ready() {
+            self.capture_service().map(|service| service.get_screens()).unwrap_or_default()
+        } else {
+            Vec::new()
+        };
+        let settings = self.settings.get_settings();
+        let reserved = [
+            Hotkey {
+                modifiers: settings.hotkey_modifiers,
+                vk_code: settings.hotkey_vk_code,
+            },
+            settings.window_hotkey,
+            settings.cursor_monitor_hotkey,
+            settings.color_picker_hotkey,
+        ];
+        let Some((presets, selected)) = self.presets_draft.as_mut() else {
+            return;
+        };
+
+        let mut open = true;
+        let mut apply = false;
+        let mut cancel = false;
+        egui::Window::new("Capture Presets")
+            .open(&mut open)
+            .resizable(true)
+            .show(ctx, |ui| {
+                if presets.is_empty() {
+                    ui.weak("No capture presets");
+                }
+                for (index, preset) in presets.iter().enumerate() {
+                    if ui.selectable_label(*selected == index, &preset.name).clicked() {
+                        *selected = index;
+                    }
+                }
+                ui.horizontal(|ui| {
+                    if ui.button("Add").clicked() {
+                        let mut number = presets.len() + 1;
+                        while presets.iter().any(|preset| preset.name == format!("Preset {}", number)) {
+                            number += 1;
+                        }
+                        presets.push(CapturePreset {
+                            name: format!("Preset {}", number),
+                            ..CapturePreset::default()
+                        });
+                        *selected = presets.len() - 1;
+                    }
+                    if ui.add_enabled(*selected < presets.len(), egui::Button::new("Remove")).clicked() {
+                        presets.remove(*selected);
+                        *selected = selected.saturating_sub(1);
+                    }
+                });
+
+                if let Some(preset) = presets.get_mut(*selected) {
+                    ui.separator();
+                    Self::draw_preset_fields(ui, preset, &screens);
+                }
+
+                ui.separator();
+                let valid = match presets::validate_presets(presets, &reserved) {
+                    Ok(()) => true,
+                    Err(e) => {
+                        ui.colored_label(ui.visuals().error_fg_color, e.to_string());
+                        false
+                    }
+                };
+                ui.horizontal(|ui| {
+                    apply = ui.add_enabled(valid, egui::Button::new("Apply")).clicked();
+                    cancel = ui.button("Cancel").clicked();
+                });
+            });
+
+        if apply {
+            let presets = presets.clone();
+            self.settings.settings_mut().capture_presets = presets;
+            self.settings_dirty = true;
+            // Hotkeys are registered once, so register them again with the new presets
+            if self.hotkeys.take().is_some() {
+                self.start_hotkeys();
+            }
+        }
+        if apply || cancel || !open {
+            self.presets_draft = None;
+        }
+    }
+
+    /// Draw the Save As window
+    fn draw_save_as(&mut self, ctx: &Context) {
+        let destinations = self.settings.get_settings().save_destinations.clone();
+        let Some(draft) = self.save_as.as_mut() else {
+            return;
+        };
+
+        let mut open = true;
+        let mut save = false;
+        let mut manage = false;
+        egui::Window::new("Save As")
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                egui::Grid::new("save_as_grid").num_columns(2).show(ui, |ui| {
+                    ui.label("Save to");
+                    let selected = draft
+                        .destination
+                        .and_then(|id| destinations.iter().find(|destination| destination.id == id))
+                        .map_or_else(|| "This computer".to_string(), |destination| destination.name.clone());
+                    egui::ComboBox::from_id_source("save_as_destination")
+                        .selected_text(selected)
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut draft.destination, None, "This computer");
+                            for destination in &destinations {
+                                ui.selectable_value(&mut draft.destination, Some(destination.id), &destination.name)
+                                    .on_hover_text(destination.file_location(""));
+                            }
+                        });
+                    ui.end_row();
+
+                    if draft.destination.is_none() {
+                        ui.label("Folder");
+                        ui.text_edit_singleline(&mut draft.folder);
+                        ui.end_row();
+                    }
+
+                    ui.label("File name");
+                    let response = ui.text_edit_singleline(&mut draft.file_name);
+                    save = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                    ui.end_row();
+                });
+                ui.horizontal(|ui| {
+                    save |= ui.add_enabled(!draft.file_name.trim().is_empty(), egui::Button::new("Save")).clicked();
+                    manage = ui.button("Destinations...").clicked();
+                });
+            });
+
+        if manage {
+            self.destinations_draft = Some((destinations, 0, String::new()));
+        }
+        if save {
+            if let Some(draft) = self.save_as.take() {
+                if let Err(e) = self.submit_save_as(&draft) {
+                    self.show_error("Save Failed", e.to_string());
+                    self.save_as = Some(draft);
+                }
+            }
+        } else if !open {
+            self.save_as = None;
+        }
+    }
+
+    /// Draw the remote save destinations window
+    ///
+    /// Passwords are stored in the credential store as soon as they are
+    /// entered, and only the destinations themselves wait for Apply.
+    fn draw_save_destinations(&mut self, ctx: &Context) {
+        let Some((destinations, selected, password)) = self.destinations_draft.as_mut() else {
+            return;
+        };
+
+        let mut open = true;
+        let mut apply = false;
+        let mut cancel = false;
+        let mut stored = None;
+        egui::Window::new("Save Destinations")
+            .open(&mut open)
+            .resizable(true)
+            .show(ctx, |ui| {
+                if destinations.is_empty() {
+                    ui.weak("No save destinations");
+                }
+                for (index, destination) in destinations.iter().enumerate() {
+                    let label = format!("{} ({})", destination.name, destination.location);
+                    if ui.selectable_label(*selected == index, label).clicked() {
+                        *selected = index;
+                        password.clear();
+                    }
+                }
+                ui.horizontal(|ui| {
+                    if ui.button("Add").clicked() {
+                        let mut number = destinations.len() + 1;
+                        while destinations.iter().any(|destination| destination.name == format!("Destination {}", number)) {
+                            number += 1;
+                        }
+                        let [sftp, ..] = RemoteLocation::all();
+                        destinations.push(SaveDestination::new(format!("Destination {}", number), sftp));
+                        *selected = destinations.len() - 1;
+                        password.clear();
+                    }
+                    if ui.add_enabled(*selected < destinations.len(), egui::Button::new("Remove")).clicked() {
+                        destinations.remove(*selected);
+                        *selected = selected.saturating_sub(1);
+                        password.clear();
+                    }
+                });
+
+                if let Some(destination) = destinations.get_mut(*selected) {
+                    ui.separator();
+                    egui::Grid::new("save_destination_grid").num_columns(2).show(ui, |ui| {
+                        ui.label("Name");
+                        ui.text_edit_singleline(&mut destination.name);
+                        ui.end_row();
+
+                        ui.label("Kind");
+                        egui::ComboBox::from_id_source("save_destination_kind")
+                            .selected_text(destination.location.to_string())
+                            .show_ui(ui, |ui| {
+                                for location in RemoteLocation::all() {
+                                    let current =
+                                        std::mem::discriminant(&location) == std::mem::discriminant(&destination.location);
+                                    if ui.selectable_label(current, location.to_string()).clicked() && !current {
+                                        destination.location = location;
+                                    }
+                                }
+                            });
+                        ui.end_row();
+
+                        match &mut destination.location {
+                            RemoteLocation::Sftp { host, port, username, directory, fingerprint } => {
+                                Self::draw_server_fields(ui, host, port, username, directory, "");
+                                ui.label("Host key");
+                                ui.add(egui::TextEdit::singleline(fingerprint).hint_text("SHA256:..."))
+                                    .on_hover_text("Fingerprint of the server's host key, as shown by ssh-keygen -l");
+                                ui.end_row();
+                            }
+                            RemoteLocation::Ftp { host, port, username, directory } => {
+                                Self::draw_server_fields(ui, host, port, username, directory, "anonymous");
+                            }
+                            RemoteLocation::NetworkShare { path, username } => {
+                                ui.label("Folder");
+                                let mut text = path.display().to_string();
+                                if ui.add(egui::TextEdit::singleline(&mut text).hint_text(r"\\server\share\folder")).changed() {
+                                    *path = PathBuf::from(text);
+                                }
+                                ui.end_row();
+                                ui.label("User name");
+                                ui.add(egui::TextEdit::singleline(username).hint_text("Current Windows user"));
+                                ui.end_row();
+                            }
+                        }
+
+                        ui.label("Password");
+                        ui.add_enabled_ui(destination.uses_password(), |ui| {
+                            ui.horizontal(|ui| {
+                                ui.add(egui::TextEdit::singleline(password).password(true));
+                                if ui
+                                    .add_enabled(!password.is_empty(), egui::Button::new("Store"))
+                                    .on_hover_text("Store in the Windows Credential Manager, replacing the stored one")
+                                    .clicked()
+                                {
+                                    let result = credentials::store(&destination.credential_key(), password);
+                                    stored = Some(result.map(|()| destination.name.clone()));
+                                    password.clear();
+                                }
+                            });
+                        });
+                        ui.end_row();
+                    });
+                }
+
+                ui.separator();
+                let valid = match remote::validate_destinations(destinations) {
+                    Ok(()) => true,
+                    Err(e) => {
+                        ui.colored_label(ui.visuals().error_fg_color, e.to_string());
+                        false
+                    }
+                };
+                ui.horizontal(|ui| {
+                    apply = ui.add_enabled(valid, egui::Button::new("Apply")).clicked();
+                    cancel = ui.button("Cancel").clicked();
+                });
+            });
+        let applied = apply.then(|| destinations.clone());
+
+        match stored {
+            Some(Ok(name)) => {
+                self.notifications.push(Severity::Info, "Password Stored", format!("Stored the password of {}", name));
+            }
+            Some(Err(e)) => self.notify_error("Password Not Stored", &e),
+            None => {}
+        }
+        if let Some(destinations) = applied {
+            // Passwords of removed destinations would otherwise stay in the credential store
+            let removed: Vec<String> = self
+                .settings
+                .get_settings()
+                .save_destinations
+                .iter()
+                .filter(|old| !destinations.iter().any(|destination| destination.id == old.id))
+                .map(SaveDestination::credential_key)
+                .collect();
+            for key in removed {
+                if let Err(e) = credentials::delete(&key) {
+                    self.notify_error("Password Not Deleted", &e);
+                }
+            }
+            self.settings.settings_mut().save_destinations = destinations;
+            self.settings_dirty = true;
+        }
+        if apply || cancel || !open {
+            self.destinations_draft = None;
+        }
+    }
+
+    /// Draw the server fields of an SFTP or FTP destination
+    fn draw_server_fields(
+        ui: &mut egui::Ui,
+        host: &mut String,
+        port: &mut u16,
+        username: &mut String,
+        directory: &mut String,
+        username_hint: &str,
+    ) {
+        ui.label("Host");
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(host).hint_text("files.example.com"));
+            ui.add(egui::DragValue::new(port).clamp_range(1..=u16::MAX).prefix("Port "));
+        });
+        ui.end_row();
+        ui.label("User name");
+        ui.add(egui::TextEdit::singleline(username).hint_text(username_hint));
+        ui.end_row();
+        ui.label("Folder");
+        ui.add(egui::TextEdit::singleline(directory).hint_text("Login folder"));
+        ui.end_row();
+    }
+
+    /// Draw the progress of uploads to remote destinations
+    fn draw_transfers(&mut self, ctx: &Context) {
+        if self.transfers.is_empty() {
+            return;
+        }
+        egui::Window::new("Transfers")
+            .collapsible(true)
+            .resizable(false)
+            .show(ctx, |ui| {
+                for transfer in &self.transfers {
+                    ui.label(format!("{} to {}", transfer.file_name, transfer.destination));
+                    let fraction = if transfer.total == 0 { 1.0 } else { transfer.sent as f32 / transfer.total as f32 };
+                    ui.add(egui::ProgressBar::new(fraction).show_percentage());
+                }
+            });
+    }
+
+    /// Draw the share targets window
+    ///
+    /// Secrets are stored in the credential store as soon as they are
+    /// entered, and only the targets themselves wait for Apply.
+    fn draw_share_targets(&mut self, ctx: &Context) {
+        let Some((targets, selected, secret)) = self.share_targets_draft.as_mut() else {
+            return;
+        };
+
+        let mut open = true;
+        let mut apply = false;
+        let mut cancel = false;
+        let mut stored = None;
+        egui::Window::new("Share Targets")
+            .open(&mut open)
+            .resizable(true)
+            .show(ctx, |ui| {
+                if targets.is_empty() {
+                    ui.weak("No share targets");
+                }
+                for (index, target) in targets.iter().enumerate() {
+                    if ui.selectable_label(*selected == index, format!("{} ({})", target.name, target.service)).clicked() {
+                        *selected = index;
+                        secret.clear();
+                    }
+                }
+                ui.horizontal(|ui| {
+                    if ui.button("Add").clicked() {
+                        let mut number = targets.len() + 1;
+                        while targets.iter().any(|target| target.name == format!("Target {}", number)) {
+                            number += 1;
+                        }
+                        targets.push(ShareTarget::new(
+                            format!("Target {}", number),
+                            ShareService::Slack { channel: String::new() },
+                        ));
+                        *selected = targets.len() - 1;
+                        secret.clear();
+                    }
+                    if ui.add_enabled(*selected < targets.len(), egui::Button::new("Remove")).clicked() {
+                        targets.remove(*selected);
+                        *selected = selected.saturating_sub(1);
+                        secret.clear();
+                    }
+                });
+
+                if let Some(target) = targets.get_mut(*selected) {
+                    ui.separator();
+                    egui::Grid::new("share_target_grid").num_columns(2).show(ui, |ui| {
+                        ui.label("Name");
+                        ui.text_edit_singleline(&mut target.name);
+                        ui.end_row();
+
+                        ui.label("Service");
+                        egui::ComboBox::from_id_source("share_service")
+                            .selected_text(target.service.to_string())
+                            .show_ui(ui, |ui| {
+                                for service in ShareService::all() {
+                                    let current = std::mem::discriminant(&service) == std::mem::discriminant(&target.service);
+                                    if ui.selectable_label(current, service.to_string()).clicked() && !current {
+                                        target.service = service;
+                                    }
+                                }
+                            });
+                        ui.end_row();
+
+                        if let ShareService::Slack { channel } = &mut target.service {
+                            ui.label("Channel");
+                            ui.add(egui::TextEdit::singleline(channel).hint_text("#screenshots"));
+                            ui.end_row();
+                        }
+
+                        ui.label(target.service.secret_label());
+                        ui.horizontal(|ui| {
+                            ui.add(egui::TextEdit::singleline(secret).password(true));
+                            if ui
+                                .add_enabled(!secret.trim().is_empty(), egui::Button::new("Store"))
+                                .on_hover_text("Store in the Windows Credential Manager, replacing the stored one")
+                                .clicked()
+                            {
+                                let result = target
+                                    .service
+                                    .validate_secret(secret)
+                                    .and_then(|()| credentials::store(&target.credential_key(), secret.trim()));
+                                stored = Some(result.map(|()| target.name.clone()));
+                                secret.clear();
+                            }
+                        });
+                        ui.end_row();
+                    });
+                }
+
+                ui.separator();
+                let valid = match share::validate_share_targets(targets) {
+                    Ok(()) => true,
+                    Err(e) => {
+                        ui.colored_label(ui.visuals().error_fg_color, e.to_string());
+                        false
+                    }
+                };
+                ui.horizontal(|ui| {
+                    apply = ui.add_enabled(valid, egui::Button::new("Apply")).clicked();
+                    cancel = ui.button("Cancel").clicked();
+                });
+            });
+        let applied = apply.then(|| targets.clone());
+
+        match stored {
+            Some(Ok(name)) => {
+                self.notifications.push(Severity::Info, "Secret Stored", format!("Stored the secret of {}", name));
+            }
+            Some(Err(e)) => self.notify_error("Secret Not Stored", &e),
+            None => {}
+        }
+        if let Some(targets) = applied {
+            // Secrets of removed targets would otherwise stay in the credential store
+            let removed: Vec<String> = self
+                .settings
+                .get_settings()
+                .share_targets
+                .iter()
+                .filter(|old| !targets.iter().any(|target| target.id == old.id))
+                .map(ShareTarget::credential_key)
+                .collect();
+            for key in removed {
+                if let Err(e) = credentials::delete(&key) {
+                    self.notify_error("Secret Not Deleted", &e);
+                }
+            }
+            self.settings.settings_mut().share_targets = targets;
+            self.settings_dirty = true;
+        }
+        if apply || cancel || !open {
+            self.share_targets_draft = None;
+        }
+    }
+
+    /// Draw the grid rows choosing a capture target
+    fn draw_target_fields(ui: &mut egui::Ui, id_source: &str, target: &mut PresetTarget, screens: &[ScreenInfo]) {
+        ui.label("Capture:");
+        egui::ComboBox::from_id_source(id_source)
+            .selected_text(target.to_string())
+            .show_ui(ui, |ui| {
+                let mut targets: Vec<PresetTarget> =
+                    screens.iter().map(|screen| PresetTarget::Monitor(screen.index)).collect();
+                if let PresetTarget::Monitor(index) = *target {
+                    // Keep a disconnected monitor selectable
+                    if !screens.iter().any(|screen| screen.index == index) {
+                        targets.push(*target);
+                    }
+                }
+                for choice in targets {
+                    ui.selectable_value(target, choice, choice.to_string());
+                }
+                let region = match *target {
+                    PresetTarget::Region { .. } => *target,
+                    _ => PresetTarget::Region { x: 0, y: 0, width: 800, height: 600 },
+                };
+                ui.selectable_value(target, region, "Region");
+                let area = match *target {
+                    PresetTarget::Area { .. } => *target,
+                    _ => PresetTarget::Area { monitor: MonitorAnchor::Primary, placement: AreaPlacement::LEFT_HALF },
+                };
+                ui.selectable_value(target, area, "Area of Monitor");
+                ui.selectable_value(target, PresetTarget::ActiveWindow, "Active Window");
+            });
+        ui.end_row();
+
+        if let PresetTarget::Area { monitor, placement } = target {
+            ui.label("Monitor:");
+            egui::ComboBox::from_id_source((id_source, "monitor"))
+                .selected_text(monitor.to_string())
+                .show_ui(ui, |ui| {
+                    let mut anchors = vec![MonitorAnchor::Primary];
+                    anchors.extend(screens.iter().map(|screen| MonitorAnchor::Monitor(screen.index)));
+                    if !anchors.contains(monitor) {
+                        // Keep a disconnected monitor selectable
+                        anchors.push(*monitor);
+                    }
+                    for anchor in anchors {
+                        ui.selectable_value(monitor, anchor, anchor.to_string());
+                    }
+                });
+            ui.end_row();
+
+            ui.label("Area:");
+            ui.vertical(|ui| {
+                ui.horizontal(|ui| {
+                    for (name, half) in AreaPlacement::halves() {
+                        ui.selectable_value(placement, half, name);
+                    }
+                });
+                ui.horizontal(|ui| {
+                    let fraction = match *placement {
+                        AreaPlacement::Fraction { .. } => *placement,
+                        _ => AreaPlacement::LEFT_HALF,
+                    };
+                    if ui.radio(matches!(placement, AreaPlacement::Fraction { .. }), "Part").clicked() {
+                        *placement = fraction;
+                    }
+                    let centered = match *placement {
+                        AreaPlacement::Centered { .. } => *placement,
+                        _ => AreaPlacement::Centered { width: 1920, height: 1080 },
+                    };
+                    if ui.radio(matches!(placement, AreaPlacement::Centered { .. }), "Centered").clicked() {
+                        *placement = centered;
+                    }
+                    let offset = match *placement {
+                        AreaPlacement::Offset { .. } => *placement,
+                        _ => AreaPlacement::Offset { x: 0, y: 0, width: 800, height: 600 },
+                    };
+                    if ui.radio(matches!(placement, AreaPlacement::Offset { .. }), "Offset").clicked() {
+                        *placement = offset;
+                    }
+                });
+                ui.horizontal(|ui| match placement {
+                    AreaPlacement::Fraction { x, y, width, height } => {
+                        fn percent<'a>(value: &'a mut f32, prefix: &str) -> egui::DragValue<'a> {
+                            egui::DragValue::new(value)
+                                .prefix(prefix)
+                                .custom_formatter(|n, _| format!("{:.1}%", n * 100.0))
+                                .custom_parser(|s| s.trim_end_matches('%').trim().parse::<f64>().ok().map(|n| n / 100.0))
+                                .speed(0.005)
+                                .clamp_range(0.0..=1.0)
+                        }
+                        ui.add(percent(x, "x: "));
+                        ui.add(percent(y, "y: "));
+                        ui.add(percent(width, "w: "));
+                        ui.add(percent(height, "h: "));
+                    }
+                    AreaPlacement::Centered { width, height } => {
+                        ui.add(egui::DragValue::new(width).prefix("w: ").clamp_range(1..=u16::MAX as u32));
+                        ui.add(egui::DragValue::new(height).prefix("h: ").clamp_range(1..=u16::MAX as u32));
+                    }
+                    AreaPlacement::Offset { x, y, width, height } => {
+                        ui.add(egui::DragValue::new(x).prefix("x: "));
+                        ui.add(egui::DragValue::new(y).prefix("y: "));
+                        ui.add(egui::DragValue::new(width).prefix("w: ").clamp_range(1..=u16::MAX as u32));
+                        ui.add(egui::DragValue::new(height).prefix("h: ").clamp_range(1..=u16::MAX as u32));
+                    }
+                });
+            });
+            ui.end_row();
+        }
+
+        if let PresetTarget::Region { x, y, width, height } = target {
+            ui.label("Region:");
+            ui.horizontal(|ui| {
+                ui.add(egui::DragValue::new(x).prefix("x: "));
+                ui.add(egui::DragValue::new(y).prefix("y: "));
+                ui.add(egui::DragValue::new(width).prefix("w: ").clamp_range(1..=u16::MAX as u32));
+                ui.add(egui::DragValue::new(height).prefix("h: ").clamp_range(1..=u16::MAX as u32));
+            });
+            ui.end_row();
+        }
+    }
+
+    /// Draw the fields of one capture preset
+    fn draw_preset_fields(ui: &mut egui::Ui, preset: &mut CapturePreset, screens: &[ScreenInfo]) {
+        egui::Grid::new("capture_preset_grid").num_columns(2).show(ui, |ui| {
+            ui.label("Name:");
+            ui.text_edit_singleline(&mut preset.name);
+            ui.end_row();
+
+            Self::draw_target_fields(ui, "capture_preset_target", &mut preset.target, screens);
+
+            ui.label("Then:");
+            egui::ComboBox::from_id_source("capture_preset_output")
+                .selected_text(preset.output.to_string())
+                .show_ui(ui, |ui| {
+                    for output in PresetOutput::all() {
+                        ui.selectable_value(&mut preset.output, output, output.to_string());
+                    }
+                });
+            ui.end_row();
+
+            if preset.output == PresetOutput::Upload {
+                ui.label("Upload command:");
+                ui.text_edit_singleline(&mut preset.upload_command.program);
+                ui.end_row();
+                ui.label("Arguments:");
+                let mut args = preset.upload_command.args.join(" ");
+                if ui
+                    .text_edit_singleline(&mut args)
+                    .on_hover_text(format!("{} is replaced with the saved file", crate::routing::FILE_PLACEHOLDER))
+                    .changed()
+                {
+                    preset.upload_command.args = args.split_whitespace().map(str::to_string).collect();
+                }
+                ui.end_row();
+            }
+
+            if preset.output.saves_file() {
+                ui.label("Format:");
+                egui::ComboBox::from_id_source("capture_preset_format")
+                    .selected_text(preset.format.to_string())
+                    .show_ui(ui, |ui| {
+                        for format in ImageFormat::all() {
+                            let label = format.to_string();
+                            ui.selectable_value(&mut preset.format, format, label);
+                        }
+                    });
+                ui.end_row();
+                ui.label("File name:");
+                ui.text_edit_singleline(&mut preset.file_naming.template);
+                ui.end_row();
+            }
+
+            ui.label("Hotkey:");
+            ui.horizontal(|ui| {
+                let mut enabled = preset.hotkey.is_some();
+                if ui.checkbox(&mut enabled, "").changed() {
+                    preset.hotkey = enabled.then_some(Hotkey {
+                        modifiers: hotkeys::MOD_CONTROL | hotkeys::MOD_ALT,
+                        vk_code: 0x31, // '1' key
+                    });
+                }
+                let Some(hotkey) = preset.hotkey.as_mut() else {
+                    return;
+                };
+                for (flag, name) in hotkeys::MODIFIERS {
+                    let mut set = hotkey.modifiers & flag != 0;
+                    if ui.checkbox(&mut set, name).changed() {
+                        hotkey.modifiers ^= flag;
+                    }
+                }
+                let mut key = hotkeys::describe(0, hotkey.vk_code);
+                if ui.add(egui::TextEdit::singleline(&mut key).desired_width(40.0)).changed() {
+                    if let Some(vk_code) = hotkeys::parse_key(&key) {
+                        hotkey.vk_code = vk_code;
+                    }
+                }
+            });
+            ui.end_row();
+        });
+    }
+
+    /// Draw the per-monitor save routing window
+    fn draw_monitor_routing(&mut self, ctx: &Context) {
+        if !self.show_monitor_routing {
+            return;
+        }
+
+        let screens = if self.capture.is_ready() {
+            self.capture_service().map(|service| service.get_screens()).unwrap_or_default()
+        } else {
+            Vec::new()
+        };
+
+        let mut open = true;
+        let mut changed = false;
+        egui::Window::new("Monitor Routing")
+            .open(&mut open)
+            .resizable(true)
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "Captures from a routed monitor are saved to its folder, then its command runs. Use {} in arguments for the saved file.",
+                    crate::routing::FILE_PLACEHOLDER
+                ));
+                if !self.capture.is_ready() {
+                    ui.weak("Detecting monitors...");
+                }
+
+                let routing = &mut self.settings.settings_mut().monitor_routing;
+                let mut monitors: Vec<(String, MonitorIdentity)> = screens
+                    .iter()
+                    .map(|screen| {
+                        let primary = if screen.is_primary { " (primary)" } else { "" };
+                        (format!("Monitor {}{}", screen.index + 1, primary), MonitorIdentity::from_screen(screen))
+                    })
+                    .collect();
+                // Keep routes of disconnected monitors editable
+                for route in &routing.routes {
+                    if !monitors.iter().any(|(_, monitor)| *monitor == route.monitor) {
+                        monitors.push(("Disconnected monitor".to_string(), route.monitor));
+                    }
+                }
+
+                for (name, monitor) in monitors {
+                    ui.separator();
+                    let mut routed = routing.routes.iter().any(|route| route.monitor == monitor);
+                    if ui.checkbox(&mut routed, format!("{} - {}", name, monitor)).changed() {
+                        if routed {
+                            routing.set_route(MonitorRoute::new(monitor));
+                        } else {
+                            routing.remove_route(monitor);
+                        }
+                        changed = true;
+                    }
+                    let Some(route) = routing.routes.iter_mut().find(|route| route.monitor == monitor) else {
+                        continue;
+                    };
+
+                    ui.horizontal(|ui| {
+                        ui.label("Folder:");
+                        let mut folder = route.directory.as_ref().map(|dir| dir.display().to_string()).unwrap_or_default();
+                        if ui.text_edit_singleline(&mut folder).on_hover_text("Empty uses the default folder").changed() {
+                            route.directory = (!folder.trim().is_empty()).then(|| PathBuf::from(folder.trim()));
+                            changed = true;
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Command:");
+                        let command = route.command.get_or_insert_with(PostSaveCommand::default);
+                        changed |= ui.text_edit_singleline(&mut command.program).changed();
+                        ui.label("Arguments:");
+                        let mut args = command.args.join(" ");
+                        if ui.text_edit_singleline(&mut args).changed() {
+                            command.args = args.split_whitespace().map(str::to_string).collect();
+                            changed = true;
+                        }
+                    });
+                    if route.command.as_ref().is_some_and(|command| command.program.trim().is_empty()) {
+                        route.command = None;
+                    }
+                }
+            });
+
+        self.show_monitor_routing = open;
+        if changed {
+            self.settings_dirty = true;
+        }
+    }
+
+    /// Draw the About window with version, build, and licence information
+    fn draw_about(&mut self, ctx: &Context) {
+        if !self.show_about {
+            return;
+        }
+
+        let mut open = true;
+        let mut open_log_folder = false;
+        egui::Window::new("About")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, Vec2::ZERO)
+            .show(ctx, |ui| {
+                ui.heading(APP_TITLE);
+                egui::Grid::new("about_build_info").num_columns(2).show(ui, |ui| {
+                    ui.label("Version:");
+                    ui.label(about::VERSION);
+                    ui.end_row();
+                    ui.label("Commit:");
+                    ui.label(about::GIT_HASH);
+                    ui.end_row();
+                    ui.label("Built:");
+                    ui.label(about::build_date());
+                    ui.end_row();
+                });
+
+                ui.separator();
+                ui.collapsing("Bundled Licences", |ui| {
+                    egui::Grid::new("about_licenses").num_columns(2).striped(true).show(ui, |ui| {
+                        for bundled in about::BUNDLED_LICENSES {
+                            ui.hyperlink_to(bundled.name, bundled.homepage);
+                            ui.label(bundled.license);
+                            ui.end_row();
+                        }
+                    });
+                });
+
+                ui.separator();
+                if ui
+                    .button("Open Log Folder")
+                    .on_hover_text(about::log_file_path().display().to_string())
+                    .clicked()
+                {
+                    open_log_folder = true;
+                }
+            });
+
+        if open_log_folder {
+            if let Err(e) = about::open_folder(&about::log_directory()) {
+                self.show_error("Open Log Folder Failed", e.to_string());
+            }
+        }
+        self.show_about = open;
+    }
+
+    /// Draw notification toasts in the bottom-right corner
+    fn draw_toasts(&mut self, ctx: &Context) {
+        if !self.notifications.expire(Instant::now()) && self.notifications.toasts().is_empty() {
+            return;
+        }
+
+        let mut dismissed = None;
+        egui::Area::new("notification_toasts")
+            .anchor(egui::Align2::RIGHT_BOTTOM, Vec2::new(-12.0, -12.0))
+            .order(egui::Order::Foreground)
+            .show(ctx, |ui| {
+                ui.set_max_width(320.0);
+                for toast in self.notifications.toasts() {
+                    egui::Frame::popup(ui.style()).show(ui, |ui| {
+                        let color = match toast.severity {
+                            Severity::Info => ui.visuals().text_color(),
+                            Severity::Warning => ui.visuals().warn_fg_color,
+                            Severity::Error => ui.visuals().error_fg_color,
+                        };
+                        ui.horizontal(|ui| {
+                            ui.colored_label(color, egui::RichText::new(&toast.title).strong());
+                            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                                if accessibility::label(ui.small_button("×"), egui::WidgetType::Button, "Dismiss").on_hover_text("Dismiss").clicked() {
+                                    dismissed = Some(toast.id);
+                                }
+                            });
+                        });
+                        ui.label(&toast.message);
+                    });
+                }
+            });
+
+        if let Some(id) = dismissed {
+            self.notifications.dismiss(id);
+        }
+        // Wake up to take down toasts that time out
+        if self.notifications.expire(Instant::now()) {
+            ctx.request_repaint_after(Duration::from_secs(1));
+        }
+    }
+
+    /// Draw the log of past errors and warnings
+    fn draw_error_log(&mut self, ctx: &Context) {
+        if !self.show_error_log {
+            return;
+        }
+
+        let mut open = true;
+        let mut clear = false;
+        let mut open_log_folder = false;
+        egui::Window::new("Error Log")
+            .open(&mut open)
+            .default_size(Vec2::new(520.0, 320.0))
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    clear = ui.button("Clear").clicked();
+                    open_log_folder = ui
+                        .button("Open Log Folder")
+                        .on_hover_text(about::log_file_path().display().to_string())
+                        .clicked();
+                });
+                ui.separator();
+
+                if self.notifications.history().next().is_none() {
+                    ui.weak("No errors or warnings");
+                    return;
+                }
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    egui::Grid::new("error_log").num_columns(4).striped(true).show(ui, |ui| {
+                        for entry in self.notifications.history() {
+                            let color = match entry.severity {
+                                Severity::Info => ui.visuals().text_color(),
+                                Severity::Warning => ui.visuals().warn_fg_color,
+                                Severity::Error => ui.visuals().error_fg_color,
+                            };
+                            let time = entry.time;
+                            ui.label(format!("{:02}:{:02}:{:02}", time.hour, time.minute, time.second));
+                            ui.colored_label(color, entry.severity.to_string());
+                            ui.label(&entry.title);
+                            ui.label(&entry.message);
+                            ui.end_row();
+                        }
+                    });
+                });
+            });
+
+        if clear {
+            self.notifications.clear_history();
+        }
+        if open_log_folder {
+            if let Err(e) = about::open_folder(&about::log_directory()) {
+                self.notify_error("Open Log Folder Failed", &e);
+            }
+        }
+        self.show_error_log = open;
+    }
+
+    /// Draw the capture history with search, re-open, re-upload, and CSV export
+    fn draw_history(&mut self, ctx: &Context) {
+        if !self.show_history {
+            return;
+        }
+        let Some(history) = &self.history else {
+            self.show_history = false;
+            return;
+        };
+
+        let uploaders: Vec<(String, PostSaveCommand)> = self
+            .settings
+            .get_settings()
+            .capture_presets
+            .iter()
+            .filter(|preset| preset.output == PresetOutput::Upload)
+            .map(|preset| (preset.name.clone(), preset.upload_command.clone()))
+            .collect();
+        let mut open = true;
+        let mut reopen = None;
+        let mut reupload = None;
+        let mut export_csv = false;
+        let mut collage = None;
+        let mut clear = false;
+        egui::Window::new("Capture History")
+            .open(&mut open)
+            .default_size(Vec2::new(720.0, 360.0))
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Search:");
+                    ui.text_edit_singleline(&mut self.history_query);
+                    export_csv = ui.button("Export CSV").on_hover_text("Export the listed captures").clicked();
+                    if ui.button("Create Collage").on_hover_text("Arrange the listed saved captures in one image").clicked() {
+                        let captures = history
+                            .search(&self.history_query)
+                            .into_iter()
+                            .filter_map(|entry| entry.path.clone())
+                            .collect();
+                        collage = Some(CollageSources { captures, ..CollageSources::default() });
+                    }
+                    clear = ui.button("Clear").clicked();
+                });
+                ui.separator();
+
+                let entries = history.search(&self.history_query);
+                if entries.is_empty() {
+                    ui.weak("No captures");
+                    return;
+                }
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    egui::Grid::new("capture_history").num_columns(6).striped(true).show(ui, |ui| {
+                        for entry in entries {
+                            ui.label(entry.time().to_string());
+                            ui.label(&entry.target);
+                            ui.label(format!("{}x{}", entry.width, entry.height));
+                            match &entry.path {
+                                Some(path) => {
+                                    let name = path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+                                    if ui.link(name).on_hover_text(path.display().to_string()).clicked() {
+                                        reopen = Some(path.clone());
+                                    }
+                                }
+                                None => {
+                                    ui.weak("Not saved");
+                                }
+                            }
+                            match &entry.upload_url {
+                                Some(url) => {
+                                    ui.hyperlink(url);
+                                }
+                                None => {
+                                    ui.label("");
+                                }
+                            }
+                            ui.add_enabled_ui(entry.path.is_some() && !uploaders.is_empty(), |ui| {
+                                ui.menu_button("Re-upload", |ui| {
+                                    for (name, command) in &uploaders {
+                                        if ui.button(name).clicked() {
+                                            reupload = entry.path.clone().map(|path| (entry.id, path, command.clone()));
+                                            ui.close_menu();
+                                        }
+                                    }
+                                });
+                            });
+                            ui.end_row();
+                        }
+                    });
+                });
+            });
+        self.show_history = open;
+        if collage.is_some() {
+            self.collage_sources = collage;
+        }
+
+        if let Some(path) = reopen {
+            self.open_and_report(&path);
+        }
+        if let Some((id, path, command)) = reupload {
+            if let Err(e) = self.start_upload(&command, &path, Some(id)) {
+                self.show_error("Upload Failed", format!("{}\n{}", command.program, e));
+            }
+        }
+        if export_csv {
+            match self.export_history_csv() {
+                Ok(path) => {
+                    self.notifications.push(Severity::Info, "Capture History Exported", path.display().to_string());
+                }
+                Err(e) => self.notify_error("Export Failed", &e),
+            }
+        }
+        if clear {
+            if let Some(Err(e)) = self.history.as_mut().map(CaptureHistory::clear) {
+                self.notify_error("Capture History Not Cleared", &e);
+            }
+        }
+    }
+
+    /// List the most recent captures with their thumbnails
+    ///
+    /// Clicking a saved capture opens it, and middle-clicking copies it to the
+    /// clipboard. Captures that were never saved are shown but disabled. In
+    /// low-resource mode the entries are text only.
+    fn draw_recent_captures_menu(&mut self, ui: &mut egui::Ui) {
+        let Some(history) = &self.history else {
+            return;
+        };
+        let recent: Vec<HistoryEntry> = history.recent(history::RECENT_CAPTURES).into_iter().cloned().collect();
+        self.recent_thumbnails.retain(|id, _| recent.iter().any(|entry| entry.id == *id));
+        if recent.is_empty() {
+            ui.weak("No captures");
+            return;
+        }
+        let (mut open, mut copy) = (None, None);
+        for entry in &recent {
+            let label = format!("{}\n{}x{}, {}", entry.target, entry.width, entry.height, entry.time());
+            let button = match self.recent_thumbnail(ui.ctx(), entry.id) {
+                Some(texture) => {
+                    let size = Vec2::splat(history::THUMBNAIL_SIZE as f32);
+                    egui::Button::image_and_text(egui::Image::new(&texture).max_size(size), label)
+                }
+                None => egui::Button::new(label),
+            };
+            let response = ui.add_enabled(entry.path.is_some(), button);
+            let response = match &entry.path {
+                Some(path) => response.on_hover_text(format!("{}\nMiddle-click to copy", path.display())),
+                None => response.on_disabled_hover_text("Not saved"),
+            };
+            if response.clicked() {
+                open = entry.path.clone();
+            } else if response.clicked_by(egui::PointerButton::Middle) {
+                copy = entry.path.clone();
+            }
+        }
+        if let Some(path) = open {
+            self.open_and_report(&path);
+            ui.close_menu();
+        }
+        if let Some(path) = copy {
+            match self.copy_saved_capture(&path) {
+                Ok(()) => {
+                    self.notifications.push(Severity::Info, "Copied to Clipboard", path.display().to_string());
+                }
+                Err(e) => self.notify_error("Copy Failed", &e),
+            }
+            ui.close_menu();
+        }
+    }
+
+    /// Get the texture of a recent capture's thumbnail, loading it the first time
+    ///
+    /// Thumbnails are not loaded in low-resource mode, and the loaded ones are released.
+    fn recent_thumbnail(&mut self, ctx: &Context, id: Uuid) -> Option<TextureHandle> {
+        if !self.resource_profile().thumbnails {
+            self.recent_thumbnails.clear();
+            return None;
+        }
+        let history = self.history.as_ref()?;
+        self.recent_thumbnails
+            .entry(id)
+            .or_insert_with(|| {
+                let thumbnail = history.load_thumbnail(id)?;
+                Some(Self::load_texture(ctx, &format!("thumbnail_{}", id), &DynamicImage::ImageRgba8(thumbnail)))
+            })
+            .clone()
+    }
+
+    /// Copy a saved capture file to the clipboard without opening it
+    pub fn copy_saved_capture(&mut self, path: &Path) -> AppResult<()> {
+        let image = decode::decode_untrusted_file(path, DecodeLimits::default())?.to_rgba8();
+        clipboard::write_image(&image)?;
+        log::info!("Copied {} to the clipboard", path.display());
+        Ok(())
+    }
+
+    /// Draw the window for picking tabs and saved captures to arrange in a collage
+    fn draw_collage_window(&mut self, ctx: &Context) {
+        let Some(mut sources) = self.collage_sources.take() else {
+            return;
+        };
+
+        let tabs: Vec<(usize, Uuid, String)> = (0..self.tab_count())
+            .filter(|&index| !self.tab(index).is_empty())
+            .map(|index| (index, self.tab(index).session_id, self.tab_title(index)))
+            .collect();
+        let captures: Vec<(PathBuf, String)> = self
+            .history
+            .as_ref()
+            .map(|history| {
+                history
+                    .search("")
+                    .into_iter()
+                    .filter_map(|entry| Some((entry.path.clone()?, entry.time().to_string())))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let mut options = self.settings.get_settings().export.collage;
+        let mut open = true;
+        let mut create = false;
+        let mut cancel = false;
+        egui::Window::new("Create Collage")
+            .open(&mut open)
+            .default_width(360.0)
+            .show(ctx, |ui| {
+                ui.strong("Tabs");
+                if tabs.is_empty() {
+                    ui.weak("No open images");
+                }
+                for (_, id, title) in &tabs {
+                    let mut picked = sources.tabs.contains(id);
+                    if ui.checkbox(&mut picked, title).changed() {
+                        if picked {
+                            sources.tabs.insert(*id);
+                        } else {
+                            sources.tabs.remove(id);
+                        }
+                    }
+                }
+
+                ui.add_space(4.0);
+                ui.strong("Saved Captures");
+                if captures.is_empty() {
+                    ui.weak("No saved captures in the capture history");
+                }
+                egui::ScrollArea::vertical().max_height(160.0).show(ui, |ui| {
+                    for (path, time) in &captures {
+                        let name = path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+                        let mut picked = sources.captures.contains(path);
+                        let response = ui
+                            .checkbox(&mut picked, name)
+                            .on_hover_text(format!("{}\n{}", time, path.display()));
+                        if response.changed() {
+                            if picked {
+                                sources.captures.insert(path.clone());
+                            } else {
+                                sources.captures.remove(path);
+                            }
+                        }
+                    }
+                });
+
+                ui.separator();
+                ui.add(egui::Slider::new(&mut options.columns, 1..=10).text("Columns"));
+                ui.add(egui::Slider::new(&mut options.cell_width, 64..=1920).text("Cell width").suffix(" px"));
+                ui.add(egui::Slider::new(&mut options.spacing, 0..=128).text("Spacing").suffix(" px"));
+                Self::draw_background_editor(ui, &mut options.background);
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut options.captions, "Captions");
+                    ui.add_enabled(
+                        options.captions,
+                        egui::Slider::new(&mut options.caption_size, 8.0..=48.0).text("Size"),
+                    );
+                });
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    let picked = sources.tabs.len() + sources.captures.len();
+                    create = ui
+                        .add_enabled(picked > 0, egui::Button::new(format!("Create ({})", picked)))
+                        .on_hover_text("Open the collage in a new tab")
+                        .clicked();
+                    cancel = ui.button("Cancel").clicked();
+                });
+            });
+
+        if options != self.settings.get_settings().export.collage {
+            self.settings.settings_mut().export.collage = options;
+            self.settings_dirty = true;
+        }
+        if create {
+            let tabs: Vec<usize> = tabs
+                .iter()
+                .filter(|(_, id, _)| sources.tabs.contains(id))
+                .map(|(index, _, _)| *index)
+                .collect();
+            let captures: Vec<PathBuf> = captures
+                .into_iter()
+                .map(|(path, _)| path)
+                .filter(|path| sources.captures.contains(path))
+                .collect();
+            match self.create_collage(&tabs, &captures) {
+                Ok(()) => return,
+                Err(e) => self.show_error("Collage Failed", e.to_string()),
+            }
+        }
+        if open && !cancel {
+            self.collage_sources = Some(sources);
+        }
+    }
+
+    /// Draw the window editing the adjustment stack of the current image
+    ///
+    /// Sliders preview while dragged; adding, removing, and reordering steps
+    /// applies at once.
+    fn draw_adjustments_window(&mut self, ctx: &Context) {
+        if !self.show_adjustments {
+            return;
+        }
+        let mut steps = self.doc.adjustments.steps.clone();
+        let mut sliders = Vec::new();
+        let mut restacked = false;
+        let mut open = true;
+        egui::Window::new("Adjustments")
+            .open(&mut open)
+            .default_width(320.0)
+            .show(ctx, |ui| {
+                ui.add_enabled_ui(!self.doc.is_empty(), |ui| {
+                    if steps.is_empty() {
+                        ui.weak("No adjustments");
+                    }
+                    let mut removed = None;
+                    let mut raised = None;
+                    for (index, step) in steps.iter_mut().enumerate() {
+                        ui.horizontal(|ui| {
+                            let name = step.to_string();
+                            match step.amount_mut() {
+                                Some(amount) => sliders.push(ui.add(egui::Slider::new(amount, -100.0..=100.0).text(name))),
+                                None => {
+                                    ui.label(name);
+                                }
+                            }
+                            let up = ui.add_enabled(index > 0, egui::Button::new("↑").small());
+                            if accessibility::label(up, egui::WidgetType::Button, "Move Up").on_hover_text("Apply earlier").clicked() {
+                                raised = Some(index);
+                            }
+                            if accessibility::label(ui.small_button("×"), egui::WidgetType::Button, "Remove").on_hover_text("Remove").clicked() {
+                                removed = Some(index);
+                            }
+                        });
+                    }
+                    if let Some(index) = raised {
+                        steps.swap(index - 1, index);
+                        restacked = true;
+                    }
+                    if let Some(index) = removed {
+                        steps.remove(index);
+                        restacked = true;
+                    }
+
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        ui.menu_button("Add", |ui| {
+                            for step in Adjustment::all() {
+                                if ui.button(step.to_string()).clicked() {
+                                    steps.push(step);
+                                    restacked = true;
+                                    ui.close_menu();
+                                }
+                            }
+                        });
+                        if ui.add_enabled(!steps.is_empty(), egui::Button::new("Reset")).clicked() {
+                            steps.clear();
+                            restacked = true;
+                        }
+                    });
+                });
+            });
+        self.show_adjustments = open;
+
+        if steps != self.doc.adjustments.steps {
+            self.doc.adjustments.steps = steps;
+        }
+        if restacked {
+            self.apply_adjustments();
+        }
+        for slider in &sliders {
+            self.handle_adjustment_slider(slider);
+        }
+    }
+
+    /// Draw the controls for adjusting and confirming the capture region being selected
+    fn draw_region_selection_controls(&mut self, ctx: &Context) {
+        let Some(selection) = &self.doc.region_selection else {
+            return;
+        };
+        let application = selection.application.clone();
+        let edge = selection.edge;
+        let (mut x, mut y) = (selection.rect.min.x, selection.rect.min.y);
+        let (mut width, mut height) = (selection.rect.width(), selection.rect.height());
+        let (mut confirm, mut whole_screen, mut watch, mut cancel) = (false, false, false, false);
+        let mut changed = false;
+        let (mut width_changed, mut height_changed) = (false, false);
+        let mut constraint = self.region_constraint;
+        egui::Window::new("Select Region")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_TOP, Vec2::new(0.0, 48.0))
+            .show(ctx, |ui| {
+                ui.label("Drag on the capture to select a region, or drag the selection to move it.");
+                ui.weak("Hold Shift while dragging to keep the aspect ratio.");
+                ui.weak("Arrows move the selection (Ctrl: 10 px), Shift+arrows resize it, and Tab picks an edge.");
+                match edge {
+                    Some(edge) => ui.label(format!("Arrows adjust the {} edge", edge.to_string().to_lowercase())),
+                    None => ui.label("Arrows move the whole selection"),
+                };
+                match &application {
+                    Some(application) => ui.weak(format!("The region will be selected for {} next time", application)),
+                    None => ui.weak("The focused application is unknown, so the region won't be remembered"),
+                };
+                egui::Grid::new("region_selection").num_columns(4).show(ui, |ui| {
+                    ui.label("Shape:");
+                    let shape = match regions::SIZE_PRESETS.iter().find(|preset| preset.constraint == constraint) {
+                        Some(preset) => preset.name.to_string(),
+                        None => format!("{:.0} × {:.0}", width, height),
+                    };
+                    egui::ComboBox::from_id_source("region_shape").selected_text(shape).show_ui(ui, |ui| {
+                        for preset in regions::SIZE_PRESETS {
+                            ui.selectable_value(&mut constraint, preset.constraint, preset.name);
+                        }
+                    });
+                    ui.end_row();
+                    ui.label("X:");
+                    changed |= ui.add(egui::DragValue::new(&mut x).speed(1.0).fixed_decimals(0)).changed();
+                    ui.label("Y:");
+                    changed |= ui.add(egui::DragValue::new(&mut y).speed(1.0).fixed_decimals(0)).changed();
+                    ui.end_row();
+                    ui.label("Width:");
+                    width_changed = ui.add(egui::DragValue::new(&mut width).speed(1.0).fixed_decimals(0)).changed();
+                    ui.label("Height:");
+                    height_changed = ui.add(egui::DragValue::new(&mut height).speed(1.0).fixed_decimals(0)).changed();
+                    ui.end_row();
+                });
+                ui.horizontal(|ui| {
+                    confirm = ui.button("Capture Region").on_hover_text("Enter").clicked();
+                    whole_screen = ui.button("Whole Screen").clicked();
+                    watch = ui
+                        .button("Watch Region")
+                        .on_hover_text("Save a capture whenever this region changes, instead of capturing it now")
+                        .clicked();
+                    cancel = ui.button("Cancel").on_hover_text("Escape").clicked();
+                });
+            });
+
+        if constraint != self.region_constraint {
+            self.set_region_constraint(constraint);
+        } else if width_changed || height_changed {
+            // A typed size keeps a locked aspect ratio, or becomes the new fixed size
+            let aspect = self.region_constraint.aspect().unwrap_or(1.0);
+            match self.region_constraint {
+                SelectionConstraint::Fixed(..) => {
+                    self.set_region_constraint(SelectionConstraint::Fixed(width.max(1.0) as u32, height.max(1.0) as u32));
+                }
+                SelectionConstraint::Aspect(..) if width_changed => height = width / aspect,
+                SelectionConstraint::Aspect(..) => width = height * aspect,
+                SelectionConstraint::Free => {}
+            }
+            changed = true;
+        }
+        if changed {
+            self.set_selected_region(Rect::from_min_size(Pos2::new(x, y), Vec2::new(width, height)));
+        }
+        if confirm {
+            if let Err(e) = self.confirm_region_selection() {
+                self.show_error("Capture Failed", e.to_string());
+            }
+        } else if whole_screen {
+            self.select_whole_screen();
+        } else if watch {
+            if let Err(e) = self.watch_selected_region() {
+                self.show_error("Region Watch Failed", e.to_string());
+            }
+        } else if cancel {
+            self.cancel_region_selection();
+        }
+    }
+
+    /// Export the captures matching the history search to a CSV file in the save folder
+    pub fn export_history_csv(&mut self) -> AppResult<PathBuf> {
+        let history = self
+            .history
+            .as_ref()
+            .ok_or_else(|| AppError::Settings("キャプチャ履歴が有効になっていません".to_string()))?;
+        let csv = history::to_csv(history.search(&self.history_query));
+        let directory = self.settings.get_settings().save_directory();
+        std::fs::create_dir_all(crate::files::extended_length_path(&directory))
+            .context(format!("保存先フォルダ {} を作成できません", directory.display()))?;
+        let path = naming::unique_path(&directory, "capture-history", "csv");
+        crate::files::write_atomic(&path, csv.as_bytes())?;
+        log::info!("Exported capture history to {}", path.display());
+        Ok(path)
+    }
+
+    /// Draw the performance diagnostics overlay
+    fn draw_diagnostics_overlay(&mut self, ctx: &Context) {
+        if !self.show_diagnostics {
+            return;
+        }
+
+        self.diagnostics.document_memory = self.document_memory();
+        let diagnostics = &self.diagnostics;
+        let mut open = self.show_diagnostics;
+        egui::Window::new("Performance")
+            .open(&mut open)
+            .default_pos(ctx.screen_rect().right_top() + Vec2::new(-280.0, 40.0))
+            .resizable(false)
+            .show(ctx, |ui| {
+                let frames = &diagnostics.frame_times;
+                ui.label(format!(
+                    "Frame: {:.1} ms (avg {:.1}, p95 {:.1}, max {:.1})",
+                    frames.last().unwrap_or_default(),
+                    frames.average().unwrap_or_default(),
+                    frames.percentile(0.95).unwrap_or_default(),
+                    frames.max().unwrap_or_default(),
+                ));
+                ui.label(format!("Over 60 FPS budget: {:.0}%", diagnostics.over_budget_ratio() * 100.0));
+                Self::draw_sample_graph(ui, frames.samples().collect(), egui::Color32::LIGHT_GREEN);
+
+                let uploads = &diagnostics.texture_uploads;
+                ui.label(format!(
+                    "Texture uploads: {} (last {:.1} ms, max {:.1} ms)",
+                    uploads.len(),
+                    uploads.last().unwrap_or_default(),
+                    uploads.max().unwrap_or_default(),
+                ));
+                Self::draw_sample_graph(ui, uploads.samples().collect(), egui::Color32::LIGHT_BLUE);
+
+                ui.label(format!("Document memory: {}", diagnostics::format_bytes(diagnostics.document_memory)));
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    ui.strong("Pipeline");
+                    if ui.small_button("Reset").clicked() {
+                        profiling::reset();
+                    }
+                });
+                let profile = profiling::snapshot();
+                egui::Grid::new("pipeline_profile").striped(true).show(ui, |ui| {
+                    for heading in ["Stage", "Count", "Last", "Avg", "p95", "Max"] {
+                        ui.label(heading);
+                    }
+                    ui.end_row();
+                    for stage in profiling::Stage::all() {
+                        let samples = profile.samples(stage);
+                        ui.label(stage.to_string());
+                        ui.label(profile.count(stage).to_string());
+                        for ms in [samples.last(), samples.average(), samples.percentile(0.95), samples.max()] {
+                            ui.label(ms.map_or_else(|| "-".to_string(), |ms| format!("{:.1} ms", ms)));
+                        }
+                        ui.end_row();
+                    }
+                });
+            });
+        self.show_diagnostics = open;
+    }
+
+    /// Draw a line graph of millisecond samples with the frame budget as a reference line
+    fn draw_sample_graph(ui: &mut egui::Ui, samples: Vec<f32>, color: egui::Color32) {
+        let (rect, _) = ui.allocate_exact_size(Vec2::new(240.0, 60.0), Sense::hover());
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 2.0, ui.visuals().extreme_bg_color);
+
+        let budget = FRAME_BUDGET.as_secs_f32() * 1000.0;
+        let scale = samples.iter().copied().fold(budget * 2.0, f32::max);
+        let to_y = |ms: f32| rect.bottom() - (ms / scale).min(1.0) * rect.height();
+
+        painter.hline(rect.x_range(), to_y(budget), egui::Stroke::new(1.0, egui::Color32::from_rgb(200, 80, 80)));
+
+        if samples.len() >= 2 {
+            let step = rect.width() / (diagnostics::DIAGNOSTICS_HISTORY - 1) as f32;
+            let offset = (diagnostics::DIAGNOSTICS_HISTORY - samples.len()) as f32 * step;
+            let points = samples
+                .iter()
+                .enumerate()
+                .map(|(i, &ms)| Pos2::new(rect.left() + offset + i as f32 * step, to_y(ms)))
+                .collect();
+            painter.add(egui::Shape::line(points, egui::Stroke::new(1.0, color)));
+        }
+    }
+
+    /// Draw watermark editing controls
+    fn draw_watermark_editor(ui: &mut egui::Ui, watermark: &mut Watermark) {
+        ui.heading("Watermark");
+        ui.checkbox(&mut watermark.enabled, "Add watermark to exports");
+
+        ui.add_enabled_ui(watermark.enabled, |ui| {
+            ui.horizontal(|ui| {
+                let mut is_image = matches!(watermark.content, WatermarkContent::Image { .. });
+                if ui.radio_value(&mut is_image, false, "Text").changed()
+                    | ui.radio_value(&mut is_image, true, "Image").changed()
+                {
+                    watermark.content = if is_image {
+                        WatermarkContent::Image { path: PathBuf::new() }
+                    } else {
+                        Watermark::default().content
+                    };
+                }
+            });
+
+            match &mut watermark.content {
+                WatermarkContent::Text { text, font_size, color } => {
+                    ui.text_edit_singleline(text);
+                    ui.horizontal(|ui| {
+                        accessibility::color_button(ui, color, "Watermark color");
+                        ui.add(egui::Slider::new(font_size, 6.0..=96.0).text("Size"));
+                    });
+                }
+                WatermarkContent::Image { path } => {
+                    let mut path_text = path.display().to_string();
+                    ui.horizontal(|ui| {
+                        ui.label("PNG logo");
+                        if ui.text_edit_singleline(&mut path_text).changed() {
+                            *path = PathBuf::from(path_text.trim());
+                        }
+                    });
+                }
+            }
+
+            egui::ComboBox::from_label("Position")
+                .selected_text(watermark.anchor.to_string())
+                .show_ui(ui, |ui| {
+                    for anchor in WatermarkAnchor::all() {
+                        ui.selectable_value(&mut watermark.anchor, anchor, anchor.to_string());
+                    }
+                });
+            ui.add(egui::Slider::new(&mut watermark.margin, 0..=200).text("Margin").suffix(" px"));
+            ui.add(egui::Slider::new(&mut watermark.scale, 0.1..=4.0).text("Scale"));
+            ui.add(egui::Slider::new(&mut watermark.opacity, 0.05..=1.0).text("Opacity"));
+        });
+    }
+
+    /// Draw metadata banner controls
+    fn draw_banner_editor(ui: &mut egui::Ui, banner: &mut MetadataBanner) {
+        ui.heading("Metadata Banner");
+        ui.checkbox(&mut banner.enabled, "Add timestamp banner to exports");
+
+        ui.add_enabled_ui(banner.enabled, |ui| {
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut banner.position, BannerPosition::Top, "Top");
+                ui.radio_value(&mut banner.position, BannerPosition::Bottom, "Bottom");
+            });
+            ui.checkbox(&mut banner.show_timestamp, "Capture time (UTC)");
+            ui.checkbox(&mut banner.show_machine_name, "Machine name");
+            ui.horizontal(|ui| {
+                ui.label("Custom text");
+                ui.text_edit_singleline(&mut banner.custom_text);
+            });
+            ui.horizontal(|ui| {
+                accessibility::color_button(ui, &mut banner.background, "Banner background color");
+                accessibility::color_button(ui, &mut banner.text_color, "Banner text color");
+                ui.add(egui::Slider::new(&mut banner.font_size, 8.0..=48.0).text("Size"));
+            });
+        });
+    }
+
+    /// Draw step animation export controls
+    fn draw_animation_editor(ui: &mut egui::Ui, animation: &mut AnimationOptions) {
+        ui.heading("Step Animation");
+        ui.horizontal(|ui| {
+            ui.label("Format");
+            for format in AnimationFormat::all() {
+                ui.radio_value(&mut animation.format, format, format.to_string());
+            }
+        });
+        ui.add(
+            egui::Slider::new(&mut animation.step_delay_ms, AnimationOptions::DELAY_RANGE)
+                .text("Each step")
+                .suffix(" ms"),
+        );
+        ui.add(
+            egui::Slider::new(&mut animation.last_delay_ms, AnimationOptions::DELAY_RANGE)
+                .text("Last step")
+                .suffix(" ms"),
+        );
+        ui.checkbox(&mut animation.loop_forever, "Loop");
+    }
+
+    /// Draw controls for a solid or gradient background
+    fn draw_background_editor(ui: &mut egui::Ui, background: &mut DecorationBackground) {
+        ui.horizontal(|ui| {
+            let mut gradient = matches!(background, DecorationBackground::Gradient { .. });
+            ui.label("Background");
+            if ui.radio_value(&mut gradient, false, "Solid").changed()
+                | ui.radio_value(&mut gradient, true, "Gradient").changed()
+            {
+                *background = match *background {
+                    DecorationBackground::Solid(color) if gradient => {
+                        DecorationBackground::Gradient { start: color, end: color }
+                    }
+                    DecorationBackground::Gradient { start, .. } if !gradient => DecorationBackground::Solid(start),
+                    background => background,
+                };
+            }
+        });
+        ui.horizontal(|ui| match background {
+            DecorationBackground::Solid(color) => {
+                accessibility::color_button(ui, color, "Background color");
+            }
+            DecorationBackground::Gradient { start, end } => {
+                accessibility::color_button(ui, start, "Gradient start color");
+                ui.label("to");
+                accessibility::color_button(ui, end, "Gradient end color");
+            }
+        });
+    }
+
+    /// Draw decoration preset selection and editing controls
+    fn draw_decoration_editor(
+        ui: &mut egui::Ui,
+        settings: &mut DecorationSettings,
+        name_input: &mut String,
+        notifications: &mut Notifications,
+    ) {
+        ui.heading("Decoration");
+
+        let active = settings.active_preset.clone();
+        let mut selected = active.clone();
+        egui::ComboBox::from_label("Preset")
+            .selected_text(selected.as_deref().unwrap_or("None"))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut selected, None, "None");
+                for preset in &settings.presets {
+                    ui.selectable_value(&mut selected, Some(preset.name.clone()), &preset.name);
+                }
+            });
+        if selected != active {
+            if let Err(e) = settings.set_active_preset(selected.as_deref()) {
+                notifications.error("Preset Not Applied", &e);
+            }
+        }
+
+        let Some(name) = settings.active_preset.clone() else {
+            return;
+        };
+        let Some(mut decoration) = settings.active().copied() else {
+            return;
+        };
+
+        ui.add(egui::Slider::new(&mut decoration.padding, 0..=256).text("Padding").suffix(" px"));
+        ui.add(egui::Slider::new(&mut decoration.corner_radius, 0.0..=64.0).text("Corner radius"));
+
+        Self::draw_background_editor(ui, &mut decoration.background);
+
+        let mut has_shadow = decoration.shadow.is_some();
+        if ui.checkbox(&mut has_shadow, "Drop shadow").changed() {
+            decoration.shadow = has_shadow.then(DropShadow::default);
+        }
+        if let Some(ref mut shadow) = decoration.shadow {
+            ui.horizontal(|ui| {
+                accessibility::color_button(ui, &mut shadow.color, "Shadow color");
+                ui.add(egui::DragValue::new(&mut shadow.offset.x).prefix("x: "));
+                ui.add(egui::DragValue::new(&mut shadow.offset.y).prefix("y: "));
+            });
+            ui.add(egui::Slider::new(&mut shadow.blur, 0.0..=48.0).text("Blur"));
+        }
+
+        if Some(&decoration) != settings.active() {
+            if let Err(e) = settings.save_preset(&name, decoration) {
+                notifications.error("Preset Not Updated", &e);
+            }
+        }
+
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(name_input);
+            if ui.button("Save as Preset").clicked() {
+                let new_name = name_input.trim().to_string();
+                match settings
+                    .save_preset(&new_name, decoration)
+                    .and_then(|_| settings.set_active_preset(Some(&new_name)))
+                {
+                    Ok(()) => name_input.clear(),
+                    Err(e) => {
+                        notifications.error("Preset Not Saved", &e);
+                    }
+                }
+            }
+        });
+        if ui.button("Delete Preset").clicked() {
+            settings.remove_preset(&name);
+        }
+    }
+
+    /// Capture the next key press as the new chord for `action`
+    fn capture_rebinding(&mut self, ctx: &Context, action: ShortcutAction) {
+        let pressed = ctx.input(|i| {
+            i.events.iter().find_map(|event| match event {
+                egui::Event::Key { key, pressed: true, modifiers, .. } => Some((*key, *modifiers)),
+                _ => None,
+            })
+        });
+
+        if let Some((key, modifiers)) = pressed {
+            self.rebinding_action = None;
+            if key == egui::Key::Escape && modifiers.is_none() {
+                return;
+            }
+            self.shortcut_error = self
+                .rebind_shortcut(action, KeyChord::from_modifiers(key, modifiers))
+                .err()
+                .map(|e| e.to_string());
+        }
+    }
+
+    /// Draw the tool panel
+    fn draw_tool_panel(&mut self, ctx: &Context) {
+        egui::SidePanel::left("tool_panel").show(ctx, |ui| {
+            egui::CollapsingHeader::new("Tools").default_open(true).show(ui, |ui| {
+                self.draw_toolbar(ui);
+                self.draw_tool_options(ui);
+            });
+
+            ui.separator();
+
+            egui::CollapsingHeader::new("Style").default_open(true).show(ui, |ui| self.draw_style_section(ui));
+
+            ui.separator();
+
+            egui::CollapsingHeader::new("Picked Colors").default_open(true).show(ui, |ui| self.draw_palette_section(ui));
+
+            ui.separator();
+
+            egui::CollapsingHeader::new("View").default_open(true).show(ui, |ui| self.draw_view_section(ui));
+
+            ui.separator();
+
+            egui::CollapsingHeader::new("Adjustments").default_open(true).show(ui, |ui| self.draw_adjustments_section(ui));
+
+            let targets = self.settings.get_settings().share_targets.clone();
+            if !targets.is_empty() {
+                ui.separator();
+                egui::CollapsingHeader::new("Share").default_open(true).show(ui, |ui| {
+                    ui.add_enabled_ui(!self.doc.is_empty(), |ui| {
+                        ui.horizontal_wrapped(|ui| {
+                            for target in &targets {
+                                if ui.button(&target.name).on_hover_text(format!("Post the image to {}", target.service)).clicked() {
+                                    if let Err(e) = self.share(target) {
+                                        self.notify_error("Share Failed", &e);
+                                    }
+                                }
+                            }
+                        });
+                    });
+                });
+            }
+
+            ui.separator();
+
+            // Test image button
+            if ui.button("Load Test Image").clicked() {
+                if let Err(e) = self.load_test_image() {
+                    self.notify_error("Test Image Not Loaded", &e);
+                }
+            }
+            
+            ui.separator();
+            ui.label(format!("Zoom: {:.0}%", self.doc.zoom_level * 100.0));
+            if self.doc.pan_offset != Vec2::ZERO {
+                ui.label(format!("Pan: ({:.0}, {:.0})", self.doc.pan_offset.x, self.doc.pan_offset.y));
+            }
+            if self.doc.full_resolution.is_some() {
+                let restore = ui
+                    .button("Reduced Resolution")
+                    .on_hover_text("The full image is on disk to save memory and is used for exports. Click to load it.");
+                if restore.clicked() {
+                    if let Err(e) = self.doc.restore_full_resolution() {
+                        self.notify_error("Full Resolution Not Loaded", &e);
+                    }
+                }
+            }
+        });
+    }
+
+    /// Draw the tools as icon buttons, wrapping in narrow panels and moving the rest into an overflow menu
+    fn draw_toolbar(&mut self, ui: &mut egui::Ui) {
+        let tools = Tool::all();
+        let button_width = toolbar::ICON_SIZE + 2.0 * ui.spacing().button_padding.x;
+        let spacing = ui.spacing().item_spacing.x;
+        let layout = ToolbarLayout::new(ui.available_width(), button_width, spacing, tools.len());
+        let tint = ui.visuals().widgets.inactive.fg_stroke.color;
+        let icon = |source| egui::Image::new(source).fit_to_exact_size(Vec2::splat(toolbar::ICON_SIZE)).tint(tint);
+
+        let (shown, hidden) = tools.split_at(layout.visible);
+        let rows = shown.chunks(layout.columns).count();
+        for (row_index, row) in shown.chunks(layout.columns).enumerate() {
+            ui.horizontal(|ui| {
+                for (column, tool) in row.iter().enumerate() {
+                    let slot = (row_index * layout.columns + column + 1) as u8;
+                    let shortcut = self.settings.get_settings().shortcuts.display(ShortcutAction::SwitchTool(slot));
+                    let button = egui::ImageButton::new(icon(toolbar::icon(tool))).selected(self.current_tool == *tool);
+                    let response = accessibility::label(ui.add(button), egui::WidgetType::Button, toolbar::name(tool))
+                        .on_hover_text(toolbar::tooltip(tool, &shortcut));
+                    if response.clicked() {
+                        self.current_tool = tool.clone();
+                    }
+                }
+                if row_index + 1 == rows && layout.overflows(tools.len()) {
+                    let more = egui::ImageButton::new(icon(toolbar::OVERFLOW_ICON))
+                        .selected(hidden.contains(&self.current_tool));
+                    let response = accessibility::label(ui.add(more), egui::WidgetType::Button, "More tools")
+                        .on_hover_text("More tools");
+                    let popup_id = ui.make_persistent_id("tool_overflow");
+                    if response.clicked() {
+                        ui.memory_mut(|memory| memory.toggle_popup(popup_id));
+                    }
+                    egui::popup_below_widget(ui, popup_id, &response, |ui| {
+                        for tool in hidden {
+                            if ui.selectable_label(self.current_tool == *tool, toolbar::name(tool)).clicked() {
+                                self.current_tool = tool.clone();
+                                ui.memory_mut(|memory| memory.close_popup());
+                            }
+                        }
+                    });
+                }
+            });
+        }
+    }
+
+    /// Draw the options of the current tool below the toolbar
+    fn draw_tool_options(&mut self, ui: &mut egui::Ui) {
+        match self.current_tool {
+            Tool::Sticker => {
+                ui.horizontal(|ui| {
+                    ui.label("Sticker");
+                    let button = ui.button(&self.sticker_symbol).on_hover_text("Choose the sticker");
+                    let popup_id = ui.make_persistent_id("sticker_picker");
+                    if button.clicked() {
+                        ui.memory_mut(|memory| memory.toggle_popup(popup_id));
+                    }
+                    egui::popup_below_widget(ui, popup_id, &button, |ui| {
+                        if let Some(symbol) = Self::draw_emoji_picker(ui, &mut self.emoji_category) {
+                            self.sticker_symbol = symbol.to_string();
+                            ui.memory_mut(|memory| memory.close_popup());
+                        }
+                    });
+                });
+            }
+            Tool::Measure => {
+                ui.checkbox(&mut self.keep_measurements, "Keep measurements")
+                    .on_hover_text("Keep each measurement as a dimension annotation");
+            }
+            Tool::Perspective => {
+                ui.horizontal(|ui| {
+                    if ui.add_enabled(!self.doc.is_empty(), egui::Button::new("Straighten")).clicked() {
+                        if let Err(e) = self.correct_perspective() {
+                            self.show_error("Perspective Correction Failed", e.to_string());
+                        }
+                    }
+                    if ui.add_enabled(self.doc.perspective.is_some(), egui::Button::new("Reset Corners")).clicked() {
+                        self.reset_perspective();
+                    }
+                });
+            }
+            Tool::Select | Tool::Rectangle | Tool::Text | Tool::Eraser => {}
+        }
+    }
+
+    /// Draw the zoom controls
+    fn draw_view_section(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if ui.add(self.action_button("Zoom In", ShortcutAction::ZoomIn)).clicked() {
+                self.zoom_in();
+            }
+            if ui.add(self.action_button("Zoom Out", ShortcutAction::ZoomOut)).clicked() {
+                self.zoom_out();
+            }
+        });
+        
+        // Zoom slider
+        ui.add(egui::Slider::new(&mut self.doc.zoom_level, 0.1..=10.0)
+            .text("Zoom")
+            .suffix("%")
+            .custom_formatter(|n, _| format!("{:.0}", n * 100.0))
+            .custom_parser(|s| s.parse::<f64>().map(|n| n / 100.0).ok()));
+        
+        if ui.add(self.action_button("Actual Size", ShortcutAction::ZoomReset)).clicked() {
+            self.doc.zoom_level = 1.0;
+        }
+        if ui.add(self.action_button("Fit to Screen", ShortcutAction::ZoomFit)).clicked() {
+            self.zoom_to_fit();
+        }
+        let has_selection = self.doc.annotations.iter().any(|a| a.is_selected);
+        if ui
+            .add_enabled(has_selection, self.action_button("Zoom to Selection", ShortcutAction::ZoomToSelection))
+            .clicked()
+        {
+            self.zoom_to_selection();
+        }
+        if ui.button("Reset View").clicked() {
+            self.doc.zoom_level = 1.0;
+            self.doc.pan_offset = Vec2::ZERO;
+        }
+    }
+
+    /// Draw the buttons opening and resetting the image adjustments
+    fn draw_adjustments_section(&mut self, ui: &mut egui::Ui) {
+        ui.add_enabled_ui(self.doc.source_image.is_some(), |ui| {
+            ui.horizontal(|ui| {
+                if ui.button("Adjustments...").clicked() {
+                    self.show_adjustments = true;
+                }
+                if ui.add_enabled(!self.doc.adjustments.steps.is_empty(), egui::Button::new("Reset")).clicked() {
+                    self.set_adjustments(Adjustments::default());
+                }
+            });
+            if self.doc.adjustment_job.is_some() {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.weak("Applying adjustments...");
+                });
+            }
+        });
+    }
+
+    /// Draw the colors picked from the screen, each copied again when clicked
+    fn draw_palette_section(&mut self, ui: &mut egui::Ui) {
+        let colors = self.settings.get_settings().palette.colors.clone();
+        if colors.is_empty() {
+            let hotkey = self.settings.get_settings().color_picker_hotkey;
+            ui.weak(format!(
+                "Press {} to pick a color from the screen",
+                hotkeys::describe(hotkey.modifiers, hotkey.vk_code)
+            ));
+            return;
+        }
+
+        ui.horizontal_wrapped(|ui| {
+            for color in &colors {
+                let hex = palette::hex(*color);
+                let (rect, response) = ui.allocate_exact_size(Vec2::splat(18.0), egui::Sense::click());
+                ui.painter().rect_filled(rect, 2.0, *color);
+                ui.painter().rect_stroke(rect, 2.0, ui.visuals().widgets.inactive.fg_stroke);
+                if response.on_hover_text(format!("{} (click to copy)", hex)).clicked() {
+                    ui.ctx().output_mut(|output| output.copied_text = hex);
+                }
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.menu_button("Export", |ui| {
+                for format in PaletteFormat::all() {
+                    if ui.button(format!("{} (.{})", format, format.extension())).clicked() {
+                        match self.export_palette(format) {
+                            Ok(path) => {
+                                self.notifications.push(Severity::Info, "Palette Exported", path.display().to_string());
+                            }
+                            Err(e) => self.notify_error("Export Failed", &e),
+                        }
+                        ui.close_menu();
+                    }
+                }
+            });
+            if ui.button("Clear").clicked() {
+                self.settings.settings_mut().palette.clear();
+                self.settings_dirty = true;
+            }
+        });
+    }
+
+    /// Draw the style editor with preset selection
+    fn draw_style_section(&mut self, ui: &mut egui::Ui) {
+        // Preset selection
+        let styles = &self.settings.get_settings().styles;
+        let active = styles.active_preset.clone();
+        let preset_names: Vec<String> = styles.presets.iter().map(|p| p.name.clone()).collect();
+        let mut selected = active.clone();
+        egui::ComboBox::from_label("Preset")
+            .selected_text(selected.as_deref().unwrap_or("Tool defaults"))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut selected, None, "Tool defaults");
+                for name in &preset_names {
+                    ui.selectable_value(&mut selected, Some(name.clone()), name);
+                }
+            });
+        if selected != active {
+            let styles = &mut self.settings.settings_mut().styles;
+            if let Err(e) = styles.set_active_preset(selected.as_deref()) {
+                self.notifications.error("Preset Not Applied", &e);
+            }
+            self.settings_dirty = true;
+        }
+
+        // Edit the active preset, or the current tool's default style
+        if active.is_none() && self.current_tool == Tool::Select {
+            ui.label("Select a drawing tool to edit its default style");
+        } else {
+            let mut style = self.settings.get_settings().styles.style_for_tool(&self.current_tool);
+            let mut changed = false;
+
+            ui.horizontal(|ui| {
+                ui.label("Stroke");
+                changed |= accessibility::color_button(ui, &mut style.stroke_color, "Stroke color").changed();
+                changed |= ui.add(egui::Slider::new(&mut style.stroke_width, 0.5..=20.0)).changed();
+            });
+            ui.horizontal(|ui| {
+                let mut has_fill = style.fill_color.is_some();
+                if ui.checkbox(&mut has_fill, "Fill").changed() {
+                    style.fill_color = has_fill.then_some(egui::Color32::from_rgba_unmultiplied(255, 255, 0, 96));
+                    changed = true;
+                }
+                if let Some(ref mut fill) = style.fill_color {
+                    changed |= accessibility::color_button(ui, fill, "Fill color").changed();
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Text");
+                changed |= accessibility::color_button(ui, &mut style.text_color, "Text color").changed();
+                changed |= ui.add(egui::Slider::new(&mut style.font_size, 6.0..=96.0)).changed();
+            });
+            ui.horizontal(|ui| {
+                ui.label("Font");
+                egui::ComboBox::from_id_source("style_font_family")
+                    .selected_text(style.font_family.as_deref().unwrap_or("Default"))
+                    .show_ui(ui, |ui| {
+                        changed |= ui.selectable_value(&mut style.font_family, None, "Default").changed();
+                        for family in text::font_families(text::installed_fonts()) {
+                            changed |= ui.selectable_value(&mut style.font_family, Some(family.clone()), family).changed();
+                        }
+                    });
+            });
+            changed |= ui.add(egui::Slider::new(&mut style.alpha, 0.05..=1.0).text("Opacity")).changed();
+
+            if changed {
+                let styles = &mut self.settings.settings_mut().styles;
+                match active {
+                    Some(ref name) => {
+                        if let Err(e) = styles.save_preset(name, style.clone()) {
+                            self.notifications.error("Preset Not Updated", &e);
+                        }
+                    }
+                    None => styles.set_tool_default(self.current_tool.clone(), style.clone()),
+                }
+                self.settings_dirty = true;
+            }
+
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(&mut self.preset_name_input);
+                if ui.button("Save as Preset").clicked() {
+                    let name = self.preset_name_input.trim().to_string();
+                    let styles = &mut self.settings.settings_mut().styles;
+                    match styles.save_preset(&name, style).and_then(|_| styles.set_active_preset(Some(&name))) {
+                        Ok(()) => {
+                            self.preset_name_input.clear();
+                            self.settings_dirty = true;
+                        }
+                        Err(e) => {
+                            self.notifications.error("Preset Not Saved", &e);
+                        }
+                    }
+                }
+            });
+        }
+
+        if let Some(ref name) = active {
+            if ui.button("Delete Preset").clicked() {
+                self.settings.settings_mut().styles.remove_preset(name);
+                self.settings_dirty = true;
+            }
+        }
+
+        ui.separator();
+    }
+
+    /// Draw the properties panel of the selected annotations
+    ///
+    /// The panel edits a copy of the first selected annotation and applies
+    /// what changed to all of them. With several selected, only the fields
+    /// they share are shown, and the position is that of the whole selection.
+    fn draw_properties_panel(&mut self, ctx: &Context) {
+        let selected: Vec<&AnnotationItem> = self.doc.annotations.iter().filter(|a| a.is_selected).collect();
+        let Some(kind) = SelectionKind::of(&selected) else {
+            return;
+        };
+        let count = selected.len();
+        let mut before = selected[0].clone();
+        let bounds = if count == 1 {
+            before.bounds()
+        } else {
+            selected.iter().map(|a| a.bounds()).reduce(|a, b| a.union(b)).unwrap_or(Rect::NOTHING)
+        };
+        if count > 1 {
+            before.position = bounds.min;
+        }
+        let mut after = before.clone();
+
+        egui::SidePanel::right("properties_panel").show(ctx, |ui| {
+            if count == 1 {
+                ui.heading("Properties");
+            } else {
+                ui.heading(format!("Properties ({} selected)", count));
+            }
+            ui.separator();
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                egui::Grid::new("annotation_properties").num_columns(2).show(ui, |ui| {
+                    ui.label("X:");
+                    ui.add(egui::DragValue::new(&mut after.position.x).suffix(" px"));
+                    ui.end_row();
+                    ui.label("Y:");
+                    ui.add(egui::DragValue::new(&mut after.position.y).suffix(" px"));
+                    ui.end_row();
+
+                    match (kind, &mut after.annotation_type) {
+                        (
+                            SelectionKind::Rectangles,
+                            AnnotationType::Rectangle { size, stroke_color, stroke_width, fill_color },
+                        ) => {
+                            ui.label("Width:");
+                            ui.add(egui::DragValue::new(&mut size.x).clamp_range(1.0..=f32::MAX).suffix(" px"));
+                            ui.end_row();
+                            ui.label("Height:");
+                            ui.add(egui::DragValue::new(&mut size.y).clamp_range(1.0..=f32::MAX).suffix(" px"));
+                            ui.end_row();
+
+                            ui.label("Stroke:");
+                            ui.horizontal(|ui| {
+                                accessibility::color_button(ui, stroke_color, "Stroke color");
+                                ui.add(egui::DragValue::new(stroke_width).clamp_range(0.0..=50.0).suffix(" px"));
+                            });
+                            ui.end_row();
+
+                            ui.label("Fill:");
+                            ui.horizontal(|ui| {
+                                let mut filled = fill_color.is_some();
+                                if ui.checkbox(&mut filled, "").changed() {
+                                    *fill_color = filled.then_some(stroke_color.gamma_multiply(0.25));
+                                }
+                                if let Some(fill) = fill_color {
+                                    accessibility::color_button(ui, fill, "Fill color");
+                                }
+                            });
+                            ui.end_row();
+                        }
+                        (SelectionKind::Images, AnnotationType::Image { size, .. }) => {
+                            // Width and height change together to keep the aspect ratio
+                            let aspect = size.x / size.y;
+                            let mut width = size.x;
+                            let mut height = size.y;
+                            ui.label("Width:");
+                            let width_changed = ui
+                                .add(egui::DragValue::new(&mut width).clamp_range(1.0..=f32::MAX).suffix(" px"))
+                                .changed();
+                            ui.end_row();
+                            ui.label("Height:");
+                            let height_changed = ui
+                                .add(egui::DragValue::new(&mut height).clamp_range(1.0..=f32::MAX).suffix(" px"))
+                                .changed();
+                            ui.end_row();
+                            if width_changed {
+                                *size = Vec2::new(width, width / aspect);
+                            } else if height_changed {
+                                *size = Vec2::new(height * aspect, height);
+                            }
+                        }
+                        (SelectionKind::Texts, AnnotationType::Text { font_size, color, format, .. }) => {
+                            // Text size follows the font size, content, and wrap width
+                            if count == 1 {
+                                ui.label("Width:");
+                                ui.label(format!("{:.0} px", bounds.width()));
+                                ui.end_row();
+                                ui.label("Height:");
+                                ui.label(format!("{:.0} px", bounds.height()));
+                                ui.end_row();
+                            }
+
+                            ui.label("Font size:");
+                            ui.add(egui::DragValue::new(font_size).clamp_range(4.0..=400.0).suffix(" px"));
+                            ui.end_row();
+                            ui.label("Color:");
+                            accessibility::color_button(ui, color, "Text color");
+                            ui.end_row();
+                            Self::draw_text_format_fields(ui, format, bounds.width());
+                        }
+                        (SelectionKind::Dimensions, AnnotationType::Dimension { color, width, .. }) => {
+                            if count == 1 {
+                                ui.label("Measured:");
+                                ui.label(render::dimension_label(&before));
+                                ui.end_row();
+                            }
+                            ui.label("Line:");
+                            ui.horizontal(|ui| {
+                                accessibility::color_button(ui, color, "Line color");
+                                ui.add(egui::DragValue::new(width).clamp_range(0.5..=50.0).suffix(" px"));
+                            });
+                            ui.end_row();
+                        }
+                        _ => {}
+                    }
+
+                    ui.label("Opacity:");
+                    ui.add(egui::Slider::new(&mut after.opacity, 0.0..=1.0));
+                    ui.end_row();
+                    ui.label("Blend:");
+                    egui::ComboBox::from_id_source("annotation_blend_mode")
+                        .selected_text(after.blend_mode.to_string())
+                        .show_ui(ui, |ui| {
+                            for mode in BlendMode::all() {
+                                ui.selectable_value(&mut after.blend_mode, mode, mode.to_string());
+                            }
+                        });
+                    ui.end_row();
+
+                    ui.label("Rotation:");
+                    ui.add(egui::DragValue::new(&mut after.rotation).clamp_range(-360.0..=360.0).suffix("°"));
+                    ui.end_row();
+
+                    // Zero means the annotation is shown in every frame
+                    ui.label("Step:");
+                    let mut step = after.step.unwrap_or(0);
+                    if ui
+                        .add(
+                            egui::DragValue::new(&mut step)
+                                .clamp_range(0..=animation::MAX_STEP)
+                                .custom_formatter(|n, _| if n == 0.0 { "None".to_string() } else { format!("{:.0}", n) }),
+                        )
+                        .on_hover_text("Annotations appear one step at a time in animated exports")
+                        .changed()
+                    {
+                        after.step = Some(step).filter(|&step| step > 0);
+                    }
+                    ui.end_row();
+                });
+
+                if let (1, AnnotationType::Text { content, .. }) = (count, &mut after.annotation_type) {
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        ui.heading("Selected Text");
+                        let button = ui.button("Emoji").on_hover_text("Insert an emoji or symbol");
+                        let popup_id = ui.make_persistent_id("text_emoji_picker");
+                        if button.clicked() {
+                            ui.memory_mut(|memory| memory.toggle_popup(popup_id));
+                        }
+                        egui::popup_below_widget(ui, popup_id, &button, |ui| {
+                            if let Some(symbol) = Self::draw_emoji_picker(ui, &mut self.emoji_category) {
+                                // Insert at the text cursor, or at the end if the text was never focused
+                                let edit_id = egui::Id::new(SELECTED_TEXT_ID);
+                                let mut state =
+                                    egui::text_edit::TextEditState::load(ui.ctx(), edit_id).unwrap_or_default();
+                                let cursor = state.ccursor_range().map_or(usize::MAX, |range| range.primary.index);
+                                let after = emoji::insert_at(content, cursor, symbol);
+                                state.set_ccursor_range(Some(egui::text::CCursorRange::one(egui::text::CCursor::new(
+                                    after,
+                                ))));
+                                state.store(ui.ctx(), edit_id);
+                            }
+                        });
+                    });
+                    ui.add(egui::TextEdit::multiline(content).id(egui::Id::new(SELECTED_TEXT_ID)));
+                }
+            });
+        });
+
+        if after != before {
+            for annotation in self.doc.annotations.iter_mut().filter(|a| a.is_selected) {
+                properties::apply_changes(&before, &after, annotation);
+            }
+            self.mark_content_changed();
+        }
+    }
+
+    /// Draw the emoji and symbol picker, returning the one clicked
+    fn draw_emoji_picker(ui: &mut egui::Ui, category: &mut usize) -> Option<&'static str> {
+        ui.set_min_width(260.0);
+        ui.horizontal_wrapped(|ui| {
+            for (index, entry) in emoji::CATEGORIES.iter().enumerate() {
+                ui.selectable_value(category, index, entry.name);
+            }
+        });
+        ui.separator();
+
+        let symbols = emoji::CATEGORIES.get(*category).unwrap_or(&emoji::CATEGORIES[0]).symbols;
+        let mut picked = None;
+        egui::Grid::new("emoji_picker_grid").show(ui, |ui| {
+            for (index, symbol) in symbols.iter().enumerate() {
+                if ui.add(egui::Button::new(egui::RichText::new(*symbol).size(20.0)).frame(false)).clicked() {
+                    picked = Some(*symbol);
+                }
+                if index % EMOJI_PICKER_COLUMNS == EMOJI_PICKER_COLUMNS - 1 {
+                    ui.end_row();
+                }
+            }
+        });
+        picked
+    }
+
+    /// Draw the formatting rows of a text annotation's properties, returning whether anything changed
+    fn draw_text_format_fields(ui: &mut egui::Ui, format: &mut text::TextFormat, text_width: f32) -> bool {
+        let mut changed = false;
+
+        ui.label("Font:");
+        egui::ComboBox::from_id_source("text_font_family")
+            .selected_text(format.font_family.as_deref().unwrap_or("Default"))
+            .show_ui(ui, |ui| {
+                changed |= ui.selectable_value(&mut format.font_family, None, "Default").changed();
+                for family in text::font_families(text::installed_fonts()) {
+                    changed |= ui.selectable_value(&mut format.font_family, Some(family.clone()), family).changed();
+                }
+            });
+        ui.end_row();
+
+        ui.label("Style:");
+        ui.horizontal(|ui| {
+            changed |= ui.toggle_value(&mut format.bold, egui::RichText::new("B").strong()).on_hover_text("Bold").changed();
+            changed |= ui.toggle_value(&mut format.italic, egui::RichText::new("I").italics()).on_hover_text("Italic").changed();
+        });
+        ui.end_row();
+
+        ui.label("Align:");
+        ui.horizontal(|ui| {
+            for align in text::TextAlign::all() {
+                changed |= ui.selectable_value(&mut format.align, align, align.to_string()).changed();
+            }
+        });
+        ui.end_row();
+
+        // Wrapping starts at the current text width so turning it on doesn't reflow the text
+        ui.label("Wrap width:");
+        ui.horizontal(|ui| {
+            let mut wrap = format.wrap_width.is_some();
+            if ui.checkbox(&mut wrap, "").changed() {
+                format.wrap_width = wrap.then_some(text_width.round().max(text::MIN_WRAP_WIDTH));
+                changed = true;
+            }
+            if let Some(width) = &mut format.wrap_width {
+                changed |= ui
+                    .add(egui::DragValue::new(width).clamp_range(text::MIN_WRAP_WIDTH..=f32::MAX).suffix(" px"))
+                    .changed();
+            }
+        });
+        ui.end_row();
+
+        ui.label("Background:");
+        ui.horizontal(|ui| {
+            let mut filled = format.background.is_some();
+            if ui.checkbox(&mut filled, "").changed() {
+                format.background = filled.then_some(egui::Color32::from_rgb(255, 255, 200));
+                changed = true;
+            }
+            if let Some(background) = &mut format.background {
+                changed |= accessibility::color_button(ui, background, "Background color").changed();
+            }
+        });
+        ui.end_row();
+
+        ui.label("Outline:");
+        ui.horizontal(|ui| {
+            let mut outlined = format.outline.is_some();
+            if ui.checkbox(&mut outlined, "").changed() {
+                format.outline = outlined.then_some(egui::Stroke::new(2.0, egui::Color32::BLACK));
+                changed = true;
+            }
+            if let Some(outline) = &mut format.outline {
+                changed |= accessibility::color_button(ui, &mut outline.color, "Outline color").changed();
+                changed |= ui
+                    .add(egui::DragValue::new(&mut outline.width).clamp_range(0.5..=50.0).suffix(" px"))
+                    .changed();
+            }
+        });
+        ui.end_row();
+
+        changed
+    }
+
+    /// Draw the main canvas area
+    fn draw_canvas(&mut self, ctx: &Context) {
+        egui::CentralPanel::default().show(ctx, |ui| {
+            self.canvas_rect = Some(ui.available_rect_before_wrap());
+
+            // Ensure texture is created
+            self.ensure_texture(ctx);
+            self.ensure_image_textures(ctx);
+            self.ensure_blend_previews(ctx);
+            self.ensure_comparison_texture(ctx);
+            self.update_adjustment_preview(ctx);
+
+            if self.is_capturing() {
+                ui.centered_and_justified(|ui| {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label("Capturing...");
+                    });
+                });
+            } else if self.doc.texture.is_some() {
+                // Show the adjustment preview while a slider is being dragged
+                let preview = self.doc.preview_texture.clone();
+                self.draw_image_with_controls(ui, preview.as_ref());
+            } else {
+                // Show placeholder when no image is loaded
+                ui.centered_and_justified(|ui| {
+                    ui.vertical_centered(|ui| {
+                        ui.label("Take a screenshot or open an image file");
+                        ui.separator();
+                        ui.label("Or click 'Load Test Image' button in the left panel");
+                    });
+                });
+            }
+        });
+    }
+
+    /// Draw the image with zoom and pan controls
+    fn draw_image_with_controls(&mut self, ui: &mut egui::Ui, preview: Option<&TextureHandle>) {
+        let available_rect = ui.available_rect_before_wrap();
+        let response = accessibility::label(
+            ui.allocate_rect(available_rect, Sense::click_and_drag()),
+            egui::WidgetType::Other,
+            "Screenshot canvas",
+        );
+
+        // Handle mouse interactions
+        self.handle_mouse_interactions(&response, available_rect);
+
+        // Calculate image display parameters (the preview texture may be lower resolution)
+        let original_size = self
+            .image_size()
+            .or_else(|| self.doc.texture.as_ref().map(TiledTexture::size_vec2))
+            .unwrap_or(Vec2::ZERO);
+        let display_size = original_size * self.doc.zoom_level as f32;
+        
+        // Calculate image position with pan offset
+        let center_offset = (available_rect.size() - display_size) * 0.5;
+        let image_pos = available_rect.min + center_offset + self.doc.pan_offset;
+        let image_rect = Rect::from_min_size(image_pos, display_size);
+
+        // Handle drawing/selection with the current tool, or the capture region being selected
+        if self.doc.region_selection.is_some() {
+            self.handle_region_drag(&response, image_rect);
+        } else if self.doc.color_picking {
+            if let Some(pointer) = response.interact_pointer_pos().filter(|_| response.clicked()) {
+                let position = self.screen_to_image(pointer, image_rect);
+                self.pick_screen_color(&response.ctx, position);
+            }
+        } else {
+            self.handle_tool_interactions(&response, image_rect);
+            self.show_selection_menu(&response);
+        }
+        self.update_canvas_cursor(ui, &response, image_rect);
+
+        // Clip the drawing to the available area
+        ui.allocate_ui_at_rect(available_rect, |ui| {
+            // Set clipping rectangle to prevent drawing outside the canvas area
+            ui.set_clip_rect(available_rect);
+            
+            // Draw background
+            ui.painter().rect_filled(
+                available_rect,
+                0.0,
+                ui.style().visuals.extreme_bg_color,
+            );
+
+            // Calculate the visible portion of the image that intersects with available area
+            let visible_image_rect = image_rect.intersect(available_rect);
+            
+            // Draw the image only if it's visible
+            if visible_image_rect.width() > 0.0 && visible_image_rect.height() > 0.0 {
+                // Calculate UV coordinates for the visible portion
+                let uv_rect = if image_rect.width() > 0.0 && image_rect.height() > 0.0 {
+                    let left = ((visible_image_rect.min.x - image_rect.min.x) / image_rect.width()).max(0.0);
+                    let top = ((visible_image_rect.min.y - image_rect.min.y) / image_rect.height()).max(0.0);
+                    let right = ((visible_image_rect.max.x - image_rect.min.x) / image_rect.width()).min(1.0);
+                    let bottom = ((visible_image_rect.max.y - image_rect.min.y) / image_rect.height()).min(1.0);
+                    
+                    Rect::from_min_max(
+                        Pos2::new(left, top),
+                        Pos2::new(right, bottom)
+                    )
+                } else {
+                    Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0))
+                };
+
+                match (preview, &self.doc.texture) {
+                    (Some(preview), _) => {
+                        ui.painter().image(preview.id(), visible_image_rect, uv_rect, egui::Color32::WHITE);
+                    }
+                    (None, Some(texture)) => texture.paint(ui.painter(), image_rect),
+                    (None, None) => {}
+                }
+            }
+
+            // Draw image border (only the visible part)
+            if visible_image_rect.width() > 0.0 && visible_image_rect.height() > 0.0 {
+                ui.painter().rect_stroke(
+                    visible_image_rect,
+                    0.0,
+                    egui::Stroke::new(1.0, ui.style().visuals.widgets.inactive.bg_stroke.color),
+                );
+            }
+
+            let grid = self.settings.get_settings().grid;
+            if grid.show_grid {
+                self.draw_grid(ui, &grid, image_rect, visible_image_rect);
+            }
+            if grid.shows_pixel_grid(self.doc.zoom_level as f32) {
+                self.draw_pixel_grid(ui, image_rect, visible_image_rect);
+            }
+
+            // Draw annotations (they will be clipped automatically)
+            self.draw_annotations(ui, image_rect);
+            self.draw_diff_highlights(ui, image_rect);
+            self.draw_privacy_highlights(ui, image_rect);
+            self.draw_image_comparison_overlay(ui, image_rect);
+            self.draw_rectangle_preview(ui, &response, image_rect);
+            self.draw_eraser_preview(ui, &response, image_rect);
+            self.draw_selection_region(ui, image_rect);
+            self.draw_measurement(ui, image_rect);
+            self.draw_perspective(ui, image_rect);
+            self.draw_guides(ui, image_rect);
+            self.draw_region_selection(ui, &response, image_rect, preview);
+            self.draw_color_picker(ui, &response, image_rect, preview);
+
+            let mut overlay_rect = available_rect;
+            if grid.show_rulers {
+                self.draw_rulers(ui, &response, available_rect, image_rect);
+                overlay_rect.min += Vec2::splat(RULER_SIZE);
+            }
+
+            // Show zoom and pan info overlay
+            self.draw_info_overlay(ui, overlay_rect);
+        });
+    }
+
+    /// Handle mouse interactions for panning and zooming
+    fn handle_mouse_interactions(&mut self, response: &Response, available_rect: Rect) {
+        // Handle scrolling and pinching for panning and zooming
+        if response.hovered() {
+            let (scroll_delta, zoom_delta, shift) =
+                response.ctx.input(|i| (i.scroll_delta, i.zoom_delta(), i.modifiers.shift));
+            self.handle_scroll_gesture(scroll_delta, zoom_delta, shift, response.hover_pos(), available_rect);
+        }
+
+        // Handle middle mouse button or the pan key for panning
+        if self.is_pan_gesture(response) && response.dragged() {
+            let delta = response.drag_delta();
+            let new_pan_offset = self.doc.pan_offset + delta;
+            
+            // Apply pan limits to prevent the image from going completely off-screen
+            self.doc.pan_offset = self.constrain_pan_offset(new_pan_offset, available_rect);
+        }
+
+        if response.double_clicked() {
+            self.handle_double_click();
+        }
+    }
+
+    /// Change the view as set for double-clicking the canvas
+    fn handle_double_click(&mut self) {
+        match self.settings.get_settings().gestures.double_click {
+            DoubleClickAction::ResetView => {
+                self.doc.zoom_level = 1.0;
+                self.doc.pan_offset = Vec2::ZERO;
+            }
+            DoubleClickAction::FitToScreen => self.zoom_to_fit(),
+            DoubleClickAction::ActualSize => self.doc.zoom_level = 1.0,
+            DoubleClickAction::Nothing => {}
+        }
+    }
+
+    /// Pan or zoom the canvas by a scroll and pinch zoom factor as set for scrolling
+    ///
+    /// Pinching (or Ctrl+scrolling) always zooms. Other scrolling zooms or
+    /// pans as set; following the pan scheme, it pans with the Space scheme,
+    /// like two-finger touchpad scrolling, and zooms with the Shift scheme,
+    /// like a mouse wheel. Panning with Shift held turns vertical scrolling
+    /// horizontal, for mouse wheels without a horizontal axis.
+    fn handle_scroll_gesture(
+        &mut self,
+        scroll_delta: Vec2,
+        zoom_delta: f32,
+        shift: bool,
+        pointer: Option<Pos2>,
+        available_rect: Rect,
+    ) {
+        let gestures = self.settings.get_settings().gestures;
+        if zoom_delta != 1.0 {
+            self.zoom_towards((zoom_delta as f64).powf(gestures.zoom_sensitivity as f64), pointer, available_rect);
+        }
+        if scroll_delta == Vec2::ZERO {
+            return;
+        }
+        let zooms = match gestures.scroll {
+            ScrollAction::PanScheme => self.settings.get_settings().pan.scheme == PanScheme::ShiftDrag,
+            ScrollAction::Zoom => true,
+            ScrollAction::Pan => false,
+        };
+        if zooms {
+            if scroll_delta.y != 0.0 {
+                let factor = 1.0 + scroll_delta.y as f64 * 0.001 * gestures.zoom_sensitivity as f64;
+                self.zoom_towards(factor, pointer, available_rect);
+            }
+            return;
+        }
+        let delta = if shift && gestures.scroll == ScrollAction::Pan && scroll_delta.x == 0.0 {
+            Vec2::new(scroll_delta.y, 0.0)
+        } else {
+            scroll_delta
+        };
+        let new_pan_offset = self.doc.pan_offset + delta;
+        self.doc.pan_offset = self.constrain_pan_offset(new_pan_offset, available_rect);
+    }
+
+    /// Zoom by a factor, keeping the image point under the pointer in place
+    fn zoom_towards(&mut self, factor: f64, pointer: Option<Pos2>, available_rect: Rect) {
+        let old_zoom = self.doc.zoom_level;
+        self.doc.zoom_level = (self.doc.zoom_level * factor).clamp(0.1, 10.0);
+
+        // Adjust pan offset to zoom towards mouse cursor
+        if let Some(mouse_pos) = pointer {
+            let relative_pos = mouse_pos - available_rect.center();
+            let zoom_change = (self.doc.zoom_level / old_zoom - 1.0) as f32;
+            self.doc.pan_offset -= relative_pos * zoom_change;
+        }
+    }
+
+    /// Check if the pan key of the pan scheme is held: Space, or Shift outside region selection
+    ///
+    /// Space does not pan while a text field has keyboard focus. Shift locks
+    /// the aspect ratio instead while a capture region is selected.
+    fn is_pan_key_held(&self, ctx: &egui::Context) -> bool {
+        match self.settings.get_settings().pan.scheme {
+            PanScheme::SpaceBar => ctx.input(|i| i.key_down(egui::Key::Space)) && !ctx.wants_keyboard_input(),
+            PanScheme::ShiftDrag => ctx.input(|i| i.modifiers.shift) && self.doc.region_selection.is_none(),
+        }
+    }
+
+    /// Show the cursor for the active tool and what is under the pointer on the canvas
+    fn update_canvas_cursor(&self, ui: &egui::Ui, response: &Response, image_rect: Rect) {
+        let dragging = response.dragged();
+        let Some(pointer) = response.hover_pos().or(response.interact_pointer_pos().filter(|_| dragging)) else {
+            return;
+        };
+        let pos = self.screen_to_image(pointer, image_rect);
+        let target = match &self.doc.region_selection {
+            Some(selection) if selection.rect.contains(pos) => CanvasTarget::RegionSelection,
+            Some(_) => CanvasTarget::Canvas,
+            None => self.canvas_target(pos),
+        };
+        let state = CursorState {
+            tool: self.current_tool.clone(),
+            target,
+            dragging,
+            panning: response.dragged_by(egui::PointerButton::Middle) || self.is_pan_key_held(ui.ctx()),
+            picking_color: self.doc.color_picking,
+            selecting_region: self.doc.region_selection.is_some(),
+        };
+        match cursors::canvas_cursor(&state) {
+            CanvasCursor::Icon(icon) => ui.ctx().set_cursor_icon(icon),
+            CanvasCursor::Eyedropper => {
+                ui.ctx().set_cursor_icon(egui::CursorIcon::None);
+                let layer = egui::LayerId::new(egui::Order::Tooltip, egui::Id::new("eyedropper_cursor"));
+                cursors::paint_eyedropper(&ui.ctx().layer_painter(layer), pointer);
+            }
+        }
+    }
+
+    /// Find what the active tool would act on at an image-space point, including a drag in progress
+    fn canvas_target(&self, pos: Pos2) -> CanvasTarget {
+        match self.current_tool {
+            Tool::Select => {
+                if let Some((id, corner)) = self.doc.resizing.or_else(|| self.resize_handle_at(pos)) {
+                    if let Some(annotation) = self.doc.annotations.iter().find(|a| a.id == id) {
+                        return CanvasTarget::ResizeHandle(annotation.corners()[corner] - annotation.bounds().center());
+                    }
+                }
+                if self.doc.rotating.is_some() || self.rotate_handle_at(pos).is_some() {
+                    CanvasTarget::RotateHandle
+                } else if self.doc.drag_target.is_some() || self.annotation_at(pos).is_some() {
+                    CanvasTarget::Annotation
+                } else {
+                    CanvasTarget::Canvas
+                }
+            }
+            Tool::Perspective if self.doc.perspective_handle.is_some() || self.perspective_handle_at(pos).is_some() => {
+                CanvasTarget::PerspectiveHandle
+            }
+            _ => CanvasTarget::Canvas,
+        }
+    }
+
+    /// Check if the pointer gesture on the canvas is a pan rather than a tool action
+    fn is_pan_gesture(&self, response: &Response) -> bool {
+        response.dragged_by(egui::PointerButton::Middle) || self.is_pan_key_held(&response.ctx)
+    }
+
+    /// Move the capture region being selected, or draw a new one, by dragging on the canvas
+    ///
+    /// Fixed-size selections are only moved, and Shift keeps the aspect ratio
+    /// of the selection while drawing. Ctrl snaps the selection to the layout
+    /// grid of the screen, except while drawing with a locked ratio.
+    fn handle_region_drag(&mut self, response: &Response, image_rect: Rect) {
+        if self.is_pan_gesture(response) {
+            return;
+        }
+        let (Some(size), Some(pointer)) = (self.image_size(), response.interact_pointer_pos()) else {
+            return;
+        };
+        let bounds = Rect::from_min_size(Pos2::ZERO, size);
+        let pos = self.screen_to_image(pointer, image_rect);
+        let constraint = self.region_constraint;
+        let (keep_aspect, layout) = response.ctx.input(|i| (i.modifiers.shift, i.modifiers.ctrl));
+        let Some(selection) = &mut self.doc.region_selection else {
+            return;
+        };
+        if response.drag_started_by(egui::PointerButton::Primary) {
+            selection.drag = Some(match constraint {
+                _ if selection.rect.contains(pos) => RegionDrag::Move { grab: pos - selection.rect.min },
+                SelectionConstraint::Fixed(..) => {
+                    // The fixed-size selection jumps to the pointer and is moved from there
+                    selection.rect = regions::fit_within(Rect::from_center_size(pos, selection.rect.size()), bounds);
+                    RegionDrag::Move { grab: pos - selection.rect.min }
+                }
+                _ => RegionDrag::Draw { start: pos, aspect: selection.rect.width() / selection.rect.height().max(1.0) },
+            });
+        }
+        let moved = match selection.drag.filter(|_| response.dragged_by(egui::PointerButton::Primary)) {
+            // Keep the whole selection on the image while moving it
+            Some(RegionDrag::Move { grab }) => {
+                let rect = Rect::from_min_size(pos - grab, selection.rect.size());
+                Some(if layout { regions::snap_to_layout(rect, bounds) } else { regions::fit_within(rect, bounds) })
+            }
+            Some(RegionDrag::Draw { start, aspect }) => match constraint.aspect().or(keep_aspect.then_some(aspect)) {
+                Some(aspect) => Some(regions::aspect_rect(start, pos, aspect, bounds)),
+                None if layout => Some(regions::layout_rect(start, pos, bounds)),
+                None => Some(Rect::from_two_pos(start, pos)),
+            },
+            None => None,
+        };
+        if response.drag_released() {
+            selection.drag = None;
+        }
+        if let Some(rect) = moved {
+            self.set_selected_region(rect);
+        }
+    }
+
+    /// Convert a screen position to image pixel coordinates
+    fn screen_to_image(&self, pos: Pos2, image_rect: Rect) -> Pos2 {
+        ((pos - image_rect.min) / self.doc.zoom_level as f32).to_pos2()
+    }
+
+    /// Snap an image-space point to the grid when grid snapping applies
+    fn snap_to_grid(&self, pos: Pos2, modifiers: egui::Modifiers) -> Pos2 {
+        let grid = self.settings.get_settings().grid;
+        if grid.is_snapping(modifiers) {
+            grid.snap_point(pos)
+        } else {
+            pos
+        }
+    }
+
+    /// Collect the edges and centers of other annotations and the image center to align to
+    fn align_targets(&self, exclude: Option<Uuid>) -> AlignTargets {
+        let mut targets = AlignTargets::new();
+        for annotation in self.doc.annotations.iter().filter(|annotation| Some(annotation.id) != exclude) {
+            targets.add_rect(annotation.bounds());
+        }
+        if let Some(size) = self.image_size() {
+            targets.add_center(Rect::from_min_size(Pos2::ZERO, size));
+        }
+        targets
+    }
+
+    /// Tolerance for alignment snapping in image pixels
+    fn align_tolerance(&self) -> f32 {
+        ALIGN_TOLERANCE / self.doc.zoom_level as f32
+    }
+
+    /// Snap a point being drawn to the grid, then into alignment with other annotations
+    ///
+    /// Holding Alt inverts grid snapping and turns off alignment snapping.
+    fn snap_drawing_point(&self, pos: Pos2, modifiers: egui::Modifiers) -> (Pos2, Vec<Guide>) {
+        let pos = self.snap_to_grid(pos, modifiers);
+        if modifiers.alt {
+            return (pos, Vec::new());
+        }
+        let alignment = self.align_targets(None).align_point(pos, self.align_tolerance());
+        (pos + alignment.offset, alignment.guides)
+    }
+
+    /// Move the dragged annotation by the pointer movement, snapping it into alignment
+    fn move_dragged_annotation(&mut self, id: Uuid, delta: Vec2, modifiers: egui::Modifiers) {
+        self.doc.drag_moved += delta;
+        let Some(bounds) = self.doc.annotations.iter().find(|a| a.id == id).map(AnnotationItem::bounds) else {
+            return;
+        };
+        let position = self.doc.drag_origin + self.doc.drag_moved;
+        let alignment = if modifiers.alt {
+            Default::default()
+        } else {
+            let moved = bounds.translate(position - bounds.min);
+            self.align_targets(Some(id)).align_rect(moved, self.align_tolerance())
+        };
+        self.doc.guides = alignment.guides;
+        if let Some(annotation) = self.doc.annotations.iter_mut().find(|a| a.id == id) {
+            annotation.position = position + alignment.offset;
+            self.mark_content_changed();
+        }
+    }
+
+    /// Find the selected annotation whose rotate handle is under an image-space point
+    fn rotate_handle_at(&self, point: Pos2) -> Option<Uuid> {
+        let zoom = self.doc.zoom_level as f32;
+        let distance = ROTATE_HANDLE_DISTANCE / zoom;
+        // Slightly larger than drawn so the small handle is easy to grab
+        let radius = (ROTATE_HANDLE_RADIUS + 3.0) / zoom;
+        self.doc.annotations
+            .iter()
+            .rev()
+            .filter(|a| a.is_selected)
+            .find(|a| a.rotate_handle(distance).distance(point) <= radius)
+            .map(|a| a.id)
+    }
+
+    /// Turn the rotated annotation's handle towards the pointer, in fixed steps with Ctrl held
+    fn rotate_dragged_annotation(&mut self, id: Uuid, pointer: Pos2, modifiers: egui::Modifiers) {
+        let Some(annotation) = self.doc.annotations.iter_mut().find(|a| a.id == id) else {
+            return;
+        };
+        let rotation = annotation.rotation_towards(pointer);
+        annotation.rotation = if modifiers.command {
+            (rotation / ROTATION_SNAP_STEP).round() * ROTATION_SNAP_STEP
+        } else {
+            rotation
+        };
+        self.mark_content_changed();
+    }
+
+    /// Find the selected annotation and corner whose resize handle is under an image-space point
+    fn resize_handle_at(&self, point: Pos2) -> Option<(Uuid, usize)> {
+        let reach = (SELECTION_HANDLE_SIZE * 0.5 + 2.0) / self.doc.zoom_level as f32;
+        self.doc.annotations
+            .iter()
+            .rev()
+            .filter(|a| a.is_selected && !matches!(a.annotation_type, AnnotationType::Text { .. }))
+            .find_map(|a| {
+                let corner = a.corners().iter().position(|corner| corner.distance(point) <= reach)?;
+                Some((a.id, corner))
+            })
+    }
+
+    /// Drag a corner of the resized annotation to the pointer, keeping the aspect ratio of images
+    fn resize_dragged_annotation(&mut self, id: Uuid, corner: usize, pointer: Pos2) {
+        let Some(annotation) = self.doc.annotations.iter_mut().find(|a| a.id == id) else {
+            return;
+        };
+        let keep_aspect = matches!(annotation.annotation_type, AnnotationType::Image { .. });
+        annotation.resize_corner(corner, pointer, keep_aspect);
+        self.mark_content_changed();
+    }
+
+    /// Handle annotation creation, selection, moving, and rotating for the current tool
+    fn handle_tool_interactions(&mut self, response: &Response, image_rect: Rect) {
+        if self.is_pan_gesture(response) {
+            return;
+        }
+        let modifiers = response.ctx.input(|i| i.modifiers);
+        let pointer = response.interact_pointer_pos().map(|pos| self.screen_to_image(pos, image_rect));
+        let snapped = pointer.map(|pos| self.snap_drawing_point(pos, modifiers));
+
+        if response.drag_started_by(egui::PointerButton::Primary) {
+            if let (Some(pos), Some((snapped, _))) = (pointer, &snapped) {
+                match self.current_tool {
+                    Tool::Select if self.rotate_handle_at(pos).is_some() => {
+                        self.doc.rotating = self.rotate_handle_at(pos);
+                    }
+                    Tool::Select if self.resize_handle_at(pos).is_some() => {
+                        self.doc.resizing = self.resize_handle_at(pos);
+                    }
+                    Tool::Select => {
+                        self.doc.drag_target = self.annotation_at(pos);
+                        self.select_annotation(self.doc.drag_target);
+                        let target = self.doc.drag_target;
+                        if let Some(annotation) = self.doc.annotations.iter().find(|a| Some(a.id) == target) {
+                            self.doc.drag_origin = annotation.position;
+                            self.doc.drag_moved = Vec2::ZERO;
+                        } else {
+                            // Dragging on the image outside annotations selects a region
+                            self.doc.selecting_start = Some(pos);
+                            self.doc.selection_region = None;
+                        }
+                    }
+                    Tool::Rectangle => self.doc.drawing_start = Some(*snapped),
+                    Tool::Eraser => self.doc.erasing_start = Some(pos),
+                    Tool::Measure => self.doc.measurement = Some([*snapped, *snapped]),
+                    Tool::Perspective => self.doc.perspective_handle = self.perspective_handle_at(pos),
+                    Tool::Text | Tool::Sticker => {}
+                }
+            }
+        }
+
+        if response.dragged_by(egui::PointerButton::Primary) {
+            if let (Some(corner), Some(pos)) = (self.doc.perspective_handle, pointer) {
+                self.set_perspective_corner(corner, pos);
+            } else if let (Some(id), Some(pos)) = (self.doc.rotating, pointer) {
+                self.rotate_dragged_annotation(id, pos, modifiers);
+            } else if let (Some((id, corner)), Some(pos)) = (self.doc.resizing, pointer) {
+                self.resize_dragged_annotation(id, corner, pos);
+            } else if let Some(id) = self.doc.drag_target {
+                let delta = response.drag_delta() / self.doc.zoom_level as f32;
+                self.move_dragged_annotation(id, delta, modifiers);
+            } else if let (Some(start), Some(pos), Some(size)) = (self.doc.selecting_start, pointer, self.image_size()) {
+                let region = Rect::from_two_pos(start, pos).intersect(Rect::from_min_size(Pos2::ZERO, size));
+                self.doc.selection_region = Some(region);
+            } else if let (Some(_), Some((_, guides))) = (self.doc.drawing_start, &snapped) {
+                self.doc.guides = guides.clone();
+            } else if let (Tool::Measure, Some([start, _]), Some((end, guides))) =
+                (&self.current_tool, self.doc.measurement, &snapped)
+            {
+                // Shift keeps the line at a multiple of 45 degrees
+                if modifiers.shift {
+                    self.doc.measurement = Some([start, measure::constrain(start, *end)]);
+                    self.doc.guides.clear();
+                } else {
+                    self.doc.measurement = Some([start, *end]);
+                    self.doc.guides = guides.clone();
+                }
+            }
+        }
+
+        if response.drag_released_by(egui::PointerButton::Primary) {
+            self.doc.perspective_handle = None;
+            self.doc.drag_target = None;
+            self.doc.rotating = None;
+            self.doc.resizing = None;
+            self.doc.guides.clear();
+            if let (Some(start), Some((end, _))) = (self.doc.drawing_start.take(), snapped.clone()) {
+                let rect = Rect::from_two_pos(start, end);
+                if rect.width() >= MIN_RECTANGLE_SIZE && rect.height() >= MIN_RECTANGLE_SIZE {
+                    if let Some(annotation) = self.create_annotation(&Tool::Rectangle, rect.min, rect.size()) {
+                        self.add_annotation(annotation);
+                    }
+                }
+            }
+            if let (Some(start), Some(end)) = (self.doc.erasing_start.take(), pointer) {
+                self.erase_in(Rect::from_two_pos(start, end));
+            }
+            if self.doc.selecting_start.take().is_some() {
+                self.finish_region_selection();
+            }
+            if self.current_tool == Tool::Measure && self.keep_measurements {
+                self.keep_measurement();
+            }
+        }
+
+        if response.clicked_by(egui::PointerButton::Primary) {
+            if let (Some(pos), Some((snapped, _))) = (pointer, snapped) {
+                match self.current_tool {
+                    Tool::Select if self.rotate_handle_at(pos).is_some() || self.resize_handle_at(pos).is_some() => {}
+                    Tool::Select => {
+                        self.doc.selection_region = None;
+                        self.select_annotation(self.annotation_at(pos));
+                    }
+                    Tool::Text => {
+                        if let Some(annotation) = self.create_annotation(&Tool::Text, snapped, Vec2::ZERO) {
+                            let id = annotation.id;
+                            self.add_annotation(annotation);
+                            self.select_annotation(Some(id));
+                        }
+                    }
+                    // Stickers are stamped one after another without selecting them
+                    Tool::Sticker => {
+                        if let Some(annotation) = self.create_annotation(&Tool::Sticker, snapped, Vec2::ZERO) {
+                            self.add_annotation(annotation);
+                        }
+                    }
+                    Tool::Eraser => {
+                        if let Some(id) = self.annotation_at(pos) {
+                            self.erase(&[id]);
+                        }
+                    }
+                    Tool::Measure => self.doc.measurement = None,
+                    Tool::Rectangle | Tool::Perspective => {}
+                }
+            }
+        }
+    }
+
+    /// Keep the region dragged out with the Select tool and select the annotations touching it
+    ///
+    /// Regions smaller than a rectangle annotation are dropped, as the drag
+    /// was most likely a click.
+    fn finish_region_selection(&mut self) {
+        let Some(region) = self.doc.selection_region.filter(|region| {
+            region.width() >= MIN_RECTANGLE_SIZE && region.height() >= MIN_RECTANGLE_SIZE
+        }) else {
+            self.doc.selection_region = None;
+            return;
+        };
+        for annotation in &mut self.doc.annotations {
+            annotation.is_selected = annotation.intersects(region);
+        }
+    }
+
+    /// Offer actions on the region dragged out with the Select tool on right click
+    fn show_selection_menu(&mut self, response: &Response) {
+        if self.current_tool != Tool::Select || self.doc.selection_region.is_none() {
+            return;
+        }
+        response.clone().context_menu(|ui| {
+            if ui.button("Crop image to selection").clicked() {
+                if let Err(e) = self.crop_to_selection() {
+                    self.notify_error("Crop Failed", &e);
+                }
+                ui.close_menu();
+            }
+            if ui.button("Clear selection").clicked() {
+                self.doc.selection_region = None;
+                ui.close_menu();
+            }
+        });
+    }
+
+    /// Draw the region being or last dragged out with the Select tool
+    fn draw_selection_region(&self, ui: &mut egui::Ui, image_rect: Rect) {
+        let Some(region) = self.doc.selection_region.filter(|_| self.current_tool == Tool::Select) else {
+            return;
+        };
+        let zoom = self.doc.zoom_level as f32;
+        let to_screen = |point: Pos2| image_rect.min + point.to_vec2() * zoom;
+        let rect = Rect::from_min_max(to_screen(region.min), to_screen(region.max));
+        let stroke = ui.visuals().selection.stroke;
+        ui.painter().rect_filled(rect, 0.0, ui.visuals().selection.bg_fill.gamma_multiply(0.15));
+        let outline = [rect.left_top(), rect.right_top(), rect.right_bottom(), rect.left_bottom(), rect.left_top()];
+        ui.painter().extend(egui::Shape::dashed_line(&outline, stroke, 6.0, 4.0));
+    }
+
+    /// Draw the alignment guides of the annotation being moved or drawn
+    fn draw_guides(&self, ui: &mut egui::Ui, image_rect: Rect) {
+        let zoom = self.doc.zoom_level as f32;
+        let stroke = egui::Stroke::new(1.0, GUIDE_COLOR);
+        for guide in &self.doc.guides {
+            let position = guide.position * zoom;
+            let span = (guide.start * zoom)..=(guide.end * zoom);
+            match guide.axis {
+                GuideAxis::Vertical => ui.painter().vline(
+                    image_rect.min.x + position,
+                    (image_rect.min.y + span.start())..=(image_rect.min.y + span.end()),
+                    stroke,
+                ),
+                GuideAxis::Horizontal => ui.painter().hline(
+                    (image_rect.min.x + span.start())..=(image_rect.min.x + span.end()),
+                    image_rect.min.y + position,
+                    stroke,
+                ),
+            }
+        }
+    }
+
+    /// Draw grid lines over the visible part of the image, fading subdivisions
+    fn draw_grid(&self, ui: &mut egui::Ui, grid: &GridOptions, image_rect: Rect, visible_rect: Rect) {
+        if visible_rect.width() <= 0.0 || visible_rect.height() <= 0.0 {
+            return;
+        }
+        let zoom = self.doc.zoom_level as f32;
+        let painter = ui.painter().with_clip_rect(visible_rect);
+        let major = egui::Stroke::new(1.0, egui::Color32::from_white_alpha(90));
+        let minor = egui::Stroke::new(1.0, egui::Color32::from_white_alpha(35));
+        let visible = Rect::from_min_max(
+            self.screen_to_image(visible_rect.min, image_rect),
+            self.screen_to_image(visible_rect.max, image_rect),
+        );
+
+        for (step, stroke) in [(grid.step(), minor), (grid.spacing, major)] {
+            let is_subdivision = stroke == minor;
+            if step * zoom < MIN_GRID_LINE_SPACING || (is_subdivision && grid.subdivisions == 1) {
+                continue;
+            }
+            for x in grid::lines(visible.min.x, visible.max.x, step) {
+                let screen_x = image_rect.min.x + x * zoom;
+                painter.vline(screen_x, visible_rect.y_range(), stroke);
+            }
+            for y in grid::lines(visible.min.y, visible.max.y, step) {
+                let screen_y = image_rect.min.y + y * zoom;
+                painter.hline(visible_rect.x_range(), screen_y, stroke);
+            }
+        }
+    }
+
+    /// Draw lines between individual image pixels over the visible part of the image
+    fn draw_pixel_grid(&self, ui: &mut egui::Ui, image_rect: Rect, visible_rect: Rect) {
+        if visible_rect.width() <= 0.0 || visible_rect.height() <= 0.0 {
+            return;
+        }
+        let zoom = self.doc.zoom_level as f32;
+        let painter = ui.painter().with_clip_rect(visible_rect);
+        // Mid gray stays visible over both light and dark pixels
+        let stroke = egui::Stroke::new(1.0, egui::Color32::from_rgba_unmultiplied(128, 128, 128, 70));
+        let visible = Rect::from_min_max(
+            self.screen_to_image(visible_rect.min, image_rect),
+            self.screen_to_image(visible_rect.max, image_rect),
+        );
+        for x in grid::lines(visible.min.x, visible.max.x, 1.0) {
+            painter.vline(image_rect.min.x + x * zoom, visible_rect.y_range(), stroke);
+        }
+        for y in grid::lines(visible.min.y, visible.max.y, 1.0) {
+            painter.hline(visible_rect.x_range(), image_rect.min.y + y * zoom, stroke);
+        }
+    }
+
+    /// Draw pixel rulers along the top and left canvas edges with a pointer marker
+    fn draw_rulers(&self, ui: &mut egui::Ui, response: &Response, available_rect: Rect, image_rect: Rect) {
+        let zoom = self.doc.zoom_level as f32;
+        let visuals = ui.visuals();
+        let background = visuals.faint_bg_color;
+        let stroke = egui::Stroke::new(1.0, visuals.text_color().gamma_multiply(0.6));
+        let text_color = visuals.text_color();
+        let font = egui::FontId::proportional(10.0);
+        let painter = ui.painter();
+
+        let top = Rect::from_min_max(
+            available_rect.min,
+            Pos2::new(available_rect.max.x, available_rect.min.y + RULER_SIZE),
+        );
+        let left = Rect::from_min_max(
+            available_rect.min,
+            Pos2::new(available_rect.min.x + RULER_SIZE, available_rect.max.y),
+        );
+        painter.rect_filled(top, 0.0, background);
+        painter.rect_filled(left, 0.0, background);
+
+        let label_step = grid::ruler_step(zoom);
+        let tick_step = label_step / 5.0;
+        let start = self.screen_to_image(available_rect.min, image_rect);
+        let end = self.screen_to_image(available_rect.max, image_rect);
+
+        let top_painter = painter.with_clip_rect(Rect::from_min_max(Pos2::new(left.max.x, top.min.y), top.max));
+        for x in grid::lines(start.x, end.x, tick_step) {
+            let screen_x = image_rect.min.x + x * zoom;
+            let labeled = (x / label_step).round() * label_step == x;
+            let length = if labeled { RULER_SIZE } else { RULER_SIZE * 0.3 };
+            top_painter.vline(screen_x, (top.max.y - length)..=top.max.y, stroke);
+            if labeled {
+                let position = Pos2::new(screen_x + 2.0, top.min.y);
+                top_painter.text(position, egui::Align2::LEFT_TOP, x.to_string(), font.clone(), text_color);
+            }
+        }
+
+        let left_painter = painter.with_clip_rect(Rect::from_min_max(Pos2::new(left.min.x, top.max.y), left.max));
+        for y in grid::lines(start.y, end.y, tick_step) {
+            let screen_y = image_rect.min.y + y * zoom;
+            let labeled = (y / label_step).round() * label_step == y;
+            let length = if labeled { RULER_SIZE } else { RULER_SIZE * 0.3 };
+            left_painter.hline((left.max.x - length)..=left.max.x, screen_y, stroke);
+            if labeled {
+                let position = Pos2::new(left.min.x + 1.0, screen_y + 2.0);
+                left_painter.text(position, egui::Align2::LEFT_TOP, y.to_string(), font.clone(), text_color);
+            }
+        }
+
+        // Mark the pointer position on both rulers
+        if let Some(pointer) = response.hover_pos() {
+            let marker = egui::Stroke::new(1.0, visuals.selection.stroke.color);
+            top_painter.vline(pointer.x, top.y_range(), marker);
+            left_painter.hline(left.x_range(), pointer.y, marker);
+        }
+    }
+
+    /// Draw the rectangle currently being dragged out
+    fn draw_rectangle_preview(&self, ui: &mut egui::Ui, response: &Response, image_rect: Rect) {
+        let (Some(start), Some(current)) = (self.doc.drawing_start, response.interact_pointer_pos()) else {
+            return;
+        };
+        let style = self.settings.get_settings().styles.style_for_tool(&Tool::Rectangle);
+        let zoom = self.doc.zoom_level as f32;
+        let start = image_rect.min + start.to_vec2() * zoom;
+        let modifiers = response.ctx.input(|i| i.modifiers);
+        let (current, _) = self.snap_drawing_point(self.screen_to_image(current, image_rect), modifiers);
+        let current = image_rect.min + current.to_vec2() * zoom;
+        ui.painter().rect_stroke(
+            Rect::from_two_pos(start, current),
+            0.0,
+            egui::Stroke::new(style.stroke_width, style.stroke_color.gamma_multiply(style.alpha)),
+        );
+    }
+
+    /// Draw the region being dragged out with the Eraser tool, outlining the annotations it will delete
+    fn draw_eraser_preview(&self, ui: &mut egui::Ui, response: &Response, image_rect: Rect) {
+        let (Some(start), Some(current)) = (self.doc.erasing_start, response.interact_pointer_pos()) else {
+            return;
+        };
+        let zoom = self.doc.zoom_level as f32;
+        let to_screen = |point: Pos2| image_rect.min + point.to_vec2() * zoom;
+        let region = Rect::from_two_pos(start, self.screen_to_image(current, image_rect));
+        let stroke = egui::Stroke::new(1.0, ERASER_COLOR);
+        for annotation in self.doc.annotations.iter().filter(|a| a.intersects(region)) {
+            ui.painter().add(egui::Shape::closed_line(annotation.corners().map(to_screen).to_vec(), stroke));
+        }
+        let region = Rect::from_min_max(to_screen(region.min), to_screen(region.max));
+        ui.painter().rect(region, 0.0, ERASER_COLOR.gamma_multiply(0.15), stroke);
+    }
+
+    /// Build the shapes of a dimension annotation: its line, end ticks, and label
+    fn dimension_shapes(ui: &egui::Ui, annotation: &AnnotationItem, image_rect: Rect, zoom: f32) -> Vec<egui::Shape> {
+        let AnnotationType::Dimension { end, color, width, .. } = &annotation.annotation_type else {
+            return Vec::new();
+        };
+        let center = annotation.bounds().center();
+        let place = |point: Pos2| image_rect.min + rotate_about(point, center, annotation.rotation).to_vec2() * zoom;
+        let (start, end) = (annotation.position, annotation.position + *end);
+        let stroke = egui::Stroke::new(width * zoom, *color);
+        let [first, last] = measure::ticks(start, end);
+        let mut shapes: Vec<egui::Shape> = [[start, end], first, last]
+            .into_iter()
+            .map(|[a, b]| egui::Shape::line_segment([place(a), place(b)], stroke))
+            .collect();
+
+        let format = measure::label_format();
+        let font_size = measure::LABEL_FONT_SIZE;
+        let label = render::dimension_label(annotation);
+        let job = text::layout_job(&label, font_size, *color, &format, egui::FontFamily::Proportional, false, zoom);
+        let galley = ui.fonts(|fonts| fonts.layout_job(job));
+        let rect = measure::label_rect(start, end, *width, text::box_size(galley.size() / zoom, font_size, &format));
+        if let Some(background) = format.background {
+            let corners = [rect.left_top(), rect.right_top(), rect.right_bottom(), rect.left_bottom()].map(place);
+            shapes.push(egui::Shape::convex_polygon(corners.to_vec(), background, egui::Stroke::NONE));
+        }
+        let pos = place(rect.min + text::text_anchor(rect.size(), font_size, &format));
+        let angle = annotation.rotation.to_radians();
+        shapes.push(egui::Shape::Text(egui::epaint::TextShape { angle, ..egui::epaint::TextShape::new(pos, galley) }));
+        shapes
+    }
+
+    /// Draw the line being measured with the Measure tool
+    fn draw_measurement(&self, ui: &mut egui::Ui, image_rect: Rect) {
+        let (Tool::Measure, Some(dimension)) = (&self.current_tool, self.measurement_dimension()) else {
+            return;
+        };
+        let zoom = self.doc.zoom_level as f32;
+        ui.painter().extend(Self::dimension_shapes(ui, &dimension, image_rect, zoom));
+    }
+
+    /// Draw the region the Perspective tool will straighten, with handles on its corners
+    fn draw_perspective(&self, ui: &mut egui::Ui, image_rect: Rect) {
+        let (Tool::Perspective, Some(corners)) = (&self.current_tool, self.perspective_corners()) else {
+            return;
+        };
+        let zoom = self.doc.zoom_level as f32;
+        let points = corners.map(|corner| image_rect.min + corner.to_vec2() * zoom);
+        let color = ui.visuals().selection.bg_fill;
+        ui.painter().add(egui::Shape::closed_line(points.to_vec(), egui::Stroke::new(2.0, color)));
+        for (index, point) in points.into_iter().enumerate() {
+            let radius = if self.doc.perspective_handle == Some(index) {
+                PERSPECTIVE_HANDLE_RADIUS + 2.0
+            } else {
+                PERSPECTIVE_HANDLE_RADIUS
+            };
+            ui.painter().circle(point, radius, egui::Color32::WHITE, egui::Stroke::new(2.0, color));
+        }
+    }
+
+    /// Get the current measurement as a dimension annotation styled for the Measure tool
+    fn measurement_dimension(&self) -> Option<AnnotationItem> {
+        let [start, end] = self.doc.measurement?;
+        self.create_annotation(&Tool::Measure, start, end - start)
+    }
+
+    /// Draw annotations over the image
+    fn draw_annotations(&self, ui: &mut egui::Ui, image_rect: Rect) {
+        let zoom = self.doc.zoom_level as f32;
+        let to_screen = |point: Pos2| image_rect.min + point.to_vec2() * zoom;
+        for annotation in &self.doc.annotations {
+            let annotation_pos = to_screen(annotation.position);
+            let mut shapes = Vec::new();
+
+            match &annotation.annotation_type {
+                crate::AnnotationType::Rectangle { size, stroke_color, stroke_width, fill_color } => {
+                    let stroke = egui::Stroke::new(*stroke_width, *stroke_color);
+                    if annotation.is_rotated() {
+                        let points: Vec<Pos2> = annotation.corners().into_iter().map(to_screen).collect();
+                        if let Some(fill) = fill_color {
+                            shapes.push(egui::Shape::convex_polygon(points.clone(), *fill, egui::Stroke::NONE));
+                        }
+                        shapes.push(egui::Shape::closed_line(points, stroke));
+                    } else {
+                        let rect = Rect::from_min_size(annotation_pos, *size * zoom);
+                        if let Some(fill) = fill_color {
+                            shapes.push(egui::Shape::rect_filled(rect, 0.0, *fill));
+                        }
+                        shapes.push(egui::Shape::rect_stroke(rect, 0.0, stroke));
+                    }
+                }
+                crate::AnnotationType::Text { content, font_size, color, format } => {
+                    let face = text::resolve_face(format, text::installed_fonts());
+                    // Faces not registered yet are drawn with the built-in font
+                    let family = face
+                        .face
+                        .as_ref()
+                        .map(|font| egui::FontFamily::Name(font.name.as_str().into()))
+                        .filter(|family| ui.fonts(|fonts| fonts.families().contains(family)))
+                        .unwrap_or(egui::FontFamily::Proportional);
+                    let job = text::layout_job(content, *font_size, *color, format, family, face.synthetic_italic, zoom);
+                    let galley = ui.fonts(|fonts| fonts.layout_job(job));
+                    let text_size = galley.size() / zoom + Vec2::new(face.extra_width(*font_size), 0.0);
+                    let box_size = text::box_size(text_size, *font_size, format);
+                    let center = annotation.bounds().center();
+                    let place = |point: Pos2| to_screen(rotate_about(point, center, annotation.rotation));
+
+                    if format.has_box() {
+                        let rect = Rect::from_min_size(annotation.position, box_size);
+                        let corners: Vec<Pos2> = [rect.left_top(), rect.right_top(), rect.right_bottom(), rect.left_bottom()]
+                            .into_iter()
+                            .map(place)
+                            .collect();
+                        if let Some(background) = format.background {
+                            shapes.push(egui::Shape::convex_polygon(corners.clone(), background, egui::Stroke::NONE));
+                        }
+                        if let Some(outline) = format.outline {
+                            shapes.push(egui::Shape::closed_line(corners, egui::Stroke::new(outline.width * zoom, outline.color)));
+                        }
+                    }
+
+                    // egui rotates text about its top-left corner, so rotate that corner about the center first
+                    let angle = annotation.rotation.to_radians();
+                    let pos = place(annotation.position + text::text_anchor(box_size, *font_size, format));
+                    if face.synthetic_bold {
+                        let offset = Vec2::angled(angle) * text::bold_offset(*font_size) * zoom;
+                        shapes.push(egui::Shape::Text(egui::epaint::TextShape {
+                            angle,
+                            ..egui::epaint::TextShape::new(pos + offset, galley.clone())
+                        }));
+                    }
+                    shapes.push(egui::Shape::Text(egui::epaint::TextShape { angle, ..egui::epaint::TextShape::new(pos, galley) }));
+                }
+                crate::AnnotationType::Image { opacity, .. } => {
+                    let corners = annotation.corners().map(to_screen);
+                    match self.doc.image_textures.get(&annotation.id) {
+                        Some(Some(texture)) => {
+                            let tint = egui::Color32::WHITE.gamma_multiply(*opacity);
+                            let uvs = [Pos2::new(0.0, 0.0), Pos2::new(1.0, 0.0), Pos2::new(1.0, 1.0), Pos2::new(0.0, 1.0)];
+                            let mut mesh = egui::Mesh::with_texture(texture.id());
+                            for (pos, uv) in corners.into_iter().zip(uvs) {
+                                mesh.vertices.push(egui::epaint::Vertex { pos, uv, color: tint });
+                            }
+                            mesh.add_triangle(0, 1, 2);
+                            mesh.add_triangle(0, 2, 3);
+                            shapes.push(egui::Shape::mesh(mesh));
+                        }
+                        // Not uploaded yet or undecodable: show where the image is
+                        _ => {
+                            shapes.push(egui::Shape::closed_line(
+                                corners.to_vec(),
+                                egui::Stroke::new(1.0, egui::Color32::GRAY),
+                            ));
+                        }
+                    }
+                }
+                crate::AnnotationType::Dimension { .. } => shapes = Self::dimension_shapes(ui, annotation, image_rect, zoom),
+            }
+
+            // egui can't blend other than normally, so such annotations show their rendered pixels
+            match self.doc.blend_previews.get(&annotation.id) {
+                Some(preview) if annotation.blend_mode != BlendMode::Normal => {
+                    let rect = Rect::from_min_max(to_screen(preview.region.min), to_screen(preview.region.max));
+                    let uv = Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0));
+                    ui.painter().image(preview.texture.id(), rect, uv, egui::Color32::WHITE);
+                }
+                _ => {
+                    let mut shape = egui::Shape::Vec(shapes);
+                    if annotation.opacity < 1.0 {
+                        let opacity = annotation.opacity.max(0.0);
+                        egui::epaint::shape_transform::adjust_colors(&mut shape, &|color| {
+                            *color = color.gamma_multiply(opacity);
+                        });
+                    }
+                    ui.painter().add(shape);
+                }
+            }
+
+            // Draw selection and rotate handles if selected
+            if annotation.is_selected {
+                self.draw_selection_handles(ui, annotation.corners().map(to_screen));
+                let top = to_screen(annotation.rotate_handle(0.0));
+                let handle = to_screen(annotation.rotate_handle(ROTATE_HANDLE_DISTANCE / zoom));
+                ui.painter().line_segment([top, handle], egui::Stroke::new(1.0, egui::Color32::BLUE));
+                ui.painter().circle(
+                    handle,
+                    ROTATE_HANDLE_RADIUS,
+                    egui::Color32::BLUE,
+                    egui::Stroke::new(1.0, egui::Color32::WHITE),
+                );
+            }
+        }
+    }
+
+    /// Draw the difference heatmap and changed-region boxes of the image comparison
+    fn draw_image_comparison_overlay(&self, ui: &mut egui::Ui, image_rect: Rect) {
+        let Some(comparison) = &self.doc.image_comparison else {
+            return;
+        };
+        let zoom = self.doc.zoom_level as f32;
+        if let Some(texture) = comparison.heatmap_texture.as_ref().filter(|_| comparison.show_heatmap) {
+            let size = Vec2::new(comparison.result.width as f32, comparison.result.height as f32) * zoom;
+            let uv = Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0));
+            ui.painter().image(texture.id(), Rect::from_min_size(image_rect.min, size), uv, egui::Color32::WHITE);
+        }
+        for region in &comparison.result.regions {
+            let rect = Rect::from_min_size(image_rect.min + region.min.to_vec2() * zoom, region.size() * zoom);
+            ui.painter().rect_stroke(rect.expand(2.0), 0.0, egui::Stroke::new(2.0, DIFF_REMOVED_COLOR));
+        }
+    }
+
+    /// Dim the capture outside the region being selected
+    fn draw_region_selection(&self, ui: &mut egui::Ui, response: &Response, image_rect: Rect, preview: Option<&TextureHandle>) {
+        let Some(selection) = &self.doc.region_selection else {
+            return;
+        };
+        let overlay = self.settings.get_settings().overlay;
+        let zoom = self.doc.zoom_level as f32;
+        let rect = Rect::from_min_size(image_rect.min + selection.rect.min.to_vec2() * zoom, selection.rect.size() * zoom);
+        // Bands above, below, left of, and right of the selection
+        let bands = [
+            Rect::from_min_max(image_rect.min, Pos2::new(image_rect.max.x, rect.min.y)),
+            Rect::from_min_max(Pos2::new(image_rect.min.x, rect.max.y), image_rect.max),
+            Rect::from_min_max(Pos2::new(image_rect.min.x, rect.min.y), rect.left_bottom()),
+            Rect::from_min_max(rect.right_top(), Pos2::new(image_rect.max.x, rect.max.y)),
+        ];
+        for band in bands {
+            ui.painter().rect_filled(band, 0.0, overlay.dim_color());
+        }
+        if overlay.show_thirds {
+            let stroke = egui::Stroke::new(1.0, overlay.border_color.gamma_multiply(0.6));
+            for line in regions::thirds(rect) {
+                ui.painter().line_segment(line, stroke);
+            }
+        }
+        // The layout grid the selection snaps to while Ctrl is held
+        if ui.input(|i| i.modifiers.ctrl) {
+            let stroke = egui::Stroke::new(1.0, overlay.border_color.gamma_multiply(0.35));
+            let whole = Rect::from_min_size(Pos2::ZERO, image_rect.size() / zoom);
+            for [from, to] in regions::layout_grid(whole) {
+                ui.painter().line_segment([image_rect.min + from.to_vec2() * zoom, image_rect.min + to.to_vec2() * zoom], stroke);
+            }
+        }
+        ui.painter().rect_stroke(rect, 0.0, egui::Stroke::new(overlay.border_width, overlay.border_color));
+        if let Some(edge) = selection.edge {
+            let line = match edge {
+                Edge::Left => [rect.left_top(), rect.left_bottom()],
+                Edge::Top => [rect.left_top(), rect.right_top()],
+                Edge::Right => [rect.right_top(), rect.right_bottom()],
+                Edge::Bottom => [rect.left_bottom(), rect.right_bottom()],
+            };
+            ui.painter().line_segment(line, egui::Stroke::new(3.0, ui.visuals().selection.bg_fill));
+        }
+        if overlay.show_dimensions {
+            ui.painter().text(
+                rect.left_top() - Vec2::new(0.0, 4.0),
+                egui::Align2::LEFT_BOTTOM,
+                format!("{:.0} x {:.0}", selection.rect.width(), selection.rect.height()),
+                egui::FontId::proportional(12.0),
+                egui::Color32::WHITE,
+            );
+        }
+
+        let Some(pointer) = response.hover_pos().filter(|pos| image_rect.contains(*pos)) else {
+            return;
+        };
+        if overlay.show_crosshair {
+            let stroke = egui::Stroke::new(1.0, overlay.border_color);
+            ui.painter().vline(pointer.x, image_rect.y_range(), stroke);
+            ui.painter().hline(image_rect.x_range(), pointer.y, stroke);
+        }
+        if overlay.show_loupe {
+            self.draw_loupe(ui, pointer, image_rect, preview);
+        }
+    }
+
+    /// Draw the loupe and the color under the pointer while picking a color
+    fn draw_color_picker(&self, ui: &mut egui::Ui, response: &Response, image_rect: Rect, preview: Option<&TextureHandle>) {
+        if !self.doc.color_picking {
+            return;
+        }
+        let hint = ui.painter().layout_no_wrap(
+            "Click to copy a color, Esc to cancel".to_string(),
+            egui::FontId::proportional(13.0),
+            egui::Color32::WHITE,
+        );
+        let hint_rect = Rect::from_center_size(
+            ui.clip_rect().center_top() + Vec2::new(0.0, hint.size().y * 0.5 + 8.0),
+            hint.size(),
+        );
+        ui.painter().rect_filled(hint_rect.expand(4.0), 4.0, egui::Color32::from_black_alpha(180));
+        ui.painter().galley(hint_rect.min, hint);
+
+        let Some(pointer) = response.hover_pos().filter(|pos| image_rect.contains(*pos)) else {
+            return;
+        };
+        self.draw_loupe(ui, pointer, image_rect, preview);
+        let Some(color) = self.pixel_color(self.screen_to_image(pointer, image_rect)) else {
+            return;
+        };
+
+        // A swatch and the HEX value below the loupe, or above it at the bottom of the canvas
+        let loupe = regions::loupe_rect(pointer, LOUPE_SIZE, LOUPE_OFFSET, ui.clip_rect());
+        let galley = ui.painter().layout_no_wrap(palette::hex(color), egui::FontId::monospace(12.0), egui::Color32::WHITE);
+        let height = galley.size().y;
+        let below = loupe.left_bottom() + Vec2::new(0.0, 6.0);
+        let top_left = if below.y + height + 3.0 > ui.clip_rect().max.y {
+            loupe.left_top() - Vec2::new(0.0, height + 6.0)
+        } else {
+            below
+        };
+        let swatch = Rect::from_min_size(top_left, Vec2::splat(height));
+        let label = Rect::from_min_size(swatch.right_top() + Vec2::new(4.0, 0.0), galley.size());
+        ui.painter().rect_filled(swatch.union(label).expand(3.0), 2.0, egui::Color32::from_black_alpha(200));
+        ui.painter().rect_filled(swatch, 0.0, color);
+        ui.painter().rect_stroke(swatch, 0.0, egui::Stroke::new(1.0, egui::Color32::WHITE));
+        ui.painter().galley(label.min, galley);
+    }
+
+    /// Draw an enlarged view of the canvas around the pointer, with its image coordinates
+    fn draw_loupe(&self, ui: &mut egui::Ui, pointer: Pos2, image_rect: Rect, preview: Option<&TextureHandle>) {
+        let loupe = regions::loupe_rect(pointer, LOUPE_SIZE, LOUPE_OFFSET, ui.clip_rect());
+        let painter = ui.painter().with_clip_rect(loupe);
+        painter.rect_filled(loupe, 0.0, egui::Color32::BLACK);
+        // The image enlarged so the point under the pointer is at the loupe center
+        let magnified = Rect::from_min_size(
+            loupe.center() - (pointer - image_rect.min) * LOUPE_MAGNIFICATION,
+            image_rect.size() * LOUPE_MAGNIFICATION,
+        );
+        match (preview, &self.doc.texture) {
+            (Some(preview), _) => {
+                let uv = Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0));
+                painter.image(preview.id(), magnified, uv, egui::Color32::WHITE);
+            }
+            (None, Some(texture)) => texture.paint(&painter, magnified),
+            (None, None) => {}
+        }
+        let pixel_size = (self.doc.zoom_level as f32 * LOUPE_MAGNIFICATION).max(3.0);
+        painter.rect_stroke(
+            Rect::from_center_size(loupe.center(), Vec2::splat(pixel_size)),
+            0.0,
+            egui::Stroke::new(1.0, egui::Color32::WHITE),
+        );
+        ui.painter().rect_stroke(loupe, 0.0, egui::Stroke::new(1.0, egui::Color32::WHITE));
+
+        let position = self.screen_to_image(pointer, image_rect);
+        let galley = painter.layout_no_wrap(
+            format!("{:.0}, {:.0}", position.x.floor(), position.y.floor()),
+            egui::FontId::monospace(11.0),
+            egui::Color32::WHITE,
+        );
+        let label = Rect::from_min_size(
+            loupe.center_bottom() - Vec2::new(galley.size().x * 0.5, galley.size().y + 4.0),
+            galley.size(),
+        );
+        painter.rect_filled(label.expand(2.0), 2.0, egui::Color32::from_black_alpha(180));
+        painter.galley(label.min, galley);
+    }
+
+    /// Outline annotations added, removed, or changed since the compared project revision
+    fn draw_diff_highlights(&self, ui: &mut egui::Ui, image_rect: Rect) {
+        let Some(diff) = &self.doc.project_diff else {
+            return;
+        };
+
+        let zoom = self.doc.zoom_level as f32;
+        let to_screen = |annotation: &AnnotationItem| {
+            let bounds = annotation.bounds();
+            Rect::from_min_size(image_rect.min + bounds.min.to_vec2() * zoom, bounds.size() * zoom).expand(3.0)
+        };
+        for annotation in &diff.removed {
+            ui.painter().rect_stroke(to_screen(annotation), 2.0, egui::Stroke::new(2.0, DIFF_REMOVED_COLOR));
+        }
+        for (old, _) in &diff.changed {
+            ui.painter().rect_stroke(to_screen(old), 2.0, egui::Stroke::new(1.0, DIFF_CHANGED_COLOR.gamma_multiply(0.5)));
+        }
+        for annotation in self.doc.annotations.iter() {
+            let color = if diff.is_added(annotation.id) {
+                DIFF_ADDED_COLOR
+            } else if diff.is_changed(annotation.id) {
+                DIFF_CHANGED_COLOR
+            } else {
+                continue;
+            };
+            ui.painter().rect_stroke(to_screen(annotation), 2.0, egui::Stroke::new(2.0, color));
+        }
+    }
+
+    /// Draw selection handles at the corners of an annotation
+    fn draw_selection_handles(&self, ui: &mut egui::Ui, corners: [Pos2; 4]) {
+        let handle_size = SELECTION_HANDLE_SIZE;
+        let handle_color = egui::Color32::BLUE;
+        
+        for corner in corners {
+            let handle_rect = Rect::from_center_size(corner, Vec2::splat(handle_size));
+            ui.painter().rect_filled(handle_rect, 2.0, handle_color);
+            ui.painter().rect_stroke(handle_rect, 2.0, egui::Stroke::new(1.0, egui::Color32::WHITE));
+        }
+    }
+
+    /// Constrain pan offset to keep at least part of the image visible, unless free pan is on
+    fn constrain_pan_offset(&self, pan_offset: Vec2, available_rect: Rect) -> Vec2 {
+        let pan = self.settings.get_settings().pan;
+        if pan.free_pan {
+            return pan_offset;
+        }
+
+        if let Some(original_size) = self.image_size() {
+            let display_size = original_size * self.doc.zoom_level as f32;
+            
+            // Calculate the bounds for the pan offset
+            let min_visible_size = pan.min_visible_margin; // Keep at least this many pixels of the image visible
+            
+            let max_pan_x = (available_rect.width() - min_visible_size).max(0.0);
+            let min_pan_x = -(display_size.x - min_visible_size).max(0.0);
+            
+            let max_pan_y = (available_rect.height() - min_visible_size).max(0.0);
+            let min_pan_y = -(display_size.y - min_visible_size).max(0.0);
+            
+            Vec2::new(
+                pan_offset.x.clamp(min_pan_x, max_pan_x),
+                pan_offset.y.clamp(min_pan_y, max_pan_y)
+            )
+        } else {
+            pan_offset
+        }
+    }
+
+    /// Draw info overlay showing zoom and pan information
+    fn draw_info_overlay(&self, ui: &mut egui::Ui, available_rect: Rect) {
+        if self.doc.zoom_level != 1.0 || self.doc.pan_offset != Vec2::ZERO {
+            let overlay_pos = available_rect.min + Vec2::new(10.0, 10.0);
+            let info_text = format!(
+                "Zoom: {:.0}%{}",
+                self.doc.zoom_level * 100.0,
+                if self.doc.pan_offset != Vec2::ZERO {
+                    format!(" | Pan: ({:.0}, {:.0})", self.doc.pan_offset.x, self.doc.pan_offset.y)
+                } else {
+                    String::new()
+                }
+            );
+            
+            // Draw background
+            let text_size = ui.painter().layout_no_wrap(
+                info_text.clone(),
+                egui::FontId::proportional(12.0),
+                egui::Color32::WHITE,
+            ).size();
+            
+            let bg_rect = Rect::from_min_size(
+                overlay_pos,
+                text_size + Vec2::splat(8.0),
+            );
+            
+            ui.painter().rect_filled(
+                bg_rect,
+                4.0,
+                egui::Color32::from_black_alpha(180),
+            );
+            
+            // Draw text
+            ui.painter().text(
+                overlay_pos + Vec2::splat(4.0),
+                egui::Align2::LEFT_TOP,
+                info_text,
+                egui::FontId::proportional(12.0),
+                egui::Color32::WHITE,
+            );
+        }
+    }
+}
+
+impl eframe::App for EditorApp {
+    fn update(&mut self, ctx: &Context, _frame: &mut eframe::Frame) {
+        let frame_started = Instant::now();
+
+        // Handle close request, letting background saves finish first
+        self.handle_close_request(ctx);
+        if self.should_close && !self.has_pending_saves() {
+            // Unsaved tabs were saved or discarded on purpose; nothing is left to recover
+            if let Some(store) = &mut self.session {
+                store.clear();
+            }
+            ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+            return;
+        }
+
+        self.poll_power_source(Instant::now());
+        self.apply_resource_profile(ctx);
+        self.apply_theme(ctx);
+        self.register_text_faces(ctx);
+        self.handle_shortcuts(ctx);
+        self.handle_dropped_files(ctx);
+        self.poll_background_saves(ctx);
+        self.poll_uploads();
+        self.poll_actions();
+        self.poll_transfers();
+        self.poll_privacy_check();
+        self.poll_events();
+        self.poll_pending_capture();
+        self.poll_interval_capture(Instant::now());
+        self.poll_region_watch(Instant::now());
+        self.poll_monitor_cycle(Instant::now());
+        self.enforce_memory_budget();
+        let session_pending = self.autosave_session(Instant::now());
+        self.reveal_window(ctx);
+
+        // Draw UI components
+        self.draw_menu_bar(ctx);
+        self.draw_tab_bar(ctx);
+        self.draw_tool_panel(ctx);
+        self.draw_properties_panel(ctx);
+        self.draw_canvas(ctx);
+        self.commit_undo_step(ctx);
+        self.draw_shortcut_settings(ctx);
+        self.draw_export_options(ctx);
+        self.draw_diagnostics_overlay(ctx);
+        self.draw_open_dialog(ctx);
+        self.draw_monitor_routing(ctx);
+        self.draw_compare_projects(ctx);
+        self.draw_image_comparison(ctx);
+        self.draw_file_naming(ctx);
+        self.draw_capture_presets(ctx);
+        self.draw_share_targets(ctx);
+        self.draw_save_as(ctx);
+        self.draw_save_destinations(ctx);
+        self.draw_transfers(ctx);
+        self.draw_encrypted_export(ctx);
+        self.draw_encrypted_open(ctx);
+        self.draw_privacy_warning(ctx);
+        self.draw_privacy_settings(ctx);
+        self.draw_interval_settings(ctx);
+        self.draw_watch_settings(ctx);
+        self.draw_comment_editor(ctx);
+        self.draw_template_name_dialog(ctx);
+        self.draw_about(ctx);
+        self.draw_error_log(ctx);
+        self.draw_history(ctx);
+        self.draw_collage_window(ctx);
+        self.draw_adjustments_window(ctx);
+        self.draw_region_selection_controls(ctx);
+        self.draw_monitor_cycle_label(ctx);
+        self.draw_capture_toast(ctx);
+        self.draw_toasts(ctx);
+        self.draw_close_tab_prompt(ctx);
+        self.draw_exit_prompt(ctx);
+        self.draw_recovery_prompt(ctx);
+        self.draw_error_dialog(ctx);
+
+        // Persist settings changes once the pointer is released
+        if self.settings_dirty && !ctx.input(|i| i.pointer.any_down()) {
+            self.save_settings();
+        }
+
+        // Wake up to write the recovery session even if the user stops interacting
+        if session_pending {
+            ctx.request_repaint_after(session::AUTOSAVE_INTERVAL.saturating_sub(self.session_saved_at.elapsed()));
+        }
+
+        if self.resource_profile().continuous_repaint {
+            // Request repaint for smooth interaction
+            ctx.request_repaint();
+        } else if self.is_capturing() || self.is_uploading_texture() {
+            // Keep the spinner moving and finish streaming texture tiles
+            ctx.request_repaint();
+        } else if self.has_pending_saves() {
+            // Keep polling until background saves finish
+            ctx.request_repaint_after(BACKGROUND_SAVE_POLL_INTERVAL);
+        } else if self.is_adjusting() {
+            // Wake up for the debounced preview and worker results even without input
+            ctx.request_repaint_after(crate::adjustments::PREVIEW_DEBOUNCE);
+        } else if self.settings.get_settings().low_resource_mode == LowResourceMode::Auto {
+            // Keep polling the power source while idle
+            ctx.request_repaint_after(POWER_POLL_INTERVAL);
+        }
+
+        // Wake up for the next interval frame or region check even without input
+        if self.interval_capture.is_some() || self.watch_capture.is_some() {
+            ctx.request_repaint_after(BACKGROUND_SAVE_POLL_INTERVAL);
+        }
+        if let Some(run) = &self.interval_run {
+            ctx.request_repaint_after(run.time_until_due(Instant::now()));
+        }
+        if let Some(watch) = &self.region_watch {
+            ctx.request_repaint_after(watch.time_until_due(Instant::now()));
+        }
+
+        self.update_window_title(ctx);
+        self.diagnostics.record_frame(frame_started.elapsed());
+    }
+
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shortcuts::NudgeDirection;
+
+    #[test]
+    fn test_editor_app_creation() {
+        let app = EditorApp::new();
+        assert!(app.doc.source_image.is_none());
+        assert!(app.doc.texture.is_none());
+        assert!(app.doc.annotations.is_empty());
+        assert_eq!(app.current_tool, Tool::Select);
+        assert_eq!(app.doc.zoom_level, 1.0);
+        assert_eq!(app.doc.pan_offset, Vec2::ZERO);
+        assert!(!app.should_close);
+        assert!(!app.is_panning);
+        assert!(app.last_mouse_pos.is_none());
+    }
+
+    #[test]
+    fn test_editor_app_default() {
+        let app = EditorApp::default();
+        assert!(app.doc.source_image.is_none());
+        assert_eq!(app.current_tool, Tool::Select);
+        assert_eq!(app.doc.zoom_level, 1.0);
+        assert_eq!(app.doc.pan_offset, Vec2::ZERO);
+    }
+
+    #[test]
+    fn test_tool_management() {
+        let mut app = EditorApp::new();
+        
+        // Test initial tool
+        assert_eq!(app.current_tool(), &Tool::Select);
+        
+        // Test setting tools
+        app.set_tool(Tool::Rectangle);
+        assert_eq!(app.current_tool(), &Tool::Rectangle);
+        
+        app.set_tool(Tool::Text);
+        assert_eq!(app.current_tool(), &Tool::Text);
+    }
+
+    #[test]
+    fn test_close_functionality() {
+        let mut app = EditorApp::new();
+        
+        // Initially should not close
+        assert!(!app.should_close());
+        
+        // Request close
+        app.request_close();
+        assert!(app.should_close());
+    }
+
+    #[test]
+    fn test_load_image() {
+        let mut app = EditorApp::new();
+        
+        // Create a test image
+        let test_image = DynamicImage::new_rgb8(100, 100);
+        
+        // Load the image
+        let result = app.load_image(test_image);
+        assert!(result.is_ok());
+        assert!(app.doc.source_image.is_some());
+        
+        // Check that view state is reset
+        assert_eq!(app.doc.zoom_level, 1.0);
+        assert_eq!(app.doc.pan_offset, Vec2::ZERO);
+    }
+
+    #[test]
+    fn test_load_test_image() {
+        let mut app = EditorApp::new();
+        
+        // Load test image
+        let result = app.load_test_image();
+        assert!(result.is_ok());
+        assert!(app.doc.source_image.is_some());
+        
+        // Verify the test image has expected dimensions
+        if let Some(ref image) = app.doc.source_image {
+            assert_eq!(image.width(), 400);
+            assert_eq!(image.height(), 300);
+        }
+    }
+
+    #[test]
+    fn test_zoom_and_pan_state() {
+        let mut app = EditorApp::new();
+        
+        // Test initial state
+        assert_eq!(app.doc.zoom_level, 1.0);
+        assert_eq!(app.doc.pan_offset, Vec2::ZERO);
+        
+        // Modify zoom and pan (simulating user interaction)
+        app.doc.zoom_level = 2.0;
+        app.doc.pan_offset = Vec2::new(10.0, 20.0);
+        
+        // Load new image should reset view state
+        let test_image = DynamicImage::new_rgb8(100, 100);
+        let result = app.load_image(test_image);
+        assert!(result.is_ok());
+        assert_eq!(app.doc.zoom_level, 1.0);
+        assert_eq!(app.doc.pan_offset, Vec2::ZERO);
+    }
+
+    #[test]
+    fn test_flattened_image_cached_until_change() {
+        let mut app = EditorApp::new();
+        assert!(app.flattened_image().is_none());
+
+        app.load_image(DynamicImage::new_rgb8(50, 50)).unwrap();
+        let first = app.flattened_image().unwrap();
+        let second = app.flattened_image().unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+
+        // Adding an annotation invalidates the cached composite
+        let annotation = AnnotationItem::new_rectangle(Pos2::new(5.0, 5.0), Vec2::new(10.0, 10.0));
+        let id = annotation.id;
+        app.add_annotation(annotation);
+        let third = app.flattened_image().unwrap();
+        assert!(!Arc::ptr_eq(&second, &third));
+
+        assert!(app.remove_annotation(id).is_some());
+        assert!(app.annotations().is_empty());
+        let fourth = app.flattened_image().unwrap();
+        assert!(!Arc::ptr_eq(&third, &fourth));
+    }
+
+    #[test]
+    fn test_adjustments_apply_at_full_resolution() {
+        let mut app = EditorApp::new();
+        let image = DynamicImage::ImageRgb8(image::RgbImage::from_pixel(20, 10, image::Rgb([100, 100, 100])));
+        app.load_image(image).unwrap();
+        let before = app.flattened_image().unwrap();
+
+        app.set_adjustments(Adjustments::new(vec![Adjustment::Brightness(20.0)]));
+        app.doc.wait_for_adjustments();
+        let adjusted = app.doc.adjusted_image.as_ref().unwrap();
+        assert_eq!((adjusted.width(), adjusted.height()), (20, 10));
+
+        let after = app.flattened_image().unwrap();
+        assert!(!Arc::ptr_eq(&before, &after));
+        assert!(after.get_pixel(0, 0).0[0] > 100);
+
+        // Resetting to identity drops the adjusted copy
+        app.set_adjustments(Adjustments::default());
+        assert!(app.doc.adjusted_image.is_none());
+    }
+
+    #[test]
+    fn test_load_image_resets_adjustments() {
+        let mut app = EditorApp::new();
+        app.load_image(DynamicImage::new_rgb8(10, 10)).unwrap();
+        app.set_adjustments(Adjustments::new(vec![Adjustment::Brightness(10.0), Adjustment::Contrast(10.0)]));
+
+        app.load_image(DynamicImage::new_rgb8(10, 10)).unwrap();
+        assert!(app.adjustments().is_identity());
+        assert!(app.doc.adjusted_image.is_none());
+    }
+
+    #[test]
+    fn test_auto_trim_moves_annotations_with_the_image() {
+        let mut app = EditorApp::new();
+        let mut image = image::RgbaImage::from_pixel(50, 40, image::Rgba([0, 120, 215, 255]));
+        for x in 10..40 {
+            for y in 5..35 {
+                image.put_pixel(x, y, image::Rgba([255, 255, 255, 255]));
+            }
+        }
+        app.load_image(DynamicImage::ImageRgba8(image)).unwrap();
+        app.add_annotation(AnnotationItem::new_rectangle(Pos2::new(12.0, 8.0), Vec2::new(5.0, 5.0)));
+
+        assert!(app.auto_trim().unwrap());
+        assert_eq!(app.image_size(), Some(Vec2::new(30.0, 30.0)));
+        assert_eq!(app.annotations()[0].position, Pos2::new(2.0, 3.0));
+        assert!(app.is_dirty());
+        // Trimming can't be undone, so the history starts over
+        assert!(!app.undo());
+
+        assert!(!app.auto_trim().unwrap());
+    }
+
+    #[test]
+    fn test_crop_to_selection_can_be_undone() {
+        let ctx = Context::default();
+        let mut app = EditorApp::new();
+        app.load_image(DynamicImage::ImageRgba8(RgbaImage::new(100, 80))).unwrap();
+        let rectangle = AnnotationItem::new_rectangle(Pos2::new(30.0, 20.0), Vec2::new(10.0, 10.0));
+        let id = rectangle.id;
+        app.add_annotation(rectangle);
+        app.add_annotation(AnnotationItem::new_rectangle(Pos2::new(80.0, 60.0), Vec2::new(10.0, 10.0)));
+        app.commit_undo_step(&ctx);
+
+        // Dragging out a region selects the annotations touching it
+        app.doc.selection_region = Some(Rect::from_min_max(Pos2::new(20.0, 10.0), Pos2::new(70.4, 59.6)));
+        app.finish_region_selection();
+        assert_eq!(app.selected_annotation_ids(), vec![id]);
+
+        app.crop_to_selection().unwrap();
+        assert_eq!(app.image_size(), Some(Vec2::new(50.0, 50.0)));
+        assert_eq!(app.annotations()[0].position, Pos2::new(10.0, 10.0));
+        assert_eq!(app.selection_region(), None);
+
+        // A second crop is a step of its own
+        app.doc.selection_region = Some(Rect::from_min_max(Pos2::new(5.0, 5.0), Pos2::new(25.0, 25.0)));
+        app.crop_to_selection().unwrap();
+        assert_eq!(app.image_size(), Some(Vec2::new(20.0, 20.0)));
+
+        assert!(app.undo());
+        assert_eq!(app.image_size(), Some(Vec2::new(50.0, 50.0)));
+        assert!(app.undo());
+        assert_eq!(app.image_size(), Some(Vec2::new(100.0, 80.0)));
+        assert_eq!(app.annotations()[0].position, Pos2::new(30.0, 20.0));
+        assert!(app.redo());
+        assert_eq!(app.image_size(), Some(Vec2::new(50.0, 50.0)));
+        assert_eq!(app.annotations()[0].position, Pos2::new(10.0, 10.0));
+
+        // Regions outside the image are rejected
+        app.doc.selection_region = Some(Rect::from_min_max(Pos2::new(60.0, 60.0), Pos2::new(90.0, 90.0)));
+        assert!(app.crop_to_selection().is_err());
+        assert_eq!(app.image_size(), Some(Vec2::new(50.0, 50.0)));
+    }
+
+    #[test]
+    fn test_canvas_target_under_pointer() {
+        let mut app = EditorApp::new();
+        let rectangle = AnnotationItem::new_rectangle(Pos2::new(10.0, 10.0), Vec2::new(40.0, 20.0));
+        let id = rectangle.id;
+        app.add_annotation(rectangle);
+        assert_eq!(app.canvas_target(Pos2::new(30.0, 20.0)), CanvasTarget::Annotation);
+        assert_eq!(app.canvas_target(Pos2::new(80.0, 80.0)), CanvasTarget::Canvas);
+
+        // Handles of the selected annotation take precedence over the annotation itself
+        app.select_annotation(Some(id));
+        assert_eq!(app.canvas_target(Pos2::new(50.0, 30.0)), CanvasTarget::ResizeHandle(Vec2::new(20.0, 10.0)));
+        let handle = app.doc.annotations[0].rotate_handle(ROTATE_HANDLE_DISTANCE);
+        assert_eq!(app.canvas_target(handle), CanvasTarget::RotateHandle);
+
+        app.current_tool = Tool::Rectangle;
+        assert_eq!(app.canvas_target(Pos2::new(30.0, 20.0)), CanvasTarget::Canvas);
+    }
+
+    #[test]
+    fn test_perspective_tool_straightens_dragged_region() {
+        let mut app = EditorApp::new();
+        app.load_image(DynamicImage::new_rgba8(100, 80)).unwrap();
+        assert_eq!(app.perspective_corners().unwrap()[2], Pos2::new(100.0, 80.0));
+
+        // Corners are kept on the image
+        app.set_perspective_corner(0, Pos2::new(-5.0, 10.0));
+        app.set_perspective_corner(1, Pos2::new(60.0, 10.0));
+        app.set_perspective_corner(2, Pos2::new(70.0, 50.0));
+        app.set_perspective_corner(3, Pos2::new(10.0, 50.0));
+        assert_eq!(app.perspective_corners().unwrap()[0], Pos2::new(0.0, 10.0));
+        app.add_annotation(AnnotationItem::new_rectangle(Pos2::new(10.0, 50.0), Vec2::new(5.0, 5.0)));
+
+        app.correct_perspective().unwrap();
+        assert_eq!(app.image_size(), Some(Vec2::new(60.0, 41.0)));
+        assert!(app.annotations()[0].position.distance(Pos2::new(0.0, 41.0)) < 0.01);
+        // The corners start over on the straightened image
+        assert_eq!(app.perspective_corners().unwrap()[2], Pos2::new(60.0, 41.0));
+        assert!(!app.undo());
+    }
+
+    #[test]
+    fn test_adjustments_are_undoable_and_saved_with_the_project() {
+        let dir = std::env::temp_dir().join(format!("lsa-editor-adjustments-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let ctx = Context::default();
+        let mut app = EditorApp::new();
+        let image = DynamicImage::ImageRgb8(image::RgbImage::from_pixel(8, 8, image::Rgb([200, 100, 50])));
+        app.load_image(image).unwrap();
+        app.save_to(&dir.join("photo.png")).unwrap();
+
+        let stack = Adjustments::new(vec![Adjustment::Grayscale, Adjustment::Invert]);
+        app.set_adjustments(Adjustments::new(vec![Adjustment::Grayscale]));
+        app.commit_undo_step(&ctx);
+        app.set_adjustments(stack.clone());
+        let [r, g, b, _] = app.flattened_image().unwrap().get_pixel(0, 0).0;
+        assert!(r == g && g == b && r < 200);
+        assert!(app.is_dirty());
+        // The source image is left as it was
+        assert_eq!(app.doc.source_image.as_ref().unwrap().to_rgb8().get_pixel(0, 0).0, [200, 100, 50]);
+
+        assert!(app.undo());
+        assert_eq!(app.adjustments(), &Adjustments::new(vec![Adjustment::Grayscale]));
+        assert!(app.undo());
+        assert!(app.adjustments().is_identity());
+        assert_eq!(app.flattened_image().unwrap().get_pixel(0, 0).0, [200, 100, 50, 255]);
+        assert!(app.redo() && app.redo());
+        assert_eq!(app.adjustments(), &stack);
+
+        let project_path = app.save_project().unwrap();
+        let mut reopened = EditorApp::new();
+        reopened.open_project(&project_path).unwrap();
+        assert_eq!(reopened.adjustments(), &stack);
+        assert_eq!(reopened.flattened_image().unwrap().get_pixel(0, 0).0, [r, g, b, 255]);
+        assert!(!reopened.is_dirty());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_create_annotation_uses_tool_default_style() {
+        let mut app = EditorApp::new();
+        let style = AnnotationStyle {
+            stroke_color: egui::Color32::BLUE,
+            stroke_width: 6.0,
+            ..AnnotationStyle::default()
+        };
+        app.settings.settings_mut().styles.set_tool_default(Tool::Rectangle, style);
+
+        let annotation = app
+            .create_annotation(&Tool::Rectangle, Pos2::new(1.0, 2.0), Vec2::new(30.0, 20.0))
+            .unwrap();
+        match annotation.annotation_type {
+            AnnotationType::Rectangle { stroke_color, stroke_width, .. } => {
+                assert_eq!(stroke_color, egui::Color32::BLUE);
+                assert_eq!(stroke_width, 6.0);
+            }
+            _ => panic!("Expected Rectangle annotation type"),
+        }
+        assert!(app.create_annotation(&Tool::Select, Pos2::ZERO, Vec2::ZERO).is_none());
+    }
+
+    #[test]
+    fn test_create_annotation_uses_active_preset() {
+        let mut app = EditorApp::new();
+        let preset = AnnotationStyle {
+            font_size: 32.0,
+            ..AnnotationStyle::default()
+        };
+        let styles = &mut app.settings.settings_mut().styles;
+        styles.save_preset("Large", preset).unwrap();
+        styles.set_active_preset(Some("Large")).unwrap();
+
+        let annotation = app.create_annotation(&Tool::Text, Pos2::ZERO, Vec2::ZERO).unwrap();
+        match annotation.annotation_type {
+            AnnotationType::Text { font_size, .. } => assert_eq!(font_size, 32.0),
+            _ => panic!("Expected Text annotation type"),
+        }
+    }
+
+    #[test]
+    fn test_keep_measurement() {
+        let mut app = EditorApp::new();
+        app.doc.measurement = Some([Pos2::new(10.0, 10.0), Pos2::new(11.0, 10.0)]);
+        assert!(!app.keep_measurement());
+
+        app.doc.source_screen = Some(ScreenInfo {
+            index: 0,
+            bounds: Rect::from_min_size(Pos2::ZERO, Vec2::new(1920.0, 1080.0)),
+            dpi_scale_x: 1.5,
+            dpi_scale_y: 1.5,
+            is_primary: true,
+        });
+        app.doc.measurement = Some([Pos2::new(10.0, 10.0), Pos2::new(154.0, 10.0)]);
+        assert!(app.keep_measurement());
+        assert_eq!(app.doc.measurement, None);
+        let dimension = &app.annotations()[0];
+        assert_eq!(render::dimension_label(dimension), "144 px (25.4 mm, 1.00 in), 0.0°");
+    }
+
+    #[test]
+    fn test_sticker_is_centered_on_click() {
+        let mut app = EditorApp::new();
+        app.sticker_symbol = "✅".to_string();
+        let sticker = app.create_annotation(&Tool::Sticker, Pos2::new(100.0, 100.0), Vec2::ZERO).unwrap();
+        assert!((sticker.bounds().center() - Pos2::new(100.0, 100.0)).length() < 0.001);
+        match sticker.annotation_type {
+            AnnotationType::Text { ref content, font_size, .. } => assert_eq!((content.as_str(), font_size), ("✅", 48.0)),
+            _ => panic!("Expected Text annotation type"),
+        }
+    }
+
+    #[test]
+    fn test_undo_and_redo_annotation_edits() {
+        let ctx = Context::default();
+        let mut app = EditorApp::new();
+        let rectangle = AnnotationItem::new_rectangle(Pos2::new(0.0, 0.0), Vec2::new(10.0, 10.0));
+        let id = rectangle.id;
+        app.add_annotation(rectangle);
+        app.commit_undo_step(&ctx);
+        app.select_annotation(Some(id));
+        app.doc.annotations[0].position = Pos2::new(30.0, 0.0);
+        app.mark_content_changed();
+
+        // The uncommitted move is undone first, keeping the selection
+        assert!(app.undo());
+        assert_eq!(app.annotations()[0].position, Pos2::new(0.0, 0.0));
+        assert_eq!(app.selected_annotation_ids(), vec![id]);
+        assert!(app.undo());
+        assert!(app.annotations().is_empty());
+        assert!(!app.undo());
+
+        assert!(app.redo());
+        assert!(app.redo());
+        assert_eq!(app.annotations()[0].position, Pos2::new(30.0, 0.0));
+        assert!(!app.redo());
+    }
+
+    #[test]
+    fn test_eraser_deletes_clicked_and_touched_annotations() {
+        let mut app = EditorApp::new();
+        let positions = [Pos2::new(0.0, 0.0), Pos2::new(20.0, 0.0), Pos2::new(60.0, 0.0)];
+        let ids: Vec<Uuid> = positions
+            .into_iter()
+            .map(|position| {
+                let annotation = AnnotationItem::new_rectangle(position, Vec2::new(10.0, 10.0));
+                let id = annotation.id;
+                app.add_annotation(annotation);
+                id
+            })
+            .collect();
+
+        assert_eq!(app.erase_in(Rect::from_min_max(Pos2::new(5.0, 5.0), Pos2::new(25.0, 30.0))), 2);
+        assert_eq!(app.annotations().len(), 1);
+        assert_eq!(app.erase_in(Rect::from_min_max(Pos2::new(30.0, 0.0), Pos2::new(40.0, 10.0))), 0);
+        assert_eq!(app.erase(&[ids[2]]), 1);
+        assert!(app.annotations().is_empty());
+    }
+
+    #[test]
+    fn test_selection_and_delete() {
+        let mut app = EditorApp::new();
+        let first = AnnotationItem::new_rectangle(Pos2::new(0.0, 0.0), Vec2::new(10.0, 10.0));
+        let second = AnnotationItem::new_rectangle(Pos2::new(5.0, 5.0), Vec2::new(10.0, 10.0));
+        let (first_id, second_id) = (first.id, second.id);
+        app.add_annotation(first);
+        app.add_annotation(second);
+
+        // Topmost annotation wins the hit test
+        assert_eq!(app.annotation_at(Pos2::new(7.0, 7.0)), Some(second_id));
+        assert_eq!(app.annotation_at(Pos2::new(2.0, 2.0)), Some(first_id));
+        assert_eq!(app.annotation_at(Pos2::new(50.0, 50.0)), None);
+
+        app.select_annotation(Some(first_id));
+        assert_eq!(app.selected_annotation_ids(), vec![first_id]);
+
+        app.delete_selected();
+        assert_eq!(app.annotations().len(), 1);
+        assert_eq!(app.annotations()[0].id, second_id);
+
+        app.select_annotation(None);
+        assert!(app.selected_annotation_ids().is_empty());
+    }
+
+    #[test]
+    fn test_copy_and_paste_style() {
+        let mut app = EditorApp::new();
+        let mut source = AnnotationItem::new_rectangle(Pos2::ZERO, Vec2::new(10.0, 10.0));
+        AnnotationStyle {
+            stroke_color: egui::Color32::GREEN,
+            stroke_width: 8.0,
+            ..AnnotationStyle::default()
+        }
+        .apply_to(&mut source);
+        let target = AnnotationItem::new_rectangle(Pos2::new(20.0, 20.0), Vec2::new(5.0, 5.0));
+        let (source_id, target_id) = (source.id, target.id);
+        app.add_annotation(source);
+        app.add_annotation(target);
+
+        // Nothing selected: nothing to copy
+        assert!(!app.copy_style());
+
+        app.select_annotation(Some(source_id));
+        assert!(app.copy_style());
+        app.select_annotation(Some(target_id));
+        assert!(app.paste_style());
+
+        match app.annotations()[1].annotation_type {
+            AnnotationType::Rectangle { stroke_color, stroke_width, size, .. } => {
+                assert_eq!(stroke_color, egui::Color32::GREEN);
+                assert_eq!(stroke_width, 8.0);
+                assert_eq!(size, Vec2::new(5.0, 5.0));
+            }
+            _ => panic!("Expected Rectangle annotation type"),
+        }
+    }
+
+    #[test]
+    fn test_perform_action_switches_tools_and_zoom() {
+        let mut app = EditorApp::new();
+        app.perform_action(ShortcutAction::SwitchTool(2));
+        assert_eq!(app.current_tool(), &Tool::Rectangle);
+        app.perform_action(ShortcutAction::SwitchTool(3));
+        assert_eq!(app.current_tool(), &Tool::Text);
+
+        // Slots without a tool are ignored
+        app.perform_action(ShortcutAction::SwitchTool(9));
+        assert_eq!(app.current_tool(), &Tool::Text);
+
+        app.perform_action(ShortcutAction::ZoomIn);
+        assert!(app.zoom_level() > 1.0);
+        app.perform_action(ShortcutAction::ZoomReset);
+        assert_eq!(app.zoom_level(), 1.0);
+        app.perform_action(ShortcutAction::ZoomOut);
+        assert!(app.zoom_level() < 1.0);
+    }
+
+    #[test]
+    fn test_zoom_to_fit_and_selection() {
+        let mut app = EditorApp::new();
+        app.load_test_image().unwrap();
+        app.canvas_rect = Some(Rect::from_min_size(Pos2::new(200.0, 30.0), Vec2::new(240.0, 340.0)));
+
+        // The 400x300 test image is limited by the canvas width, less the margins
+        app.perform_action(ShortcutAction::ZoomFit);
+        assert_eq!(app.zoom_level(), 0.5);
+        assert_eq!(app.doc.pan_offset, Vec2::ZERO);
+
+        // Nothing selected leaves the view alone
+        assert!(!app.zoom_to_selection());
+        let annotation = AnnotationItem::new_rectangle(Pos2::new(300.0, 200.0), Vec2::new(50.0, 25.0));
+        let id = annotation.id;
+        app.add_annotation(annotation);
+        app.select_annotation(Some(id));
+        assert!(app.zoom_to_selection());
+        assert_eq!(app.zoom_level(), 4.0);
+        // The selection's center (325, 212.5) is moved to the canvas center
+        assert_eq!(app.doc.pan_offset, Vec2::new(-500.0, -250.0));
+
+        // Small images aren't enlarged to fit
+        app.canvas_rect = Some(Rect::from_min_size(Pos2::ZERO, Vec2::new(2000.0, 2000.0)));
+        app.zoom_to_fit();
+        assert_eq!(app.zoom_level(), 1.0);
+    }
+
+    #[test]
+    fn test_perform_delete_action() {
+        let mut app = EditorApp::new();
+        let annotation = AnnotationItem::new_rectangle(Pos2::ZERO, Vec2::new(10.0, 10.0));
+        let id = annotation.id;
+        app.add_annotation(annotation);
+        app.select_annotation(Some(id));
+
+        app.perform_action(ShortcutAction::Delete);
+        assert!(app.annotations().is_empty());
+    }
+
+    #[test]
+    fn test_rebind_shortcut_updates_settings() {
+        let mut app = EditorApp::new();
+        let chord = KeyChord::ctrl(egui::Key::K);
+        app.rebind_shortcut(ShortcutAction::Delete, chord).unwrap();
+        assert_eq!(app.settings().get_settings().shortcuts.chord_for(ShortcutAction::Delete), Some(chord));
+
+        // Conflicting chord is rejected and leaves the binding unchanged
+        assert!(app.rebind_shortcut(ShortcutAction::Delete, KeyChord::ctrl(egui::Key::S)).is_err());
+        assert_eq!(app.settings().get_settings().shortcuts.chord_for(ShortcutAction::Delete), Some(chord));
+    }
+
+    #[test]
+    fn test_capture_service_is_lazy() {
+        let mut app = EditorApp::new();
+        assert!(!app.capture.is_ready());
+
+        // Headless environments have no screens, so only check the error kind
+        match app.capture_service() {
+            Ok(_) => assert!(app.capture.is_ready()),
+            // A failure is retried on the next use
+            Err(AppError::ScreenCapture(_)) => assert!(!app.capture.is_ready()),
+            Err(e) => panic!("Unexpected error: {}", e),
+        }
+    }
+
+    #[test]
+    fn test_low_resource_mode_profile() {
+        let mut app = EditorApp::new();
+        app.set_low_resource_mode(LowResourceMode::On);
+        assert!(app.resource_profile().is_low());
+        assert!(!app.resource_profile().continuous_repaint);
+
+        app.set_low_resource_mode(LowResourceMode::Off);
+        assert_eq!(app.resource_profile(), ResourceProfile::FULL);
+
+        // Auto follows the detected power source
+        app.set_low_resource_mode(LowResourceMode::Auto);
+        app.power_source = PowerSource::Battery;
+        assert!(app.resource_profile().is_low());
+        app.power_source = PowerSource::Ac;
+        assert!(!app.resource_profile().is_low());
+    }
+
+    #[test]
+    fn test_export_image_applies_resize() {
+        let mut app = EditorApp::new();
+        assert!(app.export_image().is_none());
+
+        app.load_test_image().unwrap();
+        app.settings.settings_mut().export.resize = ResizeMode::Percentage(50.0);
+        let exported = app.export_image().unwrap();
+        assert_eq!(exported.dimensions(), (200, 150));
+    }
+
+    #[test]
+    fn test_save_to_uses_extension_format() {
+        let dir = std::env::temp_dir().join(format!("lsa-editor-save-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("capture.bmp");
+
+        let mut app = EditorApp::new();
+        assert!(app.save_to(&path).is_err());
+
+        app.load_test_image().unwrap();
+        app.save_to(&path).unwrap();
+        assert_eq!(app.file_path(), Some(path.as_path()));
+        assert_eq!(image::ImageFormat::from_path(&path).unwrap(), image::ImageFormat::Bmp);
+        assert!(image::open(&path).is_ok());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_export_image_applies_decoration() {
+        let mut app = EditorApp::new();
+        app.load_test_image().unwrap();
+
+        let decoration = crate::render::Decoration {
+            padding: 10,
+            background: DecorationBackground::Solid(egui::Color32::WHITE),
+            corner_radius: 0.0,
+            shadow: None,
+        };
+        let settings = &mut app.settings.settings_mut().export.decoration;
+        settings.save_preset("Test", decoration).unwrap();
+        settings.set_active_preset(Some("Test")).unwrap();
+
+        let exported = app.export_image().unwrap();
+        assert_eq!(exported.dimensions(), (420, 320));
+        assert_eq!(exported.get_pixel(0, 0).0, [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn test_document_memory_tracks_loaded_image() {
+        let mut app = EditorApp::new();
+        assert_eq!(app.document_memory(), 0);
+
+        app.load_test_image().unwrap();
+        let loaded = app.document_memory();
+        assert_eq!(loaded, 400 * 300 * 3);
+
+        app.flattened_image();
+        assert!(app.document_memory() > loaded);
+    }
+
+    #[test]
+    fn test_images_over_the_memory_budget_show_a_proxy() {
+        let mut app = EditorApp::new();
+        app.settings.settings_mut().memory = MemoryOptions { budget_mb: 0, proxy_max_side: 100 };
+        let mut pixels = RgbaImage::from_pixel(400, 200, image::Rgba([0, 0, 255, 255]));
+        pixels.put_pixel(399, 199, image::Rgba([255, 0, 0, 255]));
+        app.load_image(DynamicImage::ImageRgba8(pixels.clone())).unwrap();
+        let full_size = app.document_memory();
+
+        app.enforce_memory_budget();
+        assert!(app.doc.full_resolution.is_some());
+        assert_eq!(app.doc.source_image.as_ref().unwrap().dimensions(), (100, 50));
+        assert!(app.document_memory() < full_size);
+        // Annotations and exports keep using full-resolution pixels
+        assert_eq!(app.image_size(), Some(Vec2::new(400.0, 200.0)));
+        assert_eq!(app.flattened_image().unwrap().as_ref(), &pixels);
+
+        // Editing the pixels loads the full image back for good
+        app.doc.restore_full_resolution().unwrap();
+        app.enforce_memory_budget();
+        assert!(app.doc.full_resolution.is_none());
+        assert_eq!(app.doc.source_image.as_ref().unwrap().to_rgba8(), pixels);
+    }
+
+    #[test]
+    fn test_load_untrusted_bytes() {
+        let mut app = EditorApp::new();
+        assert!(app.load_untrusted_bytes(b"not an image".to_vec()).is_err());
+        assert!(app.image_size().is_none());
+
+        let mut bytes = Vec::new();
+        DynamicImage::new_rgba8(12, 7)
+            .write_to(&mut std::io::Cursor::new(&mut bytes), image::ImageOutputFormat::Png)
+            .unwrap();
+        app.load_untrusted_bytes(bytes).unwrap();
+        assert_eq!(app.image_size(), Some(Vec2::new(12.0, 7.0)));
+    }
+
+    #[test]
+    fn test_save_failure_shows_error_dialog() {
+        let mut app = EditorApp::new();
+        app.load_test_image().unwrap();
+        app.doc.file_path = Some(std::env::temp_dir().join(format!("lsa-missing-{}", Uuid::new_v4())).join("shot.png"));
+
+        app.perform_action(ShortcutAction::Save);
+        let (title, _) = app.error_dialog().expect("save failure should show a dialog");
+        assert_eq!(title, "Save Failed");
+    }
+
+    #[test]
+    fn test_save_and_open_japanese_path() {
+        let dir = std::env::temp_dir().join(format!("lsa-editor-unicode-{}", Uuid::new_v4())).join("スクリーンショット");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("画面キャプチャ.png");
+
+        let mut app = EditorApp::new();
+        app.load_test_image().unwrap();
+        app.save_to(&path).unwrap();
+
+        let mut reopened = EditorApp::new();
+        reopened.open_file(&path).unwrap();
+        assert_eq!(reopened.file_path(), Some(path.as_path()));
+        let recent = &reopened.settings.get_settings().recent_files;
+        assert!(recent[0].ends_with("スクリーンショット/画面キャプチャ.png"));
+
+        std::fs::remove_dir_all(dir.parent().unwrap()).ok();
+    }
+
+    #[test]
+    fn test_cloud_save_runs_in_background() {
+        let root = std::env::temp_dir().join(format!("lsa-onedrive-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&root).unwrap();
+        let path = root.join("capture.png");
+
+        let mut app = EditorApp::new();
+        app.cloud_sync_roots = vec![root.clone()];
+        app.load_test_image().unwrap();
+        app.settings.settings_mut().copy_remote_save_location = true;
+        app.save_to(&path).unwrap();
+        assert!(app.has_pending_saves());
+        assert_eq!(app.file_path(), None);
+
+        let ctx = Context::default();
+        let started = Instant::now();
+        while app.has_pending_saves() && started.elapsed() < Duration::from_secs(10) {
+            std::thread::sleep(Duration::from_millis(10));
+            app.poll_background_saves(&ctx);
+        }
+        assert_eq!(app.file_path(), Some(path.as_path()));
+        assert!(image::open(&path).is_ok());
+        assert_eq!(ctx.output(|output| output.copied_text.clone()), path.display().to_string());
+
+        std::fs::remove_dir_all(&root).ok();
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn test_paste_failure_shows_error_dialog() {
+        let mut app = EditorApp::new();
+        app.perform_action(ShortcutAction::Paste);
+        let (title, _) = app.error_dialog().expect("paste failure should show a dialog");
+        assert_eq!(title, "Paste Failed");
+        assert!(app.doc.source_image.is_none());
+    }
+
+    #[test]
+    fn test_open_gif_uses_first_frame() {
+        use image::codecs::gif::GifEncoder;
+        use image::{Delay, Frame, Rgba};
+
+        let dir = std::env::temp_dir().join(format!("lsa-editor-open-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("animation.gif");
+        {
+            let file = std::fs::File::create(&path).unwrap();
+            let mut encoder = GifEncoder::new(file);
+            for color in [[255, 0, 0, 255], [0, 0, 255, 255]] {
+                let frame = Frame::from_parts(RgbaImage::from_pixel(4, 4, Rgba(color)), 0, 0, Delay::from_numer_denom_ms(100, 1));
+                encoder.encode_frame(frame).unwrap();
+            }
+        }
+
+        let mut app = EditorApp::new();
+        app.open_file(&path).unwrap();
+        assert_eq!(app.doc.source_image.as_ref().unwrap().to_rgba8().get_pixel(0, 0).0, [255, 0, 0, 255]);
+        // Save overwrites the opened file
+        assert_eq!(app.file_path(), Some(path.as_path()));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_open_failure_shows_error_dialog() {
+        let mut app = EditorApp::new();
+        app.open_and_report(Path::new("/nonexistent/画像.png"));
+        assert_eq!(app.error_dialog().map(|(title, _)| title.as_str()), Some("Open Failed"));
+        assert!(app.settings.get_settings().recent_files.is_empty());
+    }
+
+    #[test]
+    fn test_session_recovery() {
+        let dir = std::env::temp_dir().join(format!("lsa-editor-session-{}", Uuid::new_v4()));
+        let mut app = EditorApp::new();
+        app.enable_session_recovery(dir.clone());
+        assert!(!app.has_recovered_session());
+
+        app.load_test_image().unwrap();
+        app.add_annotation(AnnotationItem::new_text(Pos2::new(3.0, 4.0), "Keep me".to_string()));
+        let started = Instant::now();
+        // Nothing is written until the interval has passed
+        assert!(app.autosave_session(started));
+        assert!(!dir.exists());
+        assert!(!app.autosave_session(started + session::AUTOSAVE_INTERVAL));
+        app.session.as_mut().unwrap().finish();
+
+        // The next run finds the unsaved tab, as if the first one had crashed
+        let mut restarted = EditorApp::new();
+        restarted.enable_session_recovery(dir.clone());
+        assert!(restarted.has_recovered_session());
+        // Nothing is overwritten while the user decides
+        assert!(!restarted.autosave_session(started + session::AUTOSAVE_INTERVAL * 2));
+        assert_eq!(restarted.restore_session(), 1);
+        assert!(!restarted.has_recovered_session());
+        assert!(restarted.is_tab_dirty(0));
+        assert_eq!(restarted.image_size(), app.image_size());
+        let texts: Vec<&AnnotationType> = restarted.annotations().iter().map(|item| &item.annotation_type).collect();
+        assert!(matches!(texts[..], [AnnotationType::Text { ref content, .. }] if content == "Keep me"));
+
+        // Once nothing is unsaved, the session is removed
+        restarted.discard_tab(0);
+        assert!(!restarted.autosave_session(started + session::AUTOSAVE_INTERVAL * 3));
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn test_dropped_file_failure_shows_toast() {
+        let mut app = EditorApp::new();
+        let input = egui::RawInput {
+            dropped_files: vec![egui::DroppedFile {
+                path: Some(PathBuf::from("/nonexistent/dropped.png")),
+                name: "dropped.png".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let _ = Context::default().run(input, |ctx| app.handle_dropped_files(ctx));
+
+        // Non-fatal errors don't interrupt with a dialog
+        assert!(app.error_dialog().is_none());
+        let toast = &app.notifications().toasts()[0];
+        assert_eq!((toast.severity, toast.title.as_str()), (Severity::Error, "Open Failed"));
+        assert!(toast.message.starts_with("dropped.png を開けませんでした: "));
+
+        // Errors shown in a dialog are kept in the log without a toast
+        app.show_error("Save Failed", "disk full".to_string());
+        assert_eq!(app.notifications().toasts().len(), 1);
+        let titles: Vec<&str> = app.notifications().history().map(|entry| entry.title.as_str()).collect();
+        assert_eq!(titles, vec!["Save Failed", "Open Failed"]);
+    }
+
+    #[test]
+    fn test_capture_saves_to_monitor_route() {
+        let screen = ScreenInfo {
+            index: 1,
+            bounds: Rect::from_min_size(Pos2::new(1920.0, 0.0), Vec2::new(1280.0, 1024.0)),
+            dpi_scale_x: 1.0,
+            dpi_scale_y: 1.0,
+            is_primary: false,
+        };
+        let dir = std::env::temp_dir().join(format!("lsa-editor-route-{}", Uuid::new_v4()));
+        let mut app = EditorApp::new();
+        app.settings.settings_mut().monitor_routing.set_route(MonitorRoute {
+            directory: Some(dir.clone()),
+            ..MonitorRoute::new(MonitorIdentity::from_screen(&screen))
+        });
+
+        let image = DynamicImage::ImageRgba8(RgbaImage::new(8, 8));
+        app.load_capture(image.clone(), screen).unwrap();
+        app.perform_action(ShortcutAction::Save);
+        assert!(app.error_dialog().is_none());
+        assert_eq!(app.file_path().and_then(Path::parent), Some(dir.as_path()));
+
+        // Opening another image drops the monitor association
+        app.load_image(image).unwrap();
+        assert!(app.active_route().is_none());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_background_capture_is_loaded_when_finished() {
+        let screen = ScreenInfo {
+            index: 0,
+            bounds: Rect::from_min_size(Pos2::ZERO, Vec2::new(16.0, 9.0)),
+            dpi_scale_x: 1.0,
+            dpi_scale_y: 1.0,
+            is_primary: true,
+        };
+        let mut app = EditorApp::new();
+        app.pending_capture = Some(
+            PendingCapture::spawn(move || {
+                Ok(crate::capture::CapturedFrame {
+                    image: RgbaImage::new(16, 9),
+                    screen,
+                    window_title: None,
+                    application: None,
+                    icc_profile: None,
+                })
+            })
+            .unwrap(),
+        );
+        assert!(app.is_capturing());
+
+        let started = Instant::now();
+        while app.is_capturing() && started.elapsed() < Duration::from_secs(10) {
+            std::thread::sleep(Duration::from_millis(1));
+            app.poll_pending_capture();
+        }
+        assert_eq!(app.image_size(), Some(Vec2::new(16.0, 9.0)));
+        assert_eq!(app.doc.source_screen.as_ref().map(|screen| screen.index), Some(0));
+    }
+
+    #[test]
+    fn test_captured_window_names_saved_file() {
+        let dir = std::env::temp_dir().join(format!("lsa-editor-window-name-{}", Uuid::new_v4()));
+        let mut app = EditorApp::new();
+        app.settings.settings_mut().default_save_directory = Some(dir.to_string_lossy().into_owned());
+        app.settings.settings_mut().file_naming.template = "{app} - {title}".to_string();
+        let screen = ScreenInfo {
+            index: 0,
+            bounds: Rect::from_min_size(Pos2::ZERO, Vec2::new(16.0, 9.0)),
+            dpi_scale_x: 1.0,
+            dpi_scale_y: 1.0,
+            is_primary: true,
+        };
+        let frame = crate::capture::CapturedFrame {
+            image: RgbaImage::new(16, 9),
+            screen,
+            window_title: Some("notes.txt - Notepad".to_string()),
+            application: Some("notepad.exe".to_string()),
+            icc_profile: None,
+        };
+        app.open_capture(Ok(frame)).unwrap();
+        app.save().unwrap();
+        assert_eq!(app.file_path(), Some(dir.join("notepad - notes.txt - Notepad.png").as_path()));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_counter_numbers_saved_files_across_restarts() {
+        let dir = std::env::temp_dir().join(format!("lsa-editor-counter-{}", Uuid::new_v4()));
+        let save_new_capture = |app: &mut EditorApp| {
+            app.settings.settings_mut().default_save_directory = Some(dir.to_string_lossy().into_owned());
+            app.settings.settings_mut().file_naming.template = "shot_{counter}".to_string();
+            app.enable_file_counters(dir.join("counters.json"));
+            app.load_image(DynamicImage::ImageRgba8(RgbaImage::new(4, 4))).unwrap();
+            app.save().unwrap();
+            app.file_path().unwrap().file_name().unwrap().to_string_lossy().into_owned()
+        };
+
+        let mut app = EditorApp::new();
+        assert_eq!(save_new_capture(&mut app), "shot_001.png");
+        assert_eq!(save_new_capture(&mut app), "shot_002.png");
+        // A new run continues the numbering
+        assert_eq!(save_new_capture(&mut EditorApp::new()), "shot_003.png");
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_captures_are_recorded_in_history() {
+        let dir = std::env::temp_dir().join(format!("lsa-editor-history-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let screen = ScreenInfo {
+            index: 1,
+            bounds: Rect::from_min_size(Pos2::ZERO, Vec2::new(16.0, 9.0)),
+            dpi_scale_x: 1.0,
+            dpi_scale_y: 1.0,
+            is_primary: false,
+        };
+        let mut app = EditorApp::new();
+        app.enable_capture_history(dir.join("history.jsonl"));
+        let frame = crate::capture::CapturedFrame {
+            image: RgbaImage::new(16, 9),
+            screen,
+            window_title: Some("Notepad".to_string()),
+            application: Some("notepad.exe".to_string()),
+            icc_profile: None,
+        };
+        app.open_capture(Ok(frame)).unwrap();
+        assert_eq!(app.image_metadata().application.as_deref(), Some("notepad.exe"));
+        let entry = app.capture_history().unwrap().entries()[0].clone();
+        assert_eq!(entry.target, "Window: Notepad");
+        assert_eq!((entry.width, entry.height), (16, 9));
+        assert_eq!(entry.path, None);
+        // Kept with a thumbnail for the Recent Captures menu
+        let thumbnail = app.capture_history().unwrap().load_thumbnail(entry.id).unwrap();
+        assert_eq!(thumbnail.dimensions(), (16, 9));
+        let ctx = Context::default();
+        assert!(app.recent_thumbnail(&ctx, entry.id).is_some());
+        // Listed as text only, with the loaded thumbnails released, in low-resource mode
+        app.set_low_resource_mode(LowResourceMode::On);
+        assert!(app.recent_thumbnail(&ctx, entry.id).is_none());
+        assert!(app.recent_thumbnails.is_empty());
+        app.set_low_resource_mode(LowResourceMode::Off);
+
+        // Saving the capture records where it went, and opened files aren't captures
+        let path = dir.join("notepad.png");
+        app.save_to(&path).unwrap();
+        app.open_file(&path).unwrap();
+        let history = CaptureHistory::load(dir.join("history.jsonl")).unwrap();
+        assert_eq!(history.entries().len(), 1);
+        assert_eq!(history.entries()[0].path.as_deref(), Some(path.as_path()));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_templates_apply_to_new_captures() {
+        let dir = std::env::temp_dir().join(format!("lsa-editor-templates-{}", Uuid::new_v4()));
+        let mut app = EditorApp::new();
+        app.enable_templates(dir.join("templates.json"));
+        app.load_image(DynamicImage::ImageRgba8(RgbaImage::new(400, 300))).unwrap();
+        app.add_annotation(AnnotationItem::new_rectangle(Pos2::new(10.0, 10.0), Vec2::new(300.0, 40.0)));
+        app.add_annotation(AnnotationItem::new_text(Pos2::new(20.0, 20.0), "Bug report".to_string()));
+        assert!(app.save_template("").is_err());
+        app.save_template("Header").unwrap();
+
+        // A narrower capture gets the header moved onto it, selected and undoable
+        app.load_image(DynamicImage::ImageRgba8(RgbaImage::new(200, 100))).unwrap();
+        assert!(app.apply_template("Header"));
+        assert!(!app.apply_template("Missing"));
+        let annotations = &app.doc.annotations;
+        assert_eq!(annotations.len(), 2);
+        assert!(annotations.iter().all(|a| a.is_selected));
+        assert_eq!(annotations[0].position, Pos2::new(0.0, 10.0));
+
+        // Templates persist for the next run
+        let mut next = EditorApp::new();
+        next.enable_templates(dir.join("templates.json"));
+        assert_eq!(next.templates()[0].name, "Header");
+        assert!(next.delete_template("Header").unwrap());
+        assert!(next.templates().is_empty());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_compare_project_revisions() {
+        let dir = std::env::temp_dir().join(format!("lsa-editor-project-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut app = EditorApp::new();
+        app.load_test_image().unwrap();
+        assert!(app.save_project().is_err());
+
+        app.save_to(&dir.join("tutorial.png")).unwrap();
+        app.add_annotation(AnnotationItem::new_rectangle(Pos2::new(5.0, 5.0), Vec2::new(10.0, 10.0)));
+        let project_path = app.save_project().unwrap();
+        let old_path = dir.join("tutorial-v1.lsaproj");
+        std::fs::copy(&project_path, &old_path).unwrap();
+
+        app.add_annotation(AnnotationItem::new_text(Pos2::new(20.0, 20.0), "Step 2".to_string()));
+        app.save_project().unwrap();
+
+        let mut reviewer = EditorApp::new();
+        reviewer.compare_projects(&old_path, &project_path).unwrap();
+        assert_eq!(reviewer.annotations().len(), 2);
+        let diff = reviewer.project_diff().unwrap();
+        assert_eq!((diff.added.len(), diff.removed.len(), diff.changed.len(), diff.unchanged), (1, 0, 0, 1));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_capture_edit_export_with_mock_screens() {
+        use crate::capture::mock::{MockBackend, MockScreen, PixelPattern};
+
+        let screen = MockScreen {
+            primary: true,
+            pattern: PixelPattern::Solid { color: [0, 0, 255, 255] },
+            ..MockScreen::new(64, 48)
+        };
+        let mut app = EditorApp::new();
+        app.set_capture_service(CaptureService::with_backend(MockBackend::new(vec![screen])).unwrap());
+        app.start_capture().unwrap();
+
+        let started = Instant::now();
+        while app.is_capturing() && started.elapsed() < Duration::from_secs(10) {
+            std::thread::sleep(Duration::from_millis(1));
+            app.poll_pending_capture();
+        }
+        assert_eq!(app.image_size(), Some(Vec2::new(64.0, 48.0)));
+        assert!(app.doc.source_screen.as_ref().is_some_and(|screen| screen.is_primary));
+
+        let mut annotation = AnnotationItem::new_rectangle(Pos2::new(8.0, 8.0), Vec2::new(16.0, 16.0));
+        if let AnnotationType::Rectangle { fill_color, .. } = &mut annotation.annotation_type {
+            *fill_color = Some(egui::Color32::RED);
+        }
+        app.add_annotation(annotation);
+
+        let exported = app.export_image().unwrap();
+        assert_eq!(exported.dimensions(), (64, 48));
+        assert_eq!(exported.get_pixel(0, 0).0, [0, 0, 255, 255]);
+        assert_eq!(exported.get_pixel(16, 16).0, [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_pan_margin_and_free_pan() {
+        let mut app = EditorApp::new();
+        app.load_test_image().unwrap();
+        let canvas = Rect::from_min_size(Pos2::ZERO, Vec2::new(800.0, 600.0));
+        let far = Vec2::new(5000.0, -5000.0);
+
+        // The 400x300 test image keeps 50px visible by default
+        assert_eq!(app.constrain_pan_offset(far, canvas), Vec2::new(750.0, -250.0));
+
+        app.settings.settings_mut().pan.min_visible_margin = 100.0;
+        assert_eq!(app.constrain_pan_offset(far, canvas), Vec2::new(700.0, -200.0));
+
+        app.settings.settings_mut().pan.free_pan = true;
+        assert_eq!(app.constrain_pan_offset(far, canvas), far);
+    }
+
+    #[test]
+    fn test_scroll_gestures_follow_pan_scheme() {
+        let mut app = EditorApp::new();
+        app.load_test_image().unwrap();
+        let canvas = Rect::from_min_size(Pos2::ZERO, Vec2::new(800.0, 600.0));
+
+        // Two-finger scrolling pans, pinching zooms towards the pointer
+        app.handle_scroll_gesture(Vec2::new(-20.0, 30.0), 1.0, false, Some(canvas.center()), canvas);
+        assert_eq!(app.doc.pan_offset, Vec2::new(-20.0, 30.0));
+        assert_eq!(app.doc.zoom_level, 1.0);
+        app.handle_scroll_gesture(Vec2::ZERO, 2.0, false, Some(canvas.center() + Vec2::new(100.0, 0.0)), canvas);
+        assert_eq!(app.doc.zoom_level, 2.0);
+        assert_eq!(app.doc.pan_offset, Vec2::new(-120.0, 30.0));
+
+        // The classic scheme zooms with the scroll wheel instead
+        app.settings.settings_mut().pan.scheme = PanScheme::ShiftDrag;
+        app.handle_scroll_gesture(Vec2::new(0.0, 500.0), 1.0, false, None, canvas);
+        assert_eq!(app.doc.zoom_level, 3.0);
+        assert_eq!(app.doc.pan_offset, Vec2::new(-120.0, 30.0));
+    }
+
+    #[test]
+    fn test_configured_scroll_and_double_click_gestures() {
+        let mut app = EditorApp::new();
+        app.load_test_image().unwrap();
+        let canvas = Rect::from_min_size(Pos2::ZERO, Vec2::new(800.0, 600.0));
+
+        // Scrolling pans vertically, or horizontally with Shift, whatever the pan scheme
+        app.settings.settings_mut().pan.scheme = PanScheme::ShiftDrag;
+        app.settings.settings_mut().gestures.scroll = ScrollAction::Pan;
+        app.handle_scroll_gesture(Vec2::new(0.0, 40.0), 1.0, false, None, canvas);
+        app.handle_scroll_gesture(Vec2::new(0.0, -25.0), 1.0, true, None, canvas);
+        assert_eq!(app.doc.pan_offset, Vec2::new(-25.0, 40.0));
+        assert_eq!(app.doc.zoom_level, 1.0);
+
+        // Zooming with half the sensitivity
+        app.settings.settings_mut().gestures.scroll = ScrollAction::Zoom;
+        app.settings.settings_mut().gestures.zoom_sensitivity = 0.5;
+        app.handle_scroll_gesture(Vec2::new(0.0, 1000.0), 1.0, false, None, canvas);
+        assert_eq!(app.doc.zoom_level, 1.5);
+        app.handle_scroll_gesture(Vec2::ZERO, 4.0, false, None, canvas);
+        assert_eq!(app.doc.zoom_level, 3.0);
+
+        app.settings.settings_mut().gestures.double_click = DoubleClickAction::ActualSize;
+        app.handle_double_click();
+        assert_eq!((app.doc.zoom_level, app.doc.pan_offset), (1.0, Vec2::new(-25.0, 40.0)));
+        app.settings.settings_mut().gestures.double_click = DoubleClickAction::ResetView;
+        app.handle_double_click();
+        assert_eq!((app.doc.zoom_level, app.doc.pan_offset), (1.0, Vec2::ZERO));
+        app.settings.settings_mut().gestures.double_click = DoubleClickAction::Nothing;
+        app.doc.zoom_level = 2.0;
+        app.handle_double_click();
+        assert_eq!(app.doc.zoom_level, 2.0);
+    }
+
+    #[test]
+    fn test_import_annotations_onto_image() {
+        let dir = std::env::temp_dir().join(format!("lsa-editor-import-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("overlay.svg");
+        std::fs::write(
+            &path,
+            r#"<svg><rect width="10" height="10"/><circle r="3"/><text y="20">Note</text></svg>"#,
+        )
+        .unwrap();
+
+        let mut app = EditorApp::new();
+        assert!(app.import_annotations(&path).is_err());
+
+        app.load_test_image().unwrap();
+        let result = app.import_annotations(&path).unwrap();
+        assert_eq!(result.skipped, 1);
+        assert_eq!(app.annotations().len(), 2);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_tabs_keep_independent_documents() {
+        let dir = std::env::temp_dir().join(format!("lsa-editor-tabs-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("opened.png");
+        RgbaImage::new(8, 8).save(&path).unwrap();
+
+        let mut app = EditorApp::new();
+        assert_eq!(app.tab_count(), 1);
+        app.open_file(&path).unwrap();
+        // The empty start tab is reused
+        assert_eq!(app.tab_count(), 1);
+        assert!(!app.is_tab_dirty(0));
+        app.zoom_in();
+
+        app.load_test_image().unwrap();
+        assert_eq!((app.tab_count(), app.active_tab()), (2, 1));
+        assert_eq!(app.tab_title(1), "Untitled");
+        assert!(app.is_tab_dirty(1));
+        app.add_annotation(AnnotationItem::new_rectangle(Pos2::ZERO, Vec2::new(10.0, 10.0)));
+        assert_eq!(app.zoom_level(), 1.0);
+
+        app.perform_action(ShortcutAction::NextTab);
+        assert_eq!(app.active_tab(), 0);
+        assert_eq!(app.tab_title(0), "opened.png");
+        assert!(app.annotations().is_empty());
+        assert!(app.zoom_level() > 1.0);
+        assert_eq!(app.image_size(), Some(Vec2::new(8.0, 8.0)));
+
+        // Closing a dirty tab waits for the user to save or discard it
+        assert!(!app.close_tab(1));
+        assert_eq!(app.closing_tab, Some(1));
+        app.discard_tab(1);
+        assert_eq!((app.tab_count(), app.active_tab()), (1, 0));
+
+        // Closing the last tab leaves an empty one
+        assert!(app.close_tab(0));
+        assert_eq!(app.tab_count(), 1);
+        assert!(app.doc.is_empty());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_inactive_tabs_are_compressed() {
+        let mut app = EditorApp::new();
+        app.load_test_image().unwrap();
+        app.add_annotation(AnnotationItem::new_rectangle(Pos2::ZERO, Vec2::new(10.0, 10.0)));
+        let pixels = app.doc.source_image.as_ref().unwrap().to_rgba8();
+        let _ = app.doc.composite();
+        let active_bytes = app.doc.memory_usage();
+
+        app.load_test_image().unwrap();
+        assert_eq!(app.active_tab(), 1);
+        let inactive = &app.tabs[0];
+        assert!(inactive.source_image.is_none());
+        assert!(inactive.memory_usage() < active_bytes / 4);
+        // Still a dirty tab with an image, whose pixels can be read without selecting it
+        assert!(app.is_tab_dirty(0));
+        assert_eq!(inactive.image_dimensions(), Some((400, 300)));
+        assert_eq!(inactive.full_source_image().unwrap().unwrap().to_rgba8(), pixels);
+
+        // Decoded again once selected
+        app.select_tab(0);
+        assert!(app.doc.suspended_image.is_none());
+        assert_eq!(app.doc.source_image.as_ref().unwrap().to_rgba8(), pixels);
+        assert_eq!(app.annotations().len(), 1);
+        assert!(app.tabs[1].source_image.is_none());
+    }
+
+    #[test]
+    fn test_exit_with_unsaved_changes_asks_first() {
+        let dir = std::env::temp_dir().join(format!("lsa-editor-dirty-{}", Uuid::new_v4()));
+        let mut app = EditorApp::new();
+        app.settings.settings_mut().default_save_directory = Some(dir.display().to_string());
+        assert_eq!(app.window_title(), APP_TITLE);
+
+        // An unsaved capture is dirty
+        app.load_test_image().unwrap();
+        assert!(app.is_dirty());
+        assert_eq!(app.window_title(), format!("Untitled* - {}", APP_TITLE));
+        app.request_close();
+        assert!(!app.should_close());
+        assert!(app.show_exit_prompt);
+
+        assert!(app.save_all());
+        assert!(!app.has_unsaved_changes());
+        assert!(app.window_title().ends_with(&format!(".png - {}", APP_TITLE)));
+
+        // Annotating makes the saved image dirty again
+        app.add_annotation(AnnotationItem::new_rectangle(Pos2::ZERO, Vec2::new(10.0, 10.0)));
+        assert!(app.is_dirty());
+        app.perform_action(ShortcutAction::Save);
+        app.request_close();
+        assert!(app.should_close());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_moved_annotation_snaps_into_alignment() {
+        let mut app = EditorApp::new();
+        app.load_test_image().unwrap();
+        app.add_annotation(AnnotationItem::new_rectangle(Pos2::new(40.0, 40.0), Vec2::new(60.0, 30.0)));
+        let moved = AnnotationItem::new_rectangle(Pos2::new(150.0, 200.0), Vec2::new(20.0, 20.0));
+        let id = moved.id;
+        app.add_annotation(moved);
+
+        app.doc.drag_origin = Pos2::new(150.0, 200.0);
+        app.move_dragged_annotation(id, Vec2::new(-107.0, 0.0), egui::Modifiers::NONE);
+        let position = app.annotations().iter().find(|a| a.id == id).unwrap().position;
+        assert_eq!(position, Pos2::new(40.0, 200.0));
+        assert_eq!(app.doc.guides.len(), 1);
+
+        // Alt moves freely
+        app.move_dragged_annotation(id, Vec2::ZERO, egui::Modifiers::ALT);
+        let position = app.annotations().iter().find(|a| a.id == id).unwrap().position;
+        assert_eq!(position, Pos2::new(43.0, 200.0));
+        assert!(app.doc.guides.is_empty());
+    }
+
+    #[test]
+    fn test_copy_paste_and_duplicate_annotations() {
+        let mut app = EditorApp::new();
+        app.load_test_image().unwrap();
+        let mut rectangle = AnnotationItem::new_rectangle(Pos2::new(20.0, 20.0), Vec2::new(30.0, 30.0));
+        if let AnnotationType::Rectangle { ref mut stroke_color, .. } = rectangle.annotation_type {
+            *stroke_color = egui::Color32::GREEN;
+        }
+        let id = rectangle.id;
+        app.add_annotation(rectangle.clone());
+        assert!(!app.copy_annotations().unwrap());
+
+        app.select_annotation(Some(id));
+        app.perform_action(ShortcutAction::Duplicate);
+        assert_eq!(app.annotations().len(), 2);
+        let duplicate = &app.annotations()[1];
+        assert_eq!(duplicate.position, Pos2::new(30.0, 30.0));
+        assert_eq!(duplicate.annotation_type, rectangle.annotation_type);
+        assert_eq!(app.selected_annotation_ids(), vec![duplicate.id]);
+
+        // Copy in one tab and paste twice into another
+        app.select_annotation(Some(id));
+        app.perform_action(ShortcutAction::Copy);
+        assert!(app.has_copied_annotations());
+        app.load_test_image().unwrap();
+        app.perform_action(ShortcutAction::Paste);
+        app.perform_action(ShortcutAction::Paste);
+        assert!(app.error_dialog().is_none());
+        let positions: Vec<Pos2> = app.annotations().iter().map(|a| a.position).collect();
+        assert_eq!(positions, vec![Pos2::new(30.0, 30.0), Pos2::new(40.0, 40.0)]);
+        assert_eq!(app.annotations()[0].annotation_type, rectangle.annotation_type);
+        assert_ne!(app.annotations()[0].id, id);
+    }
+
+    #[test]
+    fn test_nudge_selected_annotations() {
+        let mut app = EditorApp::new();
+        app.load_test_image().unwrap();
+        let annotation = AnnotationItem::new_rectangle(Pos2::new(20.0, 20.0), Vec2::new(30.0, 30.0));
+        let id = annotation.id;
+        app.add_annotation(annotation);
+        assert!(!app.nudge_selected(Vec2::new(1.0, 0.0)));
+
+        app.select_annotation(Some(id));
+        let revision = app.doc.content_revision;
+        app.perform_action(ShortcutAction::Nudge(NudgeDirection::Right));
+        app.perform_action(ShortcutAction::NudgeFar(NudgeDirection::Up));
+        assert_eq!(app.annotations()[0].position, Pos2::new(21.0, 10.0));
+        assert_ne!(app.doc.content_revision, revision);
+
+        // Nudges go through the shortcut map, so they can be rebound
+        app.rebind_shortcut(ShortcutAction::Nudge(NudgeDirection::Left), KeyChord::key(egui::Key::H)).unwrap();
+        let ctx = Context::default();
+        let press = |key| egui::Event::Key { key, pressed: true, repeat: false, modifiers: egui::Modifiers::NONE };
+        ctx.begin_frame(egui::RawInput {
+            events: vec![press(egui::Key::H), press(egui::Key::ArrowLeft)],
+            ..Default::default()
+        });
+        app.handle_shortcuts(&ctx);
+        let _ = ctx.end_frame();
+        assert_eq!(app.annotations()[0].position, Pos2::new(20.0, 10.0));
+    }
+
+    #[test]
+    fn test_place_and_select_annotations_with_keyboard() {
+        let mut app = EditorApp::new();
+        assert_eq!(app.place_annotation(), None);
+        app.load_test_image().unwrap();
+        let size = app.image_size().unwrap();
+
+        // The Select tool has nothing to place
+        assert_eq!(app.place_annotation(), None);
+        app.perform_action(ShortcutAction::SwitchTool(2));
+        app.perform_action(ShortcutAction::PlaceAnnotation);
+        let rectangle = app.annotations()[0].clone();
+        assert!(rectangle.is_selected);
+        assert_eq!(rectangle.position + PLACED_RECTANGLE_SIZE * 0.5, (size * 0.5).to_pos2());
+
+        // Placed in the middle of the panned view, kept inside the image
+        app.doc.zoom_level = 2.0;
+        app.doc.pan_offset = Vec2::new(size.x, 0.0);
+        app.current_tool = Tool::Text;
+        let text = app.place_annotation().unwrap();
+        assert_eq!(app.annotations()[1].position, Pos2::new(0.0, size.y * 0.5));
+        assert_eq!(app.selected_annotation_ids(), vec![text]);
+
+        // Selection cycles through the annotations
+        assert_eq!(app.select_next_annotation(), Some(rectangle.id));
+        app.perform_action(ShortcutAction::SelectNext);
+        assert_eq!(app.selected_annotation_ids(), vec![text]);
+        app.select_annotation(None);
+        assert_eq!(app.select_next_annotation(), Some(rectangle.id));
+    }
+
+    #[test]
+    fn test_rotate_handle_turns_annotation() {
+        let mut app = EditorApp::new();
+        app.load_test_image().unwrap();
+        app.doc.zoom_level = 2.0;
+        let annotation = AnnotationItem::new_rectangle(Pos2::new(100.0, 100.0), Vec2::new(40.0, 20.0));
+        let id = annotation.id;
+        app.add_annotation(annotation);
+
+        // The handle sits 24 screen px (12 image px) above the top edge, only when selected
+        let handle = Pos2::new(120.0, 88.0);
+        assert_eq!(app.rotate_handle_at(handle), None);
+        app.select_annotation(Some(id));
+        assert_eq!(app.rotate_handle_at(handle), Some(id));
+
+        app.rotate_dragged_annotation(id, Pos2::new(160.0, 112.0), egui::Modifiers::NONE);
+        assert!((app.annotations()[0].rotation - 92.86).abs() < 0.01);
+        app.rotate_dragged_annotation(id, Pos2::new(160.0, 112.0), egui::Modifiers::COMMAND);
+        assert_eq!(app.annotations()[0].rotation, 90.0);
+        assert!(app.is_dirty());
+
+        // The handle follows the rotation
+        assert_eq!(app.rotate_handle_at(handle), None);
+        assert_eq!(app.rotate_handle_at(Pos2::new(142.0, 110.0)), Some(id));
+    }
+
+    #[test]
+    fn test_insert_and_resize_image_annotation() {
+        let mut app = EditorApp::new();
+        let logo = DynamicImage::ImageRgba8(RgbaImage::from_pixel(400, 100, image::Rgba([0, 0, 255, 255])));
+        assert!(app.insert_image(&logo).is_err());
+
+        app.load_test_image().unwrap();
+        let id = app.insert_image(&logo).unwrap();
+        assert_eq!(app.selected_annotation_ids(), vec![id]);
+        // Scaled down to half the screenshot's width and centered
+        let size = app.image_size().unwrap();
+        let bounds = app.annotations()[0].bounds();
+        assert_eq!(bounds.width(), size.x * MAX_INSERTED_IMAGE_FRACTION);
+        assert_eq!(bounds.width() / bounds.height(), 4.0);
+        assert_eq!(bounds.center(), (size * 0.5).to_pos2());
+
+        // Dragging the bottom-right handle keeps the aspect ratio
+        assert_eq!(app.resize_handle_at(bounds.right_bottom() + Vec2::splat(2.0)), Some((id, 2)));
+        app.resize_dragged_annotation(id, 2, bounds.left_top() + Vec2::new(100.0, 10.0));
+        let resized = app.annotations()[0].bounds();
+        assert_eq!(resized.min, bounds.min);
+        assert_eq!(resized.size(), Vec2::new(100.0, 25.0));
+
+        let flattened = app.flattened_image().unwrap();
+        assert_eq!(flattened.get_pixel(resized.min.x as u32 + 1, resized.min.y as u32 + 1).0, [0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn test_compare_with_image_and_tab() {
+        let mut app = EditorApp::new();
+        assert!(app.compare_with_image(RgbaImage::new(4, 4), "empty".to_string()).is_err());
+        app.load_test_image().unwrap();
+        let mut reference = app.display_image().unwrap().to_rgba8();
+
+        assert!(app.compare_with_image(reference.clone(), "same".to_string()).unwrap().is_identical());
+
+        for x in 20..30 {
+            reference.put_pixel(x, 40, image::Rgba([0, 0, 0, 255]));
+        }
+        let result = app.compare_with_image(reference.clone(), "changed".to_string()).unwrap();
+        assert_eq!(result.changed_pixels, 10);
+        assert_eq!(result.regions, vec![Rect::from_min_max(Pos2::new(20.0, 40.0), Pos2::new(30.0, 41.0))]);
+
+        // Raising the threshold past the change hides it
+        app.set_comparison_threshold(255);
+        assert!(app.image_comparison().unwrap().is_identical());
+        app.clear_image_comparison();
+        assert!(app.image_comparison().is_none());
+
+        // Compare with the image in another tab
+        app.load_image(DynamicImage::ImageRgba8(reference)).unwrap();
+        assert_eq!(app.compare_with_tab(0).unwrap().changed_pixels, 10);
+        assert!(app.compare_with_tab(1).is_err());
+    }
+
+    #[test]
+    fn test_create_collage_from_tabs_and_saved_captures() {
+        let dir = std::env::temp_dir().join(format!("lsa-editor-collage-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let saved = dir.join("saved.png");
+        RgbaImage::from_pixel(30, 20, image::Rgba([0, 0, 255, 255])).save(&saved).unwrap();
+
+        let mut app = EditorApp::new();
+        app.load_image(DynamicImage::ImageRgba8(RgbaImage::new(40, 30))).unwrap();
+        app.load_image(DynamicImage::ImageRgba8(RgbaImage::new(10, 10))).unwrap();
+        app.create_collage(&[0, 1], &[saved]).unwrap();
+
+        // The collage opens in a new tab with a cell per image
+        assert_eq!((app.tab_count(), app.active_tab()), (3, 2));
+        let options = render::CollageOptions::default();
+        let size = app.image_size().unwrap();
+        assert_eq!(size.x as u32, 3 * (options.cell_width + options.spacing) + options.spacing);
+
+        assert!(app.create_collage(&[], &[dir.join("missing.png")]).is_err());
+        assert!(app.create_collage(&[], &[]).is_err());
+        assert_eq!(app.tab_count(), 3);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_window_hotkey_captures_and_auto_saves() {
+        use crate::capture::mock::{MockBackend, MockScreen};
+
+        let dir = std::env::temp_dir().join(format!("lsa-editor-hotkey-{}", Uuid::new_v4()));
+        let mut backend = MockBackend::new(vec![MockScreen::new(64, 48)]);
+        backend.active_window = Some(Rect::from_min_size(Pos2::new(10.0, 5.0), Vec2::new(30.0, 20.0)));
+        let mut app = EditorApp::new();
+        app.set_capture_service(CaptureService::with_backend(backend).unwrap());
+        app.settings.settings_mut().default_save_directory = Some(dir.to_string_lossy().into_owned());
+        app.settings.settings_mut().auto_save_captures = true;
+        let window_hotkey = hotkeys::configured_hotkeys(app.settings.get_settings())[1].clone();
+
+        // Paused hotkeys start nothing
+        app.set_trigger_enabled(Trigger::Hotkeys, false);
+        app.event_sender().send(AppEvent::Hotkey(window_hotkey.clone()));
+        app.poll_events();
+        assert!(!app.is_capturing());
+
+        app.set_trigger_enabled(Trigger::Hotkeys, true);
+        app.event_sender().send(AppEvent::Hotkey(window_hotkey));
+        app.poll_events();
+        let started = Instant::now();
+        while app.is_capturing() && started.elapsed() < Duration::from_secs(10) {
+            std::thread::sleep(Duration::from_millis(1));
+            app.poll_pending_capture();
+        }
+        assert_eq!(app.image_size(), Some(Vec2::new(30.0, 20.0)));
+        let saved = app.file_path().unwrap().to_path_buf();
+        assert!(saved.starts_with(&dir));
+        assert_eq!(image::open(&saved).unwrap().to_rgba8().dimensions(), (30, 20));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_screen_hotkey_cycles_monitors() {
+        use crate::capture::mock::{MockBackend, MockScreen};
+
+        let screens = vec![
+            MockScreen::new(64, 48),
+            MockScreen { x: 64, ..MockScreen::new(32, 16) },
+            MockScreen { x: 96, ..MockScreen::new(16, 16) },
+        ];
+        let mut app = EditorApp::new();
+        app.set_capture_service(CaptureService::with_backend(MockBackend::new(screens)).unwrap());
+        app.settings.settings_mut().cycle_monitors = true;
+        let screen_hotkey = hotkeys::configured_hotkeys(app.settings.get_settings())
+            .into_iter()
+            .find(|hotkey| HotkeyAction::from_id(hotkey.id) == Some(HotkeyAction::CaptureScreen))
+            .unwrap();
+
+        for _ in 0..2 {
+            app.event_sender().send(AppEvent::Hotkey(screen_hotkey.clone()));
+            app.poll_events();
+        }
+        assert_eq!(app.monitor_cycle.chosen(), Some(CycleTarget::Monitor(1)));
+        // Nothing is captured until the hotkey has been left alone
+        app.poll_monitor_cycle(Instant::now());
+        assert!(!app.is_capturing());
+
+        app.poll_monitor_cycle(Instant::now() + crate::monitor_cycle::CYCLE_TIMEOUT);
+        let started = Instant::now();
+        while app.is_capturing() && started.elapsed() < Duration::from_secs(10) {
+            std::thread::sleep(Duration::from_millis(1));
+            app.poll_pending_capture();
+        }
+        assert_eq!(app.image_size(), Some(Vec2::new(32.0, 16.0)));
+        assert_eq!(app.monitor_cycle.chosen(), None);
+    }
+
+    #[test]
+    fn test_cursor_monitor_hotkey_captures_screen_under_cursor() {
+        use crate::capture::mock::{MockBackend, MockScreen};
+
+        let mut backend = MockBackend::new(vec![MockScreen::new(64, 48), MockScreen { x: 64, ..MockScreen::new(32, 16) }]);
+        backend.active_screen = Some(0);
+        backend.cursor = Some(Pos2::new(80.0, 10.0));
+        let mut app = EditorApp::new();
+        app.set_capture_service(CaptureService::with_backend(backend).unwrap());
+        app.settings.settings_mut().capture_active_monitor = true;
+        let cursor_hotkey = hotkeys::configured_hotkeys(app.settings.get_settings())
+            .into_iter()
+            .find(|hotkey| HotkeyAction::from_id(hotkey.id) == Some(HotkeyAction::CaptureCursorMonitor))
+            .unwrap();
+
+        app.event_sender().send(AppEvent::Hotkey(cursor_hotkey));
+        app.poll_events();
+        let started = Instant::now();
+        while app.is_capturing() && started.elapsed() < Duration::from_secs(10) {
+            std::thread::sleep(Duration::from_millis(1));
+            app.poll_pending_capture();
+        }
+        // The monitor under the cursor, not the one showing the focused window
+        assert_eq!(app.image_size(), Some(Vec2::new(32.0, 16.0)));
+    }
+
+    #[test]
+    fn test_color_picker_hotkey_copies_color_and_closes_frame() {
+        use crate::capture::mock::{MockBackend, MockScreen, PixelPattern};
 
-                ui.painter().image(
-                    texture.id(),
-                    visible_image_rect,
-                    uv_rect,
-                    egui::Color32::WHITE,
-                );
-            }
+        let screen = MockScreen { pattern: PixelPattern::Solid { color: [30, 144, 255, 255] }, ..MockScreen::new(32, 16) };
+        let mut app = EditorApp::new();
+        app.set_capture_service(CaptureService::with_backend(MockBackend::new(vec![screen])).unwrap());
+        app.load_test_image().unwrap();
+        let picker_hotkey = hotkeys::configured_hotkeys(app.settings.get_settings())
+            .into_iter()
+            .find(|hotkey| HotkeyAction::from_id(hotkey.id) == Some(HotkeyAction::PickColor))
+            .unwrap();
 
-            // Draw image border (only the visible part)
-            if visible_image_rect.width() > 0.0 && visible_image_rect.height() > 0.0 {
-                ui.painter().rect_stroke(
-                    visible_image_rect,
-                    0.0,
-                    egui::Stroke::new(1.0, ui.style().visuals.widgets.inactive.bg_stroke.color),
-                );
+        let pick = |app: &mut EditorApp| {
+            app.event_sender().send(AppEvent::Hotkey(picker_hotkey.clone()));
+            app.poll_events();
+            let started = Instant::now();
+            while app.is_capturing() && started.elapsed() < Duration::from_secs(10) {
+                std::thread::sleep(Duration::from_millis(1));
+                app.poll_pending_capture();
             }
+        };
+        pick(&mut app);
+        // The frozen frame opens in a tab of its own, with nothing unsaved
+        assert!(app.is_picking_color());
+        assert_eq!((app.tab_count(), app.image_size()), (2, Some(Vec2::new(32.0, 16.0))));
+        assert!(!app.is_dirty());
 
-            // Draw annotations (they will be clipped automatically)
-            self.draw_annotations(ui, image_rect);
+        let ctx = Context::default();
+        assert_eq!(app.pick_screen_color(&ctx, Pos2::new(40.0, 4.0)), None);
+        assert_eq!(app.pick_screen_color(&ctx, Pos2::new(4.0, 4.0)), Some(egui::Color32::from_rgb(30, 144, 255)));
+        assert_eq!(ctx.output(|output| output.copied_text.clone()), "#1E90FF");
+        assert_eq!(app.settings.get_settings().palette.colors, vec![egui::Color32::from_rgb(30, 144, 255)]);
+        assert!(!app.is_picking_color());
+        assert_eq!(app.tab_count(), 1);
+        assert!(app.history.as_ref().is_none_or(|history| history.search("").is_empty()));
 
-            // Show zoom and pan info overlay
-            self.draw_info_overlay(ui, available_rect);
-        });
+        // Cancelling closes the frame without picking
+        pick(&mut app);
+        app.cancel_color_pick();
+        assert_eq!(app.tab_count(), 1);
+        assert_eq!(app.pick_screen_color(&ctx, Pos2::new(4.0, 4.0)), None);
     }
 
-    /// Handle mouse interactions for panning and zooming
-    fn handle_mouse_interactions(&mut self, response: &Response, available_rect: Rect) {
-        // Handle scroll wheel for zooming
-        if response.hovered() {
-            let scroll_delta = response.ctx.input(|i| i.scroll_delta.y);
-            if scroll_delta != 0.0 {
-                let zoom_factor = 1.0 + scroll_delta * 0.001;
-                let old_zoom = self.zoom_level;
-                self.zoom_level = (self.zoom_level * zoom_factor as f64).clamp(0.1, 10.0);
-                
-                // Adjust pan offset to zoom towards mouse cursor
-                if let Some(mouse_pos) = response.hover_pos() {
-                    let relative_pos = mouse_pos - available_rect.center();
-                    let zoom_change = (self.zoom_level / old_zoom - 1.0) as f32;
-                    self.pan_offset -= relative_pos * zoom_change;
-                }
+    #[test]
+    fn test_hotkey_capture_selects_region_remembered_for_application() {
+        use crate::capture::mock::{MockBackend, MockScreen};
+
+        let mut backend = MockBackend::new(vec![MockScreen::new(64, 48), MockScreen { x: 64, scale: 2.0, ..MockScreen::new(32, 32) }]);
+        backend.active_application = Some("firefox.exe".to_string());
+        let mut app = EditorApp::new();
+        app.set_capture_service(CaptureService::with_backend(backend).unwrap());
+        app.settings.settings_mut().select_capture_region = true;
+        let capture = |app: &mut EditorApp| {
+            app.start_hotkey_capture().unwrap();
+            let started = Instant::now();
+            while app.is_capturing() && started.elapsed() < Duration::from_secs(10) {
+                std::thread::sleep(Duration::from_millis(1));
+                app.poll_pending_capture();
             }
-        }
+        };
 
-        // Handle middle mouse button or right mouse button for panning
-        if response.dragged_by(egui::PointerButton::Middle) || 
-           (response.dragged_by(egui::PointerButton::Primary) && 
-            response.ctx.input(|i| i.modifiers.shift)) {
-            
-            let delta = response.drag_delta();
-            let new_pan_offset = self.pan_offset + delta;
-            
-            // Apply pan limits to prevent the image from going completely off-screen
-            self.pan_offset = self.constrain_pan_offset(new_pan_offset, available_rect);
+        // Nothing remembered yet: the whole primary screen is selected
+        capture(&mut app);
+        assert!(app.is_selecting_region());
+        assert_eq!(app.selected_region(), Some(Rect::from_min_size(Pos2::ZERO, Vec2::new(64.0, 48.0))));
+        app.set_selected_region(Rect::from_min_size(Pos2::new(10.0, 5.0), Vec2::new(20.0, 10.0)));
+        app.confirm_region_selection().unwrap();
+        assert!(!app.is_selecting_region());
+        assert_eq!(app.image_size(), Some(Vec2::new(20.0, 10.0)));
+        let memory = &app.settings.get_settings().region_memory;
+        assert_eq!(memory.region_for("firefox.exe"), Some(Rect::from_min_size(Pos2::new(10.0, 5.0), Vec2::new(20.0, 10.0))));
+
+        // A region on the high-DPI screen is captured there and selected in its pixels
+        let region = Rect::from_min_size(Pos2::new(70.0, 2.0), Vec2::new(10.0, 8.0));
+        app.settings.settings_mut().region_memory.remember("firefox.exe", region);
+        capture(&mut app);
+        assert_eq!(app.image_size(), Some(Vec2::new(64.0, 64.0)));
+        assert_eq!(app.selected_region(), Some(Rect::from_min_size(Pos2::new(12.0, 4.0), Vec2::new(20.0, 16.0))));
+
+        // Keeping the whole screen leaves the remembered region alone
+        app.select_whole_screen();
+        assert!(!app.is_selecting_region());
+        assert_eq!(app.image_size(), Some(Vec2::new(64.0, 64.0)));
+        assert_eq!(app.settings.get_settings().region_memory.region_for("firefox.exe"), Some(region));
+    }
+
+    #[test]
+    fn test_watch_selected_region_saves_changes() {
+        use crate::capture::mock::{MockBackend, MockScreen};
+
+        let dir = std::env::temp_dir().join(format!("lsa-editor-watch-{}", Uuid::new_v4()));
+        let mut app = EditorApp::new();
+        app.set_capture_service(CaptureService::with_backend(MockBackend::new(vec![MockScreen::new(64, 48)])).unwrap());
+        app.settings.settings_mut().watch.directory = Some(dir.clone());
+        let error = app.start_region_watch(Rect::from_min_size(Pos2::ZERO, Vec2::new(0.5, 10.0))).unwrap_err();
+        assert_eq!((error.code(), error.exit_code()), ("invalid_argument", 2));
+        assert!(!app.is_watching_region());
+        app.start_watch_selection().unwrap();
+        let started = Instant::now();
+        while app.is_capturing() && started.elapsed() < Duration::from_secs(10) {
+            std::thread::sleep(Duration::from_millis(1));
+            app.poll_pending_capture();
         }
+        assert!(app.is_selecting_region());
+        app.set_selected_region(Rect::from_min_size(Pos2::new(10.0, 5.0), Vec2::new(20.0, 10.0)));
+        app.watch_selected_region().unwrap();
+        // The capture for choosing the region is thrown away
+        assert!(app.doc.is_empty());
+        assert_eq!(app.region_watch.as_ref().unwrap().region(), Rect::from_min_size(Pos2::new(10.0, 5.0), Vec2::new(20.0, 10.0)));
 
-        // Handle double-click to reset zoom and pan
-        if response.double_clicked() {
-            self.zoom_level = 1.0;
-            self.pan_offset = Vec2::ZERO;
+        // The first check is the baseline
+        let now = Instant::now();
+        app.poll_region_watch(now);
+        while app.watch_capture.is_some() && started.elapsed() < Duration::from_secs(10) {
+            std::thread::sleep(Duration::from_millis(1));
+            app.poll_region_watch(now);
         }
+        let watch = app.region_watch.as_ref().unwrap();
+        assert_eq!((watch.checks(), watch.saved()), (1, 0));
+        let folder = watch.folder().to_path_buf();
+        assert!(!folder.exists());
+
+        // A dialog appearing in the region is saved
+        let mut frame = CapturedFrame {
+            image: RgbaImage::from_pixel(20, 10, image::Rgba([255, 0, 0, 255])),
+            screen: app.capture_service().unwrap().get_screens()[0].clone(),
+            window_title: None,
+            application: None,
+            icc_profile: None,
+        };
+        app.save_watch_frame(frame.clone()).unwrap();
+        let saved = app.last_capture_path.clone().unwrap();
+        assert_eq!(saved.parent(), Some(folder.as_path()));
+        assert_eq!(image::open(&saved).unwrap().to_rgba8().dimensions(), (20, 10));
+        assert_eq!(app.notifications.toasts().last().unwrap().title, "Change Captured");
+        frame.image.put_pixel(0, 0, image::Rgba([0, 0, 0, 255]));
+        app.save_watch_frame(frame).unwrap();
+        assert_eq!(app.region_watch.as_ref().unwrap().saved(), 1);
+
+        app.stop_region_watch();
+        assert!(!app.is_watching_region());
+        assert_eq!(app.notifications.toasts().last().unwrap().title, "Region Watch Stopped");
+        std::fs::remove_dir_all(&dir).ok();
     }
 
-    /// Draw annotations over the image
-    fn draw_annotations(&self, ui: &mut egui::Ui, image_rect: Rect) {
-        for annotation in &self.annotations {
-            let annotation_pos = image_rect.min + annotation.position.to_vec2() * self.zoom_level as f32;
-            
-            match &annotation.annotation_type {
-                crate::AnnotationType::Rectangle { size, stroke_color, stroke_width } => {
-                    let rect_size = *size * self.zoom_level as f32;
-                    let rect = Rect::from_min_size(annotation_pos, rect_size);
-                    
-                    ui.painter().rect_stroke(
-                        rect,
-                        0.0,
-                        egui::Stroke::new(*stroke_width, *stroke_color),
-                    );
-                    
-                    // Draw selection handles if selected
-                    if annotation.is_selected {
-                        self.draw_selection_handles(ui, rect);
-                    }
-                }
-                crate::AnnotationType::Text { content, font_size, color } => {
-                    let scaled_font_size = font_size * self.zoom_level as f32;
-                    ui.painter().text(
-                        annotation_pos,
-                        egui::Align2::LEFT_TOP,
-                        content,
-                        egui::FontId::proportional(scaled_font_size),
-                        *color,
-                    );
-                }
-            }
-        }
+    #[test]
+    fn test_region_selection_constraints() {
+        let mut app = EditorApp::new();
+        let screen = ScreenInfo {
+            index: 0,
+            bounds: Rect::from_min_size(Pos2::ZERO, Vec2::new(64.0, 48.0)),
+            dpi_scale_x: 1.0,
+            dpi_scale_y: 1.0,
+            is_primary: true,
+        };
+        app.load_capture(DynamicImage::ImageRgba8(RgbaImage::new(64, 48)), screen).unwrap();
+        app.start_region_selection(None);
+
+        // A fixed size is centered on the selection, and an aspect ratio shrinks it
+        app.set_region_constraint(SelectionConstraint::Fixed(20, 10));
+        assert_eq!(app.selected_region(), Some(Rect::from_min_size(Pos2::new(22.0, 19.0), Vec2::new(20.0, 10.0))));
+        app.set_region_constraint(SelectionConstraint::Aspect(1, 1));
+        assert_eq!(app.selected_region(), Some(Rect::from_min_size(Pos2::new(27.0, 19.0), Vec2::new(10.0, 10.0))));
+
+        // The constraint applies to the next capture's selection too
+        app.select_whole_screen();
+        app.start_region_selection(None);
+        assert_eq!(app.selected_region(), Some(Rect::from_min_size(Pos2::new(8.0, 0.0), Vec2::new(48.0, 48.0))));
+        app.confirm_region_selection().unwrap();
+        assert_eq!(app.image_size(), Some(Vec2::new(48.0, 48.0)));
     }
 
-    /// Draw selection handles around a rectangle
-    fn draw_selection_handles(&self, ui: &mut egui::Ui, rect: Rect) {
-        let handle_size = 6.0;
-        let handle_color = egui::Color32::BLUE;
-        
-        let corners = [
-            rect.min,
-            Pos2::new(rect.max.x, rect.min.y),
-            rect.max,
-            Pos2::new(rect.min.x, rect.max.y),
+    #[test]
+    fn test_region_selection_keyboard() {
+        // Modifiers are reset on the first frame, when the window gains focus
+        let ctx = Context::default();
+        let _ = ctx.run(egui::RawInput::default(), |_| {});
+        let press = |app: &mut EditorApp, key: egui::Key, modifiers: egui::Modifiers| {
+            let input = egui::RawInput {
+                events: vec![egui::Event::Key { key, pressed: true, repeat: false, modifiers }],
+                modifiers,
+                ..Default::default()
+            };
+            let _ = ctx.run(input, |ctx| app.handle_shortcuts(ctx));
+        };
+        let rect = |x: f32, y: f32, width: f32, height: f32| Rect::from_min_size(Pos2::new(x, y), Vec2::new(width, height));
+        let mut app = EditorApp::new();
+        let screen = ScreenInfo {
+            index: 0,
+            bounds: Rect::from_min_size(Pos2::ZERO, Vec2::new(64.0, 48.0)),
+            dpi_scale_x: 1.0,
+            dpi_scale_y: 1.0,
+            is_primary: true,
+        };
+        app.load_capture(DynamicImage::ImageRgba8(RgbaImage::new(64, 48)), screen.clone()).unwrap();
+        app.start_region_selection(None);
+        app.set_selected_region(rect(10.0, 10.0, 20.0, 10.0));
+
+        press(&mut app, egui::Key::ArrowRight, egui::Modifiers::NONE);
+        press(&mut app, egui::Key::ArrowDown, egui::Modifiers::COMMAND);
+        assert_eq!(app.selected_region(), Some(rect(11.0, 20.0, 20.0, 10.0)));
+        press(&mut app, egui::Key::ArrowLeft, egui::Modifiers::SHIFT);
+        assert_eq!(app.selected_region(), Some(rect(11.0, 20.0, 19.0, 10.0)));
+
+        // Tab picks the left edge, then the top edge
+        press(&mut app, egui::Key::Tab, egui::Modifiers::NONE);
+        press(&mut app, egui::Key::ArrowLeft, egui::Modifiers::NONE);
+        assert_eq!(app.selected_region(), Some(rect(10.0, 20.0, 20.0, 10.0)));
+        press(&mut app, egui::Key::Tab, egui::Modifiers::NONE);
+        press(&mut app, egui::Key::ArrowDown, egui::Modifiers::NONE);
+        assert_eq!(app.selected_region(), Some(rect(10.0, 21.0, 20.0, 9.0)));
+
+        press(&mut app, egui::Key::Enter, egui::Modifiers::NONE);
+        assert!(!app.is_selecting_region());
+        assert_eq!(app.image_size(), Some(Vec2::new(20.0, 9.0)));
+
+        // Escape throws the capture away
+        app.load_capture(DynamicImage::ImageRgba8(RgbaImage::new(64, 48)), screen).unwrap();
+        app.start_region_selection(None);
+        assert_eq!(app.tab_count(), 2);
+        press(&mut app, egui::Key::Escape, egui::Modifiers::NONE);
+        assert!(!app.is_selecting_region());
+        assert_eq!(app.tab_count(), 1);
+        assert_eq!(app.image_size(), Some(Vec2::new(20.0, 9.0)));
+    }
+
+    #[test]
+    fn test_undo_save_waits_for_upload() {
+        let dir = std::env::temp_dir().join(format!("lsa-editor-undo-upload-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let saved = dir.join("shot.png");
+        std::fs::write(&saved, b"png").unwrap();
+
+        let mut app = EditorApp::new();
+        let frame = CapturedFrame {
+            image: RgbaImage::new(4, 3),
+            screen: ScreenInfo {
+                index: 0,
+                bounds: Rect::from_min_size(Pos2::ZERO, Vec2::new(4.0, 3.0)),
+                dpi_scale_x: 1.0,
+                dpi_scale_y: 1.0,
+                is_primary: true,
+            },
+            window_title: None,
+            application: None,
+            icc_profile: None,
+        };
+        let toast = CaptureToast::new("Upload", "Saved".to_string(), Some(saved.clone()), None);
+        app.silent_capture = Some(SilentCapture { toast, frame, history_id: None, texture: None });
+        let (sender, receiver) = crossbeam_channel::bounded(1);
+        app.pending_uploads.push(PendingUpload { path: saved.clone(), history_id: None, receiver });
+
+        // The file stays, and so does the toast, while the upload reads it
+        let ctx = Context::default();
+        assert!(app.run_capture_action(&ctx, CaptureAction::UndoSave).is_err());
+        assert!(saved.exists());
+        assert!(app.silent_capture.is_some());
+
+        sender.send(None).unwrap();
+        app.poll_uploads();
+        app.run_capture_action(&ctx, CaptureAction::UndoSave).unwrap();
+        assert!(!saved.exists());
+        assert!(app.silent_capture.is_none());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_capture_preset_saves_without_opening() {
+        use crate::capture::mock::{MockBackend, MockScreen};
+
+        let dir = std::env::temp_dir().join(format!("lsa-editor-preset-{}", Uuid::new_v4()));
+        let backend = MockBackend::new(vec![MockScreen::new(64, 48), MockScreen { x: 64, ..MockScreen::new(32, 32) }]);
+        let mut app = EditorApp::new();
+        app.set_capture_service(CaptureService::with_backend(backend).unwrap());
+        app.settings.settings_mut().default_save_directory = Some(dir.to_string_lossy().into_owned());
+        app.settings.settings_mut().capture_presets = vec![
+            CapturePreset::default(),
+            CapturePreset {
+                name: "Region".to_string(),
+                target: PresetTarget::Region { x: 70, y: 2, width: 10, height: 8 },
+                output: PresetOutput::AutoSave,
+                format: ImageFormat::Jpg,
+                file_naming: FileNameTemplate {
+                    template: "region".to_string(),
+                    ..FileNameTemplate::default()
+                },
+                hotkey: Some(Hotkey { modifiers: 0, vk_code: 0x78 }),
+                ..CapturePreset::default()
+            },
         ];
-        
-        for corner in corners {
-            let handle_rect = Rect::from_center_size(corner, Vec2::splat(handle_size));
-            ui.painter().rect_filled(handle_rect, 2.0, handle_color);
-            ui.painter().rect_stroke(handle_rect, 2.0, egui::Stroke::new(1.0, egui::Color32::WHITE));
+        assert!(app.run_capture_preset(5).is_err());
+
+        let preset_hotkey = hotkeys::configured_hotkeys(app.settings.get_settings())[4].clone();
+        app.event_sender().send(AppEvent::Hotkey(preset_hotkey));
+        app.poll_events();
+        let started = Instant::now();
+        while app.is_capturing() && started.elapsed() < Duration::from_secs(10) {
+            std::thread::sleep(Duration::from_millis(1));
+            app.poll_pending_capture();
         }
-    }
 
-    /// Constrain pan offset to keep at least part of the image visible
-    fn constrain_pan_offset(&self, pan_offset: Vec2, available_rect: Rect) -> Vec2 {
-        if let Some(ref texture) = self.texture {
-            let original_size = texture.size_vec2();
-            let display_size = original_size * self.zoom_level as f32;
-            
-            // Calculate the bounds for the pan offset
-            let min_visible_size = 50.0; // Keep at least 50 pixels of the image visible
-            
-            let max_pan_x = (available_rect.width() - min_visible_size).max(0.0);
-            let min_pan_x = -(display_size.x - min_visible_size).max(0.0);
-            
-            let max_pan_y = (available_rect.height() - min_visible_size).max(0.0);
-            let min_pan_y = -(display_size.y - min_visible_size).max(0.0);
-            
-            Vec2::new(
-                pan_offset.x.clamp(min_pan_x, max_pan_x),
-                pan_offset.y.clamp(min_pan_y, max_pan_y)
-            )
-        } else {
-            pan_offset
+        // Saved with the preset's name and format, and the editor stays empty
+        let saved = dir.join("region.jpg");
+        assert_eq!(app.last_capture_path.as_deref(), Some(saved.as_path()));
+        assert_eq!(image::open(&saved).unwrap().to_rgba8().dimensions(), (10, 8));
+        assert!(app.doc.is_empty());
+        assert_eq!(app.notifications.history().next().unwrap().title, "Region");
+
+        // The capture toast offers to copy the path, and to undo the save
+        let ctx = Context::default();
+        let toast = &app.silent_capture.as_ref().unwrap().toast;
+        assert_eq!((toast.title.as_str(), toast.path.as_deref()), ("Region", Some(saved.as_path())));
+        assert_eq!(toast.thumbnail.as_ref().unwrap().dimensions(), (10, 8));
+        app.run_capture_action(&ctx, CaptureAction::UndoSave).unwrap();
+        assert!(!saved.exists());
+        assert!(app.silent_capture.is_none());
+        assert_eq!(app.last_capture_path, None);
+        assert_eq!(app.notifications.toasts().last().unwrap().title, "Save Undone");
+
+        // An area preset is placed on the second monitor when it runs
+        app.settings.settings_mut().capture_presets[0].target = PresetTarget::Area {
+            monitor: MonitorAnchor::Monitor(1),
+            placement: AreaPlacement::RIGHT_HALF,
+        };
+        app.run_capture_preset(0).unwrap();
+        let started = Instant::now();
+        while app.is_capturing() && started.elapsed() < Duration::from_secs(10) {
+            std::thread::sleep(Duration::from_millis(1));
+            app.poll_pending_capture();
         }
+        assert_eq!(app.image_size(), Some(Vec2::new(16.0, 32.0)));
+
+        std::fs::remove_dir_all(&dir).ok();
     }
 
-    /// Draw info overlay showing zoom and pan information
-    fn draw_info_overlay(&self, ui: &mut egui::Ui, available_rect: Rect) {
-        if self.zoom_level != 1.0 || self.pan_offset != Vec2::ZERO {
-            let overlay_pos = available_rect.min + Vec2::new(10.0, 10.0);
-            let info_text = format!(
-                "Zoom: {:.0}%{}",
-                self.zoom_level * 100.0,
-                if self.pan_offset != Vec2::ZERO {
-                    format!(" | Pan: ({:.0}, {:.0})", self.pan_offset.x, self.pan_offset.y)
-                } else {
-                    String::new()
-                }
-            );
-            
-            // Draw background
-            let text_size = ui.painter().layout_no_wrap(
-                info_text.clone(),
-                egui::FontId::proportional(12.0),
-                egui::Color32::WHITE,
-            ).size();
-            
-            let bg_rect = Rect::from_min_size(
-                overlay_pos,
-                text_size + Vec2::splat(8.0),
-            );
-            
-            ui.painter().rect_filled(
-                bg_rect,
-                4.0,
-                egui::Color32::from_black_alpha(180),
-            );
-            
-            // Draw text
-            ui.painter().text(
-                overlay_pos + Vec2::splat(4.0),
-                egui::Align2::LEFT_TOP,
-                info_text,
-                egui::FontId::proportional(12.0),
-                egui::Color32::WHITE,
-            );
+    #[test]
+    fn test_low_resource_mode_skips_capture_thumbnails() {
+        use crate::capture::mock::{MockBackend, MockScreen};
+
+        let dir = std::env::temp_dir().join(format!("lsa-editor-low-thumbnails-{}", Uuid::new_v4()));
+        let mut app = EditorApp::new();
+        app.set_capture_service(CaptureService::with_backend(MockBackend::new(vec![MockScreen::new(32, 24)])).unwrap());
+        app.enable_capture_history(dir.join("history.jsonl"));
+        app.set_low_resource_mode(LowResourceMode::On);
+        app.settings.settings_mut().default_save_directory = Some(dir.to_string_lossy().into_owned());
+        app.settings.settings_mut().capture_presets =
+            vec![CapturePreset { name: "Saved".to_string(), output: PresetOutput::AutoSave, ..CapturePreset::default() }];
+        app.run_capture_preset(0).unwrap();
+        let started = Instant::now();
+        while app.is_capturing() && started.elapsed() < Duration::from_secs(10) {
+            std::thread::sleep(Duration::from_millis(1));
+            app.poll_pending_capture();
         }
+
+        // Recorded and toasted without a picture
+        assert!(app.silent_capture.as_ref().unwrap().toast.thumbnail.is_none());
+        let entry = app.capture_history().unwrap().entries()[0].clone();
+        assert!(app.capture_history().unwrap().load_thumbnail(entry.id).is_none());
+        assert!(!app.capture_history().unwrap().thumbnail_path(entry.id).exists());
+
+        std::fs::remove_dir_all(&dir).ok();
     }
-}
 
-impl eframe::App for EditorApp {
-    fn update(&mut self, ctx: &Context, _frame: &mut eframe::Frame) {
-        // Handle close request
-        if self.should_close {
-            ctx.send_viewport_cmd(egui::ViewportCommand::Close);
-            return;
+    #[test]
+    fn test_interval_capture_skips_unchanged_frames() {
+        use crate::capture::mock::{MockBackend, MockScreen};
+
+        let dir = std::env::temp_dir().join(format!("lsa-editor-interval-{}", Uuid::new_v4()));
+        let mut app = EditorApp::new();
+        app.set_capture_service(CaptureService::with_backend(MockBackend::new(vec![MockScreen::new(32, 24)])).unwrap());
+        app.settings.settings_mut().interval_capture = IntervalSettings {
+            interval_secs: 5,
+            directory: Some(dir.clone()),
+            ..IntervalSettings::default()
+        };
+        app.event_sender().send(AppEvent::IntervalCapture(true));
+        app.poll_events();
+        assert!(app.is_interval_capture_running());
+
+        let started = Instant::now();
+        let take_frame = |app: &mut EditorApp, now: Instant| {
+            app.poll_interval_capture(now);
+            while app.interval_capture.is_some() && started.elapsed() < Duration::from_secs(10) {
+                std::thread::sleep(Duration::from_millis(1));
+                app.poll_interval_capture(now);
+            }
+        };
+        take_frame(&mut app, started);
+        let folder = app.interval_run.as_ref().unwrap().folder().to_path_buf();
+        assert_eq!(folder.parent(), Some(dir.as_path()));
+        assert_eq!(image::open(folder.join("00001.png")).unwrap().to_rgba8().dimensions(), (32, 24));
+
+        // The screen hasn't changed, so the next frame is skipped
+        take_frame(&mut app, started + Duration::from_secs(5));
+        assert_eq!(app.interval_run.as_ref().unwrap().skipped(), 1);
+        // Nothing is captured while the trigger is paused
+        app.set_trigger_enabled(Trigger::IntervalCapture, false);
+        app.poll_interval_capture(started + Duration::from_secs(10));
+        assert!(app.interval_capture.is_none());
+
+        app.event_sender().send(AppEvent::IntervalCapture(false));
+        app.poll_events();
+        assert!(!app.is_interval_capture_running());
+        assert_eq!(app.notifications.toasts().last().unwrap().title, "Interval Capture Stopped");
+        assert_eq!(std::fs::read_dir(&folder).unwrap().count(), 1);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_run_post_capture_actions() {
+        struct Measure;
+        impl PostCaptureAction for Measure {
+            fn name(&self) -> &str {
+                "Measure"
+            }
+
+            fn run(&self, input: plugins::ActionInput) -> AppResult<plugins::ActionOutput> {
+                Ok(plugins::ActionOutput {
+                    url: None,
+                    message: Some(format!("{}x{}", input.image.width(), input.image.height())),
+                })
+            }
         }
 
-        // Draw UI components
-        self.draw_menu_bar(ctx);
-        self.draw_tool_panel(ctx);
-        self.draw_canvas(ctx);
+        let mut app = EditorApp::new();
+        app.plugins_mut().register(Arc::new(Measure)).unwrap();
+        let actions = app.plugins.actions().to_vec();
+        assert!(app.run_actions(actions.clone()).is_err());
 
-        // Request repaint for smooth interaction
-        ctx.request_repaint();
+        app.load_test_image().unwrap();
+        app.run_actions(actions).unwrap();
+        let started = Instant::now();
+        while app.has_pending_actions() && started.elapsed() < Duration::from_secs(10) {
+            std::thread::sleep(Duration::from_millis(1));
+            app.poll_actions();
+        }
+        let toast = app.notifications.toasts().last().unwrap();
+        assert_eq!((toast.title.as_str(), toast.message.as_str()), ("Measure", "400x300"));
     }
 
+    #[test]
+    fn test_save_as_remembers_destination() {
+        let dir = std::env::temp_dir().join(format!("lsa-editor-save-as-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let destination =
+            SaveDestination::new("Share", RemoteLocation::NetworkShare { path: dir.clone(), username: String::new() });
+        let id = destination.id;
+        let mut app = EditorApp::new();
+        app.settings.settings_mut().save_destinations = vec![destination];
+        app.load_test_image().unwrap();
 
-}
+        app.open_save_as();
+        let mut draft = app.save_as.take().unwrap();
+        assert_eq!(draft.destination, None);
+        draft.destination = Some(id);
+        draft.file_name = "remote.png".to_string();
+        app.submit_save_as(&draft).unwrap();
+        assert!(app.has_pending_saves());
+        let started = Instant::now();
+        while app.has_pending_saves() && started.elapsed() < Duration::from_secs(10) {
+            std::thread::sleep(Duration::from_millis(1));
+            app.poll_transfers();
+        }
+        assert!(dir.join("remote.png").exists());
+        assert_eq!(app.notifications.toasts().last().unwrap().title, "Saved");
+        // A remote copy doesn't become the document's file
+        assert!(app.file_path().is_none());
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        app.open_save_as();
+        assert_eq!(app.save_as.as_ref().unwrap().destination, Some(id));
+        std::fs::remove_dir_all(&dir).ok();
+    }
 
     #[test]
-    fn test_editor_app_creation() {
-        let app = EditorApp::new();
-        assert!(app.source_image.is_none());
-        assert!(app.texture.is_none());
-        assert!(app.annotations.is_empty());
-        assert_eq!(app.current_tool, Tool::Select);
-        assert_eq!(app.zoom_level, 1.0);
-        assert_eq!(app.pan_offset, Vec2::ZERO);
-        assert!(!app.should_close);
-        assert!(!app.is_panning);
-        assert!(app.last_mouse_pos.is_none());
+    fn test_encrypted_export_round_trip() {
+        let dir = std::env::temp_dir().join(format!("lsa-editor-encrypted-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut app = EditorApp::new();
+        app.load_test_image().unwrap();
+        let path = dir.join("secret.zip");
+        assert!(app.export_encrypted(&path, "short").is_err());
+        app.export_encrypted(&path, "correct horse").unwrap();
+
+        let mut other = EditorApp::new();
+        assert!(other.open_encrypted(&path, "wrong password").is_err());
+        other.open_encrypted(&path, "correct horse").unwrap();
+        assert_eq!(other.image_size(), Some(Vec2::new(400.0, 300.0)));
+        // Saving must not overwrite the container with the plain image
+        assert!(other.file_path().is_none());
+
+        // Opening the container like an image asks for its password
+        other.open_and_report(&path);
+        assert_eq!(other.encrypted_open.as_ref().map(|(path, _)| PathBuf::from(path)), Some(path));
+        std::fs::remove_dir_all(&dir).ok();
     }
 
     #[test]
-    fn test_editor_app_default() {
-        let app = EditorApp::default();
-        assert!(app.source_image.is_none());
-        assert_eq!(app.current_tool, Tool::Select);
-        assert_eq!(app.zoom_level, 1.0);
-        assert_eq!(app.pan_offset, Vec2::ZERO);
+    fn test_privacy_findings_blur_and_release() {
+        let mut app = EditorApp::new();
+        app.load_test_image().unwrap();
+        let before = app.flattened_image().unwrap();
+        let bounds = Rect::from_min_size(Pos2::new(40.0, 40.0), Vec2::new(120.0, 16.0));
+        let finding = Finding { kind: privacy::FindingKind::Email, text: "taro@example.com".to_string(), bounds };
+        let (sender, receiver) = crossbeam_channel::bounded(1);
+        sender.send(Ok(vec![finding.clone(), finding])).unwrap();
+        app.privacy_check = Some(PrivacyCheck {
+            session_id: app.doc.session_id,
+            output: GuardedOutput::Actions(Vec::new()),
+            receiver: Some(receiver),
+            findings: Vec::new(),
+        });
+
+        // The output waits while findings are shown
+        app.poll_privacy_check();
+        assert_eq!(app.privacy_findings().len(), 2);
+        app.blur_finding(0);
+        assert_eq!(app.privacy_findings().len(), 1);
+        assert!(app.has_unsaved_changes());
+        assert_ne!(app.flattened_image().unwrap().as_raw(), before.as_raw());
+
+        app.resolve_privacy_check(true);
+        assert!(app.privacy_check.is_none());
+        assert!(app.privacy_findings().is_empty());
     }
 
     #[test]
-    fn test_tool_management() {
+    fn test_share_without_secret_reports_failure() {
         let mut app = EditorApp::new();
-        
-        // Test initial tool
-        assert_eq!(app.current_tool(), &Tool::Select);
-        
-        // Test setting tools
-        app.set_tool(Tool::Rectangle);
-        assert_eq!(app.current_tool(), &Tool::Rectangle);
-        
-        app.set_tool(Tool::Text);
-        assert_eq!(app.current_tool(), &Tool::Text);
+        app.load_test_image().unwrap();
+        // No secret is stored for a new target, so it fails before sending anything
+        let target = ShareTarget::new("Team", ShareService::Discord);
+        app.share(&target).unwrap();
+        let started = Instant::now();
+        while app.has_pending_actions() && started.elapsed() < Duration::from_secs(10) {
+            std::thread::sleep(Duration::from_millis(1));
+            app.poll_actions();
+        }
+        let toast = app.notifications.toasts().last().unwrap();
+        assert_eq!(toast.severity, Severity::Error);
+        assert!(toast.message.contains("Team"));
     }
 
     #[test]
-    fn test_close_functionality() {
+    fn test_export_step_animation() {
+        let dir = std::env::temp_dir().join(format!("lsa-editor-steps-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
         let mut app = EditorApp::new();
-        
-        // Initially should not close
-        assert!(!app.should_close());
-        
-        // Request close
-        app.request_close();
-        assert!(app.should_close());
+        app.load_test_image().unwrap();
+        let path = dir.join("steps.gif");
+        assert!(app.export_step_animation(&path, &ExportOptions::default()).is_err());
+
+        // Each assignment starts a new step
+        assert_eq!(app.assign_next_step(), None);
+        for x in [10.0, 60.0, 110.0] {
+            let annotation = AnnotationItem::new_rectangle(Pos2::new(x, 10.0), Vec2::new(30.0, 30.0));
+            let id = annotation.id;
+            app.add_annotation(annotation);
+            app.select_annotation(Some(id));
+            if x < 100.0 {
+                app.assign_next_step();
+            }
+        }
+        let steps: Vec<Option<u32>> = app.annotations().iter().map(|a| a.step).collect();
+        assert_eq!(steps, vec![Some(1), Some(2), None]);
+
+        // The untagged frame plus one per step, resized like still exports
+        let options = ExportOptions { resize: ResizeMode::Percentage(50.0), ..Default::default() };
+        assert_eq!(app.export_step_animation(&path, &options).unwrap(), 3);
+        let decoder = image::codecs::gif::GifDecoder::new(std::fs::File::open(&path).unwrap()).unwrap();
+        let frames = image::AnimationDecoder::into_frames(decoder).collect_frames().unwrap();
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[0].buffer().dimensions(), (200, 150));
+        std::fs::remove_dir_all(&dir).ok();
     }
 
     #[test]
-    fn test_load_image() {
+    fn test_export_embeds_metadata() {
+        let dir = std::env::temp_dir().join(format!("lsa-editor-metadata-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let screen = ScreenInfo {
+            index: 1,
+            bounds: Rect::from_min_size(Pos2::new(1920.0, 0.0), Vec2::new(1280.0, 1024.0)),
+            dpi_scale_x: 1.0,
+            dpi_scale_y: 1.0,
+            is_primary: false,
+        };
         let mut app = EditorApp::new();
-        
-        // Create a test image
-        let test_image = DynamicImage::new_rgb8(100, 100);
-        
-        // Load the image
-        let result = app.load_image(test_image);
-        assert!(result.is_ok());
-        assert!(app.source_image.is_some());
-        
-        // Check that view state is reset
-        assert_eq!(app.zoom_level, 1.0);
-        assert_eq!(app.pan_offset, Vec2::ZERO);
+        app.load_capture(DynamicImage::ImageRgba8(RgbaImage::new(8, 8)), screen).unwrap();
+        app.doc.window_title = Some("Terminal".to_string());
+        app.set_comment("  Release candidate  ");
+        assert!(app.is_dirty());
+
+        let metadata = app.image_metadata();
+        assert_eq!(metadata.monitor.as_deref(), Some("Monitor 2 (1280x1024 at (1920, 0))"));
+        assert_eq!(metadata.comment.as_deref(), Some("Release candidate"));
+
+        let contains = |data: &[u8], text: &str| data.windows(text.len()).any(|window| window == text.as_bytes());
+        let path = dir.join("with.png");
+        app.export_to(&path, &ExportOptions::default()).unwrap();
+        let data = std::fs::read(&path).unwrap();
+        assert!(contains(&data, "Terminal") && contains(&data, "Release candidate"));
+
+        let path = dir.join("stripped.jpg");
+        app.export_to(&path, &ExportOptions { strip_metadata: true, ..Default::default() }).unwrap();
+        let data = std::fs::read(&path).unwrap();
+        assert!(!contains(&data, "Exif") && !contains(&data, "Terminal"));
+        assert!(image::load_from_memory(&data).is_ok());
+
+        std::fs::remove_dir_all(&dir).ok();
     }
 
     #[test]
-    fn test_load_test_image() {
+    fn test_automation_requests() {
+        use crate::capture::mock::{MockBackend, MockScreen};
+
+        let dir = std::env::temp_dir().join(format!("lsa-editor-automation-{}", Uuid::new_v4()));
         let mut app = EditorApp::new();
-        
-        // Load test image
-        let result = app.load_test_image();
-        assert!(result.is_ok());
-        assert!(app.source_image.is_some());
-        
-        // Verify the test image has expected dimensions
-        if let Some(ref image) = app.source_image {
-            assert_eq!(image.width(), 400);
-            assert_eq!(image.height(), 300);
+        app.set_capture_service(CaptureService::with_backend(MockBackend::new(vec![MockScreen::new(64, 48)])).unwrap());
+        app.settings.settings_mut().default_save_directory = Some(dir.to_string_lossy().into_owned());
+        app.settings.settings_mut().auto_save_captures = true;
+
+        let request = |app: &mut EditorApp, request: AutomationRequest| {
+            let (reply, receiver) = crossbeam_channel::bounded(1);
+            app.event_sender().send(AppEvent::Automation(AutomationCall::new(request, reply)));
+            app.poll_events();
+            receiver
+        };
+
+        let capture = request(&mut app, AutomationRequest::capture(CaptureTarget::Screen));
+        assert!(capture.try_recv().is_err());
+        let started = Instant::now();
+        while app.is_capturing() && started.elapsed() < Duration::from_secs(10) {
+            std::thread::sleep(Duration::from_millis(1));
+            app.poll_pending_capture();
         }
+        let result = capture.try_recv().unwrap().unwrap();
+        assert_eq!((result["width"].as_u64(), result["height"].as_u64()), (Some(64), Some(48)));
+        let saved = PathBuf::from(result["path"].as_str().unwrap());
+        assert!(saved.starts_with(&dir));
+
+        let last = request(&mut app, AutomationRequest::LastCapture).try_recv().unwrap().unwrap();
+        assert_eq!(last["path"].as_str(), saved.to_str());
+
+        let exported = dir.join("half.png");
+        let options = ExportOptions { resize: ResizeMode::Percentage(50.0), ..Default::default() };
+        let export = AutomationRequest::Export { path: exported.clone(), options: Some(Box::new(options)) };
+        let result = request(&mut app, export).try_recv().unwrap().unwrap();
+        assert_eq!(result["width"].as_u64(), Some(32));
+        assert_eq!(image::open(&exported).unwrap().to_rgba8().dimensions(), (32, 24));
+        // Exporting leaves the save target alone
+        assert_eq!(app.file_path(), Some(saved.as_path()));
+
+        let missing = AutomationRequest::Open { path: dir.join("missing.png") };
+        assert!(request(&mut app, missing).try_recv().unwrap().is_err());
+
+        let selector = WindowSelector { title: Some("Calculator".to_string()), process: None };
+        let error = request(&mut app, AutomationRequest::capture_window(selector)).try_recv().unwrap().unwrap_err();
+        assert_eq!(error.code, "window_not_found");
+
+        std::fs::remove_dir_all(&dir).ok();
     }
 
     #[test]
-    fn test_zoom_and_pan_state() {
+    fn test_background_start_waits_for_capture() {
+        let ctx = Context::default();
         let mut app = EditorApp::new();
-        
-        // Test initial state
-        assert_eq!(app.zoom_level, 1.0);
-        assert_eq!(app.pan_offset, Vec2::ZERO);
-        
-        // Modify zoom and pan (simulating user interaction)
-        app.zoom_level = 2.0;
-        app.pan_offset = Vec2::new(10.0, 20.0);
-        
-        // Load new image should reset view state
-        let test_image = DynamicImage::new_rgb8(100, 100);
-        let result = app.load_image(test_image);
-        assert!(result.is_ok());
-        assert_eq!(app.zoom_level, 1.0);
-        assert_eq!(app.pan_offset, Vec2::ZERO);
+        app.start_in_background();
+
+        app.reveal_window(&ctx);
+        assert!(app.hidden_until_capture);
+
+        app.load_test_image().unwrap();
+        app.reveal_window(&ctx);
+        assert!(!app.hidden_until_capture);
     }
-}
\ No newline at end of file
+}
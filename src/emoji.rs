@@ -0,0 +1,91 @@
+//! Emoji and symbol picker
+//!
+//! The picker offers emoji and symbols by category, for inserting into a
+//! text annotation or for the sticker tool, which stamps the chosen one as an
+//! annotation of its own where the canvas is clicked. Every entry is a single
+//! character drawn by the built-in fonts; the installed emoji fonts in
+//! [`text`](crate::text)'s fallback chain cover the rest. egui draws glyphs
+//! in one color, so emoji take the annotation's text color.
+
+/// Emoji and symbols shown together in the picker
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EmojiCategory {
+    pub name: &'static str,
+    pub symbols: &'static [&'static str],
+}
+
+/// Picker categories, in the order they are shown
+pub const CATEGORIES: [EmojiCategory; 6] = [
+    EmojiCategory {
+        name: "Smileys",
+        symbols: &[
+            "😀", "😃", "😄", "😁", "😆", "😅", "😂", "😉", "😊", "😍", "😘", "😎", "😐", "😑", "😶", "😏",
+            "😮", "😲", "😴", "😢", "😭", "😡", "😱", "😇",
+        ],
+    },
+    EmojiCategory {
+        name: "Hands",
+        symbols: &["👍", "👎", "👌", "✌", "👏", "🙌", "🙏", "👋", "👉", "👈", "👆", "👇", "☝", "✋", "💪"],
+    },
+    EmojiCategory {
+        name: "Marks",
+        symbols: &[
+            "✅", "❌", "❗", "❓", "⚠", "⛔", "🚫", "✔", "✖", "★", "☆", "♥", "💡", "🔥", "⭐", "💯", "🔔", "🔒",
+            "🔓", "⏰", "⏳", "👀",
+        ],
+    },
+    EmojiCategory {
+        name: "Arrows",
+        symbols: &["⬅", "➡", "⬆", "⬇", "↖", "↗", "↘", "↙", "↔", "↕", "↩", "↪", "⤴", "⤵", "▶", "◀", "🔼", "🔽", "🔄", "🔁"],
+    },
+    EmojiCategory {
+        name: "Shapes",
+        symbols: &["🔴", "🔵", "⚫", "⚪", "⬛", "⬜", "◼", "■", "○", "🔶", "🔷", "🔸", "🔹", "🔺", "🔻"],
+    },
+    EmojiCategory {
+        name: "Objects",
+        symbols: &[
+            "📌", "📍", "📎", "📝", "📋", "📁", "📂", "📷", "💻", "📱", "🔍", "🔧", "🐛", "🚀", "🎉", "📈", "📉",
+        ],
+    },
+];
+
+/// Symbol the sticker tool stamps until another is picked
+pub const DEFAULT_STICKER: &str = "👍";
+
+/// Insert a symbol into text at a character index, returning the character index after it
+///
+/// Indices past the end insert at the end.
+pub fn insert_at(text: &mut String, char_index: usize, symbol: &str) -> usize {
+    let byte_index = text.char_indices().nth(char_index).map_or(text.len(), |(index, _)| index);
+    text.insert_str(byte_index, symbol);
+    text[..byte_index].chars().count() + symbol.chars().count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use egui::epaint::text::{FontDefinitions, Fonts};
+    use egui::FontId;
+
+    #[test]
+    fn test_symbols_have_builtin_glyphs() {
+        let fonts = Fonts::new(1.0, 2048, FontDefinitions::default());
+        let font = FontId::proportional(14.0);
+        for category in CATEGORIES {
+            for symbol in category.symbols {
+                assert_eq!(symbol.chars().count(), 1, "{}", symbol);
+                assert!(fonts.has_glyphs(&font, symbol), "{} in {}", symbol, category.name);
+            }
+        }
+    }
+
+    #[test]
+    fn test_insert_at() {
+        let mut text = "手順 2".to_string();
+        assert_eq!(insert_at(&mut text, 2, "👉"), 3);
+        assert_eq!(text, "手順👉 2");
+        assert_eq!(insert_at(&mut text, 99, "✅"), 6);
+        assert_eq!(text, "手順👉 2✅");
+    }
+}
@@ -0,0 +1,219 @@
+//! Application event bus
+//!
+//! Background producers such as the hotkey thread, the control and
+//! automation servers, and the command line don't touch editor state
+//! directly. They post [`AppEvent`]s through an [`EventSender`], which also
+//! wakes the egui loop so events are handled even while the window is idle.
+//! Each frame the
+//! [`AppController`] drains the bus, drops events of paused triggers, and
+//! hands the rest to the editor.
+
+use crate::automation::AutomationCall;
+use crate::control::{SharedTriggers, Trigger, TriggerState};
+use crate::types::HotkeyEvent;
+use crossbeam_channel::{Receiver, Sender};
+use std::path::PathBuf;
+use std::sync::{Arc, OnceLock};
+
+/// Callback waking the UI loop
+type Waker = Box<dyn Fn() + Send + Sync>;
+
+/// An event posted to the editor from another subsystem
+#[derive(Debug, Clone, PartialEq)]
+pub enum AppEvent {
+    /// A global hotkey was pressed
+    Hotkey(HotkeyEvent),
+    /// A control command changed which triggers are enabled
+    TriggersChanged(TriggerState),
+    /// Open an image or project file, e.g. one passed on the command line
+    OpenFile(PathBuf),
+    /// A request from an external tool, answered by the editor
+    Automation(AutomationCall),
+    /// A control command started (`true`) or stopped interval capture
+    IntervalCapture(bool),
+}
+
+impl AppEvent {
+    /// Get the capture trigger the event comes from, if it can be paused
+    pub fn trigger(&self) -> Option<Trigger> {
+        match self {
+            AppEvent::Hotkey(_) => Some(Trigger::Hotkeys),
+            AppEvent::TriggersChanged(_)
+            | AppEvent::OpenFile(_)
+            | AppEvent::Automation(_)
+            | AppEvent::IntervalCapture(_) => None,
+        }
+    }
+}
+
+/// Handle for posting events from any thread
+#[derive(Clone)]
+pub struct EventSender {
+    sender: Sender<AppEvent>,
+    waker: Arc<OnceLock<Waker>>,
+}
+
+impl std::fmt::Debug for EventSender {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EventSender").field("has_waker", &self.waker.get().is_some()).finish()
+    }
+}
+
+impl EventSender {
+    /// Post an event and wake the UI loop, returning false if the bus is gone
+    pub fn send(&self, event: AppEvent) -> bool {
+        if self.sender.send(event).is_err() {
+            return false;
+        }
+        if let Some(wake) = self.waker.get() {
+            wake();
+        }
+        true
+    }
+}
+
+/// Channel carrying events to the UI thread
+#[derive(Debug)]
+pub struct EventBus {
+    sender: EventSender,
+    receiver: Receiver<AppEvent>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        Self {
+            sender: EventSender {
+                sender,
+                waker: Arc::new(OnceLock::new()),
+            },
+            receiver,
+        }
+    }
+}
+
+impl EventBus {
+    /// Create an empty bus
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get a handle for posting events
+    pub fn sender(&self) -> EventSender {
+        self.sender.clone()
+    }
+
+    /// Set the callback waking the UI loop when an event is posted
+    ///
+    /// Only the first waker is kept; the UI context doesn't change once the
+    /// window is up.
+    pub fn set_waker(&self, wake: impl Fn() + Send + Sync + 'static) {
+        let _ = self.sender.waker.set(Box::new(wake));
+    }
+
+    /// Take the events posted since the last call, oldest first
+    pub fn drain(&self) -> Vec<AppEvent> {
+        self.receiver.try_iter().collect()
+    }
+}
+
+/// Routes events from the bus to the editor
+///
+/// Policy shared by all producers lives here rather than in each of them:
+/// events from paused triggers are dropped no matter where they came from.
+#[derive(Debug)]
+pub struct AppController {
+    bus: EventBus,
+    triggers: SharedTriggers,
+}
+
+impl AppController {
+    /// Create a controller filtering events by the given trigger state
+    pub fn new(triggers: SharedTriggers) -> Self {
+        Self {
+            bus: EventBus::new(),
+            triggers,
+        }
+    }
+
+    /// Get a handle for posting events
+    pub fn sender(&self) -> EventSender {
+        self.bus.sender()
+    }
+
+    /// Set the callback waking the UI loop when an event is posted
+    pub fn set_waker(&self, wake: impl Fn() + Send + Sync + 'static) {
+        self.bus.set_waker(wake);
+    }
+
+    /// Take the pending events for the editor, dropping those of paused triggers
+    pub fn poll(&self) -> Vec<AppEvent> {
+        let events = self.bus.drain();
+        if events.is_empty() {
+            return events;
+        }
+        let state = *self.triggers.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        events
+            .into_iter()
+            .filter(|event| match event.trigger() {
+                Some(trigger) if !state.is_enabled(trigger) => {
+                    log::info!("Ignoring {:?} while {} is paused", event, trigger);
+                    false
+                }
+                _ => true,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn hotkey(id: i32) -> AppEvent {
+        AppEvent::Hotkey(HotkeyEvent { id, modifiers: 0x0002, vk_code: 0x53 })
+    }
+
+    #[test]
+    fn test_bus_delivers_in_order_and_wakes() {
+        let bus = EventBus::new();
+        let wakes = Arc::new(AtomicUsize::new(0));
+        let counter = wakes.clone();
+        bus.set_waker(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+
+        let sender = bus.sender();
+        std::thread::spawn(move || {
+            assert!(sender.send(hotkey(1)));
+            assert!(sender.send(AppEvent::OpenFile(PathBuf::from("a.png"))));
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!(bus.drain(), vec![hotkey(1), AppEvent::OpenFile(PathBuf::from("a.png"))]);
+        assert!(bus.drain().is_empty());
+        assert_eq!(wakes.load(Ordering::SeqCst), 2);
+
+        let sender = bus.sender();
+        drop(bus);
+        assert!(!sender.send(hotkey(1)));
+    }
+
+    #[test]
+    fn test_controller_drops_paused_triggers() {
+        let triggers = SharedTriggers::default();
+        let controller = AppController::new(triggers.clone());
+        let sender = controller.sender();
+
+        triggers.lock().unwrap().set_enabled(Trigger::Hotkeys, false);
+        sender.send(hotkey(2));
+        sender.send(AppEvent::OpenFile(PathBuf::from("b.png")));
+        assert_eq!(controller.poll(), vec![AppEvent::OpenFile(PathBuf::from("b.png"))]);
+
+        triggers.lock().unwrap().set_enabled(Trigger::Hotkeys, true);
+        sender.send(hotkey(2));
+        assert_eq!(controller.poll(), vec![hotkey(2)]);
+    }
+}
@@ -0,0 +1,413 @@
+//! Export pipeline
+//!
+//! Exports take the flattened composite from `render`, apply the configured
+//! output transformations (resizing, watermark, then decoration), and encode
+//! the result in the selected file format.
+
+pub mod secure;
+
+use crate::animation::AnimationOptions;
+use crate::decode::DecodeLimits;
+use crate::export::secure::SecureFormat;
+use crate::files;
+use crate::metadata::{self, ImageMetadata};
+use crate::render::{self, CollageOptions, DecorationSettings, MetadataBanner, Watermark};
+use crate::svg::SvgOptions;
+use crate::types::{AppError, AppResult, ImageFormat};
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageOutputFormat, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// Largest percentage accepted by [`ResizeMode::Percentage`]
+const MAX_RESIZE_PERCENTAGE: f32 = 1000.0;
+
+/// How the exported image should be resized
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum ResizeMode {
+    /// Keep the original dimensions
+    #[default]
+    Original,
+    /// Scale both dimensions by a percentage
+    Percentage(f32),
+    /// Shrink to fit within the given bounds, keeping the aspect ratio (0 means unbounded)
+    MaxDimensions { max_width: u32, max_height: u32 },
+    /// Resize to exact dimensions, ignoring the aspect ratio
+    Exact { width: u32, height: u32 },
+}
+
+impl ResizeMode {
+    /// Compute the output size for an image of the given size
+    pub fn target_size(&self, width: u32, height: u32) -> (u32, u32) {
+        let scaled = |scale: f64| {
+            (
+                ((width as f64 * scale).round() as u32).max(1),
+                ((height as f64 * scale).round() as u32).max(1),
+            )
+        };
+
+        match *self {
+            ResizeMode::Original => (width, height),
+            ResizeMode::Percentage(percent) => scaled(percent as f64 / 100.0),
+            ResizeMode::MaxDimensions { max_width, max_height } => {
+                let limit = |max: u32, size: u32| {
+                    if max == 0 || size == 0 { 1.0 } else { max as f64 / size as f64 }
+                };
+                let scale = limit(max_width, width).min(limit(max_height, height));
+                if scale < 1.0 {
+                    scaled(scale)
+                } else {
+                    (width, height)
+                }
+            }
+            ResizeMode::Exact { width, height } => (width, height),
+        }
+    }
+
+    /// Validate resize parameters
+    ///
+    /// Exact sizes are held to the limits for decoding untrusted images, so an
+    /// export never allocates more than an opened image could.
+    pub fn validate(&self) -> AppResult<()> {
+        match *self {
+            ResizeMode::Percentage(percent) if !(percent > 0.0 && percent <= MAX_RESIZE_PERCENTAGE) => Err(
+                AppError::Settings(format!("縮小率は0より大きく{}%以下で指定してください", MAX_RESIZE_PERCENTAGE)),
+            ),
+            ResizeMode::Exact { width, height } if width == 0 || height == 0 => {
+                Err(AppError::Settings("出力サイズは1ピクセル以上で指定してください".to_string()))
+            }
+            ResizeMode::Exact { width, height } => {
+                let limits = DecodeLimits::default();
+                let max_pixels = limits.max_alloc / 4;
+                if width > limits.max_width || height > limits.max_height || width as u64 * height as u64 > max_pixels {
+                    Err(AppError::Settings(format!(
+                        "出力サイズは幅{}、高さ{}ピクセル以下、合計{}ピクセル以下で指定してください",
+                        limits.max_width, limits.max_height, max_pixels
+                    )))
+                } else {
+                    Ok(())
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Resampling filter used when resizing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ResizeFilter {
+    Nearest,
+    Bilinear,
+    #[default]
+    Lanczos3,
+}
+
+impl ResizeFilter {
+    /// Get all filters in display order
+    pub fn all() -> Vec<ResizeFilter> {
+        vec![ResizeFilter::Nearest, ResizeFilter::Bilinear, ResizeFilter::Lanczos3]
+    }
+
+    /// Get the corresponding `image` crate filter
+    pub fn filter_type(&self) -> FilterType {
+        match self {
+            ResizeFilter::Nearest => FilterType::Nearest,
+            ResizeFilter::Bilinear => FilterType::Triangle,
+            ResizeFilter::Lanczos3 => FilterType::Lanczos3,
+        }
+    }
+}
+
+impl std::fmt::Display for ResizeFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResizeFilter::Nearest => write!(f, "Nearest"),
+            ResizeFilter::Bilinear => write!(f, "Bilinear"),
+            ResizeFilter::Lanczos3 => write!(f, "Lanczos3"),
+        }
+    }
+}
+
+/// Options applied to every export
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExportOptions {
+    pub resize: ResizeMode,
+    pub filter: ResizeFilter,
+    /// Border, background, and shadow decoration presets
+    pub decoration: DecorationSettings,
+    /// Watermark stamped onto every export
+    pub watermark: Watermark,
+    /// Timestamp and machine name strip added outside the image
+    pub banner: MetadataBanner,
+    /// Write exported files without capture metadata, for privacy
+    pub strip_metadata: bool,
+    /// Format and timing of animated step exports
+    pub animation: AnimationOptions,
+    /// How the screenshot is included in SVG exports
+    pub svg: SvgOptions,
+    /// Container of password-protected exports
+    pub encryption: SecureFormat,
+    /// Grid and captions of collages made from several captures
+    pub collage: CollageOptions,
+}
+
+impl ExportOptions {
+    /// Validate export options
+    pub fn validate(&self) -> AppResult<()> {
+        self.resize.validate()?;
+        self.decoration.validate()?;
+        self.watermark.validate()?;
+        self.banner.validate()?;
+        self.animation.validate()?;
+        self.collage.validate()
+    }
+}
+
+/// Apply export options to a flattened composite
+///
+/// `captured_at` is shown in the metadata banner, if enabled.
+pub fn prepare_export(composite: &RgbaImage, options: &ExportOptions, captured_at: SystemTime) -> RgbaImage {
+    let (width, height) = options.resize.target_size(composite.width(), composite.height());
+    let mut output = if (width, height) == composite.dimensions() {
+        composite.clone()
+    } else {
+        image::imageops::resize(composite, width, height, options.filter.filter_type())
+    };
+
+    if options.watermark.enabled {
+        match options.watermark.load_logo() {
+            Ok(logo) => render::apply_watermark(&mut output, &options.watermark, logo.as_ref()),
+            Err(e) => log::warn!("Skipping watermark, failed to load logo: {}", e),
+        }
+    }
+
+    if options.banner.enabled {
+        output = render::apply_banner(&output, &options.banner, captured_at);
+    }
+
+    match options.decoration.active() {
+        Some(decoration) => render::decorate(&output, decoration),
+        None => output,
+    }
+}
+
+/// JPEG quality used for exports
+const JPEG_QUALITY: u8 = 75;
+
+/// Encode an image in the given format
+pub fn encode_image(image: &RgbaImage, format: &ImageFormat) -> AppResult<Vec<u8>> {
+    let image = DynamicImage::ImageRgba8(image.clone());
+    let mut data = Vec::new();
+    let mut cursor = Cursor::new(&mut data);
+    let result = match format {
+        ImageFormat::Png => image.write_to(&mut cursor, ImageOutputFormat::Png),
+        // JPEG has no alpha channel, so transparent parts such as rounded
+        // window corners are flattened onto white instead of turning black
+        ImageFormat::Jpg => DynamicImage::ImageRgb8(flatten_onto_white(&image.to_rgba8()))
+            .write_to(&mut cursor, ImageOutputFormat::Jpeg(JPEG_QUALITY)),
+        ImageFormat::Bmp => image.write_to(&mut cursor, ImageOutputFormat::Bmp),
+    };
+    result.map_err(|e| AppError::ImageProcessing(format!("画像のエンコードに失敗しました: {}", e)))?;
+    Ok(data)
+}
+
+/// Composite an image onto an opaque white background
+fn flatten_onto_white(image: &RgbaImage) -> image::RgbImage {
+    image::RgbImage::from_fn(image.width(), image.height(), |x, y| {
+        let [r, g, b, a] = image.get_pixel(x, y).0;
+        let alpha = a as u32;
+        let blend = |c: u8| ((c as u32 * alpha + 255 * (255 - alpha) + 127) / 255) as u8;
+        image::Rgb([blend(r), blend(g), blend(b)])
+    })
+}
+
+/// Encode an exported image, embedding its metadata unless the options strip it
+///
+/// The color profile in `metadata`, if any, is embedded either way.
+pub fn encode_export(
+    image: &RgbaImage,
+    format: &ImageFormat,
+    options: &ExportOptions,
+    metadata: &ImageMetadata,
+) -> AppResult<Vec<u8>> {
+    let mut data = encode_image(image, format)?;
+    if let Some(profile) = &metadata.icc_profile {
+        data = metadata::embed_icc_profile(data, format, profile)?;
+    }
+    if options.strip_metadata {
+        return Ok(data);
+    }
+    metadata::embed(data, format, metadata)
+}
+
+/// Encode an image and atomically write it to a file
+pub fn save_image(image: &RgbaImage, path: &Path, format: &ImageFormat) -> AppResult<()> {
+    let data = encode_image(image, format)?;
+    files::write_atomic(path, &data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    fn test_image(width: u32, height: u32) -> RgbaImage {
+        RgbaImage::from_pixel(width, height, Rgba([10, 20, 30, 255]))
+    }
+
+    #[test]
+    fn test_target_size_modes() {
+        assert_eq!(ResizeMode::Original.target_size(200, 100), (200, 100));
+        assert_eq!(ResizeMode::Percentage(50.0).target_size(200, 100), (100, 50));
+        assert_eq!(ResizeMode::Exact { width: 30, height: 40 }.target_size(200, 100), (30, 40));
+    }
+
+    #[test]
+    fn test_max_dimensions_keeps_aspect_ratio() {
+        let mode = ResizeMode::MaxDimensions { max_width: 100, max_height: 100 };
+        assert_eq!(mode.target_size(400, 200), (100, 50));
+        // Images already within bounds are not enlarged
+        assert_eq!(mode.target_size(80, 40), (80, 40));
+
+        let width_only = ResizeMode::MaxDimensions { max_width: 50, max_height: 0 };
+        assert_eq!(width_only.target_size(200, 1000), (50, 250));
+    }
+
+    #[test]
+    fn test_tiny_percentage_keeps_one_pixel() {
+        assert_eq!(ResizeMode::Percentage(0.1).target_size(10, 10), (1, 1));
+    }
+
+    #[test]
+    fn test_validate_resize() {
+        assert!(ResizeMode::Original.validate().is_ok());
+        assert!(ResizeMode::Percentage(0.0).validate().is_err());
+        assert!(ResizeMode::Percentage(f32::NAN).validate().is_err());
+        assert!(ResizeMode::Exact { width: 0, height: 10 }.validate().is_err());
+        assert!(ResizeMode::Exact { width: 32_768, height: 8_192 }.validate().is_ok());
+        assert!(ResizeMode::Exact { width: 32_769, height: 1 }.validate().is_err());
+        let error = ResizeMode::Exact { width: 32_768, height: 32_768 }.validate().unwrap_err();
+        assert!(matches!(error, AppError::Settings(_)));
+    }
+
+    #[test]
+    fn test_prepare_export_resizes() {
+        let options = ExportOptions {
+            resize: ResizeMode::Percentage(25.0),
+            filter: ResizeFilter::Bilinear,
+            ..ExportOptions::default()
+        };
+        let exported = prepare_export(&test_image(40, 20), &options, SystemTime::now());
+        assert_eq!(exported.dimensions(), (10, 5));
+        assert_eq!(exported.get_pixel(0, 0).0, [10, 20, 30, 255]);
+
+        let original = prepare_export(&test_image(40, 20), &ExportOptions::default(), SystemTime::now());
+        assert_eq!(original.dimensions(), (40, 20));
+    }
+
+    #[test]
+    fn test_prepare_export_decorates_after_resize() {
+        let mut options = ExportOptions {
+            resize: ResizeMode::Percentage(50.0),
+            ..ExportOptions::default()
+        };
+        options.decoration.set_active_preset(Some("Simple Border")).unwrap();
+        let padding = options.decoration.active().unwrap().padding;
+
+        let exported = prepare_export(&test_image(40, 20), &options, SystemTime::now());
+        assert_eq!(exported.dimensions(), (20 + padding * 2, 10 + padding * 2));
+    }
+
+    #[test]
+    fn test_prepare_export_applies_watermark() {
+        let dir = std::env::temp_dir().join(format!("lsa-export-wm-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let logo_path = dir.join("logo.png");
+        save_image(&RgbaImage::from_pixel(4, 4, Rgba([255, 0, 0, 255])), &logo_path, &ImageFormat::Png).unwrap();
+
+        let options = ExportOptions {
+            watermark: Watermark {
+                enabled: true,
+                content: render::WatermarkContent::Image { path: logo_path },
+                anchor: render::WatermarkAnchor::TopLeft,
+                margin: 0,
+                scale: 1.0,
+                opacity: 1.0,
+            },
+            ..ExportOptions::default()
+        };
+        let exported = prepare_export(&test_image(20, 20), &options, SystemTime::now());
+        assert_eq!(exported.get_pixel(0, 0).0, [255, 0, 0, 255]);
+        assert_eq!(exported.get_pixel(10, 10).0, [10, 20, 30, 255]);
+
+        // A missing logo skips the watermark instead of failing the export
+        let mut missing = options.clone();
+        missing.watermark.content = render::WatermarkContent::Image { path: dir.join("missing.png") };
+        let exported = prepare_export(&test_image(20, 20), &missing, SystemTime::now());
+        assert_eq!(exported.get_pixel(0, 0).0, [10, 20, 30, 255]);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_save_image_formats() {
+        let dir = std::env::temp_dir().join(format!("lsa-export-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        for format in ImageFormat::all() {
+            let path = dir.join(format!("out.{}", format.extension()));
+            save_image(&test_image(8, 4), &path, &format).unwrap();
+            let loaded = image::open(&path).unwrap();
+            assert_eq!((loaded.width(), loaded.height()), (8, 4));
+        }
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_transparency_survives_png_and_flattens_to_white_in_jpeg() {
+        // The transparent half fills whole JPEG blocks, so compression doesn't blur it
+        let image = RgbaImage::from_fn(16, 8, |x, _| {
+            if x < 8 {
+                image::Rgba([0, 0, 0, 0])
+            } else {
+                image::Rgba([0, 0, 255, 255])
+            }
+        });
+
+        let png = image::load_from_memory(&encode_image(&image, &ImageFormat::Png).unwrap()).unwrap();
+        assert_eq!(png.to_rgba8().get_pixel(0, 0).0, [0, 0, 0, 0]);
+
+        let jpeg = image::load_from_memory(&encode_image(&image, &ImageFormat::Jpg).unwrap()).unwrap();
+        assert!(jpeg.to_rgb8().get_pixel(0, 0).0.iter().all(|&c| c > 200));
+    }
+
+    #[test]
+    fn test_export_options_serialization() {
+        let options = ExportOptions {
+            resize: ResizeMode::MaxDimensions { max_width: 1280, max_height: 0 },
+            filter: ResizeFilter::Nearest,
+            ..ExportOptions::default()
+        };
+        let json = serde_json::to_string(&options).unwrap();
+        let restored: ExportOptions = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, options);
+    }
+
+    #[test]
+    fn test_prepare_export_adds_banner_inside_decoration() {
+        let mut options = ExportOptions::default();
+        options.banner.enabled = true;
+        let plain = prepare_export(&test_image(40, 20), &options, SystemTime::now());
+        assert_eq!(plain.width(), 40);
+        assert!(plain.height() > 20);
+
+        options.decoration.set_active_preset(Some("Simple Border")).unwrap();
+        let padding = options.decoration.active().unwrap().padding;
+        let decorated = prepare_export(&test_image(40, 20), &options, SystemTime::now());
+        assert_eq!(decorated.dimensions(), (40 + padding * 2, plain.height() + padding * 2));
+    }
+}
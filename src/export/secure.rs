@@ -0,0 +1,227 @@
+//! Password-protected exports
+//!
+//! Sensitive captures can be exported into an encrypted container instead of
+//! a plain image file: a ZIP archive with AES-256 encryption, which Windows
+//! users can open with 7-Zip and most archive tools, or an
+//! [age](https://age-encryption.org) file encrypted with a passphrase. Both
+//! can be opened again in the editor with the password.
+
+use crate::types::{AppError, AppResult};
+use age::secrecy::SecretString;
+use serde::{Deserialize, Serialize};
+use std::io::{Cursor, Read, Write};
+use std::path::Path;
+use zip::write::SimpleFileOptions;
+use zip::{AesMode, CompressionMethod, ZipArchive, ZipWriter};
+
+/// Shortest password accepted for encrypted exports
+pub const MIN_PASSWORD_CHARS: usize = 8;
+
+/// First bytes of a ZIP archive
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+
+/// First bytes of an age file
+const AGE_MAGIC: &[u8] = b"age-encryption.org/v1";
+
+/// Largest decrypted file accepted, so a crafted container can't exhaust memory
+const MAX_DECRYPTED_BYTES: u64 = 512 * 1024 * 1024;
+
+/// Encrypted container formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum SecureFormat {
+    /// ZIP archive with AES-256 encryption
+    #[default]
+    Zip,
+    /// age file encrypted with a passphrase
+    Age,
+}
+
+impl SecureFormat {
+    /// Get all container formats
+    pub fn all() -> [SecureFormat; 2] {
+        [SecureFormat::Zip, SecureFormat::Age]
+    }
+
+    /// Get the file extension for this format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            SecureFormat::Zip => "zip",
+            SecureFormat::Age => "age",
+        }
+    }
+
+    /// Detect the format of a file from its first bytes
+    pub fn detect(data: &[u8]) -> Option<SecureFormat> {
+        if data.starts_with(ZIP_MAGIC) {
+            Some(SecureFormat::Zip)
+        } else if data.starts_with(AGE_MAGIC) {
+            Some(SecureFormat::Age)
+        } else {
+            None
+        }
+    }
+}
+
+impl std::fmt::Display for SecureFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SecureFormat::Zip => write!(f, "ZIP (AES-256)"),
+            SecureFormat::Age => write!(f, "age"),
+        }
+    }
+}
+
+/// Check whether a path has the extension of an encrypted container
+pub fn is_secure_file(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| {
+        SecureFormat::all().iter().any(|format| ext.eq_ignore_ascii_case(format.extension()))
+    })
+}
+
+/// Check that a password is acceptable for encrypting
+pub fn validate_password(password: &str) -> AppResult<()> {
+    if password.chars().count() < MIN_PASSWORD_CHARS {
+        return Err(AppError::Encryption(format!("パスワードは{}文字以上にしてください", MIN_PASSWORD_CHARS)));
+    }
+    Ok(())
+}
+
+/// Encrypt a file's contents with a password
+///
+/// `file_name` is the name of the file inside a ZIP archive; age files hold
+/// the contents only.
+pub fn encrypt(data: &[u8], file_name: &str, password: &str, format: SecureFormat) -> AppResult<Vec<u8>> {
+    validate_password(password)?;
+    match format {
+        SecureFormat::Zip => encrypt_zip(data, file_name, password)
+            .map_err(|e| AppError::Encryption(format!("ZIP の作成に失敗しました: {}", e))),
+        SecureFormat::Age => encrypt_age(data, password, None),
+    }
+}
+
+fn encrypt_zip(data: &[u8], file_name: &str, password: &str) -> zip::result::ZipResult<Vec<u8>> {
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    // Images are already compressed
+    let options = SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Stored)
+        .with_aes_encryption(AesMode::Aes256, password);
+    writer.start_file(file_name, options)?;
+    writer.write_all(data)?;
+    Ok(writer.finish()?.into_inner())
+}
+
+/// Encrypt with age, with the passphrase work factor (log2 of the scrypt cost) calibrated unless given
+fn encrypt_age(data: &[u8], password: &str, work_factor: Option<u8>) -> AppResult<Vec<u8>> {
+    let mut recipient = age::scrypt::Recipient::new(SecretString::from(password.to_string()));
+    if let Some(work_factor) = work_factor {
+        recipient.set_work_factor(work_factor);
+    }
+    age::encrypt(&recipient, data).map_err(|e| AppError::Encryption(format!("age での暗号化に失敗しました: {}", e)))
+}
+
+/// A file taken out of an encrypted container
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecryptedFile {
+    /// Name of the file inside a ZIP archive
+    pub name: Option<String>,
+    pub data: Vec<u8>,
+}
+
+/// Decrypt a container written by [`encrypt`], detecting its format
+///
+/// ZIP archives give their first file.
+pub fn decrypt(container: &[u8], password: &str) -> AppResult<DecryptedFile> {
+    match SecureFormat::detect(container) {
+        Some(SecureFormat::Zip) => decrypt_zip(container, password),
+        Some(SecureFormat::Age) => decrypt_age(container, password),
+        None => Err(AppError::Encryption("暗号化された ZIP または age ファイルではありません".to_string())),
+    }
+}
+
+fn decrypt_zip(container: &[u8], password: &str) -> AppResult<DecryptedFile> {
+    let mut archive = ZipArchive::new(Cursor::new(container))
+        .map_err(|e| AppError::Encryption(format!("ZIP を読み込めません: {}", e)))?;
+    let file = match archive.by_index_decrypt(0, password.as_bytes()) {
+        Ok(file) => file,
+        Err(zip::result::ZipError::InvalidPassword) => return Err(wrong_password()),
+        Err(e) => return Err(AppError::Encryption(format!("ZIP を読み込めません: {}", e))),
+    };
+    if !file.encrypted() {
+        return Err(AppError::Encryption("ZIP が暗号化されていません".to_string()));
+    }
+    let name = Some(file.name().to_string());
+    let data = read_limited(file).map_err(|e| match e.kind() {
+        // AES entries are authenticated, so a wrong password can also show up while reading
+        std::io::ErrorKind::InvalidData | std::io::ErrorKind::InvalidInput => wrong_password(),
+        _ => AppError::Encryption(format!("ZIP を読み込めません: {}", e)),
+    })?;
+    Ok(DecryptedFile { name, data })
+}
+
+fn decrypt_age(container: &[u8], password: &str) -> AppResult<DecryptedFile> {
+    let decryptor = age::Decryptor::new(container)
+        .map_err(|e| AppError::Encryption(format!("age ファイルを読み込めません: {}", e)))?;
+    if !decryptor.is_scrypt() {
+        return Err(AppError::Encryption("パスワードで暗号化された age ファイルではありません".to_string()));
+    }
+    let identity = age::scrypt::Identity::new(SecretString::from(password.to_string()));
+    let reader = match decryptor.decrypt(std::iter::once(&identity as &dyn age::Identity)) {
+        Ok(reader) => reader,
+        Err(age::DecryptError::DecryptionFailed | age::DecryptError::NoMatchingKeys) => return Err(wrong_password()),
+        Err(e) => return Err(AppError::Encryption(format!("age ファイルを復号できません: {}", e))),
+    };
+    let data = read_limited(reader).map_err(|e| AppError::Encryption(format!("age ファイルを復号できません: {}", e)))?;
+    Ok(DecryptedFile { name: None, data })
+}
+
+/// Read everything, up to [`MAX_DECRYPTED_BYTES`]
+fn read_limited(reader: impl Read) -> std::io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    reader.take(MAX_DECRYPTED_BYTES + 1).read_to_end(&mut data)?;
+    if data.len() as u64 > MAX_DECRYPTED_BYTES {
+        return Err(std::io::Error::other("復号したファイルが大きすぎます"));
+    }
+    Ok(data)
+}
+
+fn wrong_password() -> AppError {
+    AppError::Encryption("パスワードが違います".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zip_round_trip() {
+        let container = encrypt(b"secret image", "capture.png", "correct horse", SecureFormat::Zip).unwrap();
+        assert_eq!(SecureFormat::detect(&container), Some(SecureFormat::Zip));
+        // The contents are not stored in the clear
+        assert!(!container.windows(12).any(|window| window == b"secret image"));
+
+        let file = decrypt(&container, "correct horse").unwrap();
+        assert_eq!(file.name.as_deref(), Some("capture.png"));
+        assert_eq!(file.data, b"secret image");
+        let error = decrypt(&container, "wrong password").unwrap_err();
+        assert_eq!(error.to_string(), wrong_password().to_string());
+    }
+
+    #[test]
+    fn test_age_round_trip() {
+        // A low work factor keeps the test fast in unoptimized builds
+        let container = encrypt_age(b"secret image", "correct horse", Some(4)).unwrap();
+        assert_eq!(SecureFormat::detect(&container), Some(SecureFormat::Age));
+        let file = decrypt(&container, "correct horse").unwrap();
+        assert_eq!((file.name, file.data), (None, b"secret image".to_vec()));
+        assert!(decrypt(&container, "wrong password").is_err());
+    }
+
+    #[test]
+    fn test_rejected_input() {
+        assert!(encrypt(b"image", "a.png", "short", SecureFormat::Zip).is_err());
+        assert!(decrypt(b"\x89PNG plain image", "correct horse").is_err());
+        assert!(is_secure_file(Path::new("capture.AGE")));
+        assert!(is_secure_file(Path::new("capture.zip")));
+        assert!(!is_secure_file(Path::new("capture.png")));
+    }
+}
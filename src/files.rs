@@ -0,0 +1,476 @@
+//! Safe file writing
+//!
+//! Files are written to a temporary sibling and atomically renamed into
+//! place, so a crash or a full disk never leaves a truncated image behind.
+//! Temporary files left over from an interrupted save are removed on startup.
+//!
+//! Paths are kept as `Path`/`OsStr` end to end so non-ASCII file and
+//! directory names round-trip unchanged, and on Windows paths longer than
+//! `MAX_PATH` are given the `\\?\` extended-length prefix.
+
+use crate::types::{AppError, AppResult};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Prefix of temporary files created by [`write_atomic`]
+pub const TEMP_FILE_PREFIX: &str = ".lsa-tmp-";
+
+/// Temporary files younger than this may belong to a save in progress
+pub const ORPHAN_MIN_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// Longest path Windows APIs accept without the extended-length prefix
+const WINDOWS_MAX_PATH: usize = 260;
+
+/// Characters that are not allowed in Windows file names
+const INVALID_FILE_NAME_CHARS: [char; 9] = ['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+
+/// Device names reserved by Windows regardless of extension
+const RESERVED_FILE_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9", "LPT1",
+    "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Get a path usable with file system APIs regardless of its length
+///
+/// On Windows, absolute paths longer than `MAX_PATH` are converted to the
+/// `\\?\` extended-length form. Other paths are returned unchanged.
+pub fn extended_length_path(path: &Path) -> PathBuf {
+    #[cfg(windows)]
+    {
+        if let Some(extended) = path.to_str().and_then(add_long_path_prefix) {
+            return PathBuf::from(extended);
+        }
+    }
+    path.to_path_buf()
+}
+
+/// Add the extended-length prefix to a long absolute Windows path
+///
+/// Returns `None` for short, relative, or already prefixed paths.
+#[cfg_attr(not(windows), allow(dead_code))]
+fn add_long_path_prefix(path: &str) -> Option<String> {
+    if path.encode_utf16().count() < WINDOWS_MAX_PATH || path.starts_with("\\\\?\\") {
+        return None;
+    }
+    // The prefix disables path normalization, so separators must be backslashes
+    let path = path.replace('/', "\\");
+    if let Some(share) = path.strip_prefix("\\\\") {
+        return Some(format!("\\\\?\\UNC\\{}", share));
+    }
+    let bytes = path.as_bytes();
+    let is_drive_absolute = bytes.len() >= 3 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' && bytes[2] == b'\\';
+    is_drive_absolute.then(|| format!("\\\\?\\{}", path))
+}
+
+/// Make a string safe to use as a file name on every supported platform
+///
+/// Non-ASCII characters are kept; only characters Windows rejects, trailing
+/// dots and spaces, and reserved device names are replaced.
+pub fn sanitize_file_name(name: &str) -> String {
+    let mut sanitized: String = name
+        .chars()
+        .map(|c| if c.is_control() || INVALID_FILE_NAME_CHARS.contains(&c) { '_' } else { c })
+        .collect();
+    sanitized.truncate(sanitized.trim_end_matches(['.', ' ']).len());
+
+    let stem = sanitized.split('.').next().unwrap_or_default();
+    if RESERVED_FILE_NAMES.iter().any(|reserved| stem.eq_ignore_ascii_case(reserved)) {
+        sanitized.insert(0, '_');
+    }
+    if sanitized.is_empty() {
+        sanitized.push('_');
+    }
+    sanitized
+}
+
+/// Write `data` to `path` atomically via a temporary file in the same directory
+pub fn write_atomic(path: &Path, data: &[u8]) -> AppResult<()> {
+    try_write_atomic(path, data).map_err(|e| map_io_error(e, path))
+}
+
+/// Write atomically, retrying while another process holds the file locked
+///
+/// Sync clients (OneDrive) and file servers briefly lock files they are
+/// uploading or scanning, so a save onto such a location may need a few tries.
+pub fn write_atomic_with_retry(path: &Path, data: &[u8], policy: RetryPolicy) -> AppResult<()> {
+    retry_transient(policy, || try_write_atomic(path, data)).map_err(|e| map_io_error(e, path))
+}
+
+/// Write atomically, keeping the raw I/O error
+fn try_write_atomic(path: &Path, data: &[u8]) -> io::Result<()> {
+    let target = extended_length_path(path);
+    let temp_path = temp_path_for(&target);
+    let result = write_and_sync(&temp_path, data).and_then(|_| fs::rename(&temp_path, &target));
+
+    if result.is_err() {
+        // Best effort: don't leave a partial file behind
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+/// How often to retry an operation that failed with a transient error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first
+    pub attempts: u32,
+    /// Delay before the first retry, doubled after each further failure
+    pub initial_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 5,
+            initial_delay: Duration::from_millis(200),
+        }
+    }
+}
+
+/// Run `operation`, retrying transient lock errors according to `policy`
+pub fn retry_transient<T>(policy: RetryPolicy, operation: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+    retry_when(policy, is_transient_error, operation)
+}
+
+/// Run `operation`, retrying the errors `retryable` accepts according to `policy`
+pub fn retry_when<T>(
+    policy: RetryPolicy,
+    retryable: impl Fn(&io::Error) -> bool,
+    mut operation: impl FnMut() -> io::Result<T>,
+) -> io::Result<T> {
+    let mut delay = policy.initial_delay;
+    let mut attempt = 1;
+    loop {
+        match operation() {
+            Err(e) if attempt < policy.attempts && retryable(&e) => {
+                log::warn!("Retrying after transient error (attempt {}): {}", attempt, e);
+                std::thread::sleep(delay);
+                delay *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Check whether an I/O error is likely to go away if retried
+///
+/// Covers sharing and lock violations from sync clients or virus scanners
+/// and timeouts from network shares.
+pub fn is_transient_error(error: &io::Error) -> bool {
+    // ERROR_SHARING_VIOLATION and ERROR_LOCK_VIOLATION
+    const WINDOWS_LOCK_ERRORS: [i32; 2] = [32, 33];
+
+    if cfg!(windows) && error.raw_os_error().is_some_and(|code| WINDOWS_LOCK_ERRORS.contains(&code)) {
+        return true;
+    }
+    matches!(
+        error.kind(),
+        io::ErrorKind::ResourceBusy | io::ErrorKind::TimedOut | io::ErrorKind::Interrupted
+    )
+}
+
+/// Kind of storage a path is on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SaveLocation {
+    Local,
+    /// A network share (UNC path or mapped network drive)
+    Network,
+    /// A folder synchronized by a cloud client such as OneDrive
+    CloudSynced,
+}
+
+impl SaveLocation {
+    /// Check whether saves to this location should go through the background writer
+    pub fn is_remote(&self) -> bool {
+        *self != SaveLocation::Local
+    }
+}
+
+/// Environment variables set by OneDrive to its sync folders
+const CLOUD_SYNC_ROOT_VARIABLES: [&str; 3] = ["OneDrive", "OneDriveCommercial", "OneDriveConsumer"];
+
+/// Get the folders synchronized by a cloud client, from the environment
+pub fn cloud_sync_roots() -> Vec<PathBuf> {
+    CLOUD_SYNC_ROOT_VARIABLES
+        .iter()
+        .filter_map(std::env::var_os)
+        .map(PathBuf::from)
+        .collect()
+}
+
+/// Detect what kind of storage `path` is on, given the cloud sync roots
+pub fn save_location(path: &Path, cloud_roots: &[PathBuf]) -> SaveLocation {
+    match classify_location(path, cloud_roots) {
+        SaveLocation::Local if is_remote_drive(path) => SaveLocation::Network,
+        location => location,
+    }
+}
+
+/// Classify a path from its form and the known cloud sync roots
+fn classify_location(path: &Path, cloud_roots: &[PathBuf]) -> SaveLocation {
+    if cloud_roots.iter().any(|root| !root.as_os_str().is_empty() && path.starts_with(root)) {
+        return SaveLocation::CloudSynced;
+    }
+    let path = path.to_string_lossy();
+    let is_unc = path.starts_with("\\\\?\\UNC\\")
+        || (!path.starts_with("\\\\?\\") && (path.starts_with("\\\\") || path.starts_with("//")));
+    if is_unc {
+        SaveLocation::Network
+    } else {
+        SaveLocation::Local
+    }
+}
+
+/// Convert a string to a null-terminated UTF-16 string for Win32 APIs
+#[cfg(windows)]
+pub(crate) fn to_wide(text: &str) -> Vec<u16> {
+    text.encode_utf16().chain(std::iter::once(0)).collect()
+}
+
+/// Check whether a path is on a mapped network drive
+#[cfg(windows)]
+fn is_remote_drive(path: &Path) -> bool {
+    use std::os::windows::ffi::OsStrExt;
+    use std::path::Component;
+    use winapi::um::fileapi::GetDriveTypeW;
+    use winapi::um::winbase::DRIVE_REMOTE;
+
+    let Some(Component::Prefix(prefix)) = path.components().next() else {
+        return false;
+    };
+    let mut root: Vec<u16> = prefix.as_os_str().encode_wide().collect();
+    root.extend("\\\0".encode_utf16());
+    // SAFETY: `root` is a valid null-terminated UTF-16 string
+    unsafe { GetDriveTypeW(root.as_ptr()) == DRIVE_REMOTE }
+}
+
+/// Check whether a path is on a mapped network drive
+#[cfg(not(windows))]
+fn is_remote_drive(_path: &Path) -> bool {
+    false
+}
+
+/// Remove temporary files older than `min_age` left behind by interrupted saves
+///
+/// Returns the number of files removed. Missing directories are ignored.
+pub fn cleanup_orphaned_temp_files(dir: &Path, min_age: Duration) -> usize {
+    let Ok(entries) = fs::read_dir(extended_length_path(dir)) else {
+        return 0;
+    };
+
+    let now = SystemTime::now();
+    let mut removed = 0;
+    for entry in entries.flatten() {
+        if !entry.file_name().to_string_lossy().starts_with(TEMP_FILE_PREFIX) {
+            continue;
+        }
+        let age = entry
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| now.duration_since(modified).ok())
+            .unwrap_or_default();
+        if age < min_age {
+            continue;
+        }
+        match fs::remove_file(entry.path()) {
+            Ok(()) => removed += 1,
+            Err(e) => log::warn!("Failed to remove orphaned temp file {}: {}", entry.path().display(), e),
+        }
+    }
+    removed
+}
+
+/// Check whether an I/O error means the disk is full
+pub fn is_disk_full(error: &io::Error) -> bool {
+    error.kind() == io::ErrorKind::StorageFull
+}
+
+/// Convert an I/O error while writing `path` into an application error
+pub fn map_io_error(error: io::Error, path: &Path) -> AppError {
+    if is_disk_full(&error) {
+        AppError::DiskFull(path.display().to_string())
+    } else {
+        AppError::FileAccess(error)
+    }
+}
+
+/// Temporary sibling path used while writing `path`
+fn temp_path_for(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!("{}{}-{}", TEMP_FILE_PREFIX, uuid::Uuid::new_v4().simple(), name))
+}
+
+/// Write data and flush it to disk
+fn write_and_sync(path: &Path, data: &[u8]) -> io::Result<()> {
+    let mut file = fs::File::create(path)?;
+    file.write_all(data)?;
+    file.sync_all()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("lsa-files-test-{}-{}", name, uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn entry_names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_write_atomic_creates_and_replaces() {
+        let dir = temp_dir("write");
+        let path = dir.join("image.png");
+
+        write_atomic(&path, b"first").unwrap();
+        write_atomic(&path, b"second").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"second");
+        // No temporary files are left behind
+        assert_eq!(entry_names(&dir), vec!["image.png".to_string()]);
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_write_atomic_failure_leaves_no_temp_file() {
+        let dir = temp_dir("fail");
+        let missing = dir.join("missing").join("image.png");
+        assert!(matches!(write_atomic(&missing, b"data"), Err(AppError::FileAccess(_))));
+        assert!(entry_names(&dir).is_empty());
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_cleanup_orphaned_temp_files() {
+        let dir = temp_dir("cleanup");
+        fs::write(dir.join(format!("{}abc-image.png", TEMP_FILE_PREFIX)), b"partial").unwrap();
+        fs::write(dir.join("keep.png"), b"image").unwrap();
+
+        // Fresh temp files may belong to a save in progress
+        assert_eq!(cleanup_orphaned_temp_files(&dir, ORPHAN_MIN_AGE), 0);
+        assert_eq!(cleanup_orphaned_temp_files(&dir, Duration::ZERO), 1);
+        assert_eq!(entry_names(&dir), vec!["keep.png".to_string()]);
+
+        assert_eq!(cleanup_orphaned_temp_files(&dir.join("missing"), Duration::ZERO), 0);
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_disk_full_error_mapping() {
+        let full = io::Error::new(io::ErrorKind::StorageFull, "no space");
+        assert!(matches!(map_io_error(full, Path::new("a.png")), AppError::DiskFull(_)));
+
+        let denied = io::Error::new(io::ErrorKind::PermissionDenied, "denied");
+        assert!(matches!(map_io_error(denied, Path::new("a.png")), AppError::FileAccess(_)));
+    }
+
+    #[test]
+    fn test_write_atomic_japanese_path() {
+        let dir = temp_dir("unicode").join("スクリーンショット");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("画面キャプチャ_テスト.png");
+
+        write_atomic(&path, b"image").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"image");
+        assert_eq!(entry_names(&dir), vec!["画面キャプチャ_テスト.png".to_string()]);
+
+        fs::remove_dir_all(dir.parent().unwrap()).ok();
+    }
+
+    #[test]
+    fn test_long_path_prefix() {
+        let long_name = "長".repeat(WINDOWS_MAX_PATH);
+        assert_eq!(add_long_path_prefix("C:\\short\\image.png"), None);
+        assert_eq!(
+            add_long_path_prefix(&format!("C:\\{}/image.png", long_name)),
+            Some(format!("\\\\?\\C:\\{}\\image.png", long_name))
+        );
+        assert_eq!(
+            add_long_path_prefix(&format!("\\\\server\\share\\{}", long_name)),
+            Some(format!("\\\\?\\UNC\\server\\share\\{}", long_name))
+        );
+        // Relative and already prefixed paths are left alone
+        assert_eq!(add_long_path_prefix(&long_name), None);
+        assert_eq!(add_long_path_prefix(&format!("\\\\?\\C:\\{}", long_name)), None);
+    }
+
+    #[test]
+    fn test_sanitize_file_name() {
+        assert_eq!(sanitize_file_name("スクリーンショット 2024-01-01.png"), "スクリーンショット 2024-01-01.png");
+        assert_eq!(sanitize_file_name("会議: 議事録/メモ?.png"), "会議_ 議事録_メモ_.png");
+        assert_eq!(sanitize_file_name("name. . "), "name");
+        assert_eq!(sanitize_file_name("con.png"), "_con.png");
+        assert_eq!(sanitize_file_name(""), "_");
+    }
+
+    #[test]
+    fn test_retry_transient_errors() {
+        let policy = RetryPolicy { attempts: 3, initial_delay: Duration::from_millis(1) };
+
+        let mut calls = 0;
+        let result = retry_transient(policy, || {
+            calls += 1;
+            if calls < 3 {
+                Err(io::Error::new(io::ErrorKind::ResourceBusy, "locked"))
+            } else {
+                Ok(calls)
+            }
+        });
+        assert_eq!(result.unwrap(), 3);
+
+        // Permanent errors and exhausted attempts are returned immediately
+        let mut calls = 0;
+        let result: io::Result<()> = retry_transient(policy, || {
+            calls += 1;
+            Err(io::Error::new(io::ErrorKind::NotFound, "missing"))
+        });
+        assert!(result.is_err());
+        assert_eq!(calls, 1);
+
+        let mut calls = 0;
+        let result: io::Result<()> = retry_transient(policy, || {
+            calls += 1;
+            Err(io::Error::new(io::ErrorKind::TimedOut, "timeout"))
+        });
+        assert!(result.is_err());
+        assert_eq!(calls, 3);
+    }
+
+    #[test]
+    fn test_classify_location() {
+        let roots = vec![PathBuf::from("/home/user/OneDrive")];
+        assert_eq!(classify_location(Path::new("/home/user/OneDrive/写真/a.png"), &roots), SaveLocation::CloudSynced);
+        assert_eq!(classify_location(Path::new("/home/user/OneDriveOther/a.png"), &roots), SaveLocation::Local);
+        assert_eq!(classify_location(Path::new("\\\\server\\share\\a.png"), &roots), SaveLocation::Network);
+        assert_eq!(classify_location(Path::new("\\\\?\\UNC\\server\\share\\a.png"), &roots), SaveLocation::Network);
+        assert_eq!(classify_location(Path::new("\\\\?\\C:\\a.png"), &roots), SaveLocation::Local);
+        assert_eq!(classify_location(Path::new("C:\\Users\\a.png"), &[]), SaveLocation::Local);
+        assert!(SaveLocation::CloudSynced.is_remote());
+    }
+
+    #[test]
+    fn test_write_atomic_with_retry() {
+        let dir = temp_dir("retry");
+        let path = dir.join("image.png");
+        write_atomic_with_retry(&path, b"data", RetryPolicy::default()).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"data");
+        fs::remove_dir_all(&dir).ok();
+    }
+}
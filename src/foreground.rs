@@ -0,0 +1,153 @@
+//! The window in front when a capture starts
+//!
+//! Window and region captures record the title of the foreground window and
+//! the executable name of its process, for file names and embedded
+//! metadata. The Win32 queries live here; capture backends expose them
+//! through [`CaptureBackend`](crate::capture::CaptureBackend), which the mock
+//! backend fakes in tests.
+
+use std::path::Path;
+
+/// Longest window title used in a file name, in characters
+pub const MAX_FILE_NAME_TITLE_CHARS: usize = 60;
+
+/// Title and application of the window in front when a capture started
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ForegroundWindow {
+    pub title: Option<String>,
+    /// Executable name of the window's process, e.g. `notepad.exe`
+    pub application: Option<String>,
+}
+
+impl ForegroundWindow {
+    /// Get the application name without the executable extension, e.g. `notepad`
+    pub fn app_name(&self) -> Option<&str> {
+        let application = self.application.as_deref()?;
+        let stem = match application.rsplit_once('.') {
+            Some((stem, extension)) if extension.eq_ignore_ascii_case("exe") && !stem.is_empty() => stem,
+            _ => application,
+        };
+        Some(stem)
+    }
+
+    /// Get the title shortened for use in a file name
+    pub fn short_title(&self) -> Option<String> {
+        let title = self.title.as_deref()?.trim();
+        if title.is_empty() {
+            return None;
+        }
+        let mut short: String = title.chars().take(MAX_FILE_NAME_TITLE_CHARS).collect();
+        if short.len() < title.len() {
+            short.truncate(short.trim_end().len());
+        }
+        Some(short)
+    }
+}
+
+/// Get the file name of an executable from its full path
+pub fn executable_name(path: &Path) -> Option<String> {
+    path.file_name().map(|name| name.to_string_lossy().into_owned())
+}
+
+/// Get the title of the foreground window
+#[cfg(windows)]
+pub fn window_title() -> Option<String> {
+    use winapi::um::winuser::GetForegroundWindow;
+
+    // SAFETY: GetForegroundWindow has no preconditions and may return null
+    let window = unsafe { GetForegroundWindow() };
+    if window.is_null() {
+        return None;
+    }
+    title_of(window)
+}
+
+/// Get the title of a window
+#[cfg(windows)]
+pub(crate) fn title_of(window: winapi::shared::windef::HWND) -> Option<String> {
+    use winapi::um::winuser::GetWindowTextW;
+
+    let mut buffer = [0u16; 512];
+    // SAFETY: the buffer length is passed in characters; the text is truncated to fit
+    let length = unsafe { GetWindowTextW(window, buffer.as_mut_ptr(), buffer.len() as i32) };
+    (length > 0).then(|| String::from_utf16_lossy(&buffer[..length as usize]))
+}
+
+/// Get the title of the foreground window
+#[cfg(not(windows))]
+pub fn window_title() -> Option<String> {
+    None
+}
+
+/// Get the executable name of the process owning the foreground window
+#[cfg(windows)]
+pub fn application() -> Option<String> {
+    use winapi::um::winuser::GetForegroundWindow;
+
+    // SAFETY: GetForegroundWindow has no preconditions and may return null
+    let window = unsafe { GetForegroundWindow() };
+    if window.is_null() {
+        return None;
+    }
+    application_of(window)
+}
+
+/// Get the executable name of the process owning a window
+#[cfg(windows)]
+pub(crate) fn application_of(window: winapi::shared::windef::HWND) -> Option<String> {
+    use std::os::windows::ffi::OsStringExt;
+    use winapi::um::handleapi::CloseHandle;
+    use winapi::um::processthreadsapi::OpenProcess;
+    use winapi::um::winbase::QueryFullProcessImageNameW;
+    use winapi::um::winnt::PROCESS_QUERY_LIMITED_INFORMATION;
+    use winapi::um::winuser::GetWindowThreadProcessId;
+
+    let mut process_id = 0;
+    // SAFETY: `process_id` is a writable u32; a stale handle yields 0
+    unsafe { GetWindowThreadProcessId(window, &mut process_id) };
+    if process_id == 0 {
+        return None;
+    }
+    // SAFETY: OpenProcess returns null on failure, e.g. for elevated processes
+    let process = unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, process_id) };
+    if process.is_null() {
+        return None;
+    }
+    let mut buffer = [0u16; 1024];
+    let mut length = buffer.len() as u32;
+    // SAFETY: `length` holds the buffer size in characters and receives the path length
+    let ok = unsafe { QueryFullProcessImageNameW(process, 0, buffer.as_mut_ptr(), &mut length) };
+    // SAFETY: `process` was opened above and is closed once
+    unsafe { CloseHandle(process) };
+    if ok == 0 {
+        return None;
+    }
+    executable_name(Path::new(&std::ffi::OsString::from_wide(&buffer[..length as usize])))
+}
+
+/// Get the executable name of the process owning the foreground window
+#[cfg(not(windows))]
+pub fn application() -> Option<String> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names_for_file_names() {
+        let window = ForegroundWindow {
+            title: Some(format!("  {} - Notepad", "x".repeat(70))),
+            application: Some("Notepad.EXE".to_string()),
+        };
+        assert_eq!(window.app_name(), Some("Notepad"));
+        assert_eq!(window.short_title().unwrap().chars().count(), MAX_FILE_NAME_TITLE_CHARS);
+        assert_eq!(ForegroundWindow { application: Some("code".to_string()), ..Default::default() }.app_name(), Some("code"));
+        assert_eq!(ForegroundWindow::default().app_name(), None);
+        assert_eq!(
+            executable_name(Path::new("C:/Program Files/Mozilla Firefox/firefox.exe")).as_deref(),
+            Some("firefox.exe")
+        );
+    }
+}
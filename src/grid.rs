@@ -0,0 +1,168 @@
+//! Canvas grid and rulers
+//!
+//! The grid is laid out in image pixels, so it scales with the zoom level,
+//! and annotation drawing can snap to its intersections. Rulers along the
+//! canvas edges label image coordinates with a tick spacing chosen to stay
+//! readable at the current zoom. Zoomed in far enough, a grid between
+//! individual image pixels is drawn as well.
+
+use crate::types::{AppError, AppResult};
+use egui::{Modifiers, Pos2};
+use serde::{Deserialize, Serialize};
+
+/// Thickness of the rulers in screen pixels
+pub const RULER_SIZE: f32 = 20.0;
+
+/// Minimum screen distance between labeled ruler ticks
+const MIN_RULER_LABEL_SPACING: f32 = 60.0;
+
+/// Grid lines closer than this on screen are not drawn
+pub const MIN_GRID_LINE_SPACING: f32 = 4.0;
+
+/// The pixel grid is drawn above this zoom level (800%)
+pub const PIXEL_GRID_MIN_ZOOM: f32 = 8.0;
+
+/// Grid and ruler settings
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GridOptions {
+    /// Whether the grid is drawn over the image
+    pub show_grid: bool,
+    /// Whether pixel rulers are drawn along the canvas edges
+    pub show_rulers: bool,
+    /// Distance between major grid lines in image pixels
+    pub spacing: f32,
+    /// Number of cells each major cell is divided into
+    pub subdivisions: u32,
+    /// Whether annotation drawing snaps to grid intersections when no modifier is held
+    pub snap: bool,
+    /// Whether lines between image pixels are drawn above [`PIXEL_GRID_MIN_ZOOM`]
+    pub pixel_grid: bool,
+}
+
+impl Default for GridOptions {
+    fn default() -> Self {
+        Self {
+            show_grid: false,
+            show_rulers: false,
+            spacing: 50.0,
+            subdivisions: 5,
+            snap: false,
+            pixel_grid: true,
+        }
+    }
+}
+
+impl GridOptions {
+    /// Largest allowed major grid spacing
+    pub const MAX_SPACING: f32 = 1000.0;
+
+    /// Largest allowed number of subdivisions
+    pub const MAX_SUBDIVISIONS: u32 = 10;
+
+    /// Distance between adjacent grid lines, including subdivisions
+    pub fn step(&self) -> f32 {
+        self.spacing / self.subdivisions.max(1) as f32
+    }
+
+    /// Check whether snapping applies, with Alt inverting the setting
+    pub fn is_snapping(&self, modifiers: Modifiers) -> bool {
+        self.snap != modifiers.alt
+    }
+
+    /// Check whether the pixel grid is drawn at a zoom level
+    pub fn shows_pixel_grid(&self, zoom: f32) -> bool {
+        self.pixel_grid && zoom > PIXEL_GRID_MIN_ZOOM
+    }
+
+    /// Snap an image-space point to the nearest grid intersection
+    pub fn snap_point(&self, point: Pos2) -> Pos2 {
+        let step = self.step();
+        Pos2::new((point.x / step).round() * step, (point.y / step).round() * step)
+    }
+
+    /// Validate grid options
+    pub fn validate(&self) -> AppResult<()> {
+        if !(self.spacing >= 2.0 && self.spacing <= Self::MAX_SPACING) {
+            return Err(AppError::Settings(format!(
+                "グリッド間隔は2〜{}ピクセルで指定してください",
+                Self::MAX_SPACING
+            )));
+        }
+        if !(1..=Self::MAX_SUBDIVISIONS).contains(&self.subdivisions) {
+            return Err(AppError::Settings(format!(
+                "グリッドの分割数は1〜{}で指定してください",
+                Self::MAX_SUBDIVISIONS
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Multiples of `step` from `start` to `end` inclusive
+pub fn lines(start: f32, end: f32, step: f32) -> impl Iterator<Item = f32> {
+    let first = (start / step).ceil() as i64;
+    let last = (end / step).floor() as i64;
+    (first..=last).map(move |index| index as f32 * step)
+}
+
+/// Image-pixel distance between labeled ruler ticks at a zoom level
+///
+/// Picks the smallest 1, 2, or 5 times a power of ten that keeps labels at
+/// least [`MIN_RULER_LABEL_SPACING`] screen pixels apart.
+pub fn ruler_step(zoom: f32) -> f32 {
+    let mut magnitude = 1.0;
+    loop {
+        for factor in [1.0, 2.0, 5.0] {
+            let step = factor * magnitude;
+            if step * zoom >= MIN_RULER_LABEL_SPACING {
+                return step;
+            }
+        }
+        magnitude *= 10.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snap_to_intersections() {
+        let grid = GridOptions::default();
+        assert_eq!(grid.step(), 10.0);
+        assert_eq!(grid.snap_point(Pos2::new(14.0, 26.0)), Pos2::new(10.0, 30.0));
+
+        let coarse = GridOptions { subdivisions: 1, ..grid };
+        assert_eq!(coarse.snap_point(Pos2::new(74.0, 20.0)), Pos2::new(50.0, 0.0));
+
+        assert!(!grid.is_snapping(Modifiers::NONE));
+        assert!(grid.is_snapping(Modifiers::ALT));
+    }
+
+    #[test]
+    fn test_pixel_grid_above_800_percent() {
+        let grid = GridOptions::default();
+        assert!(!grid.shows_pixel_grid(8.0));
+        assert!(grid.shows_pixel_grid(8.5));
+        assert!(!GridOptions { pixel_grid: false, ..grid }.shows_pixel_grid(10.0));
+    }
+
+    #[test]
+    fn test_lines_and_ruler_step() {
+        assert_eq!(lines(-5.0, 25.0, 10.0).collect::<Vec<_>>(), vec![0.0, 10.0, 20.0]);
+        assert_eq!(lines(1.0, 9.0, 10.0).count(), 0);
+
+        assert_eq!(ruler_step(1.0), 100.0);
+        assert_eq!(ruler_step(4.0), 20.0);
+        assert_eq!(ruler_step(0.1), 1000.0);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(GridOptions::default().validate().is_ok());
+        assert!(GridOptions { spacing: 0.5, ..Default::default() }.validate().is_err());
+        assert!(GridOptions { subdivisions: 0, ..Default::default() }.validate().is_err());
+        assert!(GridOptions { subdivisions: 11, ..Default::default() }.validate().is_err());
+    }
+}
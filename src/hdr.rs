@@ -0,0 +1,236 @@
+//! HDR capture tone mapping
+//!
+//! The 8-bit desktop image of a screen in HDR mode clips everything brighter
+//! than SDR white. HDR screens are instead captured as 16-bit floating point
+//! scRGB, linear light where 1.0 is 80 nits, and tone mapped here to 8-bit
+//! sRGB with a choice of operators.
+
+use crate::color::linear_to_srgb;
+use crate::types::{AppError, AppResult};
+use image::{Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+
+/// Brightness of scRGB 1.0, in nits
+pub const SCRGB_WHITE_NITS: f32 = 80.0;
+
+/// Entries of the linear-to-sRGB encoding table
+const ENCODE_TABLE_SIZE: usize = 4096;
+
+/// How HDR values brighter than SDR white are brought into 8-bit range
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ToneMapping {
+    /// Cut off everything brighter than SDR white, like the 8-bit desktop image
+    Clip,
+    /// Extended Reinhard on luminance, with the brightest pixel mapped to white
+    Reinhard,
+    /// Filmic curve approximating the ACES reference rendering
+    #[default]
+    Aces,
+}
+
+impl ToneMapping {
+    /// Get all tone mapping operators
+    pub fn all() -> [ToneMapping; 3] {
+        [ToneMapping::Clip, ToneMapping::Reinhard, ToneMapping::Aces]
+    }
+}
+
+impl std::fmt::Display for ToneMapping {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ToneMapping::Clip => write!(f, "Clip"),
+            ToneMapping::Reinhard => write!(f, "Reinhard"),
+            ToneMapping::Aces => write!(f, "ACES"),
+        }
+    }
+}
+
+/// HDR capture settings
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HdrOptions {
+    /// Capture screens in HDR mode at full precision instead of the clipped 8-bit image
+    pub capture_hdr: bool,
+    pub tone_mapping: ToneMapping,
+    /// Brightness of SDR content on HDR screens, in nits, as set in the display settings
+    pub sdr_white_nits: f32,
+}
+
+impl Default for HdrOptions {
+    fn default() -> Self {
+        Self {
+            capture_hdr: true,
+            tone_mapping: ToneMapping::default(),
+            sdr_white_nits: SCRGB_WHITE_NITS,
+        }
+    }
+}
+
+impl HdrOptions {
+    /// Range of the SDR content brightness offered by the display settings
+    pub const SDR_WHITE_RANGE: std::ops::RangeInclusive<f32> = 80.0..=480.0;
+
+    /// Validate HDR options
+    pub fn validate(&self) -> AppResult<()> {
+        if !Self::SDR_WHITE_RANGE.contains(&self.sdr_white_nits) {
+            return Err(AppError::Settings(format!(
+                "SDRの白の明るさは{}〜{}ニトで指定してください",
+                Self::SDR_WHITE_RANGE.start(),
+                Self::SDR_WHITE_RANGE.end()
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// A linear scRGB image, as captured from an HDR screen
+#[derive(Debug, Clone, PartialEq)]
+pub struct HdrImage {
+    pub width: u32,
+    pub height: u32,
+    /// RGBA values, row by row
+    pub pixels: Vec<[f32; 4]>,
+}
+
+impl HdrImage {
+    /// Create an image from 16-bit floating point RGBA values, row by row
+    pub fn from_f16(width: u32, height: u32, values: &[u16]) -> AppResult<Self> {
+        if values.len() != width as usize * height as usize * 4 {
+            return Err(AppError::ImageProcessing(format!(
+                "HDR frame has {} values, expected {} for {}x{}",
+                values.len(),
+                width as usize * height as usize * 4,
+                width,
+                height
+            )));
+        }
+        let pixels = values
+            .chunks_exact(4)
+            .map(|pixel| [f16_to_f32(pixel[0]), f16_to_f32(pixel[1]), f16_to_f32(pixel[2]), f16_to_f32(pixel[3])])
+            .collect();
+        Ok(Self { width, height, pixels })
+    }
+}
+
+/// Convert an IEEE 754 half-precision value to `f32`
+pub fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = (bits >> 10) & 0x1F;
+    let mantissa = f32::from(bits & 0x03FF);
+    match exponent {
+        0 => sign * mantissa * 2f32.powi(-24),
+        0x1F if mantissa == 0.0 => sign * f32::INFINITY,
+        0x1F => f32::NAN,
+        _ => sign * (1.0 + mantissa / 1024.0) * 2f32.powi(i32::from(exponent) - 15),
+    }
+}
+
+/// Tone map an HDR capture to 8-bit sRGB
+///
+/// Values are first scaled so SDR white is 1.0. Out-of-gamut (negative)
+/// components are clipped, and the result is opaque.
+pub fn tone_map(image: &HdrImage, options: &HdrOptions) -> RgbaImage {
+    let scale = SCRGB_WHITE_NITS / options.sdr_white_nits;
+    let scaled = |pixel: &[f32; 4]| {
+        let channel = |value: f32| if value.is_finite() { value.max(0.0) * scale } else { 0.0 };
+        [channel(pixel[0]), channel(pixel[1]), channel(pixel[2])]
+    };
+    // The brightest pixel becomes white with Reinhard
+    let peak = match options.tone_mapping {
+        ToneMapping::Reinhard => image.pixels.iter().map(|pixel| luminance(scaled(pixel))).fold(1.0, f32::max),
+        _ => 1.0,
+    };
+    let encode: Vec<u8> = (0..ENCODE_TABLE_SIZE)
+        .map(|index| (linear_to_srgb(index as f32 / (ENCODE_TABLE_SIZE - 1) as f32) * 255.0).round() as u8)
+        .collect();
+
+    let mut output = RgbaImage::new(image.width, image.height);
+    for (target, pixel) in output.pixels_mut().zip(&image.pixels) {
+        let rgb = scaled(pixel);
+        let mapped = match options.tone_mapping {
+            ToneMapping::Clip => rgb,
+            ToneMapping::Reinhard => {
+                // Scale the channels by the ratio of mapped to original luminance
+                let l = luminance(rgb);
+                let ratio = (1.0 + l / (peak * peak)) / (1.0 + l);
+                rgb.map(|value| value * ratio)
+            }
+            ToneMapping::Aces => rgb.map(aces),
+        };
+        let [r, g, b] =
+            mapped.map(|value| encode[(value.clamp(0.0, 1.0) * (ENCODE_TABLE_SIZE - 1) as f32).round() as usize]);
+        *target = Rgba([r, g, b, 255]);
+    }
+    output
+}
+
+/// Relative luminance of linear Rec. 709 RGB
+fn luminance(rgb: [f32; 3]) -> f32 {
+    0.2126 * rgb[0] + 0.7152 * rgb[1] + 0.0722 * rgb[2]
+}
+
+/// Krzysztof Narkowicz's fit of the ACES filmic curve
+fn aces(x: f32) -> f32 {
+    (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(values: &[f32]) -> HdrImage {
+        HdrImage {
+            width: values.len() as u32,
+            height: 1,
+            pixels: values.iter().map(|&value| [value, value, value, 1.0]).collect(),
+        }
+    }
+
+    fn gray_levels(image: &RgbaImage) -> Vec<u8> {
+        image.pixels().map(|pixel| pixel[0]).collect()
+    }
+
+    #[test]
+    fn test_f16_to_f32() {
+        assert_eq!(f16_to_f32(0x3C00), 1.0);
+        assert_eq!(f16_to_f32(0x4000), 2.0);
+        assert_eq!(f16_to_f32(0xC000), -2.0);
+        assert_eq!(f16_to_f32(0x3555), 0.333_251_95);
+        assert_eq!(f16_to_f32(0x7BFF), 65504.0);
+        assert_eq!(f16_to_f32(0x0001), 2f32.powi(-24));
+        assert_eq!(f16_to_f32(0x7C00), f32::INFINITY);
+        assert!(f16_to_f32(0x7E00).is_nan());
+
+        let hdr = HdrImage::from_f16(1, 1, &[0x3C00, 0x4000, 0, 0x3C00]).unwrap();
+        assert_eq!(hdr.pixels, vec![[1.0, 2.0, 0.0, 1.0]]);
+        assert!(HdrImage::from_f16(2, 1, &[0; 4]).is_err());
+    }
+
+    #[test]
+    fn test_tone_mapping() {
+        let hdr = image(&[0.0, 0.214, 1.0, 2.0, 4.0, -1.0, f32::NAN]);
+        let clip = HdrOptions { tone_mapping: ToneMapping::Clip, ..HdrOptions::default() };
+        // SDR content is unchanged and highlights are lost
+        assert_eq!(gray_levels(&tone_map(&hdr, &clip)), vec![0, 127, 255, 255, 255, 0, 0]);
+
+        // The curves keep highlights apart, brightest last
+        for tone_mapping in [ToneMapping::Reinhard, ToneMapping::Aces] {
+            let levels = gray_levels(&tone_map(&hdr, &HdrOptions { tone_mapping, ..clip.clone() }));
+            assert!(levels[..5].windows(2).all(|pair| pair[0] < pair[1]), "{}: {:?}", tone_mapping, levels);
+            assert_eq!(levels[0], 0);
+        }
+        let reinhard = tone_map(&hdr, &HdrOptions { tone_mapping: ToneMapping::Reinhard, ..clip.clone() });
+        assert_eq!(reinhard.get_pixel(4, 0).0, [255, 255, 255, 255]);
+
+        // With brighter SDR content, twice scRGB white is SDR white
+        let bright = HdrOptions { sdr_white_nits: 160.0, ..clip };
+        assert_eq!(gray_levels(&tone_map(&hdr, &bright))[..4], [0, 92, 188, 255]);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(HdrOptions::default().validate().is_ok());
+        assert!(HdrOptions { sdr_white_nits: 40.0, ..HdrOptions::default() }.validate().is_err());
+        assert!(HdrOptions { sdr_white_nits: f32::NAN, ..HdrOptions::default() }.validate().is_err());
+    }
+}
@@ -0,0 +1,383 @@
+//! Capture history
+//!
+//! Every capture is recorded in a JSON-lines file in the per-user config
+//! directory: when it was taken, what was captured, its size, and where it
+//! was saved and uploaded. New captures are appended as single lines, so an
+//! interrupted write loses at most the last entry; lines that can't be read
+//! are skipped. The History window searches the log, re-opens and re-uploads
+//! files, and exports it to CSV for users who need to document their work.
+//!
+//! Small thumbnails of the [`RECENT_CAPTURES`] most recent captures are kept
+//! as PNG files in a folder next to the log, so the Recent Captures menu can
+//! show them without decoding full-size images.
+
+use crate::datetime::DateTime;
+use crate::decode::{self, DecodeLimits};
+use crate::files::write_atomic;
+use crate::settings::config_dir;
+use crate::types::{AppError, AppResult};
+use image::{GenericImageView, Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::io::{Cursor, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+/// History file in the config directory
+const HISTORY_FILE_NAME: &str = "history.jsonl";
+
+/// Thumbnail folder next to the history file
+const THUMBNAIL_DIR_NAME: &str = "thumbnails";
+
+/// Longest side of a capture thumbnail in pixels
+pub const THUMBNAIL_SIZE: u32 = 96;
+
+/// Number of recent captures whose thumbnails are kept
+pub const RECENT_CAPTURES: usize = 3;
+
+/// Get the default history file
+pub fn default_history_path() -> PathBuf {
+    config_dir().join(HISTORY_FILE_NAME)
+}
+
+/// One capture
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub id: Uuid,
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+    /// What was captured, e.g. "Monitor 1 (1920x1080 at (0, 0))"
+    pub target: String,
+    pub width: u32,
+    pub height: u32,
+    /// File the capture was saved to, if it was saved
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+    /// Link printed by the upload command, if the capture was uploaded
+    #[serde(default)]
+    pub upload_url: Option<String>,
+}
+
+impl HistoryEntry {
+    /// Create an entry for a capture taken at `captured_at`
+    pub fn new(captured_at: SystemTime, target: String, width: u32, height: u32) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            timestamp: captured_at.duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_secs()).unwrap_or_default(),
+            target,
+            width,
+            height,
+            path: None,
+            upload_url: None,
+        }
+    }
+
+    /// Get when the capture was taken
+    pub fn time(&self) -> DateTime {
+        DateTime::from_unix_seconds(self.timestamp)
+    }
+
+    /// Check whether the entry matches a search, ignoring case
+    ///
+    /// The target, saved file, upload link, and date are searched.
+    pub fn matches(&self, query: &str) -> bool {
+        let query = query.trim().to_lowercase();
+        if query.is_empty() {
+            return true;
+        }
+        [
+            Some(self.target.clone()),
+            self.path.as_ref().map(|path| path.display().to_string()),
+            self.upload_url.clone(),
+            Some(self.time().to_string()),
+        ]
+        .into_iter()
+        .flatten()
+        .any(|field| field.to_lowercase().contains(&query))
+    }
+}
+
+/// The capture log and its file
+#[derive(Debug)]
+pub struct CaptureHistory {
+    path: PathBuf,
+    /// Oldest first
+    entries: Vec<HistoryEntry>,
+}
+
+impl CaptureHistory {
+    /// Load the history from `path`, starting empty if the file doesn't exist
+    pub fn load(path: PathBuf) -> AppResult<Self> {
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let mut entries = Vec::new();
+        for (number, line) in text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+            match serde_json::from_str(line) {
+                Ok(entry) => entries.push(entry),
+                Err(e) => log::warn!("Skipping line {} of {}: {}", number + 1, path.display(), e),
+            }
+        }
+        Ok(Self { path, entries })
+    }
+
+    /// Get the file the history is kept in
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get all entries, oldest first
+    pub fn entries(&self) -> &[HistoryEntry] {
+        &self.entries
+    }
+
+    /// Get an entry by id
+    pub fn get(&self, id: Uuid) -> Option<&HistoryEntry> {
+        self.entries.iter().find(|entry| entry.id == id)
+    }
+
+    /// Get the `count` most recent entries, newest first
+    pub fn recent(&self, count: usize) -> Vec<&HistoryEntry> {
+        self.entries.iter().rev().take(count).collect()
+    }
+
+    /// Get the file a capture's thumbnail is kept in
+    pub fn thumbnail_path(&self, id: Uuid) -> PathBuf {
+        self.path.with_file_name(THUMBNAIL_DIR_NAME).join(format!("{}.png", id))
+    }
+
+    /// Keep a thumbnail of a recorded capture
+    ///
+    /// Thumbnails of captures no longer among the [`RECENT_CAPTURES`] most
+    /// recent are deleted.
+    pub fn save_thumbnail(&self, id: Uuid, thumbnail: &RgbaImage) -> AppResult<()> {
+        let path = self.thumbnail_path(id);
+        let mut png = Vec::new();
+        thumbnail
+            .write_to(&mut Cursor::new(&mut png), image::ImageOutputFormat::Png)
+            .map_err(|e| AppError::ImageProcessing(format!("サムネイルを作成できません: {}", e)))?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        write_atomic(&path, &png)?;
+        self.prune_thumbnails();
+        Ok(())
+    }
+
+    /// Load a capture's thumbnail, if it is kept
+    pub fn load_thumbnail(&self, id: Uuid) -> Option<RgbaImage> {
+        let path = self.thumbnail_path(id);
+        if !path.exists() {
+            return None;
+        }
+        match decode::decode_untrusted_file(&path, DecodeLimits::default()) {
+            Ok(image) => Some(image.to_rgba8()),
+            Err(e) => {
+                log::warn!("Failed to load thumbnail {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+
+    /// Delete the thumbnails of all but the most recent captures
+    fn prune_thumbnails(&self) {
+        let keep: Vec<PathBuf> = self.recent(RECENT_CAPTURES).iter().map(|entry| self.thumbnail_path(entry.id)).collect();
+        let Ok(files) = std::fs::read_dir(self.path.with_file_name(THUMBNAIL_DIR_NAME)) else {
+            return;
+        };
+        for path in files.flatten().map(|file| file.path()) {
+            if !keep.contains(&path) {
+                if let Err(e) = std::fs::remove_file(&path) {
+                    log::warn!("Failed to delete thumbnail {}: {}", path.display(), e);
+                }
+            }
+        }
+    }
+
+    /// Find the entries matching a search, newest first
+    pub fn search(&self, query: &str) -> Vec<&HistoryEntry> {
+        self.entries.iter().rev().filter(|entry| entry.matches(query)).collect()
+    }
+
+    /// Add an entry, appending it to the file
+    pub fn record(&mut self, entry: HistoryEntry) -> AppResult<()> {
+        let line = serialize(&entry)?;
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = std::fs::OpenOptions::new().create(true).append(true).open(&self.path)?;
+        file.write_all(format!("{}\n", line).as_bytes())?;
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Change an entry and rewrite the file, returning false if there is no such entry
+    pub fn update(&mut self, id: Uuid, change: impl FnOnce(&mut HistoryEntry)) -> AppResult<bool> {
+        let Some(entry) = self.entries.iter_mut().find(|entry| entry.id == id) else {
+            return Ok(false);
+        };
+        change(entry);
+        self.rewrite()?;
+        Ok(true)
+    }
+
+    /// Forget all entries and their thumbnails
+    pub fn clear(&mut self) -> AppResult<()> {
+        self.entries.clear();
+        self.prune_thumbnails();
+        self.rewrite()
+    }
+
+    fn rewrite(&self) -> AppResult<()> {
+        let mut text = String::new();
+        for entry in &self.entries {
+            text.push_str(&serialize(entry)?);
+            text.push('\n');
+        }
+        write_atomic(&self.path, text.as_bytes())
+    }
+}
+
+fn serialize(entry: &HistoryEntry) -> AppResult<String> {
+    serde_json::to_string(entry).map_err(|e| AppError::FileAccess(e.into()))
+}
+
+/// Shrink a capture to a thumbnail no larger than [`THUMBNAIL_SIZE`] on either side
+pub fn thumbnail(image: &impl GenericImageView<Pixel = Rgba<u8>>) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    let scale = (THUMBNAIL_SIZE as f32 / width.max(height).max(1) as f32).min(1.0);
+    let size = |side: u32| ((side as f32 * scale).round() as u32).max(1);
+    image::imageops::thumbnail(image, size(width), size(height))
+}
+
+/// Format entries as CSV, with a header row
+pub fn to_csv<'a>(entries: impl IntoIterator<Item = &'a HistoryEntry>) -> String {
+    let mut csv = String::from("Time (UTC),Target,Width,Height,File,Upload URL\r\n");
+    for entry in entries {
+        let time = entry.time();
+        let fields = [
+            format!(
+                "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+                time.year, time.month, time.day, time.hour, time.minute, time.second
+            ),
+            entry.target.clone(),
+            entry.width.to_string(),
+            entry.height.to_string(),
+            entry.path.as_ref().map(|path| path.display().to_string()).unwrap_or_default(),
+            entry.upload_url.clone().unwrap_or_default(),
+        ];
+        let row: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+        csv.push_str(&row.join(","));
+        csv.push_str("\r\n");
+    }
+    csv
+}
+
+/// Quote a CSV field if it needs it
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn entry(target: &str, seconds: u64) -> HistoryEntry {
+        HistoryEntry::new(UNIX_EPOCH + Duration::from_secs(seconds), target.to_string(), 1920, 1080)
+    }
+
+    #[test]
+    fn test_record_and_reload() {
+        let path = std::env::temp_dir().join(format!("lsa-history-{}", Uuid::new_v4())).join(HISTORY_FILE_NAME);
+        let mut history = CaptureHistory::load(path.clone()).unwrap();
+        assert!(history.entries().is_empty());
+
+        let first = entry("Monitor 1", 1_700_000_000);
+        let second = HistoryEntry {
+            path: Some(PathBuf::from("C:/Shots/bug.png")),
+            ..entry("Window: Notepad", 1_700_000_100)
+        };
+        history.record(first.clone()).unwrap();
+        history.record(second.clone()).unwrap();
+        assert!(history
+            .update(first.id, |entry| entry.upload_url = Some("https://example.com/a".to_string()))
+            .unwrap());
+        assert!(!history.update(Uuid::new_v4(), |_| {}).unwrap());
+
+        // A torn last line is skipped
+        let mut file = std::fs::OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"{\"id\": ").unwrap();
+        drop(file);
+
+        let reloaded = CaptureHistory::load(path.clone()).unwrap();
+        assert_eq!(reloaded.entries().len(), 2);
+        assert_eq!(reloaded.get(first.id).unwrap().upload_url.as_deref(), Some("https://example.com/a"));
+        assert_eq!(reloaded.entries()[1], second);
+
+        // Newest first, matching any field
+        let found: Vec<&str> = reloaded.search("").iter().map(|entry| entry.target.as_str()).collect();
+        assert_eq!(found, vec!["Window: Notepad", "Monitor 1"]);
+        assert_eq!(reloaded.search("NOTEPAD").len(), 1);
+        assert_eq!(reloaded.search("bug.png").len(), 1);
+        assert_eq!(reloaded.search("example.com").len(), 1);
+        assert_eq!(reloaded.search("2023-11-14").len(), 2);
+        assert!(reloaded.search("monitor 2").is_empty());
+
+        let mut reloaded = reloaded;
+        reloaded.clear().unwrap();
+        assert!(CaptureHistory::load(path.clone()).unwrap().entries().is_empty());
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn test_thumbnails_of_recent_captures() {
+        let path = std::env::temp_dir().join(format!("lsa-history-{}", Uuid::new_v4())).join(HISTORY_FILE_NAME);
+        let mut history = CaptureHistory::load(path.clone()).unwrap();
+        let capture = RgbaImage::from_pixel(1920, 1080, Rgba([40, 90, 200, 255]));
+        let small = thumbnail(&capture);
+        assert_eq!(small.dimensions(), (96, 54));
+        assert_eq!(thumbnail(&RgbaImage::new(40, 10)).dimensions(), (40, 10));
+
+        let entries: Vec<HistoryEntry> = (0..4).map(|index| entry(&format!("Monitor {}", index), index)).collect();
+        for entry in &entries {
+            history.record(entry.clone()).unwrap();
+            history.save_thumbnail(entry.id, &small).unwrap();
+        }
+        let recent: Vec<&str> = history.recent(RECENT_CAPTURES).iter().map(|entry| entry.target.as_str()).collect();
+        assert_eq!(recent, vec!["Monitor 3", "Monitor 2", "Monitor 1"]);
+
+        // Only the most recent thumbnails are kept
+        assert_eq!(history.load_thumbnail(entries[3].id), Some(small));
+        assert!(history.load_thumbnail(entries[1].id).is_some());
+        assert_eq!(history.load_thumbnail(entries[0].id), None);
+        assert!(!history.thumbnail_path(entries[0].id).exists());
+
+        history.clear().unwrap();
+        assert!(!history.thumbnail_path(entries[3].id).exists());
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn test_csv_export() {
+        let quoted = HistoryEntry {
+            path: Some(PathBuf::from("C:/Shots/a,b.png")),
+            ..entry("Window: \"Report\" - Word", 1_700_000_000)
+        };
+        let csv = to_csv([&entry("Monitor 1", 0), &quoted]);
+        let lines: Vec<&str> = csv.split("\r\n").collect();
+        assert_eq!(lines[0], "Time (UTC),Target,Width,Height,File,Upload URL");
+        assert_eq!(lines[1], "1970-01-01 00:00:00,Monitor 1,1920,1080,,");
+        assert_eq!(
+            lines[2],
+            "2023-11-14 22:13:20,\"Window: \"\"Report\"\" - Word\",1920,1080,\"C:/Shots/a,b.png\","
+        );
+        assert_eq!(lines[3], "");
+    }
+}
@@ -0,0 +1,302 @@
+//! Global capture hotkeys
+//!
+//! The screen hotkey (Ctrl+Shift+S by default), the window hotkey
+//! (Ctrl+Shift+W), and the hotkey capturing the monitor under the cursor
+//! (Ctrl+Shift+M) work while the editor is in the background, as does the
+//! color picker hotkey (Ctrl+Shift+C), which picks a color from the screen
+//! instead of capturing it. They are
+//! registered with the system on a dedicated thread whose message loop posts
+//! presses to the event bus as
+//! [`AppEvent::Hotkey`](crate::events::AppEvent::Hotkey). Capture presets can
+//! have hotkeys of their own, registered alongside. Global hotkeys are only
+//! available on Windows; elsewhere nothing is registered.
+
+use crate::events::EventSender;
+#[cfg(windows)]
+use crate::types::AppError;
+use crate::types::{AppResult, AppSettings, HotkeyEvent};
+
+/// Id of the hotkey capturing the screen
+pub const SCREEN_HOTKEY_ID: i32 = 1;
+
+/// Id of the hotkey capturing the focused window
+pub const WINDOW_HOTKEY_ID: i32 = 2;
+
+/// Id of the hotkey capturing the monitor containing the mouse cursor
+pub const CURSOR_MONITOR_HOTKEY_ID: i32 = 3;
+
+/// Id of the hotkey picking a color from the screen
+pub const COLOR_PICKER_HOTKEY_ID: i32 = 4;
+
+/// Id of the first capture preset's hotkey; later presets follow in order
+pub const PRESET_HOTKEY_BASE: i32 = 100;
+
+pub const MOD_ALT: u32 = 0x0001;
+pub const MOD_CONTROL: u32 = 0x0002;
+pub const MOD_SHIFT: u32 = 0x0004;
+pub const MOD_WIN: u32 = 0x0008;
+
+/// Modifier flags and their display names, in display order
+pub const MODIFIERS: [(u32, &str); 4] = [(MOD_CONTROL, "Ctrl"), (MOD_ALT, "Alt"), (MOD_SHIFT, "Shift"), (MOD_WIN, "Win")];
+
+/// Capture started by a hotkey
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HotkeyAction {
+    /// Capture the screen, like File > New Screenshot
+    CaptureScreen,
+    /// Capture only the focused window
+    CaptureWindow,
+    /// Capture the monitor containing the mouse cursor
+    CaptureCursorMonitor,
+    /// Run the capture preset at an index of the settings' presets
+    RunPreset(usize),
+    /// Pick a color from the screen under the cursor
+    PickColor,
+}
+
+impl HotkeyAction {
+    /// Get the action of a registered hotkey id
+    pub fn from_id(id: i32) -> Option<Self> {
+        match id {
+            SCREEN_HOTKEY_ID => Some(HotkeyAction::CaptureScreen),
+            WINDOW_HOTKEY_ID => Some(HotkeyAction::CaptureWindow),
+            CURSOR_MONITOR_HOTKEY_ID => Some(HotkeyAction::CaptureCursorMonitor),
+            COLOR_PICKER_HOTKEY_ID => Some(HotkeyAction::PickColor),
+            id if id >= PRESET_HOTKEY_BASE => Some(HotkeyAction::RunPreset((id - PRESET_HOTKEY_BASE) as usize)),
+            _ => None,
+        }
+    }
+}
+
+/// Get the hotkeys to register for the settings
+pub fn configured_hotkeys(settings: &AppSettings) -> Vec<HotkeyEvent> {
+    let mut hotkeys = vec![
+        HotkeyEvent {
+            id: SCREEN_HOTKEY_ID,
+            modifiers: settings.hotkey_modifiers,
+            vk_code: settings.hotkey_vk_code,
+        },
+        HotkeyEvent {
+            id: WINDOW_HOTKEY_ID,
+            modifiers: settings.window_hotkey.modifiers,
+            vk_code: settings.window_hotkey.vk_code,
+        },
+        HotkeyEvent {
+            id: CURSOR_MONITOR_HOTKEY_ID,
+            modifiers: settings.cursor_monitor_hotkey.modifiers,
+            vk_code: settings.cursor_monitor_hotkey.vk_code,
+        },
+        HotkeyEvent {
+            id: COLOR_PICKER_HOTKEY_ID,
+            modifiers: settings.color_picker_hotkey.modifiers,
+            vk_code: settings.color_picker_hotkey.vk_code,
+        },
+    ];
+    for (index, preset) in settings.capture_presets.iter().enumerate() {
+        if let Some(hotkey) = preset.hotkey {
+            hotkeys.push(HotkeyEvent {
+                id: PRESET_HOTKEY_BASE + index as i32,
+                modifiers: hotkey.modifiers,
+                vk_code: hotkey.vk_code,
+            });
+        }
+    }
+    hotkeys
+}
+
+/// Describe a hotkey for display, e.g. "Ctrl+Shift+W"
+pub fn describe(modifiers: u32, vk_code: u32) -> String {
+    let mut parts: Vec<String> = MODIFIERS
+        .iter()
+        .filter(|(flag, _)| modifiers & flag != 0)
+        .map(|(_, name)| name.to_string())
+        .collect();
+    let key = match vk_code {
+        0x30..=0x39 | 0x41..=0x5A => char::from(vk_code as u8).to_string(),
+        0x70..=0x87 => format!("F{}", vk_code - 0x6F),
+        _ => format!("0x{:02X}", vk_code),
+    };
+    parts.push(key);
+    parts.join("+")
+}
+
+/// Get the virtual key code of a key name as shown by [`describe`], e.g. "W" or "F9"
+pub fn parse_key(name: &str) -> Option<u32> {
+    let name = name.trim().to_ascii_uppercase();
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c @ ('0'..='9' | 'A'..='Z')), None) => Some(c as u32),
+        (Some('F'), Some(_)) => match name[1..].parse::<u32>() {
+            Ok(number @ 1..=24) => Some(0x6F + number),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Global hotkeys registered with the system
+///
+/// The hotkeys stay registered until the listener is dropped.
+#[derive(Debug)]
+pub struct HotkeyListener {
+    /// Thread running the message loop, told to quit on drop
+    #[cfg(windows)]
+    thread_id: u32,
+}
+
+impl HotkeyListener {
+    /// Register hotkeys, posting their presses to the event bus
+    ///
+    /// Hotkeys already taken by another application are skipped with a
+    /// warning rather than failing the others.
+    #[cfg(windows)]
+    pub fn start(hotkeys: Vec<HotkeyEvent>, events: EventSender) -> AppResult<Self> {
+        let (ready_sender, ready_receiver) = crossbeam_channel::bounded(1);
+        std::thread::Builder::new()
+            .name("hotkeys".to_string())
+            .spawn(move || windows::run_message_loop(&hotkeys, &events, &ready_sender))
+            .map_err(|e| AppError::HotkeyRegistration(format!("スレッドを開始できません: {}", e)))?;
+        let thread_id = ready_receiver
+            .recv()
+            .map_err(|_| AppError::HotkeyRegistration("ホットキースレッドが停止しました".to_string()))?;
+        Ok(Self { thread_id })
+    }
+
+    /// Register hotkeys, posting their presses to the event bus
+    ///
+    /// Global hotkeys are not supported on this platform, so nothing is
+    /// ever posted.
+    #[cfg(not(windows))]
+    pub fn start(hotkeys: Vec<HotkeyEvent>, events: EventSender) -> AppResult<Self> {
+        let _ = events;
+        log::info!("Global hotkeys are not supported on this platform; {} not registered", hotkeys.len());
+        Ok(Self {})
+    }
+}
+
+#[cfg(windows)]
+impl Drop for HotkeyListener {
+    fn drop(&mut self) {
+        use winapi::um::winuser::{PostThreadMessageW, WM_QUIT};
+        // SAFETY: posting to a thread that already exited fails harmlessly
+        unsafe { PostThreadMessageW(self.thread_id, WM_QUIT, 0, 0) };
+    }
+}
+
+#[cfg(windows)]
+mod windows {
+    use super::describe;
+    use crate::events::{AppEvent, EventSender};
+    use crate::types::HotkeyEvent;
+    use crossbeam_channel::Sender;
+    use std::ptr::null_mut;
+    use winapi::um::processthreadsapi::GetCurrentThreadId;
+    use winapi::um::winuser::{GetMessageW, RegisterHotKey, UnregisterHotKey, MOD_NOREPEAT, MSG, WM_HOTKEY};
+
+    /// Register the hotkeys and forward presses until WM_QUIT arrives
+    pub(super) fn run_message_loop(
+        hotkeys: &[HotkeyEvent],
+        events: &EventSender,
+        ready: &Sender<u32>,
+    ) {
+        // Without a window, hotkey presses are posted to this thread's message queue
+        let mut registered = Vec::new();
+        for hotkey in hotkeys {
+            let modifiers = hotkey.modifiers | MOD_NOREPEAT as u32;
+            // SAFETY: a null window handle associates the hotkey with the calling thread
+            if unsafe { RegisterHotKey(null_mut(), hotkey.id, modifiers, hotkey.vk_code) } != 0 {
+                registered.push(hotkey);
+            } else {
+                log::warn!(
+                    "Hotkey {} is already in use by another application",
+                    describe(hotkey.modifiers, hotkey.vk_code)
+                );
+            }
+        }
+        // SAFETY: GetCurrentThreadId has no preconditions
+        let _ = ready.send(unsafe { GetCurrentThreadId() });
+
+        let mut message: MSG = unsafe { std::mem::zeroed() };
+        // SAFETY: `message` is a writable MSG; GetMessageW returns 0 for WM_QUIT and -1 on error
+        while unsafe { GetMessageW(&mut message, null_mut(), 0, 0) } > 0 {
+            if message.message != WM_HOTKEY {
+                continue;
+            }
+            let Some(hotkey) = registered.iter().find(|hotkey| hotkey.id == message.wParam as i32) else {
+                continue;
+            };
+            if !events.send(AppEvent::Hotkey((*hotkey).clone())) {
+                break;
+            }
+        }
+
+        for hotkey in registered {
+            // SAFETY: the hotkey was registered by this thread
+            unsafe { UnregisterHotKey(null_mut(), hotkey.id) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::presets::CapturePreset;
+    use crate::types::Hotkey;
+
+    #[test]
+    fn test_configured_hotkeys() {
+        let hotkeys = configured_hotkeys(&AppSettings::default());
+        let described: Vec<(Option<HotkeyAction>, String)> = hotkeys
+            .iter()
+            .map(|hotkey| (HotkeyAction::from_id(hotkey.id), describe(hotkey.modifiers, hotkey.vk_code)))
+            .collect();
+        assert_eq!(
+            described,
+            vec![
+                (Some(HotkeyAction::CaptureScreen), "Ctrl+Shift+S".to_string()),
+                (Some(HotkeyAction::CaptureWindow), "Ctrl+Shift+W".to_string()),
+                (Some(HotkeyAction::CaptureCursorMonitor), "Ctrl+Shift+M".to_string()),
+                (Some(HotkeyAction::PickColor), "Ctrl+Shift+C".to_string()),
+            ]
+        );
+
+        assert_eq!(describe(MOD_ALT | MOD_WIN, 0x71), "Alt+Win+F2");
+        assert_eq!(describe(0, 0x2C), "0x2C");
+        assert_eq!(HotkeyAction::from_id(5), None);
+    }
+
+    #[test]
+    fn test_preset_hotkeys() {
+        let settings = AppSettings {
+            capture_presets: vec![
+                CapturePreset { name: "Clipboard".to_string(), ..CapturePreset::default() },
+                CapturePreset {
+                    name: "Window".to_string(),
+                    hotkey: Some(Hotkey { modifiers: MOD_CONTROL, vk_code: 0x78 }),
+                    ..CapturePreset::default()
+                },
+            ],
+            ..AppSettings::default()
+        };
+        let hotkeys = configured_hotkeys(&settings);
+        assert_eq!(hotkeys.len(), 5);
+        // Ids follow the preset's position, including presets without a hotkey
+        assert_eq!(HotkeyAction::from_id(hotkeys[4].id), Some(HotkeyAction::RunPreset(1)));
+        assert_eq!(describe(hotkeys[4].modifiers, hotkeys[4].vk_code), "Ctrl+F9");
+    }
+
+    #[test]
+    fn test_parse_key() {
+        assert_eq!(parse_key("w"), Some(0x57));
+        assert_eq!(parse_key(" 5 "), Some(0x35));
+        assert_eq!(parse_key("F1"), Some(0x70));
+        assert_eq!(parse_key("f24"), Some(0x87));
+        assert_eq!(parse_key("F25"), None);
+        assert_eq!(parse_key("F"), Some(0x46));
+        assert_eq!(parse_key("Space"), None);
+        assert_eq!(parse_key(""), None);
+        for vk_code in [0x30, 0x41, 0x5A, 0x70, 0x87] {
+            assert_eq!(parse_key(&describe(0, vk_code)), Some(vk_code));
+        }
+    }
+}
@@ -0,0 +1,448 @@
+//! Whole-image operations
+//!
+//! Operations that change the size of the edited image, such as trimming
+//! the uniform borders left around a window captured with some desktop
+//! background, or straightening a photographed screen with a perspective
+//! warp. They work on image pixels and leave moving annotations to the
+//! editor. Window captures are also cleaned up here, cutting out their
+//! rounded corners and framing them in a drop shadow on transparency.
+
+use crate::types::{AppError, AppResult};
+use egui::{Pos2, Rect, Vec2};
+use image::{Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+
+/// Options of the Auto Trim action
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrimOptions {
+    /// Largest difference of any channel from the border color for a pixel to count as border
+    pub tolerance: u8,
+}
+
+impl Default for TrimOptions {
+    fn default() -> Self {
+        Self { tolerance: 8 }
+    }
+}
+
+impl TrimOptions {
+    /// Largest allowed tolerance; above it, most images would be trimmed away
+    pub const MAX_TOLERANCE: u8 = 128;
+
+    /// Check that the options are usable
+    pub fn validate(&self) -> AppResult<()> {
+        if self.tolerance > Self::MAX_TOLERANCE {
+            return Err(AppError::Settings(format!(
+                "自動トリミングの許容値は {} 以下にしてください",
+                Self::MAX_TOLERANCE
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Find the part of an image left after removing uniform borders, in image pixels
+///
+/// Each edge is trimmed on its own, so the borders may have different
+/// colors. A row or column is border when all of its pixels are within
+/// `tolerance` of the color of the edge's outermost corner pixel. Returns
+/// `None` if there is no border, or if the whole image is uniform.
+pub fn trim_bounds(image: &RgbaImage, tolerance: u8) -> Option<Rect> {
+    let (width, height) = image.dimensions();
+    if width == 0 || height == 0 {
+        return None;
+    }
+    let matches = |color: Rgba<u8>, x: u32, y: u32| {
+        let pixel = image.get_pixel(x, y);
+        pixel.0.iter().zip(color.0).all(|(a, b)| a.abs_diff(b) <= tolerance)
+    };
+    let row_is_border = |y: u32, color: Rgba<u8>| (0..width).all(|x| matches(color, x, y));
+
+    let top_color = *image.get_pixel(0, 0);
+    let top = (0..height).take_while(|&y| row_is_border(y, top_color)).count() as u32;
+    if top == height {
+        return None;
+    }
+    let bottom_color = *image.get_pixel(0, height - 1);
+    let bottom = (top..height).rev().take_while(|&y| row_is_border(y, bottom_color)).count() as u32;
+    let (min_y, max_y) = (top, height - bottom);
+
+    // Columns only need to match within the rows that are kept
+    let column_is_border = |x: u32, color: Rgba<u8>| (min_y..max_y).all(|y| matches(color, x, y));
+    let left_color = *image.get_pixel(0, min_y);
+    let left = (0..width).take_while(|&x| column_is_border(x, left_color)).count() as u32;
+    let right_color = *image.get_pixel(width - 1, min_y);
+    let right = (left..width).rev().take_while(|&x| column_is_border(x, right_color)).count() as u32;
+    let (min_x, max_x) = (left, width - right);
+
+    if min_x >= max_x || (min_x, min_y, max_x, max_y) == (0, 0, width, height) {
+        return None;
+    }
+    Some(Rect::from_min_max(
+        Pos2::new(min_x as f32, min_y as f32),
+        Pos2::new(max_x as f32, max_y as f32),
+    ))
+}
+
+/// Projective transform of the plane, mapping any quadrilateral onto any other
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Homography {
+    /// Row-major 3×3 matrix, with the last entry fixed at 1
+    matrix: [f64; 9],
+}
+
+impl Homography {
+    /// Find the transform mapping each point of `from` onto the matching point of `to`
+    ///
+    /// Returns `None` if three of either set of points lie on a line.
+    pub fn from_points(from: [Pos2; 4], to: [Pos2; 4]) -> Option<Self> {
+        // Two equations per point pair in the eight unknown matrix entries
+        let mut system = [[0.0f64; 9]; 8];
+        for (i, (p, q)) in from.iter().zip(&to).enumerate() {
+            let (x, y, u, v) = (p.x as f64, p.y as f64, q.x as f64, q.y as f64);
+            system[2 * i] = [x, y, 1.0, 0.0, 0.0, 0.0, -u * x, -u * y, u];
+            system[2 * i + 1] = [0.0, 0.0, 0.0, x, y, 1.0, -v * x, -v * y, v];
+        }
+        let solution = solve(system)?;
+        let mut matrix = [1.0; 9];
+        matrix[..8].copy_from_slice(&solution);
+        Some(Self { matrix })
+    }
+
+    /// Map a point through the transform
+    pub fn map(&self, point: Pos2) -> Pos2 {
+        let [a, b, c, d, e, f, g, h, i] = self.matrix;
+        let (x, y) = (point.x as f64, point.y as f64);
+        let w = g * x + h * y + i;
+        Pos2::new(((a * x + b * y + c) / w) as f32, ((d * x + e * y + f) / w) as f32)
+    }
+}
+
+/// Solve a linear system of eight equations given as augmented rows, by Gaussian elimination
+fn solve(mut system: [[f64; 9]; 8]) -> Option<[f64; 8]> {
+    for column in 0..8 {
+        let pivot = (column..8).max_by(|&a, &b| system[a][column].abs().total_cmp(&system[b][column].abs()))?;
+        if system[pivot][column].abs() < 1e-9 {
+            return None;
+        }
+        system.swap(column, pivot);
+        let pivot_row = system[column];
+        for (index, row) in system.iter_mut().enumerate() {
+            if index != column {
+                let factor = row[column] / pivot_row[column];
+                for (value, pivot_value) in row.iter_mut().zip(pivot_row).skip(column) {
+                    *value -= factor * pivot_value;
+                }
+            }
+        }
+    }
+    Some(std::array::from_fn(|row| system[row][8] / system[row][row]))
+}
+
+/// Get the size of the rectangle a quadrilateral is straightened into
+///
+/// Corners are in the order top-left, top-right, bottom-right, bottom-left.
+/// Each side of the rectangle is as long as the longer of the two opposite
+/// sides of the quadrilateral, so no detail is lost.
+pub fn straightened_size(corners: [Pos2; 4]) -> (u32, u32) {
+    let [top_left, top_right, bottom_right, bottom_left] = corners;
+    let width = top_left.distance(top_right).max(bottom_left.distance(bottom_right));
+    let height = top_left.distance(bottom_left).max(top_right.distance(bottom_right));
+    (width.round() as u32, height.round() as u32)
+}
+
+/// Check whether corners in order form a convex quadrilateral, in either direction
+fn is_convex(corners: [Pos2; 4]) -> bool {
+    let turns: Vec<f32> = (0..4)
+        .map(|i| {
+            let [a, b, c] = [corners[i], corners[(i + 1) % 4], corners[(i + 2) % 4]];
+            (b - a).x * (c - b).y - (b - a).y * (c - b).x
+        })
+        .collect();
+    turns.iter().all(|turn| *turn > 0.0) || turns.iter().all(|turn| *turn < 0.0)
+}
+
+/// Warp the quadrilateral within `corners` of an image into a straight rectangle
+///
+/// Corners are in image pixels, in the order top-left, top-right,
+/// bottom-right, bottom-left. Pixels are sampled bilinearly; parts of the
+/// quadrilateral outside the image become transparent.
+pub fn warp_perspective(image: &RgbaImage, corners: [Pos2; 4]) -> AppResult<RgbaImage> {
+    let invalid = || AppError::ImageProcessing("四隅が四角形を囲んでいません".to_string());
+    if !is_convex(corners) {
+        return Err(invalid());
+    }
+    let (width, height) = straightened_size(corners);
+    if width == 0 || height == 0 {
+        return Err(invalid());
+    }
+    let (w, h) = (width as f32, height as f32);
+    let rectangle = [Pos2::ZERO, Pos2::new(w, 0.0), Pos2::new(w, h), Pos2::new(0.0, h)];
+    let to_source = Homography::from_points(rectangle, corners).ok_or_else(invalid)?;
+    Ok(RgbaImage::from_fn(width, height, |x, y| {
+        let source = to_source.map(Pos2::new(x as f32 + 0.5, y as f32 + 0.5));
+        sample_bilinear(image, source - Vec2::splat(0.5))
+    }))
+}
+
+/// Sample an image between pixel centers, treating pixels outside it as transparent
+fn sample_bilinear(image: &RgbaImage, point: Pos2) -> Rgba<u8> {
+    let (x0, y0) = (point.x.floor(), point.y.floor());
+    let (fx, fy) = (point.x - x0, point.y - y0);
+    let pixel = |x: f32, y: f32| {
+        if x < 0.0 || y < 0.0 || x >= image.width() as f32 || y >= image.height() as f32 {
+            [0.0; 4]
+        } else {
+            image.get_pixel(x as u32, y as u32).0.map(f32::from)
+        }
+    };
+    let [a, b, c, d] = [pixel(x0, y0), pixel(x0 + 1.0, y0), pixel(x0, y0 + 1.0), pixel(x0 + 1.0, y0 + 1.0)];
+    Rgba(std::array::from_fn(|i| {
+        let top = a[i] + (b[i] - a[i]) * fx;
+        let bottom = c[i] + (d[i] - c[i]) * fx;
+        (top + (bottom - top) * fy).round().clamp(0.0, 255.0) as u8
+    }))
+}
+
+/// A soft shadow cast by a window onto a transparent background
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DropShadow {
+    /// Distance over which the shadow fades out, in pixels
+    pub blur: f32,
+    /// Shift of the shadow from the window, in pixels
+    pub offset: Vec2,
+    /// Opacity of the shadow under the window, 0 to 1
+    pub opacity: f32,
+}
+
+impl DropShadow {
+    /// A shadow resembling the one Windows 11 draws around windows at 100% scaling
+    pub const WINDOW: DropShadow = DropShadow {
+        blur: 16.0,
+        offset: Vec2::new(0.0, 4.0),
+        opacity: 0.4,
+    };
+
+    /// Scale the shadow for a display scaling factor
+    pub fn scaled(self, scale: f32) -> Self {
+        Self {
+            blur: self.blur * scale,
+            offset: self.offset * scale,
+            ..self
+        }
+    }
+
+    /// Transparent margin needed around an image on each side to fit the shadow
+    fn margin(&self) -> u32 {
+        (self.blur + self.offset.x.abs().max(self.offset.y.abs())).ceil() as u32
+    }
+}
+
+/// Make the corners of an image outside rounded corners of `radius` transparent
+///
+/// Pixels on the curve are partly transparent, so the corners stay smooth.
+pub fn round_corners(image: &mut RgbaImage, radius: f32) {
+    let (width, height) = image.dimensions();
+    let radius = radius.min(width.min(height) as f32 / 2.0);
+    if radius <= 0.0 {
+        return;
+    }
+    let reach = radius.ceil() as u32;
+    let bounds = Rect::from_min_size(Pos2::ZERO, Vec2::new(width as f32, height as f32));
+    for y in (0..reach).chain(height.saturating_sub(reach).max(reach)..height) {
+        for x in (0..reach).chain(width.saturating_sub(reach).max(reach)..width) {
+            let center = Pos2::new(x as f32 + 0.5, y as f32 + 0.5);
+            let coverage = (0.5 - rounded_rect_distance(center, bounds, radius)).clamp(0.0, 1.0);
+            let pixel = image.get_pixel_mut(x, y);
+            pixel.0[3] = (pixel.0[3] as f32 * coverage).round() as u8;
+        }
+    }
+}
+
+/// Put an image with rounded corners of `corner_radius` on a transparent background with a drop shadow
+///
+/// The image grows by the shadow's margin on every side.
+pub fn add_drop_shadow(image: &RgbaImage, corner_radius: f32, shadow: DropShadow) -> RgbaImage {
+    let margin = shadow.margin();
+    let (width, height) = image.dimensions();
+    let shape = Rect::from_min_size(Pos2::new(margin as f32, margin as f32), Vec2::new(width as f32, height as f32))
+        .translate(shadow.offset);
+    let radius = corner_radius.min(width.min(height) as f32 / 2.0).max(0.0);
+    let mut framed = RgbaImage::from_fn(width + 2 * margin, height + 2 * margin, |x, y| {
+        let distance = rounded_rect_distance(Pos2::new(x as f32 + 0.5, y as f32 + 0.5), shape, radius);
+        // Fade out smoothly over the blur distance
+        let t = (1.0 - distance / shadow.blur.max(1.0)).clamp(0.0, 1.0);
+        let alpha = shadow.opacity * t * t * (3.0 - 2.0 * t);
+        Rgba([0, 0, 0, (alpha * 255.0).round() as u8])
+    });
+    for (x, y, pixel) in image.enumerate_pixels() {
+        let target = framed.get_pixel_mut(x + margin, y + margin);
+        *target = blend_over(*pixel, *target);
+    }
+    framed
+}
+
+/// Get the distance from a point to a rectangle with rounded corners, negative inside it
+fn rounded_rect_distance(point: Pos2, rect: Rect, radius: f32) -> f32 {
+    let half = rect.size() / 2.0 - Vec2::splat(radius);
+    let offset = (point - rect.center()).abs() - half;
+    offset.max(Vec2::ZERO).length() + offset.x.max(offset.y).min(0.0) - radius
+}
+
+/// Composite a straight-alpha pixel over another
+fn blend_over(top: Rgba<u8>, bottom: Rgba<u8>) -> Rgba<u8> {
+    let top_alpha = top.0[3] as f32 / 255.0;
+    let bottom_alpha = bottom.0[3] as f32 / 255.0 * (1.0 - top_alpha);
+    let alpha = top_alpha + bottom_alpha;
+    if alpha <= 0.0 {
+        return Rgba([0, 0, 0, 0]);
+    }
+    Rgba(std::array::from_fn(|i| {
+        if i == 3 {
+            (alpha * 255.0).round() as u8
+        } else {
+            ((top.0[i] as f32 * top_alpha + bottom.0[i] as f32 * bottom_alpha) / alpha).round() as u8
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Image of `background` with a `content` rectangle from `min` to `max` (exclusive)
+    fn framed(size: (u32, u32), background: Rgba<u8>, content: Rgba<u8>, min: (u32, u32), max: (u32, u32)) -> RgbaImage {
+        RgbaImage::from_fn(size.0, size.1, |x, y| {
+            if (min.0..max.0).contains(&x) && (min.1..max.1).contains(&y) {
+                content
+            } else {
+                background
+            }
+        })
+    }
+
+    fn rect(min: (f32, f32), max: (f32, f32)) -> Rect {
+        Rect::from_min_max(Pos2::new(min.0, min.1), Pos2::new(max.0, max.1))
+    }
+
+    #[test]
+    fn test_trim_uniform_border() {
+        let desktop = Rgba([0, 120, 215, 255]);
+        let window = framed((40, 30), desktop, Rgba([255, 255, 255, 255]), (5, 3), (31, 27));
+        assert_eq!(trim_bounds(&window, 0), Some(rect((5.0, 3.0), (31.0, 27.0))));
+
+        // Nothing to trim on a plain image or an image without borders
+        assert_eq!(trim_bounds(&RgbaImage::from_pixel(8, 8, desktop), 0), None);
+        assert_eq!(trim_bounds(&framed((10, 10), desktop, Rgba([0, 0, 0, 255]), (0, 0), (10, 10)), 0), None);
+    }
+
+    #[test]
+    fn test_trim_tolerance() {
+        // Slightly noisy desktop background around the content
+        let mut image = framed((20, 20), Rgba([100, 100, 100, 255]), Rgba([0, 0, 0, 255]), (4, 4), (16, 16));
+        image.put_pixel(1, 1, Rgba([104, 97, 100, 255]));
+        assert_eq!(trim_bounds(&image, 0), Some(rect((1.0, 1.0), (16.0, 16.0))));
+        assert_eq!(trim_bounds(&image, 4), Some(rect((4.0, 4.0), (16.0, 16.0))));
+    }
+
+    #[test]
+    fn test_trim_edges_with_different_colors() {
+        // A band of another color along the bottom, as when a taskbar is captured
+        let mut image = framed((30, 20), Rgba([255, 0, 0, 255]), Rgba([0, 255, 0, 255]), (6, 2), (24, 14));
+        for x in 0..30 {
+            for y in 17..20 {
+                image.put_pixel(x, y, Rgba([20, 20, 20, 255]));
+            }
+        }
+        // The red rows between the content and the band are kept, as the bottom edge is dark
+        assert_eq!(trim_bounds(&image, 0), Some(rect((6.0, 2.0), (24.0, 17.0))));
+    }
+
+    #[test]
+    fn test_homography_maps_corners() {
+        let square = [Pos2::ZERO, Pos2::new(1.0, 0.0), Pos2::new(1.0, 1.0), Pos2::new(0.0, 1.0)];
+        let quad = [Pos2::new(10.0, 5.0), Pos2::new(50.0, 0.0), Pos2::new(60.0, 40.0), Pos2::new(0.0, 30.0)];
+        let homography = Homography::from_points(square, quad).unwrap();
+        for (from, to) in square.into_iter().zip(quad) {
+            assert!(homography.map(from).distance(to) < 1e-3);
+        }
+
+        // Three points on a line can't be mapped onto a quadrilateral
+        let collinear = [Pos2::ZERO, Pos2::new(1.0, 0.0), Pos2::new(2.0, 0.0), Pos2::new(0.0, 1.0)];
+        assert_eq!(Homography::from_points(collinear, quad), None);
+    }
+
+    #[test]
+    fn test_warp_straightens_trapezoid() {
+        // Left half red and right half blue, seen as a trapezoid narrower at the top
+        let image = RgbaImage::from_fn(40, 40, |x, _| {
+            if x < 20 {
+                Rgba([255, 0, 0, 255])
+            } else {
+                Rgba([0, 0, 255, 255])
+            }
+        });
+        let corners = [Pos2::new(10.0, 0.0), Pos2::new(30.0, 0.0), Pos2::new(40.0, 40.0), Pos2::new(0.0, 40.0)];
+        assert_eq!(straightened_size(corners), (40, 41));
+
+        let warped = warp_perspective(&image, corners).unwrap();
+        assert_eq!(warped.dimensions(), (40, 41));
+        // The center line stays in the middle, with each color filling its half
+        for y in [1, 20, 39] {
+            assert_eq!(warped.get_pixel(5, y).0, [255, 0, 0, 255]);
+            assert_eq!(warped.get_pixel(34, y).0, [0, 0, 255, 255]);
+        }
+    }
+
+    #[test]
+    fn test_warp_whole_image_keeps_pixels() {
+        let image = RgbaImage::from_fn(16, 8, |x, y| Rgba([x as u8 * 10, y as u8 * 20, 0, 255]));
+        let corners = [Pos2::ZERO, Pos2::new(16.0, 0.0), Pos2::new(16.0, 8.0), Pos2::new(0.0, 8.0)];
+        assert_eq!(warp_perspective(&image, corners).unwrap(), image);
+
+        // Corners crossing over each other don't enclose a quadrilateral
+        let crossed = [Pos2::ZERO, Pos2::new(16.0, 8.0), Pos2::new(16.0, 0.0), Pos2::new(0.0, 8.0)];
+        assert!(warp_perspective(&image, crossed).is_err());
+    }
+
+    #[test]
+    fn test_round_corners() {
+        let mut image = RgbaImage::from_pixel(40, 30, Rgba([200, 200, 200, 255]));
+        round_corners(&mut image, 8.0);
+        for (x, y) in [(0, 0), (39, 0), (0, 29), (39, 29)] {
+            assert_eq!(image.get_pixel(x, y).0[3], 0);
+        }
+        // Pixels on the curve are partly covered, and the edges between the corners are kept
+        let curve = image.get_pixel(2, 2).0[3];
+        assert!(curve > 0 && curve < 255, "alpha {}", curve);
+        assert_eq!(image.get_pixel(20, 0).0, [200, 200, 200, 255]);
+        assert_eq!(image.get_pixel(0, 15).0, [200, 200, 200, 255]);
+        assert_eq!(image.get_pixel(8, 8).0[3], 255);
+    }
+
+    #[test]
+    fn test_drop_shadow_frames_the_image() {
+        let image = RgbaImage::from_pixel(40, 30, Rgba([255, 255, 255, 255]));
+        let shadow = DropShadow::WINDOW;
+        let framed = add_drop_shadow(&image, 0.0, shadow);
+        let margin = 20;
+        assert_eq!(framed.dimensions(), (40 + 2 * margin, 30 + 2 * margin));
+        assert_eq!(framed.get_pixel(margin, margin).0, [255, 255, 255, 255]);
+        assert_eq!(framed.get_pixel(0, 0).0[3], 0);
+
+        // The shadow is offset downwards, so it is darker below the window than above it
+        let above = framed.get_pixel(margin + 20, margin - 4).0[3];
+        let below = framed.get_pixel(margin + 20, margin + 30 + 4).0[3];
+        assert!(below > above && above > 0, "above {} below {}", above, below);
+        assert!(framed.pixels().all(|p| p.0[3] == 255 || p.0[..3] == [0, 0, 0]));
+
+        assert_eq!(shadow.scaled(1.5).margin(), 30);
+    }
+
+    #[test]
+    fn test_validate_tolerance() {
+        assert!(TrimOptions::default().validate().is_ok());
+        assert!(TrimOptions { tolerance: TrimOptions::MAX_TOLERANCE + 1 }.validate().is_err());
+    }
+}
@@ -0,0 +1,184 @@
+//! Compressed in-memory image storage
+//!
+//! Documents that are not currently being edited keep their pixels as a
+//! PNG-encoded buffer instead of decoded RGBA, and are decoded lazily when
+//! they become active again. This bounds memory use when many documents
+//! are open at once.
+
+use crate::types::{AppError, AppResult};
+use image::DynamicImage;
+use std::io::Cursor;
+
+/// PNG-encoded image data with its dimensions
+#[derive(Debug, Clone)]
+pub struct EncodedImage {
+    data: Vec<u8>,
+    width: u32,
+    height: u32,
+}
+
+impl EncodedImage {
+    /// Encode an image as PNG
+    pub fn encode(image: &DynamicImage) -> AppResult<Self> {
+        let mut data = Vec::new();
+        image
+            .write_to(&mut Cursor::new(&mut data), image::ImageOutputFormat::Png)
+            .map_err(|e| AppError::ImageProcessing(format!("PNGエンコードに失敗しました: {}", e)))?;
+        Ok(Self {
+            data,
+            width: image.width(),
+            height: image.height(),
+        })
+    }
+
+    /// Decode the PNG data back into an image
+    pub fn decode(&self) -> AppResult<DynamicImage> {
+        image::load_from_memory_with_format(&self.data, image::ImageFormat::Png)
+            .map_err(|e| AppError::ImageProcessing(format!("PNGデコードに失敗しました: {}", e)))
+    }
+
+    /// Get the encoded PNG bytes
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+/// Image storage that is either decoded pixels or a compressed encoding
+#[derive(Debug, Clone)]
+pub enum ImageStore {
+    Decoded(DynamicImage),
+    Encoded(EncodedImage),
+}
+
+impl ImageStore {
+    /// Create a store holding decoded pixels
+    pub fn new(image: DynamicImage) -> Self {
+        ImageStore::Decoded(image)
+    }
+
+    /// Check whether the pixels are currently decoded
+    pub fn is_decoded(&self) -> bool {
+        matches!(self, ImageStore::Decoded(_))
+    }
+
+    /// Get the image dimensions without decoding
+    pub fn dimensions(&self) -> (u32, u32) {
+        match self {
+            ImageStore::Decoded(image) => (image.width(), image.height()),
+            ImageStore::Encoded(encoded) => (encoded.width, encoded.height),
+        }
+    }
+
+    /// Get the decoded image if it is currently in memory
+    pub fn get(&self) -> Option<&DynamicImage> {
+        match self {
+            ImageStore::Decoded(image) => Some(image),
+            ImageStore::Encoded(_) => None,
+        }
+    }
+
+    /// Compress the pixels, releasing the decoded buffer
+    pub fn compress(&mut self) -> AppResult<()> {
+        if let ImageStore::Decoded(image) = self {
+            *self = ImageStore::Encoded(EncodedImage::encode(image)?);
+        }
+        Ok(())
+    }
+
+    /// Decode the pixels if needed and return them
+    pub fn decode(&mut self) -> AppResult<&DynamicImage> {
+        if let ImageStore::Encoded(encoded) = self {
+            *self = ImageStore::Decoded(encoded.decode()?);
+        }
+        match self {
+            ImageStore::Decoded(image) => Ok(image),
+            ImageStore::Encoded(_) => unreachable!("image was just decoded"),
+        }
+    }
+
+    /// Get a decoded copy of the image, leaving the store as it is
+    pub fn to_image(&self) -> AppResult<DynamicImage> {
+        match self {
+            ImageStore::Decoded(image) => Ok(image.clone()),
+            ImageStore::Encoded(encoded) => encoded.decode(),
+        }
+    }
+
+    /// Consume the store and return the decoded image
+    pub fn into_image(self) -> AppResult<DynamicImage> {
+        match self {
+            ImageStore::Decoded(image) => Ok(image),
+            ImageStore::Encoded(encoded) => encoded.decode(),
+        }
+    }
+
+    /// Approximate number of bytes used by the stored pixels
+    pub fn memory_usage(&self) -> usize {
+        match self {
+            ImageStore::Decoded(image) => image.as_bytes().len(),
+            ImageStore::Encoded(encoded) => encoded.data.len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    fn test_image() -> DynamicImage {
+        let mut image = RgbaImage::from_pixel(64, 32, Rgba([10, 20, 30, 255]));
+        image.put_pixel(5, 7, Rgba([200, 100, 50, 255]));
+        DynamicImage::ImageRgba8(image)
+    }
+
+    #[test]
+    fn test_store_starts_decoded() {
+        let store = ImageStore::new(test_image());
+        assert!(store.is_decoded());
+        assert!(store.get().is_some());
+        assert_eq!(store.dimensions(), (64, 32));
+    }
+
+    #[test]
+    fn test_compress_and_decode_round_trip() {
+        let original = test_image();
+        let mut store = ImageStore::new(original.clone());
+
+        store.compress().unwrap();
+        assert!(!store.is_decoded());
+        assert!(store.get().is_none());
+        assert_eq!(store.dimensions(), (64, 32));
+
+        let decoded = store.decode().unwrap();
+        assert_eq!(decoded.to_rgba8(), original.to_rgba8());
+        assert!(store.is_decoded());
+    }
+
+    #[test]
+    fn test_compress_reduces_memory() {
+        let mut store = ImageStore::new(test_image());
+        let decoded_size = store.memory_usage();
+        store.compress().unwrap();
+        assert!(store.memory_usage() < decoded_size);
+    }
+
+    #[test]
+    fn test_compress_is_idempotent() {
+        let mut store = ImageStore::new(test_image());
+        store.compress().unwrap();
+        let size = store.memory_usage();
+        store.compress().unwrap();
+        assert_eq!(store.memory_usage(), size);
+    }
+
+    #[test]
+    fn test_into_image_from_encoded() {
+        let original = test_image();
+        let mut store = ImageStore::new(original.clone());
+        store.compress().unwrap();
+        assert_eq!(store.to_image().unwrap().to_rgba8(), original.to_rgba8());
+        assert!(!store.is_decoded());
+        assert_eq!(store.into_image().unwrap().to_rgba8(), original.to_rgba8());
+    }
+}
@@ -0,0 +1,432 @@
+//! Annotation import from other tools
+//!
+//! Converts annotations from ShareX shape JSON and simple SVG overlays into
+//! [`AnnotationItem`]s. Only rectangles and text map onto this crate's model;
+//! other shapes (arrows, blur, ellipses, ...) are skipped and counted so the
+//! user can be told what was left out.
+
+use crate::types::{AnnotationItem, AnnotationType, AppError, AppResult};
+use egui::{Color32, Pos2, Vec2};
+use serde_json::Value;
+use std::path::Path;
+
+/// File extensions that can be imported
+pub const IMPORT_EXTENSIONS: [&str; 2] = ["json", "svg"];
+
+/// Fraction of the font size between the top of a text line and its baseline
+pub(crate) const BASELINE_RATIO: f32 = 0.8;
+
+/// Annotations converted from another tool's format
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImportResult {
+    pub annotations: Vec<AnnotationItem>,
+    /// Shapes with no equivalent annotation type
+    pub skipped: usize,
+}
+
+/// Import annotations from a file, choosing the format from its extension
+pub fn import_file(path: &Path) -> AppResult<ImportResult> {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_ascii_lowercase)
+        .unwrap_or_default();
+    let contents = std::fs::read_to_string(path)?;
+    match extension.as_str() {
+        "json" => import_sharex_json(&contents),
+        "svg" => import_svg(&contents),
+        _ => Err(AppError::Project(format!("インポートできないファイル形式です: {}", path.display()))),
+    }
+}
+
+/// Check whether a file can be imported as annotations
+pub fn is_importable(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| IMPORT_EXTENSIONS.iter().any(|known| ext.eq_ignore_ascii_case(known)))
+}
+
+/// Import ShareX shapes serialized as JSON
+///
+/// Accepts an array of shapes or an object with a `Shapes` array. Shapes are
+/// identified by their `$type` (or `ShapeType`) name, with rectangles given as
+/// `"X, Y, Width, Height"` strings and colors as names, `"R, G, B"`,
+/// `"A, R, G, B"`, or `#RRGGBB`/`#AARRGGBB`.
+pub fn import_sharex_json(json: &str) -> AppResult<ImportResult> {
+    let value: Value = serde_json::from_str(json)
+        .map_err(|e| AppError::Project(format!("ShareXデータの解析に失敗しました: {}", e)))?;
+    let shapes = match &value {
+        Value::Array(shapes) => shapes,
+        Value::Object(object) => object
+            .get("Shapes")
+            .and_then(Value::as_array)
+            .ok_or_else(|| AppError::Project("ShareXデータに図形がありません".to_string()))?,
+        _ => return Err(AppError::Project("ShareXデータの形式が不正です".to_string())),
+    };
+
+    let mut result = ImportResult::default();
+    for shape in shapes {
+        match sharex_shape(shape) {
+            Some(annotation) => result.annotations.push(annotation),
+            None => result.skipped += 1,
+        }
+    }
+    Ok(result)
+}
+
+fn sharex_shape(shape: &Value) -> Option<AnnotationItem> {
+    let type_name = shape.get("$type").or_else(|| shape.get("ShapeType"))?.as_str()?;
+    // "ShareX.ScreenCaptureLib.RectangleDrawingShape, ShareX.ScreenCaptureLib" -> "RectangleDrawingShape"
+    let type_name = type_name.split(',').next()?.rsplit('.').next()?.trim();
+    let (x, y, width, height) = sharex_rectangle(shape.get("Rectangle")?)?;
+    let position = Pos2::new(x.min(x + width), y.min(y + height));
+    let size = Vec2::new(width.abs(), height.abs());
+    let color = |key: &str| shape.get(key).and_then(Value::as_str).and_then(parse_sharex_color);
+    let border_size = shape.get("BorderSize").and_then(Value::as_f64).map(|size| size as f32);
+
+    match type_name {
+        "RectangleDrawingShape" | "RectangleRegionShape" => {
+            let mut annotation = AnnotationItem::new_rectangle(position, size);
+            if let AnnotationType::Rectangle { stroke_color, stroke_width, fill_color, .. } = &mut annotation.annotation_type {
+                *stroke_color = color("BorderColor").unwrap_or(*stroke_color);
+                *stroke_width = border_size.unwrap_or(*stroke_width);
+                *fill_color = color("FillColor").filter(|fill| fill.a() > 0);
+            }
+            Some(annotation)
+        }
+        "HighlightEffectShape" => {
+            let mut annotation = AnnotationItem::new_rectangle(position, size);
+            if let AnnotationType::Rectangle { stroke_width, fill_color, .. } = &mut annotation.annotation_type {
+                *stroke_width = 0.0;
+                let [r, g, b, _] = color("HighlightColor").unwrap_or(Color32::YELLOW).to_srgba_unmultiplied();
+                *fill_color = Some(Color32::from_rgba_unmultiplied(r, g, b, 96));
+            }
+            Some(annotation)
+        }
+        "TextDrawingShape" | "TextOutlineDrawingShape" | "SpeechBalloonDrawingShape" => {
+            let content = shape.get("Text")?.as_str()?.to_string();
+            if content.trim().is_empty() {
+                return None;
+            }
+            let options = shape.get("TextOptions");
+            let mut annotation = AnnotationItem::new_text(position, content);
+            if let AnnotationType::Text { font_size, color: text_color, .. } = &mut annotation.annotation_type {
+                if let Some(size) = options.and_then(|options| options.get("Size")).and_then(Value::as_f64) {
+                    *font_size = size as f32;
+                }
+                if let Some(color) = options
+                    .and_then(|options| options.get("Color"))
+                    .and_then(Value::as_str)
+                    .and_then(parse_sharex_color)
+                {
+                    *text_color = color;
+                }
+            }
+            Some(annotation)
+        }
+        _ => None,
+    }
+}
+
+/// Parse a rectangle serialized as "X, Y, Width, Height" or as an object
+fn sharex_rectangle(value: &Value) -> Option<(f32, f32, f32, f32)> {
+    match value {
+        Value::String(text) => {
+            let parts: Vec<f32> = text.split(',').map(|part| part.trim().parse().ok()).collect::<Option<_>>()?;
+            match parts[..] {
+                [x, y, width, height] => Some((x, y, width, height)),
+                _ => None,
+            }
+        }
+        Value::Object(object) => {
+            let field = |key: &str| object.get(key).and_then(Value::as_f64).map(|value| value as f32);
+            Some((field("X")?, field("Y")?, field("Width")?, field("Height")?))
+        }
+        _ => None,
+    }
+}
+
+/// Parse a .NET color: a name, "R, G, B", "A, R, G, B", or hex
+fn parse_sharex_color(text: &str) -> Option<Color32> {
+    let parts: Option<Vec<u8>> = text.split(',').map(|part| part.trim().parse().ok()).collect();
+    match parts.as_deref() {
+        Some(&[r, g, b]) => Some(Color32::from_rgb(r, g, b)),
+        Some(&[a, r, g, b]) => Some(Color32::from_rgba_unmultiplied(r, g, b, a)),
+        _ => parse_hex_color(text, true).or_else(|| named_color(text)),
+    }
+}
+
+/// Parse `#RGB`, `#RRGGBB`, or 8-digit hex with alpha first (.NET) or last (CSS)
+fn parse_hex_color(text: &str, alpha_first: bool) -> Option<Color32> {
+    let hex = text.trim().strip_prefix('#')?;
+    let digits: Vec<u8> = match hex.len() {
+        3 => hex
+            .chars()
+            .map(|c| c.to_digit(16).map(|value| value as u8 * 17))
+            .collect::<Option<_>>()?,
+        6 | 8 => (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+            .collect::<Option<_>>()?,
+        _ => return None,
+    };
+    match digits[..] {
+        [r, g, b] => Some(Color32::from_rgb(r, g, b)),
+        [a, r, g, b] if alpha_first => Some(Color32::from_rgba_unmultiplied(r, g, b, a)),
+        [r, g, b, a] => Some(Color32::from_rgba_unmultiplied(r, g, b, a)),
+        _ => None,
+    }
+}
+
+/// Look up a common color name
+fn named_color(name: &str) -> Option<Color32> {
+    let color = match name.trim().to_ascii_lowercase().as_str() {
+        "transparent" => Color32::TRANSPARENT,
+        "black" => Color32::BLACK,
+        "white" => Color32::WHITE,
+        "red" => Color32::from_rgb(255, 0, 0),
+        "green" => Color32::from_rgb(0, 128, 0),
+        "lime" => Color32::from_rgb(0, 255, 0),
+        "blue" => Color32::from_rgb(0, 0, 255),
+        "yellow" => Color32::from_rgb(255, 255, 0),
+        "orange" => Color32::from_rgb(255, 165, 0),
+        "purple" => Color32::from_rgb(128, 0, 128),
+        "gray" | "grey" => Color32::from_rgb(128, 128, 128),
+        _ => return None,
+    };
+    Some(color)
+}
+
+/// Import rectangles and text from an SVG overlay
+///
+/// Only `translate` transforms are supported; shapes under other
+/// transforms are skipped rather than placed in the wrong spot.
+pub fn import_svg(svg: &str) -> AppResult<ImportResult> {
+    let document = roxmltree::Document::parse(svg)
+        .map_err(|e| AppError::Project(format!("SVGの解析に失敗しました: {}", e)))?;
+
+    let mut result = ImportResult::default();
+    for node in document.descendants().filter(|node| node.is_element()) {
+        let name = node.tag_name().name();
+        let is_shape = matches!(name, "rect" | "text" | "line" | "polyline" | "polygon" | "path" | "circle" | "ellipse");
+        if !is_shape {
+            continue;
+        }
+        let annotation = svg_offset(node).and_then(|offset| match name {
+            "rect" => svg_rect(node, offset),
+            "text" => svg_text(node, offset),
+            _ => None,
+        });
+        match annotation {
+            Some(annotation) => result.annotations.push(annotation),
+            None => result.skipped += 1,
+        }
+    }
+    Ok(result)
+}
+
+/// Get an SVG presentation attribute, preferring the inline style
+fn svg_attribute<'a>(node: roxmltree::Node<'a, '_>, name: &str) -> Option<&'a str> {
+    let from_style = node.attribute("style").and_then(|style| {
+        style.split(';').find_map(|declaration| {
+            let (key, value) = declaration.split_once(':')?;
+            (key.trim() == name).then(|| value.trim())
+        })
+    });
+    from_style.or_else(|| node.attribute(name))
+}
+
+/// Parse an SVG length in user units ("12", "12px")
+fn svg_length(node: roxmltree::Node, name: &str) -> Option<f32> {
+    svg_attribute(node, name)?.trim().trim_end_matches("px").parse().ok()
+}
+
+/// Parse an SVG paint, returning `Some(None)` for "none"
+fn svg_paint(node: roxmltree::Node, name: &str, opacity_name: &str) -> Option<Option<Color32>> {
+    let text = svg_attribute(node, name)?.trim();
+    if text == "none" || text == "transparent" {
+        return Some(None);
+    }
+    let color = parse_hex_color(text, false).or_else(|| parse_css_rgb(text)).or_else(|| named_color(text))?;
+    let opacity = svg_attribute(node, opacity_name)
+        .and_then(|value| value.trim().parse::<f32>().ok())
+        .unwrap_or(1.0)
+        .clamp(0.0, 1.0);
+    let [r, g, b, a] = color.to_srgba_unmultiplied();
+    Some(Some(Color32::from_rgba_unmultiplied(r, g, b, (a as f32 * opacity).round() as u8)))
+}
+
+/// Parse `rgb(r, g, b)`
+fn parse_css_rgb(text: &str) -> Option<Color32> {
+    let inner = text.strip_prefix("rgb(")?.strip_suffix(')')?;
+    let parts: Vec<u8> = inner.split(',').map(|part| part.trim().parse().ok()).collect::<Option<_>>()?;
+    match parts[..] {
+        [r, g, b] => Some(Color32::from_rgb(r, g, b)),
+        _ => None,
+    }
+}
+
+/// Sum the `translate` transforms of a node and its ancestors
+fn svg_offset(node: roxmltree::Node) -> Option<Vec2> {
+    let mut offset = Vec2::ZERO;
+    for ancestor in node.ancestors().filter(|ancestor| ancestor.is_element()) {
+        let Some(transform) = ancestor.attribute("transform") else {
+            continue;
+        };
+        let arguments = transform.trim().strip_prefix("translate(")?.strip_suffix(')')?;
+        let values: Vec<f32> = arguments
+            .split([',', ' '])
+            .filter(|part| !part.is_empty())
+            .map(|part| part.parse().ok())
+            .collect::<Option<_>>()?;
+        match values[..] {
+            [x] => offset.x += x,
+            [x, y] => offset += Vec2::new(x, y),
+            _ => return None,
+        }
+    }
+    Some(offset)
+}
+
+fn svg_rect(node: roxmltree::Node, offset: Vec2) -> Option<AnnotationItem> {
+    let position = Pos2::new(svg_length(node, "x").unwrap_or(0.0), svg_length(node, "y").unwrap_or(0.0)) + offset;
+    let size = Vec2::new(svg_length(node, "width")?, svg_length(node, "height")?);
+    let mut annotation = AnnotationItem::new_rectangle(position, size);
+    if let AnnotationType::Rectangle { stroke_color, stroke_width, fill_color, .. } = &mut annotation.annotation_type {
+        match svg_paint(node, "stroke", "stroke-opacity") {
+            Some(Some(color)) => *stroke_color = color,
+            Some(None) => *stroke_width = 0.0,
+            None => {}
+        }
+        if let Some(width) = svg_length(node, "stroke-width") {
+            *stroke_width = if *stroke_width == 0.0 { 0.0 } else { width };
+        }
+        // SVG fills default to black, but overlays are drawn over a screenshot
+        *fill_color = svg_paint(node, "fill", "fill-opacity").flatten();
+    }
+    Some(annotation)
+}
+
+fn svg_text(node: roxmltree::Node, offset: Vec2) -> Option<AnnotationItem> {
+    let content: String = node
+        .descendants()
+        .filter(|child| child.is_text())
+        .filter_map(|child| child.text())
+        .collect::<Vec<_>>()
+        .join("");
+    let content = content.split_whitespace().collect::<Vec<_>>().join(" ");
+    if content.is_empty() {
+        return None;
+    }
+
+    let size = svg_length(node, "font-size").unwrap_or(16.0);
+    // SVG positions text by its baseline, annotations by their top-left corner
+    let baseline = Pos2::new(svg_length(node, "x").unwrap_or(0.0), svg_length(node, "y").unwrap_or(0.0)) + offset;
+    let mut annotation = AnnotationItem::new_text(baseline - Vec2::new(0.0, size * BASELINE_RATIO), content);
+    if let AnnotationType::Text { font_size, color, format, .. } = &mut annotation.annotation_type {
+        *font_size = size;
+        if let Some(Some(fill)) = svg_paint(node, "fill", "fill-opacity") {
+            *color = fill;
+        }
+        format.bold = svg_attribute(node, "font-weight")
+            .is_some_and(|weight| weight.trim() == "bold" || weight.trim().parse::<u32>().is_ok_and(|weight| weight >= 600));
+        format.italic = svg_attribute(node, "font-style").is_some_and(|style| matches!(style.trim(), "italic" | "oblique"));
+    }
+    Some(annotation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::text::TextFormat;
+
+    #[test]
+    fn test_import_sharex_shapes() {
+        let json = r##"{ "Shapes": [
+            { "$type": "ShareX.ScreenCaptureLib.RectangleDrawingShape, ShareX.ScreenCaptureLib",
+              "Rectangle": "10, 20, 100, 50", "BorderColor": "0, 0, 255", "BorderSize": 4, "FillColor": "Transparent" },
+            { "$type": "ShareX.ScreenCaptureLib.TextDrawingShape, ShareX.ScreenCaptureLib",
+              "Rectangle": { "X": 5, "Y": 5, "Width": 80, "Height": 20 }, "Text": "手順 1",
+              "TextOptions": { "Size": 18, "Color": "#FFFF0000" } },
+            { "ShapeType": "BlurEffectShape", "Rectangle": "0, 0, 10, 10" },
+            { "ShapeType": "HighlightEffectShape", "Rectangle": "50, 60, -20, -10" }
+        ] }"##;
+        let result = import_sharex_json(json).unwrap();
+        assert_eq!(result.annotations.len(), 3);
+        assert_eq!(result.skipped, 1);
+
+        let rectangle = &result.annotations[0];
+        assert_eq!(rectangle.position, Pos2::new(10.0, 20.0));
+        assert_eq!(
+            rectangle.annotation_type,
+            AnnotationType::Rectangle {
+                size: Vec2::new(100.0, 50.0),
+                stroke_color: Color32::from_rgb(0, 0, 255),
+                stroke_width: 4.0,
+                fill_color: None,
+            }
+        );
+        assert_eq!(
+            result.annotations[1].annotation_type,
+            AnnotationType::Text {
+                content: "手順 1".to_string(),
+                font_size: 18.0,
+                color: Color32::RED,
+                format: TextFormat::default(),
+            }
+        );
+        // Rectangles drawn right-to-left have negative sizes
+        assert_eq!(result.annotations[2].bounds().min, Pos2::new(30.0, 50.0));
+
+        assert!(import_sharex_json("42").is_err());
+        assert!(import_sharex_json("{ not json").is_err());
+    }
+
+    #[test]
+    fn test_import_svg_overlay() {
+        let svg = r##"<svg xmlns="http://www.w3.org/2000/svg" width="400" height="300">
+            <g transform="translate(10, 20)">
+                <rect x="5" y="5" width="50" height="40" stroke="#00ff00" stroke-width="3" fill="none"/>
+                <text x="0" y="100" style="font-size: 20px; fill: rgb(0, 0, 255)">Click <tspan>here</tspan></text>
+            </g>
+            <rect width="10" height="10" style="stroke: none; fill: yellow" fill-opacity="0.5"/>
+            <ellipse cx="5" cy="5" rx="2" ry="2"/>
+            <rect transform="rotate(45)" width="10" height="10"/>
+        </svg>"##;
+        let result = import_svg(svg).unwrap();
+        assert_eq!(result.annotations.len(), 3);
+        assert_eq!(result.skipped, 2);
+
+        assert_eq!(result.annotations[0].position, Pos2::new(15.0, 25.0));
+        assert!(matches!(
+            result.annotations[0].annotation_type,
+            AnnotationType::Rectangle { stroke_width: 3.0, fill_color: None, .. }
+        ));
+        let text = &result.annotations[1];
+        assert_eq!(text.position, Pos2::new(10.0, 104.0));
+        assert_eq!(
+            text.annotation_type,
+            AnnotationType::Text {
+                content: "Click here".to_string(),
+                font_size: 20.0,
+                color: Color32::from_rgb(0, 0, 255),
+                format: TextFormat::default(),
+            }
+        );
+        assert!(matches!(
+            result.annotations[2].annotation_type,
+            AnnotationType::Rectangle { stroke_width: 0.0, fill_color: Some(fill), .. } if fill.a() == 128
+        ));
+
+        assert!(import_svg("<svg><rect").is_err());
+    }
+
+    #[test]
+    fn test_color_parsing() {
+        assert_eq!(parse_sharex_color("128, 255, 0, 0"), Some(Color32::from_rgba_unmultiplied(255, 0, 0, 128)));
+        assert_eq!(parse_hex_color("#f00", false), Some(Color32::RED));
+        assert_eq!(parse_hex_color("#ff000080", false), Some(Color32::from_rgba_unmultiplied(255, 0, 0, 128)));
+        assert_eq!(parse_sharex_color("Orange"), Some(Color32::from_rgb(255, 165, 0)));
+        assert_eq!(parse_sharex_color("chartreuse-ish"), None);
+    }
+}
@@ -4,10 +4,78 @@
 //! that allows users to capture screen areas and perform basic editing.
 
 pub mod types;
+pub mod about;
+pub mod accessibility;
+pub mod adjustments;
+pub mod align;
+pub mod animation;
+pub mod automation;
+pub mod autostart;
+pub mod base64;
+pub mod batch;
 pub mod capture;
+pub mod clipboard;
+pub mod color;
+pub mod control;
+pub mod credentials;
+pub mod cursors;
+pub mod datetime;
+pub mod decode;
+pub mod dialogs;
+pub mod diagnostics;
+pub mod diff;
 pub mod editor_app;
+pub mod emoji;
+pub mod events;
+pub mod export;
+pub mod files;
+pub mod foreground;
+pub mod grid;
+pub mod hdr;
+pub mod history;
+pub mod hotkeys;
+pub mod image_ops;
+pub mod image_store;
+pub mod import;
+pub mod measure;
+pub mod memory;
+pub mod metadata;
+pub mod monitor_cycle;
+pub mod naming;
+pub mod notifications;
+pub mod ocr;
+pub mod palette;
+pub mod plugins;
+pub mod power;
+pub mod presets;
+pub mod privacy;
+pub mod profiling;
+pub mod properties;
+pub mod project;
+pub mod regions;
+pub mod remote;
+pub mod render;
+pub mod routing;
+pub mod schedule;
+pub mod services;
+pub mod session;
+pub mod settings;
+pub mod share;
+pub mod shortcuts;
+pub mod snap;
+pub mod style;
+pub mod svg;
+pub mod templates;
+pub mod text;
+pub mod tiles;
+pub mod toolbar;
+pub mod undo;
+pub mod watch;
+pub mod window_list;
+pub mod writer;
 
 // Re-export commonly used types
 pub use types::*;
 pub use capture::CaptureService;
-pub use editor_app::EditorApp;
\ No newline at end of file
+pub use editor_app::EditorApp;
+pub use settings::SettingsManager;
\ No newline at end of file
@@ -1,16 +1,95 @@
 use log::info;
-use lightweight_screenshot_app::{AppSettings, EditorApp, Tool};
+use log::warn;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Instant;
+use lightweight_screenshot_app::about;
+use lightweight_screenshot_app::autostart;
+use lightweight_screenshot_app::batch::{self, BatchArgs};
+use lightweight_screenshot_app::capture::CaptureService;
+use lightweight_screenshot_app::control::{self, ControlCommand};
+use lightweight_screenshot_app::decode::{self, DecodeLimits};
+use lightweight_screenshot_app::diff;
+use lightweight_screenshot_app::export;
+use lightweight_screenshot_app::history;
+use lightweight_screenshot_app::hotkeys;
+use lightweight_screenshot_app::naming;
+use lightweight_screenshot_app::plugins;
+use lightweight_screenshot_app::session;
+use lightweight_screenshot_app::templates;
+use lightweight_screenshot_app::window_list::WindowSelector;
+use lightweight_screenshot_app::editor_app::APP_TITLE;
+use lightweight_screenshot_app::events::AppEvent;
+use lightweight_screenshot_app::{AppError, EditorApp, ImageFormat, SettingsManager, Tool};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Initialize logging
-    env_logger::init();
+    let startup = Instant::now();
+
+    // Initialize logging to stderr and the session log file
+    let mut logger = env_logger::Builder::from_default_env();
+    if let Some(log_file) = open_log_file() {
+        logger.target(env_logger::Target::Pipe(Box::new(LogTee(log_file))));
+    }
+    logger.init();
+
+    // `control <command>` talks to the running instance instead of starting the UI
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    // Errors in these modes exit with a status for their kind (see `AppError::exit_code`)
+    if args.first().map(String::as_str) == Some("control") {
+        let state = ControlCommand::parse(&args[1..])
+            .and_then(|command| control::send_command(&control::default_endpoint_path(), command))
+            .unwrap_or_else(|e| exit_with_error(&e));
+        println!("{}", state);
+        return Ok(());
+    }
+
+    // `diff <a> <b>` compares two screenshots and exits with status 1 if they differ
+    if args.first().map(String::as_str) == Some("diff") {
+        let differs = run_diff(&args[1..]).unwrap_or_else(|e| exit_with_error(e.as_ref()));
+        std::process::exit(i32::from(differs));
+    }
+
+    // `batch --input DIR --output DIR --preset NAME` processes a folder and exits with status 1 if any image failed
+    if args.first().map(String::as_str) == Some("batch") {
+        let failed = run_batch(&args[1..]).unwrap_or_else(|e| exit_with_error(e.as_ref()));
+        std::process::exit(i32::from(failed));
+    }
+
+    // `capture --window-title TEXT --process NAME --output PATH` saves one window without starting the UI
+    if args.first().map(String::as_str) == Some("capture") {
+        run_capture(&args[1..]).unwrap_or_else(|e| exit_with_error(e.as_ref()));
+        return Ok(());
+    }
     
-    info!("Lightweight Screenshot App starting...");
+    info!("Lightweight Screenshot App {} starting...", about::version_line());
     
-    // Initialize app settings to verify types work
-    let settings = AppSettings::default();
-    info!("Loaded settings with hotkey: Ctrl+Shift+S");
-    info!("Default image format: {}", settings.default_image_format);
+    // Load persisted settings, falling back to defaults
+    let settings = SettingsManager::new().unwrap_or_else(|e| {
+        warn!("Failed to initialize settings, using defaults: {}", e);
+        SettingsManager::default()
+    });
+    let loaded = settings.get_settings();
+    info!(
+        "Loaded settings with hotkeys: {} (screen), {} (window), {} (monitor under cursor), {} (color picker)",
+        hotkeys::describe(loaded.hotkey_modifiers, loaded.hotkey_vk_code),
+        hotkeys::describe(loaded.window_hotkey.modifiers, loaded.window_hotkey.vk_code),
+        hotkeys::describe(loaded.cursor_monitor_hotkey.modifiers, loaded.cursor_monitor_hotkey.vk_code),
+        hotkeys::describe(loaded.color_picker_hotkey.modifiers, loaded.color_picker_hotkey.vk_code)
+    );
+    info!("Default image format: {}", settings.get_settings().default_image_format);
+
+    // Keep the registered login command pointing at this executable
+    if settings.get_settings().launch_at_login {
+        if let Err(e) = autostart::set_launch_at_login(true) {
+            warn!("Failed to update launch at login: {}", e);
+        }
+    }
+
+    // `--background` starts with the window hidden until a capture happens
+    let background = args.iter().any(|arg| arg == autostart::BACKGROUND_FLAG);
+    if background {
+        info!("Starting in the background");
+    }
     
     // Initialize default tool
     let current_tool = Tool::default();
@@ -21,8 +100,9 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         viewport: egui::ViewportBuilder::default()
             .with_inner_size([1024.0, 768.0])
             .with_min_inner_size([800.0, 600.0])
-            .with_title("Lightweight Screenshot App")
-            .with_icon(load_icon()),
+            .with_title(APP_TITLE)
+            .with_icon(load_icon())
+            .with_visible(!background),
         ..Default::default()
     };
     
@@ -30,11 +110,34 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     
     // Run the native egui application
     eframe::run_native(
-        "Lightweight Screenshot App",
+        APP_TITLE,
         native_options,
-        Box::new(|_cc| {
-            // Create and return the editor application
-            Box::new(EditorApp::new())
+        Box::new(move |cc| {
+            // Toolbar icons are SVG files embedded in the binary
+            egui_extras::install_image_loaders(&cc.egui_ctx);
+            // Create the editor and defer expensive service setup to background threads
+            let mut app = EditorApp::with_settings(settings);
+            app.start_background_init();
+            app.set_event_waker(&cc.egui_ctx);
+            app.start_control_server();
+            app.start_automation_server();
+            app.start_hotkeys();
+            // Offer to restore unsaved work if the previous run crashed
+            app.enable_session_recovery(session::default_session_dir());
+            app.enable_capture_history(history::default_history_path());
+            app.enable_plugins(&plugins::default_plugins_path());
+            app.enable_templates(templates::default_templates_path());
+            app.enable_file_counters(naming::default_counters_path());
+            if background {
+                app.start_in_background();
+            }
+            // Open an image passed on the command line (kept as OsString so non-ASCII paths survive)
+            let mut paths = std::env::args_os().skip(1).filter(|arg| arg != autostart::BACKGROUND_FLAG);
+            if let Some(path) = paths.next().map(PathBuf::from) {
+                app.event_sender().send(AppEvent::OpenFile(path));
+            }
+            info!("Editor ready in {:?}", startup.elapsed());
+            Box::new(app)
         }),
     )?;
     
@@ -42,6 +145,105 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+/// Print an error and exit with the status for its kind
+///
+/// Errors other than `AppError`, such as invalid arguments, exit with status 2.
+fn exit_with_error(error: &(dyn std::error::Error + 'static)) -> ! {
+    eprintln!("Error: {}", error);
+    std::process::exit(error.downcast_ref::<AppError>().map_or(2, AppError::exit_code))
+}
+
+/// Compare two images, printing a summary and the changed regions
+///
+/// Usage: `diff <a> <b> [--threshold N] [--heatmap PATH]`. Returns whether
+/// the images differ.
+fn run_diff(args: &[String]) -> Result<bool, Box<dyn std::error::Error>> {
+    let mut paths = Vec::new();
+    let mut threshold = diff::DEFAULT_THRESHOLD;
+    let mut heatmap_path = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--threshold" => threshold = args.next().ok_or("--threshold needs a value")?.parse()?,
+            "--heatmap" => heatmap_path = Some(PathBuf::from(args.next().ok_or("--heatmap needs a path")?)),
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+    let [a, b] = paths.as_slice() else {
+        return Err("usage: diff <a> <b> [--threshold N] [--heatmap PATH]".into());
+    };
+
+    let load = |path: &PathBuf| decode::decode_untrusted_file(path, DecodeLimits::default()).map(|image| image.to_rgba8());
+    let result = diff::compare(&load(a)?, &load(b)?, threshold);
+    println!("{}", result.summary());
+    for region in &result.regions {
+        println!("  {}x{} at ({}, {})", region.width(), region.height(), region.min.x, region.min.y);
+    }
+    if let Some(path) = heatmap_path {
+        export::save_image(&result.heatmap, &path, &ImageFormat::Png)?;
+    }
+    Ok(!result.is_identical())
+}
+
+/// Apply a batch preset from the settings to a folder of images
+///
+/// Usage: `batch --input DIR --output DIR --preset NAME`. Prints each written
+/// file and each failure. Returns whether any image failed.
+fn run_batch(args: &[String]) -> Result<bool, Box<dyn std::error::Error>> {
+    let args = BatchArgs::parse(args)?;
+    let settings = SettingsManager::new()?;
+    let preset = batch::find_preset(&settings.get_settings().batch_presets, &args.preset)?;
+    let report = batch::run(&args.input, &args.output, preset)?;
+    for path in &report.written {
+        println!("{}", path.display());
+    }
+    for (path, error) in &report.failed {
+        eprintln!("{}: {}", path.display(), error);
+    }
+    println!("{}", report.summary());
+    Ok(!report.failed.is_empty())
+}
+
+/// Capture the one window matching the given title and process, and save it
+///
+/// Usage: `capture [--window-title TEXT] [--process NAME] --output PATH`,
+/// with at least one of the selectors. Fails with status 7 when no window
+/// matches and 8 when several do.
+fn run_capture(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    const USAGE: &str = "usage: capture [--window-title TEXT] [--process NAME] --output PATH";
+    let mut selector = WindowSelector::default();
+    let mut output = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let value = args.next().ok_or(USAGE)?;
+        match arg.as_str() {
+            "--window-title" => selector.title = Some(value.clone()),
+            "--process" => selector.process = Some(value.clone()),
+            "--output" => output = Some(PathBuf::from(value)),
+            _ => return Err(USAGE.into()),
+        }
+    }
+    let output = output.ok_or(USAGE)?;
+    if selector.is_empty() {
+        return Err(USAGE.into());
+    }
+
+    let settings = SettingsManager::new().unwrap_or_default();
+    let settings = settings.get_settings();
+    let mut service = CaptureService::new()?;
+    service.set_convert_to_srgb(settings.color.convert_to_srgb);
+    service.set_hdr_options(settings.hdr.clone());
+    service.set_window_capture_options(settings.window_capture);
+    let image = service.capture_window(&selector)?.into_rgba8();
+    let format = output
+        .extension()
+        .and_then(|extension| ImageFormat::from_extension(&extension.to_string_lossy()))
+        .unwrap_or(settings.default_image_format.clone());
+    export::save_image(&image, &output, &format)?;
+    println!("{} ({}x{})", output.display(), image.width(), image.height());
+    Ok(())
+}
+
 /// Load application icon (placeholder implementation)
 fn load_icon() -> egui::IconData {
     // For now, return a default icon
@@ -51,4 +253,28 @@ fn load_icon() -> egui::IconData {
         width: 32,
         height: 32,
     }
-}
\ No newline at end of file
+}
+
+/// Create the log file of this session, replacing the previous session's log
+fn open_log_file() -> Option<std::fs::File> {
+    let path = about::log_file_path();
+    std::fs::create_dir_all(about::log_directory())
+        .and_then(|_| std::fs::File::create(&path))
+        .map_err(|e| eprintln!("Failed to create log file {}: {}", path.display(), e))
+        .ok()
+}
+
+/// Log writer copying output to stderr and the log file
+struct LogTee(std::fs::File);
+
+impl Write for LogTee {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let _ = std::io::stderr().write_all(buf);
+        self.0.write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.flush()
+    }
+}
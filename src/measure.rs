@@ -0,0 +1,140 @@
+//! Distance and angle measurement
+//!
+//! The Measure tool drags a line across the image and reads out its length
+//! and angle, for checking spacing and alignment in UI and design reviews.
+//! Lengths are in image pixels, and also in millimeters and inches when the
+//! DPI of the capture is known. A measurement can be kept as a dimension
+//! annotation, whose label is worked out again whenever it is edited.
+
+use crate::text::TextFormat;
+use crate::types::ScreenInfo;
+use egui::{Color32, Pos2, Rect, Vec2};
+
+/// Millimeters in an inch
+pub const MM_PER_INCH: f32 = 25.4;
+
+/// Pixels per logical inch at 100% display scaling
+pub const BASE_DPI: f32 = 96.0;
+
+/// Length of the ticks across each end of a dimension line
+pub const TICK_LENGTH: f32 = 8.0;
+
+/// Font size of dimension labels
+pub const LABEL_FONT_SIZE: f32 = 12.0;
+
+/// Gap between a dimension line and its label
+const LABEL_GAP: f32 = 2.0;
+
+/// Distance from a dimension line within which it can be clicked
+pub const HIT_MARGIN: f32 = 6.0;
+
+/// Get the pixels per inch of a capture from its monitor's display scaling
+pub fn pixels_per_inch(screen: &ScreenInfo) -> f32 {
+    BASE_DPI * screen.dpi_scale_x
+}
+
+/// Get the angle of a line in degrees within (-180, 180], counterclockwise from pointing right
+pub fn angle(delta: Vec2) -> f32 {
+    // Image y points down, so it is flipped to measure like a protractor
+    let degrees = (-delta.y).atan2(delta.x).to_degrees();
+    if degrees <= -180.0 {
+        degrees + 360.0
+    } else if degrees == 0.0 {
+        // Avoid showing -0.0
+        0.0
+    } else {
+        degrees
+    }
+}
+
+/// Get the label of a measured line: its length, in physical units when `pixels_per_inch` is known, and angle
+pub fn label(delta: Vec2, pixels_per_inch: Option<f32>) -> String {
+    let length = delta.length();
+    let physical = pixels_per_inch.filter(|ppi| *ppi > 0.0).map_or(String::new(), |ppi| {
+        let inches = length / ppi;
+        format!(" ({:.1} mm, {:.2} in)", inches * MM_PER_INCH, inches)
+    });
+    format!("{:.0} px{}, {:.1}°", length, physical, angle(delta))
+}
+
+/// Move `end` so the line from `start` points in a multiple of 45 degrees, keeping its length
+pub fn constrain(start: Pos2, end: Pos2) -> Pos2 {
+    let delta = end - start;
+    let step = std::f32::consts::FRAC_PI_4;
+    let snapped = (delta.y.atan2(delta.x) / step).round() * step;
+    start + Vec2::angled(snapped) * delta.length()
+}
+
+/// Get the ticks across both ends of a dimension line
+pub fn ticks(start: Pos2, end: Pos2) -> [[Pos2; 2]; 2] {
+    let half = normal(end - start) * TICK_LENGTH * 0.5;
+    [[start - half, start + half], [end - half, end + half]]
+}
+
+/// Get where a label of `label_size` sits beside a dimension line of `width`
+///
+/// The label is centered on the line, on its upper side.
+pub fn label_rect(start: Pos2, end: Pos2, width: f32, label_size: Vec2) -> Rect {
+    let normal = normal(end - start);
+    // Half the label's extent across the line
+    let reach = normal.x.abs() * label_size.x * 0.5 + normal.y.abs() * label_size.y * 0.5;
+    let center = start + (end - start) * 0.5 + normal * (reach + width * 0.5 + LABEL_GAP);
+    Rect::from_center_size(center, label_size)
+}
+
+/// Formatting of dimension labels: a light box behind the text, so it stays readable on any image
+pub fn label_format() -> TextFormat {
+    TextFormat {
+        background: Some(Color32::from_white_alpha(230)),
+        ..TextFormat::default()
+    }
+}
+
+/// Unit vector across a line, pointing up (or left for vertical lines)
+fn normal(delta: Vec2) -> Vec2 {
+    let direction = delta.normalized();
+    if !direction.is_finite() || direction == Vec2::ZERO {
+        return -Vec2::Y;
+    }
+    let normal = Vec2::new(direction.y, -direction.x);
+    if normal.y > 0.0 || (normal.y == 0.0 && normal.x > 0.0) {
+        -normal
+    } else {
+        normal
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_label() {
+        assert_eq!(label(Vec2::new(96.0, 0.0), None), "96 px, 0.0°");
+        assert_eq!(label(Vec2::new(0.0, -96.0), Some(96.0)), "96 px (25.4 mm, 1.00 in), 90.0°");
+        assert_eq!(label(Vec2::new(-30.0, 30.0), Some(0.0)), "42 px, -135.0°");
+        assert_eq!(angle(Vec2::new(-10.0, 0.0)), 180.0);
+    }
+
+    #[test]
+    fn test_constrain() {
+        let start = Pos2::new(10.0, 10.0);
+        let end = constrain(start, Pos2::new(110.0, 20.0));
+        assert!((end - Pos2::new(110.5, 10.0)).length() < 0.01);
+        let diagonal = constrain(start, Pos2::new(50.0, 45.0));
+        assert!((diagonal.x - diagonal.y).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_label_sits_above_the_line() {
+        let size = Vec2::new(60.0, 16.0);
+        let rect = label_rect(Pos2::new(0.0, 50.0), Pos2::new(100.0, 50.0), 2.0, size);
+        assert_eq!(rect, Rect::from_center_size(Pos2::new(50.0, 50.0 - 8.0 - 1.0 - LABEL_GAP), size));
+
+        // Vertical lines are labeled on their left
+        let rect = label_rect(Pos2::new(50.0, 100.0), Pos2::new(50.0, 0.0), 2.0, size);
+        assert!(rect.max.x < 50.0);
+        let [first, _] = ticks(Pos2::new(50.0, 100.0), Pos2::new(50.0, 0.0));
+        assert_eq!(first, [Pos2::new(54.0, 100.0), Pos2::new(46.0, 100.0)]);
+    }
+}
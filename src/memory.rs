@@ -0,0 +1,202 @@
+//! Memory budget for large images
+//!
+//! Stitched and multi-monitor captures can take hundreds of megabytes once
+//! decoded, and each open tab keeps its own copy. When the images and
+//! textures of all tabs exceed the budget, the largest images are written to
+//! a temporary file and memory-mapped, and the editor shows a downsampled
+//! proxy instead. The full-resolution pixels are read back from the map only
+//! when they are needed: for exports, and for edits of the pixels
+//! themselves, which restore the full image first.
+
+use crate::types::{AppError, AppResult};
+use image::{DynamicImage, RgbaImage};
+use memmap2::Mmap;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// Bytes per megabyte of the budget
+const MEGABYTE: usize = 1024 * 1024;
+
+/// Memory budget settings
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MemoryOptions {
+    /// Most megabytes of decoded images and textures kept for all tabs
+    pub budget_mb: u32,
+    /// Longest side in pixels of the proxy shown for an image over the budget
+    pub proxy_max_side: u32,
+}
+
+impl Default for MemoryOptions {
+    fn default() -> Self {
+        Self {
+            budget_mb: 2048,
+            proxy_max_side: 4096,
+        }
+    }
+}
+
+impl MemoryOptions {
+    /// Smallest allowed budget in megabytes
+    pub const MIN_BUDGET_MB: u32 = 256;
+
+    /// Largest allowed budget in megabytes
+    pub const MAX_BUDGET_MB: u32 = 65536;
+
+    /// Smallest allowed proxy side, below which text in screenshots is unreadable
+    pub const MIN_PROXY_SIDE: u32 = 1024;
+
+    /// Largest allowed proxy side
+    pub const MAX_PROXY_SIDE: u32 = 16384;
+
+    /// Get the budget in bytes
+    pub fn budget_bytes(&self) -> usize {
+        self.budget_mb as usize * MEGABYTE
+    }
+
+    /// Validate memory options
+    pub fn validate(&self) -> AppResult<()> {
+        if !(Self::MIN_BUDGET_MB..=Self::MAX_BUDGET_MB).contains(&self.budget_mb) {
+            return Err(AppError::Settings(format!(
+                "メモリ上限は{}〜{}MBで指定してください",
+                Self::MIN_BUDGET_MB,
+                Self::MAX_BUDGET_MB
+            )));
+        }
+        if !(Self::MIN_PROXY_SIDE..=Self::MAX_PROXY_SIDE).contains(&self.proxy_max_side) {
+            return Err(AppError::Settings(format!(
+                "縮小表示の最大サイズは{}〜{}ピクセルで指定してください",
+                Self::MIN_PROXY_SIDE,
+                Self::MAX_PROXY_SIDE
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Get the size of the proxy shown for an image, or `None` if the image is not larger
+pub fn proxy_size(width: u32, height: u32, max_side: u32) -> Option<(u32, u32)> {
+    let longest = width.max(height);
+    if longest <= max_side || max_side == 0 {
+        return None;
+    }
+    let scale = max_side as f64 / longest as f64;
+    let scaled = |side: u32| ((side as f64 * scale).round() as u32).max(1);
+    Some((scaled(width), scaled(height)))
+}
+
+/// Downsample an image to its proxy, or `None` if it is not larger than `max_side`
+pub fn proxy(image: &DynamicImage, max_side: u32) -> Option<DynamicImage> {
+    let (width, height) = proxy_size(image.width(), image.height(), max_side)?;
+    Some(image.resize_exact(width, height, image::imageops::FilterType::Triangle))
+}
+
+/// Full-resolution RGBA pixels in a memory-mapped temporary file
+///
+/// The file is deleted when the image is dropped.
+#[derive(Debug)]
+pub struct SpilledImage {
+    path: PathBuf,
+    /// Mapping of the file, released before the file is deleted
+    map: Option<Mmap>,
+    width: u32,
+    height: u32,
+}
+
+impl SpilledImage {
+    /// Write an image to a new file in `directory` and map it
+    pub fn spill(image: &DynamicImage, directory: &Path) -> AppResult<Self> {
+        std::fs::create_dir_all(directory)?;
+        let path = directory.join(format!("spill-{}.rgba", Uuid::new_v4()));
+        let rgba = image.to_rgba8();
+        let map = (|| {
+            let mut file = File::create(&path)?;
+            file.write_all(rgba.as_raw())?;
+            file.sync_data()?;
+            // The file is only written here and removed when the image is dropped
+            unsafe { Mmap::map(&File::open(&path)?) }
+        })()
+        .map_err(|e| {
+            let _ = std::fs::remove_file(&path);
+            AppError::FileAccess(e)
+        })?;
+        Ok(Self {
+            path,
+            map: Some(map),
+            width: rgba.width(),
+            height: rgba.height(),
+        })
+    }
+
+    /// Get the directory spilled images are written to by default
+    pub fn default_directory() -> PathBuf {
+        std::env::temp_dir().join("lightweight-screenshot-app")
+    }
+
+    /// Get the image dimensions
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Read the full-resolution pixels into memory
+    pub fn load(&self) -> AppResult<DynamicImage> {
+        let bytes = self.map.as_deref().unwrap_or_default();
+        RgbaImage::from_raw(self.width, self.height, bytes.to_vec())
+            .map(DynamicImage::ImageRgba8)
+            .ok_or_else(|| AppError::ImageProcessing("一時ファイルの画像サイズが一致しません".to_string()))
+    }
+}
+
+impl Drop for SpilledImage {
+    fn drop(&mut self) {
+        // Windows can't delete a file that is still mapped
+        self.map = None;
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            log::warn!("Failed to remove {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_proxy_size_keeps_aspect_ratio() {
+        assert_eq!(proxy_size(2000, 1000, 4096), None);
+        assert_eq!(proxy_size(3000, 40000, 4000), Some((300, 4000)));
+        assert_eq!(proxy_size(10000, 10, 1000), Some((1000, 1)));
+
+        let image = DynamicImage::ImageRgba8(RgbaImage::new(400, 100));
+        assert_eq!(proxy(&image, 200).unwrap().to_rgba8().dimensions(), (200, 50));
+        assert!(proxy(&image, 400).is_none());
+    }
+
+    #[test]
+    fn test_spill_round_trip_and_cleanup() {
+        let directory = std::env::temp_dir().join(format!("lsa-memory-{}", Uuid::new_v4()));
+        let mut pixels = RgbaImage::new(5, 3);
+        pixels.put_pixel(4, 2, image::Rgba([1, 2, 3, 4]));
+        let image = DynamicImage::ImageRgba8(pixels);
+
+        let spilled = SpilledImage::spill(&image, &directory).unwrap();
+        assert_eq!(spilled.dimensions(), (5, 3));
+        assert_eq!(spilled.load().unwrap().to_rgba8(), image.to_rgba8());
+        let path = spilled.path.clone();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 5 * 3 * 4);
+
+        drop(spilled);
+        assert!(!path.exists());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(MemoryOptions::default().validate().is_ok());
+        assert!(MemoryOptions { budget_mb: 10, ..Default::default() }.validate().is_err());
+        assert!(MemoryOptions { proxy_max_side: 100, ..Default::default() }.validate().is_err());
+    }
+}
@@ -0,0 +1,539 @@
+//! Capture metadata embedded in exported files
+//!
+//! The `image` crate writes no metadata, so it is added to the encoded file
+//! afterwards: PNG files get text chunks after the header, and JPEG files an
+//! EXIF segment after the start of the image. BMP files have no place for
+//! metadata and are left as they are.
+//!
+//! | Field        | PNG keyword     | EXIF tag                          |
+//! |--------------|-----------------|-----------------------------------|
+//! | Capture time | `Creation Time` | `DateTimeOriginal` (UTC)          |
+//! | Monitor      | `Source`        | `Model`                           |
+//! | Window title | `Title`         | `ImageDescription`                |
+//! | Application  | `Application`   | `DocumentName`                    |
+//! | App version  | `Software`      | `Software`                        |
+//! | Comment      | `Comment`       | `UserComment`                     |
+//!
+//! An ICC color profile goes in an `iCCP` chunk or `ICC_PROFILE` APP2
+//! segments. It describes the pixels rather than the capture, so it is kept
+//! even when the rest of the metadata is stripped.
+
+use crate::datetime::DateTime;
+use crate::routing::MonitorIdentity;
+use crate::types::{AppError, AppResult, ImageFormat, ScreenInfo};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// Most characters accepted for the user comment
+pub const MAX_COMMENT_CHARS: usize = 1000;
+
+/// PNG file signature
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+/// Largest JPEG segment payload, including its two length bytes
+const MAX_JPEG_SEGMENT: usize = 0xFFFF;
+
+/// Identifier starting each JPEG segment of an ICC profile
+const ICC_JPEG_MARKER: &[u8; 12] = b"ICC_PROFILE\0";
+
+/// Largest block of uncompressed data in a zlib stream
+const MAX_STORED_BLOCK: usize = 0xFFFF;
+
+/// What is known about how an image was captured
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageMetadata {
+    pub captured_at: SystemTime,
+    /// Description of the monitor the image was captured from
+    pub monitor: Option<String>,
+    /// Title of the captured window
+    pub window_title: Option<String>,
+    /// Executable name of the captured window's application
+    pub application: Option<String>,
+    /// Name and version of the application writing the file
+    pub software: String,
+    /// Comment entered by the user
+    pub comment: Option<String>,
+    /// ICC profile to embed, describing the color space of the pixels
+    pub icc_profile: Option<Arc<Vec<u8>>>,
+}
+
+impl ImageMetadata {
+    /// Create metadata for an image captured at `captured_at`
+    pub fn new(captured_at: SystemTime) -> Self {
+        Self {
+            captured_at,
+            monitor: None,
+            window_title: None,
+            application: None,
+            software: format!("{} {}", crate::editor_app::APP_TITLE, crate::about::VERSION),
+            comment: None,
+            icc_profile: None,
+        }
+    }
+
+    /// Describe a monitor, e.g. "Monitor 2 (1920x1080 at (1920, 0))"
+    pub fn describe_monitor(screen: &ScreenInfo) -> String {
+        let primary = if screen.is_primary { ", primary" } else { "" };
+        format!("Monitor {} ({}{})", screen.index + 1, MonitorIdentity::from_screen(screen), primary)
+    }
+
+    /// Get the fields that are set, as PNG keyword and text pairs
+    fn text_fields(&self) -> Vec<(&'static str, String)> {
+        let time = DateTime::from_system_time(self.captured_at);
+        let mut fields = vec![(
+            "Creation Time",
+            format!(
+                "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+                time.year, time.month, time.day, time.hour, time.minute, time.second
+            ),
+        )];
+        if let Some(monitor) = &self.monitor {
+            fields.push(("Source", monitor.clone()));
+        }
+        if let Some(title) = &self.window_title {
+            fields.push(("Title", title.clone()));
+        }
+        if let Some(application) = &self.application {
+            fields.push(("Application", application.clone()));
+        }
+        fields.push(("Software", self.software.clone()));
+        if let Some(comment) = &self.comment {
+            fields.push(("Comment", comment.clone()));
+        }
+        fields
+    }
+}
+
+/// Add metadata to an encoded image
+pub fn embed(data: Vec<u8>, format: &ImageFormat, metadata: &ImageMetadata) -> AppResult<Vec<u8>> {
+    match format {
+        ImageFormat::Png => embed_png(data, metadata),
+        ImageFormat::Jpg => embed_jpeg(data, metadata),
+        ImageFormat::Bmp => Ok(data),
+    }
+}
+
+/// Add an ICC color profile to an encoded image
+pub fn embed_icc_profile(data: Vec<u8>, format: &ImageFormat, profile: &[u8]) -> AppResult<Vec<u8>> {
+    match format {
+        ImageFormat::Png => {
+            let header_end = png_header_end(&data).ok_or_else(|| {
+                AppError::ImageProcessing("PNGデータが不正なためカラープロファイルを埋め込めません".to_string())
+            })?;
+            // Profile name, then compression method 0 (zlib)
+            let body = [b"ICC profile\0\0".as_slice(), &zlib_stored(profile)].concat();
+            let mut output = Vec::with_capacity(data.len() + body.len() + 12);
+            output.extend_from_slice(&data[..header_end]);
+            write_png_chunk(&mut output, b"iCCP", &body);
+            output.extend_from_slice(&data[header_end..]);
+            Ok(output)
+        }
+        ImageFormat::Jpg => {
+            let insert_at = jpeg_insert_offset(&data).ok_or_else(|| {
+                AppError::ImageProcessing("JPEGデータが不正なためカラープロファイルを埋め込めません".to_string())
+            })?;
+            // Each segment holds the marker, its sequence number, and the segment count
+            let chunk_size = MAX_JPEG_SEGMENT - 2 - ICC_JPEG_MARKER.len() - 2;
+            let chunks: Vec<&[u8]> = profile.chunks(chunk_size).collect();
+            if chunks.len() > usize::from(u8::MAX) {
+                return Err(AppError::ImageProcessing("カラープロファイルが大きすぎるためJPEGに埋め込めません".to_string()));
+            }
+            let mut output = Vec::with_capacity(data.len() + profile.len() + chunks.len() * 18);
+            output.extend_from_slice(&data[..insert_at]);
+            for (index, chunk) in chunks.iter().enumerate() {
+                output.extend_from_slice(&[0xFF, 0xE2]);
+                output.extend_from_slice(&((2 + ICC_JPEG_MARKER.len() + 2 + chunk.len()) as u16).to_be_bytes());
+                output.extend_from_slice(ICC_JPEG_MARKER);
+                output.extend_from_slice(&[index as u8 + 1, chunks.len() as u8]);
+                output.extend_from_slice(chunk);
+            }
+            output.extend_from_slice(&data[insert_at..]);
+            Ok(output)
+        }
+        ImageFormat::Bmp => Ok(data),
+    }
+}
+
+/// Wrap data in a zlib stream of uncompressed blocks
+///
+/// Profiles are small, so they aren't worth a compressor.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut output = vec![0x78, 0x01];
+    let mut blocks = data.chunks(MAX_STORED_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        output.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        output.push(u8::from(blocks.peek().is_none()));
+        let length = block.len() as u16;
+        output.extend_from_slice(&length.to_le_bytes());
+        output.extend_from_slice(&(!length).to_le_bytes());
+        output.extend_from_slice(block);
+    }
+    output.extend_from_slice(&adler32(data).to_be_bytes());
+    output
+}
+
+/// Adler-32 checksum as used by zlib streams
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in bytes {
+        a = (a + u32::from(byte)) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+/// Insert text chunks after the PNG header chunk
+fn embed_png(data: Vec<u8>, metadata: &ImageMetadata) -> AppResult<Vec<u8>> {
+    let header_end = png_header_end(&data)
+        .ok_or_else(|| AppError::ImageProcessing("PNGデータが不正なためメタデータを埋め込めません".to_string()))?;
+
+    let mut output = Vec::with_capacity(data.len() + 256);
+    output.extend_from_slice(&data[..header_end]);
+    for (keyword, text) in metadata.text_fields() {
+        if text.is_ascii() {
+            write_png_chunk(&mut output, b"tEXt", &[keyword.as_bytes(), &[0], text.as_bytes()].concat());
+        } else {
+            // Latin-1 tEXt can't hold other scripts; iTXt carries UTF-8, uncompressed
+            let body = [keyword.as_bytes(), &[0, 0, 0, 0, 0], text.as_bytes()].concat();
+            write_png_chunk(&mut output, b"iTXt", &body);
+        }
+    }
+    output.extend_from_slice(&data[header_end..]);
+    Ok(output)
+}
+
+/// Get the offset just past the IHDR chunk, if the data starts with one
+fn png_header_end(data: &[u8]) -> Option<usize> {
+    if !data.starts_with(&PNG_SIGNATURE) || data.get(12..16)? != b"IHDR" {
+        return None;
+    }
+    let length = u32::from_be_bytes(data.get(8..12)?.try_into().ok()?) as usize;
+    let end = 8 + 4 + 4 + length + 4;
+    (end <= data.len()).then_some(end)
+}
+
+/// Append a PNG chunk with its length and checksum
+fn write_png_chunk(output: &mut Vec<u8>, kind: &[u8; 4], body: &[u8]) {
+    output.extend_from_slice(&(body.len() as u32).to_be_bytes());
+    let start = output.len();
+    output.extend_from_slice(kind);
+    output.extend_from_slice(body);
+    let crc = crc32(&output[start..]);
+    output.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// CRC-32 as used by PNG chunks
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in bytes {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+/// Insert an EXIF segment after the JPEG start marker and any JFIF segment
+fn embed_jpeg(data: Vec<u8>, metadata: &ImageMetadata) -> AppResult<Vec<u8>> {
+    let insert_at = jpeg_insert_offset(&data)
+        .ok_or_else(|| AppError::ImageProcessing("JPEGデータが不正なためメタデータを埋め込めません".to_string()))?;
+
+    let payload = [b"Exif\0\0".as_slice(), &exif_tiff(metadata)].concat();
+    let segment_length = payload.len() + 2;
+    if segment_length > MAX_JPEG_SEGMENT {
+        return Err(AppError::ImageProcessing("メタデータが大きすぎるためJPEGに埋め込めません".to_string()));
+    }
+    let mut output = Vec::with_capacity(data.len() + segment_length + 2);
+    output.extend_from_slice(&data[..insert_at]);
+    output.extend_from_slice(&[0xFF, 0xE1]);
+    output.extend_from_slice(&(segment_length as u16).to_be_bytes());
+    output.extend_from_slice(&payload);
+    output.extend_from_slice(&data[insert_at..]);
+    Ok(output)
+}
+
+/// Get the offset after the JPEG start marker and any JFIF segment, if the data starts with one
+fn jpeg_insert_offset(data: &[u8]) -> Option<usize> {
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+    // Readers expect JFIF, when present, to be the first segment
+    if data.get(2..4) == Some(&[0xFF, 0xE0]) {
+        let length = data.get(4..6).map_or(0, |length| u16::from_be_bytes([length[0], length[1]]) as usize);
+        return Some((4 + length).min(data.len()));
+    }
+    Some(2)
+}
+
+/// EXIF field types
+const TYPE_ASCII: u16 = 2;
+const TYPE_LONG: u16 = 4;
+const TYPE_UNDEFINED: u16 = 7;
+
+/// One field of an EXIF directory
+struct IfdEntry {
+    tag: u16,
+    kind: u16,
+    data: Vec<u8>,
+}
+
+impl IfdEntry {
+    fn ascii(tag: u16, text: &str) -> Self {
+        Self { tag, kind: TYPE_ASCII, data: [text.as_bytes(), &[0]].concat() }
+    }
+
+    fn undefined(tag: u16, data: Vec<u8>) -> Self {
+        Self { tag, kind: TYPE_UNDEFINED, data }
+    }
+
+    fn long(tag: u16, value: u32) -> Self {
+        Self { tag, kind: TYPE_LONG, data: value.to_le_bytes().to_vec() }
+    }
+
+    /// Number of values, counted in the field type's units
+    fn count(&self) -> u32 {
+        match self.kind {
+            TYPE_LONG => (self.data.len() / 4) as u32,
+            _ => self.data.len() as u32,
+        }
+    }
+}
+
+/// Build the little-endian TIFF structure of the EXIF segment
+fn exif_tiff(metadata: &ImageMetadata) -> Vec<u8> {
+    let time = DateTime::from_system_time(metadata.captured_at);
+    let timestamp = format!(
+        "{:04}:{:02}:{:02} {:02}:{:02}:{:02}",
+        time.year, time.month, time.day, time.hour, time.minute, time.second
+    );
+
+    let mut primary = Vec::new();
+    if let Some(application) = &metadata.application {
+        primary.push(IfdEntry::ascii(0x010D, application));
+    }
+    if let Some(title) = &metadata.window_title {
+        primary.push(IfdEntry::ascii(0x010E, title));
+    }
+    if let Some(monitor) = &metadata.monitor {
+        primary.push(IfdEntry::ascii(0x0110, monitor));
+    }
+    primary.push(IfdEntry::ascii(0x0131, &metadata.software));
+    primary.push(IfdEntry::ascii(0x0132, &timestamp));
+
+    let mut exif = vec![
+        IfdEntry::undefined(0x9000, b"0232".to_vec()),
+        IfdEntry::ascii(0x9003, &timestamp),
+        IfdEntry::ascii(0x9011, "+00:00"),
+    ];
+    if let Some(comment) = &metadata.comment {
+        exif.push(IfdEntry::undefined(0x9286, user_comment(comment)));
+    }
+
+    const HEADER_LENGTH: u32 = 8;
+    // The pointer to the EXIF directory doesn't change the primary directory's length
+    primary.push(IfdEntry::long(0x8769, 0));
+    let exif_offset = HEADER_LENGTH + ifd_length(&primary);
+    primary.last_mut().expect("pointer was just added").data = exif_offset.to_le_bytes().to_vec();
+
+    let mut tiff = vec![b'I', b'I', 0x2A, 0x00];
+    tiff.extend_from_slice(&HEADER_LENGTH.to_le_bytes());
+    write_ifd(&mut tiff, &primary);
+    write_ifd(&mut tiff, &exif);
+    tiff
+}
+
+/// Encode a user comment with its character code prefix
+fn user_comment(comment: &str) -> Vec<u8> {
+    if comment.is_ascii() {
+        [b"ASCII\0\0\0".as_slice(), comment.as_bytes()].concat()
+    } else {
+        // UCS-2 in the byte order of the TIFF header
+        let text: Vec<u8> = comment.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        [b"UNICODE\0".as_slice(), &text].concat()
+    }
+}
+
+/// Bytes a directory takes, including values stored after it
+fn ifd_length(entries: &[IfdEntry]) -> u32 {
+    let values: usize = entries
+        .iter()
+        .filter(|entry| entry.data.len() > 4)
+        .map(|entry| entry.data.len() + entry.data.len() % 2)
+        .sum();
+    (2 + entries.len() * 12 + 4 + values) as u32
+}
+
+/// Append a directory whose values follow it, with offsets relative to the TIFF start
+fn write_ifd(tiff: &mut Vec<u8>, entries: &[IfdEntry]) {
+    let start = tiff.len();
+    let mut value_offset = start + 2 + entries.len() * 12 + 4;
+    let mut values = Vec::new();
+
+    tiff.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    for entry in entries {
+        tiff.extend_from_slice(&entry.tag.to_le_bytes());
+        tiff.extend_from_slice(&entry.kind.to_le_bytes());
+        tiff.extend_from_slice(&entry.count().to_le_bytes());
+        if entry.data.len() <= 4 {
+            let mut inline = [0u8; 4];
+            inline[..entry.data.len()].copy_from_slice(&entry.data);
+            tiff.extend_from_slice(&inline);
+        } else {
+            tiff.extend_from_slice(&(value_offset as u32).to_le_bytes());
+            values.extend_from_slice(&entry.data);
+            // Values start on word boundaries
+            if entry.data.len() % 2 == 1 {
+                values.push(0);
+            }
+            value_offset = start + 2 + entries.len() * 12 + 4 + values.len();
+        }
+    }
+    // No further directories
+    tiff.extend_from_slice(&0u32.to_le_bytes());
+    tiff.extend_from_slice(&values);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::encode_image;
+    use image::RgbaImage;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn metadata() -> ImageMetadata {
+        ImageMetadata {
+            captured_at: UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+            monitor: Some("Monitor 1 (1920x1080 at (0, 0), primary)".to_string()),
+            window_title: Some("メモ帳".to_string()),
+            application: Some("notepad.exe".to_string()),
+            software: "Lightweight Screenshot App 0.1.0".to_string(),
+            comment: Some("Build 42".to_string()),
+            icc_profile: None,
+        }
+    }
+
+    /// Read the text chunks of a PNG file
+    fn png_text(data: &[u8]) -> Vec<(String, String)> {
+        let mut chunks = Vec::new();
+        let mut offset = 8;
+        while offset + 8 <= data.len() {
+            let length = u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap()) as usize;
+            let kind = &data[offset + 4..offset + 8];
+            let body = &data[offset + 8..offset + 8 + length];
+            let split = body.iter().position(|&b| b == 0).unwrap_or(0);
+            let keyword = String::from_utf8_lossy(&body[..split]).into_owned();
+            match kind {
+                b"tEXt" => chunks.push((keyword, String::from_utf8_lossy(&body[split + 1..]).into_owned())),
+                b"iTXt" => chunks.push((keyword, String::from_utf8_lossy(&body[split + 5..]).into_owned())),
+                _ => {}
+            }
+            offset += 12 + length;
+        }
+        chunks
+    }
+
+    #[test]
+    fn test_png_text_chunks() {
+        let image = RgbaImage::from_pixel(3, 2, image::Rgba([1, 2, 3, 255]));
+        let data = embed(encode_image(&image, &ImageFormat::Png).unwrap(), &ImageFormat::Png, &metadata()).unwrap();
+
+        // The decoder checks the chunk checksums
+        let decoded = image::load_from_memory(&data).unwrap().to_rgba8();
+        assert_eq!(decoded, image);
+        assert_eq!(
+            png_text(&data),
+            vec![
+                ("Creation Time".to_string(), "2023-11-14T22:13:20Z".to_string()),
+                ("Source".to_string(), "Monitor 1 (1920x1080 at (0, 0), primary)".to_string()),
+                ("Title".to_string(), "メモ帳".to_string()),
+                ("Application".to_string(), "notepad.exe".to_string()),
+                ("Software".to_string(), "Lightweight Screenshot App 0.1.0".to_string()),
+                ("Comment".to_string(), "Build 42".to_string()),
+            ]
+        );
+        assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+    }
+
+    #[test]
+    fn test_jpeg_exif_segment() {
+        let image = RgbaImage::from_pixel(8, 8, image::Rgba([200, 100, 50, 255]));
+        let mut metadata = metadata();
+        metadata.comment = Some("確認済み".to_string());
+        let data = embed(encode_image(&image, &ImageFormat::Jpg).unwrap(), &ImageFormat::Jpg, &metadata).unwrap();
+        assert_eq!(image::load_from_memory(&data).unwrap().to_rgba8().dimensions(), (8, 8));
+
+        let exif = data.windows(6).position(|window| window == b"Exif\0\0").expect("EXIF segment");
+        assert_eq!(&data[exif - 4..exif - 2], &[0xFF, 0xE1]);
+        let tiff = &data[exif + 6..];
+        assert_eq!(&tiff[..4], b"II\x2A\x00");
+
+        // Follow the primary directory to the EXIF directory and its timestamp
+        let read_u16 = |offset: usize| u16::from_le_bytes([tiff[offset], tiff[offset + 1]]);
+        let read_u32 = |offset: usize| u32::from_le_bytes(tiff[offset..offset + 4].try_into().unwrap()) as usize;
+        let find = |ifd: usize, tag: u16| {
+            (0..read_u16(ifd) as usize)
+                .map(|index| ifd + 2 + index * 12)
+                .find(|&entry| read_u16(entry) == tag)
+                .expect("tag should be present")
+        };
+        let ascii = |entry: usize| {
+            let (count, offset) = (read_u32(entry + 4), read_u32(entry + 8));
+            String::from_utf8(tiff[offset..offset + count - 1].to_vec()).unwrap()
+        };
+        let primary = read_u32(4);
+        assert_eq!(ascii(find(primary, 0x010D)), "notepad.exe");
+        assert_eq!(ascii(find(primary, 0x010E)), "メモ帳");
+        assert_eq!(ascii(find(primary, 0x0131)), "Lightweight Screenshot App 0.1.0");
+        let exif_ifd = read_u32(find(primary, 0x8769) + 8);
+        assert_eq!(ascii(find(exif_ifd, 0x9003)), "2023:11:14 22:13:20");
+        let comment = find(exif_ifd, 0x9286);
+        let (count, offset) = (read_u32(comment + 4), read_u32(comment + 8));
+        assert_eq!(&tiff[offset..offset + 8], b"UNICODE\0");
+        let units: Vec<u16> =
+            tiff[offset + 8..offset + count].chunks(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]])).collect();
+        assert_eq!(String::from_utf16(&units).unwrap(), "確認済み");
+    }
+
+    #[test]
+    fn test_bmp_unchanged_and_invalid_data() {
+        let data = vec![b'B', b'M', 0, 0];
+        assert_eq!(embed(data.clone(), &ImageFormat::Bmp, &metadata()).unwrap(), data);
+        assert!(embed(data.clone(), &ImageFormat::Png, &metadata()).is_err());
+        assert!(embed(data, &ImageFormat::Jpg, &metadata()).is_err());
+    }
+
+    #[test]
+    fn test_icc_profile() {
+        let profile = crate::color::srgb_icc_profile();
+        let image = RgbaImage::from_pixel(4, 4, image::Rgba([10, 20, 30, 255]));
+
+        let png = embed_icc_profile(encode_image(&image, &ImageFormat::Png).unwrap(), &ImageFormat::Png, &profile).unwrap();
+        assert_eq!(image::load_from_memory(&png).unwrap().to_rgba8(), image);
+        let chunk = png.windows(4).position(|window| window == b"iCCP").expect("iCCP chunk");
+        let length = u32::from_be_bytes(png[chunk - 4..chunk].try_into().unwrap()) as usize;
+        let body = &png[chunk + 4..chunk + 4 + length];
+        assert!(body.starts_with(b"ICC profile\0\0\x78\x01"));
+        // One stored block holds the whole profile
+        let length = (profile.len() as u16).to_le_bytes();
+        assert_eq!(&body[15..20], &[1, length[0], length[1], !length[0], !length[1]]);
+        assert_eq!(&body[20..20 + profile.len()], profile.as_slice());
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+
+        let jpeg = embed_icc_profile(encode_image(&image, &ImageFormat::Jpg).unwrap(), &ImageFormat::Jpg, &profile).unwrap();
+        assert_eq!(image::load_from_memory(&jpeg).unwrap().to_rgba8().dimensions(), (4, 4));
+        let segment = jpeg.windows(12).position(|window| window == ICC_JPEG_MARKER).expect("APP2 segment");
+        assert_eq!(&jpeg[segment - 4..segment - 2], &[0xFF, 0xE2]);
+        assert_eq!(&jpeg[segment + 12..segment + 14], &[1, 1]);
+        assert_eq!(&jpeg[segment + 14..segment + 14 + profile.len()], profile.as_slice());
+
+        // Large profiles span several segments and blocks
+        let large = vec![7u8; 150_000];
+        let jpeg = embed_icc_profile(encode_image(&image, &ImageFormat::Jpg).unwrap(), &ImageFormat::Jpg, &large).unwrap();
+        assert_eq!(jpeg.windows(12).filter(|window| window == ICC_JPEG_MARKER).count(), 3);
+        assert_eq!(zlib_stored(&large).len(), 2 + 3 * 5 + large.len() + 4);
+        assert!(embed_icc_profile(vec![0, 1], &ImageFormat::Png, &profile).is_err());
+    }
+}
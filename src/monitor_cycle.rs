@@ -0,0 +1,113 @@
+//! Choosing the monitor of a screen capture by pressing the hotkey again
+//!
+//! With monitor cycling on, the screen capture hotkey doesn't capture right
+//! away. The first press picks monitor 1, and each press within
+//! [`CYCLE_TIMEOUT`] moves on to the next monitor and finally to all
+//! monitors at once, then wraps around. A label on screen shows the current
+//! choice, and once the hotkey hasn't been pressed for [`CYCLE_TIMEOUT`],
+//! the chosen target is captured.
+
+use std::time::{Duration, Instant};
+
+/// How long after the last press the chosen target is captured
+pub const CYCLE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// What the cycling hotkey captures
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CycleTarget {
+    /// The screen with this index
+    Monitor(usize),
+    /// Every screen, arranged as on the desktop
+    AllMonitors,
+}
+
+impl CycleTarget {
+    /// Get the label shown while the target is chosen, e.g. "Monitor 2"
+    pub fn label(&self) -> String {
+        match self {
+            CycleTarget::Monitor(index) => format!("Monitor {}", index + 1),
+            CycleTarget::AllMonitors => "All Monitors".to_string(),
+        }
+    }
+}
+
+/// Presses of the cycling hotkey, until the chosen target is captured
+#[derive(Debug, Clone, Default)]
+pub struct MonitorCycle {
+    /// Current choice and when the hotkey was last pressed
+    chosen: Option<(CycleTarget, Instant)>,
+}
+
+impl MonitorCycle {
+    /// Register a press of the hotkey with `screens` monitors, returning the new choice
+    ///
+    /// A press after the previous choice was captured starts over at monitor
+    /// 1. With a single monitor there is nothing to cycle through.
+    pub fn press(&mut self, screens: usize, now: Instant) -> CycleTarget {
+        let target = match self.chosen {
+            _ if screens <= 1 => CycleTarget::Monitor(0),
+            Some((CycleTarget::Monitor(index), _)) if index + 1 < screens => CycleTarget::Monitor(index + 1),
+            Some((CycleTarget::Monitor(_), _)) => CycleTarget::AllMonitors,
+            Some((CycleTarget::AllMonitors, _)) | None => CycleTarget::Monitor(0),
+        };
+        self.chosen = Some((target, now));
+        target
+    }
+
+    /// Get the current choice while the hotkey is being pressed
+    pub fn chosen(&self) -> Option<CycleTarget> {
+        self.chosen.map(|(target, _)| target)
+    }
+
+    /// Get how long until the choice is captured, if a choice is pending
+    pub fn time_until_due(&self, now: Instant) -> Option<Duration> {
+        self.chosen
+            .map(|(_, pressed)| CYCLE_TIMEOUT.saturating_sub(now.saturating_duration_since(pressed)))
+    }
+
+    /// Take the choice once the hotkey hasn't been pressed for [`CYCLE_TIMEOUT`]
+    pub fn take_due(&mut self, now: Instant) -> Option<CycleTarget> {
+        let (target, pressed) = self.chosen?;
+        if now.saturating_duration_since(pressed) < CYCLE_TIMEOUT {
+            return None;
+        }
+        self.chosen = None;
+        Some(target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_presses_cycle_through_monitors_then_all() {
+        let start = Instant::now();
+        let mut cycle = MonitorCycle::default();
+        let presses: Vec<CycleTarget> = (0..5)
+            .map(|i| cycle.press(3, start + Duration::from_millis(500 * i)))
+            .collect();
+        assert_eq!(
+            presses,
+            vec![
+                CycleTarget::Monitor(0),
+                CycleTarget::Monitor(1),
+                CycleTarget::Monitor(2),
+                CycleTarget::AllMonitors,
+                CycleTarget::Monitor(0),
+            ]
+        );
+        assert_eq!(presses[3].label(), "All Monitors");
+
+        // Captured two seconds after the last press, at 2 s
+        let last = start + Duration::from_millis(2000);
+        assert_eq!(cycle.take_due(last + Duration::from_millis(1999)), None);
+        assert_eq!(cycle.time_until_due(last + Duration::from_millis(1500)), Some(Duration::from_millis(500)));
+        assert_eq!(cycle.take_due(last + CYCLE_TIMEOUT), Some(CycleTarget::Monitor(0)));
+        assert_eq!(cycle.chosen(), None);
+
+        // The next press starts over
+        assert_eq!(cycle.press(3, last + Duration::from_secs(10)), CycleTarget::Monitor(0));
+        assert_eq!(cycle.press(1, last + Duration::from_secs(11)), CycleTarget::Monitor(0));
+    }
+}
@@ -0,0 +1,544 @@
+//! File name templates
+//!
+//! New screenshots are named from a template such as
+//! `screenshot_{date}_{time}`. The `{date}` and `{time}` tokens are formatted
+//! with strftime-style patterns from settings, or with the conventions of the
+//! system locale when no pattern is set (e.g. `2024-05-01` or `2024年05月01日`).
+//! `{app}` and `{title}` name the window a window or region capture shows;
+//! they are empty for other images.
+//!
+//! `{counter}` numbers the files. The last number of each counter is kept
+//! in `counters.json` in the config directory, so numbering continues after
+//! a restart. Templates with the same text before `{counter}` share a
+//! counter, since they name the same series of files, and templates of
+//! different presets with different prefixes count independently.
+
+use crate::datetime::DateTime;
+use crate::files::{sanitize_file_name, write_atomic};
+use crate::foreground::ForegroundWindow;
+use crate::settings::config_dir;
+use crate::types::{AppError, AppResult};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Template token replaced with the formatted date
+pub const DATE_TOKEN: &str = "date";
+
+/// Template token replaced with the formatted time
+pub const TIME_TOKEN: &str = "time";
+
+/// Template token replaced with the captured application's name, e.g. `notepad`
+pub const APP_TOKEN: &str = "app";
+
+/// Template token replaced with the captured window's title
+pub const TITLE_TOKEN: &str = "title";
+
+/// Template token replaced with a number counting up across captures and restarts
+pub const COUNTER_TOKEN: &str = "counter";
+
+/// Most digits `{counter}` can be padded to
+pub const MAX_COUNTER_WIDTH: u32 = 10;
+
+/// Counters file in the config directory
+const COUNTERS_FILE_NAME: &str = "counters.json";
+
+/// Start dates (year, month, day) and names of Japanese eras, newest first
+const JAPANESE_ERAS: [((i32, u32, u32), &str); 5] = [
+    ((2019, 5, 1), "令和"),
+    ((1989, 1, 8), "平成"),
+    ((1926, 12, 25), "昭和"),
+    ((1912, 7, 30), "大正"),
+    // First day of the Gregorian calendar in Japan
+    ((1873, 1, 1), "明治"),
+];
+
+/// When the `{counter}` number starts over at 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum CounterReset {
+    /// Keep counting up
+    #[default]
+    Never,
+    /// Start over on the first capture of each day
+    Daily,
+}
+
+impl CounterReset {
+    /// Get all reset options
+    pub fn all() -> [CounterReset; 2] {
+        [CounterReset::Never, CounterReset::Daily]
+    }
+}
+
+impl std::fmt::Display for CounterReset {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CounterReset::Never => write!(f, "Never"),
+            CounterReset::Daily => write!(f, "Every Day"),
+        }
+    }
+}
+
+/// Template for the names of new screenshots
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FileNameTemplate {
+    /// File name without extension, with `{date}`, `{time}`, `{app}`, `{title}`, and `{counter}` tokens
+    pub template: String,
+    /// strftime-style pattern for `{date}`, or empty for the locale's format
+    pub date_format: String,
+    /// strftime-style pattern for `{time}`, or empty for the locale's format
+    pub time_format: String,
+    /// Digits `{counter}` is zero-padded to
+    pub counter_width: u32,
+    pub counter_reset: CounterReset,
+}
+
+impl Default for FileNameTemplate {
+    fn default() -> Self {
+        Self {
+            template: "screenshot_{date}_{time}".to_string(),
+            date_format: String::new(),
+            time_format: String::new(),
+            counter_width: 3,
+            counter_reset: CounterReset::Never,
+        }
+    }
+}
+
+impl FileNameTemplate {
+    /// Build a file name (without extension) for a capture time
+    pub fn render(&self, time: &DateTime, locale: &str) -> AppResult<String> {
+        self.render_for(time, locale, &ForegroundWindow::default())
+    }
+
+    /// Build a file name (without extension) for a capture of a window
+    ///
+    /// Characters not allowed in file names, e.g. in the window title, are
+    /// replaced. `{counter}` is rendered as 1; see [`Self::render_numbered`].
+    pub fn render_for(&self, time: &DateTime, locale: &str, window: &ForegroundWindow) -> AppResult<String> {
+        self.render_numbered(time, locale, window, 1)
+    }
+
+    /// Build a file name (without extension) for a capture of a window with a `{counter}` number
+    pub fn render_numbered(&self, time: &DateTime, locale: &str, window: &ForegroundWindow, counter: u64) -> AppResult<String> {
+        Ok(sanitize_file_name(&self.render_unsanitized(time, locale, window, counter)?))
+    }
+
+    /// Get the text before `{counter}`, which identifies the counter, or `None` without one
+    pub fn counter_scope(&self) -> Option<&str> {
+        let token = format!("{{{}}}", COUNTER_TOKEN);
+        self.template.find(&token).map(|start| &self.template[..start])
+    }
+
+    /// Build a file name for the current local time in the system locale
+    pub fn preview(&self) -> AppResult<String> {
+        self.render(&DateTime::now_local(), &system_locale())
+    }
+
+    fn render_unsanitized(&self, time: &DateTime, locale: &str, window: &ForegroundWindow, counter: u64) -> AppResult<String> {
+        let (locale_date, locale_time) = locale_formats(locale);
+        let date_format = if self.date_format.trim().is_empty() { locale_date } else { self.date_format.as_str() };
+        let time_format = if self.time_format.trim().is_empty() { locale_time } else { self.time_format.as_str() };
+
+        let mut name = String::new();
+        let mut rest = self.template.as_str();
+        while let Some(start) = rest.find('{') {
+            name.push_str(&rest[..start]);
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| AppError::Settings("テンプレートの { が閉じられていません".to_string()))?;
+            let token = &rest[start + 1..start + end];
+            match token {
+                DATE_TOKEN => name.push_str(&format_datetime(date_format, time)?),
+                TIME_TOKEN => name.push_str(&format_datetime(time_format, time)?),
+                APP_TOKEN => name.push_str(window.app_name().unwrap_or_default()),
+                TITLE_TOKEN => name.push_str(&window.short_title().unwrap_or_default()),
+                COUNTER_TOKEN => name.push_str(&format!("{:0width$}", counter, width = self.counter_width as usize)),
+                _ => return Err(AppError::Settings(format!("不明なテンプレートトークンです: {{{}}}", token))),
+            }
+            rest = &rest[start + end + 1..];
+        }
+        name.push_str(rest);
+        Ok(name)
+    }
+
+    /// Validate the template and date formats
+    pub fn validate(&self) -> AppResult<()> {
+        if self.template.trim().is_empty() {
+            return Err(AppError::Settings("ファイル名テンプレートが空です".to_string()));
+        }
+        if !(1..=MAX_COUNTER_WIDTH).contains(&self.counter_width) {
+            return Err(AppError::Settings(format!(
+                "連番の桁数は1〜{}で指定してください",
+                MAX_COUNTER_WIDTH
+            )));
+        }
+        let sample = DateTime {
+            year: 2024,
+            month: 5,
+            day: 1,
+            hour: 9,
+            minute: 5,
+            second: 3,
+        };
+        let window = ForegroundWindow {
+            title: Some("Untitled - Notepad".to_string()),
+            application: Some("notepad.exe".to_string()),
+        };
+        let name = self.render_unsanitized(&sample, &system_locale(), &window, 1)?;
+        if name != sanitize_file_name(&name) {
+            return Err(AppError::Settings(format!(
+                "ファイル名に使用できない文字が含まれています: {}",
+                name
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Format a date and time with a strftime-style pattern
+///
+/// Supports `%Y %y %m %d %H %I %M %S %p %%`, the `-` flag to drop zero
+/// padding (`%-m`), and `%EC`/`%Ey` for the Japanese era name and year.
+pub fn format_datetime(pattern: &str, time: &DateTime) -> AppResult<String> {
+    let mut output = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            output.push(c);
+            continue;
+        }
+
+        let mut spec = chars.next();
+        let pad = spec != Some('-');
+        if !pad {
+            spec = chars.next();
+        }
+        let number = |value: u32| if pad { format!("{:02}", value) } else { value.to_string() };
+        match spec {
+            Some('Y') => output.push_str(&time.year.to_string()),
+            Some('y') => output.push_str(&number(time.year.rem_euclid(100) as u32)),
+            Some('m') => output.push_str(&number(time.month)),
+            Some('d') => output.push_str(&number(time.day)),
+            Some('H') => output.push_str(&number(time.hour)),
+            Some('I') => output.push_str(&number((time.hour + 11) % 12 + 1)),
+            Some('M') => output.push_str(&number(time.minute)),
+            Some('S') => output.push_str(&number(time.second)),
+            Some('p') => output.push_str(if time.hour < 12 { "AM" } else { "PM" }),
+            Some('%') => output.push('%'),
+            Some('E') => {
+                let (era, year) = japanese_era(time)
+                    .ok_or_else(|| AppError::Settings("和暦に対応していない日付です".to_string()))?;
+                match chars.next() {
+                    Some('C') => output.push_str(era),
+                    // Era years are conventionally written without padding
+                    Some('y') => output.push_str(&year.to_string()),
+                    other => return Err(unknown_specifier(format!("E{}", other.map(String::from).unwrap_or_default()))),
+                }
+            }
+            other => return Err(unknown_specifier(other.map(String::from).unwrap_or_default())),
+        }
+    }
+    Ok(output)
+}
+
+fn unknown_specifier(spec: String) -> AppError {
+    AppError::Settings(format!("不明な日付書式です: %{}", spec))
+}
+
+/// Get the Japanese era name and era year of a date
+pub fn japanese_era(time: &DateTime) -> Option<(&'static str, i32)> {
+    JAPANESE_ERAS
+        .iter()
+        .find(|(start, _)| (time.year, time.month, time.day) >= *start)
+        .map(|((start_year, _, _), name)| (*name, time.year - start_year + 1))
+}
+
+/// Get the default date and time patterns of a locale (e.g. "ja-JP")
+///
+/// Separators that are invalid in file names (`/` and `:`) are avoided.
+pub fn locale_formats(locale: &str) -> (&'static str, &'static str) {
+    let mut parts = locale.split(['-', '_']);
+    let language = parts.next().unwrap_or_default().to_ascii_lowercase();
+    let region = parts.next().unwrap_or_default().to_ascii_uppercase();
+    match (language.as_str(), region.as_str()) {
+        ("ja", _) => ("%Y年%m月%d日", "%H時%M分%S秒"),
+        ("zh", _) => ("%Y年%m月%d日", "%H-%M-%S"),
+        ("ko", _) => ("%Y년 %m월 %d일", "%H-%M-%S"),
+        ("en", "US") => ("%m-%d-%Y", "%I-%M-%S %p"),
+        ("de" | "ru" | "pl" | "cs" | "fi" | "nb" | "tr", _) => ("%d.%m.%Y", "%H-%M-%S"),
+        ("en" | "fr" | "es" | "it" | "pt" | "nl", _) => ("%d-%m-%Y", "%H-%M-%S"),
+        _ => ("%Y-%m-%d", "%H-%M-%S"),
+    }
+}
+
+/// Get the user's locale name (e.g. "ja-JP"), or an empty string if unknown
+#[cfg(windows)]
+pub fn system_locale() -> String {
+    use winapi::um::winnls::GetUserDefaultLocaleName;
+    use winapi::um::winnt::LOCALE_NAME_MAX_LENGTH;
+
+    let mut buffer = [0u16; LOCALE_NAME_MAX_LENGTH];
+    // SAFETY: the buffer length is passed with the buffer
+    let length = unsafe { GetUserDefaultLocaleName(buffer.as_mut_ptr(), buffer.len() as i32) };
+    if length <= 1 {
+        return String::new();
+    }
+    String::from_utf16_lossy(&buffer[..length as usize - 1])
+}
+
+/// Get the user's locale name (e.g. "ja-JP"), or an empty string if unknown
+#[cfg(not(windows))]
+pub fn system_locale() -> String {
+    ["LC_ALL", "LC_TIME", "LANG"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|value| !value.is_empty())
+        .map(|value| {
+            // "ja_JP.UTF-8" -> "ja-JP"
+            let name = value.split(['.', '@']).next().unwrap_or_default();
+            if name == "C" || name == "POSIX" { String::new() } else { name.replace('_', "-") }
+        })
+        .unwrap_or_default()
+}
+
+/// Get the default counters file
+pub fn default_counters_path() -> PathBuf {
+    config_dir().join(COUNTERS_FILE_NAME)
+}
+
+/// Last number of a `{counter}`
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct CounterState {
+    last: u64,
+    /// Day the last number was used, as `YYYY-MM-DD`, for daily resets
+    day: String,
+}
+
+/// `{counter}` numbers, kept in a file if one is given
+#[derive(Debug, Default)]
+pub struct CounterStore {
+    path: Option<PathBuf>,
+    /// Counters by the text before `{counter}` in their templates
+    counters: BTreeMap<String, CounterState>,
+}
+
+impl CounterStore {
+    /// Load counters from `path`, starting empty if the file doesn't exist
+    pub fn load(path: PathBuf) -> AppResult<Self> {
+        let counters = match std::fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json)
+                .map_err(|e| AppError::Settings(format!("連番ファイルの解析に失敗しました: {}", e)))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self {
+            path: Some(path),
+            counters,
+        })
+    }
+
+    /// Get the number the next file named with `template` at `time` gets, without using it
+    pub fn peek(&self, template: &FileNameTemplate, time: &DateTime) -> u64 {
+        let Some(scope) = template.counter_scope() else {
+            return 1;
+        };
+        let day = day_of(time);
+        match self.counters.get(scope) {
+            Some(state) if template.counter_reset == CounterReset::Daily && state.day != day => 1,
+            Some(state) => state.last + 1,
+            None => 1,
+        }
+    }
+
+    /// Use the next number of `template`'s counter at `time`, saving it to the file
+    ///
+    /// Templates without `{counter}` always get 1 and leave the file alone.
+    pub fn advance(&mut self, template: &FileNameTemplate, time: &DateTime) -> AppResult<u64> {
+        let number = self.peek(template, time);
+        let Some(scope) = template.counter_scope() else {
+            return Ok(number);
+        };
+        self.counters.insert(
+            scope.to_string(),
+            CounterState {
+                last: number,
+                day: day_of(time),
+            },
+        );
+        self.write()?;
+        Ok(number)
+    }
+
+    /// Write all counters to the file, if there is one
+    fn write(&self) -> AppResult<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let json = serde_json::to_string_pretty(&self.counters)
+            .map_err(|e| AppError::Settings(format!("連番のシリアライズに失敗しました: {}", e)))?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        write_atomic(path, json.as_bytes())
+    }
+}
+
+/// Get the day of a time as `YYYY-MM-DD`
+fn day_of(time: &DateTime) -> String {
+    format!("{:04}-{:02}-{:02}", time.year, time.month, time.day)
+}
+
+/// Get a path in `directory` that doesn't exist yet, adding a counter to the name if needed
+pub fn unique_path(directory: &Path, name: &str, extension: &str) -> PathBuf {
+    let mut path = directory.join(format!("{}.{}", name, extension));
+    let mut counter = 2;
+    while path.exists() {
+        path = directory.join(format!("{}_{}.{}", name, counter, extension));
+        counter += 1;
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> DateTime {
+        DateTime {
+            year: 2024,
+            month: 5,
+            day: 1,
+            hour: 14,
+            minute: 5,
+            second: 3,
+        }
+    }
+
+    #[test]
+    fn test_format_patterns() {
+        let time = sample();
+        assert_eq!(format_datetime("%Y-%m-%d", &time).unwrap(), "2024-05-01");
+        assert_eq!(format_datetime("%EC%Ey年%-m月%-d日", &time).unwrap(), "令和6年5月1日");
+        assert_eq!(format_datetime("%I-%M %p 100%%", &time).unwrap(), "02-05 PM 100%");
+        assert_eq!(format_datetime("%y%m%d", &time).unwrap(), "240501");
+        assert!(format_datetime("%Q", &time).is_err());
+        assert!(format_datetime("%", &time).is_err());
+    }
+
+    #[test]
+    fn test_japanese_era_boundaries() {
+        let date = |year, month, day| DateTime { year, month, day, hour: 0, minute: 0, second: 0 };
+        assert_eq!(japanese_era(&date(2019, 4, 30)), Some(("平成", 31)));
+        assert_eq!(japanese_era(&date(2019, 5, 1)), Some(("令和", 1)));
+        assert_eq!(japanese_era(&date(1989, 1, 7)), Some(("昭和", 64)));
+        assert_eq!(japanese_era(&date(1850, 1, 1)), None);
+    }
+
+    #[test]
+    fn test_render_with_locale() {
+        let template = FileNameTemplate::default();
+        assert_eq!(
+            template.render(&sample(), "ja-JP").unwrap(),
+            "screenshot_2024年05月01日_14時05分03秒"
+        );
+        assert_eq!(template.render(&sample(), "en-US").unwrap(), "screenshot_05-01-2024_02-05-03 PM");
+        assert_eq!(template.render(&sample(), "").unwrap(), "screenshot_2024-05-01_14-05-03");
+
+        let custom = FileNameTemplate {
+            template: "{date} 手順".to_string(),
+            date_format: "%Y%m%d".to_string(),
+            ..FileNameTemplate::default()
+        };
+        assert_eq!(custom.render(&sample(), "ja-JP").unwrap(), "20240501 手順");
+    }
+
+    #[test]
+    fn test_render_window_tokens() {
+        let template = FileNameTemplate {
+            template: "{app}_{title}_{date}".to_string(),
+            date_format: "%Y%m%d".to_string(),
+            ..FileNameTemplate::default()
+        };
+        let window = ForegroundWindow {
+            title: Some("Q3 report: draft * - Excel".to_string()),
+            application: Some("EXCEL.EXE".to_string()),
+        };
+        assert_eq!(template.render_for(&sample(), "", &window).unwrap(), "EXCEL_Q3 report_ draft _ - Excel_20240501");
+        // Other images leave the tokens empty
+        assert_eq!(template.render(&sample(), "").unwrap(), "__20240501");
+        assert!(template.validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate_template() {
+        assert!(FileNameTemplate::default().validate().is_ok());
+        let invalid = |template: &str, time_format: &str| FileNameTemplate {
+            template: template.to_string(),
+            time_format: time_format.to_string(),
+            ..FileNameTemplate::default()
+        };
+        assert!(invalid("", "").validate().is_err());
+        assert!(invalid("shot_{user}", "").validate().is_err());
+        assert!(invalid("shot_{date", "").validate().is_err());
+        assert!(invalid("shot_{time}", "%H:%M").validate().is_err());
+        assert!(invalid("shot_{time}", "%H%M").validate().is_ok());
+    }
+
+    #[test]
+    fn test_counter_token() {
+        let template = FileNameTemplate {
+            template: "bug_{counter}".to_string(),
+            counter_width: 4,
+            ..FileNameTemplate::default()
+        };
+        let window = ForegroundWindow::default();
+        assert_eq!(template.render_numbered(&sample(), "", &window, 12).unwrap(), "bug_0012");
+        assert_eq!(template.render_numbered(&sample(), "", &window, 123456).unwrap(), "bug_123456");
+        assert_eq!(template.counter_scope(), Some("bug_"));
+        assert_eq!(FileNameTemplate::default().counter_scope(), None);
+
+        assert!(FileNameTemplate { counter_width: 0, ..template.clone() }.validate().is_err());
+        assert!(template.validate().is_ok());
+    }
+
+    #[test]
+    fn test_counters_persist_per_prefix() {
+        let dir = std::env::temp_dir().join(format!("naming_test_{}", uuid::Uuid::new_v4()));
+        let path = dir.join(COUNTERS_FILE_NAME);
+        let bugs = FileNameTemplate { template: "bug_{counter}".to_string(), ..FileNameTemplate::default() };
+        let docs = FileNameTemplate { template: "doc_{counter}_{date}".to_string(), ..FileNameTemplate::default() };
+
+        let mut counters = CounterStore::load(path.clone()).unwrap();
+        assert_eq!(counters.advance(&bugs, &sample()).unwrap(), 1);
+        assert_eq!(counters.advance(&bugs, &sample()).unwrap(), 2);
+        assert_eq!(counters.advance(&docs, &sample()).unwrap(), 1);
+        assert_eq!(counters.peek(&bugs, &sample()), 3);
+
+        // Numbering continues after a restart
+        let mut reloaded = CounterStore::load(path).unwrap();
+        assert_eq!(reloaded.advance(&bugs, &sample()).unwrap(), 3);
+
+        // Daily counters start over on a new day
+        let daily = FileNameTemplate { counter_reset: CounterReset::Daily, ..bugs.clone() };
+        let next_day = DateTime { day: 2, ..sample() };
+        assert_eq!(reloaded.peek(&daily, &sample()), 4);
+        assert_eq!(reloaded.advance(&daily, &next_day).unwrap(), 1);
+        assert_eq!(reloaded.advance(&daily, &next_day).unwrap(), 2);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_unique_path() {
+        let dir = std::env::temp_dir().join(format!("naming_test_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        assert_eq!(unique_path(&dir, "shot", "png"), dir.join("shot.png"));
+        std::fs::write(dir.join("shot.png"), b"").unwrap();
+        std::fs::write(dir.join("shot_2.png"), b"").unwrap();
+        assert_eq!(unique_path(&dir, "shot", "png"), dir.join("shot_3.png"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
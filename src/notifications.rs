@@ -0,0 +1,419 @@
+//! Notifications
+//!
+//! Failures that don't need an answer from the user, such as a preset that
+//! couldn't be saved or a dropped file that couldn't be opened, are shown as
+//! toasts in the corner of the editor instead of only being logged. Every
+//! notification is also kept in a bounded history for the error log viewer.
+//! While the editor window is hidden, errors are shown as system
+//! notifications instead.
+//!
+//! Captures copied or saved by a preset without opening the editor get a
+//! [`CaptureToast`] of their own, with a thumbnail and buttons to open the
+//! capture, copy its path, or undo the save. It is drawn in a small window
+//! of its own above other windows, or shown by Windows as a [`SystemToast`]
+//! while the editor window is hidden.
+
+use crate::datetime::DateTime;
+use crate::types::AppError;
+use image::RgbaImage;
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// How long information and warning toasts stay up; error toasts stay until dismissed
+pub const TOAST_DURATION: Duration = Duration::from_secs(6);
+
+/// How long the toast of a silent capture stays up
+pub const CAPTURE_TOAST_DURATION: Duration = Duration::from_secs(8);
+
+/// Most toasts shown at once; older ones are dropped from the screen first
+const MAX_TOASTS: usize = 5;
+
+/// Most notifications kept for the error log viewer
+const MAX_HISTORY: usize = 200;
+
+/// How serious a notification is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Severity::Info => "Info",
+            Severity::Warning => "Warning",
+            Severity::Error => "Error",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A message for the user
+#[derive(Debug, Clone, PartialEq)]
+pub struct Notification {
+    pub id: u64,
+    pub severity: Severity,
+    pub title: String,
+    pub message: String,
+    /// Local time the notification was raised, for the log viewer
+    pub time: DateTime,
+    created: Instant,
+}
+
+impl Notification {
+    /// Check whether the toast should disappear on its own by `now`
+    fn has_expired(&self, now: Instant) -> bool {
+        self.severity != Severity::Error && now.duration_since(self.created) >= TOAST_DURATION
+    }
+}
+
+/// Toasts on screen and the history of past notifications
+#[derive(Debug, Default)]
+pub struct Notifications {
+    next_id: u64,
+    toasts: Vec<Notification>,
+    /// Oldest first
+    history: VecDeque<Notification>,
+}
+
+impl Notifications {
+    /// Create an empty notification center
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Log a notification, show it as a toast, and add it to the history
+    pub fn push(&mut self, severity: Severity, title: &str, message: String) -> u64 {
+        let notification = self.record(severity, title, message);
+        let id = notification.id;
+        self.toasts.push(notification);
+        if self.toasts.len() > MAX_TOASTS {
+            self.toasts.remove(0);
+        }
+        id
+    }
+
+    /// Show an error as a toast
+    pub fn error(&mut self, title: &str, error: &AppError) -> u64 {
+        self.push(Severity::Error, title, error.to_string())
+    }
+
+    /// Log a notification and add it to the history without showing a toast
+    ///
+    /// Used for errors already shown another way, e.g. in a dialog.
+    pub fn record(&mut self, severity: Severity, title: &str, message: String) -> Notification {
+        match severity {
+            Severity::Info => log::info!("{}: {}", title, message),
+            Severity::Warning => log::warn!("{}: {}", title, message),
+            Severity::Error => log::error!("{}: {}", title, message),
+        }
+        let notification = Notification {
+            id: self.next_id,
+            severity,
+            title: title.to_string(),
+            message,
+            time: DateTime::now_local(),
+            created: Instant::now(),
+        };
+        self.next_id += 1;
+        self.history.push_back(notification.clone());
+        if self.history.len() > MAX_HISTORY {
+            self.history.pop_front();
+        }
+        notification
+    }
+
+    /// Close a toast
+    pub fn dismiss(&mut self, id: u64) {
+        self.toasts.retain(|toast| toast.id != id);
+    }
+
+    /// Close the toasts that time out by `now`, returning whether any remain that will
+    pub fn expire(&mut self, now: Instant) -> bool {
+        self.toasts.retain(|toast| !toast.has_expired(now));
+        self.toasts.iter().any(|toast| toast.severity != Severity::Error)
+    }
+
+    /// Get the toasts to show, oldest first
+    pub fn toasts(&self) -> &[Notification] {
+        &self.toasts
+    }
+
+    /// Get past notifications, newest first
+    pub fn history(&self) -> impl Iterator<Item = &Notification> {
+        self.history.iter().rev()
+    }
+
+    /// Forget past notifications
+    pub fn clear_history(&mut self) {
+        self.history.clear();
+    }
+}
+
+/// What can be done from the toast of a silent capture
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureAction {
+    /// Open the capture in the editor
+    Open,
+    /// Copy the path of the saved file as text
+    CopyPath,
+    /// Delete the saved file
+    UndoSave,
+}
+
+impl CaptureAction {
+    /// Get the button label
+    pub fn label(&self) -> &'static str {
+        match self {
+            CaptureAction::Open => "Open",
+            CaptureAction::CopyPath => "Copy Path",
+            CaptureAction::UndoSave => "Undo Save",
+        }
+    }
+}
+
+/// Toast of a capture that was copied or saved without opening the editor
+#[derive(Debug, Clone)]
+pub struct CaptureToast {
+    pub title: String,
+    pub message: String,
+    /// File the capture was saved to, if it was saved
+    pub path: Option<PathBuf>,
+    /// Preview of the capture, `None` in low-resource mode
+    pub thumbnail: Option<RgbaImage>,
+    created: Instant,
+}
+
+impl CaptureToast {
+    /// Create a toast shown from now on
+    pub fn new(title: &str, message: String, path: Option<PathBuf>, thumbnail: Option<RgbaImage>) -> Self {
+        Self {
+            title: title.to_string(),
+            message,
+            path,
+            thumbnail,
+            created: Instant::now(),
+        }
+    }
+
+    /// Get the buttons of the toast; only saved captures have a path to copy or a save to undo
+    pub fn actions(&self) -> Vec<CaptureAction> {
+        match self.path {
+            Some(_) => vec![CaptureAction::Open, CaptureAction::CopyPath, CaptureAction::UndoSave],
+            None => vec![CaptureAction::Open],
+        }
+    }
+
+    /// Get how long until the toast disappears on its own
+    pub fn time_left(&self, now: Instant) -> Duration {
+        CAPTURE_TOAST_DURATION.saturating_sub(now.saturating_duration_since(self.created))
+    }
+}
+
+/// A notification shown by Windows, outside the editor window
+///
+/// The toast is described in the Windows toast XML schema and shown through
+/// the WinRT `ToastNotificationManager`. Buttons can only open links, since
+/// reporting other clicks back would need the app to be registered with
+/// Windows as a notification activator.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SystemToast {
+    pub title: String,
+    pub message: String,
+    /// Picture shown beside the text
+    pub image: Option<PathBuf>,
+    /// Buttons opening a link, as label and URI
+    pub links: Vec<(String, String)>,
+}
+
+impl SystemToast {
+    /// Create a toast with text only
+    pub fn new(title: &str, message: &str) -> Self {
+        Self {
+            title: title.to_string(),
+            message: message.to_string(),
+            image: None,
+            links: Vec::new(),
+        }
+    }
+
+    /// Describe the toast in the Windows toast XML schema
+    pub fn xml(&self) -> String {
+        let mut binding = format!("<text>{}</text><text>{}</text>", escape_xml(&self.title), escape_xml(&self.message));
+        if let Some(image) = &self.image {
+            binding.push_str(&format!(
+                "<image placement=\"appLogoOverride\" src=\"{}\"/>",
+                escape_xml(&file_uri(image))
+            ));
+        }
+        let actions: String = self
+            .links
+            .iter()
+            .map(|(label, uri)| {
+                format!(
+                    "<action content=\"{}\" activationType=\"protocol\" arguments=\"{}\"/>",
+                    escape_xml(label),
+                    escape_xml(uri)
+                )
+            })
+            .collect();
+        let actions = if actions.is_empty() { actions } else { format!("<actions>{}</actions>", actions) };
+        format!("<toast><visual><binding template=\"ToastGeneric\">{}</binding></visual>{}</toast>", binding, actions)
+    }
+
+    /// Show the toast, returning whether it was shown
+    ///
+    /// The text only ever reaches Windows as escaped XML loaded into an
+    /// `XmlDocument`, never as code to run.
+    #[cfg(windows)]
+    pub fn show(&self) -> bool {
+        use windows::core::HSTRING;
+        use windows::Data::Xml::Dom::XmlDocument;
+        use windows::UI::Notifications::{ToastNotification, ToastNotificationManager};
+        use windows::Win32::System::WinRT::{RoInitialize, RO_INIT_MULTITHREADED};
+
+        // SAFETY: initializes WinRT for this thread; an already initialized thread keeps its apartment
+        let _ = unsafe { RoInitialize(RO_INIT_MULTITHREADED) };
+        let shown = XmlDocument::new().and_then(|document| {
+            document.LoadXml(&HSTRING::from(self.xml()))?;
+            let toast = ToastNotification::CreateToastNotification(&document)?;
+            ToastNotificationManager::CreateToastNotifierWithId(&HSTRING::from(crate::editor_app::APP_TITLE))?.Show(&toast)
+        });
+        match shown {
+            Ok(()) => true,
+            Err(e) => {
+                log::warn!("Failed to show system notification: {}", e.message());
+                false
+            }
+        }
+    }
+
+    /// Show the toast, returning whether it was shown
+    ///
+    /// System notifications are not supported on this platform, so this
+    /// always returns false.
+    #[cfg(not(windows))]
+    pub fn show(&self) -> bool {
+        false
+    }
+}
+
+/// Show a notification from the operating system, returning whether it was shown
+///
+/// Used while the editor window is hidden, where toasts can't be seen.
+pub fn show_system_notification(title: &str, message: &str) -> bool {
+    SystemToast::new(title, message).show()
+}
+
+/// Escape text for an XML attribute or element
+///
+/// Control characters that XML can't hold, even escaped, are dropped.
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c.is_ascii_control() => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Get the `file:` URI of a path, with forward slashes and unsafe bytes percent-encoded
+pub fn file_uri(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let mut uri = String::from(if path.starts_with('/') { "file://" } else { "file:///" });
+    for byte in path.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~/:".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    uri
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_toasts_expire_except_errors() {
+        let mut notifications = Notifications::new();
+        let info = notifications.push(Severity::Info, "Saved", "shot.png".to_string());
+        notifications.error("Save Failed", &AppError::DiskFull("D:".to_string()));
+        assert_eq!(notifications.toasts().len(), 2);
+        assert_eq!(notifications.toasts()[0].id, info);
+
+        // Still showing the info toast, which will time out
+        assert!(notifications.expire(Instant::now()));
+        assert!(!notifications.expire(Instant::now() + TOAST_DURATION));
+        let remaining: Vec<&str> = notifications.toasts().iter().map(|toast| toast.title.as_str()).collect();
+        assert_eq!(remaining, vec!["Save Failed"]);
+
+        let error = notifications.toasts()[0].id;
+        notifications.dismiss(error);
+        assert!(notifications.toasts().is_empty());
+        // Dismissed and expired toasts stay in the history
+        let history: Vec<Severity> = notifications.history().map(|entry| entry.severity).collect();
+        assert_eq!(history, vec![Severity::Error, Severity::Info]);
+    }
+
+    #[test]
+    fn test_capture_toasts() {
+        let saved = CaptureToast::new("Bug Report", "Saved".to_string(), Some(PathBuf::from("C:/Shots/a.png")), Some(RgbaImage::new(4, 3)));
+        assert_eq!(saved.actions(), vec![CaptureAction::Open, CaptureAction::CopyPath, CaptureAction::UndoSave]);
+        let copied = CaptureToast::new("Clipboard", "Copied".to_string(), None, None);
+        assert_eq!(copied.actions(), vec![CaptureAction::Open]);
+        assert_eq!(CaptureAction::UndoSave.label(), "Undo Save");
+        assert_eq!(copied.time_left(Instant::now() + CAPTURE_TOAST_DURATION), Duration::ZERO);
+
+        let toast = SystemToast {
+            image: Some(PathBuf::from(r"C:\Users\Ann\My Shots\a.png")),
+            links: vec![("Open".to_string(), "file:///C:/a.png".to_string())],
+            ..SystemToast::new("Saved <1>", "Tom & Jerry's")
+        };
+        assert_eq!(
+            toast.xml(),
+            "<toast><visual><binding template=\"ToastGeneric\"><text>Saved &lt;1&gt;</text><text>Tom &amp; Jerry&apos;s</text>\
+             <image placement=\"appLogoOverride\" src=\"file:///C:/Users/Ann/My%20Shots/a.png\"/></binding></visual>\
+             <actions><action content=\"Open\" activationType=\"protocol\" arguments=\"file:///C:/a.png\"/></actions></toast>"
+        );
+        assert!(!SystemToast::new("Saved", "a.png").xml().contains("<actions>"));
+        // Saved paths and titles stay text, whatever they contain
+        assert_eq!(
+            SystemToast::new("a\u{1}b", "</text><action arguments=\"x\"/>\u{2019}").xml(),
+            "<toast><visual><binding template=\"ToastGeneric\"><text>ab</text>\
+             <text>&lt;/text&gt;&lt;action arguments=&quot;x&quot;/&gt;\u{2019}</text></binding></visual></toast>"
+        );
+    }
+
+    #[test]
+    fn test_limits() {
+        let mut notifications = Notifications::new();
+        for index in 0..MAX_HISTORY + 10 {
+            notifications.push(Severity::Warning, "Warning", index.to_string());
+        }
+        assert_eq!(notifications.toasts().len(), MAX_TOASTS);
+        assert_eq!(notifications.toasts()[0].message, (MAX_HISTORY + 10 - MAX_TOASTS).to_string());
+        assert_eq!(notifications.history().count(), MAX_HISTORY);
+        assert_eq!(notifications.history().next().unwrap().message, (MAX_HISTORY + 9).to_string());
+
+        // Recorded notifications skip the toasts
+        notifications.record(Severity::Error, "Open Failed", "missing.png".to_string());
+        assert_eq!(notifications.toasts().len(), MAX_TOASTS);
+        assert_eq!(notifications.history().next().unwrap().title, "Open Failed");
+
+        notifications.clear_history();
+        assert_eq!(notifications.history().count(), 0);
+    }
+}
@@ -0,0 +1,129 @@
+//! Text recognition
+//!
+//! Text in screenshots is recognized with the OCR engine built into Windows
+//! (`Windows.Media.Ocr`), in the languages of the user's profile. Recognition
+//! blocks, so callers run it on a worker thread. Other platforms have no
+//! OCR engine.
+
+use crate::types::{AppError, AppResult};
+use egui::Rect;
+use image::RgbaImage;
+
+/// A recognized word and where it is in the image
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextWord {
+    pub text: String,
+    /// Bounding box in image pixels
+    pub bounds: Rect,
+}
+
+/// A recognized line of text
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TextLine {
+    pub words: Vec<TextWord>,
+}
+
+impl TextLine {
+    /// Get the text of the line, with its words separated by spaces
+    pub fn text(&self) -> String {
+        self.words.iter().map(|word| word.text.as_str()).collect::<Vec<_>>().join(" ")
+    }
+}
+
+/// Check whether this platform has an OCR engine
+pub fn is_supported() -> bool {
+    cfg!(windows)
+}
+
+/// Recognize the text in an image
+///
+/// Images larger than the engine accepts are scaled down for recognition;
+/// the bounding boxes are always in pixels of the given image. The calling
+/// thread joins the multithreaded COM apartment.
+#[cfg(windows)]
+pub fn recognize(image: &RgbaImage) -> AppResult<Vec<TextLine>> {
+    use windows::Graphics::Imaging::{BitmapPixelFormat, SoftwareBitmap};
+    use windows::Media::Ocr::OcrEngine;
+    use windows::Security::Cryptography::CryptographicBuffer;
+    use windows::Win32::System::WinRT::{RoInitialize, RO_INIT_MULTITHREADED};
+
+    if image.width() == 0 || image.height() == 0 {
+        return Ok(Vec::new());
+    }
+    // SAFETY: initializes WinRT for this thread; an already initialized thread keeps its apartment
+    let _ = unsafe { RoInitialize(RO_INIT_MULTITHREADED) };
+
+    let engine = OcrEngine::TryCreateFromUserProfileLanguages()
+        .map_err(|_| AppError::Ocr("OCR に対応した言語がインストールされていません".to_string()))?;
+    let max_side = OcrEngine::MaxImageDimension().map_err(ocr_error)?.max(1);
+    let scale = (max_side as f32 / image.width().max(image.height()) as f32).min(1.0);
+    let scaled;
+    let source = if scale < 1.0 {
+        let width = ((image.width() as f32 * scale) as u32).max(1);
+        let height = ((image.height() as f32 * scale) as u32).max(1);
+        scaled = image::imageops::resize(image, width, height, image::imageops::FilterType::Triangle);
+        &scaled
+    } else {
+        image
+    };
+
+    let mut bgra = source.as_raw().clone();
+    for pixel in bgra.chunks_exact_mut(4) {
+        pixel.swap(0, 2);
+    }
+    let buffer = CryptographicBuffer::CreateFromByteArray(&bgra).map_err(ocr_error)?;
+    let bitmap = SoftwareBitmap::CreateCopyFromBuffer(
+        &buffer,
+        BitmapPixelFormat::Bgra8,
+        source.width() as i32,
+        source.height() as i32,
+    )
+    .map_err(ocr_error)?;
+    let result = engine.RecognizeAsync(&bitmap).and_then(|operation| operation.get()).map_err(ocr_error)?;
+
+    let mut lines = Vec::new();
+    for line in result.Lines().map_err(ocr_error)? {
+        let mut words = Vec::new();
+        for word in line.Words().map_err(ocr_error)? {
+            let rect = word.BoundingRect().map_err(ocr_error)?;
+            words.push(TextWord {
+                text: word.Text().map_err(ocr_error)?.to_string_lossy(),
+                bounds: Rect::from_min_size(
+                    egui::pos2(rect.X / scale, rect.Y / scale),
+                    egui::vec2(rect.Width / scale, rect.Height / scale),
+                ),
+            });
+        }
+        lines.push(TextLine { words });
+    }
+    Ok(lines)
+}
+
+/// Recognize the text in an image
+///
+/// OCR is only supported on Windows.
+#[cfg(not(windows))]
+pub fn recognize(_image: &RgbaImage) -> AppResult<Vec<TextLine>> {
+    Err(AppError::Ocr("この環境では OCR を利用できません".to_string()))
+}
+
+#[cfg(windows)]
+fn ocr_error(error: windows::core::Error) -> AppError {
+    AppError::Ocr(format!("文字を認識できませんでした: {}", error.message()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_text() {
+        let word = |text: &str, x: f32| TextWord {
+            text: text.to_string(),
+            bounds: Rect::from_min_size(egui::pos2(x, 0.0), egui::vec2(10.0, 10.0)),
+        };
+        let line = TextLine { words: vec![word("API", 0.0), word("key:", 12.0)] };
+        assert_eq!(line.text(), "API key:");
+        assert_eq!(TextLine::default().text(), "");
+    }
+}
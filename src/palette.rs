@@ -0,0 +1,135 @@
+//! Picked colors
+//!
+//! The color picker hotkey freezes the screen under the cursor and copies
+//! the HEX value of the clicked pixel. Picked colors are kept, newest first,
+//! in a short history shown in the editor, which can be exported as a
+//! palette file for design tools.
+
+use crate::types::{AppError, AppResult};
+use egui::Color32;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Most colors kept; older ones are dropped
+pub const MAX_COLORS: usize = 16;
+
+/// Recently picked colors, newest first
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PaletteHistory {
+    pub colors: Vec<Color32>,
+}
+
+impl PaletteHistory {
+    /// Add a picked color, moving it to the front if it was picked before
+    pub fn push(&mut self, color: Color32) {
+        self.colors.retain(|picked| *picked != color);
+        self.colors.insert(0, color);
+        self.colors.truncate(MAX_COLORS);
+    }
+
+    /// Forget all picked colors
+    pub fn clear(&mut self) {
+        self.colors.clear();
+    }
+}
+
+/// Get the HEX value of a color as copied by the picker, e.g. "#1E90FF"
+///
+/// Alpha is left out; colors picked from the screen are opaque.
+pub fn hex(color: Color32) -> String {
+    format!("#{:02X}{:02X}{:02X}", color.r(), color.g(), color.b())
+}
+
+/// File format of an exported palette
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaletteFormat {
+    /// GIMP palette, also read by Inkscape and Krita
+    Gpl,
+    /// One HEX value per line
+    Hex,
+}
+
+impl PaletteFormat {
+    /// Get all formats in menu order
+    pub fn all() -> [PaletteFormat; 2] {
+        [PaletteFormat::Gpl, PaletteFormat::Hex]
+    }
+
+    /// Get the file extension of the format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            PaletteFormat::Gpl => "gpl",
+            PaletteFormat::Hex => "txt",
+        }
+    }
+
+    /// Write colors in the format
+    pub fn render(&self, name: &str, colors: &[Color32]) -> String {
+        match self {
+            PaletteFormat::Gpl => {
+                let mut output = format!("GIMP Palette\nName: {}\nColumns: {}\n#\n", name, colors.len().clamp(1, 8));
+                for color in colors {
+                    output.push_str(&format!(
+                        "{:3} {:3} {:3}\t{}\n",
+                        color.r(),
+                        color.g(),
+                        color.b(),
+                        hex(*color)
+                    ));
+                }
+                output
+            }
+            PaletteFormat::Hex => colors.iter().map(|color| format!("{}\n", hex(*color))).collect(),
+        }
+    }
+}
+
+impl std::fmt::Display for PaletteFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PaletteFormat::Gpl => write!(f, "GIMP Palette"),
+            PaletteFormat::Hex => write!(f, "HEX List"),
+        }
+    }
+}
+
+/// Export colors to a palette file
+pub fn export(path: &Path, format: PaletteFormat, colors: &[Color32]) -> AppResult<()> {
+    if colors.is_empty() {
+        return Err(AppError::Settings("エクスポートする色がありません".to_string()));
+    }
+    let name = path.file_stem().map(|stem| stem.to_string_lossy()).unwrap_or_default();
+    crate::files::write_atomic(path, format.render(&name, colors).as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_keeps_recent_unique_colors() {
+        let mut history = PaletteHistory::default();
+        history.push(Color32::RED);
+        history.push(Color32::BLUE);
+        history.push(Color32::RED);
+        assert_eq!(history.colors, vec![Color32::RED, Color32::BLUE]);
+
+        for gray in 0..MAX_COLORS as u8 + 3 {
+            history.push(Color32::from_gray(gray));
+        }
+        assert_eq!(history.colors.len(), MAX_COLORS);
+        assert_eq!(history.colors[0], Color32::from_gray(MAX_COLORS as u8 + 2));
+    }
+
+    #[test]
+    fn test_render_formats() {
+        let colors = [Color32::from_rgb(30, 144, 255), Color32::BLACK];
+        assert_eq!(hex(colors[0]), "#1E90FF");
+        assert_eq!(PaletteFormat::Hex.render("picked", &colors), "#1E90FF\n#000000\n");
+        assert_eq!(
+            PaletteFormat::Gpl.render("picked", &colors),
+            "GIMP Palette\nName: picked\nColumns: 2\n#\n 30 144 255\t#1E90FF\n  0   0   0\t#000000\n"
+        );
+    }
+}
@@ -0,0 +1,410 @@
+//! Post-capture action plugins
+//!
+//! A post-capture action takes the flattened image and its metadata and
+//! returns a message or a link, e.g. after posting the screenshot to a chat
+//! channel. Actions are declared in `plugins.json` in the config directory
+//! and run external programs, so users can add steps like "send to Slack"
+//! without changing the application:
+//!
+//! ```json
+//! { "actions": [
+//!     { "name": "Send to Slack", "program": "python", "args": ["slack.py"], "after_capture": true }
+//! ] }
+//! ```
+//!
+//! The program gets the image as PNG on its standard input and the metadata
+//! in `LSA_*` environment variables, and may print a JSON object such as
+//! `{"url": "https://...", "message": "Posted"}`. Actions marked
+//! `after_capture` run in order after every capture, each seeing the link of
+//! the one before; the others are run from the File menu.
+
+use crate::export;
+use crate::metadata::ImageMetadata;
+use crate::settings::config_dir;
+use crate::types::{AppError, AppResult, ImageFormat};
+use crossbeam_channel::Receiver;
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+/// Plugins file in the config directory
+const PLUGINS_FILE_NAME: &str = "plugins.json";
+
+/// Longest standard error output quoted in an error message
+const MAX_ERROR_CHARS: usize = 500;
+
+/// Get the default plugins file
+pub fn default_plugins_path() -> PathBuf {
+    config_dir().join(PLUGINS_FILE_NAME)
+}
+
+/// What an action is given
+#[derive(Debug, Clone, Copy)]
+pub struct ActionInput<'a> {
+    /// The image with its annotations
+    pub image: &'a RgbaImage,
+    pub metadata: &'a ImageMetadata,
+    /// File the image was saved to, if it was saved
+    pub path: Option<&'a Path>,
+    /// Link returned by the previous action of a chain, if any
+    pub previous_url: Option<&'a str>,
+}
+
+/// What an action reports back
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ActionOutput {
+    /// Link to the result, e.g. the posted message
+    pub url: Option<String>,
+    /// Text shown to the user
+    pub message: Option<String>,
+}
+
+/// A step run on a finished capture
+pub trait PostCaptureAction: Send + Sync {
+    /// Name shown in menus and notifications
+    fn name(&self) -> &str;
+
+    /// Whether the action runs after every capture
+    fn runs_after_capture(&self) -> bool {
+        false
+    }
+
+    /// Run the action, blocking until it finishes
+    fn run(&self, input: ActionInput) -> AppResult<ActionOutput>;
+}
+
+/// An action declared in the plugins file
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExternalAction {
+    pub name: String,
+    pub program: String,
+    pub args: Vec<String>,
+    /// Run after every capture instead of only from the menu
+    pub after_capture: bool,
+}
+
+impl ExternalAction {
+    /// Environment variables describing the input
+    pub fn environment(input: &ActionInput) -> Vec<(&'static str, String)> {
+        let metadata = input.metadata;
+        let captured_at = metadata
+            .captured_at
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+        [
+            ("LSA_CAPTURED_AT", Some(captured_at.to_string())),
+            ("LSA_WIDTH", Some(input.image.width().to_string())),
+            ("LSA_HEIGHT", Some(input.image.height().to_string())),
+            ("LSA_MONITOR", metadata.monitor.clone()),
+            ("LSA_WINDOW_TITLE", metadata.window_title.clone()),
+            ("LSA_APPLICATION", metadata.application.clone()),
+            ("LSA_COMMENT", metadata.comment.clone()),
+            ("LSA_FILE", input.path.map(|path| path.display().to_string())),
+            ("LSA_PREVIOUS_URL", input.previous_url.map(str::to_string)),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some((name, value?)))
+        .collect()
+    }
+}
+
+impl PostCaptureAction for ExternalAction {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn runs_after_capture(&self) -> bool {
+        self.after_capture
+    }
+
+    fn run(&self, input: ActionInput) -> AppResult<ActionOutput> {
+        let png = export::encode_image(input.image, &ImageFormat::Png)?;
+        let mut child = Command::new(&self.program)
+            .args(&self.args)
+            .envs(Self::environment(&input))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| AppError::Plugin(format!("{} を起動できません: {}", self.program, e)))?;
+        // Written from another thread so a program printing before it has read everything can't deadlock
+        let stdin = child.stdin.take();
+        let writer = std::thread::spawn(move || {
+            if let Some(mut stdin) = stdin {
+                // A program that doesn't read the image closes the pipe early, which is fine
+                let _ = stdin.write_all(&png);
+            }
+        });
+        let output = child.wait_with_output()?;
+        let _ = writer.join();
+        if !output.status.success() {
+            let stderr: String = String::from_utf8_lossy(&output.stderr).trim().chars().take(MAX_ERROR_CHARS).collect();
+            return Err(AppError::Plugin(format!("{} が失敗しました ({}): {}", self.name, output.status, stderr)));
+        }
+        parse_output(&String::from_utf8_lossy(&output.stdout))
+    }
+}
+
+/// Parse what an external action printed
+///
+/// Programs that print nothing report nothing; anything else must be a JSON object.
+pub fn parse_output(stdout: &str) -> AppResult<ActionOutput> {
+    if stdout.trim().is_empty() {
+        return Ok(ActionOutput::default());
+    }
+    serde_json::from_str(stdout.trim())
+        .map_err(|e| AppError::Plugin(format!("プラグインの出力を解析できません: {}", e)))
+}
+
+/// Contents of the plugins file
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PluginsFile {
+    pub actions: Vec<ExternalAction>,
+}
+
+/// The available post-capture actions
+#[derive(Clone, Default)]
+pub struct ActionRegistry {
+    actions: Vec<Arc<dyn PostCaptureAction>>,
+}
+
+impl std::fmt::Debug for ActionRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.actions.iter().map(|action| action.name())).finish()
+    }
+}
+
+impl ActionRegistry {
+    /// Create a registry without actions
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load the actions declared in a plugins file, starting empty if the file doesn't exist
+    pub fn load(path: &Path) -> AppResult<Self> {
+        let json = match std::fs::read_to_string(path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let file: PluginsFile = serde_json::from_str(&json)
+            .map_err(|e| AppError::Plugin(format!("{} の解析に失敗しました: {}", path.display(), e)))?;
+        let mut registry = Self::new();
+        for action in file.actions {
+            if action.program.trim().is_empty() {
+                return Err(AppError::Plugin(format!("アクション「{}」のプログラムが設定されていません", action.name)));
+            }
+            registry.register(Arc::new(action))?;
+        }
+        Ok(registry)
+    }
+
+    /// Add an action; names must be unique
+    pub fn register(&mut self, action: Arc<dyn PostCaptureAction>) -> AppResult<()> {
+        if action.name().trim().is_empty() {
+            return Err(AppError::Plugin("アクションの名前が空です".to_string()));
+        }
+        if self.get(action.name()).is_some() {
+            return Err(AppError::Plugin(format!("アクション「{}」が重複しています", action.name())));
+        }
+        self.actions.push(action);
+        Ok(())
+    }
+
+    /// Get all actions, in the order they were declared
+    pub fn actions(&self) -> &[Arc<dyn PostCaptureAction>] {
+        &self.actions
+    }
+
+    /// Get an action by name
+    pub fn get(&self, name: &str) -> Option<Arc<dyn PostCaptureAction>> {
+        self.actions.iter().find(|action| action.name() == name).cloned()
+    }
+
+    /// Get the actions run after every capture, in order
+    pub fn after_capture(&self) -> Vec<Arc<dyn PostCaptureAction>> {
+        self.actions.iter().filter(|action| action.runs_after_capture()).cloned().collect()
+    }
+}
+
+/// Result of one action of a chain
+#[derive(Debug)]
+pub struct ActionResult {
+    pub action: String,
+    pub result: AppResult<ActionOutput>,
+}
+
+/// Run actions one after another, stopping at the first failure
+///
+/// Each action gets the link returned by the one before.
+pub fn run_chain(
+    actions: &[Arc<dyn PostCaptureAction>],
+    image: &RgbaImage,
+    metadata: &ImageMetadata,
+    path: Option<&Path>,
+) -> Vec<ActionResult> {
+    let mut results: Vec<ActionResult> = Vec::new();
+    for action in actions {
+        let previous_url = results.last().and_then(|last| last.result.as_ref().ok()?.url.as_deref());
+        let input = ActionInput { image, metadata, path, previous_url };
+        let result = action.run(input);
+        let failed = result.is_err();
+        results.push(ActionResult { action: action.name().to_string(), result });
+        if failed {
+            break;
+        }
+    }
+    results
+}
+
+/// Run actions on a worker thread, receiving their results when all finish
+pub fn spawn_chain(
+    actions: Vec<Arc<dyn PostCaptureAction>>,
+    image: Arc<RgbaImage>,
+    metadata: ImageMetadata,
+    path: Option<PathBuf>,
+) -> Receiver<Vec<ActionResult>> {
+    let (sender, receiver) = crossbeam_channel::bounded(1);
+    std::thread::spawn(move || {
+        let _ = sender.send(run_chain(&actions, &image, &metadata, path.as_deref()));
+    });
+    receiver
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use std::time::{Duration, SystemTime};
+    use uuid::Uuid;
+
+    /// Action recording the links it was given
+    struct Recorder {
+        name: &'static str,
+        url: Option<&'static str>,
+        fail: bool,
+        seen: Mutex<Vec<Option<String>>>,
+    }
+
+    impl Recorder {
+        fn new(name: &'static str, url: Option<&'static str>, fail: bool) -> Arc<Self> {
+            Arc::new(Self { name, url, fail, seen: Mutex::new(Vec::new()) })
+        }
+    }
+
+    impl PostCaptureAction for Recorder {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn run(&self, input: ActionInput) -> AppResult<ActionOutput> {
+            self.seen.lock().unwrap().push(input.previous_url.map(str::to_string));
+            if self.fail {
+                return Err(AppError::Plugin("offline".to_string()));
+            }
+            Ok(ActionOutput { url: self.url.map(str::to_string), message: None })
+        }
+    }
+
+    #[test]
+    fn test_run_chain() {
+        let upload = Recorder::new("Upload", Some("https://example.com/a.png"), false);
+        let post = Recorder::new("Post", None, true);
+        let never = Recorder::new("Never", None, false);
+        let mut registry = ActionRegistry::new();
+        registry.register(upload.clone()).unwrap();
+        registry.register(post.clone()).unwrap();
+        registry.register(never.clone()).unwrap();
+        assert!(registry.register(Recorder::new("Upload", None, false)).is_err());
+        assert!(registry.register(Recorder::new(" ", None, false)).is_err());
+
+        let image = RgbaImage::new(4, 3);
+        let metadata = ImageMetadata::new(SystemTime::now());
+        let receiver = spawn_chain(registry.actions().to_vec(), Arc::new(image), metadata, None);
+        let results = receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+
+        // Each action sees the link of the one before, and the chain stops at the failure
+        let names: Vec<&str> = results.iter().map(|result| result.action.as_str()).collect();
+        assert_eq!(names, vec!["Upload", "Post"]);
+        assert!(results[1].result.is_err());
+        assert_eq!(*post.seen.lock().unwrap(), vec![Some("https://example.com/a.png".to_string())]);
+        assert!(never.seen.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_load_plugins_file() {
+        let dir = std::env::temp_dir().join(format!("lsa-plugins-{}", Uuid::new_v4()));
+        let path = dir.join(PLUGINS_FILE_NAME);
+        assert!(ActionRegistry::load(&path).unwrap().actions().is_empty());
+
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            &path,
+            r#"{ "actions": [
+                { "name": "Send to Slack", "program": "python", "args": ["slack.py"], "after_capture": true },
+                { "name": "Archive", "program": "archive.exe" }
+            ] }"#,
+        )
+        .unwrap();
+        let registry = ActionRegistry::load(&path).unwrap();
+        assert_eq!(format!("{:?}", registry), r#"["Send to Slack", "Archive"]"#);
+        let after: Vec<String> = registry.after_capture().iter().map(|action| action.name().to_string()).collect();
+        assert_eq!(after, vec!["Send to Slack"]);
+
+        std::fs::write(&path, r#"{ "actions": [{ "name": "A", "program": "a" }, { "name": "A", "program": "b" }] }"#).unwrap();
+        assert!(ActionRegistry::load(&path).is_err());
+        std::fs::write(&path, r#"{ "actions": [{ "name": "A" }] }"#).unwrap();
+        assert!(ActionRegistry::load(&path).is_err());
+        std::fs::write(&path, "not json").unwrap();
+        assert!(ActionRegistry::load(&path).is_err());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_external_action_protocol() {
+        assert_eq!(parse_output("\n").unwrap(), ActionOutput::default());
+        assert_eq!(
+            parse_output("{\"url\": \"https://example.com/x\", \"extra\": 1}\n").unwrap(),
+            ActionOutput { url: Some("https://example.com/x".to_string()), message: None }
+        );
+        assert!(parse_output("uploaded!").is_err());
+
+        let image = RgbaImage::new(8, 6);
+        let metadata = ImageMetadata {
+            window_title: Some("Notepad".to_string()),
+            ..ImageMetadata::new(UNIX_EPOCH + Duration::from_secs(1_700_000_000))
+        };
+        let input = ActionInput {
+            image: &image,
+            metadata: &metadata,
+            path: None,
+            previous_url: Some("https://example.com/a"),
+        };
+        let environment = ExternalAction::environment(&input);
+        assert_eq!(
+            environment,
+            vec![
+                ("LSA_CAPTURED_AT", "1700000000".to_string()),
+                ("LSA_WIDTH", "8".to_string()),
+                ("LSA_HEIGHT", "6".to_string()),
+                ("LSA_WINDOW_TITLE", "Notepad".to_string()),
+                ("LSA_PREVIOUS_URL", "https://example.com/a".to_string()),
+            ]
+        );
+
+        let missing = ExternalAction {
+            name: "Missing".to_string(),
+            program: format!("lsa-missing-{}", Uuid::new_v4()),
+            ..ExternalAction::default()
+        };
+        assert!(matches!(missing.run(input), Err(AppError::Plugin(_))));
+    }
+}
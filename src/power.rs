@@ -0,0 +1,206 @@
+//! Power source detection and low-resource mode
+//!
+//! Low-resource mode turns off optional background work (thumbnails, OCR),
+//! UI animations, and continuous repainting. It can be forced on or off, or
+//! left on `Auto` to follow whether the machine is running on battery.
+
+use serde::{Deserialize, Serialize};
+
+/// Current power source of the machine
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerSource {
+    Ac,
+    Battery,
+    Unknown,
+}
+
+/// Detect the current power source
+#[cfg(windows)]
+pub fn detect_power_source() -> PowerSource {
+    use winapi::um::winbase::{GetSystemPowerStatus, SYSTEM_POWER_STATUS};
+
+    let mut status: SYSTEM_POWER_STATUS = unsafe { std::mem::zeroed() };
+    // SAFETY: `status` is a valid, writable SYSTEM_POWER_STATUS
+    if unsafe { GetSystemPowerStatus(&mut status) } == 0 {
+        return PowerSource::Unknown;
+    }
+    match status.ACLineStatus {
+        0 => PowerSource::Battery,
+        1 => PowerSource::Ac,
+        _ => PowerSource::Unknown,
+    }
+}
+
+/// Detect the current power source
+#[cfg(target_os = "linux")]
+pub fn detect_power_source() -> PowerSource {
+    detect_power_source_in(std::path::Path::new("/sys/class/power_supply"))
+}
+
+/// Detect the current power source
+#[cfg(not(any(windows, target_os = "linux")))]
+pub fn detect_power_source() -> PowerSource {
+    PowerSource::Unknown
+}
+
+/// Read power supply state from a sysfs-style directory
+#[cfg(target_os = "linux")]
+fn detect_power_source_in(dir: &std::path::Path) -> PowerSource {
+    let read = |path: std::path::PathBuf| {
+        std::fs::read_to_string(path)
+            .map(|value| value.trim().to_string())
+            .unwrap_or_default()
+    };
+
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return PowerSource::Unknown;
+    };
+
+    let mut has_battery = false;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        match read(path.join("type")).as_str() {
+            "Mains" | "USB" if read(path.join("online")) == "1" => return PowerSource::Ac,
+            "Battery" => has_battery = true,
+            _ => {}
+        }
+    }
+
+    if has_battery {
+        PowerSource::Battery
+    } else {
+        PowerSource::Unknown
+    }
+}
+
+/// Low-resource mode setting
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum LowResourceMode {
+    Off,
+    On,
+    /// Enabled automatically while running on battery
+    #[default]
+    Auto,
+}
+
+impl LowResourceMode {
+    /// Get all modes in display order
+    pub fn all() -> Vec<LowResourceMode> {
+        vec![LowResourceMode::Off, LowResourceMode::On, LowResourceMode::Auto]
+    }
+
+    /// Check whether low-resource mode is in effect for the given power source
+    pub fn is_active(&self, power: PowerSource) -> bool {
+        match self {
+            LowResourceMode::Off => false,
+            LowResourceMode::On => true,
+            LowResourceMode::Auto => power == PowerSource::Battery,
+        }
+    }
+}
+
+impl std::fmt::Display for LowResourceMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LowResourceMode::Off => write!(f, "Off"),
+            LowResourceMode::On => write!(f, "On"),
+            LowResourceMode::Auto => write!(f, "Auto (on battery)"),
+        }
+    }
+}
+
+/// Optional features that low-resource mode turns off
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResourceProfile {
+    pub thumbnails: bool,
+    pub background_ocr: bool,
+    pub animations: bool,
+    pub continuous_repaint: bool,
+}
+
+impl ResourceProfile {
+    /// Profile with every optional feature enabled
+    pub const FULL: Self = Self {
+        thumbnails: true,
+        background_ocr: true,
+        animations: true,
+        continuous_repaint: true,
+    };
+
+    /// Profile with every optional feature disabled
+    pub const LOW: Self = Self {
+        thumbnails: false,
+        background_ocr: false,
+        animations: false,
+        continuous_repaint: false,
+    };
+
+    /// Get the profile for a mode and power source
+    pub fn for_mode(mode: LowResourceMode, power: PowerSource) -> Self {
+        if mode.is_active(power) {
+            Self::LOW
+        } else {
+            Self::FULL
+        }
+    }
+
+    /// Check whether this is the low-resource profile
+    pub fn is_low(&self) -> bool {
+        *self == Self::LOW
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mode_activation() {
+        assert!(!LowResourceMode::Off.is_active(PowerSource::Battery));
+        assert!(LowResourceMode::On.is_active(PowerSource::Ac));
+        assert!(LowResourceMode::Auto.is_active(PowerSource::Battery));
+        assert!(!LowResourceMode::Auto.is_active(PowerSource::Ac));
+        assert!(!LowResourceMode::Auto.is_active(PowerSource::Unknown));
+    }
+
+    #[test]
+    fn test_profile_for_mode() {
+        assert_eq!(ResourceProfile::for_mode(LowResourceMode::Off, PowerSource::Battery), ResourceProfile::FULL);
+        let low = ResourceProfile::for_mode(LowResourceMode::Auto, PowerSource::Battery);
+        assert!(low.is_low());
+        assert!(!low.thumbnails && !low.background_ocr && !low.animations && !low.continuous_repaint);
+    }
+
+    #[test]
+    fn test_mode_serialization() {
+        let json = serde_json::to_string(&LowResourceMode::On).unwrap();
+        let restored: LowResourceMode = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, LowResourceMode::On);
+        assert_eq!(LowResourceMode::default(), LowResourceMode::Auto);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_detect_power_source_from_sysfs() {
+        let dir = std::env::temp_dir().join(format!("lsa-power-test-{}", uuid::Uuid::new_v4()));
+        let write = |name: &str, kind: &str, online: Option<&str>| {
+            let supply = dir.join(name);
+            std::fs::create_dir_all(&supply).unwrap();
+            std::fs::write(supply.join("type"), kind).unwrap();
+            if let Some(online) = online {
+                std::fs::write(supply.join("online"), online).unwrap();
+            }
+        };
+
+        assert_eq!(detect_power_source_in(&dir), PowerSource::Unknown);
+
+        write("BAT0", "Battery\n", None);
+        write("AC", "Mains\n", Some("0\n"));
+        assert_eq!(detect_power_source_in(&dir), PowerSource::Battery);
+
+        write("AC", "Mains\n", Some("1\n"));
+        assert_eq!(detect_power_source_in(&dir), PowerSource::Ac);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,435 @@
+//! Capture presets
+//!
+//! A preset names a combination of what to capture (a monitor, a fixed
+//! region of the desktop, an area of a monitor, or the focused window),
+//! what to do with the result, and how the saved file is named and
+//! encoded, so a recurring capture takes a single hotkey or menu click.
+//! Presets are stored in `AppSettings::capture_presets`, each with an
+//! optional hotkey of its own.
+//!
+//! Unlike a fixed region, an area of a monitor ("left half of monitor 2",
+//! "1920×1080 centered on the primary monitor") is placed on the monitor's
+//! current bounds each time it is captured, so it stays put when monitors
+//! are rearranged or change resolution.
+
+use crate::naming::FileNameTemplate;
+use crate::routing::PostSaveCommand;
+use crate::types::{AppError, AppResult, Hotkey, ImageFormat, ScreenInfo};
+use egui::{Rect, Vec2};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// What a preset captures
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum PresetTarget {
+    /// A whole monitor, by screen index
+    Monitor(usize),
+    /// A fixed rectangle in virtual desktop coordinates
+    Region { x: i32, y: i32, width: u32, height: u32 },
+    /// An area placed on a monitor's bounds when capturing
+    Area { monitor: MonitorAnchor, placement: AreaPlacement },
+    /// The focused window
+    ActiveWindow,
+}
+
+/// Which monitor an area is placed on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum MonitorAnchor {
+    /// Whichever monitor is primary when capturing
+    #[default]
+    Primary,
+    /// A monitor by screen index
+    Monitor(usize),
+}
+
+impl MonitorAnchor {
+    /// Find the monitor among the current screens
+    pub fn resolve<'a>(&self, screens: &'a [ScreenInfo]) -> AppResult<&'a ScreenInfo> {
+        match *self {
+            MonitorAnchor::Primary => screens
+                .iter()
+                .find(|screen| screen.is_primary)
+                .or_else(|| screens.first())
+                .ok_or(AppError::ScreenNotFound { index: 0 }),
+            MonitorAnchor::Monitor(index) => screens
+                .iter()
+                .find(|screen| screen.index == index)
+                .ok_or(AppError::ScreenNotFound { index }),
+        }
+    }
+}
+
+impl std::fmt::Display for MonitorAnchor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MonitorAnchor::Primary => write!(f, "Primary Monitor"),
+            MonitorAnchor::Monitor(index) => write!(f, "Monitor {}", index + 1),
+        }
+    }
+}
+
+/// Where an area lies on its monitor
+///
+/// Sizes and offsets are in the monitor's logical pixels, like desktop
+/// coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum AreaPlacement {
+    /// A part of the monitor in fractions of its size, e.g. the left half is `(0, 0, 0.5, 1)`
+    Fraction { x: f32, y: f32, width: f32, height: f32 },
+    /// A fixed size centered on the monitor
+    Centered { width: u32, height: u32 },
+    /// A fixed rectangle relative to the monitor's top-left corner
+    Offset { x: u32, y: u32, width: u32, height: u32 },
+}
+
+impl AreaPlacement {
+    /// The left half of a monitor
+    pub const LEFT_HALF: AreaPlacement = AreaPlacement::Fraction { x: 0.0, y: 0.0, width: 0.5, height: 1.0 };
+    /// The right half of a monitor
+    pub const RIGHT_HALF: AreaPlacement = AreaPlacement::Fraction { x: 0.5, y: 0.0, width: 0.5, height: 1.0 };
+    /// The top half of a monitor
+    pub const TOP_HALF: AreaPlacement = AreaPlacement::Fraction { x: 0.0, y: 0.0, width: 1.0, height: 0.5 };
+    /// The bottom half of a monitor
+    pub const BOTTOM_HALF: AreaPlacement = AreaPlacement::Fraction { x: 0.0, y: 0.5, width: 1.0, height: 0.5 };
+
+    /// Get the common placements with their names, for quick selection
+    pub fn halves() -> [(&'static str, AreaPlacement); 4] {
+        [
+            ("Left Half", Self::LEFT_HALF),
+            ("Right Half", Self::RIGHT_HALF),
+            ("Top Half", Self::TOP_HALF),
+            ("Bottom Half", Self::BOTTOM_HALF),
+        ]
+    }
+
+    /// Place the area on monitor `bounds`, cut off at the monitor's edges
+    ///
+    /// Returns `None` if nothing of the area is on the monitor.
+    pub fn place(&self, bounds: Rect) -> Option<Rect> {
+        let area = self.area(bounds).intersect(bounds);
+        (area.width() >= 1.0 && area.height() >= 1.0).then_some(area)
+    }
+
+    /// Get the area on monitor `bounds` in whole pixels, which may reach past the monitor
+    fn area(&self, bounds: Rect) -> Rect {
+        let area = match *self {
+            AreaPlacement::Fraction { x, y, width, height } => Rect::from_min_size(
+                bounds.min + bounds.size() * Vec2::new(x, y),
+                bounds.size() * Vec2::new(width, height),
+            ),
+            AreaPlacement::Centered { width, height } => {
+                Rect::from_center_size(bounds.center(), Vec2::new(width as f32, height as f32))
+            }
+            AreaPlacement::Offset { x, y, width, height } => Rect::from_min_size(
+                bounds.min + Vec2::new(x as f32, y as f32),
+                Vec2::new(width as f32, height as f32),
+            ),
+        };
+        // Whole pixels, so fractions of odd sizes don't blur the capture
+        Rect::from_min_max(area.min.round(), area.max.round())
+    }
+
+    /// Check that the area has a size and, for fractions, lies within the monitor
+    pub fn validate(&self) -> Result<(), String> {
+        match *self {
+            AreaPlacement::Fraction { x, y, width, height } => {
+                let fits = |start: f32, length: f32| {
+                    (0.0..1.0).contains(&start) && length > 0.0 && start + length <= 1.0 + f32::EPSILON
+                };
+                if fits(x, width) && fits(y, height) {
+                    Ok(())
+                } else {
+                    Err("範囲がモニターの外にあるか空です".to_string())
+                }
+            }
+            AreaPlacement::Centered { width, height } | AreaPlacement::Offset { width, height, .. } => {
+                if width == 0 || height == 0 {
+                    Err("範囲が空です".to_string())
+                } else {
+                    Ok(())
+                }
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for AreaPlacement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some((name, _)) = Self::halves().into_iter().find(|(_, placement)| placement == self) {
+            return write!(f, "{}", name);
+        }
+        let percent = |fraction: f32| (fraction * 1000.0).round() / 10.0;
+        match *self {
+            AreaPlacement::Fraction { x, y, width, height } => write!(
+                f,
+                "{}%×{}% at ({}%, {}%)",
+                percent(width),
+                percent(height),
+                percent(x),
+                percent(y)
+            ),
+            AreaPlacement::Centered { width, height } => write!(f, "{}×{} Centered", width, height),
+            AreaPlacement::Offset { x, y, width, height } => write!(f, "{}×{} at ({}, {})", width, height, x, y),
+        }
+    }
+}
+
+/// Get the desktop rectangle of an area on the current screens
+pub fn resolve_area(monitor: MonitorAnchor, placement: AreaPlacement, screens: &[ScreenInfo]) -> AppResult<Rect> {
+    let screen = monitor.resolve(screens)?;
+    placement.place(screen.bounds).ok_or_else(|| AppError::RegionOutOfBounds {
+        requested: placement.area(screen.bounds),
+        available: screen.bounds,
+    })
+}
+
+impl Default for PresetTarget {
+    fn default() -> Self {
+        PresetTarget::Monitor(0)
+    }
+}
+
+impl std::fmt::Display for PresetTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PresetTarget::Monitor(index) => write!(f, "Monitor {}", index + 1),
+            PresetTarget::Region { x, y, width, height } => {
+                write!(f, "Region {}x{} at ({}, {})", width, height, x, y)
+            }
+            PresetTarget::Area { monitor, placement } => write!(f, "{} of {}", placement, monitor),
+            PresetTarget::ActiveWindow => write!(f, "Active Window"),
+        }
+    }
+}
+
+/// What happens to a preset's capture
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum PresetOutput {
+    /// Open the capture in the editor
+    #[default]
+    Editor,
+    /// Copy the capture to the clipboard without showing the editor
+    Clipboard,
+    /// Save the capture to the save folder without showing the editor
+    AutoSave,
+    /// Save the capture, then hand the file to the preset's upload command
+    Upload,
+}
+
+impl PresetOutput {
+    /// Get all output actions
+    pub fn all() -> [PresetOutput; 4] {
+        [PresetOutput::Editor, PresetOutput::Clipboard, PresetOutput::AutoSave, PresetOutput::Upload]
+    }
+
+    /// Check whether the capture is written to a file
+    pub fn saves_file(&self) -> bool {
+        matches!(self, PresetOutput::AutoSave | PresetOutput::Upload)
+    }
+}
+
+impl std::fmt::Display for PresetOutput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PresetOutput::Editor => write!(f, "Open in Editor"),
+            PresetOutput::Clipboard => write!(f, "Copy to Clipboard"),
+            PresetOutput::AutoSave => write!(f, "Save to File"),
+            PresetOutput::Upload => write!(f, "Save and Upload"),
+        }
+    }
+}
+
+/// A named capture target and output action
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CapturePreset {
+    pub name: String,
+    pub target: PresetTarget,
+    pub output: PresetOutput,
+    /// Format of saved files
+    pub format: ImageFormat,
+    /// Names of saved files
+    pub file_naming: FileNameTemplate,
+    /// Hotkey running the preset, if any
+    pub hotkey: Option<Hotkey>,
+    /// Command run with the saved file by [`PresetOutput::Upload`]
+    pub upload_command: PostSaveCommand,
+}
+
+impl Default for CapturePreset {
+    fn default() -> Self {
+        Self {
+            name: "New Preset".to_string(),
+            target: PresetTarget::default(),
+            output: PresetOutput::default(),
+            format: ImageFormat::Png,
+            file_naming: FileNameTemplate::default(),
+            hotkey: None,
+            upload_command: PostSaveCommand::default(),
+        }
+    }
+}
+
+impl CapturePreset {
+    /// Validate the preset on its own
+    pub fn validate(&self) -> AppResult<()> {
+        if self.name.trim().is_empty() {
+            return Err(AppError::Settings("キャプチャプリセットの名前が空です".to_string()));
+        }
+        if let PresetTarget::Region { width, height, .. } = self.target {
+            if width == 0 || height == 0 {
+                return Err(AppError::Settings(format!(
+                    "キャプチャプリセット「{}」の範囲が空です",
+                    self.name
+                )));
+            }
+        }
+        if let PresetTarget::Area { placement, .. } = self.target {
+            placement
+                .validate()
+                .map_err(|reason| AppError::Settings(format!("キャプチャプリセット「{}」の{}", self.name, reason)))?;
+        }
+        if self.hotkey.is_some_and(|hotkey| hotkey.vk_code == 0) {
+            return Err(AppError::Settings(format!(
+                "キャプチャプリセット「{}」のホットキーのキーコードが無効です",
+                self.name
+            )));
+        }
+        if self.output == PresetOutput::Upload && self.upload_command.program.trim().is_empty() {
+            return Err(AppError::Settings(format!(
+                "キャプチャプリセット「{}」のアップロードコマンドが設定されていません",
+                self.name
+            )));
+        }
+        self.file_naming.validate()
+    }
+}
+
+/// Validate a list of presets: each on its own, with unique names and hotkeys
+///
+/// `reserved` lists hotkeys already used by other captures, which presets
+/// can't take over.
+pub fn validate_presets(presets: &[CapturePreset], reserved: &[Hotkey]) -> AppResult<()> {
+    let mut names = HashSet::new();
+    let mut hotkeys: HashSet<Hotkey> = reserved.iter().copied().collect();
+    for preset in presets {
+        preset.validate()?;
+        if !names.insert(preset.name.trim()) {
+            return Err(AppError::Settings(format!(
+                "キャプチャプリセット「{}」が重複しています",
+                preset.name
+            )));
+        }
+        if let Some(hotkey) = preset.hotkey {
+            if !hotkeys.insert(hotkey) {
+                return Err(AppError::Settings(format!(
+                    "キャプチャプリセット「{}」のホットキーが他のキャプチャと重複しています",
+                    preset.name
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preset(name: &str, hotkey: Option<Hotkey>) -> CapturePreset {
+        CapturePreset {
+            name: name.to_string(),
+            hotkey,
+            ..CapturePreset::default()
+        }
+    }
+
+    #[test]
+    fn test_validate_presets() {
+        let screen = Hotkey { modifiers: 0x0006, vk_code: 0x53 };
+        let f9 = Hotkey { modifiers: 0, vk_code: 0x78 };
+        let presets = vec![preset("Left monitor", Some(f9)), preset("Window", None), preset("Region", None)];
+        assert!(validate_presets(&presets, &[screen]).is_ok());
+
+        // Names and hotkeys are unique, including against the reserved hotkeys
+        assert!(validate_presets(&[preset("A", None), preset(" A ", None)], &[]).is_err());
+        assert!(validate_presets(&[preset("A", Some(f9)), preset("B", Some(f9))], &[]).is_err());
+        assert!(validate_presets(&[preset("A", Some(screen))], &[screen]).is_err());
+
+        assert!(preset("  ", None).validate().is_err());
+        assert!(preset("A", Some(Hotkey { modifiers: 0x0002, vk_code: 0 })).validate().is_err());
+        let empty_region = CapturePreset {
+            target: PresetTarget::Region { x: 0, y: 0, width: 0, height: 10 },
+            ..preset("Region", None)
+        };
+        assert!(empty_region.validate().is_err());
+
+        let mut upload = CapturePreset {
+            output: PresetOutput::Upload,
+            ..preset("Upload", None)
+        };
+        assert!(upload.validate().is_err());
+        upload.upload_command.program = "uploader".to_string();
+        assert!(upload.validate().is_ok());
+    }
+
+    #[test]
+    fn test_areas_follow_the_monitor() {
+        let screen = |index: usize, x: f32, width: f32, height: f32, is_primary: bool| ScreenInfo {
+            index,
+            bounds: Rect::from_min_size(egui::Pos2::new(x, 0.0), Vec2::new(width, height)),
+            dpi_scale_x: 1.0,
+            dpi_scale_y: 1.0,
+            is_primary,
+        };
+        assert!(resolve_area(MonitorAnchor::Primary, AreaPlacement::LEFT_HALF, &[]).is_err());
+
+        let screens = vec![screen(0, 0.0, 2560.0, 1440.0, true), screen(1, 2560.0, 1920.0, 1080.0, false)];
+        let area = resolve_area(MonitorAnchor::Monitor(1), AreaPlacement::LEFT_HALF, &screens).unwrap();
+        assert_eq!(area, Rect::from_min_size(egui::Pos2::new(2560.0, 0.0), Vec2::new(960.0, 1080.0)));
+        let centered = AreaPlacement::Centered { width: 1920, height: 1080 };
+        let area = resolve_area(MonitorAnchor::Primary, centered, &screens).unwrap();
+        assert_eq!(area, Rect::from_min_size(egui::Pos2::new(320.0, 180.0), Vec2::new(1920.0, 1080.0)));
+
+        // After the monitors are swapped, the same presets land on their new bounds
+        let swapped = vec![screen(0, 1920.0, 2560.0, 1440.0, false), screen(1, 0.0, 1920.0, 1080.0, true)];
+        let area = resolve_area(MonitorAnchor::Primary, centered, &swapped).unwrap();
+        assert_eq!(area, Rect::from_min_size(egui::Pos2::ZERO, Vec2::new(1920.0, 1080.0)));
+        let area = resolve_area(MonitorAnchor::Monitor(1), AreaPlacement::LEFT_HALF, &swapped).unwrap();
+        assert_eq!(area.width(), 960.0);
+
+        // A disconnected monitor or an area off the monitor fail to resolve
+        let gone = resolve_area(MonitorAnchor::Monitor(2), AreaPlacement::LEFT_HALF, &screens).unwrap_err();
+        assert!(matches!(gone, AppError::ScreenNotFound { index: 2 }));
+        let outside = AreaPlacement::Offset { x: 3000, y: 0, width: 100, height: 100 };
+        assert!(resolve_area(MonitorAnchor::Primary, outside, &screens).is_err());
+
+        assert_eq!(
+            PresetTarget::Area { monitor: MonitorAnchor::Monitor(1), placement: AreaPlacement::LEFT_HALF }.to_string(),
+            "Left Half of Monitor 2"
+        );
+        assert_eq!(centered.to_string(), "1920×1080 Centered");
+        assert!(AreaPlacement::Fraction { x: 0.75, y: 0.0, width: 0.5, height: 1.0 }.validate().is_err());
+        assert!(AreaPlacement::Centered { width: 0, height: 10 }.validate().is_err());
+    }
+
+    #[test]
+    fn test_preset_serialization() {
+        let preset = CapturePreset {
+            name: "Top left".to_string(),
+            target: PresetTarget::Region { x: -1920, y: 0, width: 800, height: 600 },
+            output: PresetOutput::Clipboard,
+            format: ImageFormat::Jpg,
+            hotkey: Some(Hotkey { modifiers: 0x0001, vk_code: 0x31 }),
+            ..CapturePreset::default()
+        };
+        let json = serde_json::to_string(&preset).unwrap();
+        assert_eq!(serde_json::from_str::<CapturePreset>(&json).unwrap(), preset);
+
+        // Missing fields fall back to the defaults
+        let partial: CapturePreset = serde_json::from_str(r#"{"name": "Window", "target": "ActiveWindow"}"#).unwrap();
+        assert_eq!(partial.target, PresetTarget::ActiveWindow);
+        assert_eq!(partial.output, PresetOutput::Editor);
+        assert_eq!(partial.hotkey, None);
+    }
+}
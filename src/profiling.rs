@@ -0,0 +1,154 @@
+//! Capture pipeline profiling
+//!
+//! Scoped timers measure the stages of the capture pipeline wherever they
+//! run, including worker threads, and collect the samples in a process-wide
+//! profile. The editor shows them in the Performance window, and each sample
+//! is logged at debug level, so regressions in the pipeline are measurable
+//! from user logs too.
+
+use crate::diagnostics::{SampleHistory, DIAGNOSTICS_HISTORY};
+use std::sync::{Mutex, OnceLock, PoisonError};
+use std::time::{Duration, Instant};
+
+/// A measured stage of the capture pipeline
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Stage {
+    /// Grabbing the screen, including color conversion and tone mapping
+    Capture,
+    /// Decoding an opened or pasted image file
+    Decode,
+    /// Drawing the annotations onto the image for saving, copying, and exporting
+    Flatten,
+    /// Converting and uploading image pixels to the GPU
+    TextureUpload,
+}
+
+impl Stage {
+    /// Get all stages in pipeline order
+    pub fn all() -> [Stage; 4] {
+        [Stage::Capture, Stage::Decode, Stage::Flatten, Stage::TextureUpload]
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl std::fmt::Display for Stage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Stage::Capture => write!(f, "Capture"),
+            Stage::Decode => write!(f, "Decode"),
+            Stage::Flatten => write!(f, "Flatten"),
+            Stage::TextureUpload => write!(f, "Texture Upload"),
+        }
+    }
+}
+
+/// Recent timings of each pipeline stage
+#[derive(Debug, Clone)]
+pub struct Profile {
+    stages: [SampleHistory; 4],
+    /// Samples recorded per stage, including those dropped from the history
+    counts: [u64; 4],
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self {
+            stages: Stage::all().map(|_| SampleHistory::new(DIAGNOSTICS_HISTORY)),
+            counts: [0; 4],
+        }
+    }
+}
+
+impl Profile {
+    /// Record how long a stage took
+    pub fn record(&mut self, stage: Stage, duration: Duration) {
+        self.stages[stage.index()].push(duration);
+        self.counts[stage.index()] += 1;
+    }
+
+    /// Get the recent samples of a stage
+    pub fn samples(&self, stage: Stage) -> &SampleHistory {
+        &self.stages[stage.index()]
+    }
+
+    /// Number of times a stage was recorded
+    pub fn count(&self, stage: Stage) -> u64 {
+        self.counts[stage.index()]
+    }
+}
+
+/// The profile shared by all threads
+fn global() -> &'static Mutex<Profile> {
+    static PROFILE: OnceLock<Mutex<Profile>> = OnceLock::new();
+    PROFILE.get_or_init(|| Mutex::new(Profile::default()))
+}
+
+/// Record how long a stage took in the shared profile and log it at debug level
+pub fn record(stage: Stage, duration: Duration) {
+    log::debug!("{} took {:.2} ms", stage, duration.as_secs_f64() * 1000.0);
+    // A panic while recording leaves the samples intact
+    global().lock().unwrap_or_else(PoisonError::into_inner).record(stage, duration);
+}
+
+/// Get a copy of the shared profile
+pub fn snapshot() -> Profile {
+    global().lock().unwrap_or_else(PoisonError::into_inner).clone()
+}
+
+/// Forget all recorded samples
+pub fn reset() {
+    *global().lock().unwrap_or_else(PoisonError::into_inner) = Profile::default();
+}
+
+/// Start timing a stage, recorded when the returned timer is dropped
+pub fn scope(stage: Stage) -> ScopedTimer {
+    ScopedTimer {
+        stage,
+        started: Instant::now(),
+    }
+}
+
+/// Timer recording a stage when it goes out of scope
+#[derive(Debug)]
+#[must_use = "the stage is recorded when the timer is dropped"]
+pub struct ScopedTimer {
+    stage: Stage,
+    started: Instant,
+}
+
+impl Drop for ScopedTimer {
+    fn drop(&mut self) {
+        record(self.stage, self.started.elapsed());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile_keeps_samples_per_stage() {
+        let mut profile = Profile::default();
+        profile.record(Stage::Decode, Duration::from_millis(4));
+        profile.record(Stage::Decode, Duration::from_millis(8));
+        profile.record(Stage::Flatten, Duration::from_millis(2));
+
+        assert_eq!(profile.count(Stage::Decode), 2);
+        assert_eq!(profile.samples(Stage::Decode).average(), Some(6.0));
+        assert_eq!(profile.samples(Stage::Flatten).last(), Some(2.0));
+        assert!(profile.samples(Stage::Capture).is_empty());
+    }
+
+    #[test]
+    fn test_scoped_timer_records_on_drop() {
+        let before = snapshot().count(Stage::Capture);
+        {
+            let _timer = scope(Stage::Capture);
+        }
+        // Other tests may capture concurrently, so only a lower bound holds
+        assert!(snapshot().count(Stage::Capture) > before);
+    }
+}
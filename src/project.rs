@@ -0,0 +1,195 @@
+//! Project files
+//!
+//! A project file stores the annotations of a screenshot next to a reference
+//! to the image, so annotations stay editable after saving. Two revisions of
+//! a project can be compared to review which annotations changed.
+
+use crate::adjustments::Adjustments;
+use crate::files::write_atomic;
+use crate::types::{AnnotationItem, AppError, AppResult};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// File extension of project files
+pub const PROJECT_EXTENSION: &str = "lsaproj";
+
+/// Current project file format version
+pub const PROJECT_VERSION: u32 = 1;
+
+/// Saved annotations of a screenshot
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProjectFile {
+    pub version: u32,
+    /// Screenshot image, relative to the project file when in the same folder tree
+    pub image_path: PathBuf,
+    /// Image size in pixels when the project was saved
+    pub image_size: [u32; 2],
+    pub annotations: Vec<AnnotationItem>,
+    /// Adjustment stack applied to the image
+    #[serde(default)]
+    pub adjustments: Adjustments,
+}
+
+impl ProjectFile {
+    /// Create a project for an image
+    pub fn new(image_path: PathBuf, image_size: [u32; 2], annotations: Vec<AnnotationItem>) -> Self {
+        Self {
+            version: PROJECT_VERSION,
+            image_path,
+            image_size,
+            annotations,
+            adjustments: Adjustments::default(),
+        }
+    }
+
+    /// Load a project from a JSON file
+    pub fn load_from(path: &Path) -> AppResult<Self> {
+        let json = std::fs::read_to_string(path)?;
+        let project: ProjectFile = serde_json::from_str(&json)
+            .map_err(|e| AppError::Project(format!("プロジェクトファイルの解析に失敗しました: {}", e)))?;
+        if project.version > PROJECT_VERSION {
+            return Err(AppError::Project(format!(
+                "未対応のプロジェクトファイルのバージョンです: {}",
+                project.version
+            )));
+        }
+        Ok(project)
+    }
+
+    /// Save the project to a JSON file, storing the image path relative to it where possible
+    pub fn save_to(&self, path: &Path) -> AppResult<()> {
+        let mut project = self.clone();
+        if let Some(relative) = path.parent().and_then(|dir| project.image_path.strip_prefix(dir).ok()) {
+            project.image_path = relative.to_path_buf();
+        }
+        let json = serde_json::to_string_pretty(&project)
+            .map_err(|e| AppError::Project(format!("プロジェクトのシリアライズに失敗しました: {}", e)))?;
+        write_atomic(path, json.as_bytes())
+    }
+
+    /// Resolve the image path against the folder of the project file
+    pub fn resolve_image_path(&self, project_path: &Path) -> PathBuf {
+        match project_path.parent() {
+            Some(dir) if self.image_path.is_relative() => dir.join(&self.image_path),
+            _ => self.image_path.clone(),
+        }
+    }
+}
+
+/// Get the project file path for an image
+pub fn project_path_for(image_path: &Path) -> PathBuf {
+    image_path.with_extension(PROJECT_EXTENSION)
+}
+
+/// Annotation changes between two revisions of a project
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AnnotationDiff {
+    /// Annotations only in the new revision
+    pub added: Vec<AnnotationItem>,
+    /// Annotations only in the old revision
+    pub removed: Vec<AnnotationItem>,
+    /// Annotations in both revisions that were moved or edited (old, new)
+    pub changed: Vec<(AnnotationItem, AnnotationItem)>,
+    /// Number of annotations identical in both revisions
+    pub unchanged: usize,
+}
+
+impl AnnotationDiff {
+    /// Check whether the revisions have the same annotations
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    /// Check whether an annotation of the new revision was added
+    pub fn is_added(&self, id: Uuid) -> bool {
+        self.added.iter().any(|annotation| annotation.id == id)
+    }
+
+    /// Check whether an annotation of the new revision was changed
+    pub fn is_changed(&self, id: Uuid) -> bool {
+        self.changed.iter().any(|(_, new)| new.id == id)
+    }
+}
+
+/// Compare annotations of two revisions, matching them by id
+pub fn diff_annotations(old: &[AnnotationItem], new: &[AnnotationItem]) -> AnnotationDiff {
+    let mut diff = AnnotationDiff::default();
+    for annotation in new {
+        match old.iter().find(|previous| previous.id == annotation.id) {
+            None => diff.added.push(annotation.clone()),
+            Some(previous) => {
+                if previous.position == annotation.position && previous.annotation_type == annotation.annotation_type {
+                    diff.unchanged += 1;
+                } else {
+                    diff.changed.push((previous.clone(), annotation.clone()));
+                }
+            }
+        }
+    }
+    diff.removed = old
+        .iter()
+        .filter(|previous| !new.iter().any(|annotation| annotation.id == previous.id))
+        .cloned()
+        .collect();
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::AnnotationType;
+    use egui::{Pos2, Vec2};
+
+    #[test]
+    fn test_diff_annotations() {
+        let kept = AnnotationItem::new_rectangle(Pos2::new(0.0, 0.0), Vec2::new(10.0, 10.0));
+        let moved = AnnotationItem::new_rectangle(Pos2::new(20.0, 0.0), Vec2::new(10.0, 10.0));
+        let removed = AnnotationItem::new_text(Pos2::new(0.0, 50.0), "Step 1".to_string());
+        let added = AnnotationItem::new_text(Pos2::new(0.0, 80.0), "Step 2".to_string());
+
+        let mut moved_again = moved.clone();
+        moved_again.position = Pos2::new(30.0, 0.0);
+        let mut selected = kept.clone();
+        selected.is_selected = true;
+
+        let diff = diff_annotations(
+            &[kept.clone(), moved.clone(), removed.clone()],
+            &[selected, moved_again.clone(), added.clone()],
+        );
+        assert_eq!(diff.added, vec![added.clone()]);
+        assert_eq!(diff.removed, vec![removed]);
+        assert_eq!(diff.changed, vec![(moved, moved_again.clone())]);
+        assert_eq!(diff.unchanged, 1);
+        assert!(diff.is_added(added.id));
+        assert!(diff.is_changed(moved_again.id));
+        assert!(!diff.is_changed(kept.id));
+
+        let same = [kept];
+        assert!(diff_annotations(&same, &same).is_empty());
+    }
+
+    #[test]
+    fn test_save_and_load_project() {
+        let dir = std::env::temp_dir().join(format!("project_test_{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let image_path = dir.join("tutorial.png");
+        let project_path = project_path_for(&image_path);
+        assert_eq!(project_path, dir.join("tutorial.lsaproj"));
+
+        let mut text = AnnotationItem::new_text(Pos2::new(5.0, 5.0), "手順".to_string());
+        text.is_selected = true;
+        let project = ProjectFile::new(image_path.clone(), [640, 480], vec![text]);
+        project.save_to(&project_path).unwrap();
+
+        let loaded = ProjectFile::load_from(&project_path).unwrap();
+        assert_eq!(loaded.image_path, PathBuf::from("tutorial.png"));
+        assert_eq!(loaded.resolve_image_path(&project_path), image_path);
+        assert!(!loaded.annotations[0].is_selected);
+        assert!(matches!(loaded.annotations[0].annotation_type, AnnotationType::Text { .. }));
+
+        std::fs::write(&project_path, "{\"version\": 99}").unwrap();
+        assert!(ProjectFile::load_from(&project_path).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
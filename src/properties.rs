@@ -0,0 +1,167 @@
+//! Annotation properties
+//!
+//! The properties panel edits a copy of the first selected annotation and
+//! applies only the fields that changed to every selected annotation, so a
+//! selection can be restyled at once without losing what differs between
+//! its annotations. A change of position moves every annotation by the same
+//! amount; other fields are set to the new value.
+
+use crate::text::TextFormat;
+use crate::types::{AnnotationItem, AnnotationType};
+
+/// What a selection is made of, deciding which fields the panel shows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectionKind {
+    Rectangles,
+    Texts,
+    Images,
+    Dimensions,
+    /// Annotations of different types, which only share their position, opacity, blending, rotation, and step
+    Mixed,
+}
+
+impl SelectionKind {
+    /// Get the kind of a selection, `None` if nothing is selected
+    pub fn of(selected: &[&AnnotationItem]) -> Option<SelectionKind> {
+        let kind_of = |annotation: &AnnotationItem| match annotation.annotation_type {
+            AnnotationType::Rectangle { .. } => SelectionKind::Rectangles,
+            AnnotationType::Text { .. } => SelectionKind::Texts,
+            AnnotationType::Image { .. } => SelectionKind::Images,
+            AnnotationType::Dimension { .. } => SelectionKind::Dimensions,
+        };
+        let first = kind_of(selected.first()?);
+        if selected.iter().all(|annotation| kind_of(annotation) == first) {
+            Some(first)
+        } else {
+            Some(SelectionKind::Mixed)
+        }
+    }
+}
+
+/// Apply the fields that differ between `before` and `after` to `target`
+pub fn apply_changes(before: &AnnotationItem, after: &AnnotationItem, target: &mut AnnotationItem) {
+    target.position += after.position - before.position;
+    set_if_changed(&mut target.rotation, &before.rotation, &after.rotation);
+    set_if_changed(&mut target.step, &before.step, &after.step);
+    set_if_changed(&mut target.opacity, &before.opacity, &after.opacity);
+    set_if_changed(&mut target.blend_mode, &before.blend_mode, &after.blend_mode);
+
+    match (&before.annotation_type, &after.annotation_type, &mut target.annotation_type) {
+        (
+            AnnotationType::Rectangle { size: size0, stroke_color: stroke0, stroke_width: width0, fill_color: fill0 },
+            AnnotationType::Rectangle { size: size1, stroke_color: stroke1, stroke_width: width1, fill_color: fill1 },
+            AnnotationType::Rectangle { size, stroke_color, stroke_width, fill_color },
+        ) => {
+            set_if_changed(size, size0, size1);
+            set_if_changed(stroke_color, stroke0, stroke1);
+            set_if_changed(stroke_width, width0, width1);
+            set_if_changed(fill_color, fill0, fill1);
+        }
+        (
+            AnnotationType::Text { content: content0, font_size: size0, color: color0, format: format0 },
+            AnnotationType::Text { content: content1, font_size: size1, color: color1, format: format1 },
+            AnnotationType::Text { content, font_size, color, format },
+        ) => {
+            set_if_changed(content, content0, content1);
+            set_if_changed(font_size, size0, size1);
+            set_if_changed(color, color0, color1);
+            apply_format_changes(format0, format1, format);
+        }
+        (
+            AnnotationType::Image { size: size0, opacity: opacity0, .. },
+            AnnotationType::Image { size: size1, opacity: opacity1, .. },
+            AnnotationType::Image { size, opacity, .. },
+        ) => {
+            set_if_changed(size, size0, size1);
+            set_if_changed(opacity, opacity0, opacity1);
+        }
+        (
+            AnnotationType::Dimension { color: color0, width: width0, .. },
+            AnnotationType::Dimension { color: color1, width: width1, .. },
+            AnnotationType::Dimension { color, width, .. },
+        ) => {
+            set_if_changed(color, color0, color1);
+            set_if_changed(width, width0, width1);
+        }
+        _ => {}
+    }
+}
+
+/// Apply the text formatting fields that differ between `before` and `after` to `target`
+fn apply_format_changes(before: &TextFormat, after: &TextFormat, target: &mut TextFormat) {
+    set_if_changed(&mut target.bold, &before.bold, &after.bold);
+    set_if_changed(&mut target.italic, &before.italic, &after.italic);
+    set_if_changed(&mut target.font_family, &before.font_family, &after.font_family);
+    set_if_changed(&mut target.background, &before.background, &after.background);
+    set_if_changed(&mut target.outline, &before.outline, &after.outline);
+    set_if_changed(&mut target.align, &before.align, &after.align);
+    set_if_changed(&mut target.wrap_width, &before.wrap_width, &after.wrap_width);
+}
+
+fn set_if_changed<T: PartialEq + Clone>(target: &mut T, before: &T, after: &T) {
+    if before != after {
+        *target = after.clone();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use egui::{Color32, Pos2, Vec2};
+
+    #[test]
+    fn test_selection_kind() {
+        let rectangle = AnnotationItem::new_rectangle(Pos2::ZERO, Vec2::splat(10.0));
+        let text = AnnotationItem::new_text(Pos2::ZERO, "Hi".to_string());
+        assert_eq!(SelectionKind::of(&[]), None);
+        assert_eq!(SelectionKind::of(&[&rectangle, &rectangle]), Some(SelectionKind::Rectangles));
+        assert_eq!(SelectionKind::of(&[&text]), Some(SelectionKind::Texts));
+        assert_eq!(SelectionKind::of(&[&text, &rectangle]), Some(SelectionKind::Mixed));
+    }
+
+    #[test]
+    fn test_apply_changes_keeps_other_fields() {
+        let mut first = AnnotationItem::new_rectangle(Pos2::new(10.0, 10.0), Vec2::new(20.0, 20.0));
+        let mut second = AnnotationItem::new_rectangle(Pos2::new(50.0, 0.0), Vec2::new(40.0, 10.0));
+        if let AnnotationType::Rectangle { stroke_color, .. } = &mut second.annotation_type {
+            *stroke_color = Color32::GREEN;
+        }
+
+        // The stroke width is changed and the selection moved right
+        let before = first.clone();
+        let mut after = before.clone();
+        after.position.x += 5.0;
+        if let AnnotationType::Rectangle { stroke_width, .. } = &mut after.annotation_type {
+            *stroke_width = 6.0;
+        }
+        apply_changes(&before, &after, &mut first);
+        apply_changes(&before, &after, &mut second);
+
+        assert_eq!(second.position, Pos2::new(55.0, 0.0));
+        assert_eq!(first.position, Pos2::new(15.0, 10.0));
+        let AnnotationType::Rectangle { size, stroke_color, stroke_width, .. } = second.annotation_type else {
+            unreachable!()
+        };
+        assert_eq!((size, stroke_color, stroke_width), (Vec2::new(40.0, 10.0), Color32::GREEN, 6.0));
+    }
+
+    #[test]
+    fn test_apply_text_format_changes() {
+        let mut text = AnnotationItem::new_text(Pos2::ZERO, "Keep".to_string());
+        if let AnnotationType::Text { format, .. } = &mut text.annotation_type {
+            format.italic = true;
+        }
+        let before = AnnotationItem::new_text(Pos2::ZERO, "First".to_string());
+        let mut after = before.clone();
+        if let AnnotationType::Text { format, .. } = &mut after.annotation_type {
+            format.bold = true;
+        }
+        apply_changes(&before, &after, &mut text);
+
+        let AnnotationType::Text { content, format, .. } = text.annotation_type else {
+            unreachable!()
+        };
+        assert_eq!(content, "Keep");
+        assert!(format.bold && format.italic);
+    }
+}
@@ -0,0 +1,524 @@
+//! Region memory per application
+//!
+//! When screen captures are followed by a region selection, the region picked
+//! is remembered for the application that was focused, keyed by its executable
+//! name. The next capture taken while the same application is focused starts
+//! with that region selected.
+//!
+//! The selection can also be constrained to an aspect ratio or an exact
+//! size, picked from common screen and social media sizes, and adjusted
+//! with the keyboard alone. How the selection overlay looks is configured
+//! with [`OverlaySettings`].
+//!
+//! Holding Ctrl while drawing or moving the selection snaps it to the
+//! layout grid of the captured screen: its halves, thirds, and quarters.
+//! That makes tidy regions such as "the left half" or "the middle third"
+//! easy to pick without lining up pixels by hand.
+
+use crate::types::{AppError, AppResult};
+use egui::{Color32, Pos2, Rect, Vec2};
+use serde::{Deserialize, Serialize};
+
+/// Number of applications whose regions are remembered
+pub const MAX_REMEMBERED_APPS: usize = 32;
+
+/// Fractions of the screen on the layout grid: edges, halves, thirds, and quarters
+const LAYOUT_FRACTIONS: [f32; 7] = [0.0, 0.25, 1.0 / 3.0, 0.5, 2.0 / 3.0, 0.75, 1.0];
+
+/// How the shape of the selected region is constrained
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SelectionConstraint {
+    #[default]
+    Free,
+    /// Width to height ratio
+    Aspect(u32, u32),
+    /// Exact size in pixels; the selection is moved rather than drawn
+    Fixed(u32, u32),
+}
+
+impl SelectionConstraint {
+    /// Get the width to height ratio the selection is locked to, if any
+    pub fn aspect(self) -> Option<f32> {
+        match self {
+            SelectionConstraint::Free => None,
+            SelectionConstraint::Aspect(width, height) | SelectionConstraint::Fixed(width, height) => {
+                Some(width.max(1) as f32 / height.max(1) as f32)
+            }
+        }
+    }
+}
+
+/// A named selection shape offered when selecting a region
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SizePreset {
+    pub name: &'static str,
+    pub constraint: SelectionConstraint,
+}
+
+/// Aspect ratios and sizes offered when selecting a region
+pub const SIZE_PRESETS: &[SizePreset] = &[
+    SizePreset { name: "Free", constraint: SelectionConstraint::Free },
+    SizePreset { name: "16:9", constraint: SelectionConstraint::Aspect(16, 9) },
+    SizePreset { name: "4:3", constraint: SelectionConstraint::Aspect(4, 3) },
+    SizePreset { name: "1:1", constraint: SelectionConstraint::Aspect(1, 1) },
+    SizePreset { name: "1280 × 720 (HD)", constraint: SelectionConstraint::Fixed(1280, 720) },
+    SizePreset { name: "1920 × 1080 (Full HD)", constraint: SelectionConstraint::Fixed(1920, 1080) },
+    SizePreset { name: "1080 × 1080 (Instagram square)", constraint: SelectionConstraint::Fixed(1080, 1080) },
+    SizePreset { name: "1080 × 1350 (Instagram portrait)", constraint: SelectionConstraint::Fixed(1080, 1350) },
+    SizePreset { name: "1200 × 630 (Link preview)", constraint: SelectionConstraint::Fixed(1200, 630) },
+    SizePreset { name: "1600 × 900 (X post)", constraint: SelectionConstraint::Fixed(1600, 900) },
+];
+
+/// Get the rectangle drawn from `start` toward `end` with a width to height ratio
+///
+/// The rectangle covers the dragged box along its longer side and is
+/// shrunk to stay within `bounds`, keeping `start` as its corner.
+pub fn aspect_rect(start: Pos2, end: Pos2, aspect: f32, bounds: Rect) -> Rect {
+    let delta = end - start;
+    let (sign_x, sign_y) = (if delta.x < 0.0 { -1.0 } else { 1.0 }, if delta.y < 0.0 { -1.0 } else { 1.0 });
+    let room_x = if sign_x < 0.0 { start.x - bounds.min.x } else { bounds.max.x - start.x };
+    let room_y = if sign_y < 0.0 { start.y - bounds.min.y } else { bounds.max.y - start.y };
+    let width = delta.x.abs().max(delta.y.abs() * aspect).min(room_x).min(room_y * aspect).max(0.0);
+    Rect::from_two_pos(start, start + Vec2::new(sign_x * width, sign_y * width / aspect))
+}
+
+/// Reshape a selection to a constraint around its center, keeping it within `bounds`
+///
+/// Aspect ratios shrink the longer side; fixed sizes replace the size,
+/// shrunk if the bounds are smaller.
+pub fn constrain(rect: Rect, constraint: SelectionConstraint, bounds: Rect) -> Rect {
+    let size = match constraint {
+        SelectionConstraint::Free => rect.size(),
+        SelectionConstraint::Aspect(..) => {
+            let aspect = constraint.aspect().unwrap_or(1.0);
+            if rect.width() > rect.height() * aspect {
+                Vec2::new(rect.height() * aspect, rect.height())
+            } else {
+                Vec2::new(rect.width(), rect.width() / aspect)
+            }
+        }
+        SelectionConstraint::Fixed(width, height) => Vec2::new(width as f32, height as f32),
+    };
+    fit_within(Rect::from_center_size(rect.center(), size), bounds)
+}
+
+/// Edge of the selection adjusted with the arrow keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edge {
+    Left,
+    Top,
+    Right,
+    Bottom,
+}
+
+impl Edge {
+    /// Get the edge adjusted after pressing Tab, or before with Shift+Tab
+    ///
+    /// The cycle includes `None`, where the arrow keys move the whole selection.
+    pub fn cycle(edge: Option<Edge>, backward: bool) -> Option<Edge> {
+        const CYCLE: [Option<Edge>; 5] = [None, Some(Edge::Left), Some(Edge::Top), Some(Edge::Right), Some(Edge::Bottom)];
+        let index = CYCLE.iter().position(|&candidate| candidate == edge).unwrap_or(0);
+        let next = if backward { index + CYCLE.len() - 1 } else { index + 1 };
+        CYCLE[next % CYCLE.len()]
+    }
+}
+
+impl std::fmt::Display for Edge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Edge::Left => write!(f, "Left"),
+            Edge::Top => write!(f, "Top"),
+            Edge::Right => write!(f, "Right"),
+            Edge::Bottom => write!(f, "Bottom"),
+        }
+    }
+}
+
+/// Move or resize a selection for an arrow key press
+///
+/// `offset` is the arrow's direction times the step. With an edge, that edge
+/// moves along its axis; otherwise the selection moves, or grows and shrinks
+/// from its bottom-right corner when `resize` is set. Selections stay at least
+/// a pixel in size within `bounds`, keep a locked aspect ratio, and are only
+/// moved when their size is fixed.
+pub fn nudge(
+    rect: Rect,
+    offset: Vec2,
+    edge: Option<Edge>,
+    resize: bool,
+    constraint: SelectionConstraint,
+    bounds: Rect,
+) -> Rect {
+    if matches!(constraint, SelectionConstraint::Fixed(..)) || (edge.is_none() && !resize) {
+        return fit_within(rect.translate(offset), bounds);
+    }
+    let mut resized = rect;
+    match edge {
+        Some(Edge::Left) => resized.min.x = (rect.min.x + offset.x).clamp(bounds.min.x, rect.max.x - 1.0),
+        Some(Edge::Top) => resized.min.y = (rect.min.y + offset.y).clamp(bounds.min.y, rect.max.y - 1.0),
+        Some(Edge::Right) => resized.max.x = (rect.max.x + offset.x).clamp(rect.min.x + 1.0, bounds.max.x),
+        Some(Edge::Bottom) => resized.max.y = (rect.max.y + offset.y).clamp(rect.min.y + 1.0, bounds.max.y),
+        None => resized.max = (rect.max + offset).clamp(rect.min + Vec2::splat(1.0), bounds.max),
+    }
+    if let Some(aspect) = constraint.aspect() {
+        // The other side follows the side that was adjusted
+        if offset.x != 0.0 {
+            resized.max.y = resized.min.y + resized.width() / aspect;
+        } else {
+            resized.max.x = resized.min.x + resized.height() * aspect;
+        }
+    }
+    fit_within(resized, bounds)
+}
+
+/// Move a rectangle inside `bounds`, shrinking it first if it is larger
+pub fn fit_within(rect: Rect, bounds: Rect) -> Rect {
+    let size = rect.size().min(bounds.size());
+    let min = rect.min.clamp(bounds.min, bounds.max - size);
+    Rect::from_min_size(min, size)
+}
+
+/// Appearance of the region selection overlay
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OverlaySettings {
+    /// Opacity of the shade outside the selection, from 0 to 1
+    pub dim_opacity: f32,
+    pub border_color: Color32,
+    /// Width of the selection border in screen pixels
+    pub border_width: f32,
+    /// Whether the selection size is shown above it
+    pub show_dimensions: bool,
+    /// Whether lines through the pointer span the image
+    pub show_crosshair: bool,
+    /// Whether the selection is divided into thirds
+    pub show_thirds: bool,
+    /// Whether a magnified view follows the pointer
+    pub show_loupe: bool,
+}
+
+impl Default for OverlaySettings {
+    fn default() -> Self {
+        Self {
+            dim_opacity: 0.55,
+            border_color: Color32::WHITE,
+            border_width: 1.5,
+            show_dimensions: true,
+            show_crosshair: false,
+            show_thirds: false,
+            show_loupe: false,
+        }
+    }
+}
+
+impl OverlaySettings {
+    /// Largest allowed border width
+    pub const MAX_BORDER_WIDTH: f32 = 10.0;
+
+    /// Get the color of the shade outside the selection
+    pub fn dim_color(&self) -> Color32 {
+        Color32::from_black_alpha((self.dim_opacity.clamp(0.0, 1.0) * 255.0).round() as u8)
+    }
+
+    /// Validate overlay settings
+    pub fn validate(&self) -> AppResult<()> {
+        if !(0.0..=1.0).contains(&self.dim_opacity) {
+            return Err(AppError::Settings("範囲外を暗くする不透明度は0〜100%で指定してください".to_string()));
+        }
+        if !(self.border_width > 0.0 && self.border_width <= Self::MAX_BORDER_WIDTH) {
+            return Err(AppError::Settings(format!(
+                "選択範囲の枠線の太さは0より大きく{}ピクセル以下で指定してください",
+                Self::MAX_BORDER_WIDTH
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Lines dividing a rectangle into thirds, vertical lines first
+pub fn thirds(rect: Rect) -> [[Pos2; 2]; 4] {
+    let x = |fraction: f32| rect.min.x + rect.width() * fraction;
+    let y = |fraction: f32| rect.min.y + rect.height() * fraction;
+    [
+        [Pos2::new(x(1.0 / 3.0), rect.min.y), Pos2::new(x(1.0 / 3.0), rect.max.y)],
+        [Pos2::new(x(2.0 / 3.0), rect.min.y), Pos2::new(x(2.0 / 3.0), rect.max.y)],
+        [Pos2::new(rect.min.x, y(1.0 / 3.0)), Pos2::new(rect.max.x, y(1.0 / 3.0))],
+        [Pos2::new(rect.min.x, y(2.0 / 3.0)), Pos2::new(rect.max.x, y(2.0 / 3.0))],
+    ]
+}
+
+/// Positions of the layout grid lines across a span, rounded to whole pixels
+fn layout_lines(min: f32, length: f32) -> impl Iterator<Item = f32> {
+    LAYOUT_FRACTIONS.into_iter().map(move |fraction| (min + length * fraction).round())
+}
+
+/// Layout grid line closest to `value`, other than `except`
+fn nearest_layout_line(value: f32, min: f32, length: f32, except: Option<f32>) -> f32 {
+    layout_lines(min, length)
+        .filter(|&line| Some(line) != except)
+        .min_by(|a, b| (a - value).abs().total_cmp(&(b - value).abs()))
+        .unwrap_or(value)
+}
+
+/// Inner lines of the layout grid of `bounds`, vertical lines first
+pub fn layout_grid(bounds: Rect) -> Vec<[Pos2; 2]> {
+    let inner = |min: f32, length: f32| {
+        let (first, last) = (min.round(), (min + length).round());
+        layout_lines(min, length).filter(move |&line| line != first && line != last)
+    };
+    let vertical = inner(bounds.min.x, bounds.width()).map(|x| [Pos2::new(x, bounds.min.y), Pos2::new(x, bounds.max.y)]);
+    let horizontal = inner(bounds.min.y, bounds.height()).map(|y| [Pos2::new(bounds.min.x, y), Pos2::new(bounds.max.x, y)]);
+    vertical.chain(horizontal).collect()
+}
+
+/// Snap a selection drawn from `start` to `end` to the layout grid of `bounds`
+///
+/// Both corners move to the closest grid lines, and the dragged corner
+/// never lands on the same line as the first one, so the selection keeps
+/// at least one grid cell.
+pub fn layout_rect(start: Pos2, end: Pos2, bounds: Rect) -> Rect {
+    let snap = |start: f32, end: f32, min: f32, length: f32| {
+        let first = nearest_layout_line(start, min, length, None);
+        (first, nearest_layout_line(end, min, length, Some(first)))
+    };
+    let (x0, x1) = snap(start.x, end.x, bounds.min.x, bounds.width());
+    let (y0, y1) = snap(start.y, end.y, bounds.min.y, bounds.height());
+    Rect::from_two_pos(Pos2::new(x0, y0), Pos2::new(x1, y1))
+}
+
+/// Move a selection so that, on each axis, whichever of its edges is closer
+/// to a layout grid line of `bounds` lies on it
+pub fn snap_to_layout(rect: Rect, bounds: Rect) -> Rect {
+    let offset = |low: f32, high: f32, min: f32, length: f32| {
+        let to_low = nearest_layout_line(low, min, length, None) - low;
+        let to_high = nearest_layout_line(high, min, length, None) - high;
+        if to_low.abs() <= to_high.abs() {
+            to_low
+        } else {
+            to_high
+        }
+    };
+    let delta = Vec2::new(
+        offset(rect.min.x, rect.max.x, bounds.min.x, bounds.width()),
+        offset(rect.min.y, rect.max.y, bounds.min.y, bounds.height()),
+    );
+    fit_within(rect.translate(delta), bounds)
+}
+
+/// Place a loupe of `size` below and right of the pointer, flipping it to
+/// the other side where it would leave `bounds`
+pub fn loupe_rect(pointer: Pos2, size: f32, offset: f32, bounds: Rect) -> Rect {
+    let mut min = pointer + Vec2::splat(offset);
+    if min.x + size > bounds.max.x {
+        min.x = pointer.x - offset - size;
+    }
+    if min.y + size > bounds.max.y {
+        min.y = pointer.y - offset - size;
+    }
+    Rect::from_min_size(min, Vec2::splat(size))
+}
+
+/// The region last selected for one application
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RememberedRegion {
+    /// Executable name, e.g. "firefox.exe"
+    pub application: String,
+    /// Desktop position and size, in the same coordinates as screen bounds
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl RememberedRegion {
+    /// Get the region as a desktop rectangle
+    pub fn bounds(&self) -> Rect {
+        Rect::from_min_size(
+            Pos2::new(self.x as f32, self.y as f32),
+            Vec2::new(self.width as f32, self.height as f32),
+        )
+    }
+}
+
+/// Regions remembered per application, most recently used first
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RegionMemory {
+    pub regions: Vec<RememberedRegion>,
+}
+
+impl RegionMemory {
+    /// Get the region remembered for an application
+    pub fn region_for(&self, application: &str) -> Option<Rect> {
+        self.regions
+            .iter()
+            .find(|region| region.application.eq_ignore_ascii_case(application))
+            .map(RememberedRegion::bounds)
+    }
+
+    /// Remember the region selected for an application
+    ///
+    /// The least recently used application is forgotten once more than
+    /// [`MAX_REMEMBERED_APPS`] are remembered.
+    pub fn remember(&mut self, application: &str, bounds: Rect) {
+        self.forget(application);
+        self.regions.insert(
+            0,
+            RememberedRegion {
+                application: application.to_string(),
+                x: bounds.min.x.round() as i32,
+                y: bounds.min.y.round() as i32,
+                width: bounds.width().round().max(1.0) as u32,
+                height: bounds.height().round().max(1.0) as u32,
+            },
+        );
+        self.regions.truncate(MAX_REMEMBERED_APPS);
+    }
+
+    /// Forget the region of an application
+    pub fn forget(&mut self, application: &str) {
+        self.regions.retain(|region| !region.application.eq_ignore_ascii_case(application));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: f32, y: f32, width: f32, height: f32) -> Rect {
+        Rect::from_min_size(Pos2::new(x, y), Vec2::new(width, height))
+    }
+
+    #[test]
+    fn test_remember_per_application() {
+        let mut memory = RegionMemory::default();
+        assert_eq!(memory.region_for("firefox.exe"), None);
+
+        memory.remember("firefox.exe", rect(-1920.0, 100.4, 800.0, 600.0));
+        memory.remember("code.exe", rect(0.0, 0.0, 1280.0, 720.0));
+        assert_eq!(memory.region_for("Firefox.EXE"), Some(rect(-1920.0, 100.0, 800.0, 600.0)));
+
+        // Selecting again replaces the region and makes it the most recent
+        memory.remember("FIREFOX.exe", rect(10.0, 20.0, 30.0, 40.0));
+        assert_eq!(memory.regions.len(), 2);
+        assert_eq!(memory.regions[0].application, "FIREFOX.exe");
+        assert_eq!(memory.region_for("firefox.exe"), Some(rect(10.0, 20.0, 30.0, 40.0)));
+
+        memory.forget("code.exe");
+        assert_eq!(memory.region_for("code.exe"), None);
+    }
+
+    #[test]
+    fn test_aspect_locked_drawing() {
+        let bounds = rect(0.0, 0.0, 100.0, 100.0);
+        // The longer side of the dragged box wins, in any direction
+        assert_eq!(aspect_rect(Pos2::new(10.0, 10.0), Pos2::new(42.0, 12.0), 16.0 / 9.0, bounds), rect(10.0, 10.0, 32.0, 18.0));
+        assert_eq!(aspect_rect(Pos2::new(50.0, 50.0), Pos2::new(45.0, 20.0), 1.0, bounds), rect(20.0, 20.0, 30.0, 30.0));
+        // Shrunk to fit the image rather than cut off
+        assert_eq!(aspect_rect(Pos2::new(60.0, 80.0), Pos2::new(130.0, 110.0), 2.0, bounds), rect(60.0, 80.0, 40.0, 20.0));
+        assert_eq!(SelectionConstraint::Free.aspect(), None);
+        assert_eq!(SelectionConstraint::Fixed(1080, 1350).aspect(), Some(0.8));
+    }
+
+    #[test]
+    fn test_constrain() {
+        let bounds = rect(0.0, 0.0, 1920.0, 1080.0);
+        let selection = rect(100.0, 100.0, 400.0, 200.0);
+        assert_eq!(constrain(selection, SelectionConstraint::Free, bounds), selection);
+        assert_eq!(constrain(selection, SelectionConstraint::Aspect(1, 1), bounds), rect(200.0, 100.0, 200.0, 200.0));
+        // A fixed size is centered on the selection and kept on the image
+        assert_eq!(constrain(selection, SelectionConstraint::Fixed(1280, 720), bounds), rect(0.0, 0.0, 1280.0, 720.0));
+        assert_eq!(
+            constrain(selection, SelectionConstraint::Fixed(1080, 1350), bounds),
+            rect(0.0, 0.0, 1080.0, 1080.0)
+        );
+    }
+
+    #[test]
+    fn test_keyboard_nudge() {
+        let bounds = rect(0.0, 0.0, 100.0, 100.0);
+        let selection = rect(10.0, 10.0, 20.0, 10.0);
+        let free = SelectionConstraint::Free;
+        let left = Vec2::new(-1.0, 0.0);
+
+        // Arrows move, stopping at the image edge
+        assert_eq!(nudge(selection, left * 10.0, None, false, free, bounds), rect(0.0, 10.0, 20.0, 10.0));
+        assert_eq!(nudge(selection, left * 20.0, None, false, free, bounds), rect(0.0, 10.0, 20.0, 10.0));
+        // Shift+arrows resize from the bottom-right corner, down to a pixel
+        assert_eq!(nudge(selection, left, None, true, free, bounds), rect(10.0, 10.0, 19.0, 10.0));
+        assert_eq!(nudge(selection, left * 50.0, None, true, free, bounds), rect(10.0, 10.0, 1.0, 10.0));
+        // A chosen edge moves along its axis only
+        assert_eq!(nudge(selection, left, Some(Edge::Left), false, free, bounds), rect(9.0, 10.0, 21.0, 10.0));
+        assert_eq!(nudge(selection, left, Some(Edge::Top), true, free, bounds), selection);
+        assert_eq!(nudge(selection, Vec2::new(0.0, -1.0), Some(Edge::Top), false, free, bounds), rect(10.0, 9.0, 20.0, 11.0));
+
+        // Locked ratios follow the adjusted side, and fixed sizes only move
+        let aspect = SelectionConstraint::Aspect(2, 1);
+        assert_eq!(nudge(selection, Vec2::new(4.0, 0.0), Some(Edge::Right), false, aspect, bounds), rect(10.0, 10.0, 24.0, 12.0));
+        let fixed = SelectionConstraint::Fixed(20, 10);
+        assert_eq!(nudge(selection, left, Some(Edge::Right), true, fixed, bounds), rect(9.0, 10.0, 20.0, 10.0));
+
+        assert_eq!(Edge::cycle(None, false), Some(Edge::Left));
+        assert_eq!(Edge::cycle(Some(Edge::Bottom), false), None);
+        assert_eq!(Edge::cycle(None, true), Some(Edge::Bottom));
+    }
+
+    #[test]
+    fn test_fit_within() {
+        let bounds = rect(0.0, 0.0, 100.0, 50.0);
+        assert_eq!(fit_within(rect(90.0, -5.0, 20.0, 10.0), bounds), rect(80.0, 0.0, 20.0, 10.0));
+        assert_eq!(fit_within(rect(10.0, 10.0, 200.0, 20.0), bounds), rect(0.0, 10.0, 100.0, 20.0));
+    }
+
+    #[test]
+    fn test_overlay_settings() {
+        let settings = OverlaySettings::default();
+        assert!(settings.validate().is_ok());
+        assert_eq!(settings.dim_color(), Color32::from_black_alpha(140));
+        assert_eq!(OverlaySettings { dim_opacity: 0.0, ..settings }.dim_color(), Color32::TRANSPARENT);
+        assert!(OverlaySettings { dim_opacity: 1.5, ..settings }.validate().is_err());
+        assert!(OverlaySettings { border_width: 0.0, ..settings }.validate().is_err());
+        assert!(OverlaySettings { border_width: f32::NAN, ..settings }.validate().is_err());
+
+        let lines = thirds(rect(0.0, 0.0, 90.0, 30.0));
+        assert_eq!(lines[1], [Pos2::new(60.0, 0.0), Pos2::new(60.0, 30.0)]);
+        assert_eq!(lines[2], [Pos2::new(0.0, 10.0), Pos2::new(90.0, 10.0)]);
+
+        // The loupe flips to stay on the canvas
+        let bounds = rect(0.0, 0.0, 400.0, 300.0);
+        assert_eq!(loupe_rect(Pos2::new(10.0, 10.0), 100.0, 20.0, bounds), rect(30.0, 30.0, 100.0, 100.0));
+        assert_eq!(loupe_rect(Pos2::new(350.0, 250.0), 100.0, 20.0, bounds), rect(230.0, 130.0, 100.0, 100.0));
+    }
+
+    #[test]
+    fn test_layout_grid_snapping() {
+        let bounds = rect(0.0, 0.0, 1920.0, 1080.0);
+        let lines = layout_grid(bounds);
+        // Quarters, thirds, and the half on each axis
+        assert_eq!(lines.len(), 10);
+        assert_eq!(lines[1], [Pos2::new(640.0, 0.0), Pos2::new(640.0, 1080.0)]);
+        assert_eq!(lines[5], [Pos2::new(0.0, 270.0), Pos2::new(1920.0, 270.0)]);
+
+        // A rough drag over the left half picks exactly the left half
+        assert_eq!(layout_rect(Pos2::new(12.0, 8.0), Pos2::new(975.0, 1060.0), bounds), rect(0.0, 0.0, 960.0, 1080.0));
+        // The middle third, dragged right to left
+        assert_eq!(layout_rect(Pos2::new(1300.0, 1075.0), Pos2::new(630.0, 3.0), bounds), rect(640.0, 0.0, 640.0, 1080.0));
+        // A tiny drag still spans a grid cell
+        assert_eq!(layout_rect(Pos2::new(5.0, 5.0), Pos2::new(9.0, 9.0), bounds), rect(0.0, 0.0, 480.0, 270.0));
+
+        // Moving snaps the closer edge and keeps the size
+        assert_eq!(snap_to_layout(rect(950.0, 30.0, 400.0, 300.0), bounds), rect(960.0, 0.0, 400.0, 300.0));
+        assert_eq!(snap_to_layout(rect(1500.0, 790.0, 400.0, 300.0), bounds), rect(1520.0, 780.0, 400.0, 300.0));
+    }
+
+    #[test]
+    fn test_least_recent_application_is_forgotten() {
+        let mut memory = RegionMemory::default();
+        for index in 0..=MAX_REMEMBERED_APPS {
+            memory.remember(&format!("app{}.exe", index), rect(0.0, 0.0, 10.0, 10.0));
+        }
+        assert_eq!(memory.regions.len(), MAX_REMEMBERED_APPS);
+        assert_eq!(memory.region_for("app0.exe"), None);
+        assert!(memory.region_for(&format!("app{}.exe", MAX_REMEMBERED_APPS)).is_some());
+    }
+}
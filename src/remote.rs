@@ -0,0 +1,702 @@
+//! Remote save destinations
+//!
+//! Besides local folders, images can be saved straight to an SFTP or FTP
+//! server or a network share that needs its own credentials. Destinations
+//! are remembered in the settings and offered in Save As; their passwords
+//! are kept in the credential store. Uploads run on a worker thread,
+//! reporting progress, and are retried after dropped connections.
+//!
+//! Files are uploaded under a temporary name and renamed when complete, so
+//! readers on the server never see a partial image, as with local saves.
+
+use crate::base64;
+use crate::credentials;
+use crate::files::{self, RetryPolicy};
+use crate::types::{AppError, AppResult};
+use crossbeam_channel::Receiver;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs};
+use std::path::PathBuf;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// How long to wait for a server before giving up on a connection attempt
+const NETWORK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Size of the pieces uploads are written in, between progress reports
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Default SFTP port
+pub const SFTP_PORT: u16 = 22;
+
+/// Default FTP port
+pub const FTP_PORT: u16 = 21;
+
+/// Where a remote destination is
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind")]
+pub enum RemoteLocation {
+    /// A directory on an SSH server
+    Sftp {
+        host: String,
+        port: u16,
+        username: String,
+        /// Directory on the server, relative to the login directory unless absolute
+        directory: String,
+        /// SHA-256 fingerprint of the server's host key, as shown by `ssh-keygen -l`
+        #[serde(default)]
+        fingerprint: String,
+    },
+    /// A directory on an FTP server, logged into anonymously without a user name
+    Ftp {
+        host: String,
+        port: u16,
+        #[serde(default)]
+        username: String,
+        directory: String,
+    },
+    /// A folder on a network share, connected to with other credentials when a user name is given
+    NetworkShare {
+        path: PathBuf,
+        #[serde(default)]
+        username: String,
+    },
+}
+
+impl RemoteLocation {
+    /// Get all kinds of location, with default fields
+    pub fn all() -> [RemoteLocation; 3] {
+        [
+            RemoteLocation::Sftp {
+                host: String::new(),
+                port: SFTP_PORT,
+                username: String::new(),
+                directory: String::new(),
+                fingerprint: String::new(),
+            },
+            RemoteLocation::Ftp { host: String::new(), port: FTP_PORT, username: String::new(), directory: String::new() },
+            RemoteLocation::NetworkShare { path: PathBuf::new(), username: String::new() },
+        ]
+    }
+
+    /// Get the user name logged in with, empty for the default
+    pub fn username(&self) -> &str {
+        match self {
+            RemoteLocation::Sftp { username, .. }
+            | RemoteLocation::Ftp { username, .. }
+            | RemoteLocation::NetworkShare { username, .. } => username,
+        }
+    }
+}
+
+impl std::fmt::Display for RemoteLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RemoteLocation::Sftp { .. } => write!(f, "SFTP"),
+            RemoteLocation::Ftp { .. } => write!(f, "FTP"),
+            RemoteLocation::NetworkShare { .. } => write!(f, "Network share"),
+        }
+    }
+}
+
+/// A remembered remote destination
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SaveDestination {
+    /// Identifies the destination's password in the credential store, so renaming keeps it
+    #[serde(default = "Uuid::new_v4")]
+    pub id: Uuid,
+    /// Name shown in Save As
+    pub name: String,
+    pub location: RemoteLocation,
+}
+
+impl SaveDestination {
+    /// Create a destination with a new identity
+    pub fn new(name: impl Into<String>, location: RemoteLocation) -> Self {
+        Self { id: Uuid::new_v4(), name: name.into(), location }
+    }
+
+    /// Get the key of the destination's password in the credential store
+    pub fn credential_key(&self) -> String {
+        format!("destination/{}", self.id)
+    }
+
+    /// Check whether the destination logs in with a password
+    ///
+    /// SFTP falls back to the SSH agent when no password is stored.
+    pub fn uses_password(&self) -> bool {
+        !self.location.username().is_empty()
+    }
+
+    /// Validate a destination
+    pub fn validate(&self) -> AppResult<()> {
+        if self.name.trim().is_empty() {
+            return Err(AppError::Settings("保存先の名前を入力してください".to_string()));
+        }
+        let invalid = |message: &str| Err(AppError::Settings(format!("保存先「{}」の{}", self.name, message)));
+        match &self.location {
+            RemoteLocation::Sftp { host, port, username, directory, .. }
+            | RemoteLocation::Ftp { host, port, username, directory } => {
+                if host.trim().is_empty() || host.contains(char::is_whitespace) {
+                    return invalid("ホスト名が正しくありません");
+                }
+                if *port == 0 {
+                    return invalid("ポート番号が正しくありません");
+                }
+                if matches!(self.location, RemoteLocation::Sftp { .. }) && username.trim().is_empty() {
+                    return invalid("ユーザー名を入力してください");
+                }
+                // A line break would end the FTP command and start another
+                if [username, directory].iter().any(|text| text.contains(['\r', '\n'])) {
+                    return invalid("ユーザー名またはフォルダに改行を含めることはできません");
+                }
+            }
+            RemoteLocation::NetworkShare { path, .. } => {
+                let path = path.to_string_lossy();
+                if !(path.starts_with("\\\\") || path.starts_with("//")) || share_root(&path).is_none() {
+                    return invalid("共有フォルダは \\\\サーバー\\共有名 の形式で入力してください");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Describe where a file saved to this destination ends up
+    pub fn file_location(&self, file_name: &str) -> String {
+        match &self.location {
+            RemoteLocation::Sftp { host, port, username, directory, .. } => {
+                format!("sftp://{}@{}{}{}", username, host_port(host, *port, SFTP_PORT), url_directory(directory), file_name)
+            }
+            RemoteLocation::Ftp { host, port, username, directory } => {
+                let user = if username.is_empty() { String::new() } else { format!("{}@", username) };
+                format!("ftp://{}{}{}{}", user, host_port(host, *port, FTP_PORT), url_directory(directory), file_name)
+            }
+            RemoteLocation::NetworkShare { path, .. } => path.join(file_name).display().to_string(),
+        }
+    }
+}
+
+/// Validate remote destinations, whose names must be unique
+pub fn validate_destinations(destinations: &[SaveDestination]) -> AppResult<()> {
+    let mut names = HashSet::new();
+    let mut ids = HashSet::new();
+    for destination in destinations {
+        destination.validate()?;
+        if !names.insert(destination.name.trim()) || !ids.insert(destination.id) {
+            return Err(AppError::Settings(format!("保存先「{}」が重複しています", destination.name)));
+        }
+    }
+    Ok(())
+}
+
+/// What an upload worker reports
+#[derive(Debug)]
+pub enum TransferEvent {
+    /// Bytes written so far, starting again from zero when a retry begins
+    Progress(u64),
+    /// Where the file was saved, or why it wasn't
+    Finished(AppResult<String>),
+}
+
+/// Upload a file on a worker thread, with the destination's stored password
+pub fn spawn_upload(
+    destination: SaveDestination,
+    file_name: String,
+    data: Vec<u8>,
+    policy: RetryPolicy,
+) -> Receiver<TransferEvent> {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    let progress_sender = sender.clone();
+    let spawned = std::thread::Builder::new().name("remote-save".to_string()).spawn(move || {
+        let result = stored_password(&destination).and_then(|password| {
+            upload_with_retry(&destination, password.as_deref(), &file_name, &data, policy, &mut |sent| {
+                let _ = progress_sender.send(TransferEvent::Progress(sent));
+            })
+        });
+        // The receiver only goes away when the editor is closing
+        let _ = sender.send(TransferEvent::Finished(result));
+    });
+    if let Err(e) = spawned {
+        let (sender, receiver) = crossbeam_channel::bounded(1);
+        let _ = sender.send(TransferEvent::Finished(Err(e.into())));
+        return receiver;
+    }
+    receiver
+}
+
+/// Get the stored password of a destination that logs in with one
+fn stored_password(destination: &SaveDestination) -> AppResult<Option<String>> {
+    if !destination.uses_password() {
+        return Ok(None);
+    }
+    let password = credentials::load(&destination.credential_key())?;
+    if password.is_none() && !matches!(destination.location, RemoteLocation::Sftp { .. }) {
+        return Err(AppError::Transfer(format!("保存先「{}」のパスワードが登録されていません", destination.name)));
+    }
+    Ok(password)
+}
+
+/// Upload a file, retrying after dropped connections and busy servers
+///
+/// Returns where the file was saved.
+pub fn upload_with_retry(
+    destination: &SaveDestination,
+    password: Option<&str>,
+    file_name: &str,
+    data: &[u8],
+    policy: RetryPolicy,
+    progress: &mut dyn FnMut(u64),
+) -> AppResult<String> {
+    if file_name.is_empty() || file_name.contains(['/', '\\', '\r', '\n']) {
+        return Err(AppError::Transfer(format!("ファイル名 {} は使用できません", file_name)));
+    }
+    files::retry_when(policy, is_retryable, || {
+        progress(0);
+        upload(destination, password, file_name, data, &mut *progress)
+    })
+    .map_err(|e| AppError::Transfer(format!("保存先「{}」に保存できませんでした: {}", destination.name, e)))?;
+    Ok(destination.file_location(file_name))
+}
+
+/// Check whether a failed upload is worth another attempt
+fn is_retryable(error: &io::Error) -> bool {
+    files::is_transient_error(error)
+        || matches!(
+            error.kind(),
+            io::ErrorKind::ConnectionRefused
+                | io::ErrorKind::ConnectionReset
+                | io::ErrorKind::ConnectionAborted
+                | io::ErrorKind::NotConnected
+                | io::ErrorKind::BrokenPipe
+                | io::ErrorKind::UnexpectedEof
+        )
+}
+
+/// Upload a file once
+fn upload(
+    destination: &SaveDestination,
+    password: Option<&str>,
+    file_name: &str,
+    data: &[u8],
+    progress: &mut dyn FnMut(u64),
+) -> io::Result<()> {
+    let temp_name = format!("{}{}-{}", files::TEMP_FILE_PREFIX, Uuid::new_v4().simple(), file_name);
+    match &destination.location {
+        RemoteLocation::Sftp { host, port, username, directory, fingerprint } => {
+            let session = sftp_session(host, *port, username, password, fingerprint)?;
+            let sftp = session.sftp()?;
+            let directory = PathBuf::from(directory);
+            let temp_path = directory.join(&temp_name);
+            let mut file = sftp.create(&temp_path)?;
+            if let Err(e) = write_chunks(&mut file, data, progress) {
+                drop(file);
+                let _ = sftp.unlink(&temp_path);
+                return Err(e);
+            }
+            drop(file);
+            let flags = ssh2::RenameFlags::OVERWRITE | ssh2::RenameFlags::ATOMIC | ssh2::RenameFlags::NATIVE;
+            sftp.rename(&temp_path, &directory.join(file_name), Some(flags))?;
+            Ok(())
+        }
+        RemoteLocation::Ftp { host, port, username, directory } => {
+            let mut control = FtpControl::connect(host, *port)?;
+            control.login(username, password)?;
+            control.expect("TYPE I", &[200])?;
+            if !directory.is_empty() {
+                control.expect(&format!("CWD {}", directory), &[250])?;
+            }
+            control.store(&temp_name, data, progress)?;
+            control.expect(&format!("RNFR {}", temp_name), &[350])?;
+            control.expect(&format!("RNTO {}", file_name), &[250])?;
+            let _ = control.command("QUIT");
+            Ok(())
+        }
+        RemoteLocation::NetworkShare { path, username } => {
+            if !username.is_empty() {
+                connect_share(&path.to_string_lossy(), username, password.unwrap_or_default())?;
+            }
+            files::write_atomic_with_retry(&path.join(file_name), data, RetryPolicy { attempts: 1, ..RetryPolicy::default() })
+                .map_err(|e| io::Error::other(e.to_string()))?;
+            progress(data.len() as u64);
+            Ok(())
+        }
+    }
+}
+
+/// Write data in chunks, reporting the bytes written after each
+fn write_chunks(writer: &mut impl Write, data: &[u8], progress: &mut dyn FnMut(u64)) -> io::Result<()> {
+    let mut sent = 0;
+    for chunk in data.chunks(CHUNK_SIZE) {
+        writer.write_all(chunk)?;
+        sent += chunk.len() as u64;
+        progress(sent);
+    }
+    writer.flush()
+}
+
+/// Connect to a server, trying each of its addresses
+fn connect(host: &str, port: u16) -> io::Result<TcpStream> {
+    let mut last_error = io::Error::new(io::ErrorKind::NotFound, format!("{} のアドレスが見つかりません", host));
+    for address in (host, port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&address, NETWORK_TIMEOUT) {
+            Ok(stream) => {
+                stream.set_read_timeout(Some(NETWORK_TIMEOUT))?;
+                stream.set_write_timeout(Some(NETWORK_TIMEOUT))?;
+                return Ok(stream);
+            }
+            Err(e) => last_error = e,
+        }
+    }
+    Err(last_error)
+}
+
+/// Open an authenticated SSH session, checking the server's host key
+fn sftp_session(
+    host: &str,
+    port: u16,
+    username: &str,
+    password: Option<&str>,
+    fingerprint: &str,
+) -> io::Result<ssh2::Session> {
+    let mut session = ssh2::Session::new()?;
+    session.set_tcp_stream(connect(host, port)?);
+    session.set_timeout(NETWORK_TIMEOUT.as_millis() as u32);
+    session.handshake()?;
+    let actual = session
+        .host_key_hash(ssh2::HashType::Sha256)
+        .map(|hash| format!("SHA256:{}", base64::encode(hash).trim_end_matches('=')))
+        .unwrap_or_default();
+    // Compared before logging in, so the password is never sent to an impostor
+    if fingerprint.trim().is_empty() || fingerprint.trim() != actual {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("サーバーのホスト鍵の指紋 {} が設定と一致しません。正しいサーバーであることを確認して設定してください", actual),
+        ));
+    }
+    match password {
+        Some(password) => session.userauth_password(username, password)?,
+        None => session.userauth_agent(username)?,
+    }
+    Ok(session)
+}
+
+/// Get the `\\server\share` part of a UNC path
+fn share_root(path: &str) -> Option<String> {
+    let mut parts = path.split(['\\', '/']).filter(|part| !part.is_empty());
+    let server = parts.next()?;
+    let share = parts.next()?;
+    Some(format!("\\\\{}\\{}", server, share))
+}
+
+/// Connect to a network share with the given credentials
+#[cfg(windows)]
+fn connect_share(path: &str, username: &str, password: &str) -> io::Result<()> {
+    use crate::files::to_wide;
+    use winapi::shared::winerror::{ERROR_SESSION_CREDENTIAL_CONFLICT, NO_ERROR};
+    use winapi::um::winnetwk::{WNetAddConnection2W, NETRESOURCEW, RESOURCETYPE_DISK};
+
+    let root = share_root(path).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, path.to_string()))?;
+    let mut remote = to_wide(&root);
+    let user = to_wide(username);
+    let password = to_wide(password);
+    // SAFETY: all fields are plain data or pointers, for which zero is a valid "unset" value
+    let mut resource: NETRESOURCEW = unsafe { std::mem::zeroed() };
+    resource.dwType = RESOURCETYPE_DISK;
+    resource.lpRemoteName = remote.as_mut_ptr();
+    // SAFETY: the strings are null-terminated and outlive the call
+    let status = unsafe { WNetAddConnection2W(&mut resource, password.as_ptr(), user.as_ptr(), 0) };
+    match status {
+        NO_ERROR => Ok(()),
+        // Already connected, possibly as another user; the write then shows whether that works
+        ERROR_SESSION_CREDENTIAL_CONFLICT => {
+            log::warn!("{} is already connected with other credentials", root);
+            Ok(())
+        }
+        status => Err(io::Error::from_raw_os_error(status as i32)),
+    }
+}
+
+/// Connect to a network share with the given credentials
+///
+/// Shares are mounted by the system on this platform, so the path is used as is.
+#[cfg(not(windows))]
+fn connect_share(_path: &str, _username: &str, _password: &str) -> io::Result<()> {
+    Ok(())
+}
+
+/// Format a host for a URL, leaving out the default port
+fn host_port(host: &str, port: u16, default_port: u16) -> String {
+    if port == default_port { host.to_string() } else { format!("{}:{}", host, port) }
+}
+
+/// Format a directory for a URL, with slashes on both ends
+fn url_directory(directory: &str) -> String {
+    let directory = directory.trim_matches('/');
+    if directory.is_empty() { "/".to_string() } else { format!("/{}/", directory) }
+}
+
+/// The control connection of an FTP session
+struct FtpControl {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl FtpControl {
+    /// Connect and read the server's greeting
+    fn connect(host: &str, port: u16) -> io::Result<Self> {
+        let stream = connect(host, port)?;
+        let mut control = Self { reader: BufReader::new(stream.try_clone()?), writer: stream };
+        control.read_expect(&[220])?;
+        Ok(control)
+    }
+
+    /// Log in, anonymously without a user name
+    fn login(&mut self, username: &str, password: Option<&str>) -> io::Result<()> {
+        let (username, password) = if username.is_empty() {
+            ("anonymous", "anonymous@")
+        } else {
+            (username, password.unwrap_or_default())
+        };
+        let (code, text) = self.command(&format!("USER {}", username))?;
+        match code {
+            230 => Ok(()),
+            331 => self.expect(&format!("PASS {}", password), &[230, 202]).map(drop),
+            _ => Err(reply_error(code, &text)),
+        }
+    }
+
+    /// Upload a file through a passive data connection
+    fn store(&mut self, name: &str, data: &[u8], progress: &mut dyn FnMut(u64)) -> io::Result<()> {
+        let text = self.expect("PASV", &[227])?;
+        let port = parse_passive_port(&text)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("FTP {}", text)))?;
+        // The advertised address is often private behind NAT, so the data connection goes to the same server
+        let address = SocketAddr::new(self.peer_ip()?, port);
+        let mut stream = TcpStream::connect_timeout(&address, NETWORK_TIMEOUT)?;
+        stream.set_write_timeout(Some(NETWORK_TIMEOUT))?;
+        self.expect(&format!("STOR {}", name), &[125, 150])?;
+        write_chunks(&mut stream, data, progress)?;
+        // Closing the data connection marks the end of the file
+        drop(stream);
+        self.read_expect(&[226, 250]).map(drop)
+    }
+
+    fn peer_ip(&self) -> io::Result<IpAddr> {
+        Ok(self.writer.peer_addr()?.ip())
+    }
+
+    /// Send a command and read the reply
+    fn command(&mut self, command: &str) -> io::Result<(u16, String)> {
+        self.writer.write_all(format!("{}\r\n", command).as_bytes())?;
+        self.read_reply()
+    }
+
+    /// Send a command, failing unless the reply has one of the accepted codes
+    fn expect(&mut self, command: &str, accepted: &[u16]) -> io::Result<String> {
+        let (code, text) = self.command(command)?;
+        if accepted.contains(&code) { Ok(text) } else { Err(reply_error(code, &text)) }
+    }
+
+    /// Read a reply, failing unless it has one of the accepted codes
+    fn read_expect(&mut self, accepted: &[u16]) -> io::Result<String> {
+        let (code, text) = self.read_reply()?;
+        if accepted.contains(&code) { Ok(text) } else { Err(reply_error(code, &text)) }
+    }
+
+    /// Read a reply, which may span several lines
+    fn read_reply(&mut self) -> io::Result<(u16, String)> {
+        let first = self.read_line()?;
+        let code: u16 = first
+            .get(..3)
+            .and_then(|code| code.parse().ok())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("FTP {}", first)))?;
+        let mut text = first.clone();
+        if first.as_bytes().get(3) == Some(&b'-') {
+            let last = format!("{} ", code);
+            loop {
+                let line = self.read_line()?;
+                text.push('\n');
+                text.push_str(&line);
+                if line.starts_with(&last) {
+                    break;
+                }
+            }
+        }
+        Ok((code, text))
+    }
+
+    fn read_line(&mut self) -> io::Result<String> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "FTP サーバーが接続を閉じました"));
+        }
+        Ok(line.trim_end().to_string())
+    }
+}
+
+/// Turn an FTP error reply into an I/O error of a matching kind
+fn reply_error(code: u16, text: &str) -> io::Error {
+    let kind = match code {
+        530 | 532 => io::ErrorKind::PermissionDenied,
+        // Transient negative replies, e.g. too many users
+        400..=499 => io::ErrorKind::ConnectionAborted,
+        _ => io::ErrorKind::Other,
+    };
+    io::Error::new(kind, format!("FTP {}", text))
+}
+
+/// Get the port of a `227 Entering Passive Mode (h1,h2,h3,h4,p1,p2)` reply
+fn parse_passive_port(text: &str) -> Option<u16> {
+    let start = text.find('(')?;
+    let end = text[start..].find(')')? + start;
+    let numbers: Vec<u16> = text[start + 1..end].split(',').map(|n| n.trim().parse().ok()).collect::<Option<_>>()?;
+    match numbers[..] {
+        [_, _, _, _, high, low] if high < 256 && low < 256 => Some(high * 256 + low),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    /// Serve one FTP upload, after refusing the first connection as busy
+    fn serve_ftp(listener: TcpListener) -> std::thread::JoinHandle<(Vec<String>, Vec<u8>)> {
+        std::thread::spawn(move || {
+            let (mut busy, _) = listener.accept().unwrap();
+            busy.write_all(b"421 Too many users\r\n").unwrap();
+            drop(busy);
+
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut writer = stream;
+            writer.write_all(b"220-Welcome\r\n220 Ready\r\n").unwrap();
+            let data_listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let data_port = data_listener.local_addr().unwrap().port();
+            let mut commands = Vec::new();
+            let mut received = Vec::new();
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap() == 0 {
+                    break;
+                }
+                let line = line.trim_end().to_string();
+                let verb = line.split(' ').next().unwrap().to_string();
+                commands.push(line);
+                let reply = match verb.as_str() {
+                    "USER" => "331 Password required".to_string(),
+                    "PASS" => "230 Logged in".to_string(),
+                    "TYPE" => "200 Binary".to_string(),
+                    "CWD" => "250 OK".to_string(),
+                    // The advertised address is ignored in favor of the control connection's
+                    "PASV" => format!("227 Entering Passive Mode (10,0,0,1,{},{})", data_port / 256, data_port % 256),
+                    "STOR" => {
+                        writer.write_all(b"150 Opening\r\n").unwrap();
+                        let (mut data, _) = data_listener.accept().unwrap();
+                        data.read_to_end(&mut received).unwrap();
+                        "226 Transfer complete".to_string()
+                    }
+                    "RNFR" => "350 Ready".to_string(),
+                    "RNTO" => "250 Renamed".to_string(),
+                    _ => {
+                        writer.write_all(b"221 Bye\r\n").unwrap();
+                        break;
+                    }
+                };
+                writer.write_all(format!("{}\r\n", reply).as_bytes()).unwrap();
+            }
+            (commands, received)
+        })
+    }
+
+    #[test]
+    fn test_ftp_upload_retries_busy_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = serve_ftp(listener);
+
+        let destination = SaveDestination::new(
+            "Team FTP",
+            RemoteLocation::Ftp {
+                host: "127.0.0.1".to_string(),
+                port,
+                username: "alice".to_string(),
+                directory: "shots".to_string(),
+            },
+        );
+        let data: Vec<u8> = (0..CHUNK_SIZE * 2 + 10).map(|i| i as u8).collect();
+        let policy = RetryPolicy { attempts: 3, initial_delay: Duration::from_millis(1) };
+        let mut reports = Vec::new();
+        let location =
+            upload_with_retry(&destination, Some("secret"), "a.png", &data, policy, &mut |sent| reports.push(sent))
+                .unwrap();
+        assert_eq!(location, format!("ftp://alice@127.0.0.1:{}/shots/a.png", port));
+
+        let (commands, received) = server.join().unwrap();
+        assert_eq!(received, data);
+        assert_eq!(&commands[..4], ["USER alice", "PASS secret", "TYPE I", "CWD shots"]);
+        // Uploaded under a temporary name, then renamed
+        let temp = commands[5].strip_prefix("STOR ").unwrap();
+        assert!(temp.starts_with(files::TEMP_FILE_PREFIX) && temp.ends_with("a.png"));
+        assert_eq!(commands[6], format!("RNFR {}", temp));
+        assert_eq!(commands[7], "RNTO a.png");
+        // Progress restarts with the second attempt
+        assert_eq!(reports, vec![0, 0, CHUNK_SIZE as u64, 2 * CHUNK_SIZE as u64, data.len() as u64]);
+    }
+
+    #[test]
+    fn test_validate_and_locations() {
+        let sftp = SaveDestination::new(
+            "Server",
+            RemoteLocation::Sftp {
+                host: "files.example.com".to_string(),
+                port: 2222,
+                username: "bob".to_string(),
+                directory: "/var/shots".to_string(),
+                fingerprint: String::new(),
+            },
+        );
+        assert!(sftp.validate().is_ok());
+        assert_eq!(sftp.file_location("a.png"), "sftp://bob@files.example.com:2222/var/shots/a.png");
+
+        let anonymous = SaveDestination::new(
+            "Public",
+            RemoteLocation::Ftp { host: "ftp.example.com".to_string(), port: FTP_PORT, username: String::new(), directory: String::new() },
+        );
+        assert!(!anonymous.uses_password());
+        assert_eq!(anonymous.file_location("a.png"), "ftp://ftp.example.com/a.png");
+        let injected = SaveDestination::new(
+            "Bad",
+            RemoteLocation::Ftp { host: "ftp.example.com".to_string(), port: FTP_PORT, username: "x\r\nDELE y".to_string(), directory: String::new() },
+        );
+        assert!(injected.validate().is_err());
+
+        let share = SaveDestination::new("Share", RemoteLocation::NetworkShare { path: PathBuf::from(r"\\nas\team\shots"), username: String::new() });
+        assert!(share.validate().is_ok());
+        let local = SaveDestination::new("Local", RemoteLocation::NetworkShare { path: PathBuf::from(r"C:\shots"), username: String::new() });
+        assert!(local.validate().is_err());
+        assert_eq!(share_root(r"\\nas\team\shots").as_deref(), Some(r"\\nas\team"));
+
+        assert!(validate_destinations(&[sftp.clone(), share.clone()]).is_ok());
+        assert!(validate_destinations(&[sftp.clone(), SaveDestination { name: " Server ".to_string(), ..share }]).is_err());
+        assert_eq!(parse_passive_port("227 Entering Passive Mode (192,168,1,2,19,137)."), Some(19 * 256 + 137));
+        assert_eq!(parse_passive_port("227 (1,2,3)"), None);
+    }
+
+    #[test]
+    fn test_network_share_upload_writes_file() {
+        let dir = std::env::temp_dir().join(format!("lsa-remote-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let share = SaveDestination::new("Share", RemoteLocation::NetworkShare { path: dir.clone(), username: String::new() });
+        let mut sent = 0;
+        upload_with_retry(&share, None, "a.png", b"image", RetryPolicy::default(), &mut |bytes| sent = bytes).unwrap();
+        assert_eq!(std::fs::read(dir.join("a.png")).unwrap(), b"image");
+        assert_eq!(sent, 5);
+        assert!(upload_with_retry(&share, None, "../a.png", b"image", RetryPolicy::default(), &mut |_| {}).is_err());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,1797 @@
+//! Annotation rendering and image flattening
+//!
+//! This module rasterizes annotations onto the source image to produce the
+//! final composite used by copy, save, and export operations. The composite
+//! is cached and only regenerated when the image or its annotations change.
+//! It also implements export decorations (padding, background, rounded
+//! corners, and drop shadow) applied around the composite, watermarks
+//! stamped onto it, and metadata banners added above or below it. Several
+//! captures can also be laid out together as a captioned collage.
+
+use crate::accessibility;
+use crate::decode::{self, DecodeLimits};
+use crate::measure;
+use crate::profiling::{self, Stage};
+use crate::text::{self, InstalledFont, ResolvedFace, TextFormat};
+use crate::types::{rotate_about, AnnotationItem, AnnotationType, AppError, AppResult, BlendMode};
+use egui::epaint::text::{FontFamily, Fonts, Galley};
+use egui::{Color32, FontId, Pos2, Rect, Vec2};
+use image::{DynamicImage, GrayImage, Luma, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::SystemTime;
+
+/// Maximum font atlas side used for offscreen text rasterization
+const FONT_ATLAS_MAX_SIDE: usize = 8192;
+
+/// Flatten annotations onto a copy of the source image
+pub fn flatten(image: &DynamicImage, annotations: &[AnnotationItem]) -> RgbaImage {
+    let _timer = profiling::scope(Stage::Flatten);
+    let mut canvas = image.to_rgba8();
+    for annotation in annotations {
+        draw_annotation(&mut canvas, annotation);
+    }
+    canvas
+}
+
+/// Flatten annotations onto the part of the image inside `region`
+///
+/// The region is clamped to whole pixels of the image. Returns the pixels
+/// and the region they cover, `None` if the region misses the image.
+pub fn flatten_region(image: &DynamicImage, annotations: &[AnnotationItem], region: Rect) -> Option<(RgbaImage, Rect)> {
+    let image_rect = Rect::from_min_size(Pos2::ZERO, Vec2::new(image.width() as f32, image.height() as f32));
+    let region = Rect::from_min_max(region.min.floor(), region.max.ceil()).intersect(image_rect);
+    if !region.is_positive() {
+        return None;
+    }
+    let mut canvas = image
+        .crop_imm(region.min.x as u32, region.min.y as u32, region.width() as u32, region.height() as u32)
+        .to_rgba8();
+    for annotation in annotations.iter().filter(|annotation| annotation_extent(annotation).intersects(region)) {
+        let shifted = AnnotationItem { position: annotation.position - region.min.to_vec2(), ..annotation.clone() };
+        draw_annotation(&mut canvas, &shifted);
+    }
+    Some((canvas, region))
+}
+
+/// Draw a single annotation onto the canvas in image pixel coordinates
+pub fn draw_annotation(canvas: &mut RgbaImage, annotation: &AnnotationItem) {
+    if annotation.opacity <= 0.0 {
+        return;
+    }
+    if annotation.is_rotated() || annotation.has_effects() {
+        draw_layered_annotation(canvas, annotation);
+    } else {
+        paint_annotation(canvas, annotation, Vec2::ZERO);
+    }
+}
+
+/// Draw an annotation unrotated, shifted by `-origin`
+fn paint_annotation(canvas: &mut RgbaImage, annotation: &AnnotationItem, origin: Vec2) {
+    let position = annotation.position - origin;
+    match &annotation.annotation_type {
+        AnnotationType::Rectangle { size, stroke_color, stroke_width, fill_color } => {
+            let rect = Rect::from_min_size(position, *size);
+            if let Some(fill) = fill_color {
+                fill_rect(canvas, rect, *fill);
+            }
+            stroke_rect(canvas, rect, *stroke_width, *stroke_color);
+        }
+        AnnotationType::Text { content, font_size, color, format } => {
+            draw_formatted_text(canvas, position, content, *font_size, *color, format);
+        }
+        AnnotationType::Image { data, size, opacity } => {
+            draw_image(canvas, Rect::from_min_size(position, *size), data, *opacity);
+        }
+        AnnotationType::Dimension { end, color, width, .. } => {
+            let (start, end) = (position, position + *end);
+            let [first, last] = measure::ticks(start, end);
+            draw_lines(canvas, &[[start, end], first, last], *width, *color);
+            let label = dimension_label(annotation);
+            let format = measure::label_format();
+            let size = measure_formatted_text(&label, measure::LABEL_FONT_SIZE, &format);
+            let rect = measure::label_rect(start, end, *width, size);
+            draw_formatted_text(canvas, rect.min, &label, measure::LABEL_FONT_SIZE, *color, &format);
+        }
+    }
+}
+
+/// Get the label of a dimension annotation, measuring the line as rotated
+pub fn dimension_label(annotation: &AnnotationItem) -> String {
+    match (&annotation.annotation_type, annotation.measured_line()) {
+        (AnnotationType::Dimension { pixels_per_inch, .. }, Some([start, end])) => {
+            measure::label(end - start, *pixels_per_inch)
+        }
+        _ => String::new(),
+    }
+}
+
+/// Area covered by an unrotated annotation, including the outer half of strokes
+fn painted_extent(annotation: &AnnotationItem) -> Rect {
+    match &annotation.annotation_type {
+        AnnotationType::Rectangle { size, stroke_width, .. } => {
+            Rect::from_min_size(annotation.position, *size).expand(stroke_width.max(0.0) * 0.5)
+        }
+        AnnotationType::Image { .. } => annotation.bounds(),
+        AnnotationType::Dimension { end, width, .. } => {
+            let (start, end) = (annotation.position, annotation.position + *end);
+            let label = dimension_label(annotation);
+            let size = measure_formatted_text(&label, measure::LABEL_FONT_SIZE, &measure::label_format());
+            Rect::from_two_pos(start, end)
+                .expand(measure::TICK_LENGTH * 0.5 + width.max(0.0))
+                .union(measure::label_rect(start, end, *width, size))
+        }
+        AnnotationType::Text { content, font_size, format, .. } => {
+            let outline = format.outline.map_or(0.0, |outline| outline.width.max(0.0) * 0.5);
+            Rect::from_min_size(annotation.position, measure_formatted_text(content, *font_size, format))
+                .expand(outline)
+                .union(annotation.bounds())
+        }
+    }
+}
+
+/// Area of the canvas an annotation can paint, after rotation
+pub fn annotation_extent(annotation: &AnnotationItem) -> Rect {
+    let extent = painted_extent(annotation).expand(1.0);
+    let center = annotation.bounds().center();
+    let corners = [extent.left_top(), extent.right_top(), extent.right_bottom(), extent.left_bottom()]
+        .map(|corner| rotate_about(corner, center, annotation.rotation));
+    Rect::from_points(&corners)
+}
+
+/// Draw an annotation by painting it onto a layer and compositing the layer
+///
+/// Rotated annotations sample the layer rotated and bilinearly, so their
+/// edges are anti-aliased at the image's full resolution, before any export
+/// resizing. Overlapping parts of the annotation (a fill under its stroke)
+/// are combined on the layer first, so opacity and blending apply to the
+/// annotation as a whole.
+fn draw_layered_annotation(canvas: &mut RgbaImage, annotation: &AnnotationItem) {
+    let extent = painted_extent(annotation).expand(1.0);
+    let origin = extent.min.floor();
+    let layer_size = (extent.max - origin).ceil();
+    let mut layer = RgbaImage::new(layer_size.x as u32, layer_size.y as u32);
+    paint_annotation(&mut layer, annotation, origin.to_vec2());
+
+    let center = annotation.bounds().center();
+    let corners = [extent.left_top(), extent.right_top(), extent.right_bottom(), extent.left_bottom()]
+        .map(|corner| rotate_about(corner, center, annotation.rotation));
+    let opacity = annotation.opacity.clamp(0.0, 1.0);
+    for_each_pixel_in(canvas, Rect::from_points(&corners), |canvas, x, y| {
+        let pixel_center = Pos2::new(x as f32 + 0.5, y as f32 + 0.5);
+        let source = rotate_about(pixel_center, center, -annotation.rotation) - origin.to_vec2();
+        let [r, g, b, a] = sample_premultiplied(&layer, source);
+        if a <= 0.0 {
+            return;
+        }
+        let unmultiply = |channel: f32| (channel * 255.0 / a).round().clamp(0.0, 255.0) as u8;
+        let color = Color32::from_rgba_unmultiplied(unmultiply(r), unmultiply(g), unmultiply(b), 255);
+        blend_pixel_with(canvas, x, y, color, a / 255.0 * opacity, annotation.blend_mode);
+    });
+}
+
+/// Bilinearly sample a layer at a position, treating pixels outside it as transparent
+///
+/// The layer was painted onto transparent black, so its colors are already
+/// premultiplied by alpha; the result is premultiplied too.
+fn sample_premultiplied(layer: &RgbaImage, position: Pos2) -> [f32; 4] {
+    let x = position.x - 0.5;
+    let y = position.y - 0.5;
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+
+    let mut sum = [0.0; 4];
+    for (dx, dy, weight) in [
+        (0.0, 0.0, (1.0 - fx) * (1.0 - fy)),
+        (1.0, 0.0, fx * (1.0 - fy)),
+        (0.0, 1.0, (1.0 - fx) * fy),
+        (1.0, 1.0, fx * fy),
+    ] {
+        let (px, py) = (x0 + dx, y0 + dy);
+        if weight <= 0.0 || px < 0.0 || py < 0.0 || px >= layer.width() as f32 || py >= layer.height() as f32 {
+            continue;
+        }
+        let pixel = layer.get_pixel(px as u32, py as u32).0;
+        for (total, channel) in sum.iter_mut().zip(pixel) {
+            *total += channel as f32 * weight;
+        }
+    }
+    sum
+}
+
+/// Draw a rectangle outline centered on the rectangle edges (matching egui's `rect_stroke`)
+pub fn stroke_rect(canvas: &mut RgbaImage, rect: Rect, width: f32, color: Color32) {
+    if width <= 0.0 {
+        return;
+    }
+
+    let outer = rect.expand(width * 0.5);
+    let inner = rect.shrink(width * 0.5);
+
+    for_each_pixel_in(canvas, outer, |canvas, x, y| {
+        let center = Pos2::new(x as f32 + 0.5, y as f32 + 0.5);
+        if !(inner.is_positive() && inner.contains(center)) {
+            blend_pixel(canvas, x, y, color, 1.0);
+        }
+    });
+}
+
+/// Draw anti-aliased line segments with round ends
+///
+/// Pixels covered by several segments are blended once, so joints are not darker.
+pub fn draw_lines(canvas: &mut RgbaImage, segments: &[[Pos2; 2]], width: f32, color: Color32) {
+    if width <= 0.0 || segments.is_empty() {
+        return;
+    }
+    let radius = width * 0.5;
+    let area = Rect::from_points(&segments.concat()).expand(radius + 1.0);
+    for_each_pixel_in(canvas, area, |canvas, x, y| {
+        let pixel_center = Pos2::new(x as f32 + 0.5, y as f32 + 0.5);
+        let distance = segments
+            .iter()
+            .map(|&[a, b]| distance_to_segment(pixel_center, a, b))
+            .fold(f32::MAX, f32::min);
+        let coverage = (radius + 0.5 - distance).clamp(0.0, 1.0);
+        if coverage > 0.0 {
+            blend_pixel(canvas, x, y, color, coverage);
+        }
+    });
+}
+
+fn distance_to_segment(point: Pos2, a: Pos2, b: Pos2) -> f32 {
+    let segment = b - a;
+    let length_sq = segment.length_sq();
+    let t = if length_sq > 0.0 { ((point - a).dot(segment) / length_sq).clamp(0.0, 1.0) } else { 0.0 };
+    point.distance(a + segment * t)
+}
+
+/// Fill a rectangle with a solid color
+pub fn fill_rect(canvas: &mut RgbaImage, rect: Rect, color: Color32) {
+    for_each_pixel_in(canvas, rect, |canvas, x, y| {
+        blend_pixel(canvas, x, y, color, 1.0);
+    });
+}
+
+/// Draw a PNG-encoded image scaled to fill `rect`
+///
+/// Image data comes from project files and the clipboard, so it is decoded
+/// with the untrusted-input limits. Data that fails to decode is skipped.
+pub fn draw_image(canvas: &mut RgbaImage, rect: Rect, data: &[u8], opacity: f32) {
+    let width = rect.width().round() as u32;
+    let height = rect.height().round() as u32;
+    if width == 0 || height == 0 || opacity <= 0.0 {
+        return;
+    }
+    let image = match decode::decode_untrusted(data.to_vec(), DecodeLimits::default()) {
+        Ok(image) => image.to_rgba8(),
+        Err(e) => {
+            log::warn!("Skipping image annotation that failed to decode: {}", e);
+            return;
+        }
+    };
+    let scaled = if (width, height) == image.dimensions() {
+        image
+    } else {
+        image::imageops::resize(&image, width, height, image::imageops::FilterType::Triangle)
+    };
+
+    let scale = Vec2::new(width as f32 / rect.width(), height as f32 / rect.height());
+    for_each_pixel_in(canvas, rect, |canvas, x, y| {
+        let source = (Pos2::new(x as f32 + 0.5, y as f32 + 0.5) - rect.min) * scale;
+        let source_x = (source.x as u32).min(width - 1);
+        let source_y = (source.y as u32).min(height - 1);
+        let [r, g, b, a] = scaled.get_pixel(source_x, source_y).0;
+        blend_pixel(canvas, x, y, Color32::from_rgba_unmultiplied(r, g, b, a), opacity);
+    });
+}
+
+/// Draw text with its top-left corner at `position`
+pub fn draw_text(canvas: &mut RgbaImage, position: Pos2, content: &str, font_size: f32, color: Color32) {
+    if content.is_empty() || font_size <= 0.0 {
+        return;
+    }
+
+    let fonts = text_fonts();
+    fonts.begin_frame(1.0, FONT_ATLAS_MAX_SIDE);
+    let galley = fonts.layout_no_wrap(content.to_owned(), FontId::proportional(font_size), color);
+    let atlas = fonts.image();
+
+    for row in &galley.rows {
+        for glyph in &row.glyphs {
+            let uv = glyph.uv_rect;
+            if uv.is_nothing() {
+                continue;
+            }
+
+            let left_top = position + glyph.pos.to_vec2() + uv.offset;
+            let origin_x = left_top.x.round() as i64;
+            let origin_y = left_top.y.round() as i64;
+
+            for ty in uv.min[1]..uv.max[1] {
+                for tx in uv.min[0]..uv.max[0] {
+                    let coverage = atlas.pixels[ty as usize * atlas.size[0] + tx as usize];
+                    if coverage <= 0.0 {
+                        continue;
+                    }
+
+                    let x = origin_x + (tx - uv.min[0]) as i64;
+                    let y = origin_y + (ty - uv.min[1]) as i64;
+                    if x >= 0 && y >= 0 && (x as u32) < canvas.width() && (y as u32) < canvas.height() {
+                        blend_pixel(canvas, x as u32, y as u32, color, coverage);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Measure the size of text as it would be drawn by [`draw_text`]
+pub fn measure_text(content: &str, font_size: f32) -> Vec2 {
+    let fonts = text_fonts();
+    fonts.begin_frame(1.0, FONT_ATLAS_MAX_SIDE);
+    fonts
+        .layout_no_wrap(content.to_owned(), FontId::proportional(font_size), Color32::WHITE)
+        .size()
+}
+
+/// Text laid out for drawing as a text annotation
+struct FormattedLayout {
+    fonts: &'static Fonts,
+    galley: Arc<Galley>,
+    face: ResolvedFace,
+    box_size: Vec2,
+}
+
+impl FormattedLayout {
+    fn new(content: &str, font_size: f32, color: Color32, format: &TextFormat) -> Self {
+        let face = text::resolve_face(format, text::installed_fonts());
+        let (fonts, family) = face
+            .face
+            .as_ref()
+            .and_then(|font| Some((face_fonts(font)?, FontFamily::Name(font.name.as_str().into()))))
+            .unwrap_or_else(|| (text_fonts(), FontFamily::Proportional));
+        fonts.begin_frame(1.0, FONT_ATLAS_MAX_SIDE);
+        let galley = fonts.layout_job(text::layout_job(content, font_size, color, format, family, false, 1.0));
+        let text_size = galley.size() + Vec2::new(face.extra_width(font_size), 0.0);
+        let box_size = text::box_size(text_size, font_size, format);
+        Self { fonts, galley, face, box_size }
+    }
+}
+
+/// Draw a text annotation with the top-left corner of its box at `position`
+///
+/// Styles the font has no face for are synthesized: italics by shearing
+/// each glyph like egui does on screen, and bold by drawing the glyphs a
+/// second time offset to the right.
+pub fn draw_formatted_text(
+    canvas: &mut RgbaImage,
+    position: Pos2,
+    content: &str,
+    font_size: f32,
+    color: Color32,
+    format: &TextFormat,
+) {
+    if font_size <= 0.0 {
+        return;
+    }
+
+    let layout = FormattedLayout::new(content, font_size, color, format);
+    let bounds = Rect::from_min_size(position, layout.box_size);
+    if let Some(background) = format.background {
+        fill_rect(canvas, bounds, background);
+    }
+    if let Some(outline) = format.outline {
+        stroke_rect(canvas, bounds, outline.width, outline.color);
+    }
+    if content.is_empty() {
+        return;
+    }
+
+    // Coverage is collected first so pixels covered twice by bold glyphs are blended once
+    let area = bounds.expand(font_size);
+    let (mask_x, mask_y) = (area.min.x.floor() as i64, area.min.y.floor() as i64);
+    let mask_width = area.width().ceil() as i64 + 1;
+    let mask_height = area.height().ceil() as i64 + 1;
+    let mut mask = vec![0.0f32; (mask_width * mask_height) as usize];
+
+    let bold_offset = if layout.face.synthetic_bold { text::bold_offset(font_size) } else { 0.0 };
+    let anchor = position + text::text_anchor(layout.box_size, font_size, format);
+    let atlas = layout.fonts.image();
+    for row in &layout.galley.rows {
+        for glyph in &row.glyphs {
+            let uv = glyph.uv_rect;
+            if uv.is_nothing() {
+                continue;
+            }
+
+            let left_top = anchor + glyph.pos.to_vec2() + uv.offset;
+            let height = (uv.max[1] - uv.min[1]) as f32;
+            for ty in uv.min[1]..uv.max[1] {
+                let dy = (ty - uv.min[1]) as f32;
+                let shear = if layout.face.synthetic_italic { text::ITALIC_SHEAR * (height - dy) } else { 0.0 };
+                for tx in uv.min[0]..uv.max[0] {
+                    let coverage = atlas.pixels[ty as usize * atlas.size[0] + tx as usize];
+                    if coverage <= 0.0 {
+                        continue;
+                    }
+
+                    let x = left_top.x + (tx - uv.min[0]) as f32 + shear;
+                    let y = (left_top.y + dy).round() as i64 - mask_y;
+                    let first = x.round() as i64 - mask_x;
+                    let last = (x + bold_offset).round() as i64 - mask_x;
+                    if y < 0 || y >= mask_height {
+                        continue;
+                    }
+                    for mx in first.max(0)..=last.min(mask_width - 1) {
+                        let cell = &mut mask[(y * mask_width + mx) as usize];
+                        *cell = cell.max(coverage);
+                    }
+                }
+            }
+        }
+    }
+
+    for (index, &coverage) in mask.iter().enumerate() {
+        if coverage <= 0.0 {
+            continue;
+        }
+        let x = mask_x + index as i64 % mask_width;
+        let y = mask_y + index as i64 / mask_width;
+        if x >= 0 && y >= 0 && (x as u32) < canvas.width() && (y as u32) < canvas.height() {
+            blend_pixel(canvas, x as u32, y as u32, color, coverage);
+        }
+    }
+}
+
+/// Get the lines of a text annotation as wrapped by [`draw_formatted_text`], and the size of its box
+pub fn formatted_text_lines(content: &str, font_size: f32, format: &TextFormat) -> (Vec<String>, Vec2) {
+    let layout = FormattedLayout::new(content, font_size, Color32::WHITE, format);
+    let lines = layout
+        .galley
+        .rows
+        .iter()
+        .map(|row| {
+            let line: String = row.glyphs.iter().map(|glyph| glyph.chr).collect();
+            // Wrapped lines keep the space they were broken at
+            if row.ends_with_newline { line } else { line.trim_end().to_string() }
+        })
+        .collect();
+    (lines, layout.box_size)
+}
+
+/// Measure the box of a text annotation as it would be drawn by [`draw_formatted_text`]
+pub fn measure_formatted_text(content: &str, font_size: f32, format: &TextFormat) -> Vec2 {
+    FormattedLayout::new(content, font_size, Color32::WHITE, format).box_size
+}
+
+/// Build the offscreen font set ahead of time
+///
+/// Font loading takes tens of milliseconds, so this is meant to be called on
+/// a background thread at startup rather than on the first text render.
+pub fn preload_fonts() {
+    measure_text("Text", 14.0);
+}
+
+/// Alpha-blend a color onto a single pixel with the given coverage (0.0-1.0)
+pub fn blend_pixel(canvas: &mut RgbaImage, x: u32, y: u32, color: Color32, coverage: f32) {
+    blend_pixel_with(canvas, x, y, color, coverage, BlendMode::Normal);
+}
+
+/// Blend a color onto a single pixel with the given coverage (0.0-1.0) and blend mode
+pub fn blend_pixel_with(canvas: &mut RgbaImage, x: u32, y: u32, color: Color32, coverage: f32, mode: BlendMode) {
+    let [r, g, b, a] = color.to_srgba_unmultiplied();
+    let alpha = (a as f32 / 255.0) * coverage.clamp(0.0, 1.0);
+    if alpha <= 0.0 {
+        return;
+    }
+
+    let pixel = canvas.get_pixel_mut(x, y);
+    let dst = pixel.0;
+    let blend = |src: u8, dst: u8| -> u8 {
+        let src = mode.blend(src as f32, dst as f32);
+        (src * alpha + dst as f32 * (1.0 - alpha)).round().clamp(0.0, 255.0) as u8
+    };
+
+    pixel.0 = [
+        blend(r, dst[0]),
+        blend(g, dst[1]),
+        blend(b, dst[2]),
+        ((alpha + dst[3] as f32 / 255.0 * (1.0 - alpha)) * 255.0).round() as u8,
+    ];
+}
+
+/// Invoke `f` for every canvas pixel whose center lies inside `rect`
+fn for_each_pixel_in(canvas: &mut RgbaImage, rect: Rect, mut f: impl FnMut(&mut RgbaImage, u32, u32)) {
+    let min_x = (rect.min.x - 0.5).ceil().max(0.0) as u32;
+    let min_y = (rect.min.y - 0.5).ceil().max(0.0) as u32;
+    let max_x = ((rect.max.x - 0.5).ceil().max(0.0) as u32).min(canvas.width());
+    let max_y = ((rect.max.y - 0.5).ceil().max(0.0) as u32).min(canvas.height());
+
+    for y in min_y..max_y {
+        for x in min_x..max_x {
+            f(canvas, x, y);
+        }
+    }
+}
+
+/// Background drawn behind a decorated screenshot
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum DecorationBackground {
+    Solid(Color32),
+    /// Diagonal gradient from the top-left to the bottom-right corner
+    Gradient { start: Color32, end: Color32 },
+}
+
+impl DecorationBackground {
+    /// Get the background color at a pixel
+    fn color_at(&self, x: u32, y: u32, width: u32, height: u32) -> Color32 {
+        match *self {
+            DecorationBackground::Solid(color) => color,
+            DecorationBackground::Gradient { start, end } => {
+                let span = (width + height).saturating_sub(2).max(1) as f32;
+                let t = (x + y) as f32 / span;
+                let lerp = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+                let [r0, g0, b0, a0] = start.to_srgba_unmultiplied();
+                let [r1, g1, b1, a1] = end.to_srgba_unmultiplied();
+                Color32::from_rgba_unmultiplied(lerp(r0, r1), lerp(g0, g1), lerp(b0, b1), lerp(a0, a1))
+            }
+        }
+    }
+}
+
+/// Drop shadow cast by a decorated screenshot
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DropShadow {
+    pub offset: Vec2,
+    /// Gaussian blur radius (sigma) in pixels
+    pub blur: f32,
+    pub color: Color32,
+}
+
+impl Default for DropShadow {
+    fn default() -> Self {
+        Self {
+            offset: Vec2::new(0.0, 8.0),
+            blur: 12.0,
+            color: Color32::from_black_alpha(110),
+        }
+    }
+}
+
+/// Decoration applied around the flattened screenshot on export
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Decoration {
+    /// Background padding on every side, in pixels
+    pub padding: u32,
+    pub background: DecorationBackground,
+    /// Corner radius of the screenshot, in pixels
+    pub corner_radius: f32,
+    pub shadow: Option<DropShadow>,
+}
+
+impl Default for Decoration {
+    fn default() -> Self {
+        Self {
+            padding: 48,
+            background: DecorationBackground::Gradient {
+                start: Color32::from_rgb(99, 102, 241),
+                end: Color32::from_rgb(236, 72, 153),
+            },
+            corner_radius: 10.0,
+            shadow: Some(DropShadow::default()),
+        }
+    }
+}
+
+impl Decoration {
+    /// Validate decoration values
+    pub fn validate(&self) -> AppResult<()> {
+        if self.corner_radius < 0.0 || !self.corner_radius.is_finite() {
+            return Err(AppError::Settings("角の半径は0以上で指定してください".to_string()));
+        }
+        if let Some(shadow) = self.shadow {
+            if shadow.blur < 0.0 || !shadow.blur.is_finite() {
+                return Err(AppError::Settings("影のぼかしは0以上で指定してください".to_string()));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Wrap an image with padding, background, rounded corners, and a drop shadow
+pub fn decorate(image: &RgbaImage, decoration: &Decoration) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    let padding = decoration.padding;
+    let out_width = width + padding * 2;
+    let out_height = height + padding * 2;
+    let radius = decoration.corner_radius.min(width.min(height) as f32 / 2.0);
+
+    let mut canvas = RgbaImage::from_fn(out_width, out_height, |x, y| {
+        image::Rgba(decoration.background.color_at(x, y, out_width, out_height).to_srgba_unmultiplied())
+    });
+
+    if let Some(shadow) = decoration.shadow {
+        draw_shadow(&mut canvas, padding, (width, height), radius, &shadow);
+    }
+
+    for (x, y, pixel) in image.enumerate_pixels() {
+        let coverage = rounded_rect_coverage(x, y, width, height, radius);
+        if coverage > 0.0 {
+            let [r, g, b, a] = pixel.0;
+            blend_pixel(&mut canvas, x + padding, y + padding, Color32::from_rgba_unmultiplied(r, g, b, a), coverage);
+        }
+    }
+
+    canvas
+}
+
+/// Draw a blurred rounded-rectangle shadow for an image placed at `padding`
+fn draw_shadow(canvas: &mut RgbaImage, padding: u32, size: (u32, u32), radius: f32, shadow: &DropShadow) {
+    let (width, height) = size;
+    let (canvas_width, canvas_height) = canvas.dimensions();
+    let origin_x = padding as f32 + shadow.offset.x;
+    let origin_y = padding as f32 + shadow.offset.y;
+
+    let mut mask = GrayImage::new(canvas_width, canvas_height);
+    for y in 0..height {
+        for x in 0..width {
+            let target_x = (origin_x + x as f32).round() as i64;
+            let target_y = (origin_y + y as f32).round() as i64;
+            if target_x < 0 || target_y < 0 || target_x >= canvas_width as i64 || target_y >= canvas_height as i64 {
+                continue;
+            }
+            let coverage = rounded_rect_coverage(x, y, width, height, radius);
+            mask.put_pixel(target_x as u32, target_y as u32, Luma([(coverage * 255.0).round() as u8]));
+        }
+    }
+
+    if shadow.blur > 0.0 {
+        mask = image::imageops::blur(&mask, shadow.blur);
+    }
+
+    for (x, y, value) in mask.enumerate_pixels() {
+        if value.0[0] > 0 {
+            blend_pixel(canvas, x, y, shadow.color, value.0[0] as f32 / 255.0);
+        }
+    }
+}
+
+/// Anti-aliased coverage of pixel (x, y) inside a rounded rectangle of the given size
+fn rounded_rect_coverage(x: u32, y: u32, width: u32, height: u32, radius: f32) -> f32 {
+    if radius <= 0.0 {
+        return 1.0;
+    }
+
+    let px = x as f32 + 0.5;
+    let py = y as f32 + 0.5;
+    let dx = (radius - px).max(px - (width as f32 - radius)).max(0.0);
+    let dy = (radius - py).max(py - (height as f32 - radius)).max(0.0);
+    if dx == 0.0 || dy == 0.0 {
+        return 1.0;
+    }
+
+    let distance = (dx * dx + dy * dy).sqrt();
+    (radius - distance + 0.5).clamp(0.0, 1.0)
+}
+
+/// A named, user-editable decoration
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DecorationPreset {
+    pub name: String,
+    pub decoration: Decoration,
+}
+
+/// Decoration presets persisted with the export options
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DecorationSettings {
+    pub presets: Vec<DecorationPreset>,
+    /// Preset applied on export, or `None` to export undecorated
+    pub active_preset: Option<String>,
+}
+
+impl Default for DecorationSettings {
+    fn default() -> Self {
+        Self {
+            presets: vec![
+                DecorationPreset {
+                    name: "Gradient".to_string(),
+                    decoration: Decoration::default(),
+                },
+                DecorationPreset {
+                    name: "Simple Border".to_string(),
+                    decoration: Decoration {
+                        padding: 16,
+                        background: DecorationBackground::Solid(Color32::WHITE),
+                        corner_radius: 0.0,
+                        shadow: None,
+                    },
+                },
+            ],
+            active_preset: None,
+        }
+    }
+}
+
+impl DecorationSettings {
+    /// Get the decoration applied on export, if any
+    pub fn active(&self) -> Option<&Decoration> {
+        self.active_preset
+            .as_deref()
+            .and_then(|name| self.preset(name))
+            .map(|preset| &preset.decoration)
+    }
+
+    /// Find a preset by name
+    pub fn preset(&self, name: &str) -> Option<&DecorationPreset> {
+        self.presets.iter().find(|preset| preset.name == name)
+    }
+
+    /// Save a preset, replacing any existing preset with the same name
+    pub fn save_preset(&mut self, name: &str, decoration: Decoration) -> AppResult<()> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(AppError::Settings("プリセット名を入力してください".to_string()));
+        }
+        decoration.validate()?;
+
+        match self.presets.iter_mut().find(|preset| preset.name == name) {
+            Some(existing) => existing.decoration = decoration,
+            None => self.presets.push(DecorationPreset {
+                name: name.to_string(),
+                decoration,
+            }),
+        }
+        Ok(())
+    }
+
+    /// Remove a preset by name, deactivating it if it was active
+    pub fn remove_preset(&mut self, name: &str) -> bool {
+        let before = self.presets.len();
+        self.presets.retain(|preset| preset.name != name);
+        if self.active_preset.as_deref() == Some(name) {
+            self.active_preset = None;
+        }
+        self.presets.len() != before
+    }
+
+    /// Activate a preset by name, or disable decoration with `None`
+    pub fn set_active_preset(&mut self, name: Option<&str>) -> AppResult<()> {
+        if let Some(name) = name {
+            if self.preset(name).is_none() {
+                return Err(AppError::Settings(format!("プリセットが見つかりません: {}", name)));
+            }
+        }
+        self.active_preset = name.map(str::to_string);
+        Ok(())
+    }
+
+    /// Validate all presets
+    pub fn validate(&self) -> AppResult<()> {
+        for preset in &self.presets {
+            preset.decoration.validate()?;
+        }
+        Ok(())
+    }
+}
+
+/// Where a watermark is placed on the image
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum WatermarkAnchor {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    #[default]
+    BottomRight,
+    Center,
+}
+
+impl WatermarkAnchor {
+    /// Get all anchors in display order
+    pub fn all() -> Vec<WatermarkAnchor> {
+        vec![
+            WatermarkAnchor::TopLeft,
+            WatermarkAnchor::TopRight,
+            WatermarkAnchor::BottomLeft,
+            WatermarkAnchor::BottomRight,
+            WatermarkAnchor::Center,
+        ]
+    }
+
+    /// Top-left position of an item of `size` anchored in a canvas of `canvas_size`
+    pub fn position(&self, canvas_size: Vec2, size: Vec2, margin: f32) -> Pos2 {
+        let right = canvas_size.x - size.x - margin;
+        let bottom = canvas_size.y - size.y - margin;
+        match self {
+            WatermarkAnchor::TopLeft => Pos2::new(margin, margin),
+            WatermarkAnchor::TopRight => Pos2::new(right, margin),
+            WatermarkAnchor::BottomLeft => Pos2::new(margin, bottom),
+            WatermarkAnchor::BottomRight => Pos2::new(right, bottom),
+            WatermarkAnchor::Center => ((canvas_size - size) / 2.0).to_pos2(),
+        }
+    }
+}
+
+impl std::fmt::Display for WatermarkAnchor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WatermarkAnchor::TopLeft => write!(f, "Top left"),
+            WatermarkAnchor::TopRight => write!(f, "Top right"),
+            WatermarkAnchor::BottomLeft => write!(f, "Bottom left"),
+            WatermarkAnchor::BottomRight => write!(f, "Bottom right"),
+            WatermarkAnchor::Center => write!(f, "Center"),
+        }
+    }
+}
+
+/// What a watermark shows
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum WatermarkContent {
+    Text { text: String, font_size: f32, color: Color32 },
+    /// PNG logo loaded from disk at export time
+    Image { path: PathBuf },
+}
+
+/// Watermark stamped onto every exported image
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Watermark {
+    pub enabled: bool,
+    pub content: WatermarkContent,
+    pub anchor: WatermarkAnchor,
+    /// Distance from the anchored edges, in pixels
+    pub margin: u32,
+    /// Scale factor applied to the text size or logo dimensions
+    pub scale: f32,
+    /// Opacity (0.0-1.0)
+    pub opacity: f32,
+}
+
+impl Default for Watermark {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            content: WatermarkContent::Text {
+                text: String::new(),
+                font_size: 24.0,
+                color: Color32::WHITE,
+            },
+            anchor: WatermarkAnchor::default(),
+            margin: 16,
+            scale: 1.0,
+            opacity: 0.6,
+        }
+    }
+}
+
+impl Watermark {
+    /// Validate watermark values
+    pub fn validate(&self) -> AppResult<()> {
+        if !(self.scale > 0.0 && self.scale.is_finite()) {
+            return Err(AppError::Settings("透かしの倍率は正の値である必要があります".to_string()));
+        }
+        if !(0.0..=1.0).contains(&self.opacity) {
+            return Err(AppError::Settings("不透明度は0.0から1.0の範囲で指定してください".to_string()));
+        }
+        if let WatermarkContent::Text { font_size, .. } = self.content {
+            if font_size <= 0.0 {
+                return Err(AppError::Settings("線幅とフォントサイズは正の値である必要があります".to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Load the logo image for image watermarks
+    pub fn load_logo(&self) -> AppResult<Option<RgbaImage>> {
+        match self.content {
+            WatermarkContent::Image { ref path } => {
+                let logo = crate::decode::decode_untrusted_file(path, crate::decode::DecodeLimits::default())?;
+                Ok(Some(logo.to_rgba8()))
+            }
+            WatermarkContent::Text { .. } => Ok(None),
+        }
+    }
+}
+
+/// Stamp a watermark onto the canvas
+///
+/// `logo` is the decoded logo for image watermarks (see [`Watermark::load_logo`]).
+pub fn apply_watermark(canvas: &mut RgbaImage, watermark: &Watermark, logo: Option<&RgbaImage>) {
+    if !watermark.enabled || watermark.opacity <= 0.0 {
+        return;
+    }
+
+    let canvas_size = Vec2::new(canvas.width() as f32, canvas.height() as f32);
+    let margin = watermark.margin as f32;
+
+    match watermark.content {
+        WatermarkContent::Text { ref text, font_size, color } => {
+            let font_size = font_size * watermark.scale;
+            if text.is_empty() || font_size <= 0.0 {
+                return;
+            }
+            let size = measure_text(text, font_size);
+            let position = watermark.anchor.position(canvas_size, size, margin);
+            draw_text(canvas, position, text, font_size, color.gamma_multiply(watermark.opacity));
+        }
+        WatermarkContent::Image { .. } => {
+            let Some(logo) = logo else {
+                return;
+            };
+            let width = ((logo.width() as f32 * watermark.scale).round() as u32).max(1);
+            let height = ((logo.height() as f32 * watermark.scale).round() as u32).max(1);
+            let scaled = if (width, height) == logo.dimensions() {
+                logo.clone()
+            } else {
+                image::imageops::resize(logo, width, height, image::imageops::FilterType::Lanczos3)
+            };
+
+            let position = watermark
+                .anchor
+                .position(canvas_size, Vec2::new(width as f32, height as f32), margin);
+            let (origin_x, origin_y) = (position.x.round() as i64, position.y.round() as i64);
+            for (x, y, pixel) in scaled.enumerate_pixels() {
+                let target_x = origin_x + x as i64;
+                let target_y = origin_y + y as i64;
+                if target_x < 0 || target_y < 0 || target_x >= canvas.width() as i64 || target_y >= canvas.height() as i64 {
+                    continue;
+                }
+                let [r, g, b, a] = pixel.0;
+                blend_pixel(
+                    canvas,
+                    target_x as u32,
+                    target_y as u32,
+                    Color32::from_rgba_unmultiplied(r, g, b, a),
+                    watermark.opacity,
+                );
+            }
+        }
+    }
+}
+
+/// Edge of the image a metadata banner is attached to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum BannerPosition {
+    Top,
+    #[default]
+    Bottom,
+}
+
+/// Strip with capture metadata added outside the image content
+///
+/// Used by QA and compliance workflows that must show when and where a
+/// capture was taken without covering any of it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MetadataBanner {
+    pub enabled: bool,
+    pub position: BannerPosition,
+    pub show_timestamp: bool,
+    pub show_machine_name: bool,
+    /// Additional text, e.g. a ticket or test case number
+    pub custom_text: String,
+    pub font_size: f32,
+    /// Space around the text, in pixels
+    pub padding: u32,
+    pub background: Color32,
+    pub text_color: Color32,
+}
+
+impl Default for MetadataBanner {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            position: BannerPosition::default(),
+            show_timestamp: true,
+            show_machine_name: true,
+            custom_text: String::new(),
+            font_size: 14.0,
+            padding: 6,
+            background: Color32::from_rgb(32, 32, 32),
+            text_color: Color32::WHITE,
+        }
+    }
+}
+
+impl MetadataBanner {
+    /// Build the banner text for a capture
+    pub fn text(&self, captured_at: SystemTime, machine_name: &str) -> String {
+        let mut parts = Vec::new();
+        if self.show_timestamp {
+            parts.push(crate::datetime::DateTime::from_system_time(captured_at).to_string());
+        }
+        if self.show_machine_name && !machine_name.is_empty() {
+            parts.push(machine_name.to_string());
+        }
+        if !self.custom_text.trim().is_empty() {
+            parts.push(self.custom_text.trim().to_string());
+        }
+        parts.join("  |  ")
+    }
+
+    /// Validate banner values
+    pub fn validate(&self) -> AppResult<()> {
+        if !(self.font_size > 0.0 && self.font_size.is_finite()) {
+            return Err(AppError::Settings("線幅とフォントサイズは正の値である必要があります".to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// Get the name of this machine for metadata banners
+pub fn machine_name() -> String {
+    std::env::var("COMPUTERNAME")
+        .or_else(|_| std::env::var("HOSTNAME"))
+        .ok()
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .map(|name| name.trim().to_string())
+        .unwrap_or_default()
+}
+
+/// Add a metadata banner above or below the image
+pub fn apply_banner(image: &RgbaImage, banner: &MetadataBanner, captured_at: SystemTime) -> RgbaImage {
+    let text = banner.text(captured_at, &machine_name());
+    if !banner.enabled || text.is_empty() {
+        return image.clone();
+    }
+
+    let text_size = measure_text(&text, banner.font_size);
+    let banner_height = text_size.y.ceil() as u32 + banner.padding * 2;
+    let (width, height) = image.dimensions();
+    let [r, g, b, a] = banner.background.to_srgba_unmultiplied();
+    let mut output = RgbaImage::from_pixel(width, height + banner_height, image::Rgba([r, g, b, a]));
+
+    let (image_y, banner_y) = match banner.position {
+        BannerPosition::Top => (banner_height, 0),
+        BannerPosition::Bottom => (0, height),
+    };
+    image::imageops::replace(&mut output, image, 0, image_y as i64);
+    let text_position = Pos2::new(banner.padding as f32, (banner_y + banner.padding) as f32);
+    draw_text(&mut output, text_position, &text, banner.font_size, banner.text_color);
+    output
+}
+
+/// Gap between an image in a collage and its caption
+const CAPTION_GAP: u32 = 4;
+
+/// Grid layout and look of a collage of several captures
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CollageOptions {
+    /// Images per row
+    pub columns: u32,
+    /// Width of each cell; wider images are scaled down to fit
+    pub cell_width: u32,
+    /// Gap between cells and around the edges, in pixels
+    pub spacing: u32,
+    pub background: DecorationBackground,
+    /// Whether each image is captioned with its name
+    pub captions: bool,
+    pub caption_size: f32,
+}
+
+impl Default for CollageOptions {
+    fn default() -> Self {
+        Self {
+            columns: 3,
+            cell_width: 480,
+            spacing: 16,
+            background: DecorationBackground::Solid(Color32::from_gray(245)),
+            captions: true,
+            caption_size: 14.0,
+        }
+    }
+}
+
+impl CollageOptions {
+    /// Validate collage options
+    pub fn validate(&self) -> AppResult<()> {
+        if self.columns == 0 {
+            return Err(AppError::Settings("列数は1以上で指定してください".to_string()));
+        }
+        if self.cell_width == 0 {
+            return Err(AppError::Settings("セルの幅は1以上で指定してください".to_string()));
+        }
+        if self.caption_size <= 0.0 || !self.caption_size.is_finite() {
+            return Err(AppError::Settings("キャプションの文字サイズは0より大きい値で指定してください".to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// Arrange captioned images in a grid on a single image
+///
+/// Images wider than a cell are scaled down to fit it and narrower ones
+/// are centered. Each row is as tall as its tallest image, with the
+/// captions lined up below. Captions too long for a cell are shortened
+/// with an ellipsis.
+pub fn collage(images: &[(RgbaImage, String)], options: &CollageOptions) -> AppResult<RgbaImage> {
+    options.validate()?;
+    if images.is_empty() {
+        return Err(AppError::ImageProcessing("コラージュにする画像がありません".to_string()));
+    }
+
+    let (cell_width, spacing) = (options.cell_width, options.spacing);
+    let scaled: Vec<Cow<RgbaImage>> = images
+        .iter()
+        .map(|(image, _)| {
+            let (width, height) = image.dimensions();
+            if width <= cell_width {
+                return Cow::Borrowed(image);
+            }
+            let scaled_height = ((height as f64 * cell_width as f64 / width as f64).round() as u32).max(1);
+            Cow::Owned(image::imageops::resize(image, cell_width, scaled_height, image::imageops::FilterType::Triangle))
+        })
+        .collect();
+
+    let caption_height = if options.captions {
+        measure_text("Ag", options.caption_size).y.ceil() as u32 + CAPTION_GAP
+    } else {
+        0
+    };
+    let columns = (options.columns as usize).min(images.len());
+    let image_heights: Vec<u32> = scaled
+        .chunks(columns)
+        .map(|row| row.iter().map(|image| image.height()).max().unwrap_or(0))
+        .collect();
+    let width = columns as u32 * (cell_width + spacing) + spacing;
+    let height = image_heights.iter().map(|image_height| image_height + caption_height + spacing).sum::<u32>() + spacing;
+    let mut canvas = RgbaImage::from_fn(width, height, |x, y| {
+        image::Rgba(options.background.color_at(x, y, width, height).to_srgba_unmultiplied())
+    });
+
+    let mut row_y = spacing;
+    for (row, image_height) in image_heights.into_iter().enumerate() {
+        for column in 0..columns {
+            let index = row * columns + column;
+            let Some(image) = scaled.get(index) else {
+                break;
+            };
+            let cell_x = spacing + column as u32 * (cell_width + spacing);
+            let x = cell_x + (cell_width - image.width()) / 2;
+            let y = row_y + (image_height - image.height()) / 2;
+            image::imageops::overlay(&mut canvas, image.as_ref(), x as i64, y as i64);
+
+            if options.captions {
+                let caption = fit_caption(&images[index].1, options.caption_size, cell_width as f32);
+                let caption_width = measure_text(&caption, options.caption_size).x;
+                let caption_y = row_y + image_height + CAPTION_GAP;
+                let behind = options.background.color_at(cell_x + cell_width / 2, caption_y, width, height);
+                let position = Pos2::new(cell_x as f32 + (cell_width as f32 - caption_width) / 2.0, caption_y as f32);
+                draw_text(&mut canvas, position, &caption, options.caption_size, caption_color(behind));
+            }
+        }
+        row_y += image_height + caption_height + spacing;
+    }
+    Ok(canvas)
+}
+
+/// Shorten a caption with an ellipsis until it is at most `max_width` wide
+fn fit_caption(caption: &str, font_size: f32, max_width: f32) -> String {
+    if measure_text(caption, font_size).x <= max_width {
+        return caption.to_string();
+    }
+    let mut chars: Vec<char> = caption.chars().collect();
+    while chars.pop().is_some() {
+        let shortened = format!("{}…", chars.iter().collect::<String>());
+        if measure_text(&shortened, font_size).x <= max_width {
+            return shortened;
+        }
+    }
+    String::new()
+}
+
+/// Get black or white, whichever is easier to read on `background`
+fn caption_color(background: Color32) -> Color32 {
+    if accessibility::contrast_ratio(Color32::BLACK, background) >= accessibility::contrast_ratio(Color32::WHITE, background) {
+        Color32::BLACK
+    } else {
+        Color32::WHITE
+    }
+}
+
+/// Shared font set for offscreen text rasterization (expensive to create)
+fn text_fonts() -> &'static Fonts {
+    static FONTS: OnceLock<Fonts> = OnceLock::new();
+    FONTS.get_or_init(|| Fonts::new(1.0, FONT_ATLAS_MAX_SIDE, text::font_definitions([])))
+}
+
+/// Offscreen font set with an installed face added, created once per face
+///
+/// Font sets live for the rest of the process like the shared one. Faces
+/// that fail to load are remembered and drawn with the built-in font.
+fn face_fonts(font: &InstalledFont) -> Option<&'static Fonts> {
+    static FACES: OnceLock<Mutex<HashMap<String, Option<&'static Fonts>>>> = OnceLock::new();
+    let mut faces = FACES.get_or_init(Default::default).lock().unwrap_or_else(|e| e.into_inner());
+    *faces.entry(font.name.clone()).or_insert_with(|| match text::load_font(font) {
+        Ok(data) => {
+            let definitions = text::font_definitions([(font.name.as_str(), data)]);
+            Some(&*Box::leak(Box::new(Fonts::new(1.0, FONT_ATLAS_MAX_SIDE, definitions))))
+        }
+        Err(e) => {
+            log::warn!("Drawing text with the built-in font: {}", e);
+            None
+        }
+    })
+}
+
+/// Cache for the flattened composite of an image and its annotations
+///
+/// The cache is keyed by a content revision number that the owner bumps
+/// whenever the source image or annotations change.
+#[derive(Debug, Default)]
+pub struct FlattenCache {
+    revision: Option<u64>,
+    composite: Option<Arc<RgbaImage>>,
+}
+
+impl FlattenCache {
+    /// Create an empty cache
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the cached composite for `revision`, flattening only if it is stale
+    pub fn get_or_flatten(
+        &mut self,
+        revision: u64,
+        image: &DynamicImage,
+        annotations: &[AnnotationItem],
+    ) -> Arc<RgbaImage> {
+        if let (Some(cached_revision), Some(composite)) = (self.revision, &self.composite) {
+            if cached_revision == revision {
+                return Arc::clone(composite);
+            }
+        }
+
+        let composite = Arc::new(flatten(image, annotations));
+        self.revision = Some(revision);
+        self.composite = Some(Arc::clone(&composite));
+        composite
+    }
+
+    /// Check whether the cache holds a composite for `revision`
+    pub fn is_valid_for(&self, revision: u64) -> bool {
+        self.revision == Some(revision) && self.composite.is_some()
+    }
+
+    /// Approximate bytes held by the cached composite
+    pub fn memory_usage(&self) -> usize {
+        self.composite.as_ref().map_or(0, |composite| composite.as_raw().len())
+    }
+
+    /// Drop the cached composite
+    pub fn invalidate(&mut self) {
+        self.revision = None;
+        self.composite = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::text::TextAlign;
+    use image::Rgba;
+
+    fn white_image(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_pixel(width, height, Rgba([255, 255, 255, 255])))
+    }
+
+    #[test]
+    fn test_flatten_without_annotations_is_identity() {
+        let image = white_image(20, 10);
+        let flattened = flatten(&image, &[]);
+        assert_eq!(flattened, image.to_rgba8());
+    }
+
+    #[test]
+    fn test_flatten_rectangle_stroke() {
+        let image = white_image(40, 40);
+        let annotation = AnnotationItem::new_rectangle(Pos2::new(10.0, 10.0), Vec2::new(20.0, 20.0));
+        let flattened = flatten(&image, &[annotation]);
+
+        // Edge pixels are stroked red, the interior is untouched
+        assert_eq!(flattened.get_pixel(10, 20).0, [255, 0, 0, 255]);
+        assert_eq!(flattened.get_pixel(20, 10).0, [255, 0, 0, 255]);
+        assert_eq!(flattened.get_pixel(20, 20).0, [255, 255, 255, 255]);
+        assert_eq!(flattened.get_pixel(2, 2).0, [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn test_flatten_text_draws_pixels() {
+        let image = white_image(100, 40);
+        let annotation = AnnotationItem::new_text(Pos2::new(5.0, 5.0), "Hi".to_string());
+        let flattened = flatten(&image, &[annotation]);
+
+        let changed = flattened.pixels().filter(|p| p.0 != [255, 255, 255, 255]).count();
+        assert!(changed > 0, "Text should change some pixels");
+    }
+
+    #[test]
+    fn test_flatten_formatted_text() {
+        let ink = |image: &RgbaImage| -> Vec<u32> {
+            image.enumerate_pixels().filter(|(_, _, p)| p.0[0] < 128).map(|(x, _, _)| x).collect()
+        };
+        let mut annotation = AnnotationItem::new_text(Pos2::new(10.0, 10.0), "Hello".to_string());
+        let plain = ink(&flatten(&white_image(200, 60), &[annotation.clone()]));
+
+        let AnnotationType::Text { ref mut format, .. } = annotation.annotation_type else {
+            unreachable!()
+        };
+        format.bold = true;
+        let bold = ink(&flatten(&white_image(200, 60), &[annotation.clone()]));
+        assert!(bold.len() > plain.len(), "{} <= {}", bold.len(), plain.len());
+
+        // The box is padded by 0.3 font sizes, with the outline centered on its edge
+        let AnnotationType::Text { ref mut format, .. } = annotation.annotation_type else {
+            unreachable!()
+        };
+        *format = TextFormat {
+            background: Some(Color32::YELLOW),
+            outline: Some(egui::Stroke::new(2.0, Color32::BLUE)),
+            ..TextFormat::default()
+        };
+        let boxed = flatten(&white_image(200, 60), &[annotation.clone()]);
+        assert_eq!(boxed.get_pixel(10, 10).0, [0, 0, 255, 255]);
+        assert_eq!(boxed.get_pixel(12, 12).0, [255, 255, 0, 255]);
+        assert_eq!(boxed.get_pixel(10, 40).0, [255, 255, 255, 255]);
+
+        // Right-aligned lines end at the wrap width
+        let AnnotationType::Text { ref mut format, .. } = annotation.annotation_type else {
+            unreachable!()
+        };
+        *format = TextFormat { align: TextAlign::Right, wrap_width: Some(150.0), ..TextFormat::default() };
+        let right = ink(&flatten(&white_image(200, 60), &[annotation]));
+        assert!(right.iter().all(|&x| x > 100 && x < 160), "{:?}", right);
+    }
+
+    #[test]
+    fn test_installed_face_fonts() {
+        let dir = std::env::temp_dir().join(format!("lsa-render-font-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let definitions = egui::epaint::text::FontDefinitions::default();
+        let data = definitions.font_data.values().next().unwrap();
+        std::fs::write(dir.join("face.ttf"), &*data.font).unwrap();
+
+        let face = InstalledFont { name: "Test Face".to_string(), path: dir.join("face.ttf"), index: 0 };
+        let fonts = face_fonts(&face).unwrap();
+        fonts.begin_frame(1.0, FONT_ATLAS_MAX_SIDE);
+        let family = FontFamily::Name("Test Face".into());
+        assert!(fonts.layout_no_wrap("Hi".to_string(), FontId::new(14.0, family), Color32::BLACK).size().x > 0.0);
+
+        let missing = InstalledFont { name: "Missing Face".to_string(), path: dir.join("missing.ttf"), index: 0 };
+        assert!(face_fonts(&missing).is_none());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_flatten_rotated_rectangle() {
+        let image = white_image(80, 80);
+        let mut annotation = AnnotationItem::new_rectangle(Pos2::new(10.0, 27.0), Vec2::new(60.0, 10.0));
+        if let AnnotationType::Rectangle { ref mut fill_color, .. } = annotation.annotation_type {
+            *fill_color = Some(Color32::BLUE);
+        }
+        annotation.rotation = 90.0;
+        let flattened = flatten(&image, &[annotation]);
+
+        // The horizontal bar now stands upright through its center (40, 32)
+        assert_eq!(flattened.get_pixel(40, 10).0, [0, 0, 255, 255]);
+        assert_eq!(flattened.get_pixel(40, 55).0, [0, 0, 255, 255]);
+        assert_eq!(flattened.get_pixel(15, 32).0, [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn test_rotated_edges_are_antialiased() {
+        let image = white_image(60, 60);
+        let mut annotation = AnnotationItem::new_rectangle(Pos2::new(15.0, 15.0), Vec2::new(30.0, 30.0));
+        if let AnnotationType::Rectangle { ref mut fill_color, .. } = annotation.annotation_type {
+            *fill_color = Some(Color32::BLACK);
+        }
+        annotation.rotation = 30.0;
+        let flattened = flatten(&image, &[annotation]);
+
+        // Blended edge pixels sit between the black square and the white background
+        let partial = flattened.pixels().filter(|p| p.0[1] > 20 && p.0[1] < 235).count();
+        assert!(partial > 0, "Rotated edges should be blended");
+        assert_eq!(flattened.get_pixel(30, 30).0[1], 0);
+    }
+
+    #[test]
+    fn test_flatten_image_annotation() {
+        let mut stamp = Vec::new();
+        let logo = DynamicImage::ImageRgba8(RgbaImage::from_pixel(2, 1, Rgba([0, 0, 255, 255])));
+        logo.write_to(&mut std::io::Cursor::new(&mut stamp), image::ImageOutputFormat::Png).unwrap();
+
+        let mut annotation = AnnotationItem::new_image(Pos2::new(10.0, 10.0), stamp, Vec2::new(20.0, 10.0));
+        let flattened = flatten(&white_image(40, 40), &[annotation.clone()]);
+        assert_eq!(flattened.get_pixel(10, 10).0, [0, 0, 255, 255]);
+        assert_eq!(flattened.get_pixel(29, 19).0, [0, 0, 255, 255]);
+        assert_eq!(flattened.get_pixel(30, 20).0, [255, 255, 255, 255]);
+
+        if let AnnotationType::Image { ref mut opacity, .. } = annotation.annotation_type {
+            *opacity = 0.5;
+        }
+        let flattened = flatten(&white_image(40, 40), &[annotation]);
+        let [r, _, b, _] = flattened.get_pixel(20, 15).0;
+        assert!((126..=129).contains(&r));
+        assert_eq!(b, 255);
+
+        // Broken data draws nothing
+        let broken = AnnotationItem::new_image(Pos2::ZERO, vec![1, 2, 3], Vec2::new(20.0, 20.0));
+        assert_eq!(flatten(&white_image(40, 40), &[broken]), white_image(40, 40).to_rgba8());
+    }
+
+    #[test]
+    fn test_flatten_opacity_and_blend_modes() {
+        let gray = DynamicImage::ImageRgba8(RgbaImage::from_pixel(20, 20, Rgba([128, 128, 128, 255])));
+        let mut square = AnnotationItem::new_rectangle(Pos2::new(4.0, 4.0), Vec2::new(10.0, 10.0));
+        if let AnnotationType::Rectangle { fill_color, .. } = &mut square.annotation_type {
+            *fill_color = Some(Color32::RED);
+        }
+        let center = |annotation: &AnnotationItem| flatten(&gray, std::slice::from_ref(annotation)).get_pixel(9, 9).0;
+
+        assert_eq!(center(&square), [255, 0, 0, 255]);
+        assert_eq!(center(&AnnotationItem { blend_mode: BlendMode::Multiply, ..square.clone() }), [128, 0, 0, 255]);
+        assert_eq!(center(&AnnotationItem { blend_mode: BlendMode::Screen, ..square.clone() }), [255, 128, 128, 255]);
+        assert_eq!(center(&AnnotationItem { opacity: 0.5, ..square.clone() }), [192, 64, 64, 255]);
+        assert_eq!(center(&AnnotationItem { opacity: 0.0, ..square.clone() }), [128, 128, 128, 255]);
+
+        // The stroke is composited over the fill before the opacity applies
+        let edge = flatten(&gray, &[AnnotationItem { opacity: 0.5, ..square.clone() }]).get_pixel(4, 9).0;
+        assert_eq!(edge, [192, 64, 64, 255]);
+    }
+
+    #[test]
+    fn test_flatten_dimension() {
+        let dimension = AnnotationItem::new_dimension(Pos2::new(10.0, 60.0), Pos2::new(90.0, 60.0), None);
+        assert_eq!(dimension_label(&dimension), "80 px, 0.0°");
+        let flattened = flatten(&white_image(100, 80), std::slice::from_ref(&dimension));
+        // The line, a tick across its start, and the label above it
+        assert_eq!(flattened.get_pixel(50, 60).0, [255, 0, 0, 255]);
+        assert_eq!(flattened.get_pixel(10, 57).0, [255, 0, 0, 255]);
+        assert_eq!(flattened.get_pixel(50, 66).0, [255, 255, 255, 255]);
+        let label_pixels = (40..60).filter(|y| (20..80).any(|x| flattened.get_pixel(x, *y).0[1] < 128)).count();
+        assert!(label_pixels > 3);
+        assert!(annotation_extent(&dimension).min.y < 45.0);
+    }
+
+    #[test]
+    fn test_flatten_region() {
+        let mut square = AnnotationItem::new_rectangle(Pos2::new(4.0, 4.0), Vec2::new(10.0, 10.0));
+        square.blend_mode = BlendMode::Multiply;
+        let image = white_image(20, 20);
+        let (pixels, region) = flatten_region(&image, &[square.clone()], annotation_extent(&square)).unwrap();
+        assert_eq!(region, Rect::from_min_max(Pos2::new(2.0, 2.0), Pos2::new(16.0, 16.0)));
+        let whole = flatten(&image, &[square]);
+        assert_eq!(pixels.get_pixel(2, 2), whole.get_pixel(4, 4));
+
+        let outside = Rect::from_min_size(Pos2::new(30.0, 0.0), Vec2::splat(5.0));
+        assert!(flatten_region(&image, &[], outside).is_none());
+    }
+
+    #[test]
+    fn test_annotation_outside_canvas_is_clipped() {
+        let image = white_image(10, 10);
+        let annotation = AnnotationItem::new_rectangle(Pos2::new(50.0, 50.0), Vec2::new(20.0, 20.0));
+        let flattened = flatten(&image, &[annotation]);
+        assert_eq!(flattened, image.to_rgba8());
+    }
+
+    #[test]
+    fn test_preload_fonts_from_background_thread() {
+        std::thread::spawn(preload_fonts).join().unwrap();
+        assert!(measure_text("Hi", 14.0).x > 0.0);
+    }
+
+    #[test]
+    fn test_blend_pixel_half_alpha() {
+        let mut canvas = RgbaImage::from_pixel(1, 1, Rgba([0, 0, 0, 255]));
+        blend_pixel(&mut canvas, 0, 0, Color32::from_rgba_unmultiplied(255, 255, 255, 128), 1.0);
+        let value = canvas.get_pixel(0, 0).0[0];
+        assert!((127..=129).contains(&value));
+        assert_eq!(canvas.get_pixel(0, 0).0[3], 255);
+    }
+
+    fn plain_decoration(padding: u32) -> Decoration {
+        Decoration {
+            padding,
+            background: DecorationBackground::Solid(Color32::BLACK),
+            corner_radius: 0.0,
+            shadow: None,
+        }
+    }
+
+    #[test]
+    fn test_decorate_adds_padding() {
+        let image = white_image(10, 6).to_rgba8();
+        let decorated = decorate(&image, &plain_decoration(4));
+
+        assert_eq!(decorated.dimensions(), (18, 14));
+        assert_eq!(decorated.get_pixel(0, 0).0, [0, 0, 0, 255]);
+        assert_eq!(decorated.get_pixel(4, 4).0, [255, 255, 255, 255]);
+        assert_eq!(decorated.get_pixel(13, 9).0, [255, 255, 255, 255]);
+        assert_eq!(decorated.get_pixel(14, 10).0, [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_collage_layout() {
+        let options = CollageOptions {
+            columns: 2,
+            cell_width: 20,
+            spacing: 5,
+            background: DecorationBackground::Solid(Color32::BLACK),
+            captions: false,
+            ..CollageOptions::default()
+        };
+        let images = vec![
+            (white_image(10, 10).to_rgba8(), "a.png".to_string()),
+            (white_image(40, 20).to_rgba8(), "b.png".to_string()),
+            (white_image(20, 30).to_rgba8(), "c.png".to_string()),
+        ];
+        let sheet = collage(&images, &options).unwrap();
+
+        // Two rows of two cells: the second image is scaled to 20x10, the first row is 10 tall
+        assert_eq!(sheet.dimensions(), (5 + 25 * 2, 5 + 15 + 35));
+        // The narrow first image is centered in its cell
+        assert_eq!(sheet.get_pixel(9, 5).0, [0, 0, 0, 255]);
+        assert_eq!(sheet.get_pixel(10, 5).0, [255, 255, 255, 255]);
+        assert_eq!(sheet.get_pixel(30, 14).0, [255, 255, 255, 255]);
+        assert_eq!(sheet.get_pixel(5, 49).0, [255, 255, 255, 255]);
+        assert_eq!(sheet.get_pixel(30, 49).0, [0, 0, 0, 255]);
+
+        assert!(collage(&[], &options).is_err());
+        assert!(collage(&images, &CollageOptions { columns: 0, ..options }).is_err());
+    }
+
+    #[test]
+    fn test_collage_captions() {
+        let options = CollageOptions {
+            background: DecorationBackground::Solid(Color32::WHITE),
+            cell_width: 60,
+            ..CollageOptions::default()
+        };
+        let images = vec![(white_image(60, 10).to_rgba8(), "screenshot with a long name.png".to_string())];
+        let sheet = collage(&images, &options).unwrap();
+        assert!(sheet.height() > 10 + options.spacing * 2);
+        // Dark text is drawn below the image
+        assert!(sheet.pixels().skip((options.spacing + 10) as usize * sheet.width() as usize).any(|pixel| pixel.0[0] < 128));
+
+        let caption = fit_caption("screenshot with a long name.png", 14.0, 60.0);
+        assert!(caption.ends_with('…') && measure_text(&caption, 14.0).x <= 60.0);
+        assert_eq!(fit_caption("a.png", 14.0, 60.0), "a.png");
+        assert_eq!(caption_color(Color32::from_gray(30)), Color32::WHITE);
+    }
+
+    #[test]
+    fn test_decorate_rounded_corners() {
+        let image = white_image(20, 20).to_rgba8();
+        let decoration = Decoration {
+            corner_radius: 6.0,
+            ..plain_decoration(2)
+        };
+        let decorated = decorate(&image, &decoration);
+
+        // Corner pixel shows the background, the center shows the image
+        assert_eq!(decorated.get_pixel(2, 2).0, [0, 0, 0, 255]);
+        assert_eq!(decorated.get_pixel(12, 12).0, [255, 255, 255, 255]);
+        // Edge midpoints are not rounded away
+        assert_eq!(decorated.get_pixel(12, 2).0, [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn test_decorate_gradient_background() {
+        let image = white_image(2, 2).to_rgba8();
+        let decoration = Decoration {
+            background: DecorationBackground::Gradient {
+                start: Color32::BLACK,
+                end: Color32::WHITE,
+            },
+            ..plain_decoration(10)
+        };
+        let decorated = decorate(&image, &decoration);
+        let (w, h) = decorated.dimensions();
+        assert_eq!(decorated.get_pixel(0, 0).0, [0, 0, 0, 255]);
+        assert_eq!(decorated.get_pixel(w - 1, h - 1).0, [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn test_decorate_shadow_darkens_background() {
+        let image = white_image(10, 10).to_rgba8();
+        let decoration = Decoration {
+            background: DecorationBackground::Solid(Color32::WHITE),
+            shadow: Some(DropShadow {
+                offset: Vec2::new(4.0, 4.0),
+                blur: 0.0,
+                color: Color32::BLACK,
+            }),
+            ..plain_decoration(8)
+        };
+        let decorated = decorate(&image, &decoration);
+
+        // Below-right of the image lies in the shadow, the opposite corner does not
+        assert_eq!(decorated.get_pixel(20, 20).0, [0, 0, 0, 255]);
+        assert_eq!(decorated.get_pixel(2, 2).0, [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn test_decoration_presets() {
+        let mut settings = DecorationSettings::default();
+        assert!(settings.active().is_none());
+        assert!(settings.set_active_preset(Some("Missing")).is_err());
+
+        settings.save_preset("Mine", plain_decoration(3)).unwrap();
+        settings.set_active_preset(Some("Mine")).unwrap();
+        assert_eq!(settings.active(), Some(&plain_decoration(3)));
+
+        assert!(settings.remove_preset("Mine"));
+        assert!(settings.active().is_none());
+
+        let invalid = Decoration {
+            corner_radius: -1.0,
+            ..plain_decoration(0)
+        };
+        assert!(settings.save_preset("Bad", invalid).is_err());
+    }
+
+    #[test]
+    fn test_decoration_settings_serialization() {
+        let mut settings = DecorationSettings::default();
+        settings.set_active_preset(Some("Gradient")).unwrap();
+        let json = serde_json::to_string(&settings).unwrap();
+        let restored: DecorationSettings = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, settings);
+    }
+
+    fn text_watermark(anchor: WatermarkAnchor) -> Watermark {
+        Watermark {
+            enabled: true,
+            content: WatermarkContent::Text {
+                text: "WM".to_string(),
+                font_size: 16.0,
+                color: Color32::BLACK,
+            },
+            anchor,
+            margin: 4,
+            scale: 1.0,
+            opacity: 1.0,
+        }
+    }
+
+    fn changed_bounds(image: &RgbaImage) -> Option<(u32, u32, u32, u32)> {
+        image
+            .enumerate_pixels()
+            .filter(|(_, _, p)| p.0 != [255, 255, 255, 255])
+            .fold(None, |bounds, (x, y, _)| match bounds {
+                None => Some((x, y, x, y)),
+                Some((x0, y0, x1, y1)) => Some((x0.min(x), y0.min(y), x1.max(x), y1.max(y))),
+            })
+    }
+
+    #[test]
+    fn test_watermark_anchor_position() {
+        let canvas = Vec2::new(100.0, 50.0);
+        let size = Vec2::new(20.0, 10.0);
+        assert_eq!(WatermarkAnchor::TopLeft.position(canvas, size, 5.0), Pos2::new(5.0, 5.0));
+        assert_eq!(WatermarkAnchor::BottomRight.position(canvas, size, 5.0), Pos2::new(75.0, 35.0));
+        assert_eq!(WatermarkAnchor::Center.position(canvas, size, 5.0), Pos2::new(40.0, 20.0));
+    }
+
+    #[test]
+    fn test_text_watermark_respects_anchor() {
+        let mut top_left = white_image(200, 100).to_rgba8();
+        apply_watermark(&mut top_left, &text_watermark(WatermarkAnchor::TopLeft), None);
+        let (x0, y0, _, _) = changed_bounds(&top_left).expect("watermark should draw pixels");
+        assert!(x0 < 100 && y0 < 50);
+
+        let mut bottom_right = white_image(200, 100).to_rgba8();
+        apply_watermark(&mut bottom_right, &text_watermark(WatermarkAnchor::BottomRight), None);
+        let (x0, y0, x1, y1) = changed_bounds(&bottom_right).expect("watermark should draw pixels");
+        assert!(x0 > 100 && y0 > 50);
+        assert!(x1 < 200 - 4 && y1 < 100);
+    }
+
+    #[test]
+    fn test_disabled_watermark_is_noop() {
+        let mut canvas = white_image(50, 50).to_rgba8();
+        let watermark = Watermark {
+            enabled: false,
+            ..text_watermark(WatermarkAnchor::Center)
+        };
+        apply_watermark(&mut canvas, &watermark, None);
+        assert!(changed_bounds(&canvas).is_none());
+    }
+
+    #[test]
+    fn test_image_watermark_scale_and_opacity() {
+        let mut canvas = white_image(40, 40).to_rgba8();
+        let logo = RgbaImage::from_pixel(10, 10, Rgba([0, 0, 0, 255]));
+        let watermark = Watermark {
+            enabled: true,
+            content: WatermarkContent::Image { path: PathBuf::from("logo.png") },
+            anchor: WatermarkAnchor::TopLeft,
+            margin: 2,
+            scale: 2.0,
+            opacity: 0.5,
+        };
+        apply_watermark(&mut canvas, &watermark, Some(&logo));
+
+        assert_eq!(changed_bounds(&canvas), Some((2, 2, 21, 21)));
+        let value = canvas.get_pixel(10, 10).0[0];
+        assert!((126..=129).contains(&value));
+    }
+
+    #[test]
+    fn test_validate_watermark() {
+        assert!(Watermark::default().validate().is_ok());
+        assert!(Watermark { scale: 0.0, ..Watermark::default() }.validate().is_err());
+        assert!(Watermark { opacity: 2.0, ..Watermark::default() }.validate().is_err());
+    }
+
+    #[test]
+    fn test_flatten_cache_reuses_composite() {
+        let image = white_image(10, 10);
+        let mut cache = FlattenCache::new();
+        assert!(!cache.is_valid_for(0));
+
+        let first = cache.get_or_flatten(0, &image, &[]);
+        let second = cache.get_or_flatten(0, &image, &[]);
+        assert!(Arc::ptr_eq(&first, &second));
+        assert!(cache.is_valid_for(0));
+        assert_eq!(cache.memory_usage(), 10 * 10 * 4);
+    }
+
+    #[test]
+    fn test_flatten_cache_invalidates_on_revision_change() {
+        let image = white_image(10, 10);
+        let mut cache = FlattenCache::new();
+
+        let first = cache.get_or_flatten(0, &image, &[]);
+        let second = cache.get_or_flatten(1, &image, &[]);
+        assert!(!Arc::ptr_eq(&first, &second));
+        assert!(!cache.is_valid_for(0));
+
+        cache.invalidate();
+        assert!(!cache.is_valid_for(1));
+    }
+
+    #[test]
+    fn test_banner_text() {
+        let captured_at = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_709_210_096);
+        let mut banner = MetadataBanner {
+            custom_text: " QA-1234 ".to_string(),
+            ..MetadataBanner::default()
+        };
+        assert_eq!(banner.text(captured_at, "TEST-PC"), "2024-02-29 12:34:56 UTC  |  TEST-PC  |  QA-1234");
+
+        banner.show_timestamp = false;
+        banner.show_machine_name = false;
+        assert_eq!(banner.text(captured_at, "TEST-PC"), "QA-1234");
+    }
+
+    #[test]
+    fn test_apply_banner_adds_strip_outside_content() {
+        let image = RgbaImage::from_pixel(200, 40, image::Rgba([0, 128, 255, 255]));
+        let mut banner = MetadataBanner {
+            enabled: true,
+            custom_text: "QA".to_string(),
+            ..MetadataBanner::default()
+        };
+
+        let bottom = apply_banner(&image, &banner, SystemTime::now());
+        assert!(bottom.height() > image.height());
+        assert_eq!(bottom.width(), image.width());
+        assert_eq!(bottom.get_pixel(199, 0).0, [0, 128, 255, 255]);
+        assert_eq!(bottom.get_pixel(199, bottom.height() - 1).0, [32, 32, 32, 255]);
+
+        banner.position = BannerPosition::Top;
+        let top = apply_banner(&image, &banner, SystemTime::now());
+        assert_eq!(top.get_pixel(199, 0).0, [32, 32, 32, 255]);
+        assert_eq!(top.get_pixel(199, top.height() - 1).0, [0, 128, 255, 255]);
+
+        banner.enabled = false;
+        assert_eq!(apply_banner(&image, &banner, SystemTime::now()), image);
+    }
+}
@@ -0,0 +1,243 @@
+//! Per-monitor save routing
+//!
+//! Captures can be routed to a different folder and post-save command
+//! depending on the monitor they came from, e.g. saving captures of a work
+//! monitor to a shared folder and handing them to an upload tool while
+//! captures of another monitor stay in the local Pictures folder.
+
+use crate::types::{AppError, AppResult, ScreenInfo};
+use crossbeam_channel::Receiver;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+
+/// Placeholder in command arguments replaced with the saved file path
+pub const FILE_PLACEHOLDER: &str = "{file}";
+
+/// Stable identity of a monitor in the desktop layout
+///
+/// Screen indices change when monitors are reconnected, so monitors are
+/// identified by their position and size in virtual desktop coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct MonitorIdentity {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl MonitorIdentity {
+    /// Get the identity of a screen
+    pub fn from_screen(screen: &ScreenInfo) -> Self {
+        Self {
+            x: screen.bounds.min.x.round() as i32,
+            y: screen.bounds.min.y.round() as i32,
+            width: screen.bounds.width().round() as u32,
+            height: screen.bounds.height().round() as u32,
+        }
+    }
+}
+
+impl std::fmt::Display for MonitorIdentity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}x{} at ({}, {})", self.width, self.height, self.x, self.y)
+    }
+}
+
+/// Command run after a routed capture is saved
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct PostSaveCommand {
+    pub program: String,
+    /// Arguments, with [`FILE_PLACEHOLDER`] replaced by the saved file path
+    pub args: Vec<String>,
+}
+
+impl PostSaveCommand {
+    /// Get the arguments for a saved file
+    pub fn args_for(&self, path: &Path) -> Vec<String> {
+        let file = path.display().to_string();
+        self.args.iter().map(|arg| arg.replace(FILE_PLACEHOLDER, &file)).collect()
+    }
+
+    /// Start the command for a saved file without waiting for it
+    pub fn spawn(&self, path: &Path) -> AppResult<()> {
+        std::process::Command::new(&self.program)
+            .args(self.args_for(path))
+            .spawn()
+            .map(|_| ())
+            .map_err(AppError::FileAccess)
+    }
+
+    /// Start the command for a saved file and watch its output for a link
+    ///
+    /// Upload commands usually print the address of the uploaded file. The
+    /// receiver gets the first http(s) URL the command writes to its standard
+    /// output once it exits, or `None` if it printed none.
+    pub fn spawn_for_url(&self, path: &Path) -> AppResult<Receiver<Option<String>>> {
+        let child = std::process::Command::new(&self.program)
+            .args(self.args_for(path))
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(AppError::FileAccess)?;
+        let (sender, receiver) = crossbeam_channel::bounded(1);
+        std::thread::spawn(move || {
+            let url = match child.wait_with_output() {
+                Ok(output) => find_url(&String::from_utf8_lossy(&output.stdout)),
+                Err(e) => {
+                    log::warn!("Upload command failed: {}", e);
+                    None
+                }
+            };
+            let _ = sender.send(url);
+        });
+        Ok(receiver)
+    }
+}
+
+/// Find the first http(s) URL in command output
+pub fn find_url(output: &str) -> Option<String> {
+    output
+        .split_whitespace()
+        .map(|word| word.trim_start_matches(['"', '\'', '(', '<']))
+        .find(|word| word.starts_with("https://") || word.starts_with("http://"))
+        .map(|url| url.trim_end_matches(['"', '\'', ',', ')', '>', '}']).to_string())
+}
+
+/// Save destination and post-save command for one monitor
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MonitorRoute {
+    pub monitor: MonitorIdentity,
+    /// Folder for captures from this monitor, or `None` for the default folder
+    #[serde(default)]
+    pub directory: Option<PathBuf>,
+    #[serde(default)]
+    pub command: Option<PostSaveCommand>,
+}
+
+impl MonitorRoute {
+    /// Create a route that uses the defaults
+    pub fn new(monitor: MonitorIdentity) -> Self {
+        Self {
+            monitor,
+            directory: None,
+            command: None,
+        }
+    }
+}
+
+/// Per-monitor routes
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MonitorRouting {
+    pub routes: Vec<MonitorRoute>,
+}
+
+impl MonitorRouting {
+    /// Get the route for captures from a screen
+    pub fn route_for(&self, screen: &ScreenInfo) -> Option<&MonitorRoute> {
+        let monitor = MonitorIdentity::from_screen(screen);
+        self.routes.iter().find(|route| route.monitor == monitor)
+    }
+
+    /// Add a route, replacing any existing route for the same monitor
+    pub fn set_route(&mut self, route: MonitorRoute) {
+        self.remove_route(route.monitor);
+        self.routes.push(route);
+    }
+
+    /// Remove the route for a monitor
+    pub fn remove_route(&mut self, monitor: MonitorIdentity) {
+        self.routes.retain(|route| route.monitor != monitor);
+    }
+
+    /// Validate routes
+    pub fn validate(&self) -> AppResult<()> {
+        for (i, route) in self.routes.iter().enumerate() {
+            if self.routes[..i].iter().any(|other| other.monitor == route.monitor) {
+                return Err(AppError::Settings(format!("モニター {} のルートが重複しています", route.monitor)));
+            }
+            if route.command.as_ref().is_some_and(|command| command.program.trim().is_empty()) {
+                return Err(AppError::Settings(format!(
+                    "モニター {} の保存後コマンドが空です",
+                    route.monitor
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use egui::{Pos2, Rect, Vec2};
+
+    fn screen(index: usize, x: f32) -> ScreenInfo {
+        ScreenInfo {
+            index,
+            bounds: Rect::from_min_size(Pos2::new(x, 0.0), Vec2::new(1920.0, 1080.0)),
+            dpi_scale_x: 1.0,
+            dpi_scale_y: 1.0,
+            is_primary: index == 0,
+        }
+    }
+
+    #[test]
+    fn test_route_matches_monitor_identity() {
+        let work = MonitorIdentity::from_screen(&screen(1, 1920.0));
+        let mut routing = MonitorRouting::default();
+        routing.set_route(MonitorRoute {
+            directory: Some(PathBuf::from("\\\\server\\captures")),
+            ..MonitorRoute::new(work)
+        });
+
+        assert!(routing.route_for(&screen(0, 0.0)).is_none());
+        // The index may change after reconnecting, the layout does not
+        let route = routing.route_for(&screen(3, 1920.0)).unwrap();
+        assert_eq!(route.directory, Some(PathBuf::from("\\\\server\\captures")));
+
+        routing.set_route(MonitorRoute::new(work));
+        assert_eq!(routing.routes.len(), 1);
+        routing.remove_route(work);
+        assert!(routing.routes.is_empty());
+    }
+
+    #[test]
+    fn test_command_arguments() {
+        let command = PostSaveCommand {
+            program: "uploader".to_string(),
+            args: vec!["--file={file}".to_string(), "--project".to_string()],
+        };
+        assert_eq!(
+            command.args_for(Path::new("/tmp/キャプチャ.png")),
+            vec!["--file=/tmp/キャプチャ.png".to_string(), "--project".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_find_url() {
+        assert_eq!(
+            find_url("Uploading...\nDone: https://example.com/i/abc.png\n"),
+            Some("https://example.com/i/abc.png".to_string())
+        );
+        assert_eq!(find_url("{\"link\": \"http://host/x\"}"), Some("http://host/x".to_string()));
+        assert_eq!(find_url("uploaded to ftp://host/x"), None);
+    }
+
+    #[test]
+    fn test_validate_routes() {
+        let monitor = MonitorIdentity::from_screen(&screen(0, 0.0));
+        let mut routing = MonitorRouting::default();
+        routing.routes.push(MonitorRoute {
+            command: Some(PostSaveCommand::default()),
+            ..MonitorRoute::new(monitor)
+        });
+        assert!(routing.validate().is_err());
+
+        routing.routes[0].command = None;
+        assert!(routing.validate().is_ok());
+        routing.routes.push(MonitorRoute::new(monitor));
+        assert!(routing.validate().is_err());
+    }
+}
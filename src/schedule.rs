@@ -0,0 +1,293 @@
+//! Interval capture
+//!
+//! An interval run captures a chosen target every few seconds or minutes into
+//! a dated folder of its own, for time-lapses or for keeping an eye on a
+//! long-running job. Frames are numbered in order (`00001.png`, `00002.png`,
+//! ...) so they can be turned into a video directly. A run ends after a
+//! maximum number of frames or a maximum duration, or when it is stopped
+//! from the menu or with `lightweight-screenshot-app control stop interval`.
+//! With change detection on, frames identical to the last saved one are
+//! skipped.
+
+use crate::datetime::DateTime;
+use crate::diff;
+use crate::presets::PresetTarget;
+use crate::types::{AppError, AppResult, ImageFormat};
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Shortest interval between frames
+pub const MIN_INTERVAL_SECS: u64 = 1;
+
+/// Longest interval between frames
+pub const MAX_INTERVAL_SECS: u64 = 24 * 60 * 60;
+
+/// Interval capture settings
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IntervalSettings {
+    /// What each frame captures
+    pub target: PresetTarget,
+    /// Seconds between frames
+    pub interval_secs: u64,
+    /// Stop after this many saved frames
+    pub max_frames: Option<u32>,
+    /// Stop after this many minutes
+    pub max_duration_mins: Option<u64>,
+    /// Skip frames identical to the last saved one
+    pub skip_unchanged: bool,
+    /// Format of saved frames
+    pub format: ImageFormat,
+    /// Folder the dated run folders are created in, the save folder if unset
+    pub directory: Option<PathBuf>,
+}
+
+impl Default for IntervalSettings {
+    fn default() -> Self {
+        Self {
+            target: PresetTarget::default(),
+            interval_secs: 60,
+            max_frames: None,
+            max_duration_mins: None,
+            skip_unchanged: true,
+            format: ImageFormat::Png,
+            directory: None,
+        }
+    }
+}
+
+impl IntervalSettings {
+    /// Validate the interval and limits
+    pub fn validate(&self) -> AppResult<()> {
+        if !(MIN_INTERVAL_SECS..=MAX_INTERVAL_SECS).contains(&self.interval_secs) {
+            return Err(AppError::Settings(format!(
+                "撮影間隔は{}秒から{}秒の間で指定してください",
+                MIN_INTERVAL_SECS, MAX_INTERVAL_SECS
+            )));
+        }
+        if self.max_frames == Some(0) {
+            return Err(AppError::Settings("最大枚数は1枚以上にしてください".to_string()));
+        }
+        if self.max_duration_mins == Some(0) {
+            return Err(AppError::Settings("最大撮影時間は1分以上にしてください".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Get the time between frames
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_secs)
+    }
+}
+
+/// Why an interval run ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// Stopped from the menu or a control command
+    Stopped,
+    /// The maximum number of frames was saved
+    FrameLimit,
+    /// The maximum duration passed
+    TimeLimit,
+}
+
+impl std::fmt::Display for StopReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StopReason::Stopped => write!(f, "Stopped"),
+            StopReason::FrameLimit => write!(f, "Frame limit reached"),
+            StopReason::TimeLimit => write!(f, "Time limit reached"),
+        }
+    }
+}
+
+/// Get the name of the folder a run started at `time` saves to
+pub fn folder_name(time: &DateTime) -> String {
+    format!(
+        "interval_{:04}{:02}{:02}_{:02}{:02}{:02}",
+        time.year, time.month, time.day, time.hour, time.minute, time.second
+    )
+}
+
+/// A running interval capture
+///
+/// The run only keeps time; the editor takes the captures when
+/// [`take_due`](Self::take_due) says a frame is due and hands them to
+/// [`accept_frame`](Self::accept_frame).
+#[derive(Debug)]
+pub struct IntervalRun {
+    settings: IntervalSettings,
+    folder: PathBuf,
+    started: Instant,
+    next_due: Instant,
+    saved: u32,
+    skipped: u32,
+    /// Last saved frame, kept for change detection
+    last_frame: Option<RgbaImage>,
+}
+
+impl IntervalRun {
+    /// Start a run in a new dated folder under `parent`, with its first frame due now
+    pub fn new(settings: IntervalSettings, parent: &Path, started_at: &DateTime, now: Instant) -> Self {
+        Self {
+            folder: parent.join(folder_name(started_at)),
+            settings,
+            started: now,
+            next_due: now,
+            saved: 0,
+            skipped: 0,
+            last_frame: None,
+        }
+    }
+
+    /// Get the settings the run was started with
+    pub fn settings(&self) -> &IntervalSettings {
+        &self.settings
+    }
+
+    /// Get the folder frames are saved to
+    pub fn folder(&self) -> &Path {
+        &self.folder
+    }
+
+    /// Get the number of saved frames
+    pub fn saved(&self) -> u32 {
+        self.saved
+    }
+
+    /// Get the number of frames skipped as unchanged
+    pub fn skipped(&self) -> u32 {
+        self.skipped
+    }
+
+    /// Check whether the run has reached one of its limits
+    pub fn limit_reached(&self, now: Instant) -> Option<StopReason> {
+        if self.settings.max_frames.is_some_and(|max| self.saved >= max) {
+            return Some(StopReason::FrameLimit);
+        }
+        let max_duration = self.settings.max_duration_mins.map(|mins| Duration::from_secs(mins * 60));
+        if max_duration.is_some_and(|max| now.saturating_duration_since(self.started) >= max) {
+            return Some(StopReason::TimeLimit);
+        }
+        None
+    }
+
+    /// Check whether a frame is due, scheduling the next one if so
+    ///
+    /// Frames keep to the original schedule; frames missed while the app was
+    /// busy are dropped rather than taken in a burst.
+    pub fn take_due(&mut self, now: Instant) -> bool {
+        if now < self.next_due {
+            return false;
+        }
+        let interval = self.settings.interval();
+        self.next_due += interval;
+        if self.next_due <= now {
+            let behind = now.duration_since(self.next_due).as_secs() / interval.as_secs().max(1) + 1;
+            self.next_due += interval * behind as u32;
+        }
+        true
+    }
+
+    /// Get the time until the next frame is due
+    pub fn time_until_due(&self, now: Instant) -> Duration {
+        self.next_due.saturating_duration_since(now)
+    }
+
+    /// Decide whether to keep a captured frame, returning the path to save it to
+    ///
+    /// Returns `None` for a frame identical to the last saved one when
+    /// change detection is on.
+    pub fn accept_frame(&mut self, image: &RgbaImage) -> Option<PathBuf> {
+        if self.settings.skip_unchanged {
+            let unchanged = self
+                .last_frame
+                .as_ref()
+                .is_some_and(|last| !diff::changed_beyond(last, image, diff::DEFAULT_THRESHOLD, 0.0));
+            if unchanged {
+                self.skipped += 1;
+                return None;
+            }
+            self.last_frame = Some(image.clone());
+        }
+        self.saved += 1;
+        Some(self.folder.join(format!("{:05}.{}", self.saved, self.settings.format.extension())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn start_time() -> DateTime {
+        DateTime { year: 2024, month: 3, day: 9, hour: 8, minute: 5, second: 30 }
+    }
+
+    #[test]
+    fn test_schedule_and_limits() {
+        let now = Instant::now();
+        let settings = IntervalSettings {
+            interval_secs: 10,
+            max_duration_mins: Some(1),
+            ..IntervalSettings::default()
+        };
+        let mut run = IntervalRun::new(settings, Path::new("shots"), &start_time(), now);
+        assert_eq!(run.folder(), Path::new("shots").join("interval_20240309_080530"));
+
+        // The first frame is due at once, the next one an interval later
+        assert!(run.take_due(now));
+        assert!(!run.take_due(now + Duration::from_secs(9)));
+        assert_eq!(run.time_until_due(now + Duration::from_secs(4)), Duration::from_secs(6));
+        assert!(run.take_due(now + Duration::from_secs(10)));
+
+        // Missed frames are dropped, keeping to the schedule
+        assert!(run.take_due(now + Duration::from_secs(45)));
+        assert!(!run.take_due(now + Duration::from_secs(49)));
+        assert_eq!(run.time_until_due(now + Duration::from_secs(45)), Duration::from_secs(5));
+
+        assert_eq!(run.limit_reached(now + Duration::from_secs(59)), None);
+        assert_eq!(run.limit_reached(now + Duration::from_secs(60)), Some(StopReason::TimeLimit));
+    }
+
+    #[test]
+    fn test_frames_and_change_detection() {
+        let settings = IntervalSettings {
+            max_frames: Some(2),
+            format: ImageFormat::Jpg,
+            ..IntervalSettings::default()
+        };
+        let mut run = IntervalRun::new(settings, Path::new("shots"), &start_time(), Instant::now());
+        let black = RgbaImage::from_pixel(8, 8, image::Rgba([0, 0, 0, 255]));
+        let mut changed = black.clone();
+        changed.put_pixel(3, 3, image::Rgba([255, 255, 255, 255]));
+
+        let first = run.accept_frame(&black).unwrap();
+        assert_eq!(first.file_name().unwrap(), "00001.jpg");
+        assert_eq!(run.accept_frame(&black), None);
+        assert_eq!(run.skipped(), 1);
+        assert_eq!(run.limit_reached(Instant::now()), None);
+        assert!(run.accept_frame(&changed).unwrap().ends_with("00002.jpg"));
+        assert_eq!(run.limit_reached(Instant::now()), Some(StopReason::FrameLimit));
+
+        // Without change detection every frame is kept
+        let mut keep_all = IntervalRun::new(
+            IntervalSettings { skip_unchanged: false, ..IntervalSettings::default() },
+            Path::new("shots"),
+            &start_time(),
+            Instant::now(),
+        );
+        assert!(keep_all.accept_frame(&black).is_some());
+        assert!(keep_all.accept_frame(&black).is_some());
+        assert_eq!(keep_all.saved(), 2);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(IntervalSettings::default().validate().is_ok());
+        assert!(IntervalSettings { interval_secs: 0, ..IntervalSettings::default() }.validate().is_err());
+        assert!(IntervalSettings { max_frames: Some(0), ..IntervalSettings::default() }.validate().is_err());
+        assert!(IntervalSettings { max_duration_mins: Some(0), ..IntervalSettings::default() }.validate().is_err());
+    }
+}
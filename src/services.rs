@@ -0,0 +1,219 @@
+//! Deferred service initialization
+//!
+//! Expensive services (screen enumeration, font loading) are not needed to
+//! show the first frame. `LazyService` defers their construction until first
+//! use, or runs it on a background thread so it is ready by the time it is
+//! needed without delaying startup.
+
+use std::fmt;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread::JoinHandle;
+
+type Initializer<T> = Box<dyn FnOnce() -> T + Send>;
+
+enum ServiceState<T> {
+    /// Not started yet
+    Pending(Initializer<T>),
+    /// Initializing on a background thread
+    Loading(JoinHandle<T>),
+    /// Initialized and ready to use
+    Ready(T),
+    /// Temporarily taken while transitioning between states
+    Empty,
+}
+
+/// A service constructed on first use or in the background
+pub struct LazyService<T> {
+    name: &'static str,
+    state: ServiceState<T>,
+}
+
+impl<T: Send + 'static> LazyService<T> {
+    /// Create a service that runs `init` when first needed
+    pub fn new(name: &'static str, init: impl FnOnce() -> T + Send + 'static) -> Self {
+        Self {
+            name,
+            state: ServiceState::Pending(Box::new(init)),
+        }
+    }
+
+    /// Create a service that is already initialized
+    pub fn ready(name: &'static str, value: T) -> Self {
+        Self {
+            name,
+            state: ServiceState::Ready(value),
+        }
+    }
+
+    /// Start initialization on a background thread if it has not started yet
+    ///
+    /// If the thread can't be started, the service is initialized on first
+    /// use instead.
+    pub fn start_background(&mut self) {
+        if let ServiceState::Pending(_) = self.state {
+            let ServiceState::Pending(init) = std::mem::replace(&mut self.state, ServiceState::Empty) else {
+                unreachable!("state was just checked");
+            };
+
+            // Shared so the initializer can be taken back if the thread never starts
+            let init = Arc::new(Mutex::new(Some(init)));
+            let thread_init = Arc::clone(&init);
+            let name = self.name;
+            let spawned = std::thread::Builder::new().name(format!("init-{}", name)).spawn(move || {
+                let Some(init) = thread_init.lock().unwrap_or_else(PoisonError::into_inner).take() else {
+                    unreachable!("the initializer is only taken back if the thread fails to start");
+                };
+                let started = std::time::Instant::now();
+                let value = init();
+                log::debug!("Initialized {} in background in {:?}", name, started.elapsed());
+                value
+            });
+            self.state = match spawned {
+                Ok(handle) => ServiceState::Loading(handle),
+                Err(e) => {
+                    log::warn!("Failed to start initializing {} in the background: {}", name, e);
+                    let Some(init) = init.lock().unwrap_or_else(PoisonError::into_inner).take() else {
+                        unreachable!("the thread never started");
+                    };
+                    ServiceState::Pending(init)
+                }
+            };
+        }
+    }
+
+    /// Check whether the service is initialized without blocking
+    pub fn is_ready(&self) -> bool {
+        match &self.state {
+            ServiceState::Ready(_) => true,
+            ServiceState::Loading(handle) => handle.is_finished(),
+            _ => false,
+        }
+    }
+
+    /// Get the service, initializing it (or waiting for background initialization) if needed
+    pub fn get(&mut self) -> &mut T {
+        if !matches!(self.state, ServiceState::Ready(_)) {
+            let value = match std::mem::replace(&mut self.state, ServiceState::Empty) {
+                ServiceState::Pending(init) => {
+                    let started = std::time::Instant::now();
+                    let value = init();
+                    log::debug!("Initialized {} on first use in {:?}", self.name, started.elapsed());
+                    value
+                }
+                ServiceState::Loading(handle) => match handle.join() {
+                    Ok(value) => value,
+                    Err(panic) => std::panic::resume_unwind(panic),
+                },
+                ServiceState::Ready(_) | ServiceState::Empty => {
+                    unreachable!("service {} is in an invalid state", self.name)
+                }
+            };
+            self.state = ServiceState::Ready(value);
+        }
+
+        match &mut self.state {
+            ServiceState::Ready(value) => value,
+            _ => unreachable!("service was just initialized"),
+        }
+    }
+}
+
+impl<T: Send + 'static, E: Send + 'static> LazyService<Result<T, E>> {
+    /// Get a service whose initialization can fail, or the error it failed with
+    ///
+    /// A failure is not kept: the service goes back to pending with `retry`
+    /// as its initializer, so the next use tries again.
+    pub fn try_get(&mut self, retry: impl FnOnce() -> Result<T, E> + Send + 'static) -> Result<&mut T, E> {
+        if self.get().is_err() {
+            let ServiceState::Ready(Err(e)) = std::mem::replace(&mut self.state, ServiceState::Pending(Box::new(retry)))
+            else {
+                unreachable!("initialization just failed");
+            };
+            return Err(e);
+        }
+        match &mut self.state {
+            ServiceState::Ready(Ok(value)) => Ok(value),
+            _ => unreachable!("service was just initialized"),
+        }
+    }
+}
+
+impl<T> fmt::Debug for LazyService<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = match self.state {
+            ServiceState::Pending(_) => "pending",
+            ServiceState::Loading(_) => "loading",
+            ServiceState::Ready(_) => "ready",
+            ServiceState::Empty => "empty",
+        };
+        f.debug_struct("LazyService")
+            .field("name", &self.name)
+            .field("state", &state)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_initializes_on_first_use() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&calls);
+        let mut service = LazyService::new("test", move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            42
+        });
+
+        assert!(!service.is_ready());
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+
+        assert_eq!(*service.get(), 42);
+        assert_eq!(*service.get(), 42);
+        assert!(service.is_ready());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_background_initialization() {
+        let mut service = LazyService::new("test", || "loaded".to_string());
+        service.start_background();
+        // Starting twice is a no-op
+        service.start_background();
+
+        assert_eq!(service.get(), "loaded");
+        assert!(service.is_ready());
+    }
+
+    #[test]
+    fn test_failed_initialization_is_retried() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let attempt = |calls: &Arc<AtomicUsize>| {
+            let counter = Arc::clone(calls);
+            move || match counter.fetch_add(1, Ordering::SeqCst) {
+                0 => Err("no screens".to_string()),
+                _ => Ok(7),
+            }
+        };
+        let mut service = LazyService::new("test", attempt(&calls));
+        service.start_background();
+
+        assert_eq!(service.try_get(attempt(&calls)), Err("no screens".to_string()));
+        assert!(!service.is_ready());
+        assert_eq!(service.try_get(attempt(&calls)).map(|value| *value), Ok(7));
+        assert_eq!(service.try_get(attempt(&calls)).map(|value| *value), Ok(7));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_ready_service() {
+        let mut service = LazyService::ready("test", vec![1, 2, 3]);
+        assert!(service.is_ready());
+        service.start_background();
+        service.get().push(4);
+        assert_eq!(service.get().len(), 4);
+    }
+}
@@ -0,0 +1,286 @@
+//! Crash-safe session recovery
+//!
+//! While the editor runs, tabs with unsaved changes are periodically written
+//! to a session folder in the per-user config directory: each image as a PNG
+//! file, written once, and a manifest listing the tabs with their
+//! annotations. The folder is removed when nothing is left unsaved and on a
+//! clean exit, so finding it at startup means the previous session crashed
+//! or was killed, and its work can be offered for restoring.
+
+use crate::adjustments::Adjustments;
+use crate::decode::{self, DecodeLimits};
+use crate::export;
+use crate::files::write_atomic;
+use crate::settings::config_dir;
+use crate::types::{AnnotationItem, AppError, AppResult, ImageFormat};
+use image::{DynamicImage, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// How often unsaved changes are written to the session folder
+pub const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Folder in the config directory holding the session
+const SESSION_DIR_NAME: &str = "session";
+
+/// Manifest file listing the session's tabs
+const MANIFEST_FILE_NAME: &str = "session.json";
+
+/// Current session manifest format version
+const SESSION_VERSION: u32 = 1;
+
+/// Get the default session folder
+pub fn default_session_dir() -> PathBuf {
+    config_dir().join(SESSION_DIR_NAME)
+}
+
+/// A tab with unsaved changes, as listed in the manifest
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionTab {
+    /// Identifies the tab's image file in the session folder
+    pub id: Uuid,
+    /// File the image was opened from or last saved to, if any
+    pub file_path: Option<PathBuf>,
+    pub annotations: Vec<AnnotationItem>,
+    /// Adjustment stack applied to the image
+    #[serde(default)]
+    pub adjustments: Adjustments,
+}
+
+impl SessionTab {
+    /// Get the name of the tab's image file in the session folder
+    fn image_file_name(&self) -> String {
+        format!("{}.png", self.id.simple())
+    }
+}
+
+/// Tabs of the session and which one was active
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct SessionManifest {
+    version: u32,
+    active_tab: usize,
+    tabs: Vec<SessionTab>,
+}
+
+/// A tab to write to the session folder
+#[derive(Debug, Clone)]
+pub struct TabSnapshot {
+    pub tab: SessionTab,
+    /// The tab's image, or `None` if the store already has it
+    pub image: Option<RgbaImage>,
+}
+
+/// Tabs found in the session folder of a session that didn't exit cleanly
+#[derive(Debug, Clone)]
+pub struct RecoveredSession {
+    pub active_tab: usize,
+    pub tabs: Vec<SessionTab>,
+}
+
+/// Writes the session folder on a worker thread
+#[derive(Debug)]
+pub struct SessionStore {
+    dir: PathBuf,
+    /// Tabs whose image file is written
+    written_images: Arc<Mutex<HashSet<Uuid>>>,
+    /// Write in flight, if any
+    worker: Option<JoinHandle<()>>,
+}
+
+impl SessionStore {
+    /// Create a store writing to `dir`
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            written_images: Arc::new(Mutex::new(HashSet::new())),
+            worker: None,
+        }
+    }
+
+    /// Find a session left behind by a previous run, if any
+    ///
+    /// Images left over from a restored session are kept, so restoring
+    /// doesn't write them again.
+    pub fn find_recoverable(&self) -> AppResult<Option<RecoveredSession>> {
+        let path = self.dir.join(MANIFEST_FILE_NAME);
+        let json = match std::fs::read_to_string(&path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let manifest: SessionManifest = serde_json::from_str(&json)
+            .map_err(|e| AppError::Project(format!("セッションファイルの解析に失敗しました: {}", e)))?;
+        if manifest.version > SESSION_VERSION {
+            return Err(AppError::Project(format!(
+                "未対応のセッションファイルのバージョンです: {}",
+                manifest.version
+            )));
+        }
+        if manifest.tabs.is_empty() {
+            return Ok(None);
+        }
+        self.lock_written().extend(manifest.tabs.iter().map(|tab| tab.id));
+        Ok(Some(RecoveredSession {
+            active_tab: manifest.active_tab,
+            tabs: manifest.tabs,
+        }))
+    }
+
+    /// Load a recovered tab's image
+    pub fn load_image(&self, tab: &SessionTab) -> AppResult<DynamicImage> {
+        decode::decode_untrusted_file(&self.dir.join(tab.image_file_name()), DecodeLimits::default())
+    }
+
+    /// Check whether a tab's image is already in the session folder
+    pub fn has_image(&self, id: Uuid) -> bool {
+        self.lock_written().contains(&id)
+    }
+
+    /// Start writing the tabs to the session folder, replacing the previous session
+    ///
+    /// Returns false without writing if the previous write is still running.
+    pub fn save(&mut self, active_tab: usize, tabs: Vec<TabSnapshot>) -> bool {
+        if self.worker.as_ref().is_some_and(|worker| !worker.is_finished()) {
+            return false;
+        }
+        self.finish();
+
+        let dir = self.dir.clone();
+        let written_images = self.written_images.clone();
+        let spawned = std::thread::Builder::new().name("session-autosave".to_string()).spawn(move || {
+            if let Err(e) = write_session(&dir, active_tab, tabs, &written_images) {
+                log::warn!("Failed to save the session for recovery: {}", e);
+            }
+        });
+        match spawned {
+            Ok(worker) => {
+                self.worker = Some(worker);
+                true
+            }
+            Err(e) => {
+                log::warn!("Failed to spawn session autosave thread: {}", e);
+                false
+            }
+        }
+    }
+
+    /// Remove the session folder, waiting for a running write first
+    pub fn clear(&mut self) {
+        self.finish();
+        self.lock_written().clear();
+        match std::fs::remove_dir_all(&self.dir) {
+            Ok(()) => log::debug!("Removed session folder {}", self.dir.display()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => log::warn!("Failed to remove session folder {}: {}", self.dir.display(), e),
+        }
+    }
+
+    /// Wait for the running write, if any
+    pub fn finish(&mut self) {
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+
+    fn lock_written(&self) -> std::sync::MutexGuard<'_, HashSet<Uuid>> {
+        self.written_images.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Write the images not yet in the session folder, then the manifest, then drop stale images
+fn write_session(
+    dir: &Path,
+    active_tab: usize,
+    tabs: Vec<TabSnapshot>,
+    written_images: &Mutex<HashSet<Uuid>>,
+) -> AppResult<()> {
+    std::fs::create_dir_all(dir)?;
+    let mut manifest = SessionManifest {
+        version: SESSION_VERSION,
+        active_tab,
+        tabs: Vec::with_capacity(tabs.len()),
+    };
+    for snapshot in tabs {
+        if let Some(image) = snapshot.image {
+            let data = export::encode_image(&image, &ImageFormat::Png)?;
+            write_atomic(&dir.join(snapshot.tab.image_file_name()), &data)?;
+            written_images
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .insert(snapshot.tab.id);
+        }
+        manifest.tabs.push(snapshot.tab);
+    }
+
+    let json = serde_json::to_string(&manifest)
+        .map_err(|e| AppError::Project(format!("セッションのシリアライズに失敗しました: {}", e)))?;
+    write_atomic(&dir.join(MANIFEST_FILE_NAME), json.as_bytes())?;
+
+    // Images of tabs that were saved or closed since the last write
+    let kept: HashSet<String> = manifest.tabs.iter().map(SessionTab::image_file_name).collect();
+    let mut written_images = written_images.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    written_images.retain(|id| manifest.tabs.iter().any(|tab| tab.id == *id));
+    for entry in std::fs::read_dir(dir)?.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.ends_with(".png") && !kept.contains(&name) {
+            let _ = std::fs::remove_file(entry.path());
+        }
+    }
+    log::debug!("Saved {} unsaved tabs for recovery", manifest.tabs.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use egui::Pos2;
+
+    fn snapshot(color: u8) -> TabSnapshot {
+        TabSnapshot {
+            tab: SessionTab {
+                id: Uuid::new_v4(),
+                file_path: None,
+                annotations: vec![AnnotationItem::new_text(Pos2::new(1.0, 2.0), "Note".to_string())],
+                adjustments: Adjustments::default(),
+            },
+            image: Some(RgbaImage::from_pixel(4, 3, image::Rgba([color, 0, 0, 255]))),
+        }
+    }
+
+    #[test]
+    fn test_save_and_recover() {
+        let dir = std::env::temp_dir().join(format!("lsa-session-{}", Uuid::new_v4()));
+        let mut store = SessionStore::new(dir.clone());
+        assert!(store.find_recoverable().unwrap().is_none());
+
+        let first = snapshot(10);
+        let second = snapshot(20);
+        assert!(store.save(1, vec![first.clone(), second.clone()]));
+        store.finish();
+        assert!(store.has_image(first.tab.id) && store.has_image(second.tab.id));
+
+        // The first tab was saved; its image goes away with the next write
+        assert!(store.save(0, vec![TabSnapshot { image: None, ..second.clone() }]));
+        store.finish();
+        assert!(!dir.join(first.tab.image_file_name()).exists());
+
+        // A new run finds what the crashed one left behind
+        let restarted = SessionStore::new(dir.clone());
+        let recovered = restarted.find_recoverable().unwrap().expect("session should be recoverable");
+        assert_eq!(recovered.active_tab, 0);
+        assert_eq!(recovered.tabs, vec![second.tab.clone()]);
+        assert!(restarted.has_image(second.tab.id));
+        let image = restarted.load_image(&recovered.tabs[0]).unwrap().to_rgba8();
+        assert_eq!(image.dimensions(), (4, 3));
+        assert_eq!(image.get_pixel(0, 0).0, [20, 0, 0, 255]);
+
+        store.clear();
+        assert!(!dir.exists());
+        assert!(SessionStore::new(dir).find_recoverable().unwrap().is_none());
+    }
+}
@@ -0,0 +1,408 @@
+//! Application settings persistence
+//!
+//! This module manages loading and saving `AppSettings` as JSON in the
+//! per-user configuration directory.
+
+use crate::batch;
+use crate::presets;
+use crate::remote;
+use crate::share;
+use crate::types::{AppError, AppResult, AppSettings, Hotkey, ImageFormat, ResultExt};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Directory name used under the platform configuration directory
+const APP_DIR_NAME: &str = "lightweight-screenshot-app";
+
+/// Settings file name
+const SETTINGS_FILE_NAME: &str = "settings.json";
+
+/// Number of entries kept in the recent files list
+pub const MAX_RECENT_FILES: usize = 10;
+
+/// Get the per-user configuration directory for the application
+///
+/// Uses `%APPDATA%` on Windows and `$XDG_CONFIG_HOME` or `~/.config` elsewhere.
+pub fn config_dir() -> PathBuf {
+    let base = std::env::var_os("APPDATA")
+        .or_else(|| std::env::var_os("XDG_CONFIG_HOME"))
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .unwrap_or_else(std::env::temp_dir);
+    base.join(APP_DIR_NAME)
+}
+
+/// Get the default settings file path
+pub fn default_settings_path() -> PathBuf {
+    config_dir().join(SETTINGS_FILE_NAME)
+}
+
+impl AppSettings {
+    /// Load settings from a JSON file
+    pub fn load_from(path: &Path) -> AppResult<Self> {
+        let json = fs::read_to_string(path)?;
+        let settings: AppSettings = serde_json::from_str(&json)
+            .map_err(|e| AppError::Settings(format!("設定ファイルの解析に失敗しました: {}", e)))?;
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    /// Save settings to a JSON file, creating parent directories as needed
+    pub fn save_to(&self, path: &Path) -> AppResult<()> {
+        self.validate()?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).context("設定フォルダを作成できません")?;
+        }
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| AppError::Settings(format!("設定のシリアライズに失敗しました: {}", e)))?;
+        fs::write(path, json).context(format!("{} に書き込めません", path.display()))?;
+        Ok(())
+    }
+
+    /// Validate setting values
+    pub fn validate(&self) -> AppResult<()> {
+        if self.hotkey_vk_code == 0 {
+            return Err(AppError::Settings("ホットキーのキーコードが無効です".to_string()));
+        }
+        if self.window_hotkey.vk_code == 0 {
+            return Err(AppError::Settings("ウィンドウキャプチャのホットキーのキーコードが無効です".to_string()));
+        }
+        if self.window_hotkey.modifiers == self.hotkey_modifiers && self.window_hotkey.vk_code == self.hotkey_vk_code {
+            return Err(AppError::Settings("ウィンドウキャプチャのホットキーが画面キャプチャと重複しています".to_string()));
+        }
+        let screen_hotkey = Hotkey {
+            modifiers: self.hotkey_modifiers,
+            vk_code: self.hotkey_vk_code,
+        };
+        if self.cursor_monitor_hotkey.vk_code == 0 {
+            return Err(AppError::Settings("カーソル位置のモニターキャプチャのホットキーのキーコードが無効です".to_string()));
+        }
+        if self.cursor_monitor_hotkey == screen_hotkey || self.cursor_monitor_hotkey == self.window_hotkey {
+            return Err(AppError::Settings(
+                "カーソル位置のモニターキャプチャのホットキーが他のキャプチャと重複しています".to_string(),
+            ));
+        }
+        if self.color_picker_hotkey.vk_code == 0 {
+            return Err(AppError::Settings("カラーピッカーのホットキーのキーコードが無効です".to_string()));
+        }
+        if [screen_hotkey, self.window_hotkey, self.cursor_monitor_hotkey].contains(&self.color_picker_hotkey) {
+            return Err(AppError::Settings("カラーピッカーのホットキーがキャプチャのホットキーと重複しています".to_string()));
+        }
+        self.styles.validate()?;
+        self.shortcuts.validate()?;
+        self.export.validate()?;
+        self.snapping.validate()?;
+        self.monitor_routing.validate()?;
+        self.file_naming.validate()?;
+        self.pan.validate()?;
+        self.gestures.validate()?;
+        self.grid.validate()?;
+        self.hdr.validate()?;
+        self.overlay.validate()?;
+        presets::validate_presets(
+            &self.capture_presets,
+            &[screen_hotkey, self.window_hotkey, self.cursor_monitor_hotkey, self.color_picker_hotkey],
+        )?;
+        batch::validate_batch_presets(&self.batch_presets)?;
+        share::validate_share_targets(&self.share_targets)?;
+        remote::validate_destinations(&self.save_destinations)?;
+        self.privacy.validate()?;
+        self.interval_capture.validate()?;
+        self.watch.validate()?;
+        self.trim.validate()?;
+        self.memory.validate()?;
+        Ok(())
+    }
+
+    /// Get the directory new screenshots are saved to
+    ///
+    /// Uses the configured default directory, falling back to the user's
+    /// Pictures folder and then the current directory.
+    pub fn save_directory(&self) -> PathBuf {
+        if let Some(ref directory) = self.default_save_directory {
+            return PathBuf::from(directory);
+        }
+        std::env::var_os("USERPROFILE")
+            .or_else(|| std::env::var_os("HOME"))
+            .map(|home| PathBuf::from(home).join("Pictures"))
+            .unwrap_or_else(|| PathBuf::from("."))
+    }
+
+    /// Move a file to the front of the recent files list
+    pub fn add_recent_file(&mut self, path: &Path) {
+        self.recent_files.retain(|recent| recent != path);
+        self.recent_files.insert(0, path.to_path_buf());
+        self.recent_files.truncate(MAX_RECENT_FILES);
+    }
+}
+
+/// Manages the application settings and their persistence
+#[derive(Debug, Clone)]
+pub struct SettingsManager {
+    settings: AppSettings,
+    /// Settings file path, or `None` for in-memory settings that are never written
+    settings_path: Option<PathBuf>,
+}
+
+impl SettingsManager {
+    /// Load settings from the default location, falling back to defaults
+    pub fn new() -> AppResult<Self> {
+        Self::with_path(default_settings_path())
+    }
+
+    /// Load settings from a specific file, falling back to defaults if it is missing or invalid
+    pub fn with_path(path: PathBuf) -> AppResult<Self> {
+        let settings = if path.exists() {
+            match AppSettings::load_from(&path) {
+                Ok(settings) => settings,
+                Err(e) => {
+                    log::warn!("Failed to load settings from {}: {}", path.display(), e);
+                    AppSettings::default()
+                }
+            }
+        } else {
+            AppSettings::default()
+        };
+
+        Ok(Self {
+            settings,
+            settings_path: Some(path),
+        })
+    }
+
+    /// Create a manager that keeps settings in memory only
+    pub fn in_memory(settings: AppSettings) -> Self {
+        Self {
+            settings,
+            settings_path: None,
+        }
+    }
+
+    /// Get the current settings
+    pub fn get_settings(&self) -> &AppSettings {
+        &self.settings
+    }
+
+    /// Get mutable access to the settings (call [`Self::save`] to persist)
+    pub fn settings_mut(&mut self) -> &mut AppSettings {
+        &mut self.settings
+    }
+
+    /// Get the settings file path, if persisted
+    pub fn settings_path(&self) -> Option<&Path> {
+        self.settings_path.as_deref()
+    }
+
+    /// Update the default save directory and persist
+    pub fn update_save_directory(&mut self, directory: Option<String>) -> AppResult<()> {
+        self.settings.default_save_directory = directory;
+        self.save()
+    }
+
+    /// Update the default image format and persist
+    pub fn update_image_format(&mut self, format: ImageFormat) -> AppResult<()> {
+        self.settings.default_image_format = format;
+        self.save()
+    }
+
+    /// Persist the current settings
+    pub fn save(&self) -> AppResult<()> {
+        match self.settings_path {
+            Some(ref path) => self.settings.save_to(path),
+            None => Ok(()),
+        }
+    }
+
+    /// Restore default settings and persist
+    pub fn reset_to_defaults(&mut self) -> AppResult<()> {
+        self.settings = AppSettings::default();
+        self.save()
+    }
+}
+
+impl Default for SettingsManager {
+    fn default() -> Self {
+        Self::in_memory(AppSettings::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_settings_path(name: &str) -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("lsa-settings-test-{}-{}", name, uuid::Uuid::new_v4()))
+            .join(SETTINGS_FILE_NAME)
+    }
+
+    #[test]
+    fn test_settings_round_trip() {
+        let path = temp_settings_path("round-trip");
+        let settings = AppSettings {
+            default_image_format: ImageFormat::Jpg,
+            default_save_directory: Some("C:\\Screenshots".to_string()),
+            ..AppSettings::default()
+        };
+
+        settings.save_to(&path).unwrap();
+        let loaded = AppSettings::load_from(&path).unwrap();
+        assert_eq!(loaded, settings);
+
+        fs::remove_dir_all(path.parent().unwrap()).ok();
+    }
+
+    #[test]
+    fn test_load_invalid_json_fails() {
+        let path = temp_settings_path("invalid");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "not json").unwrap();
+
+        match AppSettings::load_from(&path) {
+            Err(AppError::Settings(_)) => {}
+            other => panic!("Expected Settings error, got {:?}", other),
+        }
+
+        fs::remove_dir_all(path.parent().unwrap()).ok();
+    }
+
+    #[test]
+    fn test_load_missing_fields_uses_defaults() {
+        let path = temp_settings_path("partial");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(
+            &path,
+            r#"{"hotkey_modifiers":6,"hotkey_vk_code":83,"default_save_directory":null,"default_image_format":"Png"}"#,
+        )
+        .unwrap();
+
+        let loaded = AppSettings::load_from(&path).unwrap();
+        assert_eq!(loaded, AppSettings::default());
+
+        fs::remove_dir_all(path.parent().unwrap()).ok();
+    }
+
+    #[test]
+    fn test_validate_rejects_zero_vk_code() {
+        let settings = AppSettings {
+            hotkey_vk_code: 0,
+            ..AppSettings::default()
+        };
+        assert!(settings.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_window_hotkey() {
+        let mut settings = AppSettings::default();
+        settings.window_hotkey.vk_code = 0;
+        assert!(settings.validate().is_err());
+
+        // Same chord as the screen hotkey
+        settings.window_hotkey.vk_code = settings.hotkey_vk_code;
+        assert!(settings.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_cursor_monitor_hotkey() {
+        let mut settings = AppSettings::default();
+        settings.cursor_monitor_hotkey.vk_code = 0;
+        assert!(settings.validate().is_err());
+
+        // Same chord as the window hotkey
+        settings.cursor_monitor_hotkey = settings.window_hotkey;
+        assert!(settings.validate().is_err());
+
+        // Same chord as a preset
+        settings.cursor_monitor_hotkey = Hotkey { modifiers: 0, vk_code: 0x78 };
+        assert!(settings.validate().is_ok());
+        settings.capture_presets.push(crate::presets::CapturePreset {
+            hotkey: Some(settings.cursor_monitor_hotkey),
+            ..Default::default()
+        });
+        assert!(settings.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_color_picker_hotkey() {
+        let mut settings = AppSettings::default();
+        settings.color_picker_hotkey.vk_code = 0;
+        assert!(settings.validate().is_err());
+
+        // Same chord as the screen hotkey
+        settings.color_picker_hotkey = Hotkey { modifiers: settings.hotkey_modifiers, vk_code: settings.hotkey_vk_code };
+        assert!(settings.validate().is_err());
+        settings.color_picker_hotkey = Hotkey::color_picker_default();
+        assert!(settings.validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate_preset_hotkeys() {
+        let mut settings = AppSettings::default();
+        settings.capture_presets.push(crate::presets::CapturePreset {
+            hotkey: Some(settings.window_hotkey),
+            ..Default::default()
+        });
+        assert!(settings.validate().is_err());
+
+        settings.capture_presets[0].hotkey = Some(Hotkey { modifiers: 0, vk_code: 0x78 });
+        assert!(settings.validate().is_ok());
+    }
+
+    #[test]
+    fn test_manager_falls_back_to_defaults() {
+        let path = temp_settings_path("missing");
+        let manager = SettingsManager::with_path(path.clone()).unwrap();
+        assert_eq!(manager.get_settings(), &AppSettings::default());
+        assert_eq!(manager.settings_path(), Some(path.as_path()));
+    }
+
+    #[test]
+    fn test_manager_persists_updates() {
+        let path = temp_settings_path("persist");
+        let mut manager = SettingsManager::with_path(path.clone()).unwrap();
+        manager.update_image_format(ImageFormat::Bmp).unwrap();
+
+        let reloaded = SettingsManager::with_path(path.clone()).unwrap();
+        assert_eq!(reloaded.get_settings().default_image_format, ImageFormat::Bmp);
+
+        let mut reloaded = reloaded;
+        reloaded.reset_to_defaults().unwrap();
+        assert_eq!(reloaded.get_settings(), &AppSettings::default());
+
+        fs::remove_dir_all(path.parent().unwrap()).ok();
+    }
+
+    #[test]
+    fn test_in_memory_manager_does_not_write() {
+        let manager = SettingsManager::in_memory(AppSettings::default());
+        assert!(manager.settings_path().is_none());
+        assert!(manager.save().is_ok());
+    }
+
+    #[test]
+    fn test_save_directory_prefers_configured_directory() {
+        let settings = AppSettings {
+            default_save_directory: Some("D:\\Captures".to_string()),
+            ..AppSettings::default()
+        };
+        assert_eq!(settings.save_directory(), PathBuf::from("D:\\Captures"));
+    }
+
+    #[test]
+    fn test_recent_files_keep_unicode_paths() {
+        let mut settings = AppSettings::default();
+        for i in 0..MAX_RECENT_FILES + 2 {
+            settings.add_recent_file(Path::new(&format!("/写真/スクリーンショット_{}.png", i)));
+        }
+        settings.add_recent_file(Path::new("/写真/スクリーンショット_5.png"));
+        assert_eq!(settings.recent_files.len(), MAX_RECENT_FILES);
+        assert_eq!(settings.recent_files[0], PathBuf::from("/写真/スクリーンショット_5.png"));
+        assert_eq!(settings.recent_files.iter().filter(|p| p.ends_with("スクリーンショット_5.png")).count(), 1);
+
+        let dir = std::env::temp_dir().join(format!("lsa-settings-test-{}", uuid::Uuid::new_v4())).join("設定");
+        let path = dir.join("settings.json");
+        settings.save_to(&path).unwrap();
+        assert_eq!(AppSettings::load_from(&path).unwrap(), settings);
+        fs::remove_dir_all(dir.parent().unwrap()).ok();
+    }
+}
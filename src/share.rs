@@ -0,0 +1,465 @@
+//! Built-in share targets
+//!
+//! A share target posts the flattened image to a chat service: a Slack
+//! channel through `files.upload`, a Discord channel webhook, or a Teams
+//! incoming webhook. Targets are listed in the settings; the bot token or
+//! webhook URL of each is kept in the credential store rather than the
+//! settings file, because either one grants posting to the channel.
+//!
+//! Share targets are post-capture actions, so they run on a worker thread
+//! and report their result the same way plugins do.
+
+use crate::base64;
+use crate::credentials;
+use crate::export;
+use crate::metadata::ImageMetadata;
+use crate::plugins::{ActionInput, ActionOutput, PostCaptureAction};
+use crate::types::{AppError, AppResult, ImageFormat};
+use image::imageops::FilterType;
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Slack API method uploading a file to channels
+const SLACK_UPLOAD_URL: &str = "https://slack.com/api/files.upload";
+
+/// How long a request may take before it is abandoned
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Largest message a Teams incoming webhook accepts, in bytes
+const TEAMS_MAX_MESSAGE_BYTES: usize = 28 * 1024;
+
+/// Room left in a Teams message for the card around the image
+const TEAMS_CARD_BYTES: usize = 2 * 1024;
+
+/// Scales tried in turn until an image fits in a Teams message
+const TEAMS_SCALES: [f32; 6] = [1.0, 0.75, 0.5, 0.35, 0.25, 0.15];
+
+/// Longest server response quoted in an error message
+const MAX_ERROR_CHARS: usize = 500;
+
+/// File name of the uploaded image
+const FILE_NAME: &str = "screenshot.png";
+
+/// Chat services a screenshot can be shared to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind")]
+pub enum ShareService {
+    /// Uploaded to a channel with a bot token
+    Slack {
+        /// Channel name or ID, e.g. `#screenshots`
+        channel: String,
+    },
+    /// Posted to a channel webhook
+    Discord,
+    /// Posted to an incoming webhook as an Adaptive Card
+    Teams,
+}
+
+impl ShareService {
+    /// Get all services, with default fields
+    pub fn all() -> [ShareService; 3] {
+        [ShareService::Slack { channel: String::new() }, ShareService::Discord, ShareService::Teams]
+    }
+
+    /// Describe the secret the service needs
+    pub fn secret_label(&self) -> &'static str {
+        match self {
+            ShareService::Slack { .. } => "Bot token",
+            ShareService::Discord | ShareService::Teams => "Webhook URL",
+        }
+    }
+
+    /// Check that a secret looks like what the service needs
+    pub fn validate_secret(&self, secret: &str) -> AppResult<()> {
+        let secret = secret.trim();
+        match self {
+            ShareService::Slack { .. } if secret.is_empty() => {
+                Err(AppError::Share("Slack のボットトークンを入力してください".to_string()))
+            }
+            ShareService::Slack { .. } => Ok(()),
+            ShareService::Discord | ShareService::Teams if !secret.starts_with("https://") => {
+                Err(AppError::Share(format!("{} の Webhook URL は https:// で始まる必要があります", self)))
+            }
+            ShareService::Discord | ShareService::Teams => Ok(()),
+        }
+    }
+}
+
+impl std::fmt::Display for ShareService {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ShareService::Slack { .. } => write!(f, "Slack"),
+            ShareService::Discord => write!(f, "Discord"),
+            ShareService::Teams => write!(f, "Teams"),
+        }
+    }
+}
+
+/// A configured destination for screenshots
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShareTarget {
+    /// Identifies the target's secret in the credential store, so renaming keeps it
+    #[serde(default = "Uuid::new_v4")]
+    pub id: Uuid,
+    /// Name shown in menus and notifications
+    pub name: String,
+    pub service: ShareService,
+}
+
+impl ShareTarget {
+    /// Create a target with a new identity
+    pub fn new(name: impl Into<String>, service: ShareService) -> Self {
+        Self { id: Uuid::new_v4(), name: name.into(), service }
+    }
+
+    /// Get the key of the target's secret in the credential store
+    pub fn credential_key(&self) -> String {
+        format!("share/{}", self.id)
+    }
+
+    /// Validate a share target
+    pub fn validate(&self) -> AppResult<()> {
+        if self.name.trim().is_empty() {
+            return Err(AppError::Settings("共有先の名前を入力してください".to_string()));
+        }
+        if let ShareService::Slack { channel } = &self.service {
+            if channel.trim().is_empty() {
+                return Err(AppError::Settings(format!("共有先「{}」の Slack チャンネルを入力してください", self.name)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Build the request posting an image with a message
+    ///
+    /// `secret` is the bot token for Slack and the webhook URL otherwise.
+    pub fn request(&self, secret: &str, image: &RgbaImage, message: &str) -> AppResult<ShareRequest> {
+        self.service.validate_secret(secret)?;
+        let secret = secret.trim();
+        let boundary = format!("lsa-{}", Uuid::new_v4().simple());
+        let multipart_type = format!("multipart/form-data; boundary={}", boundary);
+        match &self.service {
+            ShareService::Slack { channel } => {
+                let png = export::encode_image(image, &ImageFormat::Png)?;
+                Ok(ShareRequest {
+                    url: SLACK_UPLOAD_URL.to_string(),
+                    content_type: multipart_type,
+                    authorization: Some(format!("Bearer {}", secret)),
+                    body: multipart(
+                        &boundary,
+                        &[
+                            Part::field("channels", channel.trim()),
+                            Part::field("filename", FILE_NAME),
+                            Part::field("title", message),
+                            Part::file("file", FILE_NAME, "image/png", &png),
+                        ],
+                    ),
+                })
+            }
+            ShareService::Discord => {
+                let png = export::encode_image(image, &ImageFormat::Png)?;
+                // Without `wait` the webhook answers before the message exists, without its attachments
+                let separator = if secret.contains('?') { '&' } else { '?' };
+                let payload = json!({ "content": message }).to_string();
+                Ok(ShareRequest {
+                    url: format!("{}{}wait=true", secret, separator),
+                    content_type: multipart_type,
+                    authorization: None,
+                    body: multipart(
+                        &boundary,
+                        &[
+                            Part::file("payload_json", "", "application/json", payload.as_bytes()),
+                            Part::file("files[0]", FILE_NAME, "image/png", &png),
+                        ],
+                    ),
+                })
+            }
+            ShareService::Teams => {
+                // Incoming webhooks don't take files, so the image goes inline in the card
+                let card = json!({
+                    "type": "message",
+                    "attachments": [{
+                        "contentType": "application/vnd.microsoft.card.adaptive",
+                        "content": {
+                            "$schema": "http://adaptivecards.io/schemas/adaptive-card.json",
+                            "type": "AdaptiveCard",
+                            "version": "1.4",
+                            "body": [
+                                { "type": "TextBlock", "text": message, "wrap": true },
+                                { "type": "Image", "url": teams_image(image)?, "altText": message },
+                            ],
+                        },
+                    }],
+                });
+                Ok(ShareRequest {
+                    url: secret.to_string(),
+                    content_type: "application/json".to_string(),
+                    authorization: None,
+                    body: card.to_string().into_bytes(),
+                })
+            }
+        }
+    }
+
+    /// Interpret a successful response of the service
+    pub fn parse_response(&self, body: &str) -> AppResult<ActionOutput> {
+        match &self.service {
+            ShareService::Slack { channel } => {
+                let response: Value = serde_json::from_str(body)
+                    .map_err(|e| AppError::Share(format!("Slack の応答を解析できません: {}", e)))?;
+                // Slack reports failures with a success status and `ok: false`
+                if response["ok"] != Value::Bool(true) {
+                    let error = response["error"].as_str().unwrap_or("unknown_error");
+                    return Err(AppError::Share(format!("Slack へのアップロードに失敗しました: {}", error)));
+                }
+                Ok(ActionOutput {
+                    url: response["file"]["permalink"].as_str().map(str::to_string),
+                    message: Some(format!("Posted to Slack {}", channel.trim())),
+                })
+            }
+            ShareService::Discord => {
+                let response: Value = serde_json::from_str(body)
+                    .map_err(|e| AppError::Share(format!("Discord の応答を解析できません: {}", e)))?;
+                Ok(ActionOutput {
+                    url: response["attachments"][0]["url"].as_str().map(str::to_string),
+                    message: Some("Posted to Discord".to_string()),
+                })
+            }
+            // Teams answers with `1` or nothing, and gives no link to the message
+            ShareService::Teams => Ok(ActionOutput { url: None, message: Some("Posted to Teams".to_string()) }),
+        }
+    }
+
+    /// Send a request, returning the body of a successful response
+    fn send(&self, request: &ShareRequest) -> AppResult<String> {
+        let mut call = ureq::post(&request.url)
+            .timeout(REQUEST_TIMEOUT)
+            .set("Content-Type", &request.content_type);
+        if let Some(authorization) = &request.authorization {
+            call = call.set("Authorization", authorization);
+        }
+        match call.send_bytes(&request.body) {
+            Ok(response) => Ok(response.into_string()?),
+            Err(ureq::Error::Status(status, response)) => {
+                let body: String = response.into_string().unwrap_or_default().trim().chars().take(MAX_ERROR_CHARS).collect();
+                Err(AppError::Share(format!("{} がエラーを返しました ({}): {}", self.service, status, body)))
+            }
+            // The transport error's own message quotes the URL, which is the secret for webhooks
+            Err(ureq::Error::Transport(transport)) => Err(AppError::Share(format!(
+                "{} に接続できませんでした: {}",
+                self.service,
+                transport.message().map(str::to_string).unwrap_or_else(|| transport.kind().to_string())
+            ))),
+        }
+    }
+}
+
+impl PostCaptureAction for ShareTarget {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn run(&self, input: ActionInput) -> AppResult<ActionOutput> {
+        let secret = credentials::load(&self.credential_key())?.ok_or_else(|| {
+            AppError::Share(format!("共有先「{}」の{}が登録されていません", self.name, self.service.secret_label()))
+        })?;
+        let request = self.request(&secret, input.image, &share_message(input.metadata))?;
+        let body = self.send(&request)?;
+        self.parse_response(&body)
+    }
+}
+
+/// An HTTP POST to a share service
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShareRequest {
+    pub url: String,
+    pub content_type: String,
+    pub authorization: Option<String>,
+    pub body: Vec<u8>,
+}
+
+/// Validate share targets, whose names must be unique
+pub fn validate_share_targets(targets: &[ShareTarget]) -> AppResult<()> {
+    let mut names = HashSet::new();
+    let mut ids = HashSet::new();
+    for target in targets {
+        target.validate()?;
+        if !names.insert(target.name.trim()) || !ids.insert(target.id) {
+            return Err(AppError::Settings(format!("共有先「{}」が重複しています", target.name)));
+        }
+    }
+    Ok(())
+}
+
+/// Get the text posted with an image: its comment, else the captured window, else a generic title
+pub fn share_message(metadata: &ImageMetadata) -> String {
+    [&metadata.comment, &metadata.window_title]
+        .into_iter()
+        .flatten()
+        .find(|text| !text.trim().is_empty())
+        .map_or_else(|| "Screenshot".to_string(), |text| text.clone())
+}
+
+/// Encode an image as a data URL small enough for a Teams message
+///
+/// Screenshots rarely fit as PNG, so they are sent as JPEG, scaled down
+/// until they fit.
+fn teams_image(image: &RgbaImage) -> AppResult<String> {
+    let budget = TEAMS_MAX_MESSAGE_BYTES - TEAMS_CARD_BYTES;
+    let fits = |data: &[u8]| data.len().div_ceil(3) * 4 <= budget;
+    let png = export::encode_image(image, &ImageFormat::Png)?;
+    if fits(&png) {
+        return Ok(format!("data:image/png;base64,{}", base64::encode(&png)));
+    }
+    for scale in TEAMS_SCALES {
+        let width = ((image.width() as f32 * scale).round() as u32).max(1);
+        let height = ((image.height() as f32 * scale).round() as u32).max(1);
+        let scaled = image::imageops::resize(image, width, height, FilterType::Triangle);
+        let jpeg = export::encode_image(&scaled, &ImageFormat::Jpg)?;
+        if fits(&jpeg) {
+            return Ok(format!("data:image/jpeg;base64,{}", base64::encode(&jpeg)));
+        }
+    }
+    Err(AppError::Share(format!(
+        "画像が大きすぎて Teams に送れません (上限 {}KB)",
+        TEAMS_MAX_MESSAGE_BYTES / 1024
+    )))
+}
+
+/// One field of a multipart form
+struct Part<'a> {
+    name: &'a str,
+    /// File name and content type of file fields
+    file: Option<(&'a str, &'a str)>,
+    data: &'a [u8],
+}
+
+impl<'a> Part<'a> {
+    fn field(name: &'a str, value: &'a str) -> Self {
+        Self { name, file: None, data: value.as_bytes() }
+    }
+
+    fn file(name: &'a str, file_name: &'a str, content_type: &'a str, data: &'a [u8]) -> Self {
+        Self { name, file: Some((file_name, content_type)), data }
+    }
+}
+
+/// Encode a `multipart/form-data` body
+fn multipart(boundary: &str, parts: &[Part]) -> Vec<u8> {
+    let mut body = Vec::new();
+    for part in parts {
+        body.extend_from_slice(format!("--{}\r\n", boundary).as_bytes());
+        let disposition = match part.file {
+            // An empty file name marks a field that only needs a content type
+            Some(("", content_type)) => {
+                format!("Content-Disposition: form-data; name=\"{}\"\r\nContent-Type: {}\r\n", part.name, content_type)
+            }
+            Some((file_name, content_type)) => format!(
+                "Content-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\nContent-Type: {}\r\n",
+                part.name, file_name, content_type
+            ),
+            None => format!("Content-Disposition: form-data; name=\"{}\"\r\n", part.name),
+        };
+        body.extend_from_slice(disposition.as_bytes());
+        body.extend_from_slice(b"\r\n");
+        body.extend_from_slice(part.data);
+        body.extend_from_slice(b"\r\n");
+    }
+    body.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::SystemTime;
+
+    fn contains(haystack: &[u8], needle: &str) -> bool {
+        haystack.windows(needle.len()).any(|window| window == needle.as_bytes())
+    }
+
+    #[test]
+    fn test_requests() {
+        let image = RgbaImage::from_pixel(20, 10, image::Rgba([0, 128, 255, 255]));
+
+        let slack = ShareTarget::new("Team", ShareService::Slack { channel: "#shots".to_string() });
+        let request = slack.request(" xoxb-1 ", &image, "Login page").unwrap();
+        assert_eq!(request.url, SLACK_UPLOAD_URL);
+        assert_eq!(request.authorization.as_deref(), Some("Bearer xoxb-1"));
+        let boundary = request.content_type.strip_prefix("multipart/form-data; boundary=").unwrap();
+        assert!(contains(&request.body, "name=\"channels\"\r\n\r\n#shots\r\n"));
+        assert!(contains(&request.body, "name=\"title\"\r\n\r\nLogin page\r\n"));
+        assert!(contains(&request.body, "name=\"file\"; filename=\"screenshot.png\"\r\nContent-Type: image/png\r\n\r\n"));
+        assert!(request.body.ends_with(format!("--{}--\r\n", boundary).as_bytes()));
+
+        let discord = ShareTarget::new("Discord", ShareService::Discord);
+        let request = discord.request("https://discord.com/api/webhooks/1/abc", &image, "Login page").unwrap();
+        assert_eq!(request.url, "https://discord.com/api/webhooks/1/abc?wait=true");
+        assert!(contains(&request.body, "name=\"payload_json\"\r\nContent-Type: application/json\r\n\r\n{\"content\":\"Login page\"}"));
+        assert!(contains(&request.body, "name=\"files[0]\"; filename=\"screenshot.png\""));
+        assert!(discord.request("http://example.com/hook", &image, "x").is_err());
+
+        let teams = ShareTarget::new("Teams", ShareService::Teams);
+        let request = teams.request("https://example.webhook.office.com/abc", &image, "Login page").unwrap();
+        assert_eq!(request.content_type, "application/json");
+        let card: Value = serde_json::from_slice(&request.body).unwrap();
+        let body = &card["attachments"][0]["content"]["body"];
+        assert_eq!(body[0]["text"], "Login page");
+        assert!(body[1]["url"].as_str().unwrap().starts_with("data:image/png;base64,"));
+    }
+
+    #[test]
+    fn test_teams_image_is_scaled_to_fit() {
+        // Noise doesn't compress, so the PNG is far over the limit
+        let mut state = 1u32;
+        let image = RgbaImage::from_fn(400, 300, |_, _| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            let [r, g, b, _] = state.to_le_bytes();
+            image::Rgba([r, g, b, 255])
+        });
+        let url = teams_image(&image).unwrap();
+        assert!(url.starts_with("data:image/jpeg;base64,"));
+        assert!(url.len() <= TEAMS_MAX_MESSAGE_BYTES);
+    }
+
+    #[test]
+    fn test_parse_responses() {
+        let slack = ShareTarget::new("Team", ShareService::Slack { channel: "#shots".to_string() });
+        let output = slack.parse_response(r#"{"ok":true,"file":{"permalink":"https://x.slack.com/files/U1/F1"}}"#).unwrap();
+        assert_eq!(output.url.as_deref(), Some("https://x.slack.com/files/U1/F1"));
+        let error = slack.parse_response(r#"{"ok":false,"error":"not_in_channel"}"#).unwrap_err();
+        assert!(error.to_string().contains("not_in_channel"));
+
+        let discord = ShareTarget::new("Discord", ShareService::Discord);
+        let output = discord.parse_response(r#"{"id":"1","attachments":[{"url":"https://cdn.discordapp.com/a.png"}]}"#).unwrap();
+        assert_eq!(output.url.as_deref(), Some("https://cdn.discordapp.com/a.png"));
+
+        let teams = ShareTarget::new("Teams", ShareService::Teams);
+        assert_eq!(teams.parse_response("1").unwrap().url, None);
+    }
+
+    #[test]
+    fn test_validate_and_message() {
+        let slack = ShareTarget::new("Team", ShareService::Slack { channel: " ".to_string() });
+        assert!(slack.validate().is_err());
+        let discord = ShareTarget::new("Chat", ShareService::Discord);
+        let duplicate = ShareTarget::new(" Chat ", ShareService::Teams);
+        assert!(validate_share_targets(std::slice::from_ref(&discord)).is_ok());
+        assert!(validate_share_targets(&[discord, duplicate]).is_err());
+
+        // Secrets are not part of the settings
+        let json = serde_json::to_string(&ShareTarget::new("Team", ShareService::Slack { channel: "#a".to_string() })).unwrap();
+        assert!(json.contains(r##""service":{"kind":"Slack","channel":"#a"}"##));
+
+        let mut metadata = ImageMetadata::new(SystemTime::UNIX_EPOCH);
+        assert_eq!(share_message(&metadata), "Screenshot");
+        metadata.window_title = Some("Editor".to_string());
+        assert_eq!(share_message(&metadata), "Editor");
+        metadata.comment = Some("Broken layout".to_string());
+        assert_eq!(share_message(&metadata), "Broken layout");
+    }
+}
@@ -0,0 +1,478 @@
+//! Keyboard shortcut management
+//!
+//! This module maps editor actions to key chords. Bindings are persisted in
+//! `AppSettings`, can be rebound from the settings UI, and are checked for
+//! conflicts so that no two actions share the same chord.
+
+use crate::types::{AppError, AppResult};
+use egui::{Context, Key, Modifiers, Vec2};
+use serde::{Deserialize, Serialize};
+
+/// Editor actions that can be bound to a keyboard shortcut
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ShortcutAction {
+    Open,
+    Save,
+    SaveAs,
+    Copy,
+    /// Paste copied annotations, or open an image from the clipboard
+    Paste,
+    Undo,
+    Redo,
+    Delete,
+    /// Duplicate the selected annotations
+    Duplicate,
+    ZoomIn,
+    ZoomOut,
+    ZoomReset,
+    /// Zoom to fit the whole image in the canvas
+    ZoomFit,
+    /// Zoom to frame the selected annotations
+    ZoomToSelection,
+    /// Switch to the next editor tab
+    NextTab,
+    /// Switch to the previous editor tab
+    PreviousTab,
+    CloseTab,
+    /// Switch to the tool in slot 1-9 of the tool panel
+    SwitchTool(u8),
+    /// Add an annotation with the current tool in the middle of the view
+    PlaceAnnotation,
+    /// Select the next annotation, wrapping around
+    SelectNext,
+    /// Move the selected annotations by one pixel
+    Nudge(NudgeDirection),
+    /// Move the selected annotations by a larger step
+    NudgeFar(NudgeDirection),
+}
+
+impl ShortcutAction {
+    /// Get all bindable actions in display order
+    pub fn all() -> Vec<ShortcutAction> {
+        let mut actions = vec![
+            ShortcutAction::Open,
+            ShortcutAction::Save,
+            ShortcutAction::SaveAs,
+            ShortcutAction::Copy,
+            ShortcutAction::Paste,
+            ShortcutAction::Undo,
+            ShortcutAction::Redo,
+            ShortcutAction::Delete,
+            ShortcutAction::Duplicate,
+            ShortcutAction::ZoomIn,
+            ShortcutAction::ZoomOut,
+            ShortcutAction::ZoomReset,
+            ShortcutAction::ZoomFit,
+            ShortcutAction::ZoomToSelection,
+            ShortcutAction::NextTab,
+            ShortcutAction::PreviousTab,
+            ShortcutAction::CloseTab,
+        ];
+        actions.extend((1..=9).map(ShortcutAction::SwitchTool));
+        actions.extend([ShortcutAction::PlaceAnnotation, ShortcutAction::SelectNext]);
+        actions.extend(NudgeDirection::all().map(ShortcutAction::Nudge));
+        actions.extend(NudgeDirection::all().map(ShortcutAction::NudgeFar));
+        actions
+    }
+
+    /// Human-readable action name
+    pub fn label(&self) -> String {
+        match self {
+            ShortcutAction::Open => "Open".to_string(),
+            ShortcutAction::Save => "Save".to_string(),
+            ShortcutAction::SaveAs => "Save As".to_string(),
+            ShortcutAction::Copy => "Copy to Clipboard".to_string(),
+            ShortcutAction::Paste => "Paste".to_string(),
+            ShortcutAction::Undo => "Undo".to_string(),
+            ShortcutAction::Redo => "Redo".to_string(),
+            ShortcutAction::Delete => "Delete".to_string(),
+            ShortcutAction::Duplicate => "Duplicate".to_string(),
+            ShortcutAction::ZoomIn => "Zoom In".to_string(),
+            ShortcutAction::ZoomOut => "Zoom Out".to_string(),
+            ShortcutAction::ZoomReset => "Actual Size".to_string(),
+            ShortcutAction::ZoomFit => "Fit to Screen".to_string(),
+            ShortcutAction::ZoomToSelection => "Zoom to Selection".to_string(),
+            ShortcutAction::NextTab => "Next Tab".to_string(),
+            ShortcutAction::PreviousTab => "Previous Tab".to_string(),
+            ShortcutAction::CloseTab => "Close Tab".to_string(),
+            ShortcutAction::SwitchTool(slot) => format!("Tool {}", slot),
+            ShortcutAction::PlaceAnnotation => "Place Annotation".to_string(),
+            ShortcutAction::SelectNext => "Select Next Annotation".to_string(),
+            ShortcutAction::Nudge(direction) => format!("Nudge {}", direction.label()),
+            ShortcutAction::NudgeFar(direction) => format!("Nudge {} Further", direction.label()),
+        }
+    }
+}
+
+/// Direction of a nudge action
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum NudgeDirection {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+impl NudgeDirection {
+    /// Get all directions in display order
+    pub fn all() -> [NudgeDirection; 4] {
+        [NudgeDirection::Left, NudgeDirection::Right, NudgeDirection::Up, NudgeDirection::Down]
+    }
+
+    /// Human-readable direction name
+    pub fn label(&self) -> &'static str {
+        match self {
+            NudgeDirection::Left => "Left",
+            NudgeDirection::Right => "Right",
+            NudgeDirection::Up => "Up",
+            NudgeDirection::Down => "Down",
+        }
+    }
+
+    /// Get the unit offset in image coordinates, where y grows downward
+    pub fn offset(&self) -> Vec2 {
+        match self {
+            NudgeDirection::Left => Vec2::new(-1.0, 0.0),
+            NudgeDirection::Right => Vec2::new(1.0, 0.0),
+            NudgeDirection::Up => Vec2::new(0.0, -1.0),
+            NudgeDirection::Down => Vec2::new(0.0, 1.0),
+        }
+    }
+
+    /// Get the arrow key bound to the direction by default
+    fn arrow_key(&self) -> Key {
+        match self {
+            NudgeDirection::Left => Key::ArrowLeft,
+            NudgeDirection::Right => Key::ArrowRight,
+            NudgeDirection::Up => Key::ArrowUp,
+            NudgeDirection::Down => Key::ArrowDown,
+        }
+    }
+}
+
+/// A key combined with modifier keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct KeyChord {
+    pub key: Key,
+    #[serde(default)]
+    pub ctrl: bool,
+    #[serde(default)]
+    pub shift: bool,
+    #[serde(default)]
+    pub alt: bool,
+}
+
+impl KeyChord {
+    /// Create a chord without modifiers
+    pub const fn key(key: Key) -> Self {
+        Self { key, ctrl: false, shift: false, alt: false }
+    }
+
+    /// Create a Ctrl+key chord
+    pub const fn ctrl(key: Key) -> Self {
+        Self { key, ctrl: true, shift: false, alt: false }
+    }
+
+    /// Create a Shift+key chord
+    pub const fn shift(key: Key) -> Self {
+        Self { key, ctrl: false, shift: true, alt: false }
+    }
+
+    /// Create a Ctrl+Shift+key chord
+    pub const fn ctrl_shift(key: Key) -> Self {
+        Self { key, ctrl: true, shift: true, alt: false }
+    }
+
+    /// Create a chord from a key and egui modifiers
+    pub fn from_modifiers(key: Key, modifiers: Modifiers) -> Self {
+        Self {
+            key,
+            ctrl: modifiers.ctrl || modifiers.command,
+            shift: modifiers.shift,
+            alt: modifiers.alt,
+        }
+    }
+
+    /// Convert the modifier flags to egui modifiers
+    ///
+    /// Ctrl maps to egui's platform-independent `command` modifier, so the
+    /// same chord means Cmd on macOS.
+    pub fn modifiers(&self) -> Modifiers {
+        Modifiers {
+            alt: self.alt,
+            ctrl: false,
+            shift: self.shift,
+            mac_cmd: false,
+            command: self.ctrl,
+        }
+    }
+}
+
+impl std::fmt::Display for KeyChord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.ctrl {
+            write!(f, "Ctrl+")?;
+        }
+        if self.shift {
+            write!(f, "Shift+")?;
+        }
+        if self.alt {
+            write!(f, "Alt+")?;
+        }
+        write!(f, "{}", self.key.symbol_or_name())
+    }
+}
+
+/// A single action-to-chord binding
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ShortcutBinding {
+    pub action: ShortcutAction,
+    pub chord: KeyChord,
+}
+
+/// Mapping of actions to key chords
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ShortcutMap {
+    bindings: Vec<ShortcutBinding>,
+}
+
+impl Default for ShortcutMap {
+    fn default() -> Self {
+        let mut bindings = vec![
+            ShortcutBinding { action: ShortcutAction::Open, chord: KeyChord::ctrl(Key::O) },
+            ShortcutBinding { action: ShortcutAction::Save, chord: KeyChord::ctrl(Key::S) },
+            ShortcutBinding { action: ShortcutAction::SaveAs, chord: KeyChord::ctrl_shift(Key::S) },
+            ShortcutBinding { action: ShortcutAction::Copy, chord: KeyChord::ctrl(Key::C) },
+            ShortcutBinding { action: ShortcutAction::Paste, chord: KeyChord::ctrl(Key::V) },
+            ShortcutBinding { action: ShortcutAction::Undo, chord: KeyChord::ctrl(Key::Z) },
+            ShortcutBinding { action: ShortcutAction::Redo, chord: KeyChord::ctrl(Key::Y) },
+            ShortcutBinding { action: ShortcutAction::Delete, chord: KeyChord::key(Key::Delete) },
+            ShortcutBinding { action: ShortcutAction::Duplicate, chord: KeyChord::ctrl(Key::D) },
+            ShortcutBinding { action: ShortcutAction::ZoomIn, chord: KeyChord::ctrl(Key::PlusEquals) },
+            ShortcutBinding { action: ShortcutAction::ZoomOut, chord: KeyChord::ctrl(Key::Minus) },
+            ShortcutBinding { action: ShortcutAction::ZoomReset, chord: KeyChord::ctrl(Key::Num1) },
+            ShortcutBinding { action: ShortcutAction::ZoomFit, chord: KeyChord::ctrl(Key::Num0) },
+            ShortcutBinding { action: ShortcutAction::ZoomToSelection, chord: KeyChord::ctrl(Key::Num2) },
+            ShortcutBinding { action: ShortcutAction::NextTab, chord: KeyChord::ctrl(Key::Tab) },
+            ShortcutBinding { action: ShortcutAction::PreviousTab, chord: KeyChord::ctrl_shift(Key::Tab) },
+            ShortcutBinding { action: ShortcutAction::CloseTab, chord: KeyChord::ctrl(Key::F4) },
+        ];
+        let digit_keys = [
+            Key::Num1, Key::Num2, Key::Num3, Key::Num4, Key::Num5,
+            Key::Num6, Key::Num7, Key::Num8, Key::Num9,
+        ];
+        for (slot, key) in (1..=9).zip(digit_keys) {
+            bindings.push(ShortcutBinding {
+                action: ShortcutAction::SwitchTool(slot),
+                chord: KeyChord::key(key),
+            });
+        }
+        bindings.push(ShortcutBinding { action: ShortcutAction::PlaceAnnotation, chord: KeyChord::key(Key::Insert) });
+        bindings.push(ShortcutBinding { action: ShortcutAction::SelectNext, chord: KeyChord::key(Key::N) });
+        for direction in NudgeDirection::all() {
+            let key = direction.arrow_key();
+            bindings.push(ShortcutBinding { action: ShortcutAction::Nudge(direction), chord: KeyChord::key(key) });
+            bindings.push(ShortcutBinding { action: ShortcutAction::NudgeFar(direction), chord: KeyChord::shift(key) });
+        }
+        Self { bindings }
+    }
+}
+
+impl ShortcutMap {
+    /// Get the chord bound to an action
+    pub fn chord_for(&self, action: ShortcutAction) -> Option<KeyChord> {
+        self.bindings
+            .iter()
+            .find(|binding| binding.action == action)
+            .map(|binding| binding.chord)
+    }
+
+    /// Get the action bound to a chord
+    pub fn action_for(&self, chord: &KeyChord) -> Option<ShortcutAction> {
+        self.bindings
+            .iter()
+            .find(|binding| binding.chord == *chord)
+            .map(|binding| binding.action)
+    }
+
+    /// Get the display text for an action's shortcut, or an empty string if unbound
+    pub fn display(&self, action: ShortcutAction) -> String {
+        self.chord_for(action).map(|chord| chord.to_string()).unwrap_or_default()
+    }
+
+    /// Bind a chord to an action, failing if another action already uses it
+    pub fn bind(&mut self, action: ShortcutAction, chord: KeyChord) -> AppResult<()> {
+        if let Some(existing) = self.action_for(&chord) {
+            if existing != action {
+                return Err(AppError::Settings(format!(
+                    "ショートカット {} は既に「{}」に割り当てられています",
+                    chord,
+                    existing.label()
+                )));
+            }
+        }
+
+        self.unbind(action);
+        self.bindings.push(ShortcutBinding { action, chord });
+        Ok(())
+    }
+
+    /// Remove the binding for an action
+    pub fn unbind(&mut self, action: ShortcutAction) {
+        self.bindings.retain(|binding| binding.action != action);
+    }
+
+    /// Find pairs of actions that share a chord (e.g. from a hand-edited settings file)
+    pub fn conflicts(&self) -> Vec<(ShortcutAction, ShortcutAction)> {
+        let mut conflicts = Vec::new();
+        for (i, first) in self.bindings.iter().enumerate() {
+            for second in &self.bindings[i + 1..] {
+                if first.chord == second.chord {
+                    conflicts.push((first.action, second.action));
+                }
+            }
+        }
+        conflicts
+    }
+
+    /// Validate that no two actions share a chord
+    pub fn validate(&self) -> AppResult<()> {
+        match self.conflicts().first() {
+            Some((first, second)) => Err(AppError::Settings(format!(
+                "ショートカットが重複しています: 「{}」と「{}」",
+                first.label(),
+                second.label()
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Collect actions whose shortcuts were pressed this frame, consuming the key events
+    pub fn triggered(&self, ctx: &Context) -> Vec<ShortcutAction> {
+        ctx.input_mut(|input| {
+            self.bindings
+                .iter()
+                .filter(|binding| input.consume_key(binding.chord.modifiers(), binding.chord.key))
+                .map(|binding| binding.action)
+                .collect()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_bindings() {
+        let map = ShortcutMap::default();
+        assert_eq!(map.chord_for(ShortcutAction::Save), Some(KeyChord::ctrl(Key::S)));
+        assert_eq!(map.chord_for(ShortcutAction::Paste), Some(KeyChord::ctrl(Key::V)));
+        assert_eq!(map.chord_for(ShortcutAction::SwitchTool(1)), Some(KeyChord::key(Key::Num1)));
+        assert_eq!(map.chord_for(ShortcutAction::SwitchTool(9)), Some(KeyChord::key(Key::Num9)));
+        assert_eq!(map.action_for(&KeyChord::key(Key::Delete)), Some(ShortcutAction::Delete));
+        assert_eq!(map.chord_for(ShortcutAction::NextTab), Some(KeyChord::ctrl(Key::Tab)));
+        // Ctrl+digit zooms while the plain digit switches tools
+        assert_eq!(map.action_for(&KeyChord::ctrl(Key::Num0)), Some(ShortcutAction::ZoomFit));
+        assert_eq!(map.action_for(&KeyChord::ctrl(Key::Num1)), Some(ShortcutAction::ZoomReset));
+        assert_eq!(map.action_for(&KeyChord::ctrl(Key::PlusEquals)), Some(ShortcutAction::ZoomIn));
+        // Annotations can be placed and selected without a mouse
+        assert_eq!(map.chord_for(ShortcutAction::PlaceAnnotation), Some(KeyChord::key(Key::Insert)));
+        assert_eq!(map.chord_for(ShortcutAction::SelectNext), Some(KeyChord::key(Key::N)));
+        // Arrows nudge the selection, further with Shift
+        assert_eq!(map.action_for(&KeyChord::key(Key::ArrowLeft)), Some(ShortcutAction::Nudge(NudgeDirection::Left)));
+        assert_eq!(map.action_for(&KeyChord::shift(Key::ArrowDown)), Some(ShortcutAction::NudgeFar(NudgeDirection::Down)));
+        assert!(map.conflicts().is_empty());
+        assert!(map.validate().is_ok());
+    }
+
+    #[test]
+    fn test_all_actions_have_default_bindings() {
+        let map = ShortcutMap::default();
+        for action in ShortcutAction::all() {
+            assert!(map.chord_for(action).is_some(), "{:?} should be bound", action);
+        }
+    }
+
+    #[test]
+    fn test_rebind_action() {
+        let mut map = ShortcutMap::default();
+        map.bind(ShortcutAction::Save, KeyChord::ctrl(Key::W)).unwrap();
+        assert_eq!(map.chord_for(ShortcutAction::Save), Some(KeyChord::ctrl(Key::W)));
+        assert_eq!(map.action_for(&KeyChord::ctrl(Key::S)), None);
+
+        // Rebinding to the same chord is not a conflict
+        assert!(map.bind(ShortcutAction::Save, KeyChord::ctrl(Key::W)).is_ok());
+    }
+
+    #[test]
+    fn test_bind_conflict_is_rejected() {
+        let mut map = ShortcutMap::default();
+        let result = map.bind(ShortcutAction::Open, KeyChord::ctrl(Key::S));
+        assert!(matches!(result, Err(AppError::Settings(_))));
+
+        // Original bindings are untouched
+        assert_eq!(map.chord_for(ShortcutAction::Open), Some(KeyChord::ctrl(Key::O)));
+        assert_eq!(map.chord_for(ShortcutAction::Save), Some(KeyChord::ctrl(Key::S)));
+    }
+
+    #[test]
+    fn test_unbind() {
+        let mut map = ShortcutMap::default();
+        map.unbind(ShortcutAction::Delete);
+        assert_eq!(map.chord_for(ShortcutAction::Delete), None);
+        assert_eq!(map.display(ShortcutAction::Delete), "");
+    }
+
+    #[test]
+    fn test_conflict_detection_in_loaded_map() {
+        let json = r#"{"bindings":[
+            {"action":"Save","chord":{"key":"S","ctrl":true}},
+            {"action":"Open","chord":{"key":"S","ctrl":true}}
+        ]}"#;
+        let map: ShortcutMap = serde_json::from_str(json).unwrap();
+        assert_eq!(map.conflicts(), vec![(ShortcutAction::Save, ShortcutAction::Open)]);
+        assert!(map.validate().is_err());
+    }
+
+    #[test]
+    fn test_chord_display() {
+        assert_eq!(KeyChord::ctrl(Key::S).to_string(), "Ctrl+S");
+        assert_eq!(KeyChord::ctrl_shift(Key::S).to_string(), "Ctrl+Shift+S");
+        assert_eq!(KeyChord::key(Key::Delete).to_string(), "Delete");
+    }
+
+    #[test]
+    fn test_chord_from_modifiers() {
+        let chord = KeyChord::from_modifiers(Key::A, Modifiers::CTRL);
+        assert_eq!(chord, KeyChord::ctrl(Key::A));
+        assert!(chord.modifiers().command);
+        assert!(!chord.modifiers().shift);
+    }
+
+    #[test]
+    fn test_shortcut_map_serialization() {
+        let mut map = ShortcutMap::default();
+        map.bind(ShortcutAction::Copy, KeyChord::ctrl_shift(Key::C)).unwrap();
+        let json = serde_json::to_string(&map).unwrap();
+        let restored: ShortcutMap = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, map);
+    }
+
+    #[test]
+    fn test_triggered_consumes_matching_chord() {
+        let ctx = Context::default();
+        let map = ShortcutMap::default();
+        let press = |key, modifiers| egui::Event::Key { key, pressed: true, repeat: false, modifiers };
+
+        ctx.begin_frame(egui::RawInput {
+            events: vec![press(Key::S, Modifiers::COMMAND | Modifiers::SHIFT), press(Key::Num2, Modifiers::NONE)],
+            ..Default::default()
+        });
+        let mut actions = map.triggered(&ctx);
+        actions.sort_by_key(|action| format!("{:?}", action));
+        assert_eq!(actions, vec![ShortcutAction::SaveAs, ShortcutAction::SwitchTool(2)]);
+        let _ = ctx.end_frame();
+    }
+}
@@ -0,0 +1,240 @@
+//! Selection snapping
+//!
+//! While selecting a capture region, the selection corners snap to nearby
+//! edges of UI elements. Edges are found with a fast gradient scan of the
+//! frozen frame, and element bounds from other sources (e.g. accessibility
+//! queries) can be added as rectangles. Holding Alt inverts the snapping
+//! setting for the current drag.
+
+use crate::types::{AppError, AppResult};
+use egui::{Modifiers, Pos2, Rect};
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+
+/// Minimum luminance difference between neighbouring pixels to count as an edge
+const EDGE_THRESHOLD: i16 = 32;
+
+/// Minimum length in pixels of a straight edge worth snapping to
+const MIN_EDGE_LENGTH: u32 = 16;
+
+/// A straight horizontal or vertical edge
+///
+/// `position` is the x coordinate of a vertical edge (or y of a horizontal
+/// one), and `start..end` is the range it spans along the other axis.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EdgeSegment {
+    pub position: f32,
+    pub start: f32,
+    pub end: f32,
+}
+
+impl EdgeSegment {
+    /// Check whether the segment spans `value` along its length, with some slack
+    fn spans(&self, value: f32, slack: f32) -> bool {
+        value >= self.start - slack && value <= self.end + slack
+    }
+}
+
+/// Edges the selection can snap to, in frame pixel coordinates
+#[derive(Debug, Clone, Default)]
+pub struct EdgeMap {
+    vertical: Vec<EdgeSegment>,
+    horizontal: Vec<EdgeSegment>,
+}
+
+impl EdgeMap {
+    /// Create an empty edge map
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Detect long straight edges in a frozen frame
+    pub fn detect(frame: &RgbaImage) -> Self {
+        let gray = image::imageops::grayscale(frame);
+        let (width, height) = gray.dimensions();
+        let luma = |x: u32, y: u32| gray.get_pixel(x, y).0[0] as i16;
+        let mut edges = Self::new();
+
+        // Vertical edges lie between columns x - 1 and x
+        for x in 1..width {
+            let segments = edge_runs(height, |y| (luma(x, y) - luma(x - 1, y)).abs() >= EDGE_THRESHOLD);
+            edges.vertical.extend(segments.map(|(start, end)| EdgeSegment {
+                position: x as f32,
+                start: start as f32,
+                end: end as f32,
+            }));
+        }
+
+        // Horizontal edges lie between rows y - 1 and y
+        for y in 1..height {
+            let segments = edge_runs(width, |x| (luma(x, y) - luma(x, y - 1)).abs() >= EDGE_THRESHOLD);
+            edges.horizontal.extend(segments.map(|(start, end)| EdgeSegment {
+                position: y as f32,
+                start: start as f32,
+                end: end as f32,
+            }));
+        }
+
+        edges
+    }
+
+    /// Add the bounds of a UI element (window, panel, button)
+    pub fn add_rect(&mut self, rect: Rect) {
+        for x in [rect.min.x, rect.max.x] {
+            self.vertical.push(EdgeSegment { position: x, start: rect.min.y, end: rect.max.y });
+        }
+        for y in [rect.min.y, rect.max.y] {
+            self.horizontal.push(EdgeSegment { position: y, start: rect.min.x, end: rect.max.x });
+        }
+    }
+
+    /// Check whether no edges are known
+    pub fn is_empty(&self) -> bool {
+        self.vertical.is_empty() && self.horizontal.is_empty()
+    }
+
+    /// Snap a point to the closest edges within `distance` on each axis
+    pub fn snap_point(&self, point: Pos2, distance: f32) -> Pos2 {
+        Pos2::new(
+            nearest_edge(&self.vertical, point.x, point.y, distance).unwrap_or(point.x),
+            nearest_edge(&self.horizontal, point.y, point.x, distance).unwrap_or(point.y),
+        )
+    }
+
+    /// Snap both corners of a selection
+    pub fn snap_selection(&self, start: Pos2, end: Pos2, distance: f32) -> Rect {
+        Rect::from_two_pos(self.snap_point(start, distance), self.snap_point(end, distance))
+    }
+}
+
+/// Find runs of at least [`MIN_EDGE_LENGTH`] consecutive edge pixels
+fn edge_runs(length: u32, is_edge: impl Fn(u32) -> bool) -> impl Iterator<Item = (u32, u32)> {
+    let mut runs = Vec::new();
+    let mut run_start = None;
+    for i in 0..=length {
+        match (i < length && is_edge(i), run_start) {
+            (true, None) => run_start = Some(i),
+            (false, Some(start)) => {
+                if i - start >= MIN_EDGE_LENGTH {
+                    runs.push((start, i));
+                }
+                run_start = None;
+            }
+            _ => {}
+        }
+    }
+    runs.into_iter()
+}
+
+/// Position of the closest edge to `value` that spans `along`
+fn nearest_edge(edges: &[EdgeSegment], value: f32, along: f32, distance: f32) -> Option<f32> {
+    edges
+        .iter()
+        .filter(|edge| edge.spans(along, distance))
+        .map(|edge| (edge.position, (edge.position - value).abs()))
+        .filter(|&(_, offset)| offset <= distance)
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(position, _)| position)
+}
+
+/// Selection snapping settings
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SnapOptions {
+    /// Whether snapping is on when no modifier is held
+    pub enabled: bool,
+    /// Maximum distance in pixels from the cursor to an edge
+    pub distance: f32,
+}
+
+impl Default for SnapOptions {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            distance: 8.0,
+        }
+    }
+}
+
+impl SnapOptions {
+    /// Check whether snapping applies, with Alt inverting the setting
+    pub fn is_active(&self, modifiers: Modifiers) -> bool {
+        self.enabled != modifiers.alt
+    }
+
+    /// Validate snapping options
+    pub fn validate(&self) -> AppResult<()> {
+        if !(self.distance > 0.0 && self.distance <= 64.0) {
+            return Err(AppError::Settings("スナップ距離は0より大きく64ピクセル以下で指定してください".to_string()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    /// A dark frame with a light "window" from (20, 10) to (60, 50)
+    fn frame_with_window() -> RgbaImage {
+        RgbaImage::from_fn(100, 80, |x, y| {
+            if (20..60).contains(&x) && (10..50).contains(&y) {
+                Rgba([230, 230, 230, 255])
+            } else {
+                Rgba([20, 20, 20, 255])
+            }
+        })
+    }
+
+    #[test]
+    fn test_detect_snaps_to_window_edges() {
+        let edges = EdgeMap::detect(&frame_with_window());
+        assert!(!edges.is_empty());
+
+        assert_eq!(edges.snap_point(Pos2::new(23.0, 13.0), 8.0), Pos2::new(20.0, 10.0));
+        assert_eq!(edges.snap_point(Pos2::new(57.5, 52.0), 8.0), Pos2::new(60.0, 50.0));
+        // Too far from any edge
+        assert_eq!(edges.snap_point(Pos2::new(40.0, 30.0), 8.0), Pos2::new(40.0, 30.0));
+    }
+
+    #[test]
+    fn test_edges_only_snap_along_their_span() {
+        let edges = EdgeMap::detect(&frame_with_window());
+        // Near x = 20 but far below the window, so no vertical edge applies
+        assert_eq!(edges.snap_point(Pos2::new(22.0, 75.0), 8.0).x, 22.0);
+    }
+
+    #[test]
+    fn test_short_or_weak_edges_are_ignored() {
+        let mut frame = RgbaImage::from_pixel(50, 50, Rgba([100, 100, 100, 255]));
+        // A short mark and a faint line
+        for y in 0..5 {
+            frame.put_pixel(25, y, Rgba([255, 255, 255, 255]));
+        }
+        for y in 0..50 {
+            frame.put_pixel(10, y, Rgba([110, 110, 110, 255]));
+        }
+        assert!(EdgeMap::detect(&frame).is_empty());
+    }
+
+    #[test]
+    fn test_element_bounds_and_selection() {
+        let mut edges = EdgeMap::new();
+        edges.add_rect(Rect::from_min_max(Pos2::new(100.0, 100.0), Pos2::new(300.0, 200.0)));
+
+        let selection = edges.snap_selection(Pos2::new(296.0, 203.0), Pos2::new(104.0, 98.0), 8.0);
+        assert_eq!(selection, Rect::from_min_max(Pos2::new(100.0, 100.0), Pos2::new(300.0, 200.0)));
+    }
+
+    #[test]
+    fn test_alt_toggles_snapping() {
+        let options = SnapOptions::default();
+        assert!(options.is_active(Modifiers::NONE));
+        assert!(!options.is_active(Modifiers::ALT));
+
+        let disabled = SnapOptions { enabled: false, ..options };
+        assert!(disabled.is_active(Modifiers::ALT));
+        assert!(SnapOptions { distance: 0.0, ..options }.validate().is_err());
+    }
+}
@@ -0,0 +1,370 @@
+//! Annotation styles, per-tool defaults, and named presets
+//!
+//! Styles describe how new annotations look (stroke, fill, font, alpha).
+//! Each tool has its own default style, and users can save named presets
+//! that override the tool defaults while active.
+
+use crate::types::{AnnotationItem, AnnotationType, AppError, AppResult, Tool};
+use egui::Color32;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Visual style applied to annotations
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnnotationStyle {
+    pub stroke_color: Color32,
+    pub stroke_width: f32,
+    pub fill_color: Option<Color32>,
+    pub font_size: f32,
+    /// Font family of text, or `None` for the default font
+    #[serde(default)]
+    pub font_family: Option<String>,
+    pub text_color: Color32,
+    /// Overall opacity multiplier (0.0-1.0) applied to all colors
+    pub alpha: f32,
+}
+
+impl Default for AnnotationStyle {
+    fn default() -> Self {
+        Self {
+            stroke_color: Color32::RED,
+            stroke_width: 2.0,
+            fill_color: None,
+            font_size: 14.0,
+            font_family: None,
+            text_color: Color32::BLACK,
+            alpha: 1.0,
+        }
+    }
+}
+
+impl AnnotationStyle {
+    /// Get the built-in default style for a tool
+    pub fn default_for(tool: &Tool) -> Self {
+        match tool {
+            Tool::Text => Self {
+                text_color: Color32::BLACK,
+                font_size: 14.0,
+                ..Self::default()
+            },
+            Tool::Sticker => Self {
+                text_color: Color32::BLACK,
+                font_size: 48.0,
+                ..Self::default()
+            },
+            _ => Self::default(),
+        }
+    }
+
+    /// Capture the style of an existing annotation
+    ///
+    /// Fields the annotation does not have are taken from `base`. Colors are
+    /// copied with their alpha baked in, so the returned style has an alpha of 1.0,
+    /// except for images, whose opacity becomes the alpha.
+    pub fn from_annotation(annotation: &AnnotationItem, base: &AnnotationStyle) -> Self {
+        match &annotation.annotation_type {
+            AnnotationType::Rectangle { stroke_color, stroke_width, fill_color, .. } => Self {
+                stroke_color: *stroke_color,
+                stroke_width: *stroke_width,
+                fill_color: *fill_color,
+                alpha: 1.0,
+                ..base.clone()
+            },
+            AnnotationType::Text { font_size, color, format, .. } => Self {
+                font_size: *font_size,
+                font_family: format.font_family.clone(),
+                text_color: *color,
+                alpha: 1.0,
+                ..base.clone()
+            },
+            AnnotationType::Image { opacity, .. } => Self {
+                alpha: *opacity,
+                ..base.clone()
+            },
+            AnnotationType::Dimension { color, width, .. } => Self {
+                stroke_color: *color,
+                stroke_width: *width,
+                alpha: 1.0,
+                ..base.clone()
+            },
+        }
+    }
+
+    /// Apply this style to an annotation, keeping its geometry and content
+    pub fn apply_to(&self, annotation: &mut AnnotationItem) {
+        match &mut annotation.annotation_type {
+            AnnotationType::Rectangle { stroke_color, stroke_width, fill_color, .. } => {
+                *stroke_color = self.with_alpha(self.stroke_color);
+                *stroke_width = self.stroke_width;
+                *fill_color = self.fill_color.map(|color| self.with_alpha(color));
+            }
+            AnnotationType::Text { font_size, color, format, .. } => {
+                *font_size = self.font_size;
+                format.font_family = self.font_family.clone();
+                *color = self.with_alpha(self.text_color);
+            }
+            AnnotationType::Image { opacity, .. } => *opacity = self.alpha,
+            AnnotationType::Dimension { color, width, .. } => {
+                *color = self.with_alpha(self.stroke_color);
+                *width = self.stroke_width;
+            }
+        }
+    }
+
+    /// Validate style values
+    pub fn validate(&self) -> AppResult<()> {
+        if self.stroke_width <= 0.0 || self.font_size <= 0.0 {
+            return Err(AppError::Settings("線幅とフォントサイズは正の値である必要があります".to_string()));
+        }
+        if !(0.0..=1.0).contains(&self.alpha) {
+            return Err(AppError::Settings("不透明度は0.0から1.0の範囲で指定してください".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Multiply a color by the style's alpha
+    fn with_alpha(&self, color: Color32) -> Color32 {
+        if self.alpha >= 1.0 {
+            color
+        } else {
+            color.gamma_multiply(self.alpha.max(0.0))
+        }
+    }
+}
+
+/// A user-defined named style
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StylePreset {
+    pub name: String,
+    pub style: AnnotationStyle,
+}
+
+/// Style configuration persisted in `AppSettings`
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StyleSettings {
+    /// Default style per tool (tools without an entry use built-in defaults)
+    pub tool_defaults: HashMap<Tool, AnnotationStyle>,
+    /// User-defined named presets
+    pub presets: Vec<StylePreset>,
+    /// Name of the preset applied to new annotations, if any
+    pub active_preset: Option<String>,
+}
+
+impl StyleSettings {
+    /// Get the style new annotations created with `tool` should use
+    pub fn style_for_tool(&self, tool: &Tool) -> AnnotationStyle {
+        if let Some(preset) = self.active_preset.as_deref().and_then(|name| self.preset(name)) {
+            return preset.style.clone();
+        }
+        self.tool_default(tool)
+    }
+
+    /// Get the default style for a tool, ignoring any active preset
+    pub fn tool_default(&self, tool: &Tool) -> AnnotationStyle {
+        self.tool_defaults
+            .get(tool)
+            .cloned()
+            .unwrap_or_else(|| AnnotationStyle::default_for(tool))
+    }
+
+    /// Set the default style for a tool
+    pub fn set_tool_default(&mut self, tool: Tool, style: AnnotationStyle) {
+        self.tool_defaults.insert(tool, style);
+    }
+
+    /// Find a preset by name
+    pub fn preset(&self, name: &str) -> Option<&StylePreset> {
+        self.presets.iter().find(|preset| preset.name == name)
+    }
+
+    /// Save a preset, replacing any existing preset with the same name
+    pub fn save_preset(&mut self, name: &str, style: AnnotationStyle) -> AppResult<()> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(AppError::Settings("プリセット名を入力してください".to_string()));
+        }
+        style.validate()?;
+
+        match self.presets.iter_mut().find(|preset| preset.name == name) {
+            Some(existing) => existing.style = style,
+            None => self.presets.push(StylePreset {
+                name: name.to_string(),
+                style,
+            }),
+        }
+        Ok(())
+    }
+
+    /// Remove a preset by name, deactivating it if it was active
+    pub fn remove_preset(&mut self, name: &str) -> bool {
+        let before = self.presets.len();
+        self.presets.retain(|preset| preset.name != name);
+        if self.active_preset.as_deref() == Some(name) {
+            self.active_preset = None;
+        }
+        self.presets.len() != before
+    }
+
+    /// Activate a preset by name, or deactivate presets with `None`
+    pub fn set_active_preset(&mut self, name: Option<&str>) -> AppResult<()> {
+        if let Some(name) = name {
+            if self.preset(name).is_none() {
+                return Err(AppError::Settings(format!("プリセットが見つかりません: {}", name)));
+            }
+        }
+        self.active_preset = name.map(str::to_string);
+        Ok(())
+    }
+
+    /// Validate all stored styles
+    pub fn validate(&self) -> AppResult<()> {
+        for style in self.tool_defaults.values() {
+            style.validate()?;
+        }
+        for preset in &self.presets {
+            preset.style.validate()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use egui::{Pos2, Vec2};
+
+    fn blue_style() -> AnnotationStyle {
+        AnnotationStyle {
+            stroke_color: Color32::BLUE,
+            stroke_width: 5.0,
+            fill_color: Some(Color32::YELLOW),
+            font_size: 24.0,
+            font_family: Some("Consolas".to_string()),
+            text_color: Color32::GREEN,
+            alpha: 1.0,
+        }
+    }
+
+    #[test]
+    fn test_style_for_tool_defaults() {
+        let settings = StyleSettings::default();
+        assert_eq!(settings.style_for_tool(&Tool::Rectangle), AnnotationStyle::default());
+        assert_eq!(settings.style_for_tool(&Tool::Text).font_size, 14.0);
+    }
+
+    #[test]
+    fn test_tool_default_override() {
+        let mut settings = StyleSettings::default();
+        settings.set_tool_default(Tool::Rectangle, blue_style());
+        assert_eq!(settings.style_for_tool(&Tool::Rectangle), blue_style());
+        assert_eq!(settings.style_for_tool(&Tool::Text), AnnotationStyle::default_for(&Tool::Text));
+    }
+
+    #[test]
+    fn test_active_preset_overrides_tool_defaults() {
+        let mut settings = StyleSettings::default();
+        settings.save_preset("Blue", blue_style()).unwrap();
+        settings.set_active_preset(Some("Blue")).unwrap();
+
+        assert_eq!(settings.style_for_tool(&Tool::Rectangle), blue_style());
+        assert_eq!(settings.style_for_tool(&Tool::Text), blue_style());
+
+        settings.set_active_preset(None).unwrap();
+        assert_eq!(settings.style_for_tool(&Tool::Rectangle), AnnotationStyle::default());
+    }
+
+    #[test]
+    fn test_preset_management() {
+        let mut settings = StyleSettings::default();
+        assert!(settings.save_preset("  ", blue_style()).is_err());
+        assert!(settings.set_active_preset(Some("Missing")).is_err());
+
+        settings.save_preset("Blue", AnnotationStyle::default()).unwrap();
+        settings.save_preset("Blue", blue_style()).unwrap();
+        assert_eq!(settings.presets.len(), 1);
+        assert_eq!(settings.preset("Blue").unwrap().style, blue_style());
+
+        settings.set_active_preset(Some("Blue")).unwrap();
+        assert!(settings.remove_preset("Blue"));
+        assert!(settings.active_preset.is_none());
+        assert!(!settings.remove_preset("Blue"));
+    }
+
+    #[test]
+    fn test_apply_style_to_rectangle() {
+        let mut annotation = AnnotationItem::new_rectangle(Pos2::ZERO, Vec2::new(10.0, 10.0));
+        blue_style().apply_to(&mut annotation);
+
+        match annotation.annotation_type {
+            AnnotationType::Rectangle { stroke_color, stroke_width, fill_color, size } => {
+                assert_eq!(stroke_color, Color32::BLUE);
+                assert_eq!(stroke_width, 5.0);
+                assert_eq!(fill_color, Some(Color32::YELLOW));
+                assert_eq!(size, Vec2::new(10.0, 10.0));
+            }
+            _ => panic!("Expected Rectangle annotation type"),
+        }
+    }
+
+    #[test]
+    fn test_apply_style_alpha() {
+        let mut annotation = AnnotationItem::new_text(Pos2::ZERO, "Hi".to_string());
+        let style = AnnotationStyle { alpha: 0.5, ..blue_style() };
+        style.apply_to(&mut annotation);
+
+        match annotation.annotation_type {
+            AnnotationType::Text { color, font_size, ref format, .. } => {
+                assert_eq!(font_size, 24.0);
+                assert_eq!(format.font_family.as_deref(), Some("Consolas"));
+                assert!(color.a() < 255);
+            }
+            _ => panic!("Expected Text annotation type"),
+        }
+
+        // Copying the text's style keeps its font family
+        let copied = AnnotationStyle::from_annotation(&annotation, &AnnotationStyle::default());
+        assert_eq!(copied.font_family.as_deref(), Some("Consolas"));
+    }
+
+    #[test]
+    fn test_copy_and_paste_style_between_annotations() {
+        let mut source = AnnotationItem::new_rectangle(Pos2::ZERO, Vec2::new(10.0, 10.0));
+        blue_style().apply_to(&mut source);
+        let copied = AnnotationStyle::from_annotation(&source, &AnnotationStyle::default());
+
+        let mut target = AnnotationItem::new_rectangle(Pos2::new(50.0, 50.0), Vec2::new(5.0, 5.0));
+        copied.apply_to(&mut target);
+        assert_eq!(target.annotation_type, AnnotationType::Rectangle {
+            size: Vec2::new(5.0, 5.0),
+            stroke_color: Color32::BLUE,
+            stroke_width: 5.0,
+            fill_color: Some(Color32::YELLOW),
+        });
+    }
+
+    #[test]
+    fn test_validate_style() {
+        assert!(AnnotationStyle::default().validate().is_ok());
+        assert!(AnnotationStyle { stroke_width: 0.0, ..AnnotationStyle::default() }.validate().is_err());
+        assert!(AnnotationStyle { alpha: 1.5, ..AnnotationStyle::default() }.validate().is_err());
+    }
+
+    #[test]
+    fn test_style_settings_serialization() {
+        let mut settings = StyleSettings::default();
+        settings.set_tool_default(Tool::Text, blue_style());
+        settings.save_preset("Blue", blue_style()).unwrap();
+        settings.set_active_preset(Some("Blue")).unwrap();
+
+        let json = serde_json::to_string(&settings).unwrap();
+        let restored: StyleSettings = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, settings);
+
+        // Styles saved before font families were stored use the default font
+        let mut value = serde_json::to_value(blue_style()).unwrap();
+        value.as_object_mut().unwrap().remove("font_family");
+        let restored: AnnotationStyle = serde_json::from_value(value).unwrap();
+        assert_eq!(restored.font_family, None);
+    }
+}
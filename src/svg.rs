@@ -0,0 +1,353 @@
+//! SVG export of annotations
+//!
+//! Writes the annotations as editable vector elements over the screenshot,
+//! for further editing in Figma, Inkscape, or a browser. Rectangles become
+//! `<rect>`, text `<text>` with one `<tspan>` per line, stamped images
+//! `<image>`, and dimensions a group of `<line>`s with their label.
+//! Rotations become `rotate` transforms about the same center the renderer
+//! uses, and opacity and blend modes become `opacity` and `mix-blend-mode`.
+//! Text is broken into lines where the renderer wraps it, and text in a box
+//! is grouped with a `<rect>` for the box. The screenshot is embedded as a
+//! Base64 PNG or referenced as a separate file, and sits in its own locked
+//! layer below the annotation layer.
+
+use crate::base64;
+use crate::import::BASELINE_RATIO;
+use crate::measure;
+use crate::render;
+use crate::text::{self, TextAlign};
+use crate::types::{AnnotationItem, AnnotationType, BlendMode};
+use egui::Color32;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+/// Height of a text line as a multiple of the font size
+const LINE_HEIGHT: f32 = 1.2;
+
+/// SVG export settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SvgOptions {
+    /// Embed the screenshot in the SVG instead of writing it to a separate PNG next to it
+    pub embed_image: bool,
+}
+
+impl Default for SvgOptions {
+    fn default() -> Self {
+        Self { embed_image: true }
+    }
+}
+
+/// How the screenshot under the annotations is included
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SvgBackground<'a> {
+    /// PNG bytes embedded as a data URL
+    Embedded(&'a [u8]),
+    /// Path or URL of an image file, relative to the SVG
+    Linked(&'a str),
+    /// Annotations only
+    None,
+}
+
+/// Serialize annotations over a `width` x `height` screenshot as an SVG document
+pub fn to_svg(width: u32, height: u32, annotations: &[AnnotationItem], background: SvgBackground) -> String {
+    let mut svg = String::new();
+    let _ = writeln!(svg, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" xmlns:inkscape="http://www.inkscape.org/namespaces/inkscape" xmlns:sodipodi="http://sodipodi.sourceforge.net/DTD/sodipodi-0.dtd" width="{0}" height="{1}" viewBox="0 0 {0} {1}">"#,
+        width, height
+    );
+    let href = match background {
+        SvgBackground::Embedded(png) => Some(format!("data:image/png;base64,{}", base64::encode(png))),
+        SvgBackground::Linked(path) => Some(escape(path)),
+        SvgBackground::None => None,
+    };
+    if let Some(href) = href {
+        let _ = writeln!(
+            svg,
+            r#"  <g id="screenshot" inkscape:groupmode="layer" inkscape:label="Screenshot" sodipodi:insensitive="true">"#
+        );
+        let _ = writeln!(
+            svg,
+            r#"    <image x="0" y="0" width="{}" height="{}" xlink:href="{}" href="{}"/>"#,
+            width, height, href, href
+        );
+        let _ = writeln!(svg, "  </g>");
+    }
+    let _ = writeln!(svg, r#"  <g id="annotations" inkscape:groupmode="layer" inkscape:label="Annotations">"#);
+    for annotation in annotations {
+        let _ = writeln!(svg, "    {}", element(annotation));
+    }
+    let _ = writeln!(svg, "  </g>");
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Serialize one annotation as an SVG element
+pub fn element(annotation: &AnnotationItem) -> String {
+    let position = annotation.position;
+    // Rotation, opacity, and blending go on the element carrying the annotation's id
+    let mut attributes = if annotation.rotation != 0.0 {
+        let center = annotation.bounds().center();
+        format!(r#" transform="rotate({} {} {})""#, number(annotation.rotation), number(center.x), number(center.y))
+    } else {
+        String::new()
+    };
+    // Images fold the annotation's opacity into their own
+    if annotation.opacity < 1.0 && !matches!(annotation.annotation_type, AnnotationType::Image { .. }) {
+        let _ = write!(attributes, r#" opacity="{}""#, number(annotation.opacity));
+    }
+    match annotation.blend_mode {
+        BlendMode::Normal => {}
+        BlendMode::Multiply => attributes.push_str(r#" style="mix-blend-mode:multiply""#),
+        BlendMode::Screen => attributes.push_str(r#" style="mix-blend-mode:screen""#),
+    }
+    let id = format!("annotation-{}", annotation.id);
+    match &annotation.annotation_type {
+        AnnotationType::Rectangle { size, stroke_color, stroke_width, fill_color } => {
+            let stroke = if *stroke_width > 0.0 {
+                format!(r#"{} stroke-width="{}""#, paint("stroke", *stroke_color), number(*stroke_width))
+            } else {
+                r#"stroke="none""#.to_string()
+            };
+            let fill = fill_color.map_or_else(|| r#"fill="none""#.to_string(), |fill| paint("fill", fill));
+            format!(
+                r#"<rect id="{}" x="{}" y="{}" width="{}" height="{}" {} {}{}/>"#,
+                id,
+                number(position.x),
+                number(position.y),
+                number(size.x),
+                number(size.y),
+                stroke,
+                fill,
+                attributes
+            )
+        }
+        AnnotationType::Text { content, font_size, color, format } => {
+            // Lines are broken where the renderer wraps them, since SVG text doesn't wrap
+            let (wrapped, size) = render::formatted_text_lines(content, *font_size, format);
+            // SVG positions text by its baseline, annotations by their top-left corner
+            let anchor = position + text::text_anchor(size, *font_size, format);
+            let x = number(anchor.x);
+            let baseline = |index: usize| number(anchor.y + font_size * (BASELINE_RATIO + LINE_HEIGHT * index as f32));
+            let mut lines = String::new();
+            for (index, line) in wrapped.iter().enumerate() {
+                let _ = write!(lines, r#"<tspan x="{}" y="{}">{}</tspan>"#, x, baseline(index), escape(line));
+            }
+
+            let family = match &format.font_family {
+                Some(family) => format!("{}, sans-serif", escape(family)),
+                None => "sans-serif".to_string(),
+            };
+            let mut style = String::new();
+            if format.bold {
+                style.push_str(r#" font-weight="bold""#);
+            }
+            if format.italic {
+                style.push_str(r#" font-style="italic""#);
+            }
+            match format.align {
+                TextAlign::Left => {}
+                TextAlign::Center => style.push_str(r#" text-anchor="middle""#),
+                TextAlign::Right => style.push_str(r#" text-anchor="end""#),
+            }
+            // A boxed text is grouped with its box, which carries the id and rotation
+            let (text_id, text_transform) =
+                if format.has_box() { (String::new(), "") } else { (format!(r#" id="{}""#, id), attributes.as_str()) };
+            let text = format!(
+                r#"<text{} x="{}" y="{}" xml:space="preserve" font-family="{}" font-size="{}"{} {}{}>{}</text>"#,
+                text_id,
+                x,
+                baseline(0),
+                family,
+                number(*font_size),
+                style,
+                paint("fill", *color),
+                text_transform,
+                lines
+            );
+            if !format.has_box() {
+                return text;
+            }
+
+            let fill = format.background.map_or_else(|| r#"fill="none""#.to_string(), |fill| paint("fill", fill));
+            let stroke = match format.outline {
+                Some(outline) => format!(r#"{} stroke-width="{}""#, paint("stroke", outline.color), number(outline.width)),
+                None => r#"stroke="none""#.to_string(),
+            };
+            format!(
+                r#"<g id="{}"{}><rect x="{}" y="{}" width="{}" height="{}" {} {}/>{}</g>"#,
+                id,
+                attributes,
+                number(position.x),
+                number(position.y),
+                number(size.x),
+                number(size.y),
+                stroke,
+                fill,
+                text
+            )
+        }
+        AnnotationType::Image { data, size, opacity } => format!(
+            r#"<image id="{}" x="{}" y="{}" width="{}" height="{}" preserveAspectRatio="none" opacity="{}" xlink:href="data:image/png;base64,{}"{}/>"#,
+            id,
+            number(position.x),
+            number(position.y),
+            number(size.x),
+            number(size.y),
+            number(*opacity * annotation.opacity),
+            base64::encode(data),
+            attributes
+        ),
+        AnnotationType::Dimension { end, color, width, .. } => {
+            let (start, end) = (position, position + *end);
+            let [first, last] = measure::ticks(start, end);
+            let stroke = format!(r#"{} stroke-width="{}" stroke-linecap="round""#, paint("stroke", *color), number(*width));
+            let mut lines = String::new();
+            for [a, b] in [[start, end], first, last] {
+                let _ = write!(
+                    lines,
+                    r#"<line x1="{}" y1="{}" x2="{}" y2="{}" {}/>"#,
+                    number(a.x),
+                    number(a.y),
+                    number(b.x),
+                    number(b.y),
+                    stroke
+                );
+            }
+
+            let label = render::dimension_label(annotation);
+            let format = measure::label_format();
+            let font_size = measure::LABEL_FONT_SIZE;
+            let rect = measure::label_rect(start, end, *width, render::measure_formatted_text(&label, font_size, &format));
+            let anchor = rect.min + text::text_anchor(rect.size(), font_size, &format);
+            format!(
+                r#"<g id="{}"{}>{}<rect x="{}" y="{}" width="{}" height="{}" stroke="none" {}/><text x="{}" y="{}" font-family="sans-serif" font-size="{}" {}>{}</text></g>"#,
+                id,
+                attributes,
+                lines,
+                number(rect.min.x),
+                number(rect.min.y),
+                number(rect.width()),
+                number(rect.height()),
+                paint("fill", format.background.unwrap_or(Color32::WHITE)),
+                number(anchor.x),
+                number(anchor.y + font_size * BASELINE_RATIO),
+                number(font_size),
+                paint("fill", *color),
+                escape(&label)
+            )
+        }
+    }
+}
+
+/// Format a paint attribute, with a separate opacity for translucent colors
+fn paint(name: &str, color: Color32) -> String {
+    let [r, g, b, a] = color.to_srgba_unmultiplied();
+    let mut attribute = format!(r##"{}="#{:02x}{:02x}{:02x}""##, name, r, g, b);
+    if a < 255 {
+        let _ = write!(attribute, r#" {}-opacity="{}""#, name, number(a as f32 / 255.0));
+    }
+    attribute
+}
+
+/// Format a number without trailing zeros
+fn number(value: f32) -> String {
+    let text = format!("{:.3}", value);
+    let text = text.trim_end_matches('0').trim_end_matches('.');
+    if text == "-0" { "0".to_string() } else { text.to_string() }
+}
+
+/// Escape text for use in XML content and attribute values
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::import::import_svg;
+    use crate::text::TextFormat;
+    use egui::{Pos2, Vec2};
+
+    #[test]
+    fn test_annotation_elements() {
+        let mut rectangle = AnnotationItem::new_rectangle(Pos2::new(10.0, 20.0), Vec2::new(30.5, 40.0));
+        if let AnnotationType::Rectangle { fill_color, .. } = &mut rectangle.annotation_type {
+            *fill_color = Some(Color32::from_rgba_unmultiplied(255, 255, 0, 128));
+        }
+        rectangle.rotation = 90.0;
+        let element = element(&rectangle);
+        assert!(element.starts_with(r#"<rect id="annotation-"#));
+        assert!(element.contains(
+            r##"x="10" y="20" width="30.5" height="40" stroke="#ff0000" stroke-width="2" fill="#ffff00" fill-opacity="0.502" transform="rotate(90 25.25 40)"/>"##
+        ));
+
+        let text = AnnotationItem::new_text(Pos2::new(5.0, 0.0), "a < b\n& \"c\"".to_string());
+        let element = super::element(&text);
+        assert!(element.contains(r##"x="5" y="11.2" xml:space="preserve" font-family="sans-serif" font-size="14" fill="#000000"><tspan x="5" y="11.2">a &lt; b</tspan><tspan x="5" y="28">&amp; &quot;c&quot;</tspan>"##));
+
+        let mut boxed = AnnotationItem::new_text(Pos2::new(5.0, 0.0), "Note".to_string());
+        if let AnnotationType::Text { format, .. } = &mut boxed.annotation_type {
+            *format = TextFormat {
+                bold: true,
+                italic: true,
+                font_family: Some("Segoe UI".to_string()),
+                background: Some(Color32::YELLOW),
+                outline: Some(egui::Stroke::new(2.0, Color32::RED)),
+                ..TextFormat::default()
+            };
+        }
+        let element = super::element(&boxed);
+        assert!(element.starts_with(r#"<g id="annotation-"#));
+        assert!(element.contains(r##"<rect x="5" y="0" "##));
+        assert!(element.contains(r##"stroke="#ff0000" stroke-width="2" fill="#ffff00"/><text x="9" y="15.2" "##));
+        assert!(element.contains(r#"font-family="Segoe UI, sans-serif" font-size="14" font-weight="bold" font-style="italic""#));
+
+        let dimension = AnnotationItem::new_dimension(Pos2::new(0.0, 50.0), Pos2::new(100.0, 50.0), None);
+        let element = super::element(&dimension);
+        assert!(element.contains(r##"<line x1="0" y1="50" x2="100" y2="50" stroke="#ff0000" stroke-width="2" stroke-linecap="round"/>"##));
+        assert!(element.contains(r##"font-size="12" fill="#ff0000">100 px, 0.0°</text></g>"##));
+
+        boxed.opacity = 0.5;
+        boxed.blend_mode = BlendMode::Multiply;
+        let element = super::element(&boxed);
+        assert!(element.contains(r#"" opacity="0.5" style="mix-blend-mode:multiply"><rect "#));
+    }
+
+    #[test]
+    fn test_round_trip_through_import() {
+        let mut rectangle = AnnotationItem::new_rectangle(Pos2::new(10.0, 20.0), Vec2::new(30.0, 40.0));
+        if let AnnotationType::Rectangle { stroke_color, .. } = &mut rectangle.annotation_type {
+            *stroke_color = Color32::from_rgb(0, 0, 255);
+        }
+        let text = AnnotationItem::new_text(Pos2::new(5.0, 60.0), "Click here".to_string());
+        let svg = to_svg(200, 100, &[rectangle.clone(), text.clone()], SvgBackground::Linked("shot & co.png"));
+        assert!(svg.contains(r#"href="shot &amp; co.png""#));
+        assert!(svg.contains(r#"width="200" height="100" viewBox="0 0 200 100""#));
+
+        // The screenshot is not an annotation, so it is skipped on import
+        let imported = import_svg(&svg).unwrap();
+        assert_eq!(imported.annotations.len(), 2);
+        assert_eq!(imported.annotations[0].position, rectangle.position);
+        assert_eq!(imported.annotations[0].annotation_type, rectangle.annotation_type);
+        // The baseline round trip loses a little precision
+        assert!((imported.annotations[1].position - text.position).length() < 0.001);
+        assert_eq!(imported.annotations[1].annotation_type, text.annotation_type);
+
+        let embedded = to_svg(200, 100, &[], SvgBackground::Embedded(&[1, 2, 3]));
+        assert!(embedded.contains("data:image/png;base64,AQID"));
+        assert!(!to_svg(200, 100, &[], SvgBackground::None).contains("<image"));
+    }
+}
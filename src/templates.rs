@@ -0,0 +1,221 @@
+//! Annotation templates
+//!
+//! A template is a named set of annotations saved for reuse, such as a bug
+//! report header made of a text box, a company logo, and a red frame. The
+//! templates are kept in `templates.json` in the config directory, in the
+//! same serialized form as annotations in project files, and applying one
+//! adds copies of its annotations to the current image.
+
+use crate::files::write_atomic;
+use crate::settings::config_dir;
+use crate::types::{AnnotationItem, AppError, AppResult};
+use egui::{Rect, Vec2};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Templates file in the config directory
+const TEMPLATES_FILE_NAME: &str = "templates.json";
+
+/// Longest template name in characters
+pub const MAX_TEMPLATE_NAME_CHARS: usize = 64;
+
+/// Get the default templates file
+pub fn default_templates_path() -> PathBuf {
+    config_dir().join(TEMPLATES_FILE_NAME)
+}
+
+/// A named set of annotations
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnnotationTemplate {
+    pub name: String,
+    /// Annotations in image pixels of the image they were saved from
+    pub annotations: Vec<AnnotationItem>,
+}
+
+impl AnnotationTemplate {
+    /// Create a template from annotations, stored unselected
+    pub fn new(name: &str, annotations: &[AnnotationItem]) -> AppResult<Self> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(AppError::Settings("テンプレート名を入力してください".to_string()));
+        }
+        if name.chars().count() > MAX_TEMPLATE_NAME_CHARS {
+            return Err(AppError::Settings(format!(
+                "テンプレート名は{}文字以内で入力してください",
+                MAX_TEMPLATE_NAME_CHARS
+            )));
+        }
+        if annotations.is_empty() {
+            return Err(AppError::Settings("テンプレートに保存する注釈がありません".to_string()));
+        }
+        let annotations = annotations
+            .iter()
+            .cloned()
+            .map(|mut annotation| {
+                annotation.is_selected = false;
+                annotation
+            })
+            .collect();
+        Ok(Self {
+            name: name.to_string(),
+            annotations,
+        })
+    }
+
+    /// Get the bounds of all annotations
+    pub fn bounds(&self) -> Rect {
+        self.annotations.iter().map(AnnotationItem::bounds).fold(Rect::NOTHING, Rect::union)
+    }
+
+    /// Get copies of the annotations with new ids to add to an image of `image_size`
+    ///
+    /// The annotations keep their positions, unless they would reach past the
+    /// image; then they are moved together as far as needed to stay inside,
+    /// keeping the top-left corner on the image if they don't fit.
+    pub fn instantiate(&self, image_size: Vec2) -> Vec<AnnotationItem> {
+        let bounds = self.bounds();
+        let overflow = (bounds.max.to_vec2() - image_size).max(Vec2::ZERO);
+        let offset = -overflow.min(bounds.min.to_vec2().max(Vec2::ZERO));
+        self.annotations.iter().map(|annotation| annotation.duplicate(offset)).collect()
+    }
+}
+
+/// On-disk form of the templates file
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct TemplatesFile {
+    templates: Vec<AnnotationTemplate>,
+}
+
+/// The saved templates and their file
+#[derive(Debug)]
+pub struct TemplateLibrary {
+    path: PathBuf,
+    templates: Vec<AnnotationTemplate>,
+}
+
+impl TemplateLibrary {
+    /// Load templates from `path`, starting empty if the file doesn't exist
+    pub fn load(path: PathBuf) -> AppResult<Self> {
+        let json = match std::fs::read_to_string(&path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self { path, templates: Vec::new() }),
+            Err(e) => return Err(e.into()),
+        };
+        let file: TemplatesFile = serde_json::from_str(&json)
+            .map_err(|e| AppError::Settings(format!("テンプレートファイルの解析に失敗しました: {}", e)))?;
+        Ok(Self {
+            path,
+            templates: file.templates,
+        })
+    }
+
+    /// Get the file the templates are kept in
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get all templates in the order they were saved
+    pub fn templates(&self) -> &[AnnotationTemplate] {
+        &self.templates
+    }
+
+    /// Find a template by name
+    pub fn get(&self, name: &str) -> Option<&AnnotationTemplate> {
+        self.templates.iter().find(|template| template.name == name)
+    }
+
+    /// Save a template, replacing one with the same name
+    pub fn save(&mut self, template: AnnotationTemplate) -> AppResult<()> {
+        match self.templates.iter_mut().find(|existing| existing.name == template.name) {
+            Some(existing) => *existing = template,
+            None => self.templates.push(template),
+        }
+        self.write()
+    }
+
+    /// Delete a template by name
+    ///
+    /// Returns whether a template was deleted.
+    pub fn remove(&mut self, name: &str) -> AppResult<bool> {
+        let count = self.templates.len();
+        self.templates.retain(|template| template.name != name);
+        if self.templates.len() == count {
+            return Ok(false);
+        }
+        self.write()?;
+        Ok(true)
+    }
+
+    /// Write all templates to the file
+    fn write(&self) -> AppResult<()> {
+        let file = TemplatesFile {
+            templates: self.templates.clone(),
+        };
+        let json = serde_json::to_string_pretty(&file)
+            .map_err(|e| AppError::Settings(format!("テンプレートのシリアライズに失敗しました: {}", e)))?;
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        write_atomic(&self.path, json.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use egui::Pos2;
+
+    fn header() -> Vec<AnnotationItem> {
+        let mut frame = AnnotationItem::new_rectangle(Pos2::new(10.0, 10.0), Vec2::new(200.0, 40.0));
+        frame.is_selected = true;
+        vec![frame, AnnotationItem::new_text(Pos2::new(20.0, 20.0), "Bug report".to_string())]
+    }
+
+    #[test]
+    fn test_template_round_trip() {
+        let dir = std::env::temp_dir().join(format!("templates_test_{}", uuid::Uuid::new_v4()));
+        let path = dir.join(TEMPLATES_FILE_NAME);
+        let mut library = TemplateLibrary::load(path.clone()).unwrap();
+        assert!(library.templates().is_empty());
+
+        let template = AnnotationTemplate::new(" Bug report header ", &header()).unwrap();
+        assert_eq!(template.name, "Bug report header");
+        assert!(template.annotations.iter().all(|a| !a.is_selected));
+        library.save(template.clone()).unwrap();
+
+        // Saving under the same name replaces the template
+        let replacement = AnnotationTemplate::new("Bug report header", &header()[..1]).unwrap();
+        library.save(replacement).unwrap();
+        let reloaded = TemplateLibrary::load(path.clone()).unwrap();
+        assert_eq!(reloaded.templates().len(), 1);
+        assert_eq!(reloaded.get("Bug report header").unwrap().annotations.len(), 1);
+
+        library.remove("Bug report header").unwrap();
+        assert!(TemplateLibrary::load(path).unwrap().templates().is_empty());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_rejects_empty_names_and_templates() {
+        assert!(AnnotationTemplate::new("  ", &header()).is_err());
+        assert!(AnnotationTemplate::new(&"x".repeat(MAX_TEMPLATE_NAME_CHARS + 1), &header()).is_err());
+        assert!(AnnotationTemplate::new("Empty", &[]).is_err());
+    }
+
+    #[test]
+    fn test_instantiate_keeps_annotations_on_the_image() {
+        let template = AnnotationTemplate::new("Frame", &header()[..1]).unwrap();
+
+        let copies = template.instantiate(Vec2::new(800.0, 600.0));
+        assert_eq!(copies[0].position, Pos2::new(10.0, 10.0));
+        assert_ne!(copies[0].id, template.annotations[0].id);
+
+        // The frame reaches 210 px, so a narrower image moves it left
+        let copies = template.instantiate(Vec2::new(150.0, 600.0));
+        assert_eq!(copies[0].position, Pos2::new(0.0, 10.0));
+
+        let copies = template.instantiate(Vec2::new(205.0, 600.0));
+        assert_eq!(copies[0].position, Pos2::new(5.0, 10.0));
+    }
+}
@@ -0,0 +1,514 @@
+//! Rich text formatting
+//!
+//! Text annotations can be bold or italic, use a font installed on the
+//! system, sit in a filled and outlined box, and wrap at a set width with
+//! their lines aligned left, centered, or right. Text is laid out with egui's
+//! text layout, so the canvas and the flatten renderer break lines the same
+//! way. Bold and italic use the installed variants of a font when it has
+//! them and are synthesized otherwise. Emoji and symbols a font doesn't have
+//! come from the installed emoji fonts, then egui's built-in ones. Installed
+//! fonts are read from the Windows registry; other platforms only have the
+//! built-in fonts.
+
+use crate::types::{AppError, AppResult};
+use egui::epaint::text::{FontData, FontDefinitions, FontFamily, FontId, LayoutJob};
+use egui::{Color32, Stroke, Vec2};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Padding between the text and its box, relative to the font size
+const BOX_PADDING_RATIO: f32 = 0.3;
+
+/// Horizontal shear of synthesized italics, relative to the glyph height (matching egui)
+pub const ITALIC_SHEAR: f32 = 0.25;
+
+/// Narrowest wrap width
+pub const MIN_WRAP_WIDTH: f32 = 8.0;
+
+/// Horizontal alignment of text lines
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum TextAlign {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+impl TextAlign {
+    /// Get all alignments
+    pub fn all() -> [TextAlign; 3] {
+        [TextAlign::Left, TextAlign::Center, TextAlign::Right]
+    }
+
+    /// Get the fraction of the box width lines are aligned at
+    pub fn factor(self) -> f32 {
+        match self {
+            TextAlign::Left => 0.0,
+            TextAlign::Center => 0.5,
+            TextAlign::Right => 1.0,
+        }
+    }
+
+    fn to_egui(self) -> egui::Align {
+        match self {
+            TextAlign::Left => egui::Align::Min,
+            TextAlign::Center => egui::Align::Center,
+            TextAlign::Right => egui::Align::Max,
+        }
+    }
+}
+
+impl std::fmt::Display for TextAlign {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TextAlign::Left => write!(f, "Left"),
+            TextAlign::Center => write!(f, "Center"),
+            TextAlign::Right => write!(f, "Right"),
+        }
+    }
+}
+
+/// Formatting of a text annotation beyond its size and color
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TextFormat {
+    pub bold: bool,
+    pub italic: bool,
+    /// Installed font family, the built-in font if unset
+    pub font_family: Option<String>,
+    /// Fill of the box behind the text
+    pub background: Option<Color32>,
+    /// Outline of the box around the text
+    pub outline: Option<Stroke>,
+    pub align: TextAlign,
+    /// Width in image pixels at which lines wrap; lines only break at newlines if unset
+    pub wrap_width: Option<f32>,
+}
+
+impl TextFormat {
+    /// Check whether the text is drawn in a box
+    pub fn has_box(&self) -> bool {
+        self.background.is_some() || self.outline.is_some()
+    }
+
+    /// Get the space between the text and the edge of its box
+    pub fn padding(&self, font_size: f32) -> f32 {
+        if self.has_box() {
+            (font_size * BOX_PADDING_RATIO).round()
+        } else {
+            0.0
+        }
+    }
+
+    /// Get the wrap width actually used for layout
+    fn layout_wrap_width(&self) -> Option<f32> {
+        self.wrap_width.map(|width| width.max(MIN_WRAP_WIDTH))
+    }
+}
+
+/// Build the layout of a text annotation at `scale` screen pixels per image pixel
+///
+/// `family` is the family the annotation's font face is registered under.
+/// Lines are aligned around x = 0 of the galley for centered and
+/// right-aligned text; [`text_anchor`] gives where that is in the box.
+pub fn layout_job(
+    content: &str,
+    font_size: f32,
+    color: Color32,
+    format: &TextFormat,
+    family: FontFamily,
+    italics: bool,
+    scale: f32,
+) -> LayoutJob {
+    let mut job = LayoutJob::single_section(
+        content.to_owned(),
+        egui::text::TextFormat {
+            font_id: FontId::new(font_size * scale, family),
+            color,
+            italics,
+            ..Default::default()
+        },
+    );
+    job.wrap.max_width = format.layout_wrap_width().map_or(f32::INFINITY, |width| width * scale);
+    job.halign = format.align.to_egui();
+    job
+}
+
+/// Get the size of a text annotation's box from the size of its laid-out text
+///
+/// Wrapped text is as wide as its wrap width even when its lines are shorter.
+pub fn box_size(text_size: Vec2, font_size: f32, format: &TextFormat) -> Vec2 {
+    let width = format.layout_wrap_width().map_or(text_size.x, |width| width.max(text_size.x));
+    Vec2::new(width, text_size.y) + Vec2::splat(2.0 * format.padding(font_size))
+}
+
+/// Get the offset from the top-left corner of a text box to the point its lines are aligned at
+pub fn text_anchor(box_size: Vec2, font_size: f32, format: &TextFormat) -> Vec2 {
+    let padding = format.padding(font_size);
+    Vec2::new(padding + format.align.factor() * (box_size.x - 2.0 * padding), padding)
+}
+
+/// Estimate the size of a text annotation's box without laying it out
+///
+/// Characters are assumed to be 0.6 font sizes wide and lines 1.2 font
+/// sizes high.
+pub fn approximate_size(content: &str, font_size: f32, format: &TextFormat) -> Vec2 {
+    let char_width = font_size * 0.6;
+    let (width, lines) = match format.layout_wrap_width() {
+        Some(wrap_width) => {
+            let lines: usize = content
+                .split('\n')
+                .map(|line| ((line.len() as f32 * char_width / wrap_width).ceil() as usize).max(1))
+                .sum();
+            (wrap_width, lines)
+        }
+        None => {
+            let longest = content.split('\n').map(str::len).max().unwrap_or(0);
+            (longest as f32 * char_width, content.split('\n').count())
+        }
+    };
+    Vec2::new(width, lines as f32 * font_size * 1.2) + Vec2::splat(2.0 * format.padding(font_size))
+}
+
+/// Get how far synthesized bold text is drawn a second time to the right
+pub fn bold_offset(font_size: f32) -> f32 {
+    (font_size / 24.0).max(1.0)
+}
+
+/// A font file installed on the system
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstalledFont {
+    /// Face name, e.g. "Arial Bold"
+    pub name: String,
+    pub path: PathBuf,
+    /// Index of the face in a font collection
+    pub index: u32,
+}
+
+/// The installed face that draws a format, and the styles left to synthesize
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ResolvedFace {
+    /// Installed face, the built-in font if `None`
+    pub face: Option<InstalledFont>,
+    pub synthetic_bold: bool,
+    pub synthetic_italic: bool,
+}
+
+impl ResolvedFace {
+    /// Get how much wider synthesized styles make the text
+    pub fn extra_width(&self, font_size: f32) -> f32 {
+        let mut extra = 0.0;
+        if self.synthetic_bold {
+            extra += bold_offset(font_size);
+        }
+        if self.synthetic_italic {
+            extra += font_size * ITALIC_SHEAR;
+        }
+        extra
+    }
+}
+
+/// Choose the installed face for a format
+///
+/// The family's bold, italic, or bold italic face is used when installed;
+/// styles the chosen face lacks are synthesized. A family that isn't
+/// installed falls back to the built-in font.
+pub fn resolve_face(format: &TextFormat, fonts: &[InstalledFont]) -> ResolvedFace {
+    let unresolved = ResolvedFace {
+        face: None,
+        synthetic_bold: format.bold,
+        synthetic_italic: format.italic,
+    };
+    let Some(family) = &format.font_family else {
+        return unresolved;
+    };
+
+    let mut candidates = Vec::new();
+    if format.bold && format.italic {
+        candidates.push((format!("{} Bold Italic", family), true, true));
+    }
+    if format.bold {
+        candidates.push((format!("{} Bold", family), true, false));
+    }
+    if format.italic {
+        candidates.push((format!("{} Italic", family), false, true));
+    }
+    candidates.push((family.clone(), false, false));
+
+    candidates
+        .into_iter()
+        .find_map(|(name, has_bold, has_italic)| {
+            let font = fonts.iter().find(|font| font.name.eq_ignore_ascii_case(&name))?;
+            Some(ResolvedFace {
+                face: Some(font.clone()),
+                synthetic_bold: format.bold && !has_bold,
+                synthetic_italic: format.italic && !has_italic,
+            })
+        })
+        .unwrap_or(unresolved)
+}
+
+/// Style suffixes of faces that are variants of a family
+const STYLE_SUFFIXES: [&str; 4] = [" Bold Italic", " Bold", " Italic", " Oblique"];
+
+/// Get the installed font families, without their bold and italic faces
+pub fn font_families(fonts: &[InstalledFont]) -> Vec<String> {
+    let mut families: Vec<String> = fonts
+        .iter()
+        .filter(|font| !STYLE_SUFFIXES.iter().any(|suffix| font.name.ends_with(suffix)))
+        .map(|font| font.name.clone())
+        .collect();
+    families.sort_by_key(|name| name.to_lowercase());
+    families.dedup();
+    families
+}
+
+/// Parse a font entry of the Windows registry into its faces
+///
+/// Names look like "Arial Bold (TrueType)"; collections list their faces
+/// separated by " & ", in the order they are stored. Files are relative to
+/// the system font folder unless the path is absolute. Bitmap and vector
+/// fonts, which egui can't draw, are skipped.
+pub fn parse_font_entry(name: &str, file: &str, fonts_dir: &Path) -> Vec<InstalledFont> {
+    let path = Path::new(file);
+    let supported = path.extension().and_then(|ext| ext.to_str()).is_some_and(|ext| {
+        ["ttf", "otf", "ttc", "otc"].iter().any(|supported| ext.eq_ignore_ascii_case(supported))
+    });
+    if !supported {
+        return Vec::new();
+    }
+    let path = if path.is_absolute() { path.to_path_buf() } else { fonts_dir.join(path) };
+    let name = match name.rfind(" (") {
+        Some(end) if name.ends_with(')') => &name[..end],
+        _ => name,
+    };
+    name.split(" & ")
+        .map(str::trim)
+        .filter(|face| !face.is_empty())
+        .enumerate()
+        .map(|(index, face)| InstalledFont { name: face.to_string(), path: path.clone(), index: index as u32 })
+        .collect()
+}
+
+/// Get the fonts installed on the system, read once
+pub fn installed_fonts() -> &'static [InstalledFont] {
+    static FONTS: OnceLock<Vec<InstalledFont>> = OnceLock::new();
+    FONTS.get_or_init(|| {
+        let mut fonts = read_installed_fonts();
+        fonts.sort_by_key(|font| font.name.to_lowercase());
+        fonts.dedup_by(|a, b| a.name.eq_ignore_ascii_case(&b.name));
+        fonts
+    })
+}
+
+/// Read the font entries of the machine and the current user from the registry
+#[cfg(windows)]
+fn read_installed_fonts() -> Vec<InstalledFont> {
+    use winapi::shared::minwindef::{DWORD, HKEY};
+    use winapi::um::winnt::{KEY_READ, REG_SZ};
+    use winapi::um::winreg::{RegCloseKey, RegEnumValueW, RegOpenKeyExW, HKEY_CURRENT_USER, HKEY_LOCAL_MACHINE};
+
+    const FONTS_KEY: &str = r"SOFTWARE\Microsoft\Windows NT\CurrentVersion\Fonts";
+    let fonts_dir = std::env::var_os("WINDIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(r"C:\Windows"))
+        .join("Fonts");
+    let key_name = crate::files::to_wide(FONTS_KEY);
+
+    let mut fonts = Vec::new();
+    for root in [HKEY_LOCAL_MACHINE, HKEY_CURRENT_USER] {
+        let mut key: HKEY = std::ptr::null_mut();
+        // SAFETY: the key name is null-terminated and `key` receives the opened handle
+        if unsafe { RegOpenKeyExW(root, key_name.as_ptr(), 0, KEY_READ, &mut key) } != 0 {
+            continue;
+        }
+        for index in 0.. {
+            let mut name = vec![0u16; 16_384];
+            let mut name_len = name.len() as DWORD;
+            let mut data = vec![0u16; 1_024];
+            let mut data_size = (data.len() * 2) as DWORD;
+            let mut kind: DWORD = 0;
+            // SAFETY: the buffer lengths are passed in characters for the name and bytes for the data
+            let status = unsafe {
+                RegEnumValueW(
+                    key,
+                    index,
+                    name.as_mut_ptr(),
+                    &mut name_len,
+                    std::ptr::null_mut(),
+                    &mut kind,
+                    data.as_mut_ptr().cast(),
+                    &mut data_size,
+                )
+            };
+            if status != 0 {
+                break;
+            }
+            if kind != REG_SZ {
+                continue;
+            }
+            let name = String::from_utf16_lossy(&name[..name_len as usize]);
+            let data = &data[..data_size as usize / 2];
+            let length = data.iter().position(|&c| c == 0).unwrap_or(data.len());
+            fonts.extend(parse_font_entry(&name, &String::from_utf16_lossy(&data[..length]), &fonts_dir));
+        }
+        // SAFETY: the key was opened above
+        unsafe { RegCloseKey(key) };
+    }
+    fonts
+}
+
+/// Read the installed fonts
+///
+/// Only Windows fonts are listed, so other platforms use the built-in font.
+#[cfg(not(windows))]
+fn read_installed_fonts() -> Vec<InstalledFont> {
+    Vec::new()
+}
+
+/// Load an installed font's data
+pub fn load_font(font: &InstalledFont) -> AppResult<FontData> {
+    let data = std::fs::read(&font.path).map_err(|e| {
+        AppError::FileAccess(std::io::Error::new(e.kind(), format!("フォント {} を読み込めません: {}", font.name, e)))
+    })?;
+    let mut font_data = FontData::from_owned(data);
+    font_data.index = font.index;
+    Ok(font_data)
+}
+
+/// First of egui's built-in emoji fonts
+const BUILTIN_EMOJI_FONT: &str = "NotoEmoji-Regular";
+
+/// Installed fonts tried, in order, for emoji and symbols a text's font doesn't have
+pub const EMOJI_FALLBACK_FACES: [&str; 2] = ["Segoe UI Emoji", "Segoe UI Symbol"];
+
+/// Get the installed fonts of the emoji fallback chain, loaded once
+///
+/// The fonts are large and every font set gets a copy of its definitions,
+/// so their data is kept for the rest of the process and shared.
+fn emoji_fallbacks() -> &'static [(&'static str, FontData)] {
+    static FALLBACKS: OnceLock<Vec<(&'static str, FontData)>> = OnceLock::new();
+    FALLBACKS.get_or_init(|| {
+        EMOJI_FALLBACK_FACES
+            .iter()
+            .filter_map(|name| installed_fonts().iter().find(|font| font.name.eq_ignore_ascii_case(name)))
+            .filter_map(|font| match std::fs::read(&font.path) {
+                Ok(data) => {
+                    let mut font_data = FontData::from_static(Box::leak(data.into_boxed_slice()));
+                    font_data.index = font.index;
+                    Some((font.name.as_str(), font_data))
+                }
+                Err(e) => {
+                    log::warn!("Skipping emoji font {}: {}", font.path.display(), e);
+                    None
+                }
+            })
+            .collect()
+    })
+}
+
+/// Get the built-in fonts with installed faces added as families of their own
+///
+/// The installed emoji fonts are tried ahead of the built-in emoji fonts
+/// in every family. Each face is registered
+/// under its name, followed by the fallbacks of the proportional family for
+/// the characters it doesn't have.
+pub fn font_definitions<'a>(faces: impl IntoIterator<Item = (&'a str, FontData)>) -> FontDefinitions {
+    let mut definitions = FontDefinitions::default();
+    for (name, data) in emoji_fallbacks() {
+        definitions.font_data.insert(name.to_string(), data.clone());
+        for family in definitions.families.values_mut() {
+            let builtin_emoji = family.iter().position(|font| font == BUILTIN_EMOJI_FONT).unwrap_or(family.len());
+            family.insert(builtin_emoji, name.to_string());
+        }
+    }
+    let fallback = definitions.families.get(&FontFamily::Proportional).cloned().unwrap_or_default();
+    for (name, data) in faces {
+        definitions.font_data.insert(name.to_string(), data);
+        let mut family = vec![name.to_string()];
+        family.extend(fallback.iter().filter(|fallback| *fallback != name).cloned());
+        definitions.families.insert(FontFamily::Name(name.into()), family);
+    }
+    definitions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn font(name: &str) -> InstalledFont {
+        InstalledFont { name: name.to_string(), path: PathBuf::from(format!("{}.ttf", name)), index: 0 }
+    }
+
+    #[test]
+    fn test_parse_font_entry() {
+        let dir = Path::new("fonts");
+        assert_eq!(parse_font_entry("Arial Bold (TrueType)", "arialbd.ttf", dir), vec![InstalledFont {
+            name: "Arial Bold".to_string(),
+            path: dir.join("arialbd.ttf"),
+            index: 0,
+        }]);
+        let collection = parse_font_entry("Cambria & Cambria Math (TrueType)", "cambria.ttc", dir);
+        let faces: Vec<(&str, u32)> = collection.iter().map(|font| (font.name.as_str(), font.index)).collect();
+        assert_eq!(faces, vec![("Cambria", 0), ("Cambria Math", 1)]);
+        assert!(parse_font_entry("Modern (All res)", "modern.fon", dir).is_empty());
+    }
+
+    #[test]
+    fn test_resolve_face() {
+        let fonts = [font("Arial"), font("Arial Bold"), font("Arial Italic"), font("Consolas")];
+        let format = |family: &str, bold, italic| TextFormat {
+            font_family: Some(family.to_string()),
+            bold,
+            italic,
+            ..TextFormat::default()
+        };
+
+        // Installed variants are used, missing ones are synthesized
+        let bold_italic = resolve_face(&format("Arial", true, true), &fonts);
+        assert_eq!(bold_italic.face.unwrap().name, "Arial Bold");
+        assert!(!bold_italic.synthetic_bold && bold_italic.synthetic_italic);
+        let italic = resolve_face(&format("arial", false, true), &fonts);
+        assert_eq!((italic.face.unwrap().name.as_str(), italic.synthetic_italic), ("Arial Italic", false));
+        let bold = resolve_face(&format("Consolas", true, false), &fonts);
+        assert_eq!((bold.face.unwrap().name.as_str(), bold.synthetic_bold), ("Consolas", true));
+
+        // Missing families and the default font fall back to the built-in font
+        assert_eq!(resolve_face(&format("Missing", false, false), &fonts), ResolvedFace::default());
+        let plain = TextFormat { bold: true, ..TextFormat::default() };
+        assert_eq!(resolve_face(&plain, &fonts), ResolvedFace { synthetic_bold: true, ..ResolvedFace::default() });
+
+        assert_eq!(font_families(&fonts), vec!["Arial".to_string(), "Consolas".to_string()]);
+    }
+
+    #[test]
+    fn test_font_definitions() {
+        let definitions = font_definitions([("Test Face", FontData::from_static(&[]))]);
+        let family = &definitions.families[&FontFamily::Name("Test Face".into())];
+        // Characters the face lacks fall back to the proportional family's fonts
+        assert_eq!(family[0], "Test Face");
+        assert_eq!(family[1..], definitions.families[&FontFamily::Proportional][..]);
+        assert!(definitions.font_data.contains_key("Test Face"));
+    }
+
+    #[test]
+    fn test_box_geometry() {
+        let plain = TextFormat::default();
+        // Plain single-line text keeps the original approximation
+        assert_eq!(approximate_size("Hello", 10.0, &plain), Vec2::new(30.0, 12.0));
+
+        let boxed = TextFormat {
+            background: Some(Color32::YELLOW),
+            align: TextAlign::Center,
+            wrap_width: Some(100.0),
+            ..TextFormat::default()
+        };
+        assert_eq!(boxed.padding(10.0), 3.0);
+        // Two 60px lines at 10px per character wrap into one and two lines
+        assert_eq!(approximate_size("aaaaaaaaaa\naaaaaaaaaaaaaaaaaaaa", 10.0, &boxed), Vec2::new(106.0, 42.0));
+        let size = box_size(Vec2::new(40.0, 12.0), 10.0, &boxed);
+        assert_eq!(size, Vec2::new(106.0, 18.0));
+        assert_eq!(text_anchor(size, 10.0, &boxed), Vec2::new(53.0, 3.0));
+    }
+}
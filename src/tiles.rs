@@ -0,0 +1,561 @@
+//! Tiled texture upload
+//!
+//! Large images (4K, stitched, and multi-monitor captures) are split into
+//! tiles that are uploaded a few per frame, so showing a new image never
+//! blocks the UI for a long upload. This also keeps each texture below GPU
+//! size limits. Only tiles inside the clip rect are painted.
+//!
+//! egui textures have no mipmaps, so zooming far out of a large image would
+//! skip most of its pixels and shimmer. Downscaled copies of the image (mip
+//! levels) are built on the CPU and tiled the same way; the level closest to
+//! the zoom is painted. The smallest level is uploaded first, so a blurry
+//! preview shows right away while the full-resolution tiles follow.
+//!
+//! When the pixels change but the size does not, e.g. after blurring a
+//! region or applying adjustments, the existing textures are kept on screen
+//! and only the changed parts of each tile are uploaded again.
+//!
+//! Zoomed-in pixels are either smoothed or kept as crisp squares for pixel
+//! inspection, depending on the texture filter chosen in the settings.
+
+use egui::{Color32, Context, Painter, Pos2, Rect, TextureFilter as Filter, TextureHandle, TextureOptions, Vec2};
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+
+/// Edge length of a texture tile in pixels
+pub const TILE_SIZE: u32 = 1024;
+
+/// Maximum number of tiles uploaded per frame
+pub const TILE_UPLOADS_PER_FRAME: usize = 4;
+
+/// Most downscaled levels below the full image; the editor zooms out to 10%, just under 1/8
+const MAX_MIP_LEVELS: usize = 3;
+
+/// Levels are halved until no side is longer than this
+const MIP_MIN_SIZE: u32 = 512;
+
+/// Pixel bounds of a tile within the image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TileRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl TileRect {
+    /// Get the pixels touched by a rect in image coordinates, clamped at the origin
+    pub fn covering(rect: Rect) -> TileRect {
+        let (x, y) = (rect.min.x.max(0.0).floor() as u32, rect.min.y.max(0.0).floor() as u32);
+        let right = (rect.max.x.max(0.0).ceil() as u32).max(x);
+        let bottom = (rect.max.y.max(0.0).ceil() as u32).max(y);
+        TileRect { x, y, width: right - x, height: bottom - y }
+    }
+
+    /// Get the pixels covered by both rects, if any
+    pub fn intersect(&self, other: &TileRect) -> Option<TileRect> {
+        let (x, y) = (self.x.max(other.x), self.y.max(other.y));
+        let right = (self.x + self.width).min(other.x + other.width);
+        let bottom = (self.y + self.height).min(other.y + other.height);
+        (right > x && bottom > y).then(|| TileRect { x, y, width: right - x, height: bottom - y })
+    }
+
+    /// Get the smallest rect covering both rects
+    pub fn union(&self, other: &TileRect) -> TileRect {
+        let (x, y) = (self.x.min(other.x), self.y.min(other.y));
+        let right = (self.x + self.width).max(other.x + other.width);
+        let bottom = (self.y + self.height).max(other.y + other.height);
+        TileRect { x, y, width: right - x, height: bottom - y }
+    }
+
+    /// Get the pixels of the next smaller mip level computed from this rect
+    fn halved(&self) -> TileRect {
+        let (x, y) = (self.x / 2, self.y / 2);
+        TileRect {
+            x,
+            y,
+            width: (self.x + self.width).div_ceil(2) - x,
+            height: (self.y + self.height).div_ceil(2) - y,
+        }
+    }
+}
+
+/// Split an image into tiles of at most `tile_size` pixels, row by row
+pub fn tile_rects(width: u32, height: u32, tile_size: u32) -> Vec<TileRect> {
+    let tile_size = tile_size.max(1);
+    let mut rects = Vec::new();
+    for y in (0..height).step_by(tile_size as usize) {
+        for x in (0..width).step_by(tile_size as usize) {
+            rects.push(TileRect {
+                x,
+                y,
+                width: tile_size.min(width - x),
+                height: tile_size.min(height - y),
+            });
+        }
+    }
+    rects
+}
+
+/// Halve an image, averaging each 2x2 block; odd edges repeat the last row or column
+pub fn half_size(image: &RgbaImage) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    let (half_width, half_height) = (width.div_ceil(2).max(1), height.div_ceil(2).max(1));
+    RgbaImage::from_fn(half_width, half_height, |x, y| {
+        let (x0, y0) = ((x * 2).min(width - 1), (y * 2).min(height - 1));
+        let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+        let mut sum = [0u32; 4];
+        for pixel in [image.get_pixel(x0, y0), image.get_pixel(x1, y0), image.get_pixel(x0, y1), image.get_pixel(x1, y1)] {
+            for (total, value) in sum.iter_mut().zip(pixel.0) {
+                *total += value as u32;
+            }
+        }
+        image::Rgba(sum.map(|total| ((total + 2) / 4) as u8))
+    })
+}
+
+/// Copy part of an image for upload
+fn color_image(image: &RgbaImage, rect: TileRect) -> egui::ColorImage {
+    let pixels = image::imageops::crop_imm(image, rect.x, rect.y, rect.width, rect.height).to_image();
+    egui::ColorImage::from_rgba_unmultiplied([rect.width as usize, rect.height as usize], pixels.as_raw())
+}
+
+/// Build the full image followed by its downscaled levels
+fn mip_images(image: RgbaImage) -> Vec<RgbaImage> {
+    let mut images = vec![image];
+    while images.len() <= MAX_MIP_LEVELS {
+        let last = &images[images.len() - 1];
+        if last.width().max(last.height()) <= MIP_MIN_SIZE {
+            break;
+        }
+        images.push(half_size(last));
+    }
+    images
+}
+
+/// Get the mip level to paint at a display scale (1.0 is full size)
+///
+/// Each level halves the image, so the level is the number of halvings that
+/// still keeps at least one texel per screen pixel.
+pub fn level_for_scale(scale: f32, levels: usize) -> usize {
+    if levels == 0 || scale.is_nan() || scale <= 0.0 || scale >= 1.0 {
+        return 0;
+    }
+    ((1.0 / scale).log2().floor() as usize).min(levels - 1)
+}
+
+/// How the image texture is sampled when zoomed in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum TextureFilter {
+    /// Smooth interpolation between pixels
+    #[default]
+    Linear,
+    /// Crisp square pixels, for inspecting individual pixels
+    Nearest,
+}
+
+impl TextureFilter {
+    /// Get all filters in menu order
+    pub fn all() -> [TextureFilter; 2] {
+        [TextureFilter::Linear, TextureFilter::Nearest]
+    }
+
+    /// Get the egui texture options of the filter
+    ///
+    /// Zooming out always interpolates, since skipping pixels would shimmer.
+    pub fn options(self) -> TextureOptions {
+        let magnification = match self {
+            TextureFilter::Linear => Filter::Linear,
+            TextureFilter::Nearest => Filter::Nearest,
+        };
+        TextureOptions {
+            magnification,
+            minification: Filter::Linear,
+        }
+    }
+}
+
+impl std::fmt::Display for TextureFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TextureFilter::Linear => write!(f, "Smooth (Linear)"),
+            TextureFilter::Nearest => write!(f, "Crisp Pixels (Nearest)"),
+        }
+    }
+}
+
+/// A tile of a level and its texture
+struct Tile {
+    rect: TileRect,
+    texture: Option<TextureHandle>,
+    /// Pixels changed since the texture was uploaded, in level coordinates
+    dirty: Option<TileRect>,
+}
+
+impl Tile {
+    fn is_pending(&self) -> bool {
+        self.texture.is_none() || self.dirty.is_some()
+    }
+}
+
+/// One resolution of the image, uploaded as tiles
+struct TileLevel {
+    /// Pixels kept until every tile of the level is uploaded
+    image: Option<RgbaImage>,
+    size: [u32; 2],
+    tiles: Vec<Tile>,
+}
+
+impl TileLevel {
+    fn new(image: RgbaImage, tile_size: u32) -> Self {
+        let size = [image.width(), image.height()];
+        let tiles = tile_rects(size[0], size[1], tile_size)
+            .into_iter()
+            .map(|rect| Tile { rect, texture: None, dirty: None })
+            .collect();
+        Self {
+            image: Some(image),
+            size,
+            tiles,
+        }
+    }
+
+    /// Number of tiles not uploaded yet or changed since
+    fn pending(&self) -> usize {
+        self.tiles.iter().filter(|tile| tile.is_pending()).count()
+    }
+
+    fn is_complete(&self) -> bool {
+        self.pending() == 0
+    }
+
+    /// Check whether every tile has a texture to paint, if possibly an outdated one
+    fn has_textures(&self) -> bool {
+        self.tiles.iter().all(|tile| tile.texture.is_some())
+    }
+
+    /// Replace the level's pixels, marking the tiles touching `dirty` for upload
+    fn update(&mut self, image: RgbaImage, dirty: TileRect) {
+        for tile in &mut self.tiles {
+            if let Some(changed) = tile.rect.intersect(&dirty) {
+                tile.dirty = Some(tile.dirty.map_or(changed, |previous| previous.union(&changed)));
+            }
+        }
+        self.image = Some(image);
+    }
+
+    /// Upload up to `max_tiles` pending tiles, returning how many were uploaded
+    ///
+    /// Tiles with a texture only upload their changed pixels.
+    fn upload_next(&mut self, ctx: &Context, level: usize, max_tiles: usize, options: TextureOptions) -> usize {
+        let Some(image) = self.image.as_ref() else {
+            return 0;
+        };
+
+        let mut count = 0;
+        for (index, tile) in self.tiles.iter_mut().enumerate().filter(|(_, tile)| tile.is_pending()).take(max_tiles) {
+            let dirty = tile.dirty.take();
+            match (&mut tile.texture, dirty) {
+                (Some(texture), Some(dirty)) => {
+                    let offset = [(dirty.x - tile.rect.x) as usize, (dirty.y - tile.rect.y) as usize];
+                    texture.set_partial(offset, color_image(image, dirty), options);
+                }
+                (texture, _) => {
+                    let name = format!("screenshot_tile_{}_{}", level, index);
+                    *texture = Some(ctx.load_texture(name, color_image(image, tile.rect), options));
+                }
+            }
+            count += 1;
+        }
+
+        if self.is_complete() {
+            // The CPU copy is no longer needed
+            self.image = None;
+        }
+        count
+    }
+
+    /// Paint the uploaded tiles scaled into `image_rect`, skipping tiles outside the clip rect
+    fn paint(&self, painter: &Painter, image_rect: Rect) {
+        let scale = image_rect.size() / Vec2::new(self.size[0] as f32, self.size[1] as f32);
+        let uv = Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0));
+        for tile in &self.tiles {
+            let Some(texture) = &tile.texture else {
+                continue;
+            };
+            let rect = tile.rect;
+            let min = image_rect.min + Vec2::new(rect.x as f32, rect.y as f32) * scale;
+            let tile_rect = Rect::from_min_size(min, Vec2::new(rect.width as f32, rect.height as f32) * scale);
+            if painter.clip_rect().intersects(tile_rect) {
+                painter.image(texture.id(), tile_rect, uv, Color32::WHITE);
+            }
+        }
+    }
+
+    fn memory_usage(&self) -> usize {
+        let uploaded: usize = self
+            .tiles
+            .iter()
+            .filter(|tile| tile.texture.is_some())
+            .map(|tile| tile.rect.width as usize * tile.rect.height as usize * 4)
+            .sum();
+        uploaded + self.image.as_ref().map_or(0, |image| image.as_raw().len())
+    }
+}
+
+/// An image uploaded to the GPU as a grid of textures, with downscaled levels
+pub struct TiledTexture {
+    size: [u32; 2],
+    /// Full resolution first, then each level half the size of the one before
+    levels: Vec<TileLevel>,
+    filter: TextureFilter,
+}
+
+impl std::fmt::Debug for TiledTexture {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TiledTexture")
+            .field("size", &self.size)
+            .field("levels", &self.levels.len())
+            .field("filter", &self.filter)
+            .field("tiles", &self.levels.iter().map(|level| level.tiles.len()).sum::<usize>())
+            .field("pending", &self.levels.iter().map(TileLevel::pending).sum::<usize>())
+            .finish()
+    }
+}
+
+impl TiledTexture {
+    /// Prepare an image for upload in tiles of [`TILE_SIZE`]
+    pub fn new(image: RgbaImage) -> Self {
+        Self::with_tile_size(image, TILE_SIZE)
+    }
+
+    /// Prepare an image for upload in tiles of at most `tile_size` pixels
+    ///
+    /// Used with the GPU's maximum texture size when it is below [`TILE_SIZE`].
+    pub fn with_tile_size(image: RgbaImage, tile_size: u32) -> Self {
+        let size = [image.width(), image.height()];
+        Self {
+            size,
+            levels: mip_images(image).into_iter().map(|image| TileLevel::new(image, tile_size)).collect(),
+            filter: TextureFilter::default(),
+        }
+    }
+
+    /// Set the filter the tiles are uploaded with
+    pub fn with_filter(mut self, filter: TextureFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Filter the tiles are uploaded with
+    pub fn filter(&self) -> TextureFilter {
+        self.filter
+    }
+
+    /// Image size in pixels
+    pub fn size(&self) -> [u32; 2] {
+        self.size
+    }
+
+    /// Image size as a vector
+    pub fn size_vec2(&self) -> Vec2 {
+        Vec2::new(self.size[0] as f32, self.size[1] as f32)
+    }
+
+    /// Number of resolutions, including the full one
+    pub fn level_count(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// Check whether every tile of every level has been uploaded
+    pub fn is_complete(&self) -> bool {
+        self.levels.iter().all(TileLevel::is_complete)
+    }
+
+    /// Fraction of tiles uploaded so far
+    pub fn progress(&self) -> f32 {
+        let total: usize = self.levels.iter().map(|level| level.tiles.len()).sum();
+        if total == 0 {
+            1.0
+        } else {
+            1.0 - self.levels.iter().map(TileLevel::pending).sum::<usize>() as f32 / total as f32
+        }
+    }
+
+    /// Replace the image with changed pixels of the same size
+    ///
+    /// Only the tiles touching `dirty` (the whole image if `None`) are
+    /// uploaded again, and until they are, the old pixels stay on screen.
+    /// Returns `false`, changing nothing, if the size differs; the texture
+    /// must then be created anew.
+    pub fn update(&mut self, image: RgbaImage, dirty: Option<TileRect>) -> bool {
+        if [image.width(), image.height()] != self.size {
+            return false;
+        }
+        let whole = TileRect { x: 0, y: 0, width: self.size[0], height: self.size[1] };
+        let Some(mut dirty) = dirty.map_or(Some(whole), |dirty| dirty.intersect(&whole)) else {
+            return true;
+        };
+        for (level, image) in self.levels.iter_mut().zip(mip_images(image)) {
+            level.update(image, dirty);
+            dirty = dirty.halved();
+        }
+        true
+    }
+
+    /// Upload up to `max_tiles` pending tiles, smallest level first, returning how many were uploaded
+    pub fn upload_next(&mut self, ctx: &Context, max_tiles: usize) -> usize {
+        let mut count = 0;
+        for (index, level) in self.levels.iter_mut().enumerate().rev() {
+            if count == max_tiles {
+                break;
+            }
+            count += level.upload_next(ctx, index, max_tiles - count, self.filter.options());
+        }
+        count
+    }
+
+    /// Paint the image scaled into `image_rect`, skipping tiles outside the clip rect
+    ///
+    /// The level matching the scale is painted over the smaller levels while
+    /// its tiles are still uploading, so there are no holes.
+    pub fn paint(&self, painter: &Painter, image_rect: Rect) {
+        let scale = image_rect.width() / self.size[0].max(1) as f32;
+        let target = level_for_scale(scale, self.levels.len());
+        let first = if self.levels[target].has_textures() {
+            target
+        } else {
+            self.levels.len() - 1
+        };
+        for level in self.levels[target..=first].iter().rev() {
+            level.paint(painter, image_rect);
+        }
+    }
+
+    /// Approximate bytes held by uploaded textures and pending pixels
+    pub fn memory_usage(&self) -> usize {
+        self.levels.iter().map(TileLevel::memory_usage).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tile_rects_cover_image() {
+        let rects = tile_rects(2500, 1100, 1024);
+        assert_eq!(rects.len(), 6);
+        assert_eq!(rects[0], TileRect { x: 0, y: 0, width: 1024, height: 1024 });
+        assert_eq!(rects[2], TileRect { x: 2048, y: 0, width: 452, height: 1024 });
+        assert_eq!(rects[5], TileRect { x: 2048, y: 1024, width: 452, height: 76 });
+
+        let area: u32 = rects.iter().map(|rect| rect.width * rect.height).sum();
+        assert_eq!(area, 2500 * 1100);
+        assert!(tile_rects(0, 0, 1024).is_empty());
+    }
+
+    #[test]
+    fn test_upload_in_batches() {
+        let ctx = Context::default();
+        // Levels of 3072x1025, 1536x513, 768x257, and 384x129: 6 + 2 + 1 + 1 tiles
+        let mut texture = TiledTexture::new(RgbaImage::new(TILE_SIZE * 3, TILE_SIZE + 1));
+        assert_eq!(texture.level_count(), 4);
+        assert_eq!(texture.progress(), 0.0);
+
+        // The smaller levels come first
+        assert_eq!(texture.upload_next(&ctx, 4), 4);
+        assert!(texture.levels[1..].iter().all(TileLevel::is_complete));
+        assert_eq!(texture.levels[0].pending(), 6);
+        assert_eq!(texture.upload_next(&ctx, 4), 4);
+        assert!(!texture.is_complete());
+        assert_eq!(texture.upload_next(&ctx, 4), 2);
+        assert!(texture.is_complete());
+        assert_eq!(texture.upload_next(&ctx, 4), 0);
+        let pixels = 3072 * 1025 + 1536 * 513 + 768 * 257 + 384 * 129;
+        assert_eq!(texture.memory_usage(), pixels * 4);
+
+        // Small images have no extra levels, and tiles respect a smaller limit
+        let small = TiledTexture::with_tile_size(RgbaImage::new(500, 300), 256);
+        assert_eq!(small.level_count(), 1);
+        assert_eq!(small.levels[0].tiles.len(), 4);
+    }
+
+    #[test]
+    fn test_update_uploads_changed_tiles() {
+        let ctx = Context::default();
+        let mut texture = TiledTexture::new(RgbaImage::new(TILE_SIZE * 3, TILE_SIZE + 1));
+        while texture.upload_next(&ctx, 16) > 0 {}
+        let ids: Vec<_> = texture.levels[0].tiles.iter().map(|tile| tile.texture.as_ref().unwrap().id()).collect();
+
+        // A change inside the second tile touches one tile of each level
+        let dirty = TileRect { x: 1100, y: 10, width: 20, height: 20 };
+        assert!(texture.update(RgbaImage::new(TILE_SIZE * 3, TILE_SIZE + 1), Some(dirty)));
+        assert_eq!(texture.levels.iter().map(TileLevel::pending).collect::<Vec<_>>(), vec![1, 1, 1, 1]);
+        assert_eq!(texture.levels[0].tiles[1].dirty, Some(dirty));
+        assert_eq!(texture.levels[1].tiles[0].dirty, Some(TileRect { x: 550, y: 5, width: 10, height: 10 }));
+        assert!(texture.levels[0].has_textures());
+
+        // The changed pixels are uploaded into the existing textures
+        assert_eq!(texture.upload_next(&ctx, 16), 4);
+        assert!(texture.is_complete());
+        let updated: Vec<_> = texture.levels[0].tiles.iter().map(|tile| tile.texture.as_ref().unwrap().id()).collect();
+        assert_eq!(updated, ids);
+
+        assert!(texture.update(RgbaImage::new(TILE_SIZE * 3, TILE_SIZE + 1), None));
+        assert_eq!(texture.progress(), 0.0);
+        assert!(!texture.update(RgbaImage::new(10, 10), None));
+    }
+
+    #[test]
+    fn test_tile_rect_math() {
+        let a = TileRect { x: 0, y: 0, width: 10, height: 10 };
+        let b = TileRect { x: 5, y: 8, width: 10, height: 10 };
+        assert_eq!(a.intersect(&b), Some(TileRect { x: 5, y: 8, width: 5, height: 2 }));
+        assert_eq!(a.union(&b), TileRect { x: 0, y: 0, width: 15, height: 18 });
+        assert_eq!(a.intersect(&TileRect { x: 10, y: 0, width: 5, height: 5 }), None);
+        assert_eq!(b.halved(), TileRect { x: 2, y: 4, width: 6, height: 5 });
+        assert_eq!(
+            TileRect::covering(Rect::from_min_max(Pos2::new(-3.0, 1.5), Pos2::new(4.2, 6.0))),
+            TileRect { x: 0, y: 1, width: 5, height: 5 }
+        );
+    }
+
+    #[test]
+    fn test_half_size() {
+        let mut image = RgbaImage::from_pixel(3, 3, image::Rgba([0, 0, 0, 255]));
+        image.put_pixel(0, 0, image::Rgba([200, 100, 40, 255]));
+        image.put_pixel(2, 2, image::Rgba([80, 80, 80, 0]));
+        let half = half_size(&image);
+        assert_eq!(half.dimensions(), (2, 2));
+        assert_eq!(half.get_pixel(0, 0).0, [50, 25, 10, 255]);
+        // The odd last column and row are repeated
+        assert_eq!(half.get_pixel(1, 1).0, [80, 80, 80, 0]);
+        assert_eq!(half_size(&RgbaImage::new(1, 1)).dimensions(), (1, 1));
+    }
+
+    #[test]
+    fn test_level_for_scale() {
+        assert_eq!(level_for_scale(2.0, 4), 0);
+        assert_eq!(level_for_scale(1.0, 4), 0);
+        assert_eq!(level_for_scale(0.6, 4), 0);
+        assert_eq!(level_for_scale(0.5, 4), 1);
+        assert_eq!(level_for_scale(0.3, 4), 1);
+        assert_eq!(level_for_scale(0.2, 4), 2);
+        assert_eq!(level_for_scale(0.1, 4), 3);
+        // Limited to the levels there are
+        assert_eq!(level_for_scale(0.1, 2), 1);
+        assert_eq!(level_for_scale(0.0, 4), 0);
+    }
+
+    #[test]
+    fn test_filter_only_changes_magnification() {
+        assert_eq!(TextureFilter::Linear.options(), TextureOptions::LINEAR);
+        let nearest = TextureFilter::Nearest.options();
+        assert_eq!(nearest.magnification, Filter::Nearest);
+        assert_eq!(nearest.minification, Filter::Linear);
+
+        let texture = TiledTexture::new(RgbaImage::new(4, 4)).with_filter(TextureFilter::Nearest);
+        assert_eq!(texture.filter(), TextureFilter::Nearest);
+    }
+}
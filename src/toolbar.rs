@@ -0,0 +1,128 @@
+//! Tool panel toolbar
+//!
+//! Tools are shown as icon buttons, with the tool name and shortcut in their
+//! tooltips. The icons are SVG files under `assets/icons`, embedded in the
+//! binary and drawn by the image loaders from egui_extras, tinted with the
+//! text color so they follow the theme. In narrow panels the buttons wrap
+//! onto a second row, and tools that still don't fit move into an overflow
+//! menu.
+
+use crate::types::Tool;
+use egui::ImageSource;
+
+/// Width and height of the tool icons in points
+pub const ICON_SIZE: f32 = 20.0;
+
+/// Most rows of tool buttons before tools move into the overflow menu
+pub const MAX_ROWS: usize = 2;
+
+/// Icon of the overflow menu button
+pub const OVERFLOW_ICON: ImageSource<'static> = egui::include_image!("../assets/icons/overflow.svg");
+
+/// Get the icon of a tool
+pub fn icon(tool: &Tool) -> ImageSource<'static> {
+    match tool {
+        Tool::Select => egui::include_image!("../assets/icons/select.svg"),
+        Tool::Rectangle => egui::include_image!("../assets/icons/rectangle.svg"),
+        Tool::Text => egui::include_image!("../assets/icons/text.svg"),
+        Tool::Sticker => egui::include_image!("../assets/icons/sticker.svg"),
+        Tool::Eraser => egui::include_image!("../assets/icons/eraser.svg"),
+        Tool::Measure => egui::include_image!("../assets/icons/measure.svg"),
+        Tool::Perspective => egui::include_image!("../assets/icons/perspective.svg"),
+    }
+}
+
+/// Get the name of a tool
+pub fn name(tool: &Tool) -> &'static str {
+    match tool {
+        Tool::Select => "Select",
+        Tool::Rectangle => "Rectangle",
+        Tool::Text => "Text",
+        Tool::Sticker => "Sticker",
+        Tool::Eraser => "Eraser",
+        Tool::Measure => "Measure",
+        Tool::Perspective => "Perspective",
+    }
+}
+
+/// Get what a tool does, for its tooltip
+pub fn description(tool: &Tool) -> &'static str {
+    match tool {
+        Tool::Select => "Click an annotation to select it, or drag to select a region",
+        Tool::Rectangle => "Drag to draw a rectangle",
+        Tool::Text => "Click to add a text box",
+        Tool::Sticker => "Click to stamp the chosen sticker",
+        Tool::Eraser => "Click an annotation or drag over several to delete them",
+        Tool::Measure => "Drag to measure a distance and angle; hold Shift for multiples of 45°",
+        Tool::Perspective => "Drag the four corners onto a skewed region to straighten it",
+    }
+}
+
+/// Get the tooltip of a tool button: its name, shortcut if bound, and what it does
+pub fn tooltip(tool: &Tool, shortcut: &str) -> String {
+    if shortcut.is_empty() {
+        format!("{}\n{}", name(tool), description(tool))
+    } else {
+        format!("{} ({})\n{}", name(tool), shortcut, description(tool))
+    }
+}
+
+/// How the tool buttons are arranged in the available width
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ToolbarLayout {
+    /// Buttons per row
+    pub columns: usize,
+    /// Tools shown as buttons; the rest are in the overflow menu
+    pub visible: usize,
+}
+
+impl ToolbarLayout {
+    /// Arrange `tools` buttons of `button_width` with `spacing` between them in `available_width`
+    ///
+    /// At least one button fits per row. When the tools need more than
+    /// [`MAX_ROWS`] rows, the last slot holds the overflow menu button.
+    pub fn new(available_width: f32, button_width: f32, spacing: f32, tools: usize) -> Self {
+        let columns = (((available_width + spacing) / (button_width + spacing)).floor() as usize).max(1);
+        let slots = columns * MAX_ROWS;
+        let visible = if tools <= slots { tools } else { slots - 1 };
+        Self { columns, visible }
+    }
+
+    /// Check whether some tools are in the overflow menu
+    pub fn overflows(&self, tools: usize) -> bool {
+        self.visible < tools
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layout_wraps_and_overflows() {
+        let tools = Tool::all().len();
+
+        // A wide panel fits every tool in one row
+        let wide = ToolbarLayout::new(300.0, 28.0, 4.0, tools);
+        assert_eq!(wide, ToolbarLayout { columns: 9, visible: tools });
+        assert!(!wide.overflows(tools));
+
+        // Four columns fit in 124 points, wrapping the tools onto two rows
+        let medium = ToolbarLayout::new(124.0, 28.0, 4.0, tools);
+        assert_eq!(medium, ToolbarLayout { columns: 4, visible: tools });
+
+        // Two columns leave room for three tools and the overflow button
+        let narrow = ToolbarLayout::new(60.0, 28.0, 4.0, tools);
+        assert_eq!(narrow, ToolbarLayout { columns: 2, visible: 3 });
+        assert!(narrow.overflows(tools));
+
+        // A single button still fits in a panel narrower than it
+        assert_eq!(ToolbarLayout::new(10.0, 28.0, 4.0, tools).columns, 1);
+    }
+
+    #[test]
+    fn test_tooltip_shows_the_shortcut() {
+        assert_eq!(tooltip(&Tool::Rectangle, "2"), "Rectangle (2)\nDrag to draw a rectangle");
+        assert_eq!(tooltip(&Tool::Text, ""), "Text\nClick to add a text box");
+    }
+}
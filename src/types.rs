@@ -8,6 +8,31 @@ use egui::{Pos2, Rect, Vec2, Color32};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use uuid::Uuid;
+use std::path::PathBuf;
+use crate::style::StyleSettings;
+use crate::export::ExportOptions;
+use crate::power::LowResourceMode;
+use crate::shortcuts::ShortcutMap;
+use crate::snap::SnapOptions;
+use crate::grid::GridOptions;
+use crate::routing::MonitorRouting;
+use crate::naming::FileNameTemplate;
+use crate::presets::CapturePreset;
+use crate::batch::BatchPreset;
+use crate::share::ShareTarget;
+use crate::remote::SaveDestination;
+use crate::privacy::PrivacySettings;
+use crate::schedule::IntervalSettings;
+use crate::watch::WatchSettings;
+use crate::palette::PaletteHistory;
+use crate::image_ops::TrimOptions;
+use crate::tiles::TextureFilter;
+use crate::memory::MemoryOptions;
+use crate::text::TextFormat;
+use crate::color::ColorOptions;
+use crate::hdr::HdrOptions;
+use crate::capture::WindowCaptureOptions;
+use crate::regions::{OverlaySettings, RegionMemory};
 
 /// Represents a screen capture area with DPI information
 #[derive(Debug, Clone, PartialEq)]
@@ -40,12 +65,72 @@ pub struct ScreenInfo {
 }
 
 /// Annotation item that can be placed on an image
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct AnnotationItem {
     pub id: Uuid,
     pub position: Pos2,
+    /// Editor selection state, not persisted in project files
+    #[serde(skip)]
     pub is_selected: bool,
     pub annotation_type: AnnotationType,
+    /// Clockwise rotation in degrees about the center of the bounds
+    #[serde(default)]
+    pub rotation: f32,
+    /// Walkthrough step the annotation appears in, if any
+    #[serde(default)]
+    pub step: Option<u32>,
+    /// Opacity of the whole annotation (0.0-1.0)
+    #[serde(default = "full_opacity")]
+    pub opacity: f32,
+    /// How the annotation's colors combine with the pixels beneath it
+    #[serde(default)]
+    pub blend_mode: BlendMode,
+}
+
+fn full_opacity() -> f32 {
+    1.0
+}
+
+fn enabled() -> bool {
+    true
+}
+
+/// How an annotation's colors combine with the pixels beneath it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum BlendMode {
+    /// Paints over the pixels beneath
+    #[default]
+    Normal,
+    /// Darkens the pixels beneath; white leaves them unchanged, like a highlighter pen
+    Multiply,
+    /// Lightens the pixels beneath; black leaves them unchanged
+    Screen,
+}
+
+impl BlendMode {
+    /// Get all blend modes in menu order
+    pub fn all() -> [BlendMode; 3] {
+        [BlendMode::Normal, BlendMode::Multiply, BlendMode::Screen]
+    }
+
+    /// Blend a source channel onto a destination channel, both 0.0-255.0
+    pub fn blend(self, source: f32, destination: f32) -> f32 {
+        match self {
+            BlendMode::Normal => source,
+            BlendMode::Multiply => source * destination / 255.0,
+            BlendMode::Screen => source + destination - source * destination / 255.0,
+        }
+    }
+}
+
+impl std::fmt::Display for BlendMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BlendMode::Normal => write!(f, "Normal"),
+            BlendMode::Multiply => write!(f, "Multiply"),
+            BlendMode::Screen => write!(f, "Screen"),
+        }
+    }
 }
 
 impl AnnotationItem {
@@ -59,7 +144,12 @@ impl AnnotationItem {
                 size,
                 stroke_color: Color32::RED,
                 stroke_width: 2.0,
+                fill_color: None,
             },
+            rotation: 0.0,
+            step: None,
+            opacity: 1.0,
+            blend_mode: BlendMode::Normal,
         }
     }
 
@@ -73,43 +163,222 @@ impl AnnotationItem {
                 content,
                 font_size: 14.0,
                 color: Color32::BLACK,
+                format: TextFormat::default(),
+            },
+            rotation: 0.0,
+            step: None,
+            opacity: 1.0,
+            blend_mode: BlendMode::Normal,
+        }
+    }
+
+    /// Create a new image annotation from PNG-encoded bytes
+    pub fn new_image(position: Pos2, data: Vec<u8>, size: Vec2) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            position,
+            is_selected: false,
+            annotation_type: AnnotationType::Image { data, size, opacity: 1.0 },
+            rotation: 0.0,
+            step: None,
+            opacity: 1.0,
+            blend_mode: BlendMode::Normal,
+        }
+    }
+
+    /// Create a new dimension annotation measuring from `start` to `end`
+    pub fn new_dimension(start: Pos2, end: Pos2, pixels_per_inch: Option<f32>) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            position: start,
+            is_selected: false,
+            annotation_type: AnnotationType::Dimension {
+                end: end - start,
+                color: Color32::RED,
+                width: 2.0,
+                pixels_per_inch,
             },
+            rotation: 0.0,
+            step: None,
+            opacity: 1.0,
+            blend_mode: BlendMode::Normal,
         }
     }
 
-    /// Get the bounding rectangle of this annotation
+    /// Get the line a dimension annotation measures, after rotation
+    pub fn measured_line(&self) -> Option<[Pos2; 2]> {
+        let AnnotationType::Dimension { end, .. } = &self.annotation_type else {
+            return None;
+        };
+        let center = self.bounds().center();
+        Some([self.position, self.position + *end].map(|point| rotate_about(point, center, self.rotation)))
+    }
+
+    /// Get the bounding rectangle of this annotation, before rotation
     pub fn bounds(&self) -> Rect {
         match &self.annotation_type {
-            AnnotationType::Rectangle { size, .. } => {
+            AnnotationType::Rectangle { size, .. } | AnnotationType::Image { size, .. } => {
                 Rect::from_min_size(self.position, *size)
             }
-            AnnotationType::Text { font_size, content, .. } => {
+            AnnotationType::Text { font_size, content, format, .. } => {
                 // Approximate text bounds based on font size and content length
-                let width = content.len() as f32 * font_size * 0.6;
-                let height = *font_size * 1.2;
-                Rect::from_min_size(self.position, Vec2::new(width, height))
+                Rect::from_min_size(self.position, crate::text::approximate_size(content, *font_size, format))
+            }
+            // The line is padded so thin and straight lines can still be clicked
+            AnnotationType::Dimension { end, .. } => {
+                Rect::from_two_pos(self.position, self.position + *end).expand(crate::measure::HIT_MARGIN)
             }
         }
     }
 
+    /// Check whether the annotation is translucent or blended other than normally
+    pub fn has_effects(&self) -> bool {
+        self.opacity < 1.0 || self.blend_mode != BlendMode::Normal
+    }
+
+    /// Check whether the annotation is rotated
+    pub fn is_rotated(&self) -> bool {
+        self.rotation.rem_euclid(360.0) != 0.0
+    }
+
+    /// Get the corners of the rotated bounds, clockwise from the top-left
+    pub fn corners(&self) -> [Pos2; 4] {
+        let bounds = self.bounds();
+        let center = bounds.center();
+        [bounds.left_top(), bounds.right_top(), bounds.right_bottom(), bounds.left_bottom()]
+            .map(|corner| rotate_about(corner, center, self.rotation))
+    }
+
+    /// Get the rotate handle position, `distance` above the top edge's center
+    pub fn rotate_handle(&self, distance: f32) -> Pos2 {
+        let bounds = self.bounds();
+        let handle = Pos2::new(bounds.center().x, bounds.min.y - distance);
+        rotate_about(handle, bounds.center(), self.rotation)
+    }
+
+    /// Rotation that points the rotate handle at `point`, in degrees within (-180, 180]
+    pub fn rotation_towards(&self, point: Pos2) -> f32 {
+        let offset = point - self.bounds().center();
+        // The handle sits straight above the center at zero rotation
+        let degrees = offset.y.atan2(offset.x).to_degrees() + 90.0;
+        if degrees > 180.0 {
+            degrees - 360.0
+        } else {
+            degrees
+        }
+    }
+
+    /// Resize by dragging corner `corner` (an index into [`Self::corners`]) to `point`
+    ///
+    /// The opposite corner stays in place. With `keep_aspect` the size keeps
+    /// its current aspect ratio. Text is sized by its font and content, so it
+    /// is left unchanged, and so are dimensions, whose ends are set by measuring.
+    pub fn resize_corner(&mut self, corner: usize, point: Pos2, keep_aspect: bool) {
+        let anchor = self.corners()[(corner + 2) % 4];
+        let rotation = self.rotation;
+        let size = match &mut self.annotation_type {
+            AnnotationType::Rectangle { size, .. } | AnnotationType::Image { size, .. } => size,
+            AnnotationType::Text { .. } | AnnotationType::Dimension { .. } => return,
+        };
+
+        // Direction from the anchor to the dragged corner before rotation
+        let direction = Vec2::new(
+            if corner == 0 || corner == 3 { -1.0 } else { 1.0 },
+            if corner < 2 { -1.0 } else { 1.0 },
+        );
+        let local = rotate_about(point, anchor, -rotation) - anchor;
+        let mut new_size = (local * direction).max(Vec2::splat(MIN_RESIZE_SIZE));
+        if keep_aspect && size.x > 0.0 && size.y > 0.0 {
+            let aspect = size.x / size.y;
+            let width = new_size.x.max(new_size.y * aspect).max(MIN_RESIZE_SIZE * aspect.max(1.0));
+            new_size = Vec2::new(width, width / aspect);
+        }
+        *size = new_size;
+
+        let center = rotate_about(anchor + direction * new_size * 0.5, anchor, rotation);
+        self.position = center - new_size * 0.5;
+    }
+
     /// Check if a point is inside this annotation
     pub fn contains_point(&self, point: Pos2) -> bool {
-        self.bounds().contains(point)
+        let bounds = self.bounds();
+        bounds.contains(rotate_about(point, bounds.center(), -self.rotation))
+    }
+
+    /// Check if the rotated bounds overlap a rectangle
+    pub fn intersects(&self, rect: Rect) -> bool {
+        // Separating axis test between the rotated bounds and the rectangle
+        let corners = self.corners();
+        let rect_corners = [rect.left_top(), rect.right_top(), rect.right_bottom(), rect.left_bottom()];
+        let axes = [Vec2::X, Vec2::Y, corners[1] - corners[0], corners[3] - corners[0]];
+        axes.into_iter().filter(|axis| *axis != Vec2::ZERO).all(|axis| {
+            let project = |points: &[Pos2; 4]| {
+                points.iter().map(|point| point.to_vec2().dot(axis)).fold((f32::MAX, f32::MIN), |(min, max), p| {
+                    (min.min(p), max.max(p))
+                })
+            };
+            let (min, max) = project(&corners);
+            let (rect_min, rect_max) = project(&rect_corners);
+            min <= rect_max && rect_min <= max
+        })
     }
+
+    /// Copy the annotation with a new id, moved by `offset`
+    pub fn duplicate(&self, offset: Vec2) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            position: self.position + offset,
+            ..self.clone()
+        }
+    }
+}
+
+/// Smallest width or height an annotation can be resized to
+const MIN_RESIZE_SIZE: f32 = 4.0;
+
+/// Rotate a point clockwise by `degrees` about `pivot` (with y pointing down)
+pub fn rotate_about(point: Pos2, pivot: Pos2, degrees: f32) -> Pos2 {
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let offset = point - pivot;
+    pivot + Vec2::new(offset.x * cos - offset.y * sin, offset.x * sin + offset.y * cos)
 }
 
 /// Types of annotations that can be added to images
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum AnnotationType {
     Rectangle {
         size: Vec2,
         stroke_color: Color32,
         stroke_width: f32,
+        fill_color: Option<Color32>,
     },
     Text {
         content: String,
         font_size: f32,
         color: Color32,
+        /// Bold, italic, font, box, alignment, and wrapping
+        #[serde(default)]
+        format: TextFormat,
+    },
+    /// Another image (e.g. a logo or an earlier capture) stamped onto the screenshot
+    Image {
+        /// PNG-encoded image, stored as Base64 in JSON
+        #[serde(with = "crate::base64")]
+        data: Vec<u8>,
+        /// Displayed size in image pixels
+        size: Vec2,
+        /// Opacity (0.0-1.0)
+        opacity: f32,
+    },
+    /// Measured line from `position`, labeled with its length and angle
+    Dimension {
+        /// End of the line relative to its start
+        end: Vec2,
+        color: Color32,
+        width: f32,
+        /// Pixels per inch of the capture, for labels in millimeters and inches
+        #[serde(default)]
+        pixels_per_inch: Option<f32>,
     },
 }
 
@@ -120,6 +389,123 @@ pub struct AppSettings {
     pub hotkey_vk_code: u32,
     pub default_save_directory: Option<String>,
     pub default_image_format: ImageFormat,
+    /// Per-tool default styles and named style presets
+    #[serde(default)]
+    pub styles: StyleSettings,
+    /// Editor keyboard shortcut bindings
+    #[serde(default)]
+    pub shortcuts: ShortcutMap,
+    /// Whether to disable optional features to save CPU and battery
+    #[serde(default)]
+    pub low_resource_mode: LowResourceMode,
+    /// Output transformations applied on save and export
+    #[serde(default)]
+    pub export: ExportOptions,
+    /// Recently opened or saved files, most recent first
+    #[serde(default)]
+    pub recent_files: Vec<PathBuf>,
+    /// Snapping of the capture selection to UI element edges
+    #[serde(default)]
+    pub snapping: SnapOptions,
+    /// Copy the file location to the clipboard after saving to a network or cloud folder
+    #[serde(default)]
+    pub copy_remote_save_location: bool,
+    /// Save folders and post-save commands per source monitor
+    #[serde(default)]
+    pub monitor_routing: MonitorRouting,
+    /// Name template for new screenshots
+    #[serde(default)]
+    pub file_naming: FileNameTemplate,
+    /// How far the image can be panned off the canvas
+    #[serde(default)]
+    pub pan: PanOptions,
+    /// Canvas double-click and scroll behaviors
+    #[serde(default)]
+    pub gestures: GestureOptions,
+    /// Canvas grid, rulers, and grid snapping
+    #[serde(default)]
+    pub grid: GridOptions,
+    /// Capture the monitor showing the focused window instead of the primary screen
+    #[serde(default)]
+    pub capture_active_monitor: bool,
+    /// Hotkey capturing only the focused window
+    #[serde(default = "Hotkey::window_default")]
+    pub window_hotkey: Hotkey,
+    /// Hotkey capturing the monitor containing the mouse cursor
+    #[serde(default = "Hotkey::cursor_monitor_default")]
+    pub cursor_monitor_hotkey: Hotkey,
+    /// Hotkey picking a color from the screen instead of capturing it
+    #[serde(default = "Hotkey::color_picker_default")]
+    pub color_picker_hotkey: Hotkey,
+    /// Save hotkey and menu captures right away instead of only opening them in the editor
+    #[serde(default)]
+    pub auto_save_captures: bool,
+    /// Start in the background when the user logs in
+    #[serde(default)]
+    pub launch_at_login: bool,
+    /// Named capture presets, each optionally bound to its own hotkey
+    #[serde(default)]
+    pub capture_presets: Vec<CapturePreset>,
+    /// sRGB conversion of captures and color profiles in exported files
+    #[serde(default)]
+    pub color: ColorOptions,
+    /// Capture and tone mapping of monitors in HDR mode
+    #[serde(default)]
+    pub hdr: HdrOptions,
+    /// Cleanup of captures of single windows
+    #[serde(default)]
+    pub window_capture: WindowCaptureOptions,
+    /// Select a region of hotkey screen captures before they are opened
+    #[serde(default)]
+    pub select_capture_region: bool,
+    /// Pick the monitor of screen hotkey captures by pressing the hotkey repeatedly
+    #[serde(default)]
+    pub cycle_monitors: bool,
+    /// Show a toast with a thumbnail after a preset copies or saves a capture without the editor
+    #[serde(default = "enabled")]
+    pub capture_toast: bool,
+    /// Region last selected for each application
+    #[serde(default)]
+    pub region_memory: RegionMemory,
+    /// Use the high-contrast theme
+    #[serde(default)]
+    pub high_contrast: bool,
+    /// Appearance of the region selection overlay
+    #[serde(default)]
+    pub overlay: OverlaySettings,
+    /// Named presets of the `batch` command
+    #[serde(default)]
+    pub batch_presets: Vec<BatchPreset>,
+    /// Chat services screenshots can be shared to, without their secrets
+    #[serde(default)]
+    pub share_targets: Vec<ShareTarget>,
+    /// Remote destinations offered in Save As, without their passwords
+    #[serde(default)]
+    pub save_destinations: Vec<SaveDestination>,
+    /// Destination last saved to with Save As, `None` for a local folder
+    #[serde(default)]
+    pub last_save_destination: Option<Uuid>,
+    /// Scanning for sensitive content before copying or uploading
+    #[serde(default)]
+    pub privacy: PrivacySettings,
+    /// Target, interval, and limits of interval capture
+    #[serde(default)]
+    pub interval_capture: IntervalSettings,
+    /// Polling interval and change threshold of region watches
+    #[serde(default)]
+    pub watch: WatchSettings,
+    /// Colors picked from the screen, newest first
+    #[serde(default)]
+    pub palette: PaletteHistory,
+    /// Tolerance of the Auto Trim action
+    #[serde(default)]
+    pub trim: TrimOptions,
+    /// Smooth or crisp pixels when zoomed in
+    #[serde(default)]
+    pub texture_filter: TextureFilter,
+    /// Memory budget above which large images are shown as downsampled proxies
+    #[serde(default)]
+    pub memory: MemoryOptions,
 }
 
 impl Default for AppSettings {
@@ -130,6 +516,249 @@ impl Default for AppSettings {
             hotkey_vk_code: 0x53, // 'S' key
             default_save_directory: None,
             default_image_format: ImageFormat::Png,
+            styles: StyleSettings::default(),
+            shortcuts: ShortcutMap::default(),
+            low_resource_mode: LowResourceMode::default(),
+            export: ExportOptions::default(),
+            recent_files: Vec::new(),
+            snapping: SnapOptions::default(),
+            copy_remote_save_location: false,
+            monitor_routing: MonitorRouting::default(),
+            file_naming: FileNameTemplate::default(),
+            pan: PanOptions::default(),
+            gestures: GestureOptions::default(),
+            grid: GridOptions::default(),
+            capture_active_monitor: false,
+            window_hotkey: Hotkey::window_default(),
+            cursor_monitor_hotkey: Hotkey::cursor_monitor_default(),
+            color_picker_hotkey: Hotkey::color_picker_default(),
+            auto_save_captures: false,
+            launch_at_login: false,
+            capture_presets: Vec::new(),
+            color: ColorOptions::default(),
+            hdr: HdrOptions::default(),
+            window_capture: WindowCaptureOptions::default(),
+            select_capture_region: false,
+            cycle_monitors: false,
+            capture_toast: true,
+            region_memory: RegionMemory::default(),
+            high_contrast: false,
+            overlay: OverlaySettings::default(),
+            batch_presets: Vec::new(),
+            share_targets: Vec::new(),
+            save_destinations: Vec::new(),
+            last_save_destination: None,
+            privacy: PrivacySettings::default(),
+            interval_capture: IntervalSettings::default(),
+            watch: WatchSettings::default(),
+            palette: PaletteHistory::default(),
+            trim: TrimOptions::default(),
+            texture_filter: TextureFilter::default(),
+            memory: MemoryOptions::default(),
+        }
+    }
+}
+
+/// A global hotkey: modifier flags and a virtual key code
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Hotkey {
+    pub modifiers: u32,
+    pub vk_code: u32,
+}
+
+impl Hotkey {
+    /// Get the default hotkey capturing the focused window, Ctrl+Shift+W
+    pub fn window_default() -> Self {
+        Self {
+            modifiers: 0x0002 | 0x0004, // MOD_CONTROL | MOD_SHIFT
+            vk_code: 0x57, // 'W' key
+        }
+    }
+
+    /// Get the default hotkey capturing the monitor under the cursor, Ctrl+Shift+M
+    pub fn cursor_monitor_default() -> Self {
+        Self {
+            modifiers: 0x0002 | 0x0004, // MOD_CONTROL | MOD_SHIFT
+            vk_code: 0x4D, // 'M' key
+        }
+    }
+
+    /// Get the default hotkey picking a color from the screen, Ctrl+Shift+C
+    pub fn color_picker_default() -> Self {
+        Self {
+            modifiers: 0x0002 | 0x0004, // MOD_CONTROL | MOD_SHIFT
+            vk_code: 0x43, // 'C' key
+        }
+    }
+}
+
+/// Canvas panning settings
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PanOptions {
+    /// Screen pixels of the image kept visible when panning
+    pub min_visible_margin: f32,
+    /// Allow panning the image entirely off the canvas, e.g. while composing collages
+    pub free_pan: bool,
+    /// Keys and gestures that pan and zoom the canvas
+    pub scheme: PanScheme,
+}
+
+impl Default for PanOptions {
+    fn default() -> Self {
+        Self {
+            min_visible_margin: 50.0,
+            free_pan: false,
+            scheme: PanScheme::default(),
+        }
+    }
+}
+
+/// Keys and gestures that pan and zoom the canvas
+///
+/// The middle mouse button always pans, and pinching or Ctrl+scrolling always zooms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum PanScheme {
+    /// Dragging while holding Space pans, and scrolling pans, e.g. with two fingers on a touchpad
+    #[default]
+    SpaceBar,
+    /// Dragging while holding Shift pans, and the scroll wheel zooms
+    ShiftDrag,
+}
+
+impl PanScheme {
+    /// Get all schemes in menu order
+    pub fn all() -> [PanScheme; 2] {
+        [PanScheme::SpaceBar, PanScheme::ShiftDrag]
+    }
+}
+
+impl std::fmt::Display for PanScheme {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PanScheme::SpaceBar => write!(f, "Space + Drag, Scroll to Pan"),
+            PanScheme::ShiftDrag => write!(f, "Shift + Drag, Scroll to Zoom"),
+        }
+    }
+}
+
+impl PanOptions {
+    /// Largest allowed minimum visible margin in screen pixels
+    pub const MAX_MARGIN: f32 = 1000.0;
+
+    /// Validate panning options
+    pub fn validate(&self) -> AppResult<()> {
+        if !(0.0..=Self::MAX_MARGIN).contains(&self.min_visible_margin) {
+            return Err(AppError::Settings(format!(
+                "表示を保つ余白は0から{}ピクセルの範囲で指定してください",
+                Self::MAX_MARGIN
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// What double-clicking and scrolling on the canvas do
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GestureOptions {
+    pub double_click: DoubleClickAction,
+    pub scroll: ScrollAction,
+    /// Multiplier of how far scrolling and pinching zoom
+    pub zoom_sensitivity: f32,
+}
+
+impl Default for GestureOptions {
+    fn default() -> Self {
+        Self {
+            double_click: DoubleClickAction::default(),
+            scroll: ScrollAction::default(),
+            zoom_sensitivity: 1.0,
+        }
+    }
+}
+
+impl GestureOptions {
+    /// Range of the zoom sensitivity multiplier
+    pub const ZOOM_SENSITIVITY_RANGE: std::ops::RangeInclusive<f32> = 0.1..=5.0;
+
+    /// Validate gesture options
+    pub fn validate(&self) -> AppResult<()> {
+        if !Self::ZOOM_SENSITIVITY_RANGE.contains(&self.zoom_sensitivity) {
+            return Err(AppError::Settings(format!(
+                "ズームの感度は{}から{}の範囲で指定してください",
+                Self::ZOOM_SENSITIVITY_RANGE.start(),
+                Self::ZOOM_SENSITIVITY_RANGE.end()
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// What double-clicking the canvas does
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum DoubleClickAction {
+    /// Show the image at 100% and centered
+    #[default]
+    ResetView,
+    /// Zoom to fit the whole image on the canvas
+    FitToScreen,
+    /// Show the image at 100%, keeping the pan
+    ActualSize,
+    /// Leave the view as it is
+    Nothing,
+}
+
+impl DoubleClickAction {
+    /// Get all actions in menu order
+    pub fn all() -> [DoubleClickAction; 4] {
+        [
+            DoubleClickAction::ResetView,
+            DoubleClickAction::FitToScreen,
+            DoubleClickAction::ActualSize,
+            DoubleClickAction::Nothing,
+        ]
+    }
+}
+
+impl std::fmt::Display for DoubleClickAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DoubleClickAction::ResetView => write!(f, "Reset View"),
+            DoubleClickAction::FitToScreen => write!(f, "Fit to Screen"),
+            DoubleClickAction::ActualSize => write!(f, "Actual Size (100%)"),
+            DoubleClickAction::Nothing => write!(f, "Nothing"),
+        }
+    }
+}
+
+/// What scrolling on the canvas does
+///
+/// Pinching or Ctrl+scrolling always zooms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ScrollAction {
+    /// Pan with the Space scheme and zoom with the Shift scheme
+    #[default]
+    PanScheme,
+    /// Zoom towards the pointer
+    Zoom,
+    /// Pan vertically, or horizontally while Shift is held
+    Pan,
+}
+
+impl ScrollAction {
+    /// Get all actions in menu order
+    pub fn all() -> [ScrollAction; 3] {
+        [ScrollAction::PanScheme, ScrollAction::Zoom, ScrollAction::Pan]
+    }
+}
+
+impl std::fmt::Display for ScrollAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScrollAction::PanScheme => write!(f, "As the Pan Scheme"),
+            ScrollAction::Zoom => write!(f, "Zoom"),
+            ScrollAction::Pan => write!(f, "Pan (Shift for Horizontal)"),
         }
     }
 }
@@ -150,10 +779,30 @@ pub enum AppError {
     
     #[error("スクリーンキャプチャに失敗しました: {0}")]
     ScreenCapture(String),
+
+    #[error("画面 {index} が見つかりません")]
+    ScreenNotFound { index: usize },
+
+    /// No screen fits a lookup that is not by index, such as the primary screen
+    #[error("画面が見つかりません: {0}")]
+    NoScreen(String),
+
+    #[error("画面 {screen} のキャプチャに失敗しました: {source}")]
+    CaptureFailed {
+        screen: usize,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[error("キャプチャ範囲 {} が画面の範囲 {} からはみ出しています", describe_rect(.requested), describe_rect(.available))]
+    RegionOutOfBounds { requested: Rect, available: Rect },
     
     #[error("ファイルアクセスエラー: {0}")]
     FileAccess(#[from] std::io::Error),
     
+    #[error("ディスクの空き容量が不足しているため保存できませんでした: {0}")]
+    DiskFull(String),
+    
     #[error("クリップボードエラー: {0}")]
     Clipboard(String),
     
@@ -162,11 +811,135 @@ pub enum AppError {
     
     #[error("設定エラー: {0}")]
     Settings(String),
+    
+    #[error("プロジェクトファイルエラー: {0}")]
+    Project(String),
+    
+    #[error("制御コマンドエラー: {0}")]
+    Control(String),
+
+    #[error("自動操作エラー: {0}")]
+    Automation(String),
+
+    #[error("一括処理エラー: {0}")]
+    Batch(String),
+
+    #[error("プラグインエラー: {0}")]
+    Plugin(String),
+
+    #[error("共有エラー: {0}")]
+    Share(String),
+
+    #[error("転送エラー: {0}")]
+    Transfer(String),
+
+    #[error("暗号化エラー: {0}")]
+    Encryption(String),
+
+    #[error("OCR エラー: {0}")]
+    Ocr(String),
+
+    #[error("条件に一致するウィンドウが見つかりません: {0}")]
+    WindowNotFound(String),
+
+    #[error("条件 {selector} に一致するウィンドウが {} 個あります: {}", .titles.len(), .titles.join(" / "))]
+    AmbiguousWindow { selector: String, titles: Vec<String> },
+
+    #[error("引数が正しくありません: {0}")]
+    InvalidArgument(String),
+
+    /// An error with a description of what was being done when it happened
+    #[error("{context}: {source}")]
+    Context {
+        context: String,
+        #[source]
+        source: Box<AppError>,
+    },
+}
+
+impl AppError {
+    /// Wrap the error with a description of what was being done
+    pub fn context(self, context: impl Into<String>) -> Self {
+        AppError::Context {
+            context: context.into(),
+            source: Box::new(self),
+        }
+    }
+
+    /// Get the innermost error under any added context
+    pub fn root_cause(&self) -> &AppError {
+        match self {
+            AppError::Context { source, .. } => source.root_cause(),
+            error => error,
+        }
+    }
+
+    /// Get a stable identifier of the kind of error for machine-readable responses
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::HotkeyRegistration(_) => "hotkey_registration",
+            AppError::ScreenCapture(_) => "screen_capture",
+            AppError::ScreenNotFound { .. } | AppError::NoScreen(_) => "screen_not_found",
+            AppError::CaptureFailed { .. } => "capture_failed",
+            AppError::RegionOutOfBounds { .. } => "region_out_of_bounds",
+            AppError::FileAccess(_) => "file_access",
+            AppError::DiskFull(_) => "disk_full",
+            AppError::Clipboard(_) => "clipboard",
+            AppError::ImageProcessing(_) => "image_processing",
+            AppError::Settings(_) => "settings",
+            AppError::Project(_) => "project",
+            AppError::Control(_) => "control",
+            AppError::Automation(_) => "automation",
+            AppError::Batch(_) => "batch",
+            AppError::Plugin(_) => "plugin",
+            AppError::Share(_) => "share",
+            AppError::Transfer(_) => "transfer",
+            AppError::Encryption(_) => "encryption",
+            AppError::Ocr(_) => "ocr",
+            AppError::WindowNotFound(_) => "window_not_found",
+            AppError::AmbiguousWindow { .. } => "ambiguous_window",
+            AppError::InvalidArgument(_) => "invalid_argument",
+            AppError::Context { source, .. } => source.code(),
+        }
+    }
+
+    /// Get the process exit status for the error in command-line modes
+    ///
+    /// Status 1 is left for commands reporting a negative result, such as
+    /// `diff` finding differences.
+    pub fn exit_code(&self) -> i32 {
+        match self.root_cause() {
+            AppError::ScreenNotFound { .. } | AppError::NoScreen(_) => 3,
+            AppError::RegionOutOfBounds { .. } => 4,
+            AppError::ScreenCapture(_) | AppError::CaptureFailed { .. } => 5,
+            AppError::FileAccess(_) | AppError::DiskFull(_) => 6,
+            AppError::WindowNotFound(_) => 7,
+            AppError::AmbiguousWindow { .. } => 8,
+            _ => 2,
+        }
+    }
+}
+
+/// Describe a rectangle in error messages as its size and position
+fn describe_rect(rect: &Rect) -> String {
+    format!("{}×{} ({}, {})", rect.width(), rect.height(), rect.min.x, rect.min.y)
 }
 
 /// Result type alias for application operations
 pub type AppResult<T> = Result<T, AppError>;
 
+/// Adds context to the error of a failed operation
+pub trait ResultExt<T> {
+    /// Wrap the error with a description of what was being done
+    fn context(self, context: impl Into<String>) -> AppResult<T>;
+}
+
+impl<T, E: Into<AppError>> ResultExt<T> for Result<T, E> {
+    fn context(self, context: impl Into<String>) -> AppResult<T> {
+        self.map_err(|e| e.into().context(context))
+    }
+}
+
 /// Hotkey event information
 #[derive(Debug, Clone, PartialEq)]
 pub struct HotkeyEvent {
@@ -176,11 +949,26 @@ pub struct HotkeyEvent {
 }
 
 /// Available editing tools
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Tool {
     Select,
     Rectangle,
     Text,
+    /// Stamps the chosen emoji or symbol as a text annotation of its own
+    Sticker,
+    /// Deletes the clicked annotation, or every annotation touching a dragged region
+    Eraser,
+    /// Measures the distance and angle of a dragged line
+    Measure,
+    /// Drags four corners onto a skewed region, which is then warped into a straight rectangle
+    Perspective,
+}
+
+impl Tool {
+    /// Get all tools in tool panel order
+    pub fn all() -> Vec<Tool> {
+        vec![Tool::Select, Tool::Rectangle, Tool::Text, Tool::Sticker, Tool::Eraser, Tool::Measure, Tool::Perspective]
+    }
 }
 
 impl Default for Tool {
@@ -213,6 +1001,16 @@ impl ImageFormat {
     pub fn all() -> Vec<ImageFormat> {
         vec![ImageFormat::Png, ImageFormat::Jpg, ImageFormat::Bmp]
     }
+
+    /// Get the format for a file extension (case-insensitive)
+    pub fn from_extension(extension: &str) -> Option<ImageFormat> {
+        match extension.to_ascii_lowercase().as_str() {
+            "png" => Some(ImageFormat::Png),
+            "jpg" | "jpeg" => Some(ImageFormat::Jpg),
+            "bmp" => Some(ImageFormat::Bmp),
+            _ => None,
+        }
+    }
 }
 
 impl CaptureArea {
@@ -254,6 +1052,117 @@ impl CaptureArea {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_pan_options_validation() {
+        assert!(PanOptions::default().validate().is_ok());
+        let free = PanOptions { min_visible_margin: 0.0, free_pan: true, ..PanOptions::default() };
+        assert!(free.validate().is_ok());
+        assert!(PanOptions { min_visible_margin: -1.0, ..free }.validate().is_err());
+        assert!(PanOptions { min_visible_margin: f32::NAN, ..free }.validate().is_err());
+    }
+
+    #[test]
+    fn test_gesture_options_validation() {
+        assert!(GestureOptions::default().validate().is_ok());
+        for zoom_sensitivity in [0.0, 5.5, f32::NAN] {
+            assert!(GestureOptions { zoom_sensitivity, ..GestureOptions::default() }.validate().is_err());
+        }
+
+        // Settings saved before gestures were configurable keep the old behavior
+        let options: GestureOptions = serde_json::from_str("{}").unwrap();
+        assert_eq!(options, GestureOptions::default());
+        assert_eq!(options.double_click, DoubleClickAction::ResetView);
+    }
+
+    #[test]
+    fn test_annotation_duplicate() {
+        let mut original = AnnotationItem::new_text(Pos2::new(5.0, 5.0), "Note".to_string());
+        original.is_selected = true;
+        let copy = original.duplicate(Vec2::new(10.0, 10.0));
+        assert_ne!(copy.id, original.id);
+        assert_eq!(copy.position, Pos2::new(15.0, 15.0));
+        assert_eq!(copy.annotation_type, original.annotation_type);
+    }
+
+    #[test]
+    fn test_rotated_annotation_hit_test() {
+        let mut annotation = AnnotationItem::new_rectangle(Pos2::new(0.0, 40.0), Vec2::new(100.0, 20.0));
+        assert!(!annotation.is_rotated());
+        assert!(annotation.contains_point(Pos2::new(90.0, 50.0)));
+        assert!(!annotation.contains_point(Pos2::new(50.0, 10.0)));
+
+        // Standing upright about the center (50, 50)
+        annotation.rotation = 90.0;
+        assert!(annotation.is_rotated());
+        assert!(!annotation.contains_point(Pos2::new(90.0, 50.0)));
+        assert!(annotation.contains_point(Pos2::new(50.0, 10.0)));
+        let top_left = annotation.corners()[0];
+        assert!((top_left - Pos2::new(60.0, 0.0)).length() < 1e-4);
+
+        annotation.rotation = 360.0;
+        assert!(!annotation.is_rotated());
+    }
+
+    #[test]
+    fn test_intersects_rect() {
+        let mut annotation = AnnotationItem::new_rectangle(Pos2::new(0.0, 0.0), Vec2::new(100.0, 100.0));
+        assert!(annotation.intersects(Rect::from_min_size(Pos2::new(90.0, 90.0), Vec2::splat(20.0))));
+        assert!(annotation.intersects(Rect::from_min_size(Pos2::new(10.0, 10.0), Vec2::splat(5.0))));
+        assert!(!annotation.intersects(Rect::from_min_size(Pos2::new(101.0, 0.0), Vec2::splat(5.0))));
+
+        // Turned into a diamond, the corner of its old bounds is empty
+        annotation.rotation = 45.0;
+        assert!(!annotation.intersects(Rect::from_min_size(Pos2::new(0.0, 0.0), Vec2::splat(5.0))));
+        assert!(annotation.intersects(Rect::from_min_size(Pos2::new(48.0, -20.0), Vec2::splat(5.0))));
+    }
+
+    #[test]
+    fn test_rotate_handle() {
+        let mut annotation = AnnotationItem::new_rectangle(Pos2::new(0.0, 0.0), Vec2::new(100.0, 40.0));
+        assert_eq!(annotation.rotate_handle(10.0), Pos2::new(50.0, -10.0));
+        assert_eq!(annotation.rotation_towards(Pos2::new(50.0, -30.0)), 0.0);
+        assert_eq!(annotation.rotation_towards(Pos2::new(90.0, 20.0)), 90.0);
+        assert_eq!(annotation.rotation_towards(Pos2::new(10.0, 20.0)), -90.0);
+        assert_eq!(annotation.rotation_towards(Pos2::new(50.0, 60.0)), 180.0);
+
+        annotation.rotation = 90.0;
+        let handle = annotation.rotate_handle(10.0);
+        assert!((handle - Pos2::new(80.0, 20.0)).length() < 1e-4);
+    }
+
+    #[test]
+    fn test_resize_corner() {
+        let mut annotation = AnnotationItem::new_rectangle(Pos2::new(10.0, 10.0), Vec2::new(40.0, 20.0));
+        // Drag the bottom-right corner; the top-left stays put
+        annotation.resize_corner(2, Pos2::new(70.0, 60.0), false);
+        assert_eq!(annotation.bounds(), Rect::from_min_max(Pos2::new(10.0, 10.0), Pos2::new(70.0, 60.0)));
+
+        // Images keep their aspect ratio, anchored at the opposite corner
+        let mut image = AnnotationItem::new_image(Pos2::new(10.0, 10.0), Vec::new(), Vec2::new(40.0, 20.0));
+        image.resize_corner(0, Pos2::new(-70.0, 0.0), true);
+        assert_eq!(image.bounds(), Rect::from_min_max(Pos2::new(-70.0, -30.0), Pos2::new(50.0, 30.0)));
+
+        // Dragging past the anchor stops at the minimum size
+        image.resize_corner(2, Pos2::new(-100.0, -100.0), true);
+        assert_eq!(image.bounds().min, Pos2::new(-70.0, -30.0));
+        assert_eq!(image.bounds().size(), Vec2::new(8.0, 4.0));
+
+        // Text is sized by its font
+        let mut text = AnnotationItem::new_text(Pos2::ZERO, "Note".to_string());
+        let bounds = text.bounds();
+        text.resize_corner(2, Pos2::new(100.0, 100.0), false);
+        assert_eq!(text.bounds(), bounds);
+    }
+
+    #[test]
+    fn test_image_annotation_serializes_as_base64() {
+        let annotation = AnnotationItem::new_image(Pos2::ZERO, vec![1, 2, 3], Vec2::new(10.0, 10.0));
+        let json = serde_json::to_string(&annotation).unwrap();
+        assert!(json.contains("\"AQID\""));
+        let loaded: AnnotationItem = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded, annotation);
+    }
+
     #[test]
     fn test_capture_area_default() {
         let area = CaptureArea::default();
@@ -306,10 +1215,11 @@ mod tests {
         assert!(!rect_annotation.is_selected);
         
         match rect_annotation.annotation_type {
-            AnnotationType::Rectangle { size: rect_size, stroke_color, stroke_width } => {
+            AnnotationType::Rectangle { size: rect_size, stroke_color, stroke_width, fill_color } => {
                 assert_eq!(rect_size, size);
                 assert_eq!(stroke_color, Color32::RED);
                 assert_eq!(stroke_width, 2.0);
+                assert!(fill_color.is_none());
             }
             _ => panic!("Expected Rectangle annotation type"),
         }
@@ -325,10 +1235,11 @@ mod tests {
         assert!(!text_annotation.is_selected);
         
         match text_annotation.annotation_type {
-            AnnotationType::Text { content: text_content, font_size, color } => {
+            AnnotationType::Text { content: text_content, font_size, color, format } => {
                 assert_eq!(text_content, content);
                 assert_eq!(font_size, 14.0);
                 assert_eq!(color, Color32::BLACK);
+                assert_eq!(format, TextFormat::default());
             }
             _ => panic!("Expected Text annotation type"),
         }
@@ -382,6 +1293,9 @@ mod tests {
         let error_msg = format!("{}", error);
         assert!(error_msg.contains("ホットキー登録に失敗しました"));
         assert!(error_msg.contains("Test error"));
+
+        let error = AppError::DiskFull("C:\\shot.png".to_string());
+        assert!(format!("{}", error).contains("ディスクの空き容量"));
     }
 
     #[test]
@@ -486,6 +1400,14 @@ mod tests {
         assert_eq!(ImageFormat::Bmp.extension(), "bmp");
     }
 
+    #[test]
+    fn test_image_format_from_extension() {
+        assert_eq!(ImageFormat::from_extension("PNG"), Some(ImageFormat::Png));
+        assert_eq!(ImageFormat::from_extension("jpeg"), Some(ImageFormat::Jpg));
+        assert_eq!(ImageFormat::from_extension("bmp"), Some(ImageFormat::Bmp));
+        assert_eq!(ImageFormat::from_extension("gif"), None);
+    }
+
     #[test]
     fn test_image_format_all() {
         let formats = ImageFormat::all();
@@ -523,4 +1445,44 @@ mod tests {
         assert_eq!(physical.width(), 200.0); // 100.0 * 2.0
         assert_eq!(physical.height(), 75.0); // 50.0 * 1.5
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_error_context() {
+        let io = std::io::Error::new(std::io::ErrorKind::PermissionDenied, "denied");
+        let result: AppResult<()> = Err(io).context("保存先フォルダを作成できません");
+        let error = result.unwrap_err().context("スクリーンショットの保存に失敗しました");
+        assert_eq!(
+            error.to_string(),
+            "スクリーンショットの保存に失敗しました: 保存先フォルダを作成できません: ファイルアクセスエラー: denied"
+        );
+        assert!(matches!(error.root_cause(), AppError::FileAccess(_)));
+        let source = std::error::Error::source(&error).unwrap();
+        assert!(source.to_string().starts_with("保存先フォルダを作成できません"));
+        assert_eq!(error.code(), "file_access");
+        assert_eq!(error.exit_code(), 6);
+    }
+
+    #[test]
+    fn test_structured_capture_errors() {
+        let error = AppError::RegionOutOfBounds {
+            requested: Rect::from_min_size(Pos2::new(10.0, 20.0), Vec2::new(300.0, 200.0)),
+            available: Rect::from_min_size(Pos2::ZERO, Vec2::new(200.0, 100.0)),
+        };
+        assert_eq!(error.to_string(), "キャプチャ範囲 300×200 (10, 20) が画面の範囲 200×100 (0, 0) からはみ出しています");
+        assert_eq!((error.code(), error.exit_code()), ("region_out_of_bounds", 4));
+
+        let error = AppError::CaptureFailed { screen: 1, source: "no image".into() };
+        assert_eq!(error.to_string(), "画面 1 のキャプチャに失敗しました: no image");
+        assert_eq!(std::error::Error::source(&error).unwrap().to_string(), "no image");
+
+        let error = AppError::ScreenNotFound { index: 2 }.context("ウィンドウのキャプチャ");
+        assert_eq!((error.code(), error.exit_code()), ("screen_not_found", 3));
+
+        let error = AppError::NoScreen("プライマリ画面".to_string());
+        assert_eq!(error.to_string(), "画面が見つかりません: プライマリ画面");
+        assert_eq!((error.code(), error.exit_code()), ("screen_not_found", 3));
+
+        let error = AppError::InvalidArgument("範囲が小さすぎます".to_string()).context("範囲の監視");
+        assert_eq!((error.code(), error.exit_code()), ("invalid_argument", 2));
+    }
+}
@@ -0,0 +1,136 @@
+//! Undo history
+//!
+//! The history keeps whole snapshots of the edited state rather than
+//! individual operations, so every way of editing it (canvas drags, the
+//! properties panel, keyboard nudges, paste) can be undone without each
+//! recording its own steps. The editor commits the state once an edit is
+//! finished, for example when the mouse button is released, so a drag or a
+//! slider movement becomes a single step.
+
+/// Most steps kept; older ones are dropped
+pub const DEFAULT_LIMIT: usize = 100;
+
+/// Undo and redo stacks of snapshots
+#[derive(Debug, Clone)]
+pub struct UndoHistory<T> {
+    /// State after the last committed step
+    committed: T,
+    undo: Vec<T>,
+    redo: Vec<T>,
+    limit: usize,
+}
+
+impl<T: Clone + PartialEq + Default> Default for UndoHistory<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: Clone + PartialEq> UndoHistory<T> {
+    /// Start a history at `initial`, with nothing to undo
+    pub fn new(initial: T) -> Self {
+        Self {
+            committed: initial,
+            undo: Vec::new(),
+            redo: Vec::new(),
+            limit: DEFAULT_LIMIT,
+        }
+    }
+
+    /// Forget all steps and start again at `initial`
+    pub fn reset(&mut self, initial: T) {
+        *self = Self { limit: self.limit, ..Self::new(initial) };
+    }
+
+    /// Record `current` as a step if it differs from the last committed state
+    ///
+    /// A new step clears the redo stack. Returns whether a step was recorded.
+    pub fn commit(&mut self, current: &T) -> bool {
+        if *current == self.committed {
+            return false;
+        }
+        let previous = std::mem::replace(&mut self.committed, current.clone());
+        self.undo.push(previous);
+        if self.undo.len() > self.limit {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+        true
+    }
+
+    /// Step back from `current`, returning the state to restore
+    ///
+    /// Uncommitted changes in `current` are committed first, so they are
+    /// what gets undone.
+    pub fn undo(&mut self, current: &T) -> Option<T> {
+        self.commit(current);
+        let previous = self.undo.pop()?;
+        self.redo.push(std::mem::replace(&mut self.committed, previous));
+        Some(self.committed.clone())
+    }
+
+    /// Step forward again after [`undo`](Self::undo), returning the state to restore
+    ///
+    /// Uncommitted changes in `current` are committed first, which clears
+    /// the redo stack.
+    pub fn redo(&mut self, current: &T) -> Option<T> {
+        self.commit(current);
+        let next = self.redo.pop()?;
+        self.undo.push(std::mem::replace(&mut self.committed, next));
+        Some(self.committed.clone())
+    }
+
+    /// Check whether there is a step to undo from `current`
+    pub fn can_undo(&self, current: &T) -> bool {
+        !self.undo.is_empty() || *current != self.committed
+    }
+
+    /// Check whether there is a step to redo from `current`
+    pub fn can_redo(&self, current: &T) -> bool {
+        !self.redo.is_empty() && *current == self.committed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_undo_and_redo() {
+        let mut history = UndoHistory::new(vec![1]);
+        assert!(!history.can_undo(&vec![1]));
+        assert!(!history.commit(&vec![1]));
+        assert!(history.commit(&vec![1, 2]));
+
+        // Uncommitted changes are undone first
+        assert!(history.can_undo(&vec![1, 2, 3]));
+        assert_eq!(history.undo(&vec![1, 2, 3]), Some(vec![1, 2]));
+        assert_eq!(history.undo(&vec![1, 2]), Some(vec![1]));
+        assert_eq!(history.undo(&vec![1]), None);
+
+        assert!(history.can_redo(&vec![1]));
+        assert_eq!(history.redo(&vec![1]), Some(vec![1, 2]));
+        // A new edit drops what could be redone
+        assert_eq!(history.redo(&vec![1, 2, 4]), None);
+        assert_eq!(history.undo(&vec![1, 2, 4]), Some(vec![1, 2]));
+    }
+
+    #[test]
+    fn test_limit_and_reset() {
+        let mut history = UndoHistory::new(0);
+        for value in 1..=(DEFAULT_LIMIT as i32 + 5) {
+            history.commit(&value);
+        }
+        let mut steps = 0;
+        let mut current = DEFAULT_LIMIT as i32 + 5;
+        while let Some(previous) = history.undo(&current) {
+            current = previous;
+            steps += 1;
+        }
+        assert_eq!((steps, current), (DEFAULT_LIMIT, 5));
+
+        history.reset(42);
+        assert!(!history.can_undo(&42));
+        assert!(!history.can_redo(&42));
+    }
+}
@@ -0,0 +1,216 @@
+//! Region watch
+//!
+//! A watch polls a region of the desktop and saves a capture only when its
+//! pixels change, for catching error dialogs or notifications that come and
+//! go. Each check is compared with the one before it, so a change that stays
+//! on screen is saved once. Captures go into a dated folder of their own,
+//! named by their order and the time they were seen.
+
+use crate::datetime::DateTime;
+use crate::diff;
+use crate::types::{AppError, AppResult, ImageFormat};
+use egui::Rect;
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Shortest time between checks
+pub const MIN_POLL_INTERVAL_MS: u64 = 100;
+
+/// Longest time between checks
+pub const MAX_POLL_INTERVAL_MS: u64 = 60_000;
+
+/// Region watch settings
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WatchSettings {
+    /// Milliseconds between checks of the region
+    pub poll_interval_ms: u64,
+    /// Percentage of the region's pixels that must change for a capture to be saved
+    pub min_changed_percent: f32,
+    /// Format of saved captures
+    pub format: ImageFormat,
+    /// Folder the dated watch folders are created in, the save folder if unset
+    pub directory: Option<PathBuf>,
+}
+
+impl Default for WatchSettings {
+    fn default() -> Self {
+        Self {
+            poll_interval_ms: 500,
+            min_changed_percent: 0.5,
+            format: ImageFormat::Png,
+            directory: None,
+        }
+    }
+}
+
+impl WatchSettings {
+    /// Validate the polling interval and change threshold
+    pub fn validate(&self) -> AppResult<()> {
+        if !(MIN_POLL_INTERVAL_MS..=MAX_POLL_INTERVAL_MS).contains(&self.poll_interval_ms) {
+            return Err(AppError::Settings(format!(
+                "監視間隔は{}ミリ秒から{}ミリ秒の間で指定してください",
+                MIN_POLL_INTERVAL_MS, MAX_POLL_INTERVAL_MS
+            )));
+        }
+        if !(0.0..=100.0).contains(&self.min_changed_percent) {
+            return Err(AppError::Settings("変化の割合は0%から100%の間で指定してください".to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// Get the name of the folder a watch started at `time` saves to
+pub fn folder_name(time: &DateTime) -> String {
+    format!(
+        "watch_{:04}{:02}{:02}_{:02}{:02}{:02}",
+        time.year, time.month, time.day, time.hour, time.minute, time.second
+    )
+}
+
+/// A region being watched for changes
+///
+/// The watch only keeps time and compares frames; the editor captures the
+/// region when [`take_due`](Self::take_due) says a check is due and hands the
+/// frame to [`check_frame`](Self::check_frame).
+#[derive(Debug)]
+pub struct RegionWatch {
+    /// Watched rectangle in desktop coordinates
+    region: Rect,
+    settings: WatchSettings,
+    folder: PathBuf,
+    next_check: Instant,
+    /// Frame of the previous check
+    previous: Option<RgbaImage>,
+    checks: u32,
+    saved: u32,
+}
+
+impl RegionWatch {
+    /// Start watching a desktop rectangle, saving into a new dated folder under `parent`
+    pub fn new(region: Rect, settings: WatchSettings, parent: &Path, started_at: &DateTime, now: Instant) -> Self {
+        Self {
+            region,
+            folder: parent.join(folder_name(started_at)),
+            settings,
+            next_check: now,
+            previous: None,
+            checks: 0,
+            saved: 0,
+        }
+    }
+
+    /// Get the watched rectangle in desktop coordinates
+    pub fn region(&self) -> Rect {
+        self.region
+    }
+
+    /// Get the settings the watch was started with
+    pub fn settings(&self) -> &WatchSettings {
+        &self.settings
+    }
+
+    /// Get the folder captures are saved to
+    pub fn folder(&self) -> &Path {
+        &self.folder
+    }
+
+    /// Get the number of checks made
+    pub fn checks(&self) -> u32 {
+        self.checks
+    }
+
+    /// Get the number of saved captures
+    pub fn saved(&self) -> u32 {
+        self.saved
+    }
+
+    /// Check whether the region is due to be checked, scheduling the next check if so
+    pub fn take_due(&mut self, now: Instant) -> bool {
+        if now < self.next_check {
+            return false;
+        }
+        self.next_check = now + Duration::from_millis(self.settings.poll_interval_ms);
+        true
+    }
+
+    /// Get the time until the next check is due
+    pub fn time_until_due(&self, now: Instant) -> Duration {
+        self.next_check.saturating_duration_since(now)
+    }
+
+    /// Compare a frame with the previous check, returning the path to save it to if it changed
+    ///
+    /// The first frame is the baseline and is never saved.
+    pub fn check_frame(&mut self, image: &RgbaImage, seen_at: &DateTime) -> Option<PathBuf> {
+        self.checks += 1;
+        let min_ratio = f64::from(self.settings.min_changed_percent) / 100.0;
+        let changed = self
+            .previous
+            .as_ref()
+            .is_some_and(|previous| diff::changed_beyond(previous, image, diff::DEFAULT_THRESHOLD, min_ratio));
+        self.previous = Some(image.clone());
+        if !changed {
+            return None;
+        }
+        self.saved += 1;
+        Some(self.folder.join(format!(
+            "{:04}_{:02}{:02}{:02}.{}",
+            self.saved,
+            seen_at.hour,
+            seen_at.minute,
+            seen_at.second,
+            self.settings.format.extension()
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(hour: u32, minute: u32, second: u32) -> DateTime {
+        DateTime { year: 2024, month: 3, day: 9, hour, minute, second }
+    }
+
+    #[test]
+    fn test_saves_only_changes() {
+        let now = Instant::now();
+        let region = Rect::from_min_size(egui::pos2(100.0, 50.0), egui::vec2(20.0, 10.0));
+        let mut watch = RegionWatch::new(region, WatchSettings::default(), Path::new("shots"), &time(9, 0, 0), now);
+        assert_eq!(watch.folder(), Path::new("shots").join("watch_20240309_090000"));
+
+        assert!(watch.take_due(now));
+        assert!(!watch.take_due(now + Duration::from_millis(499)));
+        assert_eq!(watch.time_until_due(now + Duration::from_millis(200)), Duration::from_millis(300));
+        assert!(watch.take_due(now + Duration::from_millis(500)));
+
+        let quiet = RgbaImage::from_pixel(20, 10, image::Rgba([40, 40, 40, 255]));
+        let mut dialog = quiet.clone();
+        for x in 0..10 {
+            dialog.put_pixel(x, 4, image::Rgba([255, 0, 0, 255]));
+        }
+        let mut flicker = quiet.clone();
+        flicker.put_pixel(0, 0, image::Rgba([255, 255, 255, 255]));
+
+        // The baseline and an unchanged frame are not saved
+        assert_eq!(watch.check_frame(&quiet, &time(9, 0, 1)), None);
+        assert_eq!(watch.check_frame(&quiet, &time(9, 0, 2)), None);
+        // One pixel of 200 is below the default 0.5%
+        assert_eq!(watch.check_frame(&flicker, &time(9, 0, 3)), None);
+        let path = watch.check_frame(&dialog, &time(9, 12, 5)).unwrap();
+        assert_eq!(path.file_name().unwrap(), "0001_091205.png");
+        // A change that stays on screen is saved once
+        assert_eq!(watch.check_frame(&dialog, &time(9, 12, 6)), None);
+        assert_eq!((watch.checks(), watch.saved()), (5, 1));
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(WatchSettings::default().validate().is_ok());
+        assert!(WatchSettings { poll_interval_ms: 10, ..WatchSettings::default() }.validate().is_err());
+        assert!(WatchSettings { min_changed_percent: 101.0, ..WatchSettings::default() }.validate().is_err());
+    }
+}
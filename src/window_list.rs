@@ -0,0 +1,195 @@
+//! Top-level windows to capture by title or process
+//!
+//! The command line and the automation API can capture a window that isn't
+//! in front, picked by part of its title or the executable name of its
+//! process. Windows are listed through
+//! [`CaptureBackend::windows`](crate::capture::CaptureBackend::windows),
+//! which enumerates the visible top-level windows on Windows and serves
+//! fixtures in tests. A selector has to match exactly one window, so a
+//! script never captures a different window than it meant to; zero and
+//! several matches fail with errors of their own kind.
+
+use crate::capture::WindowFrame;
+use crate::types::{AppError, AppResult};
+use serde::{Deserialize, Serialize};
+
+/// A visible top-level window
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WindowInfo {
+    pub title: String,
+    /// Executable name of the window's process, e.g. `notepad.exe`
+    #[serde(default)]
+    pub application: Option<String>,
+    pub frame: WindowFrame,
+}
+
+/// Which window to capture
+///
+/// Every given condition has to hold. Both compare without regard to case,
+/// and the process name may leave out `.exe`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WindowSelector {
+    /// Part of the window title
+    pub title: Option<String>,
+    /// Executable name of the window's process
+    pub process: Option<String>,
+}
+
+impl WindowSelector {
+    /// Check whether no condition is given
+    pub fn is_empty(&self) -> bool {
+        self.title.is_none() && self.process.is_none()
+    }
+
+    /// Check whether a window meets every condition
+    pub fn matches(&self, window: &WindowInfo) -> bool {
+        let title_matches = self
+            .title
+            .as_deref()
+            .is_none_or(|title| window.title.to_lowercase().contains(&title.to_lowercase()));
+        let process_matches = self.process.as_deref().is_none_or(|process| {
+            window.application.as_deref().is_some_and(|application| {
+                let process = process.to_lowercase();
+                let application = application.to_lowercase();
+                application == process || application.strip_suffix(".exe") == Some(process.as_str())
+            })
+        });
+        title_matches && process_matches
+    }
+
+    /// Describe the conditions for error messages, e.g. `title "Notepad", process notepad.exe`
+    pub fn describe(&self) -> String {
+        let mut conditions = Vec::new();
+        if let Some(title) = &self.title {
+            conditions.push(format!("title \"{}\"", title));
+        }
+        if let Some(process) = &self.process {
+            conditions.push(format!("process {}", process));
+        }
+        conditions.join(", ")
+    }
+
+    /// Find the one window meeting the conditions
+    pub fn find<'a>(&self, windows: &'a [WindowInfo]) -> AppResult<&'a WindowInfo> {
+        if self.is_empty() {
+            return Err(AppError::InvalidArgument("ウィンドウのタイトルまたはプロセスを指定してください".to_string()));
+        }
+        let matches: Vec<&WindowInfo> = windows.iter().filter(|window| self.matches(window)).collect();
+        match matches.as_slice() {
+            [window] => Ok(window),
+            [] => Err(AppError::WindowNotFound(self.describe())),
+            _ => Err(AppError::AmbiguousWindow {
+                selector: self.describe(),
+                titles: matches.iter().map(|window| window.title.clone()).collect(),
+            }),
+        }
+    }
+}
+
+/// List the visible top-level windows, front to back
+///
+/// Minimized windows, windows without a title, and windows hidden by the
+/// window manager (such as suspended Store apps) are left out, since
+/// there's nothing on screen to capture.
+#[cfg(windows)]
+pub fn enumerate() -> Vec<WindowInfo> {
+    use crate::capture::window_frame;
+    use crate::foreground::{application_of, title_of};
+    use winapi::shared::minwindef::{BOOL, DWORD, LPARAM, TRUE};
+    use winapi::shared::windef::HWND;
+    use winapi::shared::winerror::S_OK;
+    use winapi::um::dwmapi::{DwmGetWindowAttribute, DWMWA_CLOAKED};
+    use winapi::um::winuser::{EnumWindows, IsWindowVisible};
+
+    unsafe extern "system" fn collect(window: HWND, handles: LPARAM) -> BOOL {
+        // SAFETY: `handles` is the vector passed to EnumWindows below, alive for the call
+        let handles = unsafe { &mut *(handles as *mut Vec<HWND>) };
+        handles.push(window);
+        TRUE
+    }
+
+    let mut handles: Vec<HWND> = Vec::new();
+    // SAFETY: the callback only pushes to `handles`, which outlives the call
+    unsafe { EnumWindows(Some(collect), &mut handles as *mut Vec<HWND> as LPARAM) };
+
+    handles
+        .into_iter()
+        .filter(|&window| {
+            let mut cloaked: DWORD = 0;
+            // SAFETY: `window` came from EnumWindows; `cloaked` is a writable DWORD of the size passed
+            let result = unsafe {
+                DwmGetWindowAttribute(
+                    window,
+                    DWMWA_CLOAKED,
+                    &mut cloaked as *mut DWORD as *mut _,
+                    std::mem::size_of::<DWORD>() as u32,
+                )
+            };
+            // SAFETY: a stale handle yields false
+            let visible = unsafe { IsWindowVisible(window) } != 0;
+            visible && !(result == S_OK && cloaked != 0)
+        })
+        .filter_map(|window| {
+            let title = title_of(window)?;
+            Some(WindowInfo {
+                title,
+                application: application_of(window),
+                frame: window_frame(window)?,
+            })
+        })
+        .collect()
+}
+
+/// List the visible top-level windows, front to back
+#[cfg(not(windows))]
+pub fn enumerate() -> Vec<WindowInfo> {
+    Vec::new()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use egui::{Pos2, Rect};
+
+    fn window(title: &str, application: &str) -> WindowInfo {
+        WindowInfo {
+            title: title.to_string(),
+            application: Some(application.to_string()),
+            frame: WindowFrame {
+                frame: Rect::from_min_max(Pos2::ZERO, Pos2::new(100.0, 100.0)),
+                corner_radius: 0.0,
+            },
+        }
+    }
+
+    #[test]
+    fn test_find_requires_exactly_one_match() {
+        let windows = vec![
+            window("Untitled - Notepad", "Notepad.exe"),
+            window("notes.txt - Notepad", "Notepad.exe"),
+            window("Inbox - Outlook", "OUTLOOK.EXE"),
+        ];
+        let selector = |title: Option<&str>, process: Option<&str>| WindowSelector {
+            title: title.map(str::to_string),
+            process: process.map(str::to_string),
+        };
+
+        let found = selector(Some("inbox"), None).find(&windows).unwrap();
+        assert_eq!(found.title, "Inbox - Outlook");
+        assert_eq!(selector(None, Some("outlook")).find(&windows).unwrap().title, "Inbox - Outlook");
+        assert_eq!(selector(Some("notes"), Some("notepad.exe")).find(&windows).unwrap().title, "notes.txt - Notepad");
+
+        let error = selector(None, Some("notepad.exe")).find(&windows).unwrap_err();
+        assert_eq!((error.code(), error.exit_code()), ("ambiguous_window", 8));
+        assert!(error.to_string().contains("Untitled - Notepad"));
+
+        let error = selector(Some("Calculator"), None).find(&windows).unwrap_err();
+        assert_eq!((error.code(), error.exit_code()), ("window_not_found", 7));
+        assert_eq!(error.to_string(), "条件に一致するウィンドウが見つかりません: title \"Calculator\"");
+
+        // A process name doesn't match part of another one
+        assert!(selector(None, Some("note")).find(&windows).is_err());
+        let error = WindowSelector::default().find(&windows).unwrap_err();
+        assert_eq!((error.code(), error.exit_code()), ("invalid_argument", 2));
+    }
+}
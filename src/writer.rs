@@ -0,0 +1,124 @@
+//! Background file writer
+//!
+//! Saves onto network shares and cloud-synced folders can take seconds and
+//! may hit transient lock errors, so they are written on worker threads with
+//! retries while the editor stays responsive. The editor polls for finished
+//! saves each frame.
+
+use crate::files::{self, RetryPolicy, SaveLocation};
+use crate::types::AppResult;
+use crossbeam_channel::{Receiver, Sender};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Result of a background save
+#[derive(Debug)]
+pub struct SaveOutcome {
+    pub path: PathBuf,
+    pub location: SaveLocation,
+    pub result: AppResult<()>,
+}
+
+/// Writes encoded files on background threads
+#[derive(Debug)]
+pub struct BackgroundWriter {
+    sender: Sender<SaveOutcome>,
+    receiver: Receiver<SaveOutcome>,
+    pending: usize,
+    retry: RetryPolicy,
+}
+
+impl Default for BackgroundWriter {
+    fn default() -> Self {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        Self {
+            sender,
+            receiver,
+            pending: 0,
+            retry: RetryPolicy::default(),
+        }
+    }
+}
+
+impl BackgroundWriter {
+    /// Create an idle writer
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start writing `data` to `path` atomically on a worker thread
+    pub fn submit(&mut self, path: PathBuf, location: SaveLocation, data: Vec<u8>) {
+        let sender = self.sender.clone();
+        let retry = self.retry;
+        let worker_path = path.clone();
+        let spawned = std::thread::Builder::new().name("background-save".to_string()).spawn(move || {
+            let result = files::write_atomic_with_retry(&worker_path, &data, retry);
+            // The receiver only goes away when the editor is closing
+            let _ = sender.send(SaveOutcome { path: worker_path, location, result });
+        });
+
+        if let Err(e) = spawned {
+            let _ = self.sender.send(SaveOutcome { path, location, result: Err(e.into()) });
+        }
+        self.pending += 1;
+    }
+
+    /// Number of saves that have not been collected yet
+    pub fn pending(&self) -> usize {
+        self.pending
+    }
+
+    /// Collect the saves that finished since the last poll
+    pub fn poll(&mut self) -> Vec<SaveOutcome> {
+        let outcomes: Vec<SaveOutcome> = self.receiver.try_iter().collect();
+        self.pending -= outcomes.len();
+        outcomes
+    }
+
+    /// Wait up to `timeout` for the next save to finish
+    pub fn wait(&mut self, timeout: Duration) -> Option<SaveOutcome> {
+        if self.pending == 0 {
+            return None;
+        }
+        let outcome = self.receiver.recv_timeout(timeout).ok()?;
+        self.pending -= 1;
+        Some(outcome)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::AppError;
+
+    #[test]
+    fn test_background_save() {
+        let dir = std::env::temp_dir().join(format!("lsa-writer-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("共有.png");
+
+        let mut writer = BackgroundWriter::new();
+        assert!(writer.poll().is_empty());
+        writer.submit(path.clone(), SaveLocation::Network, b"image".to_vec());
+        assert_eq!(writer.pending(), 1);
+
+        let outcome = writer.wait(Duration::from_secs(10)).unwrap();
+        assert_eq!(outcome.path, path);
+        assert_eq!(outcome.location, SaveLocation::Network);
+        assert!(outcome.result.is_ok());
+        assert_eq!(writer.pending(), 0);
+        assert_eq!(std::fs::read(&path).unwrap(), b"image");
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_background_save_failure_is_reported() {
+        let path = std::env::temp_dir().join(format!("lsa-writer-missing-{}", uuid::Uuid::new_v4())).join("a.png");
+        let mut writer = BackgroundWriter::new();
+        writer.submit(path, SaveLocation::CloudSynced, b"image".to_vec());
+
+        let outcome = writer.wait(Duration::from_secs(10)).unwrap();
+        assert!(matches!(outcome.result, Err(AppError::FileAccess(_))));
+    }
+}
@@ -0,0 +1,51 @@
+//! Capture pipeline tests against mock screens
+//!
+//! These run in headless CI, unlike the real-environment capture tests.
+
+use egui::{Pos2, Rect, Vec2};
+use lightweight_screenshot_app::capture::mock::MockBackend;
+use lightweight_screenshot_app::export::{self, ExportOptions};
+use lightweight_screenshot_app::{CaptureArea, CaptureService, ImageFormat};
+use std::path::Path;
+
+fn dual_monitor_service() -> CaptureService {
+    let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/dual_monitor.json");
+    CaptureService::with_backend(MockBackend::from_fixture_file(&fixture).unwrap()).unwrap()
+}
+
+#[test]
+fn test_mock_screen_layout() {
+    let service = dual_monitor_service();
+    assert_eq!(service.get_screens().len(), 2);
+    assert_eq!(service.get_primary_screen().unwrap().index, 0);
+    assert_eq!(service.get_desktop_bounds().size(), Vec2::new(3200.0, 1080.0));
+    assert_eq!(service.find_screen_at_point(Pos2::new(2500.0, 100.0)).unwrap().index, 1);
+}
+
+#[test]
+fn test_capture_high_dpi_screen() {
+    let service = dual_monitor_service();
+    let image = service.capture_screen_by_index(1).unwrap();
+    assert_eq!((image.width(), image.height()), (1920, 1080));
+
+    // Selection in desktop coordinates becomes a physical-pixel crop of screen 1
+    let area = service
+        .create_capture_area(Pos2::new(1920.0, 0.0), Pos2::new(2020.0, 50.0))
+        .unwrap();
+    assert_eq!(area.screen_index, 1);
+    let cropped = service.capture_area(&area).unwrap();
+    assert_eq!((cropped.width(), cropped.height()), (150, 75));
+}
+
+#[test]
+fn test_capture_and_export() {
+    let service = dual_monitor_service();
+    let area = CaptureArea::new(Rect::from_min_size(Pos2::ZERO, Vec2::new(200.0, 100.0)), 0);
+    let image = service.capture_area(&area).unwrap().into_rgba8();
+
+    let exported = export::prepare_export(&image, &ExportOptions::default(), std::time::SystemTime::now());
+    let png = export::encode_image(&exported, &ImageFormat::Png).unwrap();
+    let decoded = image::load_from_memory(&png).unwrap().into_rgba8();
+    assert_eq!(decoded.dimensions(), (200, 100));
+    assert_eq!(decoded.get_pixel(0, 0), image.get_pixel(0, 0));
+}